@@ -0,0 +1,152 @@
+//! Access to the discovery catalog through the current kubeconfig context, from the
+//! discovery ConfigMap or from `DiscoveredApi` resources.
+
+use clap::ValueEnum;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{Api, Client, api::ListParams};
+use openapi_common::ApiInventoryEntry;
+use openapi_common::catalog::{self, fetch_url};
+use openapi_common::crd::{DiscoveredApi, SPEC_CONFIGMAP_KEY};
+
+use crate::error::PluginError;
+
+/// Where the operator writes the catalog to, as its `DISCOVERY_OUTPUT`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SourceKind {
+    Configmap,
+    Crd,
+}
+
+/// Where catalog entries and stored specs are read from
+#[derive(Debug, Clone)]
+pub enum CatalogSource {
+    /// The operator's discovery ConfigMap; stored specs are in the same namespace
+    ConfigMap { namespace: String, name: String },
+    /// `DiscoveredApi` resources of all namespaces; stored specs are next to each resource
+    Crd,
+}
+
+impl CatalogSource {
+    /// Loads all catalog entries, sorted by namespace and name
+    pub async fn load_entries(&self) -> Result<Vec<ApiInventoryEntry>, PluginError> {
+        let client = Client::try_default().await?;
+        let mut apis = match self {
+            CatalogSource::ConfigMap { namespace, name } => {
+                catalog::read_configmap(client, namespace, name).await?
+            }
+            CatalogSource::Crd => {
+                let resources: Api<DiscoveredApi> = Api::all(client);
+                resources
+                    .list(&ListParams::default())
+                    .await?
+                    .items
+                    .iter()
+                    .map(|resource| ApiInventoryEntry {
+                        spec_config_map: resource.spec.spec_config_map.clone(),
+                        ..resource.to_entry()
+                    })
+                    .collect()
+            }
+        };
+
+        apis.sort_by(|a, b| (&a.namespace, &a.name).cmp(&(&b.namespace, &b.name)));
+        Ok(apis)
+    }
+
+    /// Reads the spec of an entry without a connection to the Service: the copy the
+    /// operator stored in a ConfigMap, or else the live spec through the API server's
+    /// service proxy. Specs outside the cluster (`api-doc.io/url`) are fetched directly.
+    pub async fn fetch_spec(&self, entry: &ApiInventoryEntry) -> Result<String, PluginError> {
+        let client = Client::try_default().await?;
+        if let Some(name) = &entry.spec_config_map {
+            let namespace = match self {
+                CatalogSource::ConfigMap { namespace, .. } => namespace,
+                CatalogSource::Crd => &entry.namespace,
+            };
+            let configmaps: Api<ConfigMap> = Api::namespaced(client, namespace);
+            return configmaps
+                .get(name)
+                .await?
+                .data
+                .and_then(|mut data| data.remove(SPEC_CONFIGMAP_KEY))
+                .ok_or_else(|| {
+                    PluginError::Message(format!("ConfigMap '{}' holds no spec", name))
+                });
+        }
+
+        match proxy_path(entry) {
+            Some(path) => {
+                let request = http::Request::get(path)
+                    .body(Vec::new())
+                    .map_err(|e| PluginError::Message(format!("Invalid proxy request: {}", e)))?;
+                Ok(client.request_text(request).await?)
+            }
+            None => Ok(fetch_url(&entry.url).await?),
+        }
+    }
+}
+
+/// Path of the entry's spec on the API server's service proxy, when its URL is the cluster
+/// DNS name of its Service, e.g. `http://orders.shop.svc.cluster.local:8080/openapi.json`.
+/// Entries federated from other clusters have no local Service.
+fn proxy_path(entry: &ApiInventoryEntry) -> Option<String> {
+    if entry.cluster.is_some() {
+        return None;
+    }
+    let url = reqwest::Url::parse(&entry.url).ok()?;
+    let host = url.host_str()?;
+    let service_host = format!("{}.{}.svc", entry.service_name, entry.namespace);
+    if host != service_host && !host.starts_with(&format!("{}.", service_host)) {
+        return None;
+    }
+    // The proxy speaks plain HTTP to the Service unless the name is prefixed with the scheme
+    let scheme = if url.scheme() == "https" {
+        "https:"
+    } else {
+        ""
+    };
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    Some(format!(
+        "/api/v1/namespaces/{}/services/{}{}:{}/proxy{}",
+        entry.namespace,
+        scheme,
+        entry.service_name,
+        url.port_or_known_default()?,
+        path
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proxies_only_cluster_dns_names_of_the_service() {
+        let mut entry = ApiInventoryEntry::new(
+            "shop",
+            "orders",
+            "https://orders.shop.svc.cluster.local/v3/api-docs?group=public",
+        );
+        assert_eq!(
+            proxy_path(&entry).as_deref(),
+            Some(
+                "/api/v1/namespaces/shop/services/https:orders:443/proxy/v3/api-docs?group=public"
+            )
+        );
+
+        entry.url = "http://orders.shop.svc:8080/openapi.json".to_string();
+        assert_eq!(
+            proxy_path(&entry).as_deref(),
+            Some("/api/v1/namespaces/shop/services/orders:8080/proxy/openapi.json")
+        );
+
+        entry.url = "https://api.example.com/openapi.json".to_string();
+        assert_eq!(proxy_path(&entry), None);
+        entry.url = "http://orders.shop.svc:8080/openapi.json".to_string();
+        entry.cluster = Some("eu".to_string());
+        assert_eq!(proxy_path(&entry), None);
+    }
+}
@@ -0,0 +1,51 @@
+use openapi_common::catalog::CatalogError;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum PluginError {
+    Kube(kube::Error),
+    Catalog(CatalogError),
+    Serde(serde_json::Error),
+    /// A user-facing failure that needs no further context (unknown API, bad input, ...)
+    Message(String),
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PluginError::Kube(e) => write!(f, "Kubernetes error: {}", e),
+            PluginError::Catalog(e) => write!(f, "{}", e),
+            PluginError::Serde(e) => write!(f, "Serialization error: {}", e),
+            PluginError::Message(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for PluginError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PluginError::Kube(e) => Some(e),
+            PluginError::Catalog(e) => Some(e),
+            PluginError::Serde(e) => Some(e),
+            PluginError::Message(_) => None,
+        }
+    }
+}
+
+impl From<kube::Error> for PluginError {
+    fn from(err: kube::Error) -> Self {
+        PluginError::Kube(err)
+    }
+}
+
+impl From<CatalogError> for PluginError {
+    fn from(err: CatalogError) -> Self {
+        PluginError::Catalog(err)
+    }
+}
+
+impl From<serde_json::Error> for PluginError {
+    fn from(err: serde_json::Error) -> Self {
+        PluginError::Serde(err)
+    }
+}
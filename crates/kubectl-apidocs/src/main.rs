@@ -0,0 +1,207 @@
+//! `kubectl apidocs`: the discovery catalog from the current kubeconfig context, without
+//! port-forwarding the doc server.
+//!
+//! kubectl runs any `kubectl-<name>` binary on the `PATH` as `kubectl <name>`. The catalog
+//! is read from the discovery ConfigMap, or from the `DiscoveredApi` resources with
+//! `--source crd`; specs come from the copies the operator stored or through the API
+//! server's service proxy, so only RBAC on those objects is needed.
+
+mod catalog;
+mod error;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use openapi_common::catalog::find_entry;
+use openapi_common::names::{self, NameError};
+use openapi_common::{
+    ApiInventoryEntry, DISCOVERY_CONFIGMAP_ENV, DISCOVERY_NAMESPACE_ENV, slugs, spec_utils,
+};
+
+use catalog::{CatalogSource, SourceKind};
+use error::PluginError;
+
+/// Browse the OpenAPI discovery catalog of the cluster
+#[derive(Parser, Debug)]
+#[command(name = "kubectl-apidocs", bin_name = "kubectl apidocs", version, about)]
+struct Cli {
+    /// Namespace of the discovery ConfigMap
+    #[arg(short = 'n', long, global = true, env = DISCOVERY_NAMESPACE_ENV, default_value = "default", value_parser = namespace_arg)]
+    namespace: String,
+
+    /// Name of the discovery ConfigMap
+    #[arg(long, global = true, env = DISCOVERY_CONFIGMAP_ENV, default_value = "openapi-discovery", value_parser = configmap_arg)]
+    configmap: String,
+
+    /// Read the discovery ConfigMap or the `DiscoveredApi` resources
+    #[arg(long, global = true, value_enum, default_value_t = SourceKind::Configmap)]
+    source: SourceKind,
+
+    /// Output format
+    #[arg(short = 'o', long, global = true, value_enum, default_value_t = Format::Table)]
+    output: Format,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List APIs in the catalog
+    List,
+    /// Print the catalog entry of an API (by name, id, or service name)
+    Get {
+        api: String,
+        /// Print the OpenAPI spec instead of the entry
+        #[arg(long)]
+        spec: bool,
+    },
+    /// Print the doc server URL showing an API
+    Open {
+        api: String,
+        /// Public URL of the doc server, e.g. https://apis.example.com
+        #[arg(long, env = "OPENAPI_DOC_SERVER")]
+        server: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Table,
+    Json,
+}
+
+/// Namespace flags must be valid namespace names (RFC 1123 labels)
+fn namespace_arg(value: &str) -> Result<String, NameError> {
+    names::validate_dns_label(value).map(|()| value.to_string())
+}
+
+/// ConfigMap names are RFC 1123 subdomains
+fn configmap_arg(value: &str) -> Result<String, NameError> {
+    names::validate_dns_subdomain(value).map(|()| value.to_string())
+}
+
+impl Cli {
+    fn source(&self) -> CatalogSource {
+        match self.source {
+            SourceKind::Configmap => CatalogSource::ConfigMap {
+                namespace: self.namespace.clone(),
+                name: self.configmap.clone(),
+            },
+            SourceKind::Crd => CatalogSource::Crd,
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+
+    if let Err(e) = run(cli).await {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), PluginError> {
+    let source = cli.source();
+    let apis = source.load_entries().await?;
+    match &cli.command {
+        Command::List => {
+            if cli.output == Format::Json {
+                return print_json(&apis);
+            }
+            print_table(&apis);
+            Ok(())
+        }
+        Command::Get { api, spec: false } => print_json(find_entry(&apis, api)?),
+        Command::Get { api, spec: true } => {
+            let entry = find_entry(&apis, api)?;
+            let spec = source.fetch_spec(entry).await?;
+            let spec = spec_utils::parse_spec_to_json(&spec).map_err(|e| {
+                PluginError::Message(format!(
+                    "Spec of '{}' is not valid JSON/YAML: {}",
+                    entry.name, e
+                ))
+            })?;
+            print_json(&spec)
+        }
+        Command::Open { api, server } => {
+            let entry = find_entry(&apis, api)?;
+            println!(
+                "{}/?api={}",
+                server.trim_end_matches('/'),
+                urlencoding::encode(&slug(&apis, entry))
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Slug the doc server serves the entry under, which depends on the other entries
+fn slug(apis: &[ApiInventoryEntry], entry: &ApiInventoryEntry) -> String {
+    let position = apis.iter().position(|api| api.id == entry.id);
+    position
+        .map(|i| slugs::entry_slugs(apis).swap_remove(i))
+        .unwrap_or_else(|| slugs::SlugKey::of(entry).base_slug())
+}
+
+fn print_table(apis: &[ApiInventoryEntry]) {
+    let headers = [
+        "NAME",
+        "NAMESPACE",
+        "SERVICE",
+        "LIFECYCLE",
+        "AVAILABLE",
+        "AGE",
+    ];
+    let now = chrono::Utc::now();
+    let rows: Vec<[String; 6]> = apis
+        .iter()
+        .map(|api| {
+            [
+                api.name.clone(),
+                api.namespace.clone(),
+                api.service_name.clone(),
+                api.lifecycle.map(|l| l.to_string()).unwrap_or_default(),
+                api.available.to_string(),
+                age(now - api.last_updated),
+            ]
+        })
+        .collect();
+
+    let mut widths = headers.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let format_row = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("   ")
+            .trim_end()
+            .to_string()
+    };
+    println!("{}", format_row(headers.to_vec()));
+    for row in &rows {
+        println!("{}", format_row(row.iter().map(String::as_str).collect()));
+    }
+}
+
+/// Time since the entry was updated, in kubectl's `AGE` style
+fn age(elapsed: chrono::Duration) -> String {
+    let seconds = elapsed.num_seconds().max(0);
+    match seconds {
+        0..120 => format!("{}s", seconds),
+        120..7200 => format!("{}m", seconds / 60),
+        7200..172800 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
+
+fn print_json<T: serde::Serialize + ?Sized>(value: &T) -> Result<(), PluginError> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
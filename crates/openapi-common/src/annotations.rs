@@ -0,0 +1,1151 @@
+//! Typed parsing and validation of `api-doc.io/*` annotations.
+//!
+//! Shared by the operator (to build entries), and the CLI (to lint manifests before
+//! they are applied), so both agree on what a valid configuration looks like.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::{
+    API_DOC_ALLOW_BREAKING_ANNOTATION, API_DOC_API_GROUP_ANNOTATION,
+    API_DOC_AUTH_SECRET_ANNOTATION, API_DOC_CONTRACT_CHECK_ANNOTATION,
+    API_DOC_DESCRIPTION_ANNOTATION, API_DOC_ENABLED_ANNOTATION, API_DOC_EXCLUDE_ANNOTATION,
+    API_DOC_FETCH_VIA_ANNOTATION, API_DOC_GATEWAY_PUBLISH_ANNOTATION,
+    API_DOC_INSECURE_SKIP_VERIFY_ANNOTATION, API_DOC_LIFECYCLE_ANNOTATION, API_DOC_NAME_ANNOTATION,
+    API_DOC_PATH_ANNOTATION, API_DOC_PORT_ANNOTATION, API_DOC_PORT_NAME_ANNOTATION,
+    API_DOC_REFRESH_INTERVAL_ANNOTATION, API_DOC_REFRESH_WINDOW_ANNOTATION,
+    API_DOC_SCHEME_ANNOTATION, API_DOC_SERVER_URL_ANNOTATION, API_DOC_SPEC_CONFIGMAP_ANNOTATION,
+    API_DOC_TLS_SECRET_ANNOTATION, API_DOC_UI_DARK_MODE_ANNOTATION,
+    API_DOC_UI_LAYOUT_ANNOTATION, API_DOC_UI_REDOC_ANNOTATION_PREFIX, API_DOC_UI_THEME_ANNOTATION,
+    API_DOC_URL_ANNOTATION, DEFAULT_API_DOC_PATH, Lifecycle, SPEC_FETCH_VIA_ENV, UiOptions,
+    schedule::RefreshWindow,
+};
+
+/// Prefix shared by all annotations understood by the operator
+pub const API_DOC_ANNOTATION_PREFIX: &str = "api-doc.io/";
+
+/// Every annotation the operator understands
+pub const KNOWN_ANNOTATIONS: &[&str] = &[
+    API_DOC_ENABLED_ANNOTATION,
+    API_DOC_PATH_ANNOTATION,
+    API_DOC_URL_ANNOTATION,
+    API_DOC_SPEC_CONFIGMAP_ANNOTATION,
+    API_DOC_SERVER_URL_ANNOTATION,
+    API_DOC_NAME_ANNOTATION,
+    API_DOC_DESCRIPTION_ANNOTATION,
+    API_DOC_GATEWAY_PUBLISH_ANNOTATION,
+    API_DOC_CONTRACT_CHECK_ANNOTATION,
+    API_DOC_ALLOW_BREAKING_ANNOTATION,
+    API_DOC_LIFECYCLE_ANNOTATION,
+    API_DOC_PORT_ANNOTATION,
+    API_DOC_PORT_NAME_ANNOTATION,
+    API_DOC_SCHEME_ANNOTATION,
+    API_DOC_INSECURE_SKIP_VERIFY_ANNOTATION,
+    API_DOC_TLS_SECRET_ANNOTATION,
+    API_DOC_AUTH_SECRET_ANNOTATION,
+    API_DOC_FETCH_VIA_ANNOTATION,
+    API_DOC_EXCLUDE_ANNOTATION,
+    API_DOC_UI_THEME_ANNOTATION,
+    API_DOC_UI_LAYOUT_ANNOTATION,
+    API_DOC_UI_DARK_MODE_ANNOTATION,
+    API_DOC_REFRESH_INTERVAL_ANNOTATION,
+    API_DOC_REFRESH_WINDOW_ANNOTATION,
+    API_DOC_API_GROUP_ANNOTATION,
+];
+
+/// Themes bundled with Scalar
+pub const SCALAR_THEMES: &[&str] = &[
+    "default",
+    "alternate",
+    "moon",
+    "purple",
+    "solarized",
+    "bluePlanet",
+    "deepSpace",
+    "saturn",
+    "kepler",
+    "elysiajs",
+    "fastify",
+    "mars",
+    "laserwave",
+    "none",
+];
+
+/// Whether the operator understands an annotation; Redoc options are passed through by name
+pub fn is_known_annotation(key: &str) -> bool {
+    KNOWN_ANNOTATIONS.contains(&key) || key.starts_with(API_DOC_UI_REDOC_ANNOTATION_PREFIX)
+}
+
+/// Annotations a Namespace can set for all of its Services. Names and descriptions belong
+/// to a single API, and breaking revisions are released one Service at a time.
+pub const NAMESPACE_DEFAULT_ANNOTATIONS: &[&str] = &[
+    API_DOC_ENABLED_ANNOTATION,
+    API_DOC_PATH_ANNOTATION,
+    API_DOC_PORT_ANNOTATION,
+    API_DOC_PORT_NAME_ANNOTATION,
+    API_DOC_SCHEME_ANNOTATION,
+    API_DOC_INSECURE_SKIP_VERIFY_ANNOTATION,
+    API_DOC_FETCH_VIA_ANNOTATION,
+    API_DOC_LIFECYCLE_ANNOTATION,
+    API_DOC_GATEWAY_PUBLISH_ANNOTATION,
+    API_DOC_CONTRACT_CHECK_ANNOTATION,
+    API_DOC_EXCLUDE_ANNOTATION,
+    API_DOC_REFRESH_INTERVAL_ANNOTATION,
+    API_DOC_REFRESH_WINDOW_ANNOTATION,
+];
+
+/// A Service's annotations on top of the defaults declared by its Namespace; values set on
+/// the Service win, so `api-doc.io/enabled: "false"` opts a Service out again. A port set on
+/// the Service, by number or name, replaces both port annotations of the Namespace. Only
+/// `api-doc.io/exclude: "true"` on the Namespace cannot be overridden.
+pub fn with_namespace_defaults(
+    namespace: Option<&BTreeMap<String, String>>,
+    service: &BTreeMap<String, String>,
+) -> BTreeMap<String, String> {
+    let port_annotations = [API_DOC_PORT_ANNOTATION, API_DOC_PORT_NAME_ANNOTATION];
+    let service_sets_port = port_annotations
+        .iter()
+        .any(|key| service.contains_key(*key));
+    let mut merged: BTreeMap<String, String> = namespace
+        .into_iter()
+        .flatten()
+        .filter(|(key, _)| NAMESPACE_DEFAULT_ANNOTATIONS.contains(&key.as_str()))
+        .filter(|(key, _)| !(service_sets_port && port_annotations.contains(&key.as_str())))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    merged.extend(
+        service
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone())),
+    );
+    if let Some(exclude) = namespace.and_then(|ns| ns.get(API_DOC_EXCLUDE_ANNOTATION))
+        && exclude == "true"
+    {
+        merged.insert(API_DOC_EXCLUDE_ANNOTATION.to_string(), exclude.clone());
+    }
+    merged
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum IssueSeverity {
+    /// The annotation is ignored or the service will not be discovered as intended
+    Error,
+    /// Suspicious but usable configuration
+    Warning,
+}
+
+/// A problem found while parsing annotations
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AnnotationIssue {
+    pub severity: IssueSeverity,
+    pub annotation: String,
+    pub message: String,
+}
+
+impl fmt::Display for AnnotationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.annotation, self.message)
+    }
+}
+
+/// Service port selected with `api-doc.io/port` or `api-doc.io/port-name`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortRef {
+    Number(i32),
+    /// Name of one of the Service's ports, which unlike the number is usually the same in
+    /// every environment
+    Name(String),
+}
+
+impl PortRef {
+    /// Parses a port number or an IANA service name such as `http-metrics`
+    pub fn parse(value: &str) -> Result<Self, String> {
+        if !value.is_empty() && value.chars().all(|c| c.is_ascii_digit()) {
+            return match value.parse::<i32>() {
+                Ok(port) if (1..=65535).contains(&port) => Ok(PortRef::Number(port)),
+                _ => Err(format!("port must be between 1 and 65535, got {}", value)),
+            };
+        }
+        let valid_name = (1..=15).contains(&value.len())
+            && value
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+            && value.chars().any(|c| c.is_ascii_lowercase())
+            && !value.starts_with('-')
+            && !value.ends_with('-')
+            && !value.contains("--");
+        if valid_name {
+            Ok(PortRef::Name(value.to_string()))
+        } else {
+            Err(format!(
+                "must be a port number or a port name (at most 15 lowercase letters, digits and '-'), got \"{}\"",
+                value
+            ))
+        }
+    }
+
+    /// Port number among the Service's `(name, port)` pairs; numbers are used as given
+    pub fn resolve<'a>(
+        &self,
+        ports: impl IntoIterator<Item = (Option<&'a str>, i32)>,
+    ) -> Option<i32> {
+        match self {
+            PortRef::Number(port) => Some(*port),
+            PortRef::Name(name) => ports
+                .into_iter()
+                .find(|(port_name, _)| *port_name == Some(name.as_str()))
+                .map(|(_, port)| port),
+        }
+    }
+}
+
+impl fmt::Display for PortRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PortRef::Number(port) => write!(f, "{}", port),
+            PortRef::Name(name) => f.write_str(name),
+        }
+    }
+}
+
+/// ConfigMap key holding the spec, from `api-doc.io/spec-configmap: <name>/<key>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpecConfigMapRef {
+    /// ConfigMap in the Service's namespace
+    pub name: String,
+    pub key: String,
+}
+
+impl std::str::FromStr for SpecConfigMapRef {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, key) = s
+            .trim()
+            .split_once('/')
+            .ok_or_else(|| format!("must be <configmap>/<key>, got \"{}\"", s))?;
+        crate::names::validate_dns_subdomain(name).map_err(|e| e.to_string())?;
+        let valid_key = !key.is_empty()
+            && key.len() <= 253
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid_key {
+            return Err(format!(
+                "ConfigMap key must consist of letters, digits, '-', '_' and '.', got \"{}\"",
+                key
+            ));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            key: key.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for SpecConfigMapRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.name, self.key)
+    }
+}
+
+/// Entry of `api-doc.io/server-url`, a server the stored spec advertises
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerUrl {
+    /// `cluster`: the in-cluster URL of the Service
+    Cluster,
+    /// An absolute URL, e.g. of the public gateway
+    Url(String),
+}
+
+impl std::str::FromStr for ServerUrl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "cluster" => Ok(ServerUrl::Cluster),
+            url => spec_url(url)
+                .map(|url| ServerUrl::Url(url.trim_end_matches('/').to_string()))
+                .map_err(|message| format!("{} or \"cluster\"", message)),
+        }
+    }
+}
+
+/// Scheme of the spec URL, from `api-doc.io/scheme`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UrlScheme {
+    #[default]
+    Http,
+    Https,
+}
+
+impl UrlScheme {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UrlScheme::Http => "http",
+            UrlScheme::Https => "https",
+        }
+    }
+}
+
+impl std::str::FromStr for UrlScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "http" => Ok(UrlScheme::Http),
+            "https" => Ok(UrlScheme::Https),
+            _ => Err(format!("must be \"http\" or \"https\", got \"{}\"", s)),
+        }
+    }
+}
+
+/// How the operator reaches a Service's spec, from `api-doc.io/fetch-via` or `SPEC_FETCH_VIA`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FetchVia {
+    /// Straight from the operator pod
+    #[default]
+    Direct,
+    /// Through the API server's service proxy, for Services NetworkPolicies keep the
+    /// operator from
+    ApiServer,
+}
+
+impl FetchVia {
+    /// `SPEC_FETCH_VIA`, `direct` when unset
+    pub fn from_env() -> Result<Self, String> {
+        match std::env::var(SPEC_FETCH_VIA_ENV) {
+            Ok(value) => value
+                .parse()
+                .map_err(|e| format!("Invalid {}: {}", SPEC_FETCH_VIA_ENV, e)),
+            Err(_) => Ok(FetchVia::Direct),
+        }
+    }
+}
+
+impl std::str::FromStr for FetchVia {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "direct" => Ok(FetchVia::Direct),
+            "apiserver" => Ok(FetchVia::ApiServer),
+            _ => Err(format!(
+                "must be \"direct\" or \"apiserver\", got \"{}\"",
+                s
+            )),
+        }
+    }
+}
+
+/// Parsed `api-doc.io/*` annotations of a Service
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiDocAnnotations {
+    pub enabled: bool,
+    pub path: String,
+    /// Absolute URL the spec is fetched from instead of the Service, e.g. on an external
+    /// docs host; `path`, `port` and `scheme` do not apply then
+    pub url: Option<String>,
+    /// ConfigMap key the spec is read from instead of being fetched over HTTP
+    pub spec_config_map: Option<SpecConfigMapRef>,
+    /// Servers the stored spec advertises instead of its own, in order; empty keeps them
+    pub server_urls: Vec<ServerUrl>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Publish the spec to the configured cloud API gateways
+    pub gateway_publish: bool,
+    /// Periodically call documented `GET` operations and report drift from the spec
+    pub contract_check: bool,
+    /// Publish new spec revisions even when they contain breaking changes
+    pub allow_breaking: bool,
+    pub lifecycle: Option<Lifecycle>,
+    /// Port to fetch the spec from, the Service's first port when unset
+    pub port: Option<PortRef>,
+    pub scheme: UrlScheme,
+    /// Accept any certificate of an `https` Service
+    pub insecure_skip_verify: bool,
+    /// `kubernetes.io/tls` Secret in the Service's namespace with the client certificate
+    /// presented when fetching the spec
+    pub tls_secret: Option<String>,
+    /// Secret in the Service's namespace whose keys are sent as headers when fetching the spec
+    pub auth_secret: Option<String>,
+    /// How the spec is fetched; `SPEC_FETCH_VIA` applies when unset
+    pub fetch_via: Option<FetchVia>,
+    /// Never discover or publish the Service, even when it is enabled
+    pub exclude: bool,
+    /// Appearance of the API in the documentation frontends
+    pub ui: UiOptions,
+    /// Seconds between fetches of the spec by the documentation server
+    pub refresh_interval: Option<u64>,
+    /// When the spec may be fetched
+    pub refresh_window: Option<RefreshWindow>,
+    /// Services with the same group are versions of one API in the portal
+    pub api_group: Option<String>,
+}
+
+impl Default for ApiDocAnnotations {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: DEFAULT_API_DOC_PATH.to_string(),
+            url: None,
+            spec_config_map: None,
+            server_urls: Vec::new(),
+            name: None,
+            description: None,
+            gateway_publish: false,
+            contract_check: false,
+            allow_breaking: false,
+            lifecycle: None,
+            port: None,
+            scheme: UrlScheme::Http,
+            insecure_skip_verify: false,
+            tls_secret: None,
+            auth_secret: None,
+            fetch_via: None,
+            exclude: false,
+            ui: UiOptions::default(),
+            refresh_interval: None,
+            refresh_window: None,
+            api_group: None,
+        }
+    }
+}
+
+impl ApiDocAnnotations {
+    /// Parses annotations, applying defaults and collecting issues instead of failing.
+    /// Invalid values keep the behaviour the operator has always had (e.g. anything other
+    /// than `"true"` disables discovery), but are reported so they can be fixed.
+    pub fn parse(annotations: &BTreeMap<String, String>) -> (Self, Vec<AnnotationIssue>) {
+        let mut parsed = Self::default();
+        let mut issues = Vec::new();
+        let mut issue = |severity, annotation: &str, message: String| {
+            issues.push(AnnotationIssue {
+                severity,
+                annotation: annotation.to_string(),
+                message,
+            })
+        };
+
+        for (annotation, target) in [
+            (API_DOC_ENABLED_ANNOTATION, &mut parsed.enabled),
+            (API_DOC_GATEWAY_PUBLISH_ANNOTATION, &mut parsed.gateway_publish),
+            (API_DOC_CONTRACT_CHECK_ANNOTATION, &mut parsed.contract_check),
+            (API_DOC_ALLOW_BREAKING_ANNOTATION, &mut parsed.allow_breaking),
+            (API_DOC_EXCLUDE_ANNOTATION, &mut parsed.exclude),
+            (
+                API_DOC_INSECURE_SKIP_VERIFY_ANNOTATION,
+                &mut parsed.insecure_skip_verify,
+            ),
+        ] {
+            match annotations.get(annotation).map(String::as_str) {
+                Some("true") => *target = true,
+                Some("false") | None => {}
+                Some(other) => issue(
+                    IssueSeverity::Error,
+                    annotation,
+                    format!("must be \"true\" or \"false\", got \"{}\" (treated as false)", other),
+                ),
+            }
+        }
+
+        if let Some(path) = annotations.get(API_DOC_PATH_ANNOTATION) {
+            if !path.starts_with('/') {
+                issue(
+                    IssueSeverity::Error,
+                    API_DOC_PATH_ANNOTATION,
+                    format!("must start with '/', got \"{}\"", path),
+                );
+            } else if path.chars().any(char::is_whitespace) {
+                issue(
+                    IssueSeverity::Error,
+                    API_DOC_PATH_ANNOTATION,
+                    "must not contain whitespace".to_string(),
+                );
+            }
+            parsed.path = path.clone();
+        }
+
+        if let Some(url) = annotations.get(API_DOC_URL_ANNOTATION) {
+            match spec_url(url.trim()) {
+                Ok(url) => parsed.url = Some(url),
+                Err(message) => issue(
+                    IssueSeverity::Error,
+                    API_DOC_URL_ANNOTATION,
+                    format!("{} (the Service is fetched)", message),
+                ),
+            }
+        }
+
+        if let Some(source) = annotations.get(API_DOC_SPEC_CONFIGMAP_ANNOTATION) {
+            match source.parse() {
+                Ok(source) => parsed.spec_config_map = Some(source),
+                Err(message) => issue(
+                    IssueSeverity::Error,
+                    API_DOC_SPEC_CONFIGMAP_ANNOTATION,
+                    format!("{} (the Service is fetched)", message),
+                ),
+            }
+            if parsed.spec_config_map.is_some() && parsed.url.is_some() {
+                issue(
+                    IssueSeverity::Warning,
+                    API_DOC_URL_ANNOTATION,
+                    format!("has no effect with {}", API_DOC_SPEC_CONFIGMAP_ANNOTATION),
+                );
+            }
+        }
+
+        if let Some(urls) = annotations.get(API_DOC_SERVER_URL_ANNOTATION) {
+            for url in urls.split(',').filter(|url| !url.trim().is_empty()) {
+                match url.parse() {
+                    Ok(url) => parsed.server_urls.push(url),
+                    Err(message) => issue(
+                        IssueSeverity::Error,
+                        API_DOC_SERVER_URL_ANNOTATION,
+                        format!("{} (left out)", message),
+                    ),
+                }
+            }
+        }
+
+        if let Some(name) = annotations.get(API_DOC_NAME_ANNOTATION) {
+            if name.trim().is_empty() {
+                issue(
+                    IssueSeverity::Warning,
+                    API_DOC_NAME_ANNOTATION,
+                    "is empty, the default name will be used".to_string(),
+                );
+            } else {
+                parsed.name = Some(name.clone());
+            }
+        }
+
+        parsed.description = annotations.get(API_DOC_DESCRIPTION_ANNOTATION).cloned();
+
+        if let Some(lifecycle) = annotations.get(API_DOC_LIFECYCLE_ANNOTATION) {
+            match lifecycle.parse() {
+                Ok(lifecycle) => parsed.lifecycle = Some(lifecycle),
+                Err(message) => issue(
+                    IssueSeverity::Error,
+                    API_DOC_LIFECYCLE_ANNOTATION,
+                    format!("{} (ignored)", message),
+                ),
+            }
+        }
+
+        if let Some(port) = annotations.get(API_DOC_PORT_ANNOTATION) {
+            match PortRef::parse(port.trim()) {
+                Ok(port) => parsed.port = Some(port),
+                Err(message) => issue(
+                    IssueSeverity::Error,
+                    API_DOC_PORT_ANNOTATION,
+                    format!("{} (the first port is used)", message),
+                ),
+            }
+        }
+
+        if let Some(name) = annotations.get(API_DOC_PORT_NAME_ANNOTATION) {
+            match PortRef::parse(name.trim()) {
+                Ok(port @ PortRef::Name(_)) => {
+                    if annotations.contains_key(API_DOC_PORT_ANNOTATION) {
+                        issue(
+                            IssueSeverity::Warning,
+                            API_DOC_PORT_ANNOTATION,
+                            format!("is ignored, {} is set too", API_DOC_PORT_NAME_ANNOTATION),
+                        );
+                    }
+                    parsed.port = Some(port);
+                }
+                Ok(PortRef::Number(_)) => issue(
+                    IssueSeverity::Error,
+                    API_DOC_PORT_NAME_ANNOTATION,
+                    format!(
+                        "must be a port name, use {} for port numbers (ignored)",
+                        API_DOC_PORT_ANNOTATION
+                    ),
+                ),
+                Err(message) => issue(
+                    IssueSeverity::Error,
+                    API_DOC_PORT_NAME_ANNOTATION,
+                    format!("{} (ignored)", message),
+                ),
+            }
+        }
+
+        if let Some(scheme) = annotations.get(API_DOC_SCHEME_ANNOTATION) {
+            match scheme.parse() {
+                Ok(scheme) => parsed.scheme = scheme,
+                Err(message) => issue(
+                    IssueSeverity::Error,
+                    API_DOC_SCHEME_ANNOTATION,
+                    format!("{} (http is used)", message),
+                ),
+            }
+        }
+        if parsed.url.is_some() {
+            for annotation in [
+                API_DOC_PATH_ANNOTATION,
+                API_DOC_PORT_ANNOTATION,
+                API_DOC_PORT_NAME_ANNOTATION,
+                API_DOC_SCHEME_ANNOTATION,
+            ] {
+                if annotations.contains_key(annotation) {
+                    issue(
+                        IssueSeverity::Warning,
+                        annotation,
+                        format!("has no effect with {}", API_DOC_URL_ANNOTATION),
+                    );
+                }
+            }
+        }
+        if parsed.insecure_skip_verify && !parsed.uses_https() {
+            issue(
+                IssueSeverity::Warning,
+                API_DOC_INSECURE_SKIP_VERIFY_ANNOTATION,
+                format!(
+                    "has no effect without {}: \"https\"",
+                    API_DOC_SCHEME_ANNOTATION
+                ),
+            );
+        }
+
+        if let Some(secret) = annotations.get(API_DOC_TLS_SECRET_ANNOTATION) {
+            match crate::names::validate_dns_subdomain(secret.trim()) {
+                Ok(()) => parsed.tls_secret = Some(secret.trim().to_string()),
+                Err(e) => issue(
+                    IssueSeverity::Error,
+                    API_DOC_TLS_SECRET_ANNOTATION,
+                    format!("{} (no client certificate is presented)", e),
+                ),
+            }
+            if !parsed.uses_https() {
+                issue(
+                    IssueSeverity::Warning,
+                    API_DOC_TLS_SECRET_ANNOTATION,
+                    format!(
+                        "has no effect without {}: \"https\"",
+                        API_DOC_SCHEME_ANNOTATION
+                    ),
+                );
+            }
+        }
+
+        if let Some(secret) = annotations.get(API_DOC_AUTH_SECRET_ANNOTATION) {
+            match crate::names::validate_dns_subdomain(secret.trim()) {
+                Ok(()) => parsed.auth_secret = Some(secret.trim().to_string()),
+                Err(e) => issue(
+                    IssueSeverity::Error,
+                    API_DOC_AUTH_SECRET_ANNOTATION,
+                    format!("{} (the spec is fetched without credentials)", e),
+                ),
+            }
+        }
+
+        if let Some(interval) = annotations.get(API_DOC_REFRESH_INTERVAL_ANNOTATION) {
+            match parse_interval(interval) {
+                Ok(secs) => parsed.refresh_interval = Some(secs),
+                Err(message) => issue(
+                    IssueSeverity::Error,
+                    API_DOC_REFRESH_INTERVAL_ANNOTATION,
+                    format!("{} (the server's interval is used)", message),
+                ),
+            }
+        }
+
+        if let Some(window) = annotations.get(API_DOC_REFRESH_WINDOW_ANNOTATION) {
+            match RefreshWindow::parse(window) {
+                Ok(window) => parsed.refresh_window = Some(window),
+                Err(message) => issue(
+                    IssueSeverity::Error,
+                    API_DOC_REFRESH_WINDOW_ANNOTATION,
+                    format!("{} (REFRESH_WINDOW applies)", message),
+                ),
+            }
+        }
+
+        if let Some(via) = annotations.get(API_DOC_FETCH_VIA_ANNOTATION) {
+            match via.parse() {
+                Ok(via) => parsed.fetch_via = Some(via),
+                Err(message) => issue(
+                    IssueSeverity::Error,
+                    API_DOC_FETCH_VIA_ANNOTATION,
+                    format!("{} ({} applies)", message, SPEC_FETCH_VIA_ENV),
+                ),
+            }
+        }
+
+        if let Some(group) = annotations.get(API_DOC_API_GROUP_ANNOTATION) {
+            match crate::names::validate_dns_label(group.trim()) {
+                Ok(()) => parsed.api_group = Some(group.trim().to_string()),
+                Err(e) => issue(
+                    IssueSeverity::Error,
+                    API_DOC_API_GROUP_ANNOTATION,
+                    format!("{} (not grouped)", e),
+                ),
+            }
+        }
+
+        if let Some(theme) = annotations.get(API_DOC_UI_THEME_ANNOTATION) {
+            if !SCALAR_THEMES.contains(&theme.as_str()) {
+                issue(
+                    IssueSeverity::Warning,
+                    API_DOC_UI_THEME_ANNOTATION,
+                    format!(
+                        "\"{}\" is not a Scalar theme, expected one of {}",
+                        theme,
+                        SCALAR_THEMES.join(", ")
+                    ),
+                );
+            }
+            parsed.ui.theme = Some(theme.clone());
+        }
+
+        if let Some(layout) = annotations.get(API_DOC_UI_LAYOUT_ANNOTATION) {
+            match layout.as_str() {
+                "modern" | "classic" => parsed.ui.layout = Some(layout.clone()),
+                other => issue(
+                    IssueSeverity::Error,
+                    API_DOC_UI_LAYOUT_ANNOTATION,
+                    format!("must be \"modern\" or \"classic\", got \"{}\" (ignored)", other),
+                ),
+            }
+        }
+
+        if let Some(dark_mode) = annotations.get(API_DOC_UI_DARK_MODE_ANNOTATION) {
+            match dark_mode.as_str() {
+                "true" => parsed.ui.dark_mode = Some(true),
+                "false" => parsed.ui.dark_mode = Some(false),
+                other => issue(
+                    IssueSeverity::Error,
+                    API_DOC_UI_DARK_MODE_ANNOTATION,
+                    format!("must be \"true\" or \"false\", got \"{}\" (ignored)", other),
+                ),
+            }
+        }
+
+        for (key, value) in annotations {
+            let Some(option) = key.strip_prefix(API_DOC_UI_REDOC_ANNOTATION_PREFIX) else {
+                continue;
+            };
+            // Option names end up in the page's script, so only plain identifiers are kept
+            if option.starts_with(|c: char| c.is_ascii_alphabetic())
+                && option.chars().all(|c| c.is_ascii_alphanumeric())
+            {
+                parsed.ui.redoc.insert(option.to_string(), value.clone());
+            } else {
+                issue(
+                    IssueSeverity::Error,
+                    key,
+                    "must name a Redoc option such as hideDownloadButton (ignored)".to_string(),
+                );
+            }
+        }
+
+        let api_doc_keys: Vec<&String> = annotations
+            .keys()
+            .filter(|k| k.starts_with(API_DOC_ANNOTATION_PREFIX))
+            .collect();
+        for key in &api_doc_keys {
+            if !is_known_annotation(key) {
+                let message = match closest_known_annotation(key) {
+                    Some(suggestion) => {
+                        format!("unknown annotation, did you mean {}?", suggestion)
+                    }
+                    None => "unknown annotation".to_string(),
+                };
+                issue(IssueSeverity::Warning, key, message);
+            }
+        }
+        if !parsed.enabled
+            && !parsed.exclude
+            && !annotations.contains_key(API_DOC_ENABLED_ANNOTATION)
+            && !api_doc_keys.is_empty()
+        {
+            issue(
+                IssueSeverity::Warning,
+                API_DOC_ENABLED_ANNOTATION,
+                format!(
+                    "missing, other {}* annotations are ignored",
+                    API_DOC_ANNOTATION_PREFIX
+                ),
+            );
+        }
+
+        (parsed, issues)
+    }
+
+    /// Returns only the issues found in the annotations
+    pub fn validate(annotations: &BTreeMap<String, String>) -> Vec<AnnotationIssue> {
+        Self::parse(annotations).1
+    }
+
+    /// Whether the spec is fetched over TLS
+    pub fn uses_https(&self) -> bool {
+        match &self.url {
+            Some(url) => url.starts_with("https://"),
+            None => self.scheme == UrlScheme::Https,
+        }
+    }
+}
+
+/// Checks that `value` is an absolute `http` or `https` URL
+fn spec_url(value: &str) -> Result<String, String> {
+    let url =
+        reqwest::Url::parse(value).map_err(|e| format!("invalid URL \"{}\": {}", value, e))?;
+    if !matches!(url.scheme(), "http" | "https") || !url.has_host() {
+        return Err(format!(
+            "must be an http or https URL with a host, got \"{}\"",
+            value
+        ));
+    }
+    Ok(url.to_string())
+}
+
+/// Parses an interval in seconds (`"300"`) or with a unit (`"30s"`, `"5m"`, `"1h"`, `"1d"`)
+pub fn parse_interval(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(at) => value.split_at(at),
+        None => (value, "s"),
+    };
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => 0,
+    };
+    match number.parse::<u64>() {
+        Ok(count) if count > 0 && multiplier > 0 => Ok(count.saturating_mul(multiplier)),
+        _ => Err(format!(
+            "must be a positive number of seconds or a duration such as \"5m\" or \"1h\", got \"{}\"",
+            value
+        )),
+    }
+}
+
+/// Suggests a known annotation for a likely typo (edit distance of at most 2)
+fn closest_known_annotation(key: &str) -> Option<&'static str> {
+    KNOWN_ANNOTATIONS
+        .iter()
+        .map(|known| (*known, edit_distance(key, known)))
+        .filter(|(_, distance)| *distance <= 2)
+        .min_by_key(|(_, distance)| *distance)
+        .map(|(known, _)| known)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            current.push((previous[j] + cost).min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn annotations(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn valid_annotations_have_no_issues() {
+        let (parsed, issues) = ApiDocAnnotations::parse(&annotations(&[
+            ("api-doc.io/enabled", "true"),
+            ("api-doc.io/path", "/openapi.json"),
+            ("api-doc.io/name", "Users"),
+            ("api-doc.io/lifecycle", "beta"),
+            ("api-doc.io/port", "http-metrics"),
+        ]));
+        assert!(issues.is_empty());
+        assert!(parsed.enabled);
+        assert_eq!(parsed.path, "/openapi.json");
+        assert_eq!(parsed.name.as_deref(), Some("Users"));
+        assert_eq!(parsed.lifecycle, Some(Lifecycle::Beta));
+        assert_eq!(parsed.port, Some(PortRef::Name("http-metrics".to_string())));
+    }
+
+    #[test]
+    fn port_name_wins_over_port_number() {
+        let (parsed, issues) = ApiDocAnnotations::parse(&annotations(&[
+            ("api-doc.io/enabled", "true"),
+            ("api-doc.io/port", "8080"),
+            ("api-doc.io/port-name", "http-management"),
+        ]));
+        assert_eq!(
+            parsed.port,
+            Some(PortRef::Name("http-management".to_string()))
+        );
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, IssueSeverity::Warning);
+
+        let (parsed, issues) =
+            ApiDocAnnotations::parse(&annotations(&[("api-doc.io/port-name", "8080")]));
+        assert_eq!(parsed.port, None);
+        assert_eq!(issues[0].annotation, "api-doc.io/port-name");
+
+        // The Service's port replaces the Namespace's, whichever annotation either uses
+        let merged = with_namespace_defaults(
+            Some(&annotations(&[("api-doc.io/port-name", "http-management")])),
+            &annotations(&[("api-doc.io/port", "9090")]),
+        );
+        assert_eq!(
+            ApiDocAnnotations::parse(&merged).0.port,
+            Some(PortRef::Number(9090))
+        );
+    }
+
+    #[test]
+    fn parses_scheme_and_tls_options() {
+        let (parsed, issues) = ApiDocAnnotations::parse(&annotations(&[
+            ("api-doc.io/enabled", "true"),
+            ("api-doc.io/scheme", "HTTPS"),
+            ("api-doc.io/insecure-skip-verify", "true"),
+            ("api-doc.io/tls-secret", "orders-client-tls"),
+        ]));
+        assert!(issues.is_empty());
+        assert_eq!(parsed.scheme, UrlScheme::Https);
+        assert!(parsed.insecure_skip_verify);
+        assert_eq!(parsed.tls_secret.as_deref(), Some("orders-client-tls"));
+
+        let (parsed, issues) = ApiDocAnnotations::parse(&annotations(&[
+            ("api-doc.io/enabled", "true"),
+            ("api-doc.io/scheme", "grpc"),
+            ("api-doc.io/insecure-skip-verify", "true"),
+            ("api-doc.io/tls-secret", "Orders_TLS"),
+        ]));
+        assert_eq!(parsed.scheme, UrlScheme::Http);
+        assert_eq!(parsed.tls_secret, None);
+        let flagged: Vec<_> = issues.iter().map(|i| i.annotation.as_str()).collect();
+        assert_eq!(
+            flagged,
+            vec![
+                "api-doc.io/scheme",
+                "api-doc.io/insecure-skip-verify",
+                "api-doc.io/tls-secret",
+                "api-doc.io/tls-secret"
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_external_url() {
+        let (parsed, issues) = ApiDocAnnotations::parse(&annotations(&[
+            ("api-doc.io/enabled", "true"),
+            ("api-doc.io/url", "https://docs.example.com/openapi.yaml"),
+            ("api-doc.io/insecure-skip-verify", "true"),
+        ]));
+        assert!(issues.is_empty());
+        assert_eq!(
+            parsed.url.as_deref(),
+            Some("https://docs.example.com/openapi.yaml")
+        );
+        assert!(parsed.uses_https());
+
+        let (parsed, issues) = ApiDocAnnotations::parse(&annotations(&[
+            ("api-doc.io/enabled", "true"),
+            ("api-doc.io/url", "/openapi.yaml"),
+        ]));
+        assert_eq!(parsed.url, None);
+        assert_eq!(issues[0].annotation, "api-doc.io/url");
+    }
+
+    #[test]
+    fn parses_spec_configmap() {
+        let (parsed, issues) = ApiDocAnnotations::parse(&annotations(&[
+            ("api-doc.io/enabled", "true"),
+            ("api-doc.io/spec-configmap", "orders-openapi/openapi.yaml"),
+        ]));
+        assert!(issues.is_empty());
+        assert_eq!(
+            parsed.spec_config_map,
+            Some(SpecConfigMapRef {
+                name: "orders-openapi".to_string(),
+                key: "openapi.yaml".to_string(),
+            })
+        );
+
+        for invalid in ["orders-openapi", "Orders/openapi.yaml", "orders/open api.yaml"] {
+            let (parsed, issues) = ApiDocAnnotations::parse(&annotations(&[
+                ("api-doc.io/enabled", "true"),
+                ("api-doc.io/spec-configmap", invalid),
+            ]));
+            assert_eq!(parsed.spec_config_map, None, "{}", invalid);
+            assert_eq!(issues[0].annotation, "api-doc.io/spec-configmap");
+        }
+    }
+
+    #[test]
+    fn rewrites_servers() {
+        let (parsed, issues) = ApiDocAnnotations::parse(&annotations(&[
+            ("api-doc.io/enabled", "true"),
+            (
+                "api-doc.io/server-url",
+                "https://api.example.com/orders/, cluster, localhost:8080",
+            ),
+        ]));
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].annotation, "api-doc.io/server-url");
+        assert_eq!(
+            parsed.server_urls,
+            vec![
+                ServerUrl::Url("https://api.example.com/orders".to_string()),
+                ServerUrl::Cluster,
+            ]
+        );
+
+        let spec = "openapi: 3.0.3\ninfo:\n  title: Orders\n  version: '1'\nservers:\n- url: http://localhost:8080/api/v1\npaths: {}\n";
+        let rewritten = crate::spec_utils::rewrite_servers(
+            spec,
+            &parsed.server_urls,
+            Some("http://orders.shop.svc.cluster.local:8080"),
+        )
+        .unwrap();
+        let document = crate::spec_utils::parse_spec_to_json(&rewritten).unwrap();
+        assert_eq!(
+            document["servers"],
+            serde_json::json!([
+                { "url": "https://api.example.com/orders" },
+                { "url": "http://orders.shop.svc.cluster.local:8080/api/v1" },
+            ])
+        );
+        assert!(!rewritten.trim_start().starts_with('{'));
+
+        // Without a cluster URL, a lone `cluster` keeps the spec's servers
+        let unchanged =
+            crate::spec_utils::rewrite_servers(spec, &[ServerUrl::Cluster], None).unwrap();
+        assert_eq!(unchanged, spec);
+    }
+
+    #[test]
+    fn parses_fetch_via() {
+        let (parsed, issues) = ApiDocAnnotations::parse(&annotations(&[
+            ("api-doc.io/enabled", "true"),
+            ("api-doc.io/fetch-via", "APIServer"),
+        ]));
+        assert!(issues.is_empty());
+        assert_eq!(parsed.fetch_via, Some(FetchVia::ApiServer));
+
+        let (parsed, issues) = ApiDocAnnotations::parse(&annotations(&[
+            ("api-doc.io/enabled", "true"),
+            ("api-doc.io/fetch-via", "ingress"),
+        ]));
+        assert_eq!(parsed.fetch_via, None);
+        assert_eq!(issues[0].annotation, "api-doc.io/fetch-via");
+    }
+
+    #[test]
+    fn resolves_ports_by_number_or_name() {
+        let ports = [(Some("http"), 80), (Some("http-metrics"), 9090)];
+        assert_eq!(PortRef::parse("8080").unwrap().resolve(ports), Some(8080));
+        assert_eq!(
+            PortRef::parse("http-metrics").unwrap().resolve(ports),
+            Some(9090)
+        );
+        assert_eq!(PortRef::parse("grpc").unwrap().resolve(ports), None);
+        assert!(PortRef::parse("70000").is_err());
+        assert!(PortRef::parse("HTTP").is_err());
+    }
+
+    #[test]
+    fn service_annotations_override_namespace_defaults() {
+        let namespace = annotations(&[
+            ("api-doc.io/enabled", "true"),
+            ("api-doc.io/path", "/v3/api-docs"),
+            ("api-doc.io/name", "Shared"),
+        ]);
+        let service = annotations(&[("api-doc.io/path", "/openapi.json")]);
+        let (parsed, issues) =
+            ApiDocAnnotations::parse(&with_namespace_defaults(Some(&namespace), &service));
+        assert!(issues.is_empty());
+        assert!(parsed.enabled);
+        assert_eq!(parsed.path, "/openapi.json");
+        assert_eq!(parsed.name, None);
+
+        let opted_out = annotations(&[("api-doc.io/enabled", "false")]);
+        let (parsed, _) =
+            ApiDocAnnotations::parse(&with_namespace_defaults(Some(&namespace), &opted_out));
+        assert!(!parsed.enabled);
+
+        let excluded = annotations(&[("api-doc.io/exclude", "true")]);
+        let opted_in = annotations(&[("api-doc.io/exclude", "false")]);
+        let (parsed, _) =
+            ApiDocAnnotations::parse(&with_namespace_defaults(Some(&excluded), &opted_in));
+        assert!(parsed.exclude);
+    }
+
+    #[test]
+    fn collects_ui_options() {
+        let (parsed, issues) = ApiDocAnnotations::parse(&annotations(&[
+            ("api-doc.io/enabled", "true"),
+            ("api-doc.io/ui.theme", "deepSpace"),
+            ("api-doc.io/ui.dark-mode", "true"),
+            ("api-doc.io/ui.redoc.hideDownloadButton", "true"),
+            ("api-doc.io/ui.redoc.<script>", "x"),
+        ]));
+        assert_eq!(parsed.ui.theme.as_deref(), Some("deepSpace"));
+        assert_eq!(parsed.ui.dark_mode, Some(true));
+        assert_eq!(
+            parsed.ui.redoc.keys().collect::<Vec<_>>(),
+            vec!["hideDownloadButton"]
+        );
+        let flagged: Vec<_> = issues.iter().map(|i| i.annotation.as_str()).collect();
+        assert_eq!(flagged, vec!["api-doc.io/ui.redoc.<script>"]);
+    }
+
+    #[test]
+    fn parses_intervals() {
+        assert_eq!(parse_interval("300"), Ok(300));
+        assert_eq!(parse_interval("5m"), Ok(300));
+        assert_eq!(parse_interval("1h"), Ok(3600));
+        assert!(parse_interval("0").is_err());
+        assert!(parse_interval("5 min").is_err());
+        assert!(parse_interval("").is_err());
+    }
+
+    #[test]
+    fn defaults_apply_without_annotations() {
+        let (parsed, issues) = ApiDocAnnotations::parse(&BTreeMap::new());
+        assert!(issues.is_empty());
+        assert_eq!(parsed, ApiDocAnnotations::default());
+    }
+
+    #[test]
+    fn reports_invalid_values_and_typos() {
+        let issues = ApiDocAnnotations::validate(&annotations(&[
+            ("api-doc.io/enabled", "True"),
+            ("api-doc.io/path", "openapi.json"),
+            ("api-doc.io/descripton", "typo"),
+        ]));
+        let errors: Vec<_> = issues
+            .iter()
+            .filter(|i| i.severity == IssueSeverity::Error)
+            .map(|i| i.annotation.as_str())
+            .collect();
+        assert_eq!(errors, vec!["api-doc.io/enabled", "api-doc.io/path"]);
+        assert!(issues
+            .iter()
+            .any(|i| i.message.contains("did you mean api-doc.io/description")));
+    }
+
+    #[test]
+    fn warns_when_enabled_is_missing() {
+        let issues = ApiDocAnnotations::validate(&annotations(&[("api-doc.io/path", "/docs")]));
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].annotation, "api-doc.io/enabled");
+    }
+}
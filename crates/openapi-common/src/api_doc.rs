@@ -0,0 +1,275 @@
+//! `ApiDoc` custom resource, declarative registration of an API.
+//!
+//! Annotations are untyped strings on someone else's object. An `ApiDoc` names a Service
+//! in its namespace and describes how to fetch and present its spec, validated by the API
+//! server against the CRD schema. With `WATCH_API_DOCS=true` the operator reconciles
+//! `ApiDoc`s into the same catalog as annotated Services; an `ApiDoc` takes precedence
+//! over the annotations of the Service it names.
+
+use k8s_openapi::api::core::v1::Secret;
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use kube::{CustomResource, CustomResourceExt};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::annotations::{ApiDocAnnotations, PortRef, UrlScheme, parse_interval};
+use crate::fetch::FetchAuth;
+use crate::schedule::RefreshWindow;
+use crate::{DEFAULT_API_DOC_PATH, Lifecycle, UiOptions};
+
+/// Full name of the `ApiDoc` CRD
+pub const CRD_NAME: &str = "apidocs.api-doc.io";
+
+/// Keeps an `ApiDoc` until its entry has left the catalog
+pub const FINALIZER: &str = "api-doc.io/catalog-entry";
+
+/// Registers the spec of a Service in the catalog
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(
+    group = "api-doc.io",
+    version = "v1alpha1",
+    kind = "ApiDoc",
+    plural = "apidocs",
+    shortname = "apidoc",
+    namespaced,
+    status = "ApiDocStatus",
+    printcolumn = r#"{"name":"Service","type":"string","jsonPath":".spec.service"}"#,
+    printcolumn = r#"{"name":"Available","type":"boolean","jsonPath":".status.available"}"#,
+    printcolumn = r#"{"name":"URL","type":"string","jsonPath":".status.url","priority":1}"#,
+    printcolumn = r#"{"name":"Age","type":"date","jsonPath":".metadata.creationTimestamp"}"#
+)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiDocSpec {
+    /// Service in the same namespace serving the spec
+    pub service: String,
+    /// Path of the spec on the Service, `/swagger/openapi.yml` by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Port number or name of the Service's port, its first port by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "port_schema")]
+    pub port: Option<IntOrString>,
+    /// `http` by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "scheme_schema")]
+    pub scheme: Option<UrlScheme>,
+    /// Accept any certificate of an `https` Service
+    #[serde(default)]
+    pub insecure_skip_verify: bool,
+    /// `kubernetes.io/tls` Secret in the same namespace with the client certificate
+    /// presented to an `https` Service requiring mTLS
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_secret_name: Option<String>,
+    /// Display name, `<service> API` by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "crate::crd::lifecycle_schema")]
+    pub lifecycle: Option<Lifecycle>,
+    /// Credentials sent when fetching the spec
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<ApiDocAuth>,
+    /// Time between fetches of the spec by the documentation server, e.g. `10m`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_interval: Option<String>,
+    /// Cron-like window the spec is fetched in, see `REFRESH_WINDOW`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_window: Option<String>,
+    /// Logical API this is a version of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_group: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ui: Option<UiOptions>,
+    /// Publish the spec to the configured cloud API gateways
+    #[serde(default)]
+    pub gateway_publish: bool,
+    /// Periodically call documented `GET` operations and report drift from the spec
+    #[serde(default)]
+    pub contract_check: bool,
+    /// Publish new spec revisions even when they contain breaking changes
+    #[serde(default)]
+    pub allow_breaking: bool,
+}
+
+/// Secret in the `ApiDoc`'s namespace holding the credentials
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiDocAuth {
+    pub secret_name: String,
+    #[serde(default, rename = "type")]
+    pub auth_type: AuthType,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthType {
+    /// `Authorization: Bearer` with the Secret's `token` key
+    #[default]
+    Bearer,
+    /// Basic auth with the Secret's `username` and `password` keys
+    Basic,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiDocStatus {
+    pub available: bool,
+    /// URL the spec is fetched from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Why the API is not in the catalog
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub observed_generation: Option<i64>,
+}
+
+/// Integer or string, like a Service's `targetPort`
+fn port_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+    schemars::json_schema!({
+        "description": "Port number or name of the Service's port, its first port by default",
+        "x-kubernetes-int-or-string": true,
+        "anyOf": [{ "type": "integer" }, { "type": "string" }],
+    })
+}
+
+fn scheme_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+    schemars::json_schema!({
+        "description": "Scheme of the spec URL, `http` by default",
+        "type": "string",
+        "enum": ["http", "https"],
+    })
+}
+
+impl ApiDocSpec {
+    /// The spec as the annotations the operator discovers Services with, or why it is invalid
+    pub fn annotations(&self) -> Result<ApiDocAnnotations, String> {
+        let path = self
+            .path
+            .clone()
+            .unwrap_or_else(|| DEFAULT_API_DOC_PATH.to_string());
+        if !path.starts_with('/') || path.chars().any(char::is_whitespace) {
+            return Err(format!(
+                "path must start with '/' and contain no whitespace, got \"{}\"",
+                path
+            ));
+        }
+        let port = match &self.port {
+            Some(IntOrString::Int(port)) => Some(PortRef::parse(&port.to_string())),
+            Some(IntOrString::String(name)) => Some(PortRef::parse(name)),
+            None => None,
+        }
+        .transpose()
+        .map_err(|e| format!("port {}", e))?;
+        let refresh_interval = self
+            .refresh_interval
+            .as_deref()
+            .map(parse_interval)
+            .transpose()
+            .map_err(|e| format!("refreshInterval {}", e))?;
+        let refresh_window = self
+            .refresh_window
+            .as_deref()
+            .map(RefreshWindow::parse)
+            .transpose()
+            .map_err(|e| format!("refreshWindow: {}", e))?;
+        if let Some(group) = &self.api_group {
+            crate::names::validate_dns_label(group).map_err(|e| format!("apiGroup: {}", e))?;
+        }
+        if let Some(secret) = &self.tls_secret_name {
+            crate::names::validate_dns_subdomain(secret)
+                .map_err(|e| format!("tlsSecretName: {}", e))?;
+        }
+
+        Ok(ApiDocAnnotations {
+            enabled: true,
+            path,
+            url: None,
+            spec_config_map: None,
+            server_urls: Vec::new(),
+            name: self.name.clone().filter(|name| !name.trim().is_empty()),
+            description: self.description.clone(),
+            gateway_publish: self.gateway_publish,
+            contract_check: self.contract_check,
+            allow_breaking: self.allow_breaking,
+            lifecycle: self.lifecycle,
+            port,
+            scheme: self.scheme.unwrap_or_default(),
+            insecure_skip_verify: self.insecure_skip_verify,
+            tls_secret: self.tls_secret_name.clone(),
+            auth_secret: None,
+            fetch_via: None,
+            exclude: false,
+            ui: self.ui.clone().unwrap_or_default(),
+            refresh_interval,
+            refresh_window,
+            api_group: self.api_group.clone(),
+        })
+    }
+}
+
+impl ApiDocAuth {
+    /// Credentials from the referenced Secret, or which key is missing
+    pub fn credentials(&self, secret: &Secret) -> Result<FetchAuth, String> {
+        let key = |name: &str| -> Option<String> {
+            let data = secret.data.as_ref()?.get(name)?;
+            String::from_utf8(data.0.clone())
+                .ok()
+                .map(|value| value.trim_end().to_string())
+        };
+        let missing = |name: &str| format!("Secret {} has no '{}' key", self.secret_name, name);
+        match self.auth_type {
+            AuthType::Bearer => key("token")
+                .map(FetchAuth::Bearer)
+                .ok_or_else(|| missing("token")),
+            AuthType::Basic => Ok(FetchAuth::Basic {
+                username: key("username").ok_or_else(|| missing("username"))?,
+                password: key("password"),
+            }),
+        }
+    }
+}
+
+/// The CRD manifest, generated from the Rust types
+pub fn crd() -> CustomResourceDefinition {
+    ApiDoc::crd()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spec_becomes_annotations() {
+        let spec: ApiDocSpec = serde_json::from_value(serde_json::json!({
+            "service": "orders",
+            "port": "http-admin",
+            "refreshInterval": "10m",
+            "lifecycle": "beta",
+        }))
+        .unwrap();
+        let annotations = spec.annotations().unwrap();
+        assert!(annotations.enabled);
+        assert_eq!(annotations.path, DEFAULT_API_DOC_PATH);
+        assert_eq!(
+            annotations.port,
+            Some(PortRef::Name("http-admin".to_string()))
+        );
+        assert_eq!(annotations.refresh_interval, Some(600));
+        assert_eq!(annotations.lifecycle, Some(Lifecycle::Beta));
+
+        let invalid = ApiDocSpec {
+            port: Some(IntOrString::Int(70000)),
+            ..spec.clone()
+        };
+        assert!(invalid.annotations().is_err());
+        let invalid = ApiDocSpec {
+            refresh_window: Some("* * *".to_string()),
+            ..spec
+        };
+        assert!(invalid.annotations().is_err());
+    }
+}
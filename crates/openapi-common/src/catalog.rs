@@ -0,0 +1,257 @@
+//! The discovery catalog as the operator writes it to its ConfigMap, read through the
+//! Kubernetes API by the CLI, the kubectl plugin and the doc server.
+//!
+//! The discovery ConfigMap holds one payload under the key of its encoding. A catalog too
+//! large for one ConfigMap lists the shards its other entries are in, and with
+//! `DISCOVERY_LAYOUT=entries` every entry has a key of its own next to the payload.
+
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{Api, Client};
+use std::fmt;
+
+use crate::encoding::{self, DiscoveryEncoding, EncodingError};
+use crate::fetch::{FetchConfig, FetchError, FetchOutcome, FetchRequest, SpecFetcher};
+use crate::{ApiInventoryEntry, DiscoveryConfig};
+
+#[derive(Debug)]
+pub enum CatalogError {
+    Kube(kube::Error),
+    Encoding(EncodingError),
+    Fetch(FetchError),
+    /// The discovery ConfigMap does not exist
+    NotFound {
+        namespace: String,
+        name: String,
+    },
+    /// The ConfigMap has no key of any encoding
+    NoPayload(String),
+    /// No entry has the name, id or service name looked up
+    UnknownApi(String),
+    /// Several entries have the name or service name looked up
+    Ambiguous {
+        query: String,
+        ids: Vec<String>,
+    },
+    /// The server answered a request without validators with 304
+    NotModified(String),
+}
+
+impl fmt::Display for CatalogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CatalogError::Kube(e) => write!(f, "Kubernetes error: {}", e),
+            CatalogError::Encoding(e) => write!(f, "Discovery encoding error: {}", e),
+            CatalogError::Fetch(e) => write!(f, "Spec fetch error: {}", e),
+            CatalogError::NotFound { namespace, name } => write!(
+                f,
+                "Discovery ConfigMap '{}' not found in namespace '{}'",
+                name, namespace
+            ),
+            CatalogError::NoPayload(name) => {
+                write!(f, "ConfigMap '{}' has no discovery payload", name)
+            }
+            CatalogError::UnknownApi(query) => {
+                write!(f, "No API named '{}' in the catalog", query)
+            }
+            CatalogError::Ambiguous { query, ids } => write!(
+                f,
+                "'{}' is ambiguous, use one of the ids: {}",
+                query,
+                ids.join(", ")
+            ),
+            CatalogError::NotModified(url) => {
+                write!(f, "Unexpected 304 response from {}", url)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CatalogError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CatalogError::Kube(e) => Some(e),
+            CatalogError::Encoding(e) => Some(e),
+            CatalogError::Fetch(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<kube::Error> for CatalogError {
+    fn from(err: kube::Error) -> Self {
+        CatalogError::Kube(err)
+    }
+}
+
+impl From<EncodingError> for CatalogError {
+    fn from(err: EncodingError) -> Self {
+        CatalogError::Encoding(err)
+    }
+}
+
+impl From<FetchError> for CatalogError {
+    fn from(err: FetchError) -> Self {
+        CatalogError::Fetch(err)
+    }
+}
+
+/// Discovery payload of a ConfigMap; whichever encoding the operator is configured with,
+/// only one key is set
+pub fn configmap_payload(configmap: &ConfigMap) -> Option<Vec<u8>> {
+    DiscoveryEncoding::ALL.iter().find_map(|encoding| {
+        if encoding.is_binary() {
+            configmap
+                .binary_data
+                .as_ref()
+                .and_then(|d| d.get(encoding.key()))
+                .map(|b| b.0.clone())
+        } else {
+            configmap
+                .data
+                .as_ref()
+                .and_then(|d| d.get(encoding.key()))
+                .map(|s| s.clone().into_bytes())
+        }
+    })
+}
+
+/// Payloads of the entries stored under their own keys
+pub fn entry_payloads(configmap: &ConfigMap) -> Vec<Vec<u8>> {
+    let data = configmap
+        .data
+        .iter()
+        .flatten()
+        .filter(|(key, _)| encoding::is_entry_key(key))
+        .map(|(_, value)| value.clone().into_bytes());
+    let binary_data = configmap
+        .binary_data
+        .iter()
+        .flatten()
+        .filter(|(key, _)| encoding::is_entry_key(key))
+        .map(|(_, value)| value.0.clone());
+    data.chain(binary_data).collect()
+}
+
+/// Entries of the discovery ConfigMap `name`, including those of its shards and the ones
+/// under keys of their own
+pub async fn read_configmap(
+    client: Client,
+    namespace: &str,
+    name: &str,
+) -> Result<Vec<ApiInventoryEntry>, CatalogError> {
+    let configmaps: Api<ConfigMap> = Api::namespaced(client, namespace);
+    let configmap = configmaps
+        .get_opt(name)
+        .await?
+        .ok_or_else(|| CatalogError::NotFound {
+            namespace: namespace.to_string(),
+            name: name.to_string(),
+        })?;
+    let payload =
+        configmap_payload(&configmap).ok_or_else(|| CatalogError::NoPayload(name.to_string()))?;
+    let config = encoding::decode::<DiscoveryConfig>(&payload)?;
+    let mut apis = config.apis;
+    for shard_name in &config.shards {
+        let shard = configmaps.get(shard_name).await?;
+        let payload = configmap_payload(&shard)
+            .ok_or_else(|| CatalogError::NoPayload(shard_name.to_string()))?;
+        apis.extend(encoding::decode::<DiscoveryConfig>(&payload)?.apis);
+    }
+    for payload in entry_payloads(&configmap) {
+        let entry = encoding::decode::<ApiInventoryEntry>(&payload)?;
+        apis.retain(|api| api.id != entry.id);
+        apis.push(entry);
+    }
+    Ok(apis)
+}
+
+/// Finds a single entry by display name, id (`namespace.service`), or service name
+pub fn find_entry<'a>(
+    apis: &'a [ApiInventoryEntry],
+    query: &str,
+) -> Result<&'a ApiInventoryEntry, CatalogError> {
+    let matches: Vec<&ApiInventoryEntry> = apis
+        .iter()
+        .filter(|api| api.name == query || api.id == query || api.service_name == query)
+        .collect();
+
+    match matches.as_slice() {
+        [] => Err(CatalogError::UnknownApi(query.to_string())),
+        [api] => Ok(api),
+        _ => Err(CatalogError::Ambiguous {
+            query: query.to_string(),
+            ids: matches.iter().map(|api| api.id.clone()).collect(),
+        }),
+    }
+}
+
+/// Downloads a spec with the fetch settings of the environment (`SPEC_FETCH_*`)
+pub async fn fetch_url(url: &str) -> Result<String, CatalogError> {
+    let fetcher = SpecFetcher::new(FetchConfig::from_env()?)?;
+    match fetcher.fetch(url, &FetchRequest::default()).await? {
+        FetchOutcome::Fetched(spec) => Ok(spec.body),
+        FetchOutcome::NotModified => Err(CatalogError::NotModified(url.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::ByteString;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn payloads_are_read_from_the_key_of_any_encoding() {
+        let entry = ApiInventoryEntry::new("shop", "orders", "http://orders.shop.svc/openapi.json");
+        let catalog = DiscoveryConfig {
+            apis: Vec::new(),
+            last_updated: chrono::Utc::now(),
+            tombstones: Vec::new(),
+            shards: Vec::new(),
+        };
+        let gzip = DiscoveryEncoding::Gzip;
+        let configmap = ConfigMap {
+            data: Some(BTreeMap::from([(
+                DiscoveryEncoding::Json.entry_key(&entry),
+                serde_json::to_string(&entry).unwrap(),
+            )])),
+            binary_data: Some(BTreeMap::from([(
+                gzip.key().to_string(),
+                ByteString(gzip.encode(&catalog).unwrap()),
+            )])),
+            ..Default::default()
+        };
+
+        let payload = configmap_payload(&configmap).unwrap();
+        assert!(encoding::decode::<DiscoveryConfig>(&payload).is_ok());
+        let entries = entry_payloads(&configmap);
+        assert_eq!(entries.len(), 1);
+        let decoded = encoding::decode::<ApiInventoryEntry>(&entries[0]).unwrap();
+        assert_eq!(decoded.id, "shop.orders");
+        assert_eq!(configmap_payload(&ConfigMap::default()), None);
+    }
+
+    #[test]
+    fn entries_are_found_by_name_id_or_service_name() {
+        let apis = [
+            ApiInventoryEntry::new("shop", "orders", "http://orders.shop.svc/openapi.json"),
+            ApiInventoryEntry::new("legacy", "orders", "http://orders.legacy.svc/openapi.json"),
+            ApiInventoryEntry::new("shop", "carts", "http://carts.shop.svc/openapi.json"),
+        ];
+
+        assert_eq!(find_entry(&apis, "carts API").unwrap().id, "shop.carts");
+        assert_eq!(find_entry(&apis, "carts").unwrap().id, "shop.carts");
+        assert_eq!(
+            find_entry(&apis, "legacy.orders").unwrap().id,
+            "legacy.orders"
+        );
+        assert_eq!(
+            find_entry(&apis, "orders").unwrap_err().to_string(),
+            "'orders' is ambiguous, use one of the ids: shop.orders, legacy.orders"
+        );
+        assert!(matches!(
+            find_entry(&apis, "payments"),
+            Err(CatalogError::UnknownApi(_))
+        ));
+    }
+}
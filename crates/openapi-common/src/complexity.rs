@@ -0,0 +1,245 @@
+//! Complexity metrics of OpenAPI documents.
+//!
+//! Computed by the operator for every catalog entry, by the doc server for `/stats`, and by
+//! the CLI for catalog APIs or local files, so all three report the same numbers. Schemas
+//! are walked on the raw document because the typed model does not keep them; local
+//! `$ref`s are followed, recursive schemas count their cycle once.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::OpenApiDocument;
+
+/// Size and shape of one spec
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "crd", derive(schemars::JsonSchema))]
+pub struct SpecMetrics {
+    pub paths: u32,
+    pub operations: u32,
+    /// Named schemas (`components.schemas`, or `definitions` in Swagger 2.0)
+    pub schemas: u32,
+    /// Deepest nesting of object properties and array items over all schemas
+    pub max_schema_depth: u32,
+    /// Average number of parameters per operation, path-level parameters included
+    pub avg_parameters: f64,
+    /// Size of the spec as served
+    pub size_bytes: u64,
+}
+
+impl SpecMetrics {
+    /// Parses a JSON or YAML spec and measures it
+    pub fn compute(content: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let value = crate::spec_utils::parse_spec_to_json(content)?;
+        let document = OpenApiDocument::from_value(value.clone())?;
+        Ok(Self::measure(&document, &value, content.len()))
+    }
+
+    /// Measures an already parsed spec; `value` is the raw document behind `document`
+    pub fn measure(document: &OpenApiDocument, value: &Value, size_bytes: usize) -> Self {
+        let mut operations = 0u32;
+        let mut parameters = 0usize;
+        for item in document.paths.values() {
+            for (_, operation) in item.operations() {
+                operations += 1;
+                parameters += operation.effective_parameters(item).len();
+            }
+        }
+
+        let named_schemas = value
+            .pointer("/components/schemas")
+            .or_else(|| value.get("definitions"))
+            .and_then(Value::as_object);
+        let mut walker = DepthWalker {
+            root: value,
+            memo: HashMap::new(),
+            visiting: Vec::new(),
+        };
+        let mut max_schema_depth = named_schemas
+            .into_iter()
+            .flat_map(|schemas| schemas.values())
+            .map(|schema| walker.depth(schema))
+            .max()
+            .unwrap_or(0);
+        for schema in inline_schemas(value) {
+            max_schema_depth = max_schema_depth.max(walker.depth(schema));
+        }
+
+        Self {
+            paths: document.paths.len() as u32,
+            operations,
+            schemas: named_schemas.map_or(0, |schemas| schemas.len() as u32),
+            max_schema_depth,
+            avg_parameters: if operations == 0 {
+                0.0
+            } else {
+                parameters as f64 / f64::from(operations)
+            },
+            size_bytes: size_bytes as u64,
+        }
+    }
+}
+
+/// Request, response and parameter schemas declared inline in operations
+fn inline_schemas(value: &Value) -> Vec<&Value> {
+    let mut schemas = Vec::new();
+    let Some(paths) = value.get("paths").and_then(Value::as_object) else {
+        return schemas;
+    };
+    for item in paths.values().filter_map(Value::as_object) {
+        for operation in item.values().filter_map(Value::as_object) {
+            if let Some(body) = operation.get("requestBody") {
+                schemas.extend(media_schemas(body));
+            }
+            for parameter in operation
+                .get("parameters")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+            {
+                schemas.extend(media_schemas(parameter));
+            }
+            for response in operation
+                .get("responses")
+                .and_then(Value::as_object)
+                .into_iter()
+                .flat_map(|responses| responses.values())
+            {
+                schemas.extend(media_schemas(response));
+            }
+        }
+    }
+    schemas
+}
+
+/// Schemas of a parameter, request body or response: OpenAPI 3 nests them under
+/// `content.<media type>`, Swagger 2.0 has a single `schema`
+fn media_schemas(holder: &Value) -> Vec<&Value> {
+    let mut found: Vec<&Value> = holder
+        .get("content")
+        .and_then(Value::as_object)
+        .into_iter()
+        .flat_map(|content| content.values())
+        .filter_map(|media| media.get("schema"))
+        .collect();
+    found.extend(holder.get("schema"));
+    found
+}
+
+struct DepthWalker<'a> {
+    root: &'a Value,
+    /// Depth of every `$ref` target measured so far
+    memo: HashMap<String, u32>,
+    /// `$ref`s currently being measured, to stop at recursive schemas
+    visiting: Vec<String>,
+}
+
+impl<'a> DepthWalker<'a> {
+    /// Levels of nesting: a schema without properties or items has depth 1
+    fn depth(&mut self, schema: &'a Value) -> u32 {
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            return self.reference_depth(reference);
+        }
+        let Some(object) = schema.as_object() else {
+            return 0;
+        };
+
+        let mut nested = 0;
+        for property in object
+            .get("properties")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flat_map(|properties| properties.values())
+        {
+            nested = nested.max(self.depth(property));
+        }
+        for key in ["items", "additionalProperties"] {
+            if let Some(child) = object.get(key).filter(|child| child.is_object()) {
+                nested = nested.max(self.depth(child));
+            }
+        }
+
+        // Composed schemas stay on the same level as the schema composing them
+        let mut composed = 0;
+        for key in ["allOf", "anyOf", "oneOf"] {
+            for part in object
+                .get(key)
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+            {
+                composed = composed.max(self.depth(part));
+            }
+        }
+        (1 + nested).max(composed)
+    }
+
+    fn reference_depth(&mut self, reference: &str) -> u32 {
+        if let Some(depth) = self.memo.get(reference) {
+            return *depth;
+        }
+        if self.visiting.iter().any(|r| r == reference) {
+            return 0;
+        }
+        // Only local references (`#/components/schemas/...`) can be followed
+        let Some(target) = reference
+            .strip_prefix('#')
+            .and_then(|pointer| self.root.pointer(pointer))
+        else {
+            return 1;
+        };
+
+        self.visiting.push(reference.to_string());
+        let depth = self.depth(target);
+        self.visiting.pop();
+        self.memo.insert(reference.to_string(), depth);
+        depth
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_operations_parameters_and_schema_depth() {
+        let spec = r##"{
+            "openapi": "3.0.3",
+            "info": {"title": "Orders", "version": "1"},
+            "paths": {
+                "/orders": {
+                    "get": {
+                        "parameters": [{"name": "limit", "in": "query"}],
+                        "responses": {"200": {"description": "ok", "content": {"application/json": {
+                            "schema": {"type": "array", "items": {"$ref": "#/components/schemas/Order"}}
+                        }}}}
+                    },
+                    "post": {"responses": {"201": {"description": "created"}}}
+                },
+                "/orders/{id}": {
+                    "parameters": [{"name": "id", "in": "path"}],
+                    "get": {"parameters": [{"name": "expand", "in": "query"}], "responses": {}}
+                }
+            },
+            "components": {"schemas": {
+                "Order": {"type": "object", "properties": {
+                    "id": {"type": "string"},
+                    "customer": {"$ref": "#/components/schemas/Customer"},
+                    "parent": {"$ref": "#/components/schemas/Order"}
+                }},
+                "Customer": {"type": "object", "properties": {
+                    "address": {"type": "object", "properties": {"city": {"type": "string"}}}
+                }}
+            }}
+        }"##;
+
+        let metrics = SpecMetrics::compute(spec).unwrap();
+        assert_eq!(metrics.paths, 2);
+        assert_eq!(metrics.operations, 3);
+        assert_eq!(metrics.schemas, 2);
+        // array -> Order -> Customer -> address -> city
+        assert_eq!(metrics.max_schema_depth, 5);
+        assert_eq!(metrics.avg_parameters, 1.0);
+        assert_eq!(metrics.size_bytes, spec.len() as u64);
+    }
+}
@@ -0,0 +1,567 @@
+//! Live contract checks: call documented operations and compare the responses with the spec.
+//!
+//! Only operations that are safe to call without guessing input are exercised: `GET`s
+//! whose required path and query parameters all have an example (or default). Responses
+//! are compared with the documented status codes and JSON schemas, and every difference is
+//! reported as a mismatch, i.e. the documentation has drifted from the implementation.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+
+/// Operations exercised per check, so large APIs do not get hammered
+const MAX_OPERATIONS: usize = 20;
+/// Schema mismatches reported per response
+const MAX_ERRORS_PER_RESPONSE: usize = 10;
+/// Array items validated per array
+const MAX_ITEMS_VALIDATED: usize = 10;
+/// Guards against recursive schemas
+const MAX_SCHEMA_DEPTH: usize = 32;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Outcome of one contract check of an API
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "crd", derive(schemars::JsonSchema))]
+pub struct ContractReport {
+    pub checked_at: DateTime<Utc>,
+    /// Operations that were called and compared with the spec
+    pub operations_checked: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mismatches: Vec<ContractMismatch>,
+}
+
+impl ContractReport {
+    /// Whether the live service differs from its documentation
+    pub fn has_drift(&self) -> bool {
+        !self.mismatches.is_empty()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "crd", derive(schemars::JsonSchema))]
+pub struct ContractMismatch {
+    pub method: String,
+    /// Path template as documented, e.g. `/orders/{id}`
+    pub path: String,
+    pub message: String,
+}
+
+/// A documented operation that can be called with the spec's examples
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedRequest {
+    /// Path template as documented
+    pub path: String,
+    /// Path with parameters filled in, plus the query string
+    pub target: String,
+}
+
+/// Follows local `$ref`s (`#/components/...`, `#/definitions/...`)
+fn resolve<'a>(root: &'a Value, mut value: &'a Value) -> &'a Value {
+    for _ in 0..MAX_SCHEMA_DEPTH {
+        let Some(reference) = value.get("$ref").and_then(Value::as_str) else {
+            break;
+        };
+        let Some(pointer) = reference.strip_prefix('#') else {
+            break;
+        };
+        match root.pointer(pointer) {
+            Some(target) => value = target,
+            None => break,
+        }
+    }
+    value
+}
+
+/// Example value of a parameter, from the parameter itself or its schema
+fn parameter_example(root: &Value, parameter: &Value) -> Option<String> {
+    let schema = parameter.get("schema").map(|s| resolve(root, s));
+    let example = parameter
+        .get("example")
+        .or_else(|| {
+            parameter
+                .get("examples")
+                .and_then(Value::as_object)
+                .and_then(|examples| examples.values().next())
+                .map(|example| resolve(root, example))
+                .and_then(|example| example.get("value"))
+        })
+        .or_else(|| parameter.get("x-example"))
+        .or_else(|| parameter.get("default"))
+        .or_else(|| schema.and_then(|s| s.get("example")))
+        .or_else(|| schema.and_then(|s| s.get("default")))?;
+
+    match example {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Array(items) => Some(
+            items
+                .iter()
+                .map(|item| match item {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(","),
+        ),
+        _ => None,
+    }
+}
+
+/// `GET` operations whose required parameters all have examples, with those filled in
+pub fn plan(spec: &Value) -> Vec<PlannedRequest> {
+    let Some(paths) = spec.get("paths").and_then(Value::as_object) else {
+        return Vec::new();
+    };
+
+    let mut planned = Vec::new();
+    'operations: for (path, item) in paths {
+        let item = resolve(spec, item);
+        let Some(operation) = item.get("get") else {
+            continue;
+        };
+        let required_body = operation
+            .get("requestBody")
+            .map(|body| resolve(spec, body))
+            .and_then(|body| body.get("required"))
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        if required_body {
+            continue;
+        }
+
+        // Operation parameters override path-level parameters with the same name and location
+        let mut parameters: Vec<&Value> = Vec::new();
+        for parameter in [item.get("parameters"), operation.get("parameters")]
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_array)
+            .flatten()
+        {
+            let parameter = resolve(spec, parameter);
+            let key = (parameter.get("name"), parameter.get("in"));
+            parameters.retain(|p| (p.get("name"), p.get("in")) != key);
+            parameters.push(parameter);
+        }
+
+        let mut target = path.clone();
+        let mut query = Vec::new();
+        for parameter in parameters {
+            let location = parameter.get("in").and_then(Value::as_str).unwrap_or("");
+            let required = location == "path"
+                || parameter
+                    .get("required")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false);
+            if !required {
+                continue;
+            }
+            let Some(name) = parameter.get("name").and_then(Value::as_str) else {
+                continue 'operations;
+            };
+            let Some(example) = parameter_example(spec, parameter) else {
+                continue 'operations;
+            };
+            match location {
+                "path" => {
+                    target =
+                        target.replace(&format!("{{{}}}", name), &urlencoding::encode(&example))
+                }
+                "query" => query.push(format!(
+                    "{}={}",
+                    urlencoding::encode(name),
+                    urlencoding::encode(&example)
+                )),
+                // Headers, cookies and bodies are not guessed
+                _ => continue 'operations,
+            }
+        }
+        if target.contains('{') {
+            continue;
+        }
+        if !query.is_empty() {
+            target = format!("{}?{}", target, query.join("&"));
+        }
+        planned.push(PlannedRequest {
+            path: path.clone(),
+            target,
+        });
+    }
+    planned
+}
+
+/// Where operations are served: the origin of the spec URL plus the spec's base path
+pub fn base_url(spec_url: &str, spec: &Value) -> Option<String> {
+    let url = reqwest::Url::parse(spec_url).ok()?;
+    let origin = url.origin().ascii_serialization();
+
+    let prefix = match spec.pointer("/servers/0") {
+        Some(server) => {
+            let mut server_url = server
+                .get("url")
+                .and_then(Value::as_str)
+                .unwrap_or("")
+                .to_string();
+            if let Some(variables) = server.get("variables").and_then(Value::as_object) {
+                for (name, variable) in variables {
+                    if let Some(default) = variable.get("default").and_then(Value::as_str) {
+                        server_url = server_url.replace(&format!("{{{}}}", name), default);
+                    }
+                }
+            }
+            // Absolute server URLs usually name the public host; only their path applies
+            match reqwest::Url::parse(&server_url) {
+                Ok(absolute) => absolute.path().to_string(),
+                Err(_) => server_url,
+            }
+        }
+        None => spec
+            .get("basePath")
+            .and_then(Value::as_str)
+            .unwrap_or("")
+            .to_string(),
+    };
+    let prefix = prefix.trim_end_matches('/');
+    let prefix = if prefix.is_empty() || prefix.starts_with('/') {
+        prefix.to_string()
+    } else {
+        format!("/{}", prefix)
+    };
+    Some(format!("{}{}", origin, prefix))
+}
+
+/// Documented response for a status: exact code, then range (`2XX`), then `default`
+fn documented_response<'a>(spec: &'a Value, path: &str, status: u16) -> Option<&'a Value> {
+    let responses = spec
+        .get("paths")?
+        .get(path)
+        .map(|item| resolve(spec, item))?
+        .get("get")?
+        .get("responses")?
+        .as_object()?;
+    let code = status.to_string();
+    let range = format!("{}XX", status / 100);
+    responses
+        .iter()
+        .find(|(key, _)| **key == code)
+        .or_else(|| {
+            responses
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(&range))
+        })
+        .or_else(|| responses.iter().find(|(key, _)| *key == "default"))
+        .map(|(_, response)| resolve(spec, response))
+}
+
+/// JSON schema of a documented response, for OpenAPI 3 (`content`) and Swagger 2 (`schema`)
+fn response_schema<'a>(spec: &'a Value, response: &'a Value) -> Option<&'a Value> {
+    let schema = match response.get("content").and_then(Value::as_object) {
+        Some(content) => content
+            .iter()
+            .find(|(media_type, _)| media_type.contains("json"))
+            .and_then(|(_, media)| media.get("schema")),
+        None => response.get("schema"),
+    };
+    schema.map(|schema| resolve(spec, schema))
+}
+
+fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn type_matches(expected: &str, value: &Value) -> bool {
+    let actual = json_type(value);
+    expected == actual
+        || (expected == "number" && actual == "integer")
+        || (expected == "integer" && value.as_f64().is_some_and(|n| n.fract() == 0.0))
+}
+
+/// Differences between a value and a schema, as `<location>: <problem>` messages
+pub fn validate(root: &Value, schema: &Value, value: &Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    validate_at(root, schema, value, "$", 0, &mut errors);
+    errors.truncate(MAX_ERRORS_PER_RESPONSE);
+    errors
+}
+
+fn validate_at(
+    root: &Value,
+    schema: &Value,
+    value: &Value,
+    at: &str,
+    depth: usize,
+    errors: &mut Vec<String>,
+) {
+    if depth > MAX_SCHEMA_DEPTH || errors.len() >= MAX_ERRORS_PER_RESPONSE {
+        return;
+    }
+    let schema = resolve(root, schema);
+
+    if let Some(all_of) = schema.get("allOf").and_then(Value::as_array) {
+        for sub in all_of {
+            validate_at(root, sub, value, at, depth + 1, errors);
+        }
+    }
+    for keyword in ["anyOf", "oneOf"] {
+        if let Some(alternatives) = schema.get(keyword).and_then(Value::as_array) {
+            let matches_one = alternatives.iter().any(|sub| {
+                let mut sub_errors = Vec::new();
+                validate_at(root, sub, value, at, depth + 1, &mut sub_errors);
+                sub_errors.is_empty()
+            });
+            if !matches_one {
+                errors.push(format!(
+                    "{}: matches none of the documented alternatives",
+                    at
+                ));
+            }
+        }
+    }
+
+    let mut types: Vec<&str> = match schema.get("type") {
+        Some(Value::String(t)) => vec![t.as_str()],
+        Some(Value::Array(ts)) => ts.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    if value.is_null()
+        && (schema.get("nullable").and_then(Value::as_bool) == Some(true)
+            || schema.get("x-nullable").and_then(Value::as_bool) == Some(true)
+            || types.contains(&"null"))
+    {
+        return;
+    }
+    if !types.is_empty() && !types.iter().any(|t| type_matches(t, value)) {
+        types.dedup();
+        errors.push(format!(
+            "{}: expected {}, got {}",
+            at,
+            types.join(" or "),
+            json_type(value)
+        ));
+        return;
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array)
+        && !allowed.contains(value)
+    {
+        errors.push(format!("{}: {} is not a documented value", at, value));
+    }
+
+    match value {
+        Value::Object(object) => {
+            if let Some(required) = schema.get("required").and_then(Value::as_array) {
+                for name in required.iter().filter_map(Value::as_str) {
+                    if !object.contains_key(name) {
+                        errors.push(format!("{}: missing required property '{}'", at, name));
+                    }
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for (name, property) in object {
+                let location = format!("{}.{}", at, name);
+                match (
+                    properties.and_then(|p| p.get(name)),
+                    schema.get("additionalProperties"),
+                ) {
+                    (Some(property_schema), _) => validate_at(
+                        root,
+                        property_schema,
+                        property,
+                        &location,
+                        depth + 1,
+                        errors,
+                    ),
+                    (None, Some(Value::Bool(false))) => {
+                        errors.push(format!("{}: undocumented property", location))
+                    }
+                    (None, Some(additional @ Value::Object(_))) => {
+                        validate_at(root, additional, property, &location, depth + 1, errors)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().take(MAX_ITEMS_VALIDATED).enumerate() {
+                    let location = format!("{}[{}]", at, i);
+                    validate_at(root, item_schema, item, &location, depth + 1, errors);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Mismatches between a live response and the documented ones
+pub fn compare_response(spec: &Value, path: &str, status: u16, body: &[u8]) -> Vec<String> {
+    let Some(response) = documented_response(spec, path, status) else {
+        return vec![format!("status {} is not documented", status)];
+    };
+    let Some(schema) = response_schema(spec, response) else {
+        return Vec::new();
+    };
+    match serde_json::from_slice::<Value>(body) {
+        Ok(value) => validate(spec, schema, &value),
+        Err(_) => vec![format!(
+            "status {} should return JSON, got a non-JSON body",
+            status
+        )],
+    }
+}
+
+/// Calls every planned operation of the spec against `base_url` and compares the responses
+pub async fn check(client: &reqwest::Client, base_url: &str, spec: &Value) -> ContractReport {
+    let mut report = ContractReport {
+        checked_at: Utc::now(),
+        operations_checked: 0,
+        mismatches: Vec::new(),
+    };
+
+    for request in plan(spec).into_iter().take(MAX_OPERATIONS) {
+        let url = format!("{}{}", base_url.trim_end_matches('/'), request.target);
+        let mismatch = |message: String| ContractMismatch {
+            method: "GET".to_string(),
+            path: request.path.clone(),
+            message,
+        };
+
+        let response = match client
+            .get(&url)
+            .header(reqwest::header::ACCEPT, "application/json")
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await
+        {
+            Ok(response) => response,
+            Err(e) => {
+                report
+                    .mismatches
+                    .push(mismatch(format!("request failed: {}", e)));
+                report.operations_checked += 1;
+                continue;
+            }
+        };
+        let status = response.status().as_u16();
+        // Protected operations cannot be checked without credentials
+        if matches!(status, 401 | 403) && documented_response(spec, &request.path, status).is_none()
+        {
+            continue;
+        }
+        let body = response.bytes().await.unwrap_or_default();
+
+        report.operations_checked += 1;
+        report.mismatches.extend(
+            compare_response(spec, &request.path, status, &body)
+                .into_iter()
+                .map(mismatch),
+        );
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn spec() -> Value {
+        json!({
+            "openapi": "3.0.0",
+            "servers": [{ "url": "https://api.example.com/v1" }],
+            "paths": {
+                "/orders/{id}": {
+                    "parameters": [{ "name": "id", "in": "path", "required": true, "example": 42 }],
+                    "get": {
+                        "parameters": [
+                            { "name": "expand", "in": "query", "required": true, "schema": { "type": "string", "default": "items" } },
+                            { "name": "limit", "in": "query", "schema": { "type": "integer" } }
+                        ],
+                        "responses": {
+                            "200": {
+                                "content": {
+                                    "application/json": { "schema": { "$ref": "#/components/schemas/Order" } }
+                                }
+                            },
+                            "4XX": { "description": "Client error" }
+                        }
+                    }
+                },
+                "/customers/{id}": {
+                    "get": {
+                        "parameters": [{ "name": "id", "in": "path", "required": true }],
+                        "responses": { "200": { "description": "ok" } }
+                    }
+                },
+                "/orders": { "post": { "responses": { "201": { "description": "created" } } } }
+            },
+            "components": {
+                "schemas": {
+                    "Order": {
+                        "type": "object",
+                        "required": ["id", "status"],
+                        "additionalProperties": false,
+                        "properties": {
+                            "id": { "type": "integer" },
+                            "status": { "type": "string", "enum": ["open", "shipped"] },
+                            "note": { "type": "string", "nullable": true },
+                            "items": { "type": "array", "items": { "type": "object", "required": ["sku"] } }
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn plans_only_gets_with_examples() {
+        let spec = spec();
+        assert_eq!(
+            plan(&spec),
+            vec![PlannedRequest {
+                path: "/orders/{id}".to_string(),
+                target: "/orders/42?expand=items".to_string(),
+            }]
+        );
+        assert_eq!(
+            base_url(
+                "http://orders.shop.svc.cluster.local:8080/openapi.json",
+                &spec
+            )
+            .as_deref(),
+            Some("http://orders.shop.svc.cluster.local:8080/v1")
+        );
+    }
+
+    #[test]
+    fn reports_schema_and_status_drift() {
+        let spec = spec();
+        let matching = br#"{"id": 42, "status": "open", "note": null, "items": [{"sku": "A1"}]}"#;
+        assert!(compare_response(&spec, "/orders/{id}", 200, matching).is_empty());
+        assert!(compare_response(&spec, "/orders/{id}", 404, b"").is_empty());
+
+        let drifted = br#"{"id": "42", "status": "cancelled", "items": [{}], "total": 10}"#;
+        assert_eq!(
+            compare_response(&spec, "/orders/{id}", 200, drifted),
+            vec![
+                "$.id: expected integer, got string",
+                "$.items[0]: missing required property 'sku'",
+                "$.status: \"cancelled\" is not a documented value",
+                "$.total: undocumented property",
+            ]
+        );
+        assert_eq!(
+            compare_response(&spec, "/orders/{id}", 500, b"oops"),
+            vec!["status 500 is not documented"]
+        );
+    }
+}
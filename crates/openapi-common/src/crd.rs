@@ -0,0 +1,531 @@
+//! `DiscoveredApi` custom resource, an alternative to the single discovery ConfigMap.
+//!
+//! With `DISCOVERY_OUTPUT=crd` the operator writes one `DiscoveredApi` per annotated
+//! Service, in the Service's namespace. Catalog metadata lives in `spec`, availability in
+//! `status`, and the spec body in a separate ConfigMap referenced by `spec.specConfigMap`
+//! so large documents do not bloat the resource that clients list and watch.
+//!
+//! `status.conditions` carries the usual Kubernetes conditions, `Available` and
+//! `SpecCurrent`, so `kubectl wait --for=condition=Available dapi/<service>` works.
+//!
+//! `v1alpha1` is the storage version. `v1beta1` groups the fetch source and the stored
+//! spec reference; objects are converted between the two by the operator's conversion
+//! webhook (see [`convert`]).
+
+use chrono::{DateTime, Utc};
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+use kube::core::crd::merge_crds;
+use kube::{CustomResourceExt, Resource};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub use v1alpha1::{DiscoveredApi, DiscoveredApiSpec};
+
+/// Key of the spec body in the referenced ConfigMap
+pub const SPEC_CONFIGMAP_KEY: &str = "spec";
+
+/// Key of the detached spec signature, set when the operator signs specs
+pub const SPEC_SIGNATURE_CONFIGMAP_KEY: &str = "spec.sig";
+
+/// Version the API server persists objects in
+pub const STORAGE_VERSION: &str = "v1alpha1";
+
+/// Full name of the `DiscoveredApi` CRD
+pub const CRD_NAME: &str = "discoveredapis.api-doc.io";
+
+/// The service answered the last spec fetch
+pub const CONDITION_AVAILABLE: &str = "Available";
+
+/// The stored spec is the one last fetched, not kept while the refresh window is closed
+pub const CONDITION_SPEC_CURRENT: &str = "SpecCurrent";
+
+/// A status condition in the Kubernetes convention
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiCondition {
+    #[serde(rename = "type")]
+    pub type_: String,
+    /// `True`, `False` or `Unknown`
+    pub status: String,
+    /// CamelCase reason of the last transition
+    pub reason: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub message: String,
+    pub last_transition_time: DateTime<Utc>,
+}
+
+/// Status shared by all versions
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveredApiStatus {
+    pub available: bool,
+    /// Why the last spec fetch failed, while the API is unavailable
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// Spec fetches that failed in a row since the spec was last stored
+    #[serde(default, skip_serializing_if = "crate::is_zero")]
+    pub consecutive_failures: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_updated: Option<DateTime<Utc>>,
+    /// SHA-256 of the stored spec body
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spec_sha256: Option<String>,
+    /// Latest live contract check, when the service opted in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contract: Option<crate::contract::ContractReport>,
+    /// Complexity of the stored spec
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<crate::complexity::SpecMetrics>,
+    /// The stored spec is kept because the refresh window is closed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stale_by_schedule: bool,
+    /// What the last stored revision of the spec changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_change_summary: Option<crate::diff::ChangeSummary>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conditions: Vec<ApiCondition>,
+}
+
+impl DiscoveredApiStatus {
+    /// Sets the condition of `type_`, keeping its transition time while its status is unchanged
+    pub fn set_condition(&mut self, type_: &str, status: bool, reason: &str, message: &str) {
+        let status = if status { "True" } else { "False" };
+        let last_transition_time = self
+            .conditions
+            .iter()
+            .find(|c| c.type_ == type_ && c.status == status)
+            .map(|c| c.last_transition_time)
+            .unwrap_or_else(Utc::now);
+        self.conditions.retain(|c| c.type_ != type_);
+        self.conditions.push(ApiCondition {
+            type_: type_.to_string(),
+            status: status.to_string(),
+            reason: reason.to_string(),
+            message: message.to_string(),
+            last_transition_time,
+        });
+    }
+}
+
+/// Plain string enum; the `anyOf` schemars derives for `Option<Lifecycle>` is not structural
+pub(crate) fn lifecycle_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+    let stages: Vec<&str> = crate::Lifecycle::ALL.iter().map(|s| s.as_str()).collect();
+    schemars::json_schema!({
+        "description": "Stability stage declared with `api-doc.io/lifecycle`",
+        "type": "string",
+        "enum": stages,
+    })
+}
+
+pub mod v1alpha1 {
+    use kube::CustomResource;
+    use schemars::JsonSchema;
+    use serde::{Deserialize, Serialize};
+
+    use super::DiscoveredApiStatus;
+    use crate::{ApiInventoryEntry, Lifecycle, UiOptions};
+    use chrono::Utc;
+
+    /// Catalog metadata of one discovered API
+    #[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+    #[kube(
+        group = "api-doc.io",
+        version = "v1alpha1",
+        kind = "DiscoveredApi",
+        plural = "discoveredapis",
+        shortname = "dapi",
+        namespaced,
+        status = "DiscoveredApiStatus",
+        printcolumn = r#"{"name":"API","type":"string","jsonPath":".spec.name"}"#,
+        printcolumn = r#"{"name":"Service","type":"string","jsonPath":".spec.serviceName"}"#,
+        printcolumn = r#"{"name":"Available","type":"boolean","jsonPath":".status.available"}"#,
+        printcolumn = r#"{"name":"Lifecycle","type":"string","jsonPath":".spec.lifecycle"}"#,
+        printcolumn = r#"{"name":"Age","type":"date","jsonPath":".metadata.creationTimestamp"}"#
+    )]
+    #[serde(rename_all = "camelCase")]
+    pub struct DiscoveredApiSpec {
+        /// Display name
+        pub name: String,
+        pub service_name: String,
+        /// URL the spec is fetched from
+        pub url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub description: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub tenant: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        #[schemars(schema_with = "super::lifecycle_schema")]
+        pub lifecycle: Option<Lifecycle>,
+        /// Frontend options from `api-doc.io/ui.*`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub ui: Option<UiOptions>,
+        /// Seconds between fetches of the spec, from `api-doc.io/refresh-interval`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub refresh_interval: Option<u64>,
+        /// Cron-like window the spec is fetched in, from `api-doc.io/refresh-window`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub refresh_window: Option<String>,
+        /// Logical API this is a version of, from `api-doc.io/api-group`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub api_group: Option<String>,
+        /// The server certificate of `url` is not verified, from
+        /// `api-doc.io/insecure-skip-verify`
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        pub insecure_skip_verify: bool,
+        /// ConfigMap in the same namespace holding the spec body under `spec`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub spec_config_map: Option<String>,
+    }
+
+    impl DiscoveredApi {
+        /// The catalog entry this resource describes
+        pub fn to_entry(&self) -> ApiInventoryEntry {
+            let namespace = self.metadata.namespace.clone().unwrap_or_default();
+            let status = self.status.clone().unwrap_or_default();
+            ApiInventoryEntry {
+                id: crate::entry_id(&namespace, &self.spec.service_name),
+                name: self.spec.name.clone(),
+                namespace,
+                service_name: self.spec.service_name.clone(),
+                url: self.spec.url.clone(),
+                description: self.spec.description.clone(),
+                last_updated: status
+                    .last_updated
+                    .or_else(|| self.metadata.creation_timestamp.as_ref().map(|t| t.0))
+                    .unwrap_or_else(Utc::now),
+                available: status.available,
+                last_error: status.last_error,
+                consecutive_failures: status.consecutive_failures,
+                tenant: self.spec.tenant.clone(),
+                cluster: None,
+                contract: status.contract,
+                lifecycle: self.spec.lifecycle,
+                metrics: status.metrics,
+                ui: self.spec.ui.clone(),
+                refresh_interval: self.spec.refresh_interval,
+                refresh_window: self.spec.refresh_window.clone(),
+                stale_by_schedule: status.stale_by_schedule,
+                api_group: self.spec.api_group.clone(),
+                insecure_skip_verify: self.spec.insecure_skip_verify,
+                spec_sha256: status.spec_sha256,
+                spec_config_map: None,
+                spec_url: None,
+                last_change_summary: status.last_change_summary,
+            }
+        }
+    }
+
+    impl From<&ApiInventoryEntry> for DiscoveredApiSpec {
+        fn from(entry: &ApiInventoryEntry) -> Self {
+            Self {
+                name: entry.name.clone(),
+                service_name: entry.service_name.clone(),
+                url: entry.url.clone(),
+                description: entry.description.clone(),
+                tenant: entry.tenant.clone(),
+                lifecycle: entry.lifecycle,
+                ui: entry.ui.clone(),
+                refresh_interval: entry.refresh_interval,
+                refresh_window: entry.refresh_window.clone(),
+                api_group: entry.api_group.clone(),
+                insecure_skip_verify: entry.insecure_skip_verify,
+                spec_config_map: None,
+            }
+        }
+    }
+}
+
+pub mod v1beta1 {
+    use kube::CustomResource;
+    use schemars::JsonSchema;
+    use serde::{Deserialize, Serialize};
+
+    use super::{DiscoveredApiStatus, SPEC_CONFIGMAP_KEY};
+    use crate::{Lifecycle, UiOptions};
+
+    /// Catalog metadata of one discovered API
+    #[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+    #[kube(
+        group = "api-doc.io",
+        version = "v1beta1",
+        kind = "DiscoveredApi",
+        plural = "discoveredapis",
+        shortname = "dapi",
+        namespaced,
+        status = "DiscoveredApiStatus",
+        printcolumn = r#"{"name":"API","type":"string","jsonPath":".spec.name"}"#,
+        printcolumn = r#"{"name":"Service","type":"string","jsonPath":".spec.source.serviceName"}"#,
+        printcolumn = r#"{"name":"Available","type":"boolean","jsonPath":".status.available"}"#,
+        printcolumn = r#"{"name":"Lifecycle","type":"string","jsonPath":".spec.lifecycle"}"#,
+        printcolumn = r#"{"name":"Age","type":"date","jsonPath":".metadata.creationTimestamp"}"#
+    )]
+    #[serde(rename_all = "camelCase")]
+    pub struct DiscoveredApiSpec {
+        /// Display name
+        pub name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub description: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub tenant: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        #[schemars(schema_with = "super::lifecycle_schema")]
+        pub lifecycle: Option<Lifecycle>,
+        /// Frontend options from `api-doc.io/ui.*`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub ui: Option<UiOptions>,
+        /// Seconds between fetches of the spec, from `api-doc.io/refresh-interval`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub refresh_interval: Option<u64>,
+        /// Cron-like window the spec is fetched in, from `api-doc.io/refresh-window`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub refresh_window: Option<String>,
+        /// Logical API this is a version of, from `api-doc.io/api-group`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub api_group: Option<String>,
+        /// Where the spec is fetched from
+        pub source: ApiSource,
+        /// Where the stored spec body is kept
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub spec_ref: Option<SpecReference>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+    #[serde(rename_all = "camelCase")]
+    pub struct ApiSource {
+        pub service_name: String,
+        pub url: String,
+        /// The server certificate of `url` is not verified
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        pub insecure_skip_verify: bool,
+    }
+
+    /// ConfigMap key in the resource's namespace
+    #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+    #[serde(rename_all = "camelCase")]
+    pub struct SpecReference {
+        pub config_map: String,
+        #[serde(default = "default_spec_key")]
+        pub key: String,
+    }
+
+    fn default_spec_key() -> String {
+        SPEC_CONFIGMAP_KEY.to_string()
+    }
+}
+
+impl From<v1alpha1::DiscoveredApi> for v1beta1::DiscoveredApi {
+    fn from(old: v1alpha1::DiscoveredApi) -> Self {
+        let spec = v1beta1::DiscoveredApiSpec {
+            name: old.spec.name,
+            description: old.spec.description,
+            tenant: old.spec.tenant,
+            lifecycle: old.spec.lifecycle,
+            ui: old.spec.ui,
+            refresh_interval: old.spec.refresh_interval,
+            refresh_window: old.spec.refresh_window,
+            api_group: old.spec.api_group,
+            source: v1beta1::ApiSource {
+                service_name: old.spec.service_name,
+                url: old.spec.url,
+                insecure_skip_verify: old.spec.insecure_skip_verify,
+            },
+            spec_ref: old
+                .spec
+                .spec_config_map
+                .map(|config_map| v1beta1::SpecReference {
+                    config_map,
+                    key: SPEC_CONFIGMAP_KEY.to_string(),
+                }),
+        };
+        let mut new = v1beta1::DiscoveredApi::new("", spec);
+        new.metadata = old.metadata;
+        new.status = old.status;
+        new
+    }
+}
+
+impl From<v1beta1::DiscoveredApi> for v1alpha1::DiscoveredApi {
+    /// `v1alpha1` always reads the spec body from the `spec` key, other keys are dropped
+    fn from(new: v1beta1::DiscoveredApi) -> Self {
+        let spec = v1alpha1::DiscoveredApiSpec {
+            name: new.spec.name,
+            service_name: new.spec.source.service_name,
+            url: new.spec.source.url,
+            description: new.spec.description,
+            tenant: new.spec.tenant,
+            lifecycle: new.spec.lifecycle,
+            ui: new.spec.ui,
+            refresh_interval: new.spec.refresh_interval,
+            refresh_window: new.spec.refresh_window,
+            api_group: new.spec.api_group,
+            insecure_skip_verify: new.spec.source.insecure_skip_verify,
+            spec_config_map: new.spec.spec_ref.map(|spec_ref| spec_ref.config_map),
+        };
+        let mut old = v1alpha1::DiscoveredApi::new("", spec);
+        old.metadata = new.metadata;
+        old.status = new.status;
+        old
+    }
+}
+
+/// The multi-version CRD manifest, generated from the Rust types
+pub fn crd() -> CustomResourceDefinition {
+    merge_crds(
+        vec![
+            v1alpha1::DiscoveredApi::crd(),
+            v1beta1::DiscoveredApi::crd(),
+        ],
+        STORAGE_VERSION,
+    )
+    .expect("DiscoveredApi versions share group and kind")
+}
+
+#[derive(Debug)]
+pub struct ConversionError(String);
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+impl From<serde_json::Error> for ConversionError {
+    fn from(err: serde_json::Error) -> Self {
+        ConversionError(format!("invalid DiscoveredApi: {}", err))
+    }
+}
+
+/// Converts a `DiscoveredApi` object, as sent in a `ConversionReview`, to `desired_api_version`
+pub fn convert(
+    object: serde_json::Value,
+    desired_api_version: &str,
+) -> Result<serde_json::Value, ConversionError> {
+    let alpha = v1alpha1::DiscoveredApi::api_version(&());
+    let beta = v1beta1::DiscoveredApi::api_version(&());
+    let current = object
+        .get("apiVersion")
+        .and_then(serde_json::Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+
+    if current == desired_api_version {
+        return Ok(object);
+    }
+    if current == alpha && desired_api_version == beta {
+        let old: v1alpha1::DiscoveredApi = serde_json::from_value(object)?;
+        return Ok(serde_json::to_value(v1beta1::DiscoveredApi::from(old))?);
+    }
+    if current == beta && desired_api_version == alpha {
+        let new: v1beta1::DiscoveredApi = serde_json::from_value(object)?;
+        return Ok(serde_json::to_value(v1alpha1::DiscoveredApi::from(new))?);
+    }
+    Err(ConversionError(format!(
+        "cannot convert DiscoveredApi from '{}' to '{}'",
+        current, desired_api_version
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ApiInventoryEntry;
+
+    fn entry() -> ApiInventoryEntry {
+        ApiInventoryEntry {
+            name: "Orders API".to_string(),
+            description: Some("Order management".to_string()),
+            tenant: Some("retail".to_string()),
+            lifecycle: Some(crate::Lifecycle::Ga),
+            ui: Some(crate::UiOptions {
+                theme: Some("deepSpace".to_string()),
+                ..Default::default()
+            }),
+            ..ApiInventoryEntry::new(
+                "shop",
+                "orders",
+                "http://orders.shop.svc.cluster.local:8080/openapi.json",
+            )
+        }
+    }
+
+    #[test]
+    fn resource_round_trips_to_entry() {
+        let entry = entry();
+        let mut resource = DiscoveredApi::new("orders", DiscoveredApiSpec::from(&entry));
+        resource.metadata.namespace = Some("shop".to_string());
+        resource.status = Some(DiscoveredApiStatus {
+            available: true,
+            last_error: None,
+            consecutive_failures: 0,
+            last_updated: Some(entry.last_updated),
+            spec_sha256: None,
+            contract: None,
+            metrics: None,
+            stale_by_schedule: false,
+            last_change_summary: None,
+            conditions: Vec::new(),
+        });
+
+        let converted = resource.to_entry();
+        assert_eq!(converted.id, entry.id);
+        assert_eq!(converted.tenant, entry.tenant);
+        assert_eq!(converted.lifecycle, entry.lifecycle);
+        assert_eq!(converted.last_updated, entry.last_updated);
+        assert!(converted.available);
+    }
+
+    #[test]
+    fn conditions_keep_their_transition_time_until_they_flip() {
+        let mut status = DiscoveredApiStatus::default();
+        status.set_condition(CONDITION_AVAILABLE, true, "SpecFetched", "");
+        let since = status.conditions[0].last_transition_time;
+
+        status.set_condition(CONDITION_AVAILABLE, true, "SpecFetched", "again");
+        assert_eq!(status.conditions.len(), 1);
+        assert_eq!(status.conditions[0].last_transition_time, since);
+        assert_eq!(status.conditions[0].message, "again");
+
+        status.set_condition(CONDITION_AVAILABLE, false, "FetchFailed", "timeout");
+        assert_eq!(status.conditions[0].status, "False");
+        assert!(status.conditions[0].last_transition_time >= since);
+    }
+
+    #[test]
+    fn converts_between_versions() {
+        let mut resource = DiscoveredApi::new(
+            "orders",
+            DiscoveredApiSpec {
+                spec_config_map: Some("orders-openapi-spec".to_string()),
+                ..DiscoveredApiSpec::from(&entry())
+            },
+        );
+        resource.metadata.namespace = Some("shop".to_string());
+        resource.metadata.resource_version = Some("42".to_string());
+        let original = serde_json::to_value(&resource).unwrap();
+
+        let beta = convert(original.clone(), "api-doc.io/v1beta1").unwrap();
+        assert_eq!(beta["apiVersion"], "api-doc.io/v1beta1");
+        assert_eq!(beta["spec"]["source"]["serviceName"], "orders");
+        assert_eq!(beta["spec"]["specRef"]["configMap"], "orders-openapi-spec");
+        assert_eq!(beta["metadata"]["resourceVersion"], "42");
+
+        let alpha = convert(beta, "api-doc.io/v1alpha1").unwrap();
+        assert_eq!(alpha, original);
+        assert!(convert(alpha, "api-doc.io/v2").is_err());
+    }
+
+    #[test]
+    fn crd_serves_both_versions() {
+        let crd = crd();
+        let versions: Vec<(&str, bool)> = crd
+            .spec
+            .versions
+            .iter()
+            .map(|v| (v.name.as_str(), v.storage))
+            .collect();
+        assert_eq!(versions, vec![("v1alpha1", true), ("v1beta1", false)]);
+    }
+}
@@ -0,0 +1,453 @@
+//! Structural comparison of two OpenAPI documents.
+//!
+//! Works on the typed [`OpenApiDocument`] model, so it covers paths, operations,
+//! parameters, request bodies, response codes and security schemes, but not
+//! schema-level changes inside request/response bodies.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::openapi::{OpenApiDocument, Operation, PathItem};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum Severity {
+    /// Existing clients may stop working
+    Breaking,
+    NonBreaking,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChangeKind {
+    PathAdded,
+    PathRemoved,
+    OperationAdded,
+    OperationRemoved,
+    OperationDeprecated,
+    ParameterAdded,
+    ParameterRemoved,
+    ParameterBecameRequired,
+    ParameterTypeChanged,
+    RequestBodyBecameRequired,
+    ResponseAdded,
+    ResponseRemoved,
+    SecuritySchemeAdded,
+    SecuritySchemeRemoved,
+    VersionChanged,
+}
+
+/// A single difference between two documents
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub kind: ChangeKind,
+    pub severity: Severity,
+    /// Where the change happened, e.g. `GET /users` or `/users`
+    pub location: String,
+    pub message: String,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+/// All differences between two documents, breaking changes first
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpecDiff {
+    pub changes: Vec<Change>,
+}
+
+impl SpecDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn has_breaking(&self) -> bool {
+        self.breaking().next().is_some()
+    }
+
+    pub fn breaking(&self) -> impl Iterator<Item = &Change> {
+        self.changes
+            .iter()
+            .filter(|c| c.severity == Severity::Breaking)
+    }
+
+    pub fn count(&self, kind: ChangeKind) -> usize {
+        self.changes.iter().filter(|c| c.kind == kind).count()
+    }
+
+    /// One-line human readable summary, e.g. `2 breaking, 3 non-breaking (+1 paths, -1 paths, ...)`
+    pub fn summary(&self) -> String {
+        if self.is_empty() {
+            return "no changes".to_string();
+        }
+
+        let breaking = self.breaking().count();
+        let mut details = Vec::new();
+        for (kind, label) in [
+            (ChangeKind::PathAdded, "+{} paths"),
+            (ChangeKind::PathRemoved, "-{} paths"),
+            (ChangeKind::OperationAdded, "+{} operations"),
+            (ChangeKind::OperationRemoved, "-{} operations"),
+        ] {
+            let count = self.count(kind);
+            if count > 0 {
+                details.push(label.replace("{}", &count.to_string()));
+            }
+        }
+        let changed_operations = self
+            .changes
+            .iter()
+            .filter(|c| {
+                !matches!(
+                    c.kind,
+                    ChangeKind::PathAdded
+                        | ChangeKind::PathRemoved
+                        | ChangeKind::OperationAdded
+                        | ChangeKind::OperationRemoved
+                )
+            })
+            .count();
+        if changed_operations > 0 {
+            details.push(format!("{} other changes", changed_operations));
+        }
+
+        format!(
+            "{} breaking, {} non-breaking ({})",
+            breaking,
+            self.changes.len() - breaking,
+            details.join(", ")
+        )
+    }
+}
+
+/// Paths and operations listed in a [`ChangeSummary`]; the counts cover the rest
+const MAX_SUMMARY_ITEMS: usize = 20;
+
+/// What the last stored revision of a spec changed, as recorded on its catalog entry
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "crd", derive(schemars::JsonSchema))]
+pub struct ChangeSummary {
+    /// When the revision was stored
+    pub changed_at: DateTime<Utc>,
+    /// See [`SpecDiff::summary`]
+    pub summary: String,
+    pub breaking: usize,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub added_paths: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed_paths: Vec<String>,
+    /// Operations added, removed or changed, e.g. `GET /users`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changed_operations: Vec<String>,
+}
+
+impl SpecDiff {
+    /// Summary of the revision stored at `changed_at`
+    pub fn change_summary(&self, changed_at: DateTime<Utc>) -> ChangeSummary {
+        let locations = |kinds: &[ChangeKind]| {
+            let mut locations: Vec<String> = Vec::new();
+            for change in self.changes.iter().filter(|c| kinds.contains(&c.kind)) {
+                if !locations.contains(&change.location) && locations.len() < MAX_SUMMARY_ITEMS {
+                    locations.push(change.location.clone());
+                }
+            }
+            locations
+        };
+        ChangeSummary {
+            changed_at,
+            summary: self.summary(),
+            breaking: self.breaking().count(),
+            added_paths: locations(&[ChangeKind::PathAdded]),
+            removed_paths: locations(&[ChangeKind::PathRemoved]),
+            changed_operations: locations(&[
+                ChangeKind::OperationAdded,
+                ChangeKind::OperationRemoved,
+                ChangeKind::OperationDeprecated,
+                ChangeKind::ParameterAdded,
+                ChangeKind::ParameterRemoved,
+                ChangeKind::ParameterBecameRequired,
+                ChangeKind::ParameterTypeChanged,
+                ChangeKind::RequestBodyBecameRequired,
+                ChangeKind::ResponseAdded,
+                ChangeKind::ResponseRemoved,
+            ]),
+        }
+    }
+}
+
+/// Compares `old` against `new` and reports what changed for clients of `old`
+pub fn diff_documents(old: &OpenApiDocument, new: &OpenApiDocument) -> SpecDiff {
+    let mut changes = Vec::new();
+    let mut push = |kind, severity, location: String, message: String| {
+        changes.push(Change {
+            kind,
+            severity,
+            location,
+            message,
+        })
+    };
+
+    if old.info.version != new.info.version {
+        push(
+            ChangeKind::VersionChanged,
+            Severity::NonBreaking,
+            "info.version".to_string(),
+            format!("version changed from '{}' to '{}'", old.info.version, new.info.version),
+        );
+    }
+
+    for (path, old_item) in &old.paths {
+        match new.paths.get(path) {
+            None => push(
+                ChangeKind::PathRemoved,
+                Severity::Breaking,
+                path.clone(),
+                "path removed".to_string(),
+            ),
+            Some(new_item) => diff_path_item(path, old_item, new_item, &mut push),
+        }
+    }
+    for path in new.paths.keys().filter(|p| !old.paths.contains_key(*p)) {
+        push(
+            ChangeKind::PathAdded,
+            Severity::NonBreaking,
+            path.clone(),
+            "path added".to_string(),
+        );
+    }
+
+    let old_schemes = old.security_schemes();
+    let new_schemes = new.security_schemes();
+    for name in old_schemes.keys().filter(|n| !new_schemes.contains_key(*n)) {
+        push(
+            ChangeKind::SecuritySchemeRemoved,
+            Severity::Breaking,
+            format!("securitySchemes.{}", name),
+            "security scheme removed".to_string(),
+        );
+    }
+    for name in new_schemes.keys().filter(|n| !old_schemes.contains_key(*n)) {
+        push(
+            ChangeKind::SecuritySchemeAdded,
+            Severity::NonBreaking,
+            format!("securitySchemes.{}", name),
+            "security scheme added".to_string(),
+        );
+    }
+
+    changes.sort_by(|a, b| (a.severity, &a.location).cmp(&(b.severity, &b.location)));
+    SpecDiff { changes }
+}
+
+fn diff_path_item(
+    path: &str,
+    old_item: &PathItem,
+    new_item: &PathItem,
+    push: &mut impl FnMut(ChangeKind, Severity, String, String),
+) {
+    for (method, old_op) in old_item.operations() {
+        let location = format!("{} {}", method.to_uppercase(), path);
+        match new_item.operation(method) {
+            None => push(
+                ChangeKind::OperationRemoved,
+                Severity::Breaking,
+                location,
+                "operation removed".to_string(),
+            ),
+            Some(new_op) => {
+                diff_operation(&location, old_item, old_op, new_item, new_op, push)
+            }
+        }
+    }
+    for (method, _) in new_item.operations() {
+        if old_item.operation(method).is_none() {
+            push(
+                ChangeKind::OperationAdded,
+                Severity::NonBreaking,
+                format!("{} {}", method.to_uppercase(), path),
+                "operation added".to_string(),
+            );
+        }
+    }
+}
+
+fn diff_operation(
+    location: &str,
+    old_item: &PathItem,
+    old_op: &Operation,
+    new_item: &PathItem,
+    new_op: &Operation,
+    push: &mut impl FnMut(ChangeKind, Severity, String, String),
+) {
+    if !old_op.deprecated && new_op.deprecated {
+        push(
+            ChangeKind::OperationDeprecated,
+            Severity::NonBreaking,
+            location.to_string(),
+            "operation deprecated".to_string(),
+        );
+    }
+
+    let old_params = old_op.effective_parameters(old_item);
+    let new_params = new_op.effective_parameters(new_item);
+    for old_param in &old_params {
+        let label = format!("{} parameter '{}'", old_param.location, old_param.name);
+        match new_params
+            .iter()
+            .find(|p| p.name == old_param.name && p.location == old_param.location)
+        {
+            None => push(
+                ChangeKind::ParameterRemoved,
+                Severity::Breaking,
+                location.to_string(),
+                format!("{} removed", label),
+            ),
+            Some(new_param) => {
+                if !old_param.required && new_param.required {
+                    push(
+                        ChangeKind::ParameterBecameRequired,
+                        Severity::Breaking,
+                        location.to_string(),
+                        format!("{} became required", label),
+                    );
+                }
+                if old_param.schema_type.is_some()
+                    && new_param.schema_type.is_some()
+                    && old_param.schema_type != new_param.schema_type
+                {
+                    push(
+                        ChangeKind::ParameterTypeChanged,
+                        Severity::Breaking,
+                        location.to_string(),
+                        format!(
+                            "{} type changed from {} to {}",
+                            label,
+                            old_param.schema_type.as_deref().unwrap_or_default(),
+                            new_param.schema_type.as_deref().unwrap_or_default()
+                        ),
+                    );
+                }
+            }
+        }
+    }
+    for new_param in &new_params {
+        if !old_params
+            .iter()
+            .any(|p| p.name == new_param.name && p.location == new_param.location)
+        {
+            let severity = if new_param.required {
+                Severity::Breaking
+            } else {
+                Severity::NonBreaking
+            };
+            push(
+                ChangeKind::ParameterAdded,
+                severity,
+                location.to_string(),
+                format!(
+                    "{} {} parameter '{}' added",
+                    if new_param.required { "required" } else { "optional" },
+                    new_param.location,
+                    new_param.name
+                ),
+            );
+        }
+    }
+
+    if !old_op.request_body_required() && new_op.request_body_required() {
+        push(
+            ChangeKind::RequestBodyBecameRequired,
+            Severity::Breaking,
+            location.to_string(),
+            "request body became required".to_string(),
+        );
+    }
+
+    for code in old_op.responses.keys() {
+        if !new_op.responses.contains_key(code) {
+            // Dropping a documented success response changes what clients receive
+            let severity = if code.starts_with('2') {
+                Severity::Breaking
+            } else {
+                Severity::NonBreaking
+            };
+            push(
+                ChangeKind::ResponseRemoved,
+                severity,
+                location.to_string(),
+                format!("response {} removed", code),
+            );
+        }
+    }
+    for code in new_op.responses.keys() {
+        if !old_op.responses.contains_key(code) {
+            push(
+                ChangeKind::ResponseAdded,
+                Severity::NonBreaking,
+                location.to_string(),
+                format!("response {} added", code),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(yaml: &str) -> OpenApiDocument {
+        OpenApiDocument::parse(yaml).unwrap()
+    }
+
+    #[test]
+    fn identical_documents_have_no_changes() {
+        let spec = "openapi: 3.0.0\ninfo: {title: A, version: '1'}\npaths:\n  /a:\n    get: {}\n";
+        let diff = diff_documents(&doc(spec), &doc(spec));
+        assert!(diff.is_empty());
+        assert_eq!(diff.summary(), "no changes");
+    }
+
+    #[test]
+    fn detects_breaking_and_additive_changes() {
+        let old = doc(r#"
+openapi: 3.0.0
+info: {title: A, version: "1"}
+paths:
+  /users:
+    get:
+      parameters:
+        - {name: limit, in: query, schema: {type: integer}}
+      responses: {"200": {}}
+  /legacy:
+    get: {}
+"#);
+        let new = doc(r#"
+openapi: 3.0.0
+info: {title: A, version: "2"}
+paths:
+  /users:
+    get:
+      parameters:
+        - {name: limit, in: query, required: true, schema: {type: string}}
+        - {name: tenant, in: header, required: true}
+      responses: {"200": {}, "404": {}}
+    post: {}
+"#);
+
+        let diff = diff_documents(&old, &new);
+        let kinds: Vec<ChangeKind> = diff.breaking().map(|c| c.kind).collect();
+        assert!(kinds.contains(&ChangeKind::PathRemoved));
+        assert!(kinds.contains(&ChangeKind::ParameterBecameRequired));
+        assert!(kinds.contains(&ChangeKind::ParameterTypeChanged));
+        assert!(kinds.contains(&ChangeKind::ParameterAdded));
+        assert_eq!(diff.count(ChangeKind::OperationAdded), 1);
+        assert_eq!(diff.count(ChangeKind::ResponseAdded), 1);
+        assert_eq!(diff.count(ChangeKind::VersionChanged), 1);
+        assert!(diff.has_breaking());
+        assert_eq!(diff.changes[0].severity, Severity::Breaking);
+    }
+}
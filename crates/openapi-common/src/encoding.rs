@@ -0,0 +1,331 @@
+//! Encodings of the discovery payload stored in the discovery ConfigMap (or Secret).
+//!
+//! Plain JSON stays the default because it is readable with `kubectl`. Large catalogs can
+//! use gzip-compressed JSON or MessagePack, which are stored under `binaryData` with their
+//! own key. Readers detect the encoding from the content, so switching the encoding does
+//! not require updating the operator and its readers at the same time.
+//!
+//! With the `entries` [`DiscoveryLayout`] each entry is stored under a key of its own, named
+//! after the entry with the extension of the encoding, e.g. `shop.orders.json`, and the
+//! payload key only holds the tombstones. Readers add the entries of such keys to the
+//! payload's, so they read either layout.
+
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::io::{Read, Write};
+
+use crate::ApiInventoryEntry;
+
+/// Gzip streams start with these two bytes
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+#[derive(Debug)]
+pub enum EncodingError {
+    /// Unknown encoding name
+    Unsupported(String),
+    /// Unknown storage name
+    UnsupportedStorage(String),
+    /// Unknown layout name
+    UnsupportedLayout(String),
+    Json(serde_json::Error),
+    MessagePackEncode(rmp_serde::encode::Error),
+    MessagePackDecode(rmp_serde::decode::Error),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodingError::Unsupported(name) => write!(
+                f,
+                "unsupported discovery encoding '{}', expected json, gzip or msgpack",
+                name
+            ),
+            EncodingError::UnsupportedStorage(name) => write!(
+                f,
+                "unsupported discovery storage '{}', expected configmap or secret",
+                name
+            ),
+            EncodingError::UnsupportedLayout(name) => write!(
+                f,
+                "unsupported discovery layout '{}', expected document or entries",
+                name
+            ),
+            EncodingError::Json(e) => write!(f, "JSON error: {}", e),
+            EncodingError::MessagePackEncode(e) => write!(f, "MessagePack error: {}", e),
+            EncodingError::MessagePackDecode(e) => write!(f, "MessagePack error: {}", e),
+            EncodingError::Io(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for EncodingError {}
+
+impl From<serde_json::Error> for EncodingError {
+    fn from(err: serde_json::Error) -> Self {
+        EncodingError::Json(err)
+    }
+}
+
+impl From<std::io::Error> for EncodingError {
+    fn from(err: std::io::Error) -> Self {
+        EncodingError::Io(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiscoveryEncoding {
+    /// Pretty-printed JSON in `data`
+    #[default]
+    Json,
+    /// Gzip-compressed JSON in `binaryData`
+    Gzip,
+    /// MessagePack (named fields) in `binaryData`
+    MessagePack,
+}
+
+impl DiscoveryEncoding {
+    pub const ALL: [DiscoveryEncoding; 3] = [
+        DiscoveryEncoding::Json,
+        DiscoveryEncoding::Gzip,
+        DiscoveryEncoding::MessagePack,
+    ];
+
+    pub fn parse(name: &str) -> Result<Self, EncodingError> {
+        match name.trim().to_lowercase().as_str() {
+            "" | "json" => Ok(DiscoveryEncoding::Json),
+            "gzip" | "json+gzip" => Ok(DiscoveryEncoding::Gzip),
+            "msgpack" | "messagepack" => Ok(DiscoveryEncoding::MessagePack),
+            other => Err(EncodingError::Unsupported(other.to_string())),
+        }
+    }
+
+    /// Reads `DISCOVERY_ENCODING`, defaulting to JSON
+    pub fn from_env() -> Result<Self, EncodingError> {
+        Self::parse(&std::env::var(super::DISCOVERY_ENCODING_ENV).unwrap_or_default())
+    }
+
+    /// ConfigMap key (and mounted file name) the payload is stored under
+    pub fn key(&self) -> &'static str {
+        match self {
+            DiscoveryEncoding::Json => "discovery.json",
+            DiscoveryEncoding::Gzip => "discovery.json.gz",
+            DiscoveryEncoding::MessagePack => "discovery.msgpack",
+        }
+    }
+
+    /// Encoding of the payload or entry stored under `key`, from its extension
+    pub fn for_key(key: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|encoding| key.ends_with(encoding.extension()))
+    }
+
+    /// Key of `entry` in the `entries` layout: `<namespace>.<service>`, prefixed with the
+    /// cluster of federated entries, and the extension of the encoding. Names are DNS
+    /// labels, so the dots keep keys of different entries apart.
+    pub fn entry_key(&self, entry: &ApiInventoryEntry) -> String {
+        match &entry.cluster {
+            Some(cluster) => format!(
+                "{}.{}.{}{}",
+                cluster,
+                entry.namespace,
+                entry.service_name,
+                self.extension()
+            ),
+            None => format!(
+                "{}.{}{}",
+                entry.namespace,
+                entry.service_name,
+                self.extension()
+            ),
+        }
+    }
+
+    /// `.json`, `.json.gz` or `.msgpack`
+    fn extension(&self) -> &'static str {
+        self.key().trim_start_matches("discovery")
+    }
+
+    /// Binary payloads go to the ConfigMap's `binaryData`
+    pub fn is_binary(&self) -> bool {
+        !matches!(self, DiscoveryEncoding::Json)
+    }
+
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, EncodingError> {
+        match self {
+            DiscoveryEncoding::Json => Ok(serde_json::to_vec_pretty(value)?),
+            DiscoveryEncoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+                encoder.write_all(&serde_json::to_vec(value)?)?;
+                Ok(encoder.finish()?)
+            }
+            DiscoveryEncoding::MessagePack => {
+                rmp_serde::to_vec_named(value).map_err(EncodingError::MessagePackEncode)
+            }
+        }
+    }
+}
+
+/// How the catalog is laid out in the discovery ConfigMap (or Secret)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiscoveryLayout {
+    /// All entries and tombstones in one payload, rewritten on every change
+    #[default]
+    Document,
+    /// Every entry under its own key, so a change rewrites only that key; the payload key
+    /// holds the tombstones
+    Entries,
+}
+
+impl DiscoveryLayout {
+    pub fn parse(name: &str) -> Result<Self, EncodingError> {
+        match name.trim().to_lowercase().as_str() {
+            "" | "document" => Ok(DiscoveryLayout::Document),
+            "entries" => Ok(DiscoveryLayout::Entries),
+            other => Err(EncodingError::UnsupportedLayout(other.to_string())),
+        }
+    }
+
+    /// Reads `DISCOVERY_LAYOUT`, defaulting to one document
+    pub fn from_env() -> Result<Self, EncodingError> {
+        Self::parse(&std::env::var(super::DISCOVERY_LAYOUT_ENV).unwrap_or_default())
+    }
+}
+
+/// Whether `key` holds a single entry of the `entries` layout rather than a payload, or a
+/// hidden file of a mounted ConfigMap
+pub fn is_entry_key(key: &str) -> bool {
+    DiscoveryEncoding::ALL.iter().any(|encoding| {
+        key.strip_suffix(encoding.extension()).is_some_and(|name| {
+            let labels: Vec<_> = name.split('.').collect();
+            (2..=3).contains(&labels.len()) && labels.iter().all(|label| !label.is_empty())
+        })
+    })
+}
+
+/// Kind of object the discovery payload and its shards are stored in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiscoveryStorage {
+    #[default]
+    ConfigMap,
+    /// The same keys in a Secret, for catalogs whose URLs should not be readable by
+    /// everyone allowed to read ConfigMaps
+    Secret,
+}
+
+impl DiscoveryStorage {
+    pub fn parse(name: &str) -> Result<Self, EncodingError> {
+        match name.trim().to_lowercase().as_str() {
+            "" | "configmap" => Ok(DiscoveryStorage::ConfigMap),
+            "secret" => Ok(DiscoveryStorage::Secret),
+            other => Err(EncodingError::UnsupportedStorage(other.to_string())),
+        }
+    }
+
+    /// Reads `DISCOVERY_STORAGE`, defaulting to ConfigMaps
+    pub fn from_env() -> Result<Self, EncodingError> {
+        Self::parse(&std::env::var(super::DISCOVERY_STORAGE_ENV).unwrap_or_default())
+    }
+
+    /// Kind of the objects, for logs
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DiscoveryStorage::ConfigMap => "ConfigMap",
+            DiscoveryStorage::Secret => "Secret",
+        }
+    }
+}
+
+/// Decodes a payload written with any [`DiscoveryEncoding`], detected from its content
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, EncodingError> {
+    if bytes.starts_with(&GZIP_MAGIC) {
+        let mut json = Vec::new();
+        GzDecoder::new(bytes).read_to_end(&mut json)?;
+        return Ok(serde_json::from_slice(&json)?);
+    }
+
+    match bytes.iter().find(|b| !b.is_ascii_whitespace()) {
+        Some(b'{') | None => Ok(serde_json::from_slice(bytes)?),
+        Some(_) => rmp_serde::from_slice(bytes).map_err(EncodingError::MessagePackDecode),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DiscoveryConfig;
+    use chrono::Utc;
+
+    #[test]
+    fn every_encoding_round_trips() {
+        let config = DiscoveryConfig {
+            apis: vec![ApiInventoryEntry {
+                tenant: Some("retail".to_string()),
+                ..ApiInventoryEntry::new(
+                    "shop",
+                    "orders",
+                    "http://orders.shop.svc.cluster.local:8080/openapi.json",
+                )
+            }],
+            last_updated: Utc::now(),
+            tombstones: Vec::new(),
+            shards: Vec::new(),
+        };
+
+        for encoding in DiscoveryEncoding::ALL {
+            let bytes = encoding.encode(&config).unwrap();
+            let decoded: DiscoveryConfig = decode(&bytes).unwrap();
+            assert_eq!(decoded.apis[0].id, "shop.orders", "{:?}", encoding);
+            assert_eq!(decoded.apis[0].tenant.as_deref(), Some("retail"));
+            assert_eq!(decoded.apis[0].description, None);
+        }
+    }
+
+    #[test]
+    fn entry_keys_name_the_entry_and_encoding() {
+        let mut entry =
+            ApiInventoryEntry::new("shop", "orders", "http://orders.shop.svc:8080/openapi.json");
+        assert_eq!(
+            DiscoveryEncoding::Gzip.entry_key(&entry),
+            "shop.orders.json.gz"
+        );
+        entry.cluster = Some("eu".to_string());
+        let key = DiscoveryEncoding::MessagePack.entry_key(&entry);
+        assert_eq!(key, "eu.shop.orders.msgpack");
+        assert_eq!(
+            DiscoveryEncoding::for_key(&key),
+            Some(DiscoveryEncoding::MessagePack)
+        );
+
+        assert!(is_entry_key("shop.orders.json"));
+        assert!(is_entry_key(&key));
+        for key in [
+            "discovery.json",
+            "discovery.json.gz",
+            "..data",
+            ".orders.json",
+        ] {
+            assert!(!is_entry_key(key), "{}", key);
+        }
+        assert!(DiscoveryLayout::parse("entries").is_ok());
+        assert!(DiscoveryLayout::parse("keys").is_err());
+    }
+
+    #[test]
+    fn storage_names_parse() {
+        assert_eq!(
+            DiscoveryStorage::parse("").unwrap(),
+            DiscoveryStorage::ConfigMap
+        );
+        assert_eq!(
+            DiscoveryStorage::parse("Secret").unwrap(),
+            DiscoveryStorage::Secret
+        );
+        assert!(DiscoveryStorage::parse("vault").is_err());
+    }
+}
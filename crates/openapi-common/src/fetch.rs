@@ -0,0 +1,762 @@
+//! Shared HTTP fetching of OpenAPI specs for the operator and the doc server.
+//!
+//! Wraps a single `reqwest::Client` with the behaviour both binaries need:
+//! timeouts, retries with exponential backoff, a shared rate limit, per-request
+//! auth, custom TLS trust, response size limits, compressed transfers and conditional
+//! (`ETag`/`Last-Modified`) requests.
+
+use flate2::read::GzDecoder;
+use reqwest::StatusCode;
+use reqwest::header::{
+    ETAG, HeaderMap, HeaderName, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::Read;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::rate_limit::{RateLimit, TokenBucket};
+
+/// Environment variables understood by [`FetchConfig::from_env`]
+pub const SPEC_FETCH_TIMEOUT_ENV: &str = "SPEC_FETCH_TIMEOUT";
+pub const SPEC_FETCH_CONNECT_TIMEOUT_ENV: &str = "SPEC_FETCH_CONNECT_TIMEOUT";
+pub const SPEC_FETCH_RETRIES_ENV: &str = "SPEC_FETCH_RETRIES";
+/// Milliseconds before the first retry
+pub const SPEC_FETCH_RETRY_BACKOFF_ENV: &str = "SPEC_FETCH_RETRY_BACKOFF";
+/// PEM file with CA certificates trusted in addition to the system roots
+pub const SPEC_FETCH_CA_BUNDLE_ENV: &str = "SPEC_FETCH_CA_BUNDLE";
+/// Proxy for all spec fetches, overriding `HTTP_PROXY`/`HTTPS_PROXY` (`NO_PROXY` still applies)
+pub const SPEC_FETCH_PROXY_ENV: &str = "SPEC_FETCH_PROXY";
+/// Spec requests per second across all fetches, including retries (unlimited when unset)
+pub const SPEC_FETCH_RATE_LIMIT_ENV: &str = "SPEC_FETCH_RATE_LIMIT";
+/// Requests allowed at once before `SPEC_FETCH_RATE_LIMIT` applies
+pub const SPEC_FETCH_RATE_BURST_ENV: &str = "SPEC_FETCH_RATE_BURST";
+/// Largest spec body read, in bytes, after decompression (`0` lifts the limit)
+pub const SPEC_FETCH_MAX_BYTES_ENV: &str = "SPEC_FETCH_MAX_BYTES";
+
+/// Default of `SPEC_FETCH_MAX_BYTES`, far above real specs but well below what would
+/// exhaust the memory of the operator or the server
+pub const DEFAULT_MAX_BODY_BYTES: usize = 32 * 1024 * 1024;
+
+/// Number of body bytes kept in errors for troubleshooting
+const ERROR_SNIPPET_BYTES: usize = 512;
+
+/// First bytes of a gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Client-wide fetch settings
+#[derive(Debug, Clone)]
+pub struct FetchConfig {
+    /// Total time allowed for a single attempt
+    pub timeout: Duration,
+    pub connect_timeout: Duration,
+    /// Attempts per fetch, including the first one
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubled for each further attempt
+    pub base_backoff: Duration,
+    /// Upper bound of the delay between attempts
+    pub max_backoff: Duration,
+    /// Abort the download once the body exceeds this many bytes
+    pub max_body_bytes: Option<usize>,
+    /// Proxy URL for every request; without one the client follows `HTTP_PROXY`,
+    /// `HTTPS_PROXY` and `NO_PROXY`
+    pub proxy: Option<String>,
+    /// Limit of requests shared by the fetcher and those derived from it
+    pub rate_limit: Option<RateLimit>,
+    pub tls: TlsOptions,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            connect_timeout: Duration::from_secs(5),
+            max_attempts: 3,
+            base_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+            max_body_bytes: Some(DEFAULT_MAX_BODY_BYTES),
+            proxy: None,
+            rate_limit: None,
+            tls: TlsOptions::default(),
+        }
+    }
+}
+
+impl FetchConfig {
+    /// Load configuration from environment variables, keeping defaults for anything unset.
+    /// A value that does not parse is an error naming the variable, so a typo stops the
+    /// binary at startup instead of silently falling back to the default.
+    pub fn from_env() -> Result<Self, FetchError> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// [`FetchConfig::from_env`] with variables looked up through `var`
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, FetchError> {
+        let mut config = Self::default();
+
+        if let Some(secs) = parse_var(&var, SPEC_FETCH_TIMEOUT_ENV, "seconds")? {
+            config.timeout = Duration::from_secs(secs);
+        }
+        if let Some(secs) = parse_var(&var, SPEC_FETCH_CONNECT_TIMEOUT_ENV, "seconds")? {
+            config.connect_timeout = Duration::from_secs(secs);
+        }
+        if let Some(retries) =
+            parse_var::<u32>(&var, SPEC_FETCH_RETRIES_ENV, "a number of retries")?
+        {
+            config.max_attempts = retries.saturating_add(1);
+        }
+        if let Some(millis) = parse_var(&var, SPEC_FETCH_RETRY_BACKOFF_ENV, "milliseconds")? {
+            config.base_backoff = Duration::from_millis(millis);
+        }
+        if let Some(path) = var(SPEC_FETCH_CA_BUNDLE_ENV).filter(|path| !path.trim().is_empty()) {
+            match std::fs::read(path.trim()) {
+                Ok(pem) => config.tls.ca_certificates.push(pem),
+                Err(e) => tracing::warn!(
+                    "Failed to read {} '{}', only the system roots are trusted: {}",
+                    SPEC_FETCH_CA_BUNDLE_ENV,
+                    path,
+                    e
+                ),
+            }
+        }
+        config.proxy = var(SPEC_FETCH_PROXY_ENV)
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty());
+        if let Some(rate) =
+            parse_var::<f64>(&var, SPEC_FETCH_RATE_LIMIT_ENV, "requests per second")?
+        {
+            let burst = parse_var(&var, SPEC_FETCH_RATE_BURST_ENV, "a number of requests")?;
+            config.rate_limit = RateLimit::new(rate, burst);
+        }
+        if let Some(bytes) = parse_var::<usize>(&var, SPEC_FETCH_MAX_BYTES_ENV, "bytes")? {
+            config.max_body_bytes = Some(bytes).filter(|bytes| *bytes > 0);
+        }
+
+        Ok(config)
+    }
+}
+
+/// Value of the variable `name`, `None` when unset or blank
+fn parse_var<T: std::str::FromStr>(
+    var: impl Fn(&str) -> Option<String>,
+    name: &str,
+    expected: &str,
+) -> Result<Option<T>, FetchError> {
+    let Some(value) = var(name).filter(|value| !value.trim().is_empty()) else {
+        return Ok(None);
+    };
+    value.trim().parse().map(Some).map_err(|_| {
+        FetchError::Config(format!(
+            "Invalid {}: '{}', expected {}",
+            name, value, expected
+        ))
+    })
+}
+
+/// TLS trust settings applied when building the client
+#[derive(Debug, Clone, Default)]
+pub struct TlsOptions {
+    /// Additional PEM-encoded CA certificates to trust
+    pub ca_certificates: Vec<Vec<u8>>,
+    /// Accept any server certificate. Only meant for development clusters.
+    pub insecure_skip_verify: bool,
+    /// Client certificate presented to servers requiring mTLS
+    pub client_identity: Option<ClientIdentity>,
+}
+
+/// PEM-encoded client certificate chain and its PKCS#8 private key, as in a
+/// `kubernetes.io/tls` Secret
+#[derive(Clone)]
+pub struct ClientIdentity {
+    pub certificate: Vec<u8>,
+    pub private_key: Vec<u8>,
+}
+
+impl fmt::Debug for ClientIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientIdentity").finish_non_exhaustive()
+    }
+}
+
+/// Credentials injected into a single request
+#[derive(Debug, Clone)]
+pub enum FetchAuth {
+    Bearer(String),
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+}
+
+/// Validators from a previous response, used for conditional requests
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheValidators {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+impl CacheValidators {
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let get = |name| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        Self {
+            etag: get(ETAG),
+            last_modified: get(LAST_MODIFIED),
+        }
+    }
+}
+
+/// Per-request options
+#[derive(Debug, Clone, Default)]
+pub struct FetchRequest {
+    pub auth: Option<FetchAuth>,
+    /// Extra headers sent verbatim
+    pub headers: Vec<(String, String)>,
+    /// Send `If-None-Match`/`If-Modified-Since` from a previous response
+    pub validators: Option<CacheValidators>,
+}
+
+/// A successfully downloaded spec
+#[derive(Debug, Clone)]
+pub struct FetchedSpec {
+    pub body: String,
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub validators: CacheValidators,
+}
+
+#[derive(Debug, Clone)]
+pub enum FetchOutcome {
+    Fetched(FetchedSpec),
+    /// The server answered 304 to a conditional request
+    NotModified,
+}
+
+#[derive(Debug)]
+pub enum FetchError {
+    /// Building the client or request failed (bad CA bundle, invalid header, ...)
+    Config(String),
+    /// Connection, timeout or protocol failure
+    Transport(reqwest::Error),
+    /// The server answered with a non-success status
+    Status { code: u16, snippet: String },
+    /// The body exceeded the configured size limit
+    TooLarge { limit: usize },
+    /// The body looked gzipped but did not decompress
+    Decompress(String),
+}
+
+impl FetchError {
+    /// Whether another attempt could reasonably succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            FetchError::Transport(_) => true,
+            FetchError::Status { code, .. } => {
+                *code == StatusCode::TOO_MANY_REQUESTS.as_u16() || *code >= 500
+            }
+            FetchError::Config(_) | FetchError::TooLarge { .. } | FetchError::Decompress(_) => {
+                false
+            }
+        }
+    }
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Config(msg) => write!(f, "Fetch configuration error: {}", msg),
+            FetchError::Transport(e) => write!(f, "HTTP error: {}", e),
+            FetchError::Status { code, .. } => write!(f, "HTTP error: status {}", code),
+            FetchError::TooLarge { limit } => {
+                write!(f, "Spec too large: body exceeds {} bytes", limit)
+            }
+            FetchError::Decompress(msg) => write!(f, "Invalid gzip body: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FetchError::Transport(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Step at which fetching a spec failed
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FailurePhase {
+    /// The client or request could not be built
+    Config,
+    /// The host name did not resolve
+    Dns,
+    /// No connection could be established
+    Connect,
+    /// The TLS handshake failed, e.g. on an untrusted certificate
+    Tls,
+    /// The server did not answer in time
+    Timeout,
+    /// The server answered with a non-success status
+    Status,
+    /// The body could not be read
+    Body,
+    /// The body exceeded the size limit, `SPEC_FETCH_MAX_BYTES`
+    Size,
+    /// The body is not a JSON or YAML OpenAPI document
+    Parse,
+    /// A stored spec failed signature verification
+    Signature,
+}
+
+impl fmt::Display for FailurePhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FailurePhase::Config => "config",
+            FailurePhase::Dns => "dns",
+            FailurePhase::Connect => "connect",
+            FailurePhase::Tls => "tls",
+            FailurePhase::Timeout => "timeout",
+            FailurePhase::Status => "status",
+            FailurePhase::Body => "body",
+            FailurePhase::Size => "size",
+            FailurePhase::Parse => "parse",
+            FailurePhase::Signature => "signature",
+        })
+    }
+}
+
+/// Details of a failed fetch, kept with an unavailable API for troubleshooting
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FetchFailure {
+    pub phase: FailurePhase,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Start of the response body
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+    pub message: String,
+}
+
+impl FetchFailure {
+    pub fn new(phase: FailurePhase, message: impl Into<String>) -> Self {
+        Self {
+            phase,
+            status: None,
+            snippet: None,
+            message: message.into(),
+        }
+    }
+
+    /// A successful response whose body is not a spec
+    pub fn parse(body: &str, error: impl fmt::Display) -> Self {
+        Self {
+            snippet: Some(snippet(body)).filter(|s| !s.is_empty()),
+            ..Self::new(
+                FailurePhase::Parse,
+                format!("Invalid OpenAPI document: {}", error),
+            )
+        }
+    }
+}
+
+impl fmt::Display for FetchFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({} phase)", self.message, self.phase)
+    }
+}
+
+impl std::error::Error for FetchFailure {}
+
+impl FetchError {
+    /// Troubleshooting details: the phase is told apart by the error chain since `reqwest`
+    /// only reports connection failures as a whole
+    pub fn failure(&self) -> FetchFailure {
+        match self {
+            FetchError::Config(_) => FetchFailure::new(FailurePhase::Config, self.to_string()),
+            FetchError::Transport(e) => {
+                let mut causes = Vec::new();
+                let mut source = std::error::Error::source(e);
+                while let Some(cause) = source {
+                    causes.push(cause.to_string());
+                    source = cause.source();
+                }
+                let chain = causes.join("\n").to_lowercase();
+                let phase = if chain.contains("dns error") || chain.contains("lookup address") {
+                    FailurePhase::Dns
+                } else if ["certificate", "tls", "ssl", "handshake"]
+                    .iter()
+                    .any(|hint| chain.contains(hint))
+                {
+                    FailurePhase::Tls
+                } else if e.is_connect() {
+                    FailurePhase::Connect
+                } else if e.is_timeout() {
+                    FailurePhase::Timeout
+                } else {
+                    FailurePhase::Body
+                };
+                let message = match causes.last() {
+                    Some(root) => format!("{}: {}", self, root),
+                    None => self.to_string(),
+                };
+                FetchFailure::new(phase, message)
+            }
+            FetchError::Status { code, snippet } => FetchFailure {
+                status: Some(*code),
+                snippet: Some(snippet.clone()).filter(|s| !s.is_empty()),
+                ..FetchFailure::new(FailurePhase::Status, self.to_string())
+            },
+            FetchError::TooLarge { .. } => FetchFailure::new(FailurePhase::Size, self.to_string()),
+            FetchError::Decompress(_) => FetchFailure::new(FailurePhase::Body, self.to_string()),
+        }
+    }
+}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(err: reqwest::Error) -> Self {
+        FetchError::Transport(err)
+    }
+}
+
+/// Spec fetcher shared by all reconciles / refreshes
+#[derive(Clone)]
+pub struct SpecFetcher {
+    client: reqwest::Client,
+    config: FetchConfig,
+    limiter: Option<Arc<TokenBucket>>,
+}
+
+impl SpecFetcher {
+    pub fn new(config: FetchConfig) -> Result<Self, FetchError> {
+        let client = build_client(&config, &config.tls)?;
+        let limiter = config
+            .rate_limit
+            .map(|limit| Arc::new(TokenBucket::new(limit)));
+        Ok(Self {
+            client,
+            config,
+            limiter,
+        })
+    }
+
+    pub fn config(&self) -> &FetchConfig {
+        &self.config
+    }
+
+    /// Returns a fetcher sharing this configuration and rate limit but trusting different
+    /// TLS settings
+    pub fn with_tls(&self, tls: TlsOptions) -> Result<Self, FetchError> {
+        let client = build_client(&self.config, &tls)?;
+        Ok(Self {
+            client,
+            config: FetchConfig {
+                tls,
+                ..self.config.clone()
+            },
+            limiter: self.limiter.clone(),
+        })
+    }
+
+    /// Fetches a spec, retrying transient failures with jittered exponential backoff
+    pub async fn fetch(
+        &self,
+        url: &str,
+        request: &FetchRequest,
+    ) -> Result<FetchOutcome, FetchError> {
+        let attempts = self.config.max_attempts.max(1);
+        let mut attempt = 1;
+        loop {
+            match self.fetch_once(url, request).await {
+                Err(e) if e.is_retryable() && attempt < attempts => {
+                    let delay = backoff(&self.config, attempt);
+                    tracing::debug!(
+                        "Fetching {} failed (attempt {}/{}): {}, retrying in {:?}",
+                        url,
+                        attempt,
+                        attempts,
+                        e,
+                        delay
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn fetch_once(
+        &self,
+        url: &str,
+        request: &FetchRequest,
+    ) -> Result<FetchOutcome, FetchError> {
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire().await;
+        }
+        let mut builder = self.client.get(url);
+
+        match &request.auth {
+            Some(FetchAuth::Bearer(token)) => builder = builder.bearer_auth(token),
+            Some(FetchAuth::Basic { username, password }) => {
+                builder = builder.basic_auth(username, password.as_ref())
+            }
+            None => {}
+        }
+
+        let mut headers = HeaderMap::new();
+        for (name, value) in &request.headers {
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| {
+                FetchError::Config(format!("invalid header name '{}': {}", name, e))
+            })?;
+            let value = HeaderValue::from_str(value).map_err(|e| {
+                FetchError::Config(format!("invalid value for header '{}': {}", name, e))
+            })?;
+            headers.insert(name, value);
+        }
+        if let Some(validators) = &request.validators {
+            if let Some(etag) = validators
+                .etag
+                .as_deref()
+                .and_then(|v| HeaderValue::from_str(v).ok())
+            {
+                headers.insert(IF_NONE_MATCH, etag);
+            }
+            if let Some(modified) = validators
+                .last_modified
+                .as_deref()
+                .and_then(|v| HeaderValue::from_str(v).ok())
+            {
+                headers.insert(IF_MODIFIED_SINCE, modified);
+            }
+        }
+
+        let mut response = builder.headers(headers).send().await?;
+        let status = response.status();
+
+        if status == StatusCode::NOT_MODIFIED {
+            return Ok(FetchOutcome::NotModified);
+        }
+
+        let validators = CacheValidators::from_headers(response.headers());
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+
+        if let (Some(limit), Some(length)) = (self.config.max_body_bytes, response.content_length())
+            && length as usize > limit
+        {
+            return Err(FetchError::TooLarge { limit });
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if let Some(limit) = self.config.max_body_bytes
+                && body.len() > limit
+            {
+                return Err(FetchError::TooLarge { limit });
+            }
+        }
+        if body.starts_with(&GZIP_MAGIC) {
+            body = gunzip(&body, self.config.max_body_bytes)?;
+        }
+        let body = String::from_utf8_lossy(&body).into_owned();
+
+        if !status.is_success() {
+            return Err(FetchError::Status {
+                code: status.as_u16(),
+                snippet: snippet(&body),
+            });
+        }
+
+        Ok(FetchOutcome::Fetched(FetchedSpec {
+            body,
+            status: status.as_u16(),
+            content_type,
+            validators,
+        }))
+    }
+}
+
+fn build_client(config: &FetchConfig, tls: &TlsOptions) -> Result<reqwest::Client, FetchError> {
+    // Sends `Accept-Encoding: gzip, br`; large specs compress to a fraction of their size
+    let mut builder = reqwest::Client::builder()
+        .timeout(config.timeout)
+        .connect_timeout(config.connect_timeout)
+        .gzip(true)
+        .brotli(true);
+
+    if let Some(url) = &config.proxy {
+        let proxy = reqwest::Proxy::all(url)
+            .map_err(|e| FetchError::Config(format!("invalid proxy '{}': {}", url, e)))?
+            .no_proxy(reqwest::NoProxy::from_env());
+        builder = builder.proxy(proxy);
+    }
+
+    for pem in &tls.ca_certificates {
+        let certs = reqwest::Certificate::from_pem_bundle(pem)
+            .map_err(|e| FetchError::Config(format!("invalid CA bundle: {}", e)))?;
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    if tls.insecure_skip_verify {
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    if let Some(identity) = &tls.client_identity {
+        let identity =
+            reqwest::Identity::from_pkcs8_pem(&identity.certificate, &identity.private_key)
+                .map_err(|e| FetchError::Config(format!("invalid client certificate: {}", e)))?;
+        builder = builder.identity(identity);
+    }
+
+    builder
+        .build()
+        .map_err(|e| FetchError::Config(format!("failed to build HTTP client: {}", e)))
+}
+
+/// Delay after the given failed attempt: the base backoff doubled per attempt, capped, of
+/// which a random half is waited, so fetches failing together do not retry in lockstep
+fn backoff(config: &FetchConfig, attempt: u32) -> Duration {
+    let delay = config
+        .base_backoff
+        .saturating_mul(2_u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(config.max_backoff);
+    let half = delay / 2;
+    half + half.mul_f64(fastrand::f64())
+}
+
+/// Decompresses a spec file the server stored gzipped (`openapi.json.gz`) and serves as is,
+/// without `Content-Encoding`; responses with `Content-Encoding: gzip` or `br` are already
+/// decompressed by the client
+fn gunzip(body: &[u8], limit: Option<usize>) -> Result<Vec<u8>, FetchError> {
+    let mut decompressed = Vec::new();
+    let read = GzDecoder::new(body)
+        .take(limit.map_or(u64::MAX, |limit| limit as u64 + 1))
+        .read_to_end(&mut decompressed);
+    read.map_err(|e| FetchError::Decompress(e.to_string()))?;
+    if let Some(limit) = limit
+        && decompressed.len() > limit
+    {
+        return Err(FetchError::TooLarge { limit });
+    }
+    Ok(decompressed)
+}
+
+/// Truncates a response body to a short, printable snippet
+fn snippet(body: &str) -> String {
+    let mut end = body.len().min(ERROR_SNIPPET_BYTES);
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+    body[..end].trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    #[test]
+    fn gunzips_precompressed_specs_within_the_limit() {
+        let spec = br#"{"openapi": "3.0.3", "paths": {}}"#;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(spec).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        assert!(gzipped.starts_with(&GZIP_MAGIC));
+
+        assert_eq!(gunzip(&gzipped, None).unwrap(), spec);
+        assert!(matches!(
+            gunzip(&gzipped, Some(10)),
+            Err(FetchError::TooLarge { limit: 10 })
+        ));
+        assert_eq!(
+            FetchError::TooLarge { limit: 10 }.failure().phase,
+            FailurePhase::Size
+        );
+        assert!(matches!(
+            gunzip(&GZIP_MAGIC, None),
+            Err(FetchError::Decompress(_))
+        ));
+    }
+
+    #[test]
+    fn explicit_proxy_must_be_a_url() {
+        let config = FetchConfig {
+            proxy: Some("http://proxy.corp:3128".to_string()),
+            ..Default::default()
+        };
+        assert!(SpecFetcher::new(config).is_ok());
+
+        let config = FetchConfig {
+            proxy: Some("not a proxy".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            SpecFetcher::new(config),
+            Err(FetchError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_environment_values_name_the_variable() {
+        let vars = |pairs: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                pairs
+                    .iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+
+        let config = FetchConfig::from_vars(vars(&[
+            (SPEC_FETCH_TIMEOUT_ENV, " 45 "),
+            (SPEC_FETCH_RETRIES_ENV, "0"),
+            (SPEC_FETCH_MAX_BYTES_ENV, ""),
+        ]))
+        .unwrap();
+        assert_eq!(config.timeout, Duration::from_secs(45));
+        assert_eq!(config.max_attempts, 1);
+        assert_eq!(config.max_body_bytes, FetchConfig::default().max_body_bytes);
+
+        let err = FetchConfig::from_vars(vars(&[(SPEC_FETCH_RETRY_BACKOFF_ENV, "1s")]))
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "Fetch configuration error: Invalid SPEC_FETCH_RETRY_BACKOFF: '1s', expected milliseconds"
+        );
+        assert!(FetchConfig::from_vars(vars(&[(SPEC_FETCH_MAX_BYTES_ENV, "-1")])).is_err());
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap_with_jitter() {
+        let config = FetchConfig {
+            base_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(1),
+            ..Default::default()
+        };
+        for (attempt, full) in [(1, 200), (2, 400), (3, 800), (4, 1000), (30, 1000)] {
+            let delay = backoff(&config, attempt);
+            let full = Duration::from_millis(full);
+            assert!(delay >= full / 2 && delay <= full, "{:?}", delay);
+        }
+    }
+}
@@ -1,19 +1,214 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+pub mod annotations;
+#[cfg(feature = "crd")]
+pub mod api_doc;
+#[cfg(feature = "catalog")]
+pub mod catalog;
+pub mod complexity;
+pub mod contract;
+#[cfg(feature = "crd")]
+pub mod crd;
+pub mod diff;
+pub mod digest;
+pub mod encoding;
+pub mod fetch;
+pub mod logging;
+pub mod names;
+pub mod openapi;
+pub mod rate_limit;
+pub mod s3;
+pub mod schedule;
+pub mod signing;
+pub mod sigv4;
+pub mod slugs;
+pub mod swagger;
+pub mod tenancy;
+
+pub use openapi::OpenApiDocument;
 
 /// Standard Kubernetes annotations for API documentation
 pub const API_DOC_ENABLED_ANNOTATION: &str = "api-doc.io/enabled";
 pub const API_DOC_PATH_ANNOTATION: &str = "api-doc.io/path";
 pub const API_DOC_NAME_ANNOTATION: &str = "api-doc.io/name";
 pub const API_DOC_DESCRIPTION_ANNOTATION: &str = "api-doc.io/description";
+pub const API_DOC_GATEWAY_PUBLISH_ANNOTATION: &str = "api-doc.io/gateway-publish";
+pub const API_DOC_CONTRACT_CHECK_ANNOTATION: &str = "api-doc.io/contract-check";
+pub const API_DOC_ALLOW_BREAKING_ANNOTATION: &str = "api-doc.io/allow-breaking";
+pub const API_DOC_LIFECYCLE_ANNOTATION: &str = "api-doc.io/lifecycle";
+pub const API_DOC_PORT_ANNOTATION: &str = "api-doc.io/port";
+pub const API_DOC_PORT_NAME_ANNOTATION: &str = "api-doc.io/port-name";
+pub const API_DOC_SCHEME_ANNOTATION: &str = "api-doc.io/scheme";
+pub const API_DOC_INSECURE_SKIP_VERIFY_ANNOTATION: &str = "api-doc.io/insecure-skip-verify";
+pub const API_DOC_TLS_SECRET_ANNOTATION: &str = "api-doc.io/tls-secret";
+pub const API_DOC_AUTH_SECRET_ANNOTATION: &str = "api-doc.io/auth-secret";
+pub const API_DOC_FETCH_VIA_ANNOTATION: &str = "api-doc.io/fetch-via";
+pub const API_DOC_URL_ANNOTATION: &str = "api-doc.io/url";
+pub const API_DOC_SPEC_CONFIGMAP_ANNOTATION: &str = "api-doc.io/spec-configmap";
+pub const API_DOC_SERVER_URL_ANNOTATION: &str = "api-doc.io/server-url";
+pub const API_DOC_EXCLUDE_ANNOTATION: &str = "api-doc.io/exclude";
+pub const API_DOC_REFRESH_INTERVAL_ANNOTATION: &str = "api-doc.io/refresh-interval";
+pub const API_DOC_REFRESH_WINDOW_ANNOTATION: &str = "api-doc.io/refresh-window";
+pub const API_DOC_API_GROUP_ANNOTATION: &str = "api-doc.io/api-group";
+pub const API_DOC_UI_THEME_ANNOTATION: &str = "api-doc.io/ui.theme";
+pub const API_DOC_UI_LAYOUT_ANNOTATION: &str = "api-doc.io/ui.layout";
+pub const API_DOC_UI_DARK_MODE_ANNOTATION: &str = "api-doc.io/ui.dark-mode";
+/// Prefix of Redoc options, e.g. `api-doc.io/ui.redoc.hideDownloadButton`
+pub const API_DOC_UI_REDOC_ANNOTATION_PREFIX: &str = "api-doc.io/ui.redoc.";
 
 /// Default values
 pub const DEFAULT_API_DOC_PATH: &str = "/swagger/openapi.yml";
 
 /// Environment variables
 pub const WATCH_NAMESPACES_ENV: &str = "WATCH_NAMESPACES";
+pub const WATCH_NAMESPACE_SELECTOR_ENV: &str = "WATCH_NAMESPACE_SELECTOR";
+/// Namespaces (or glob patterns) that are never discovered, whatever else matches them
+pub const EXCLUDE_NAMESPACES_ENV: &str = "EXCLUDE_NAMESPACES";
+/// Label selector of Services discovered without `api-doc.io/enabled`, e.g. `team=payments`
+pub const DISCOVERY_LABEL_SELECTOR_ENV: &str = "DISCOVERY_LABEL_SELECTOR";
 pub const DISCOVERY_NAMESPACE_ENV: &str = "DISCOVERY_NAMESPACE";
 pub const DISCOVERY_CONFIGMAP_ENV: &str = "DISCOVERY_CONFIGMAP";
+/// Where the operator writes the catalog: `configmap` (default) or `crd` (`DiscoveredApi` resources)
+pub const DISCOVERY_OUTPUT_ENV: &str = "DISCOVERY_OUTPUT";
+/// Encoding of the discovery payload: `json` (default), `gzip` or `msgpack`
+pub const DISCOVERY_ENCODING_ENV: &str = "DISCOVERY_ENCODING";
+/// Kind of object holding the discovery payload: `configmap` (default) or `secret`
+pub const DISCOVERY_STORAGE_ENV: &str = "DISCOVERY_STORAGE";
+/// Layout of the catalog in the discovery object: `document` (default) or `entries` (one key
+/// per entry)
+pub const DISCOVERY_LAYOUT_ENV: &str = "DISCOVERY_LAYOUT";
+/// Where stored specs are kept besides the catalog: `configmap` (one ConfigMap per API) or
+/// `s3`; unset keeps no copy
+pub const SPEC_STORAGE_ENV: &str = "SPEC_STORAGE";
+/// Earlier revisions of each spec kept by the spec storage (`0` keeps none)
+pub const SPEC_HISTORY_LIMIT_ENV: &str = "SPEC_HISTORY_LIMIT";
+/// How specs are fetched unless a Service sets `api-doc.io/fetch-via`: `direct` or `apiserver`
+pub const SPEC_FETCH_VIA_ENV: &str = "SPEC_FETCH_VIA";
+/// Seconds removed entries stay in the discovery document as tombstones (`0` disables)
+pub const TOMBSTONE_GRACE_PERIOD_ENV: &str = "TOMBSTONE_GRACE_PERIOD";
+pub const TENANT_MAPPING_ENV: &str = "TENANT_MAPPING";
+pub const TENANT_LABEL_ENV: &str = "TENANT_LABEL";
+/// Apply `api-doc.io/*` annotations of Namespaces to their Services (`true` to enable)
+pub const NAMESPACE_DEFAULTS_ENV: &str = "NAMESPACE_DEFAULTS";
+/// Cron-like window spec fetches are limited to, unless `api-doc.io/refresh-window` says otherwise
+pub const REFRESH_WINDOW_ENV: &str = "REFRESH_WINDOW";
+/// Reconcile `ApiDoc` resources into the catalog next to annotated Services (`true` to enable)
+pub const WATCH_API_DOCS_ENV: &str = "WATCH_API_DOCS";
+/// Treat `api-doc.io/*` annotations of Deployments like those of the Services selecting
+/// their pods (`true` to enable)
+pub const WATCH_DEPLOYMENTS_ENV: &str = "WATCH_DEPLOYMENTS";
+/// Reconcile the Services selecting a Deployment once its rollout completes (`true` to enable)
+pub const REFRESH_ON_ROLLOUT_ENV: &str = "REFRESH_ON_ROLLOUT";
+/// Objects reconciled at the same time per controller (`0` or unset = unlimited)
+pub const MAX_CONCURRENT_RECONCILES_ENV: &str = "MAX_CONCURRENT_RECONCILES";
+/// Time between reconciles of the same object, in seconds or with a unit (`5m`)
+pub const RECONCILE_INTERVAL_ENV: &str = "RECONCILE_INTERVAL";
+/// Time before a failed reconcile is retried, in seconds or with a unit (`30s`)
+pub const ERROR_RETRY_INTERVAL_ENV: &str = "ERROR_RETRY_INTERVAL";
+/// Discover and fetch as usual, but only log what would be written (`true` to enable)
+pub const DRY_RUN_ENV: &str = "DRY_RUN";
+
+/// Stability stage of an API, from `api-doc.io/lifecycle`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "crd", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Lifecycle {
+    Alpha,
+    Beta,
+    /// Generally available
+    Ga,
+    /// Still served, but consumers should migrate away
+    Deprecated,
+    /// No longer supported; kept in the catalog for reference
+    Retired,
+}
+
+impl Lifecycle {
+    pub const ALL: [Lifecycle; 5] = [
+        Lifecycle::Alpha,
+        Lifecycle::Beta,
+        Lifecycle::Ga,
+        Lifecycle::Deprecated,
+        Lifecycle::Retired,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Lifecycle::Alpha => "alpha",
+            Lifecycle::Beta => "beta",
+            Lifecycle::Ga => "ga",
+            Lifecycle::Deprecated => "deprecated",
+            Lifecycle::Retired => "retired",
+        }
+    }
+
+    /// Parses a comma-separated filter such as `beta,ga`
+    pub fn parse_list(value: &str) -> Result<Vec<Lifecycle>, String> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::parse)
+            .collect()
+    }
+}
+
+impl fmt::Display for Lifecycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Lifecycle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Lifecycle::ALL
+            .into_iter()
+            .find(|stage| stage.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                format!(
+                    "unknown lifecycle stage \"{}\", expected alpha, beta, ga, deprecated or retired",
+                    s
+                )
+            })
+    }
+}
+
+/// Per-API appearance in the documentation frontends, from `api-doc.io/ui.*`
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "crd", derive(schemars::JsonSchema))]
+pub struct UiOptions {
+    /// Scalar theme, e.g. `deepSpace`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme: Option<String>,
+    /// Scalar layout, `modern` or `classic`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dark_mode: Option<bool>,
+    /// Redoc options by name; values are JSON, or plain strings when they do not parse
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub redoc: BTreeMap<String, String>,
+}
+
+impl UiOptions {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Id of the catalog entry of a service in the operator's own cluster, `namespace.service`.
+/// Namespace and service names are DNS labels, which cannot contain the dot, so two services
+/// never share an id as `a-b/c` and `a/b-c` would with a dash. ConfigMap names and keys may
+/// contain dots, so the id can name the objects and keys stored for the entry.
+pub fn entry_id(namespace: &str, service_name: &str) -> String {
+    format!("{}.{}", namespace, service_name)
+}
 
 /// Service inventory entry for the discovery ConfigMap.
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -26,13 +221,214 @@ pub struct ApiInventoryEntry {
     pub description: Option<String>,
     pub last_updated: DateTime<Utc>,
     pub available: bool,
+    /// Why the last spec fetch failed, while the entry is unavailable
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// Spec fetches that failed in a row since the spec was last stored
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub consecutive_failures: u32,
+    /// Tenant owning the entry's namespace, `None` for entries shared with all tenants
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// Cluster the entry was federated from, `None` for the operator's own cluster
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cluster: Option<String>,
+    /// Latest live contract check, for services that opted in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub contract: Option<contract::ContractReport>,
+    /// Stability stage declared by the service
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lifecycle: Option<Lifecycle>,
+    /// Complexity of the published spec
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<complexity::SpecMetrics>,
+    /// Frontend options declared by the service
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ui: Option<UiOptions>,
+    /// Seconds between spec fetches requested by the service, from `api-doc.io/refresh-interval`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_interval: Option<u64>,
+    /// Cron-like window spec fetches are limited to, see [`schedule::RefreshWindow`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_window: Option<String>,
+    /// The spec was last fetched before the refresh window closed and is kept until it opens
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stale_by_schedule: bool,
+    /// Logical API this entry is a version of, from `api-doc.io/api-group`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_group: Option<String>,
+    /// Fetch the spec without verifying the server certificate, from
+    /// `api-doc.io/insecure-skip-verify`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub insecure_skip_verify: bool,
+    /// SHA-256 of the spec the entry was stored with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spec_sha256: Option<String>,
+    /// ConfigMap in the discovery namespace holding the spec and this entry, with
+    /// `SPEC_STORAGE=configmap`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spec_config_map: Option<String>,
+    /// `s3://` URL of the stored spec, with `SPEC_STORAGE=s3`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spec_url: Option<String>,
+    /// What the last stored revision of the spec changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_change_summary: Option<diff::ChangeSummary>,
+}
+
+impl ApiInventoryEntry {
+    /// Available entry of a service in the operator's own cluster, with its [`entry_id`], the
+    /// name discovery gives it by default and no optional metadata. The operator builds its
+    /// catalog entries from it, so other producers of entries get the same id.
+    pub fn new(namespace: &str, service_name: &str, url: impl Into<String>) -> Self {
+        ApiInventoryEntry {
+            id: entry_id(namespace, service_name),
+            name: format!("{} API", service_name),
+            namespace: namespace.to_string(),
+            service_name: service_name.to_string(),
+            url: url.into(),
+            description: None,
+            last_updated: Utc::now(),
+            available: true,
+            last_error: None,
+            consecutive_failures: 0,
+            tenant: None,
+            cluster: None,
+            contract: None,
+            lifecycle: None,
+            metrics: None,
+            ui: None,
+            refresh_interval: None,
+            refresh_window: None,
+            stale_by_schedule: false,
+            api_group: None,
+            insecure_skip_verify: false,
+            spec_sha256: None,
+            spec_config_map: None,
+            spec_url: None,
+            last_change_summary: None,
+        }
+    }
+
+    /// Whether both entries describe the same spec and metadata, regardless of when they were
+    /// last updated
+    pub fn same_content(&self, other: &ApiInventoryEntry) -> bool {
+        let content = |entry: &ApiInventoryEntry| {
+            serde_json::to_value(ApiInventoryEntry {
+                last_updated: DateTime::<Utc>::UNIX_EPOCH,
+                ..entry.clone()
+            })
+            .ok()
+        };
+        self.spec_sha256.is_some() && content(self) == content(other)
+    }
+}
+
+pub(crate) fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+/// Why an entry was removed from the catalog
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RemovalReason {
+    /// The Service was deleted
+    ServiceDeleted,
+    /// The namespace is no longer watched
+    NamespaceNotWatched,
+    /// `api-doc.io/enabled` was removed or set to false
+    DocsDisabled,
+    /// The spec could not be fetched
+    Unreachable,
+    /// The port named by `api-doc.io/port` is not on the Service
+    PortNotFound,
+    /// The namespace is in `EXCLUDE_NAMESPACES`, or `api-doc.io/exclude` is set
+    Excluded,
+    /// The `ApiDoc` registering the API was deleted or became invalid
+    ApiDocRemoved,
+    /// The Service was gone when the entry outlived `CATALOG_TTL`
+    Expired,
+    /// The Service answered with something that is not an OpenAPI document
+    InvalidSpec,
+    /// The annotated Deployment was deleted or is now discovered through a Service
+    DeploymentRemoved,
+    /// The spec exceeded `SPEC_FETCH_MAX_BYTES`
+    SpecTooLarge,
+}
+
+impl RemovalReason {
+    /// Explanation for people, e.g. in Events
+    pub fn description(&self) -> &'static str {
+        match self {
+            RemovalReason::ServiceDeleted => "the Service was deleted",
+            RemovalReason::NamespaceNotWatched => "its namespace is not watched",
+            RemovalReason::DocsDisabled => "api-doc.io/enabled is not \"true\"",
+            RemovalReason::Unreachable => "the spec could not be fetched",
+            RemovalReason::PortNotFound => {
+                "the port named by api-doc.io/port is not on the Service"
+            }
+            RemovalReason::Excluded => "the Service or its namespace is excluded",
+            RemovalReason::ApiDocRemoved => "its ApiDoc was deleted or is invalid",
+            RemovalReason::Expired => "the Service was gone after CATALOG_TTL",
+            RemovalReason::InvalidSpec => "the Service serves no valid OpenAPI document",
+            RemovalReason::DeploymentRemoved => {
+                "its Deployment was deleted or is now discovered through a Service"
+            }
+            RemovalReason::SpecTooLarge => "the spec is too large (SPEC_FETCH_MAX_BYTES)",
+        }
+    }
+}
+
+/// Record of a removed entry, kept in the discovery document for the grace period
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiTombstone {
+    pub id: String,
+    pub name: String,
+    pub namespace: String,
+    pub service_name: String,
+    pub deleted_at: DateTime<Utc>,
+    pub reason: RemovalReason,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+impl ApiTombstone {
+    pub fn for_entry(entry: &ApiInventoryEntry, reason: RemovalReason) -> Self {
+        Self {
+            id: entry.id.clone(),
+            name: entry.name.clone(),
+            namespace: entry.namespace.clone(),
+            service_name: entry.service_name.clone(),
+            deleted_at: Utc::now(),
+            reason,
+            tenant: entry.tenant.clone(),
+        }
+    }
 }
 
 /// Configuration for API discovery
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DiscoveryConfig {
     pub apis: Vec<ApiInventoryEntry>,
     pub last_updated: DateTime<Utc>,
+    /// Recently removed entries, see `TOMBSTONE_GRACE_PERIOD`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tombstones: Vec<ApiTombstone>,
+    /// ConfigMaps holding the entries when the catalog is too large for one; `apis` is
+    /// empty then
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shards: Vec<String>,
+}
+
+impl DiscoveryConfig {
+    /// Drops tombstones older than `grace` and those of entries that are back in the catalog
+    pub fn prune_tombstones(&mut self, grace: chrono::Duration) {
+        let cutoff = Utc::now() - grace;
+        let apis = &self.apis;
+        self.tombstones.retain(|tombstone| {
+            tombstone.deleted_at > cutoff && !apis.iter().any(|api| api.id == tombstone.id)
+        });
+    }
 }
 
 /// Utility functions for working with OpenAPI specs
@@ -62,6 +458,119 @@ pub mod spec_utils {
             Ok(serde_yaml::from_str(spec_content)?)
         }
     }
+
+    /// Parses OpenAPI spec content (JSON or YAML) into the typed document model
+    pub fn parse_spec(spec_content: &str) -> Result<crate::OpenApiDocument, Box<dyn std::error::Error + Send + Sync>> {
+        crate::OpenApiDocument::parse(spec_content)
+    }
+
+    /// Parses a fetched spec and checks that it is structurally an OpenAPI document: a
+    /// mapping with an `openapi` or `swagger` version, `info` and `paths`, so HTML error
+    /// pages and cut-off documents are not taken for specs
+    pub fn validate_spec(spec_content: &str) -> Result<crate::OpenApiDocument, String> {
+        let value = parse_spec_to_json(spec_content).map_err(|e| e.to_string())?;
+        let Some(raw) = value.as_object() else {
+            return Err("not a JSON or YAML mapping".to_string());
+        };
+        // The model turns malformed sections into defaults, so their shape is checked on
+        // the raw mapping
+        let declares_version = raw.contains_key("openapi") || raw.contains_key("swagger");
+        let is_mapping = |key: &str| raw.get(key).is_some_and(serde_json::Value::is_object);
+        let shape = if !is_mapping("info") {
+            Err("`info` is missing or not a mapping".to_string())
+        } else if !is_mapping("paths") && !is_mapping("webhooks") {
+            // OpenAPI 3.1 allows documents describing only webhooks
+            Err("`paths` is missing or not a mapping".to_string())
+        } else {
+            Ok(())
+        };
+
+        let document = crate::OpenApiDocument::from_value(value).map_err(|e| e.to_string())?;
+        match document.spec_version() {
+            Some(_) => {}
+            None if declares_version => {
+                return Err("the `openapi`/`swagger` version is not a string".to_string());
+            }
+            None => return Err("no `openapi` or `swagger` version".to_string()),
+        }
+        shape.map(|()| document)
+    }
+
+    /// Replaces the top-level `servers` of a spec with `urls` (see `api-doc.io/server-url`).
+    /// `cluster` becomes `cluster_origin` followed by the path of the spec's first server, so
+    /// base paths survive; without an origin it is left out. The spec keeps its format (JSON
+    /// or YAML) and is returned as is when no server is left.
+    ///
+    /// The servers are read and written with the typed model, while the rest of the
+    /// document, which the model does not cover, is kept as parsed.
+    pub fn rewrite_servers(
+        spec_content: &str,
+        urls: &[crate::annotations::ServerUrl],
+        cluster_origin: Option<&str>,
+    ) -> Result<String, String> {
+        use crate::annotations::ServerUrl;
+        use crate::openapi::Server;
+
+        let mut value = parse_spec_to_json(spec_content).map_err(|e| e.to_string())?;
+        let Some(document) = value.as_object_mut() else {
+            return Err("not a JSON or YAML mapping".to_string());
+        };
+        let current: Vec<Server> = document
+            .get("servers")
+            .cloned()
+            .and_then(|servers| serde_json::from_value(servers).ok())
+            .unwrap_or_default();
+        let base_path = current
+            .first()
+            .map(|server| match reqwest::Url::parse(&server.url) {
+                Ok(absolute) => absolute.path().to_string(),
+                // Relative server URLs are paths
+                Err(_) => server.url.clone(),
+            })
+            .unwrap_or_default();
+        let base_path = base_path.trim_end_matches('/');
+
+        let servers: Vec<Server> = urls
+            .iter()
+            .filter_map(|url| match url {
+                ServerUrl::Cluster => cluster_origin.map(|origin| {
+                    format!("{}{}", origin.trim_end_matches('/'), base_path)
+                }),
+                ServerUrl::Url(url) => Some(url.clone()),
+            })
+            .map(|url| Server {
+                url,
+                description: None,
+            })
+            .collect();
+        if servers.is_empty() {
+            return Ok(spec_content.to_string());
+        }
+        document.insert(
+            "servers".to_string(),
+            serde_json::to_value(servers).map_err(|e| e.to_string())?,
+        );
+
+        if spec_content.trim_start().starts_with('{') {
+            serde_json::to_string_pretty(&value).map_err(|e| e.to_string())
+        } else {
+            serde_yaml::to_string(&value).map_err(|e| e.to_string())
+        }
+    }
+
+    /// Converts a Swagger 2.0 spec to OpenAPI 3.0 JSON; OpenAPI 3.x specs are returned as is.
+    /// The conversion rewrites the whole document, including everything the typed model
+    /// leaves out, so it works on the parsed JSON.
+    pub fn normalize_spec(spec_content: &str) -> Result<String, String> {
+        let value = parse_spec_to_json(spec_content).map_err(|e| e.to_string())?;
+        match crate::swagger::SpecVersion::detect(&value) {
+            Some(crate::swagger::SpecVersion::Swagger2) => {
+                serde_json::to_string_pretty(&crate::swagger::to_openapi3(value))
+                    .map_err(|e| e.to_string())
+            }
+            _ => Ok(spec_content.to_string()),
+        }
+    }
 }
 
 /// Utility functions for namespace handling
@@ -87,4 +596,213 @@ pub mod namespace_utils {
             Err(_) => Some(vec!["current".to_string()]),
         }
     }
+
+    /// Parses the WATCH_NAMESPACE_SELECTOR environment variable
+    /// Returns the label selector (e.g. `team=payments,env!=dev`) if set and non-empty
+    pub fn parse_namespace_selector() -> Option<String> {
+        env::var(super::WATCH_NAMESPACE_SELECTOR_ENV)
+            .ok()
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    /// Parses the EXCLUDE_NAMESPACES environment variable, a comma-separated list of
+    /// namespaces and glob patterns; empty when unset
+    pub fn parse_exclude_namespaces() -> Vec<String> {
+        env::var(super::EXCLUDE_NAMESPACES_ENV)
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns true if a WATCH_NAMESPACES entry is a glob pattern (`team-*`, `preview-??`)
+    pub fn is_pattern(entry: &str) -> bool {
+        entry.contains('*') || entry.contains('?')
+    }
+
+    /// Matches a namespace name against a glob pattern supporting `*` and `?`
+    pub fn glob_matches(pattern: &str, name: &str) -> bool {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let name: Vec<char> = name.chars().collect();
+        let (mut p, mut n) = (0, 0);
+        let mut backtrack: Option<(usize, usize)> = None;
+
+        while n < name.len() {
+            if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+                p += 1;
+                n += 1;
+            } else if p < pattern.len() && pattern[p] == '*' {
+                backtrack = Some((p, n));
+                p += 1;
+            } else if let Some((star, matched)) = backtrack {
+                p = star + 1;
+                n = matched + 1;
+                backtrack = Some((star, matched + 1));
+            } else {
+                return false;
+            }
+        }
+
+        pattern[p..].iter().all(|c| *c == '*')
+    }
+
+    /// Returns true if the namespace is listed explicitly or matches one of the patterns
+    pub fn namespace_matches(entries: &[String], namespace: &str) -> bool {
+        entries.iter().any(|entry| {
+            if is_pattern(entry) {
+                glob_matches(entry, namespace)
+            } else {
+                entry == namespace
+            }
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn glob_patterns() {
+            assert!(glob_matches("team-*", "team-payments"));
+            assert!(glob_matches("team-*", "team-"));
+            assert!(!glob_matches("team-*", "payments"));
+            assert!(glob_matches("preview-??", "preview-42"));
+            assert!(!glob_matches("preview-??", "preview-421"));
+            assert!(glob_matches("*-prod", "payments-prod"));
+            assert!(glob_matches("a*b*c", "axxbyyc"));
+            assert!(!glob_matches("a*b*c", "axxbyy"));
+        }
+
+        #[test]
+        fn mixed_entries() {
+            let entries = vec!["default".to_string(), "team-*".to_string()];
+            assert!(namespace_matches(&entries, "default"));
+            assert!(namespace_matches(&entries, "team-a"));
+            assert!(!namespace_matches(&entries, "kube-system"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prune_drops_expired_and_restored_tombstones() {
+        let entry = |service: &str| ApiInventoryEntry {
+            spec_sha256: Some(digest::sha256_hex(service.as_bytes())),
+            ..ApiInventoryEntry::new(
+                "shop",
+                service,
+                format!(
+                    "http://{}.shop.svc.cluster.local:8080/openapi.json",
+                    service
+                ),
+            )
+        };
+        let mut expired = ApiTombstone::for_entry(&entry("carts"), RemovalReason::Unreachable);
+        expired.deleted_at = Utc::now() - chrono::Duration::hours(2);
+
+        let mut config = DiscoveryConfig {
+            apis: vec![entry("orders")],
+            last_updated: Utc::now(),
+            tombstones: vec![
+                expired,
+                ApiTombstone::for_entry(&entry("orders"), RemovalReason::Unreachable),
+                ApiTombstone::for_entry(&entry("billing"), RemovalReason::DocsDisabled),
+            ],
+            shards: Vec::new(),
+        };
+        config.prune_tombstones(chrono::Duration::hours(1));
+
+        let ids: Vec<&str> = config.tombstones.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, vec!["shop.billing"]);
+    }
+
+    #[test]
+    fn entries_differing_only_in_last_updated_have_the_same_content() {
+        let entry = ApiInventoryEntry {
+            spec_sha256: Some("ab12".to_string()),
+            ..ApiInventoryEntry::new(
+                "shop",
+                "orders",
+                "http://orders.shop.svc.cluster.local:8080/openapi.json",
+            )
+        };
+        let refreshed = ApiInventoryEntry {
+            last_updated: Utc::now(),
+            ..entry.clone()
+        };
+        assert!(refreshed.same_content(&entry));
+
+        let changed = ApiInventoryEntry {
+            spec_sha256: Some("cd34".to_string()),
+            ..refreshed.clone()
+        };
+        assert!(!changed.same_content(&entry));
+        // A failed fetch changes the entry; healthy entries leave the failure fields out
+        let failing = ApiInventoryEntry {
+            available: false,
+            last_error: Some("connect: connection refused".to_string()),
+            consecutive_failures: 2,
+            ..entry.clone()
+        };
+        assert!(!failing.same_content(&entry));
+        let json = serde_json::to_value(&entry).unwrap();
+        assert!(json.get("last_error").is_none());
+        assert!(json.get("consecutive_failures").is_none());
+        // Entries written before hashes were stored are rewritten once
+        let unhashed = ApiInventoryEntry {
+            spec_sha256: None,
+            ..entry
+        };
+        assert!(!unhashed.same_content(&unhashed.clone()));
+    }
+
+    #[test]
+    fn validation_rejects_documents_that_are_not_specs() {
+        let spec = "openapi: 3.0.3\ninfo:\n  title: Orders\n  version: '1'\npaths: {}\n";
+        assert!(spec_utils::validate_spec(spec).is_ok());
+        assert!(spec_utils::validate_spec("swagger: 2.0\ninfo: {}\npaths: {}").is_ok());
+
+        let html = "<!DOCTYPE html><html><body>502 Bad Gateway</body></html>";
+        assert!(spec_utils::validate_spec(html).is_err());
+        let cut_off = "openapi: 3.0.3\ninfo:\n  title: Orders\n";
+        assert_eq!(
+            spec_utils::validate_spec(cut_off).unwrap_err(),
+            "`paths` is missing or not a mapping"
+        );
+        assert_eq!(
+            spec_utils::validate_spec(r#"{"info": {}, "paths": {}}"#).unwrap_err(),
+            "no `openapi` or `swagger` version"
+        );
+        assert_eq!(
+            spec_utils::validate_spec(r#"{"openapi": true, "info": {}, "paths": {}}"#)
+                .unwrap_err(),
+            "the `openapi`/`swagger` version is not a string"
+        );
+        let document = spec_utils::validate_spec(spec).unwrap();
+        assert_eq!(
+            (document.spec_version(), document.info.title.as_str()),
+            (Some("3.0.3"), "Orders")
+        );
+    }
+
+    #[test]
+    fn lifecycle_filters_parse_case_insensitively() {
+        assert_eq!(
+            Lifecycle::parse_list("Beta, ga,"),
+            Ok(vec![Lifecycle::Beta, Lifecycle::Ga])
+        );
+        assert!(Lifecycle::parse_list("stable").is_err());
+        assert_eq!(
+            serde_json::to_string(&Lifecycle::Deprecated).unwrap(),
+            "\"deprecated\""
+        );
+    }
 }
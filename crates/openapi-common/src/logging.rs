@@ -0,0 +1,70 @@
+//! Log output of the operator and the doc server.
+//!
+//! Logs are human-readable lines by default. `LOG_FORMAT=json` writes one JSON object per
+//! event instead, with the fields of the enclosing spans (e.g. `namespace` and `service`
+//! of a reconcile) as structured fields, so Loki or ELK can index them without regex
+//! parsing. `RUST_LOG` filters both formats.
+
+use tracing::warn;
+use tracing_subscriber::{EnvFilter, fmt::MakeWriter};
+
+/// Log format: `text` (default) or `json`
+pub const LOG_FORMAT_ENV: &str = "LOG_FORMAT";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!(
+                "Invalid {} '{}', expected text or json",
+                LOG_FORMAT_ENV, other
+            )),
+        }
+    }
+}
+
+/// Installs the global subscriber writing to `writer` in the format of `LOG_FORMAT`.
+/// An invalid format falls back to text and is logged.
+pub fn init<W>(writer: W)
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let format = std::env::var(LOG_FORMAT_ENV)
+        .map(|v| LogFormat::parse(&v))
+        .unwrap_or(Ok(LogFormat::Text));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(writer);
+    match format {
+        Ok(LogFormat::Json) => builder
+            .json()
+            .flatten_event(true)
+            .with_current_span(false)
+            .with_span_list(true)
+            .init(),
+        Ok(LogFormat::Text) | Err(_) => builder.init(),
+    }
+    if let Err(e) = format {
+        warn!("{}, logging as text", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_format_is_case_insensitive() {
+        assert_eq!(LogFormat::parse("JSON"), Ok(LogFormat::Json));
+        assert_eq!(LogFormat::parse(""), Ok(LogFormat::Text));
+        assert!(LogFormat::parse("logfmt").is_err());
+    }
+}
@@ -0,0 +1,166 @@
+//! Validation of Kubernetes object names.
+//!
+//! Names taken from configuration (`DISCOVERY_NAMESPACE`, `DISCOVERY_CONFIGMAP`,
+//! `WEBHOOK_SERVICE`, CLI flags) are checked up front with the rules the API server
+//! applies, so a typo fails at startup with a clear message instead of on the first
+//! request. Namespaces are RFC 1123 labels, ConfigMaps RFC 1123 subdomains and Services
+//! RFC 1035 labels.
+
+use std::fmt;
+
+/// Longest RFC 1123 / RFC 1035 label
+pub const MAX_LABEL_LENGTH: usize = 63;
+/// Longest RFC 1123 subdomain
+pub const MAX_SUBDOMAIN_LENGTH: usize = 253;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NameError {
+    Empty,
+    TooLong {
+        length: usize,
+        max: usize,
+    },
+    /// Only lowercase letters, digits and `-` (and `.` between labels of a subdomain)
+    InvalidCharacter(char),
+    /// Must start with a lowercase letter or digit (a letter for RFC 1035 labels)
+    InvalidStart(char),
+    /// Must end with a lowercase letter or digit
+    InvalidEnd(char),
+    /// A subdomain with a leading, trailing or doubled `.`
+    EmptyLabel,
+}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameError::Empty => write!(f, "name is empty"),
+            NameError::TooLong { length, max } => {
+                write!(
+                    f,
+                    "name is {} characters long, at most {} are allowed",
+                    length, max
+                )
+            }
+            NameError::InvalidCharacter(c) => write!(
+                f,
+                "'{}' is not allowed, only lowercase letters, digits and '-' are",
+                c
+            ),
+            NameError::InvalidStart(c) => write!(f, "name must not start with '{}'", c),
+            NameError::InvalidEnd(c) => write!(f, "name must not end with '{}'", c),
+            NameError::EmptyLabel => {
+                write!(f, "name must not start or end with '.' or contain '..'")
+            }
+        }
+    }
+}
+
+impl std::error::Error for NameError {}
+
+/// RFC 1123 label, e.g. a namespace: at most 63 lowercase letters, digits and `-`,
+/// starting and ending with a letter or digit
+pub fn validate_dns_label(name: &str) -> Result<(), NameError> {
+    validate_label(name, false)
+}
+
+/// RFC 1123 subdomain, e.g. a ConfigMap: dot-separated RFC 1123 labels, at most 253
+/// characters in total
+pub fn validate_dns_subdomain(name: &str) -> Result<(), NameError> {
+    if name.len() > MAX_SUBDOMAIN_LENGTH {
+        return Err(NameError::TooLong {
+            length: name.len(),
+            max: MAX_SUBDOMAIN_LENGTH,
+        });
+    }
+    if name.is_empty() {
+        return Err(NameError::Empty);
+    }
+    for label in name.split('.') {
+        match validate_label(label, false) {
+            // Labels may be as long as the subdomain allows
+            Ok(()) | Err(NameError::TooLong { .. }) => {}
+            Err(NameError::Empty) => return Err(NameError::EmptyLabel),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// RFC 1035 label, e.g. a Service: like an RFC 1123 label, but starting with a letter
+pub fn validate_service_name(name: &str) -> Result<(), NameError> {
+    validate_label(name, true)
+}
+
+fn validate_label(name: &str, letter_first: bool) -> Result<(), NameError> {
+    let (Some(first), Some(last)) = (name.chars().next(), name.chars().last()) else {
+        return Err(NameError::Empty);
+    };
+    if let Some(c) = name
+        .chars()
+        .find(|c| !(c.is_ascii_lowercase() || c.is_ascii_digit() || *c == '-'))
+    {
+        return Err(NameError::InvalidCharacter(c));
+    }
+    if first == '-' || (letter_first && first.is_ascii_digit()) {
+        return Err(NameError::InvalidStart(first));
+    }
+    if last == '-' {
+        return Err(NameError::InvalidEnd(last));
+    }
+    if name.len() > MAX_LABEL_LENGTH {
+        return Err(NameError::TooLong {
+            length: name.len(),
+            max: MAX_LABEL_LENGTH,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_rfc_1123_names() {
+        assert!(validate_dns_label("team-payments").is_ok());
+        assert!(validate_dns_label("0day").is_ok());
+        assert_eq!(
+            validate_dns_label("UPPERCASE"),
+            Err(NameError::InvalidCharacter('U'))
+        );
+        assert_eq!(
+            validate_dns_label("-leading-dash"),
+            Err(NameError::InvalidStart('-'))
+        );
+        assert_eq!(
+            validate_dns_label("trailing-"),
+            Err(NameError::InvalidEnd('-'))
+        );
+        assert_eq!(validate_dns_label(""), Err(NameError::Empty));
+        assert_eq!(
+            validate_dns_label(&"a".repeat(64)),
+            Err(NameError::TooLong {
+                length: 64,
+                max: 63
+            })
+        );
+        assert_eq!(
+            validate_dns_label("api.docs"),
+            Err(NameError::InvalidCharacter('.'))
+        );
+
+        assert!(validate_dns_subdomain("api.docs").is_ok());
+        assert!(validate_dns_subdomain(&"a".repeat(100)).is_ok());
+        assert_eq!(
+            validate_dns_subdomain("api..docs"),
+            Err(NameError::EmptyLabel)
+        );
+        assert!(validate_dns_subdomain("api.-docs").is_err());
+
+        assert!(validate_service_name("orders").is_ok());
+        assert_eq!(
+            validate_service_name("1orders"),
+            Err(NameError::InvalidStart('1'))
+        );
+    }
+}
@@ -0,0 +1,381 @@
+//! Lightweight model of the parts of an OpenAPI document we actually read.
+//!
+//! Specs come from arbitrary services, so every field is parsed leniently: a
+//! missing or malformed section falls back to its default instead of failing
+//! the whole document. Anything not modelled here is simply ignored.
+//!
+//! Code reading specs goes through this model. Transformations that hand the document
+//! back, converting Swagger 2.0 or rewriting `servers`, keep the parsed JSON so nothing
+//! outside the model is lost, and use the model only for the parts they read.
+
+use serde::de::{DeserializeOwned, Deserializer};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// HTTP methods that may carry an operation in a path item, in spec order.
+pub const HTTP_METHODS: [&str; 8] = [
+    "get", "put", "post", "delete", "options", "head", "patch", "trace",
+];
+
+/// Deserializes a field, falling back to its default if the value has an unexpected shape.
+fn lenient<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned + Default,
+{
+    let value = serde_json::Value::deserialize(deserializer)?;
+    Ok(serde_json::from_value(value).unwrap_or_default())
+}
+
+/// Like [`lenient`], but also accepts numbers for version strings (`swagger: 2.0` in YAML).
+fn version_string<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned + Default,
+{
+    let value = match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Number(n) => serde_json::Value::String(n.to_string()),
+        other => other,
+    };
+    Ok(serde_json::from_value(value).unwrap_or_default())
+}
+
+/// Top-level OpenAPI (3.x) or Swagger (2.0) document.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct OpenApiDocument {
+    #[serde(default, deserialize_with = "version_string")]
+    pub openapi: Option<String>,
+    #[serde(default, deserialize_with = "version_string")]
+    pub swagger: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub info: Info,
+    #[serde(default, deserialize_with = "lenient")]
+    pub servers: Vec<Server>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub paths: BTreeMap<String, PathItem>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub tags: Vec<Tag>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub components: Components,
+    /// Swagger 2.0 equivalent of `components.securitySchemes`
+    #[serde(
+        default,
+        rename = "securityDefinitions",
+        deserialize_with = "lenient",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub security_definitions: BTreeMap<String, SecurityScheme>,
+    #[serde(default, rename = "x-internal", deserialize_with = "lenient")]
+    pub x_internal: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Info {
+    #[serde(default, deserialize_with = "lenient")]
+    pub title: String,
+    #[serde(default, deserialize_with = "version_string")]
+    pub version: String,
+    #[serde(default, deserialize_with = "lenient")]
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Server {
+    #[serde(default, deserialize_with = "lenient")]
+    pub url: String,
+    #[serde(
+        default,
+        deserialize_with = "lenient",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Tag {
+    #[serde(default, deserialize_with = "lenient")]
+    pub name: String,
+    #[serde(default, deserialize_with = "lenient")]
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Components {
+    #[serde(default, rename = "securitySchemes", deserialize_with = "lenient")]
+    pub security_schemes: BTreeMap<String, SecurityScheme>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SecurityScheme {
+    #[serde(default, rename = "type", deserialize_with = "lenient")]
+    pub scheme_type: String,
+    #[serde(default, deserialize_with = "lenient")]
+    pub scheme: Option<String>,
+    #[serde(default, rename = "in", deserialize_with = "lenient")]
+    pub location: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub name: Option<String>,
+}
+
+/// A single entry under `paths`, keeping only the operations and shared parameters.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PathItem {
+    #[serde(default, deserialize_with = "lenient")]
+    pub get: Option<Operation>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub put: Option<Operation>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub post: Option<Operation>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub delete: Option<Operation>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub options: Option<Operation>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub head: Option<Operation>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub patch: Option<Operation>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub trace: Option<Operation>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub parameters: Vec<serde_json::Value>,
+    #[serde(default, rename = "x-internal", deserialize_with = "lenient")]
+    pub x_internal: bool,
+}
+
+/// Summary view of an operation; request/response schemas are intentionally not modelled.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Operation {
+    #[serde(default, rename = "operationId", deserialize_with = "lenient")]
+    pub operation_id: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub summary: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub description: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub tags: Vec<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    pub deprecated: bool,
+    #[serde(default, deserialize_with = "lenient")]
+    pub parameters: Vec<serde_json::Value>,
+    #[serde(default, rename = "requestBody", deserialize_with = "lenient")]
+    pub request_body: Option<serde_json::Value>,
+    /// Responses keyed by status code; bodies are kept opaque
+    #[serde(default, deserialize_with = "lenient")]
+    pub responses: BTreeMap<String, serde_json::Value>,
+    #[serde(default, rename = "x-internal", deserialize_with = "lenient")]
+    pub x_internal: bool,
+}
+
+/// The fields of a parameter object that matter for compatibility checks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParameterSummary {
+    pub name: String,
+    pub location: String,
+    pub required: bool,
+    pub schema_type: Option<String>,
+}
+
+impl ParameterSummary {
+    /// Extracts a summary from a raw parameter object; `$ref` parameters are skipped
+    pub fn from_value(value: &serde_json::Value) -> Option<Self> {
+        let name = value.get("name")?.as_str()?.to_string();
+        let location = value.get("in")?.as_str()?.to_string();
+        let required = value
+            .get("required")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(location == "path");
+        let schema_type = value
+            .get("schema")
+            .and_then(|s| s.get("type"))
+            .or_else(|| value.get("type"))
+            .and_then(|t| t.as_str())
+            .map(str::to_string);
+        Some(Self {
+            name,
+            location,
+            required,
+            schema_type,
+        })
+    }
+}
+
+impl Operation {
+    /// Parameters of this operation merged with the path-level ones (operation wins)
+    pub fn effective_parameters(&self, path_item: &PathItem) -> Vec<ParameterSummary> {
+        let mut params: Vec<ParameterSummary> = self
+            .parameters
+            .iter()
+            .filter_map(ParameterSummary::from_value)
+            .collect();
+        for shared in path_item.parameters.iter().filter_map(ParameterSummary::from_value) {
+            if !params
+                .iter()
+                .any(|p| p.name == shared.name && p.location == shared.location)
+            {
+                params.push(shared);
+            }
+        }
+        params
+    }
+
+    /// Whether the request body is marked as required
+    pub fn request_body_required(&self) -> bool {
+        self.request_body
+            .as_ref()
+            .and_then(|body| body.get("required"))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+    }
+}
+
+impl PathItem {
+    /// Returns the operation defined for an HTTP method, if any
+    pub fn operation(&self, method: &str) -> Option<&Operation> {
+        match method {
+            "get" => self.get.as_ref(),
+            "put" => self.put.as_ref(),
+            "post" => self.post.as_ref(),
+            "delete" => self.delete.as_ref(),
+            "options" => self.options.as_ref(),
+            "head" => self.head.as_ref(),
+            "patch" => self.patch.as_ref(),
+            "trace" => self.trace.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Iterates over `(method, operation)` pairs defined on this path
+    pub fn operations(&self) -> impl Iterator<Item = (&'static str, &Operation)> {
+        HTTP_METHODS
+            .iter()
+            .filter_map(|method| self.operation(method).map(|op| (*method, op)))
+    }
+}
+
+impl OpenApiDocument {
+    /// Parses a JSON or YAML document. Only fails if the content is not a mapping at all.
+    pub fn parse(content: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let value = crate::spec_utils::parse_spec_to_json(content)?;
+        Self::from_value(value)
+    }
+
+    /// Builds the model from an already parsed document
+    pub fn from_value(
+        value: serde_json::Value,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        if !value.is_object() {
+            return Err("OpenAPI document must be a mapping".into());
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Declared spec version (`openapi` for 3.x, `swagger` for 2.0)
+    pub fn spec_version(&self) -> Option<&str> {
+        self.openapi.as_deref().or(self.swagger.as_deref())
+    }
+
+    /// Security schemes regardless of whether the document is 2.0 or 3.x
+    pub fn security_schemes(&self) -> &BTreeMap<String, SecurityScheme> {
+        if self.components.security_schemes.is_empty() {
+            &self.security_definitions
+        } else {
+            &self.components.security_schemes
+        }
+    }
+
+    /// Iterates over `(path, method, operation)` for every operation in the document
+    pub fn operations(&self) -> impl Iterator<Item = (&str, &'static str, &Operation)> {
+        self.paths.iter().flat_map(|(path, item)| {
+            item.operations()
+                .map(move |(method, op)| (path.as_str(), method, op))
+        })
+    }
+
+    /// Iterates over operations that are not marked `x-internal` on the document, path, or operation
+    pub fn public_operations(&self) -> impl Iterator<Item = (&str, &'static str, &Operation)> {
+        let document_internal = self.x_internal;
+        self.paths
+            .iter()
+            .filter(move |(_, item)| !document_internal && !item.x_internal)
+            .flat_map(|(path, item)| {
+                item.operations()
+                    .filter(|(_, op)| !op.x_internal)
+                    .map(move |(method, op)| (path.as_str(), method, op))
+            })
+    }
+
+    /// Distinct tag names, from the top-level `tags` list and from operations
+    pub fn tag_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.tags.iter().map(|t| t.name.clone()).collect();
+        for (_, _, op) in self.operations() {
+            names.extend(op.tags.iter().cloned());
+        }
+        names.retain(|n| !n.is_empty());
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_yaml_summary() {
+        let doc = OpenApiDocument::parse(
+            r#"
+openapi: 3.0.3
+info:
+  title: Users
+  version: "1.2"
+servers:
+  - url: http://localhost:8080
+tags:
+  - name: users
+paths:
+  /users:
+    get:
+      operationId: listUsers
+      tags: [users, admin]
+    post:
+      x-internal: true
+  /internal:
+    x-internal: true
+    get: {}
+components:
+  securitySchemes:
+    bearer:
+      type: http
+      scheme: bearer
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(doc.spec_version(), Some("3.0.3"));
+        assert_eq!(doc.info.title, "Users");
+        assert_eq!(doc.servers[0].url, "http://localhost:8080");
+        assert_eq!(doc.operations().count(), 3);
+        assert_eq!(doc.public_operations().count(), 1);
+        assert_eq!(doc.tag_names(), vec!["admin", "users"]);
+        assert_eq!(doc.security_schemes()["bearer"].scheme.as_deref(), Some("bearer"));
+    }
+
+    #[test]
+    fn tolerates_malformed_sections() {
+        let doc = OpenApiDocument::parse(
+            r#"{"swagger": 2.0, "info": "oops", "servers": {}, "paths": {"/a": {"get": "nope"}}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(doc.spec_version(), Some("2.0"));
+        assert!(doc.info.title.is_empty());
+        assert!(doc.servers.is_empty());
+        assert_eq!(doc.operations().count(), 0);
+    }
+
+    #[test]
+    fn rejects_non_mapping_documents() {
+        assert!(OpenApiDocument::parse("[1, 2, 3]").is_err());
+    }
+}
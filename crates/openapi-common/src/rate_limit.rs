@@ -0,0 +1,103 @@
+//! Token bucket limiting the rate of outbound requests.
+//!
+//! Every request takes a token; tokens are refilled at `rate` per second up to `burst`.
+//! Requests finding the bucket empty reserve the next token and wait for it, so waiting
+//! requests go out in the order they arrived, spaced evenly at the configured rate.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Requests per second and the number of requests allowed at once
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub rate: f64,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    /// Limit of `rate` requests per second; `burst` defaults to one second's worth
+    pub fn new(rate: f64, burst: Option<u32>) -> Option<Self> {
+        (rate.is_finite() && rate > 0.0).then(|| Self {
+            rate,
+            burst: burst.unwrap_or(rate.ceil() as u32).max(1),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct TokenBucket {
+    limit: RateLimit,
+    /// Available tokens, negative while requests wait for reserved ones, and when they
+    /// were last refilled
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    /// A full bucket
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            state: Mutex::new((limit.burst as f64, Instant::now())),
+        }
+    }
+
+    pub fn limit(&self) -> RateLimit {
+        self.limit
+    }
+
+    /// Waits until a request may be sent
+    pub async fn acquire(&self) {
+        let wait = self.reserve(Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Takes a token at `now`, returning how long to wait until it is available
+    fn reserve(&self, now: Instant) -> Duration {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (tokens, refilled) = &mut *state;
+        let elapsed = now.saturating_duration_since(*refilled).as_secs_f64();
+        *tokens = (*tokens + elapsed * self.limit.rate).min(self.limit.burst as f64);
+        *refilled = now.max(*refilled);
+        *tokens -= 1.0;
+        if *tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-*tokens / self.limit.rate)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_allows_bursts_then_spaces_requests() {
+        let bucket = TokenBucket::new(RateLimit::new(2.0, Some(3)).unwrap());
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            assert_eq!(bucket.reserve(start), Duration::ZERO);
+        }
+        assert_eq!(bucket.reserve(start), Duration::from_millis(500));
+        assert_eq!(bucket.reserve(start), Duration::from_secs(1));
+        // Refilled tokens pay off the reservations first
+        assert_eq!(
+            bucket.reserve(start + Duration::from_secs(2)),
+            Duration::ZERO
+        );
+        assert_eq!(
+            bucket.reserve(start + Duration::from_secs(10)),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn burst_defaults_to_one_second_of_requests() {
+        assert_eq!(RateLimit::new(2.5, None).unwrap().burst, 3);
+        assert_eq!(RateLimit::new(0.2, None).unwrap().burst, 1);
+        assert!(RateLimit::new(0.0, Some(5)).is_none());
+    }
+}
@@ -0,0 +1,406 @@
+//! Minimal S3 object client (put/get/delete) signed with [`crate::sigv4`].
+//!
+//! Uses path-style URLs so S3-compatible stores such as MinIO work with a custom endpoint.
+//! [`SpecBucket`] is the bucket specs are stored in with `SPEC_STORAGE=s3`, shared by the
+//! operator writing them and the documentation server reading them.
+
+use chrono::{DateTime, Utc};
+use reqwest::{Method, StatusCode, Url};
+use std::fmt;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use crate::signing::{SIGNATURE_SUFFIX, SpecSignature};
+use crate::sigv4::{AWS_REGION_ENV, AwsCredentials, AwsSigner, web_identity_credentials};
+
+pub const SPEC_STORAGE_S3_BUCKET_ENV: &str = "SPEC_STORAGE_S3_BUCKET";
+pub const SPEC_STORAGE_S3_PREFIX_ENV: &str = "SPEC_STORAGE_S3_PREFIX";
+pub const SPEC_STORAGE_S3_REGION_ENV: &str = "SPEC_STORAGE_S3_REGION";
+pub const SPEC_STORAGE_S3_ENDPOINT_ENV: &str = "SPEC_STORAGE_S3_ENDPOINT";
+
+/// Temporary credentials are renewed this long before they expire
+const CREDENTIALS_RENEWAL_MARGIN_SECS: i64 = 5 * 60;
+
+#[derive(Debug)]
+pub enum S3Error {
+    Http(reqwest::Error),
+    Status { code: u16, body: String },
+    InvalidUrl(String),
+    Credentials(String),
+}
+
+impl fmt::Display for S3Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            S3Error::Http(e) => write!(f, "HTTP error: {}", e),
+            S3Error::Status { code, body } => write!(f, "S3 returned status {}: {}", code, body),
+            S3Error::InvalidUrl(msg) => write!(f, "invalid S3 URL: {}", msg),
+            S3Error::Credentials(msg) => write!(f, "AWS credentials: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for S3Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            S3Error::Http(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for S3Error {
+    fn from(err: reqwest::Error) -> Self {
+        S3Error::Http(err)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct S3Client {
+    signer: AwsSigner,
+    endpoint: String,
+    bucket: String,
+    client: reqwest::Client,
+}
+
+/// Encodes an object key for the URL path, keeping `/` separators
+fn encode_key(key: &str) -> String {
+    key.split('/')
+        .map(|segment| urlencoding::encode(segment).into_owned())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+impl S3Client {
+    /// `endpoint` defaults to `https://s3.<region>.amazonaws.com`
+    pub fn new(
+        credentials: AwsCredentials,
+        region: &str,
+        bucket: &str,
+        endpoint: Option<&str>,
+    ) -> Result<Self, S3Error> {
+        Ok(Self {
+            signer: AwsSigner::new(credentials, region, "s3"),
+            endpoint: endpoint
+                .map(|e| e.trim_end_matches('/').to_string())
+                .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region)),
+            bucket: bucket.to_string(),
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(60))
+                .build()?,
+        })
+    }
+
+    pub fn bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    fn url(&self, key: &str) -> Result<Url, S3Error> {
+        let url = format!("{}/{}/{}", self.endpoint, self.bucket, encode_key(key));
+        Url::parse(&url).map_err(|e| S3Error::InvalidUrl(format!("{}: {}", url, e)))
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        key: &str,
+        content_type: Option<&str>,
+        body: Vec<u8>,
+    ) -> Result<reqwest::Response, S3Error> {
+        let request = self
+            .signer
+            .request(&self.client, method, self.url(key)?, content_type, body);
+        Ok(request.send().await?)
+    }
+
+    async fn check(response: reqwest::Response) -> Result<reqwest::Response, S3Error> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let body = response.text().await.unwrap_or_default();
+        Err(S3Error::Status {
+            code: status.as_u16(),
+            body: body.chars().take(512).collect(),
+        })
+    }
+
+    pub async fn put_object(
+        &self,
+        key: &str,
+        content_type: &str,
+        body: Vec<u8>,
+    ) -> Result<(), S3Error> {
+        let response = self
+            .send(Method::PUT, key, Some(content_type), body)
+            .await?;
+        Self::check(response).await?;
+        Ok(())
+    }
+
+    /// Returns `None` if the object does not exist
+    pub async fn get_object(&self, key: &str) -> Result<Option<Vec<u8>>, S3Error> {
+        let response = self.send(Method::GET, key, None, Vec::new()).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(Self::check(response).await?.bytes().await?.to_vec()))
+    }
+
+    pub async fn delete_object(&self, key: &str) -> Result<(), S3Error> {
+        let response = self.send(Method::DELETE, key, None, Vec::new()).await?;
+        Self::check(response).await?;
+        Ok(())
+    }
+}
+
+/// S3 client with static credentials from the environment or, without them, the IRSA role
+/// of the pod, renewed before the temporary credentials expire
+#[derive(Debug)]
+pub struct RenewingS3Client {
+    region: String,
+    bucket: String,
+    endpoint: Option<String>,
+    http: reqwest::Client,
+    current: Mutex<Option<(S3Client, Option<DateTime<Utc>>)>>,
+}
+
+impl RenewingS3Client {
+    pub fn new(region: &str, bucket: &str, endpoint: Option<&str>) -> Result<Self, S3Error> {
+        Ok(Self {
+            region: region.to_string(),
+            bucket: bucket.to_string(),
+            endpoint: endpoint.map(str::to_string),
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()?,
+            current: Mutex::new(None),
+        })
+    }
+
+    /// Client with fixed credentials that are never renewed
+    pub fn with_credentials(
+        credentials: AwsCredentials,
+        region: &str,
+        bucket: &str,
+        endpoint: Option<&str>,
+    ) -> Result<Self, S3Error> {
+        let client = S3Client::new(credentials, region, bucket, endpoint)?;
+        Ok(Self {
+            current: Mutex::new(Some((client, None))),
+            ..Self::new(region, bucket, endpoint)?
+        })
+    }
+
+    pub async fn client(&self) -> Result<S3Client, S3Error> {
+        let mut current = self.current.lock().await;
+        if let Some((client, expires_at)) = current.as_ref()
+            && expires_at.is_none_or(|at| {
+                at - Utc::now() > chrono::Duration::seconds(CREDENTIALS_RENEWAL_MARGIN_SECS)
+            })
+        {
+            return Ok(client.clone());
+        }
+
+        let (credentials, expires_at) = match AwsCredentials::from_env() {
+            Some(credentials) => (credentials, None),
+            None => match web_identity_credentials(&self.http, &self.region)
+                .await
+                .map_err(S3Error::Credentials)?
+            {
+                Some((credentials, expires_at)) => (credentials, Some(expires_at)),
+                None => {
+                    return Err(S3Error::Credentials(
+                        "set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY, or AWS_ROLE_ARN and \
+                         AWS_WEB_IDENTITY_TOKEN_FILE (IRSA)"
+                            .to_string(),
+                    ));
+                }
+            },
+        };
+        let client = S3Client::new(
+            credentials,
+            &self.region,
+            &self.bucket,
+            self.endpoint.as_deref(),
+        )?;
+        *current = Some((client.clone(), expires_at));
+        Ok(client)
+    }
+}
+
+/// Bucket and prefix specs are stored under with `SPEC_STORAGE=s3`. Entries reference
+/// their spec as `s3://<bucket>/<key>`.
+#[derive(Debug)]
+pub struct SpecBucket {
+    client: RenewingS3Client,
+    bucket: String,
+    prefix: String,
+}
+
+impl SpecBucket {
+    /// `None` when `SPEC_STORAGE_S3_BUCKET` is unset
+    pub fn from_env() -> Result<Option<Self>, S3Error> {
+        let Ok(bucket) = std::env::var(SPEC_STORAGE_S3_BUCKET_ENV) else {
+            return Ok(None);
+        };
+        let region = std::env::var(SPEC_STORAGE_S3_REGION_ENV)
+            .or_else(|_| std::env::var(AWS_REGION_ENV))
+            .unwrap_or_else(|_| "us-east-1".to_string());
+        let endpoint = std::env::var(SPEC_STORAGE_S3_ENDPOINT_ENV).ok();
+        let prefix = std::env::var(SPEC_STORAGE_S3_PREFIX_ENV).unwrap_or_default();
+        Ok(Some(Self::new(
+            RenewingS3Client::new(&region, &bucket, endpoint.as_deref())?,
+            &bucket,
+            &prefix,
+        )))
+    }
+
+    pub fn new(client: RenewingS3Client, bucket: &str, prefix: &str) -> Self {
+        let prefix = match prefix.trim_matches('/') {
+            "" => String::new(),
+            trimmed => format!("{}/", trimmed),
+        };
+        Self {
+            client,
+            bucket: bucket.to_string(),
+            prefix,
+        }
+    }
+
+    pub fn bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    /// URL of the current spec of a service, by the format of `spec`
+    pub fn url(&self, namespace: &str, service_name: &str, spec: &str) -> String {
+        let extension = if spec.trim_start().starts_with('{') {
+            "json"
+        } else {
+            "yaml"
+        };
+        format!(
+            "s3://{}/{}{}/{}.{}",
+            self.bucket, self.prefix, namespace, service_name, extension
+        )
+    }
+
+    /// Object key of a URL in this bucket
+    pub fn key<'a>(&self, url: &'a str) -> Option<&'a str> {
+        url.strip_prefix("s3://")?
+            .strip_prefix(self.bucket.as_str())?
+            .strip_prefix('/')
+            .filter(|key| !key.is_empty())
+    }
+
+    fn key_of(&self, url: &str) -> Result<String, S3Error> {
+        self.key(url)
+            .map(str::to_string)
+            .ok_or_else(|| S3Error::InvalidUrl(format!("{} is not in bucket {}", url, self.bucket)))
+    }
+
+    /// Key prefix of the earlier revisions of a service's spec
+    pub fn history_prefix(&self, namespace: &str, service_name: &str) -> String {
+        format!("{}history/{}/{}/", self.prefix, namespace, service_name)
+    }
+
+    /// Object of the bucket by key, `None` if it does not exist
+    pub async fn get_object(&self, key: &str) -> Result<Option<Vec<u8>>, S3Error> {
+        self.client.client().await?.get_object(key).await
+    }
+
+    pub async fn put_object(
+        &self,
+        key: &str,
+        content_type: &str,
+        body: Vec<u8>,
+    ) -> Result<(), S3Error> {
+        self.client
+            .client()
+            .await?
+            .put_object(key, content_type, body)
+            .await
+    }
+
+    pub async fn delete_object(&self, key: &str) -> Result<(), S3Error> {
+        self.client.client().await?.delete_object(key).await
+    }
+
+    /// Stores the spec with its signature, or removes a signature left from before
+    pub async fn put(
+        &self,
+        url: &str,
+        spec: &str,
+        signature: Option<&SpecSignature>,
+    ) -> Result<(), S3Error> {
+        let key = self.key_of(url)?;
+        let client = self.client.client().await?;
+        let content_type = if key.ends_with(".json") {
+            "application/json"
+        } else {
+            "application/yaml"
+        };
+        client
+            .put_object(&key, content_type, spec.as_bytes().to_vec())
+            .await?;
+        let signature_key = format!("{}{}", key, SIGNATURE_SUFFIX);
+        match signature {
+            Some(signature) => {
+                client
+                    .put_object(
+                        &signature_key,
+                        "application/json",
+                        signature.to_json().into_bytes(),
+                    )
+                    .await
+            }
+            None => client.delete_object(&signature_key).await,
+        }
+    }
+
+    /// Spec and its signature, `None` if the spec does not exist
+    pub async fn get(&self, url: &str) -> Result<Option<(String, Option<String>)>, S3Error> {
+        let key = self.key_of(url)?;
+        let client = self.client.client().await?;
+        let Some(spec) = client.get_object(&key).await? else {
+            return Ok(None);
+        };
+        let signature = client
+            .get_object(&format!("{}{}", key, SIGNATURE_SUFFIX))
+            .await?
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned());
+        Ok(Some((
+            String::from_utf8_lossy(&spec).into_owned(),
+            signature,
+        )))
+    }
+
+    pub async fn delete(&self, url: &str) -> Result<(), S3Error> {
+        let key = self.key_of(url)?;
+        let client = self.client.client().await?;
+        client.delete_object(&key).await?;
+        client
+            .delete_object(&format!("{}{}", key, SIGNATURE_SUFFIX))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spec_urls_map_to_keys_under_the_prefix() {
+        let client = RenewingS3Client::new("eu-west-1", "specs", None).unwrap();
+        let bucket = SpecBucket::new(client, "specs", "/catalog/");
+        let url = bucket.url("shop", "orders", "{\"openapi\": \"3.1.0\"}");
+        assert_eq!(url, "s3://specs/catalog/shop/orders.json");
+        assert_eq!(bucket.key(&url), Some("catalog/shop/orders.json"));
+        assert_eq!(
+            bucket.url("shop", "orders", "openapi: 3.1.0"),
+            "s3://specs/catalog/shop/orders.yaml"
+        );
+        assert_eq!(bucket.key("s3://other/catalog/shop/orders.json"), None);
+        assert_eq!(bucket.key("https://specs/catalog/shop/orders.json"), None);
+    }
+}
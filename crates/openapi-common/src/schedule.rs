@@ -0,0 +1,207 @@
+//! Refresh windows: cron-like expressions constraining when specs may be fetched.
+//!
+//! A window has the five fields of a crontab line, `minute hour day-of-month month
+//! day-of-week`, and is open during every minute it matches, in UTC: `* 0-6,20-23 * * *`
+//! only allows fetches at night, `* * * * 6,0` on weekends. Fields accept `*`, numbers,
+//! ranges (`1-5`), lists (`1,3`) and steps (`*/15`, `9-17/2`); days of the week run from
+//! 0 (Sunday) to 6, and 7 is Sunday as well. As in cron, when both day fields are
+//! restricted, a day matching either of them is open.
+
+use chrono::{DateTime, Datelike, Duration, Timelike, Utc};
+use std::fmt;
+
+/// Minutes are checked for at most this long when looking for the next opening
+const MAX_LOOKAHEAD_DAYS: i64 = 366;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefreshWindow {
+    expression: String,
+    /// One bit per allowed value of each field
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    /// Whether the day fields are `*`, which changes how they combine
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+impl RefreshWindow {
+    /// Parses a five-field cron expression
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day_of_month, month, day_of_week] = fields[..] else {
+            return Err(format!(
+                "expected 5 fields (minute hour day-of-month month day-of-week), got \"{}\"",
+                expression
+            ));
+        };
+
+        // Sunday may be written as 7; fold it onto 0
+        let mut days_of_week = parse_field(day_of_week, "day-of-week", 0, 7)?;
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            expression: fields.join(" "),
+            minutes: parse_field(minute, "minute", 0, 59)?,
+            hours: parse_field(hour, "hour", 0, 23)?,
+            days_of_month: parse_field(day_of_month, "day-of-month", 1, 31)?,
+            months: parse_field(month, "month", 1, 12)?,
+            days_of_week,
+            any_day_of_month: day_of_month == "*",
+            any_day_of_week: day_of_week == "*",
+        })
+    }
+
+    /// The global window from `REFRESH_WINDOW`, `None` when unset
+    pub fn from_env() -> Result<Option<Self>, String> {
+        match std::env::var(crate::REFRESH_WINDOW_ENV) {
+            Ok(value) if !value.trim().is_empty() => Self::parse(&value)
+                .map(Some)
+                .map_err(|e| format!("Invalid {}: {}", crate::REFRESH_WINDOW_ENV, e)),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    /// Whether fetches may run at `at`
+    pub fn is_open(&self, at: DateTime<Utc>) -> bool {
+        self.day_matches(at) && bit(self.hours, at.hour()) && bit(self.minutes, at.minute())
+    }
+
+    /// `at` when the window is open, otherwise the start of the next minute it opens;
+    /// `None` when it does not open within a year (e.g. `* * 31 2 *`)
+    pub fn next_open(&self, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if self.is_open(at) {
+            return Some(at);
+        }
+        let end = at + Duration::days(MAX_LOOKAHEAD_DAYS);
+        let mut next = at.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        while next < end {
+            if !self.day_matches(next) {
+                next = (next.date_naive() + Duration::days(1))
+                    .and_hms_opt(0, 0, 0)?
+                    .and_utc();
+            } else if !bit(self.hours, next.hour()) {
+                next = next.with_minute(0)? + Duration::hours(1);
+            } else if !bit(self.minutes, next.minute()) {
+                next += Duration::minutes(1);
+            } else {
+                return Some(next);
+            }
+        }
+        None
+    }
+
+    fn day_matches(&self, at: DateTime<Utc>) -> bool {
+        if !bit(self.months, at.month()) {
+            return false;
+        }
+        let day_of_month = bit(self.days_of_month, at.day());
+        let day_of_week = bit(self.days_of_week, at.weekday().num_days_from_sunday());
+        match (self.any_day_of_month, self.any_day_of_week) {
+            (false, false) => day_of_month || day_of_week,
+            _ => day_of_month && day_of_week,
+        }
+    }
+}
+
+impl fmt::Display for RefreshWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+fn bit(mask: u64, value: u32) -> bool {
+    mask & (1 << value) != 0
+}
+
+/// Bit mask of the values a field allows
+fn parse_field(field: &str, name: &str, min: u32, max: u32) -> Result<u64, String> {
+    let invalid = || format!("invalid {} field \"{}\"", name, field);
+    let value = |part: &str| -> Result<u32, String> {
+        part.parse::<u32>()
+            .ok()
+            .filter(|v| (min..=max).contains(v))
+            .ok_or_else(|| {
+                format!(
+                    "{} must be between {} and {}, got \"{}\"",
+                    name, min, max, part
+                )
+            })
+    };
+
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(invalid()),
+            },
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                // `5/15` runs from 5 to the end of the field, like in cron
+                None if step > 1 => (value(range)?, max),
+                None => {
+                    let v = value(range)?;
+                    (v, v)
+                }
+            },
+        };
+        if start > end {
+            return Err(invalid());
+        }
+        for v in (start..=end).step_by(step as usize) {
+            mask |= 1 << v;
+        }
+    }
+    Ok(mask)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn opens_and_finds_the_next_opening() {
+        // Outside business hours on weekdays, all weekend
+        let window = RefreshWindow::parse("* 0-7,18-23 * * 1-5").unwrap();
+        let monday_noon = Utc.with_ymd_and_hms(2024, 6, 3, 12, 30, 15).unwrap();
+        assert!(!window.is_open(monday_noon));
+        assert_eq!(
+            window.next_open(monday_noon),
+            Some(Utc.with_ymd_and_hms(2024, 6, 3, 18, 0, 0).unwrap())
+        );
+        assert!(window.is_open(Utc.with_ymd_and_hms(2024, 6, 3, 6, 59, 0).unwrap()));
+
+        let weekends = RefreshWindow::parse("*/30 * * * 6,7").unwrap();
+        let friday = Utc.with_ymd_and_hms(2024, 6, 7, 23, 45, 0).unwrap();
+        assert_eq!(
+            weekends.next_open(friday),
+            Some(Utc.with_ymd_and_hms(2024, 6, 8, 0, 0, 0).unwrap())
+        );
+        assert!(weekends.is_open(Utc.with_ymd_and_hms(2024, 6, 9, 10, 30, 0).unwrap()));
+        assert!(!weekends.is_open(Utc.with_ymd_and_hms(2024, 6, 9, 10, 31, 0).unwrap()));
+
+        assert_eq!(
+            RefreshWindow::parse("* * 31 2 *")
+                .unwrap()
+                .next_open(friday),
+            None
+        );
+        assert!(RefreshWindow::parse("* * * *").is_err());
+        assert!(RefreshWindow::parse("60 * * * *").is_err());
+        assert!(RefreshWindow::parse("* 5-1 * * *").is_err());
+        assert!(RefreshWindow::parse("*/0 * * * *").is_err());
+    }
+}
@@ -0,0 +1,454 @@
+//! AWS Signature Version 4 request signing.
+//!
+//! A minimal signer for the few AWS REST APIs the project talks to (API Gateway, S3),
+//! so we don't need the full AWS SDK. Credentials come from the standard
+//! `AWS_ACCESS_KEY_ID` / `AWS_SECRET_ACCESS_KEY` / `AWS_SESSION_TOKEN` variables, or on EKS
+//! from the service account token projected for IAM roles for service accounts (IRSA).
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::Url;
+use sha2::Sha256;
+
+use crate::digest::sha256_hex;
+
+pub const AWS_ACCESS_KEY_ID_ENV: &str = "AWS_ACCESS_KEY_ID";
+pub const AWS_SECRET_ACCESS_KEY_ENV: &str = "AWS_SECRET_ACCESS_KEY";
+pub const AWS_SESSION_TOKEN_ENV: &str = "AWS_SESSION_TOKEN";
+pub const AWS_REGION_ENV: &str = "AWS_REGION";
+/// Set by the EKS pod identity webhook for service accounts annotated with a role
+pub const AWS_ROLE_ARN_ENV: &str = "AWS_ROLE_ARN";
+pub const AWS_WEB_IDENTITY_TOKEN_FILE_ENV: &str = "AWS_WEB_IDENTITY_TOKEN_FILE";
+
+#[derive(Clone)]
+pub struct AwsCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl std::fmt::Debug for AwsCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AwsCredentials")
+            .field("access_key_id", &self.access_key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AwsCredentials {
+    /// Reads static credentials from the environment, `None` if they are not set
+    pub fn from_env() -> Option<Self> {
+        Some(Self {
+            access_key_id: std::env::var(AWS_ACCESS_KEY_ID_ENV).ok()?,
+            secret_access_key: std::env::var(AWS_SECRET_ACCESS_KEY_ENV).ok()?,
+            session_token: std::env::var(AWS_SESSION_TOKEN_ENV).ok(),
+        })
+    }
+}
+
+/// Temporary credentials of the role in `AWS_ROLE_ARN`, exchanged for the token in
+/// `AWS_WEB_IDENTITY_TOKEN_FILE` with STS, and when they expire. `None` if either is unset.
+pub async fn web_identity_credentials(
+    client: &reqwest::Client,
+    region: &str,
+) -> Result<Option<(AwsCredentials, DateTime<Utc>)>, String> {
+    let (Ok(role_arn), Ok(token_file)) = (
+        std::env::var(AWS_ROLE_ARN_ENV),
+        std::env::var(AWS_WEB_IDENTITY_TOKEN_FILE_ENV),
+    ) else {
+        return Ok(None);
+    };
+    let token = std::fs::read_to_string(&token_file)
+        .map_err(|e| format!("failed to read {}: {}", token_file, e))?;
+
+    let url = Url::parse_with_params(
+        &format!("https://sts.{}.amazonaws.com/", region),
+        &[
+            ("Action", "AssumeRoleWithWebIdentity"),
+            ("Version", "2011-06-15"),
+            ("RoleArn", role_arn.as_str()),
+            ("RoleSessionName", "openapi-k8s-discovery"),
+            ("WebIdentityToken", token.trim()),
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| format!("AssumeRoleWithWebIdentity failed: {}", e))?;
+    let status = response.status();
+    let body = response.text().await.map_err(|e| e.to_string())?;
+    if !status.is_success() {
+        return Err(format!(
+            "AssumeRoleWithWebIdentity for {} returned status {}: {}",
+            role_arn,
+            status.as_u16(),
+            body.chars().take(512).collect::<String>()
+        ));
+    }
+    parse_assume_role_response(&body)
+        .map(Some)
+        .ok_or_else(|| "AssumeRoleWithWebIdentity returned no credentials".to_string())
+}
+
+fn parse_assume_role_response(xml: &str) -> Option<(AwsCredentials, DateTime<Utc>)> {
+    let element = |name: &str| -> Option<String> {
+        let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+        let end = start + xml[start..].find(&format!("</{}>", name))?;
+        Some(xml[start..end].trim().to_string())
+    };
+    let expiration = DateTime::parse_from_rfc3339(&element("Expiration")?).ok()?;
+    Some((
+        AwsCredentials {
+            access_key_id: element("AccessKeyId")?,
+            secret_access_key: element("SecretAccessKey")?,
+            session_token: element("SessionToken"),
+        },
+        expiration.with_timezone(&Utc),
+    ))
+}
+
+/// The parts of a request that go into the signature
+pub struct SigningRequest<'a> {
+    pub method: &'a str,
+    pub url: &'a Url,
+    /// Additional headers to sign, e.g. `content-type`. `host` and `x-amz-*` are added.
+    pub headers: &'a [(&'a str, &'a str)],
+    pub body: &'a [u8],
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encodes everything except RFC 3986 unreserved characters
+fn uri_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn canonical_uri(url: &Url, service: &str) -> String {
+    let path = if url.path().is_empty() {
+        "/"
+    } else {
+        url.path()
+    };
+    if service == "s3" {
+        // S3 signs the path exactly as sent
+        return path.to_string();
+    }
+    // Other services sign each (already encoded) path segment encoded once more
+    path.split('/')
+        .map(uri_encode)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn canonical_query(url: &Url) -> String {
+    let mut pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(k, v)| (uri_encode(&k), uri_encode(&v)))
+        .collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Signs requests for one AWS service in one region
+#[derive(Debug, Clone)]
+pub struct AwsSigner {
+    pub credentials: AwsCredentials,
+    pub region: String,
+    /// Signing name of the service, e.g. `apigateway` or `s3`
+    pub service: String,
+}
+
+impl AwsSigner {
+    pub fn new(credentials: AwsCredentials, region: &str, service: &str) -> Self {
+        Self {
+            credentials,
+            region: region.to_string(),
+            service: service.to_string(),
+        }
+    }
+
+    /// Computes the headers to add to the request: `x-amz-date`, `authorization`, and
+    /// `x-amz-security-token` / `x-amz-content-sha256` where required.
+    pub fn sign(&self, request: &SigningRequest<'_>, now: DateTime<Utc>) -> Vec<(String, String)> {
+        let (credentials, region, service) = (
+            &self.credentials,
+            self.region.as_str(),
+            self.service.as_str(),
+        );
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = sha256_hex(request.body);
+
+        let host = match request.url.port() {
+            Some(port) => format!("{}:{}", request.url.host_str().unwrap_or_default(), port),
+            None => request.url.host_str().unwrap_or_default().to_string(),
+        };
+
+        let mut added = vec![("x-amz-date".to_string(), amz_date.clone())];
+        if service == "s3" {
+            added.push(("x-amz-content-sha256".to_string(), payload_hash.clone()));
+        }
+        if let Some(token) = &credentials.session_token {
+            added.push(("x-amz-security-token".to_string(), token.clone()));
+        }
+
+        let mut signed: Vec<(String, String)> = request
+            .headers
+            .iter()
+            .map(|(k, v)| (k.to_lowercase(), v.trim().to_string()))
+            .chain(std::iter::once(("host".to_string(), host)))
+            .chain(added.iter().cloned())
+            .collect();
+        signed.sort();
+
+        let canonical_headers: String = signed
+            .iter()
+            .map(|(k, v)| format!("{}:{}\n", k, v))
+            .collect();
+        let signed_headers = signed
+            .iter()
+            .map(|(k, _)| k.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            request.method,
+            canonical_uri(request.url, service),
+            canonical_query(request.url),
+            canonical_headers,
+            signed_headers,
+            payload_hash
+        );
+
+        let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            sha256_hex(canonical_request.as_bytes())
+        );
+
+        let mut key = hmac_sha256(
+            format!("AWS4{}", credentials.secret_access_key).as_bytes(),
+            date.as_bytes(),
+        );
+        for part in [region, service, "aws4_request"] {
+            key = hmac_sha256(&key, part.as_bytes());
+        }
+        let signature = hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()));
+
+        added.push((
+            "authorization".to_string(),
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                credentials.access_key_id, scope, signed_headers, signature
+            ),
+        ));
+        added
+    }
+
+    /// Builds a signed reqwest request. `content_type` is sent and signed when given.
+    pub fn request(
+        &self,
+        client: &reqwest::Client,
+        method: reqwest::Method,
+        url: Url,
+        content_type: Option<&str>,
+        body: Vec<u8>,
+    ) -> reqwest::RequestBuilder {
+        let extra_headers: Vec<(&str, &str)> = content_type
+            .map(|ct| vec![("content-type", ct)])
+            .unwrap_or_default();
+        let headers = self.sign(
+            &SigningRequest {
+                method: method.as_str(),
+                url: &url,
+                headers: &extra_headers,
+                body: &body,
+            },
+            Utc::now(),
+        );
+
+        let mut request = client.request(method, url);
+        for (name, value) in extra_headers
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+        {
+            request = request.header(name, value);
+        }
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        request.body(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn matches_aws_documentation_example() {
+        // "Create a signed AWS API request" example from the AWS General Reference
+        let credentials = AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+        };
+        let url =
+            Url::parse("https://iam.amazonaws.com/?Action=ListUsers&Version=2010-05-08").unwrap();
+        let signer = AwsSigner::new(credentials, "us-east-1", "iam");
+        let headers = signer.sign(
+            &SigningRequest {
+                method: "GET",
+                url: &url,
+                headers: &[(
+                    "Content-Type",
+                    "application/x-www-form-urlencoded; charset=utf-8",
+                )],
+                body: b"",
+            },
+            Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap(),
+        );
+
+        let authorization = &headers
+            .iter()
+            .find(|(k, _)| k == "authorization")
+            .unwrap()
+            .1;
+        assert_eq!(
+            authorization,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date, \
+             Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        );
+    }
+
+    #[test]
+    fn matches_aws_signature_test_suite() {
+        // Cases of the AWS Signature Version 4 test suite, which all share these
+        // credentials, scope and time
+        let credentials = AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+        };
+        let signer = AwsSigner::new(credentials, "us-east-1", "service");
+        let cases = [
+            (
+                "GET",
+                "/",
+                "",
+                "5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31",
+            ),
+            (
+                "GET",
+                "/?Param1=value1",
+                "",
+                "a67d582fa61cc504c4bae71f336f98b97f1ea3c7a6bfe1b6e45aec72011b9aeb",
+            ),
+            (
+                "GET",
+                "/?Param2=value2&Param1=value1",
+                "",
+                "b97d918cfa904a5beff61c982a1b6f458b799221646efd99d3219ec94cdf2500",
+            ),
+            (
+                "POST",
+                "/",
+                "",
+                "5da7c1a2acd57cee7505fc6676e4e544621c30862966e37dddb68e92efbe5d6b",
+            ),
+            (
+                "POST",
+                "/",
+                "Param1=value1",
+                "ff11897932ad3f4e8b18135d722051e5ac45fc38421b1da7b9d196a0fe09473a",
+            ),
+        ];
+
+        for (method, path, body, signature) in cases {
+            // The only case with a body is a form post
+            let headers: &[(&str, &str)] = if body.is_empty() {
+                &[]
+            } else {
+                &[("Content-Type", "application/x-www-form-urlencoded")]
+            };
+            let url = Url::parse(&format!("https://example.amazonaws.com{}", path)).unwrap();
+            let signed = signer.sign(
+                &SigningRequest {
+                    method,
+                    url: &url,
+                    headers,
+                    body: body.as_bytes(),
+                },
+                Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap(),
+            );
+            let signed_headers = if headers.is_empty() {
+                "host;x-amz-date"
+            } else {
+                "content-type;host;x-amz-date"
+            };
+            let expected = format!(
+                "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+                 SignedHeaders={}, Signature={}",
+                signed_headers, signature
+            );
+
+            assert!(
+                signed.contains(&("x-amz-date".to_string(), "20150830T123600Z".to_string())),
+                "{} {}",
+                method,
+                path
+            );
+            assert!(
+                signed.contains(&("authorization".to_string(), expected)),
+                "{} {}",
+                method,
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn reads_credentials_from_sts_response() {
+        let xml = r#"<AssumeRoleWithWebIdentityResponse xmlns="https://sts.amazonaws.com/doc/2011-06-15/">
+  <AssumeRoleWithWebIdentityResult>
+    <Credentials>
+      <AccessKeyId>ASIAEXAMPLE</AccessKeyId>
+      <SecretAccessKey>secret</SecretAccessKey>
+      <SessionToken>token</SessionToken>
+      <Expiration>2026-01-01T12:00:00Z</Expiration>
+    </Credentials>
+  </AssumeRoleWithWebIdentityResult>
+</AssumeRoleWithWebIdentityResponse>"#;
+        let (credentials, expiration) = parse_assume_role_response(xml).unwrap();
+        assert_eq!(credentials.access_key_id, "ASIAEXAMPLE");
+        assert_eq!(credentials.session_token.as_deref(), Some("token"));
+        assert_eq!(
+            expiration,
+            Utc.with_ymd_and_hms(2026, 1, 1, 12, 0, 0).unwrap()
+        );
+        assert!(parse_assume_role_response("<Error/>").is_none());
+    }
+}
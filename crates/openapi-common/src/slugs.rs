@@ -0,0 +1,199 @@
+//! Canonical slugs of cached APIs.
+//!
+//! The doc server caches and serves specs under a slug built from the namespace and
+//! service name (`/specs/shop-orders`) instead of the display name, which is neither unique
+//! across namespaces nor URL-friendly. Slugs only contain `a-z`, `0-9` and `-`, so they double as
+//! cache file names. Different services that still normalize to the same slug (`a-b/c`
+//! and `a/b-c`) are told apart by a short hash suffix. Entries federated from another
+//! cluster are prefixed with the cluster name, like their ids.
+
+use crate::ApiInventoryEntry;
+use crate::digest::sha256_hex;
+use std::collections::HashMap;
+
+/// Lowercase letters and digits, other characters collapsed into single dashes
+fn normalize(value: &str) -> String {
+    let mut slug = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// The service a slug is assigned to; `cluster` is set for entries federated from another
+/// cluster, which keep the namespace and service name they have there
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SlugKey<'a> {
+    pub cluster: Option<&'a str>,
+    pub namespace: &'a str,
+    pub service_name: &'a str,
+}
+
+impl<'a> SlugKey<'a> {
+    /// Key of a catalog entry
+    pub fn of(entry: &'a ApiInventoryEntry) -> Self {
+        SlugKey {
+            cluster: entry.cluster.as_deref(),
+            namespace: &entry.namespace,
+            service_name: &entry.service_name,
+        }
+    }
+
+    /// Slug before collisions are resolved, e.g. `shop-orders` or `eu-shop-orders`
+    pub fn base_slug(&self) -> String {
+        match self.cluster {
+            Some(cluster) => normalize(&format!(
+                "{}-{}-{}",
+                cluster, self.namespace, self.service_name
+            )),
+            None => base_slug(self.namespace, self.service_name),
+        }
+    }
+
+    /// Input of the hash suffix; `namespace/service` for local services, as before the
+    /// cluster was part of the key
+    fn hash_input(&self) -> String {
+        match self.cluster {
+            Some(cluster) => format!("{}/{}/{}", cluster, self.namespace, self.service_name),
+            None => format!("{}/{}", self.namespace, self.service_name),
+        }
+    }
+}
+
+/// Slug of a service before collisions are resolved, e.g. `shop-orders`
+pub fn base_slug(namespace: &str, service_name: &str) -> String {
+    normalize(&format!("{}-{}", namespace, service_name))
+}
+
+/// Slugs for the given services, in the same order. When several services share a base
+/// slug, the first in cluster/namespace/service order (local services first) keeps it and
+/// the others get a hash suffix, so the result does not depend on the order of the
+/// discovery document.
+pub fn assign_slugs(services: &[SlugKey]) -> Vec<String> {
+    let mut by_slug: HashMap<String, Vec<SlugKey>> = HashMap::new();
+    for service in services {
+        by_slug
+            .entry(service.base_slug())
+            .or_default()
+            .push(*service);
+    }
+
+    services
+        .iter()
+        .map(|service| {
+            let slug = service.base_slug();
+            let first = by_slug[&slug].iter().min().copied();
+            if first == Some(*service) {
+                slug
+            } else {
+                let hash = sha256_hex(service.hash_input().as_bytes());
+                format!("{}-{}", slug, &hash[..8])
+            }
+        })
+        .collect()
+}
+
+/// Slugs of catalog entries, in the same order
+pub fn entry_slugs<'a>(entries: impl IntoIterator<Item = &'a ApiInventoryEntry>) -> Vec<String> {
+    let services: Vec<SlugKey> = entries.into_iter().map(SlugKey::of).collect();
+    assign_slugs(&services)
+}
+
+/// Whether a request path segment can be a slug, and therefore a cache file name
+pub fn is_slug(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+/// Cache file name used for an API before slugs, derived from its display name
+pub fn legacy_cache_name(api_name: &str) -> String {
+    api_name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugs_are_stable_and_colliding_services_get_a_hash_suffix() {
+        assert_eq!(base_slug("shop", "Orders_API"), "shop-orders-api");
+        assert_eq!(base_slug("--shop", "orders--"), "shop-orders");
+
+        // `a/b-c` sorts before `a-b/c`, so it keeps the plain slug in either order
+        let local = |namespace, service_name| SlugKey {
+            cluster: None,
+            namespace,
+            service_name,
+        };
+        let services = [
+            local("a-b", "c"),
+            local("a", "b-c"),
+            local("shop", "orders"),
+        ];
+        let slugs = assign_slugs(&services);
+        assert_eq!(slugs[1], "a-b-c");
+        assert_eq!(slugs[2], "shop-orders");
+        // The suffix is the start of the SHA-256 of `namespace/service`, so URLs and
+        // cache files stay the same across releases
+        assert_eq!(slugs[0], "a-b-c-4e84717d");
+        assert_eq!(&sha256_hex(b"a-b/c")[..8], "4e84717d");
+        let mut reversed = services;
+        reversed.reverse();
+        let mut again = assign_slugs(&reversed);
+        again.reverse();
+        assert_eq!(again, slugs);
+
+        assert!(slugs.iter().all(|slug| is_slug(slug)));
+        assert!(!is_slug(""));
+        assert!(!is_slug("Shop-orders"));
+        assert!(!is_slug("shop/orders"));
+    }
+
+    #[test]
+    fn federated_entries_do_not_take_the_slug_of_a_local_service() {
+        // The remote `shop/orders` keeps its namespace and service name, as federation does
+        let services = [
+            SlugKey {
+                cluster: Some("eu"),
+                namespace: "shop",
+                service_name: "orders",
+            },
+            SlugKey {
+                cluster: None,
+                namespace: "shop",
+                service_name: "orders",
+            },
+            SlugKey {
+                cluster: None,
+                namespace: "eu-shop",
+                service_name: "orders",
+            },
+        ];
+        let slugs = assign_slugs(&services);
+        assert_eq!(slugs[1], "shop-orders");
+        // A local `eu-shop/orders` keeps its slug, the federated one is suffixed
+        assert_eq!(slugs[2], "eu-shop-orders");
+        assert_eq!(
+            slugs[0],
+            format!("eu-shop-orders-{}", &sha256_hex(b"eu/shop/orders")[..8])
+        );
+    }
+
+    #[test]
+    fn legacy_cache_names_keep_only_file_name_characters() {
+        assert_eq!(legacy_cache_name("Orders API v2"), "Orders_API_v2");
+        assert_eq!(legacy_cache_name("shop/orders-api_1"), "shop_orders-api_1");
+    }
+}
@@ -0,0 +1,621 @@
+//! Conversion of Swagger 2.0 documents to OpenAPI 3.0.
+//!
+//! Legacy services still serve Swagger 2.0, while the documentation frontends render
+//! OpenAPI 3.x best. The operator and the doc server convert those specs before storing or
+//! serving them, so every API in the catalog is described the same way. The conversion
+//! covers what frontends show: servers, parameters, request bodies, responses, security
+//! schemes and schemas, with `$ref`s rewritten to `components`. OpenAPI 3.0 and 3.1
+//! documents are kept as they are.
+
+use serde_json::{Map, Value, json};
+
+/// Version of the OpenAPI specification a document follows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecVersion {
+    Swagger2,
+    OpenApi30,
+    OpenApi31,
+}
+
+impl SpecVersion {
+    /// Version declared by `swagger` or `openapi`; `None` for anything else
+    pub fn detect(document: &Value) -> Option<Self> {
+        let version = |key: &str| match document.get(key)? {
+            Value::String(version) => Some(version.clone()),
+            Value::Number(version) => Some(version.to_string()),
+            _ => None,
+        };
+        if let Some(version) = version("openapi") {
+            return if version.starts_with("3.0") {
+                Some(SpecVersion::OpenApi30)
+            } else if version.starts_with("3.1") {
+                Some(SpecVersion::OpenApi31)
+            } else {
+                None
+            };
+        }
+        version("swagger")
+            .filter(|version| version.starts_with('2'))
+            .map(|_| SpecVersion::Swagger2)
+    }
+}
+
+/// Media type assumed when neither the operation nor the document declares one
+const DEFAULT_MEDIA_TYPE: &str = "application/json";
+
+/// Keywords of a Swagger 2.0 non-body parameter or header that describe its value and move
+/// into its `schema`
+const SCHEMA_KEYWORDS: &[&str] = &[
+    "type",
+    "format",
+    "items",
+    "default",
+    "maximum",
+    "exclusiveMaximum",
+    "minimum",
+    "exclusiveMinimum",
+    "maxLength",
+    "minLength",
+    "pattern",
+    "maxItems",
+    "minItems",
+    "uniqueItems",
+    "enum",
+    "multipleOf",
+];
+
+/// Converts a Swagger 2.0 document to OpenAPI 3.0.3; other documents are returned unchanged
+pub fn to_openapi3(document: Value) -> Value {
+    if SpecVersion::detect(&document) != Some(SpecVersion::Swagger2) {
+        return document;
+    }
+    let Value::Object(mut swagger) = document else {
+        return document;
+    };
+
+    let consumes = media_types(swagger.remove("consumes"));
+    let produces = media_types(swagger.remove("produces"));
+    let servers = servers(
+        swagger.remove("host"),
+        swagger.remove("basePath"),
+        swagger.remove("schemes"),
+    );
+    swagger.remove("swagger");
+
+    // Shared body parameters become request bodies, so their references move too
+    let shared_parameters = swagger
+        .remove("parameters")
+        .and_then(into_object)
+        .unwrap_or_default();
+    let body_parameters: Vec<String> = shared_parameters
+        .iter()
+        .filter(|(_, parameter)| is_body_or_form(parameter))
+        .map(|(name, _)| name.clone())
+        .collect();
+
+    let mut components = Map::new();
+    if let Some(definitions) = swagger.remove("definitions") {
+        components.insert("schemas".to_string(), definitions);
+    }
+    let mut parameters = Map::new();
+    let mut request_bodies = Map::new();
+    for (name, parameter) in shared_parameters {
+        if is_body_or_form(&parameter) {
+            if let Some(body) = request_body(vec![parameter], &consumes) {
+                request_bodies.insert(name, body);
+            }
+        } else {
+            parameters.insert(name, convert_parameter(parameter));
+        }
+    }
+    if !parameters.is_empty() {
+        components.insert("parameters".to_string(), Value::Object(parameters));
+    }
+    if !request_bodies.is_empty() {
+        components.insert("requestBodies".to_string(), Value::Object(request_bodies));
+    }
+    if let Some(Value::Object(responses)) = swagger.remove("responses") {
+        let responses = responses
+            .into_iter()
+            .map(|(name, response)| (name, convert_response(response, &produces)))
+            .collect();
+        components.insert("responses".to_string(), Value::Object(responses));
+    }
+    if let Some(Value::Object(schemes)) = swagger.remove("securityDefinitions") {
+        let schemes = schemes
+            .into_iter()
+            .map(|(name, scheme)| (name, convert_security_scheme(scheme)))
+            .collect();
+        components.insert("securitySchemes".to_string(), Value::Object(schemes));
+    }
+
+    if let Some(Value::Object(paths)) = swagger.remove("paths") {
+        let paths = paths
+            .into_iter()
+            .map(|(path, item)| {
+                let item = convert_path_item(item, &consumes, &produces, &body_parameters);
+                (path, item)
+            })
+            .collect();
+        swagger.insert("paths".to_string(), Value::Object(paths));
+    }
+
+    let mut openapi = Map::new();
+    openapi.insert("openapi".to_string(), json!("3.0.3"));
+    openapi.extend(swagger);
+    if !servers.is_empty() {
+        openapi.insert("servers".to_string(), Value::Array(servers));
+    }
+    if !components.is_empty() {
+        openapi.insert("components".to_string(), Value::Object(components));
+    }
+
+    let mut openapi = Value::Object(openapi);
+    rewrite_refs(&mut openapi, &body_parameters);
+    convert_schemas(&mut openapi);
+    openapi
+}
+
+fn into_object(value: Value) -> Option<Map<String, Value>> {
+    match value {
+        Value::Object(map) => Some(map),
+        _ => None,
+    }
+}
+
+fn media_types(value: Option<Value>) -> Vec<String> {
+    match value {
+        Some(Value::Array(types)) => types
+            .into_iter()
+            .filter_map(|t| t.as_str().map(str::to_string))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// One server per scheme; relative to the documentation host without `host`
+fn servers(host: Option<Value>, base_path: Option<Value>, schemes: Option<Value>) -> Vec<Value> {
+    let base_path = base_path
+        .as_ref()
+        .and_then(Value::as_str)
+        .unwrap_or("")
+        .trim_end_matches('/')
+        .to_string();
+    let Some(host) = host.as_ref().and_then(Value::as_str) else {
+        return if base_path.is_empty() {
+            Vec::new()
+        } else {
+            vec![json!({ "url": base_path })]
+        };
+    };
+    let mut schemes = media_types(schemes);
+    if schemes.is_empty() {
+        schemes.push("https".to_string());
+    }
+    schemes
+        .iter()
+        .map(|scheme| json!({ "url": format!("{}://{}{}", scheme, host, base_path) }))
+        .collect()
+}
+
+fn is_body_or_form(parameter: &Value) -> bool {
+    matches!(
+        parameter.get("in").and_then(Value::as_str),
+        Some("body" | "formData")
+    )
+}
+
+fn convert_path_item(
+    item: Value,
+    consumes: &[String],
+    produces: &[String],
+    body_parameters: &[String],
+) -> Value {
+    let Value::Object(mut item) = item else {
+        return item;
+    };
+    // Body parameters declared for the whole path go to each operation's request body
+    let (path_body, path_parameters) = split_parameters(item.remove("parameters"), body_parameters);
+    if !path_parameters.is_empty() {
+        item.insert("parameters".to_string(), Value::Array(path_parameters));
+    }
+
+    for method in crate::openapi::HTTP_METHODS {
+        let Some(Value::Object(operation)) = item.get_mut(method) else {
+            continue;
+        };
+        let consumes = match media_types(operation.remove("consumes")) {
+            types if types.is_empty() => consumes.to_vec(),
+            types => types,
+        };
+        let produces = match media_types(operation.remove("produces")) {
+            types if types.is_empty() => produces.to_vec(),
+            types => types,
+        };
+        operation.remove("schemes");
+
+        let (mut body, parameters) =
+            split_parameters(operation.remove("parameters"), body_parameters);
+        if body.is_empty() {
+            body = path_body.clone();
+        }
+        if !parameters.is_empty() {
+            operation.insert("parameters".to_string(), Value::Array(parameters));
+        }
+        if let Some(request_body) = request_body(body, &consumes) {
+            operation.insert("requestBody".to_string(), request_body);
+        }
+
+        if let Some(Value::Object(responses)) = operation.remove("responses") {
+            let responses = responses
+                .into_iter()
+                .map(|(code, response)| (code, convert_response(response, &produces)))
+                .collect();
+            operation.insert("responses".to_string(), Value::Object(responses));
+        }
+    }
+    Value::Object(item)
+}
+
+/// Splits Swagger 2.0 parameters into body/form parameters and converted other parameters.
+/// References to shared body parameters count as body parameters.
+fn split_parameters(
+    parameters: Option<Value>,
+    body_parameters: &[String],
+) -> (Vec<Value>, Vec<Value>) {
+    let Some(Value::Array(parameters)) = parameters else {
+        return (Vec::new(), Vec::new());
+    };
+    let mut body = Vec::new();
+    let mut others = Vec::new();
+    for parameter in parameters {
+        let shared_body = parameter
+            .get("$ref")
+            .and_then(Value::as_str)
+            .and_then(|reference| reference.strip_prefix("#/parameters/"))
+            .is_some_and(|name| body_parameters.iter().any(|body| body == name));
+        if shared_body || is_body_or_form(&parameter) {
+            body.push(parameter);
+        } else {
+            others.push(convert_parameter(parameter));
+        }
+    }
+    (body, others)
+}
+
+fn convert_parameter(parameter: Value) -> Value {
+    let Value::Object(mut parameter) = parameter else {
+        return parameter;
+    };
+    if parameter.contains_key("$ref") {
+        return Value::Object(parameter);
+    }
+    let location = parameter
+        .get("in")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+    if let Some(Value::String(format)) = parameter.remove("collectionFormat") {
+        let (style, explode) = match format.as_str() {
+            "multi" => ("form", true),
+            "ssv" => ("spaceDelimited", false),
+            "pipes" => ("pipeDelimited", false),
+            _ if location == "query" || location == "cookie" => ("form", false),
+            _ => ("simple", false),
+        };
+        parameter.insert("style".to_string(), json!(style));
+        parameter.insert("explode".to_string(), json!(explode));
+    }
+    let schema = take_schema(&mut parameter);
+    parameter.insert("schema".to_string(), schema);
+    Value::Object(parameter)
+}
+
+/// Moves the value keywords of a parameter or header into a schema
+fn take_schema(object: &mut Map<String, Value>) -> Value {
+    let mut schema = Map::new();
+    for keyword in SCHEMA_KEYWORDS {
+        if let Some(value) = object.remove(*keyword) {
+            schema.insert(keyword.to_string(), value);
+        }
+    }
+    Value::Object(schema)
+}
+
+/// The request body described by an operation's `body` or `formData` parameters
+fn request_body(parameters: Vec<Value>, consumes: &[String]) -> Option<Value> {
+    let mut parameters = parameters.into_iter().peekable();
+    let first = parameters.peek()?.clone();
+    if let Some(Value::String(reference)) = first.get("$ref") {
+        let name = reference.trim_start_matches("#/parameters/");
+        return Some(json!({ "$ref": format!("#/components/requestBodies/{}", name) }));
+    }
+
+    let mut body = Map::new();
+    if first.get("in").and_then(Value::as_str) == Some("body") {
+        if let Some(description) = first.get("description") {
+            body.insert("description".to_string(), description.clone());
+        }
+        if first.get("required").and_then(Value::as_bool) == Some(true) {
+            body.insert("required".to_string(), json!(true));
+        }
+        let schema = first.get("schema").cloned().unwrap_or_else(|| json!({}));
+        let types = if consumes.is_empty() {
+            vec![DEFAULT_MEDIA_TYPE.to_string()]
+        } else {
+            consumes.to_vec()
+        };
+        body.insert("content".to_string(), content(&types, &schema, None));
+        return Some(Value::Object(body));
+    }
+
+    // Form fields become the properties of one object schema
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+    let mut has_file = false;
+    for parameter in parameters {
+        let Value::Object(mut parameter) = parameter else {
+            continue;
+        };
+        let Some(Value::String(name)) = parameter.remove("name") else {
+            continue;
+        };
+        if parameter.get("required").and_then(Value::as_bool) == Some(true) {
+            required.push(json!(name));
+        }
+        has_file |= parameter.get("type").and_then(Value::as_str) == Some("file");
+        let mut schema = take_schema(&mut parameter);
+        if let Some(description) = parameter.remove("description") {
+            schema["description"] = description;
+        }
+        properties.insert(name, schema);
+    }
+    let mut schema = json!({ "type": "object", "properties": properties });
+    if !required.is_empty() {
+        schema["required"] = Value::Array(required);
+    }
+    let form_types: Vec<String> = consumes
+        .iter()
+        .filter(|t| t.starts_with("multipart/") || *t == "application/x-www-form-urlencoded")
+        .cloned()
+        .collect();
+    let types = match form_types {
+        types if !types.is_empty() => types,
+        _ if has_file => vec!["multipart/form-data".to_string()],
+        _ => vec!["application/x-www-form-urlencoded".to_string()],
+    };
+    body.insert("content".to_string(), content(&types, &schema, None));
+    Some(Value::Object(body))
+}
+
+fn content(types: &[String], schema: &Value, examples: Option<&Map<String, Value>>) -> Value {
+    let content: Map<String, Value> = types
+        .iter()
+        .map(|media_type| {
+            let mut media = json!({ "schema": schema });
+            if let Some(example) = examples.and_then(|examples| examples.get(media_type)) {
+                media["example"] = example.clone();
+            }
+            (media_type.clone(), media)
+        })
+        .collect();
+    Value::Object(content)
+}
+
+fn convert_response(response: Value, produces: &[String]) -> Value {
+    let Value::Object(mut response) = response else {
+        return response;
+    };
+    if response.contains_key("$ref") {
+        return Value::Object(response);
+    }
+    let examples = response.remove("examples").and_then(into_object);
+    if let Some(schema) = response.remove("schema") {
+        let types = if produces.is_empty() {
+            vec![DEFAULT_MEDIA_TYPE.to_string()]
+        } else {
+            produces.to_vec()
+        };
+        response.insert(
+            "content".to_string(),
+            content(&types, &schema, examples.as_ref()),
+        );
+    }
+    if let Some(Value::Object(headers)) = response.remove("headers") {
+        let headers = headers
+            .into_iter()
+            .map(|(name, header)| match header {
+                Value::Object(mut header) => {
+                    let schema = take_schema(&mut header);
+                    header.remove("collectionFormat");
+                    header.insert("schema".to_string(), schema);
+                    (name, Value::Object(header))
+                }
+                other => (name, other),
+            })
+            .collect();
+        response.insert("headers".to_string(), Value::Object(headers));
+    }
+    // A response object requires a description in both versions
+    response.entry("description").or_insert_with(|| json!(""));
+    Value::Object(response)
+}
+
+fn convert_security_scheme(scheme: Value) -> Value {
+    let Value::Object(mut scheme) = scheme else {
+        return scheme;
+    };
+    match scheme.get("type").and_then(Value::as_str) {
+        Some("basic") => {
+            scheme.insert("type".to_string(), json!("http"));
+            scheme.insert("scheme".to_string(), json!("basic"));
+        }
+        Some("oauth2") => {
+            let flow = scheme.remove("flow");
+            let mut settings = Map::new();
+            for key in ["authorizationUrl", "tokenUrl"] {
+                if let Some(url) = scheme.remove(key) {
+                    settings.insert(key.to_string(), url);
+                }
+            }
+            settings.insert(
+                "scopes".to_string(),
+                scheme.remove("scopes").unwrap_or_else(|| json!({})),
+            );
+            let flow = match flow.as_ref().and_then(Value::as_str) {
+                Some("accessCode") => "authorizationCode",
+                Some("application") => "clientCredentials",
+                Some("password") => "password",
+                _ => "implicit",
+            };
+            scheme.insert("flows".to_string(), json!({ flow: settings }));
+        }
+        _ => {}
+    }
+    Value::Object(scheme)
+}
+
+/// Points references at the `components` the definitions were moved to
+fn rewrite_refs(value: &mut Value, body_parameters: &[String]) {
+    match value {
+        Value::Object(map) => {
+            if let Some(Value::String(reference)) = map.get_mut("$ref") {
+                if let Some(name) = reference.strip_prefix("#/definitions/") {
+                    *reference = format!("#/components/schemas/{}", name);
+                } else if let Some(name) = reference.strip_prefix("#/parameters/") {
+                    let section = if body_parameters.iter().any(|body| body == name) {
+                        "requestBodies"
+                    } else {
+                        "parameters"
+                    };
+                    *reference = format!("#/components/{}/{}", section, name);
+                } else if let Some(name) = reference.strip_prefix("#/responses/") {
+                    *reference = format!("#/components/responses/{}", name);
+                }
+            }
+            for child in map.values_mut() {
+                rewrite_refs(child, body_parameters);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                rewrite_refs(item, body_parameters);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Swagger-only schema keywords: `type: file` and `x-nullable`
+fn convert_schemas(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            if map.get("type").and_then(Value::as_str) == Some("file") {
+                map.insert("type".to_string(), json!("string"));
+                map.insert("format".to_string(), json!("binary"));
+            }
+            if let Some(nullable) = map.remove("x-nullable") {
+                map.insert("nullable".to_string(), nullable);
+            }
+            for child in map.values_mut() {
+                convert_schemas(child);
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(convert_schemas),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_swagger_2_documents() {
+        let swagger = json!({
+            "swagger": "2.0",
+            "info": { "title": "Orders", "version": "1" },
+            "host": "orders.example.com",
+            "basePath": "/v1/",
+            "schemes": ["https"],
+            "produces": ["application/json"],
+            "securityDefinitions": { "basic": { "type": "basic" } },
+            "paths": {
+                "/orders": {
+                    "get": {
+                        "parameters": [{
+                            "name": "status", "in": "query", "type": "array",
+                            "items": { "type": "string" }, "collectionFormat": "csv"
+                        }],
+                        "responses": {
+                            "200": {
+                                "description": "Orders",
+                                "schema": {
+                                    "type": "array",
+                                    "items": { "$ref": "#/definitions/Order" }
+                                }
+                            }
+                        }
+                    },
+                    "post": {
+                        "parameters": [{
+                            "name": "order", "in": "body", "required": true,
+                            "schema": { "$ref": "#/definitions/Order" }
+                        }],
+                        "responses": { "201": { "description": "Created" } }
+                    }
+                }
+            },
+            "definitions": {
+                "Order": {
+                    "type": "object",
+                    "properties": { "note": { "type": "string", "x-nullable": true } }
+                }
+            }
+        });
+        assert_eq!(SpecVersion::detect(&swagger), Some(SpecVersion::Swagger2));
+
+        let openapi = to_openapi3(swagger);
+        assert_eq!(SpecVersion::detect(&openapi), Some(SpecVersion::OpenApi30));
+        assert_eq!(openapi.get("swagger"), None);
+        assert_eq!(
+            openapi["servers"],
+            json!([{ "url": "https://orders.example.com/v1" }])
+        );
+        assert_eq!(
+            openapi["components"]["securitySchemes"]["basic"],
+            json!({ "type": "http", "scheme": "basic" })
+        );
+        let get = &openapi["paths"]["/orders"]["get"];
+        assert_eq!(
+            get["parameters"][0],
+            json!({
+                "name": "status", "in": "query", "style": "form", "explode": false,
+                "schema": { "type": "array", "items": { "type": "string" } }
+            })
+        );
+        assert_eq!(
+            get["responses"]["200"]["content"]["application/json"]["schema"]["items"]["$ref"],
+            "#/components/schemas/Order"
+        );
+        let post = &openapi["paths"]["/orders"]["post"];
+        assert_eq!(post.get("parameters"), None);
+        assert_eq!(post["requestBody"]["required"], true);
+        assert_eq!(
+            post["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/Order"
+        );
+        assert_eq!(
+            openapi["components"]["schemas"]["Order"]["properties"]["note"],
+            json!({ "type": "string", "nullable": true })
+        );
+    }
+
+    #[test]
+    fn keeps_openapi_3_documents() {
+        let openapi = json!({ "openapi": "3.1.0", "info": {}, "paths": {} });
+        assert_eq!(SpecVersion::detect(&openapi), Some(SpecVersion::OpenApi31));
+        assert_eq!(to_openapi3(openapi.clone()), openapi);
+        assert_eq!(SpecVersion::detect(&json!({ "swagger": "1.2" })), None);
+    }
+}
@@ -0,0 +1,117 @@
+//! Tenants group namespaces into isolated catalogs, e.g. one per business unit.
+//!
+//! The operator stamps every catalog entry with its tenant, resolved from an explicit
+//! namespace mapping (`TENANT_MAPPING`) or a namespace label (`TENANT_LABEL`). Consumers
+//! such as the documentation server only show a tenant's entries to that tenant; entries
+//! without a tenant are shared with everyone.
+
+use std::collections::BTreeMap;
+
+/// Parses `key=value` pairs separated by commas, skipping malformed items
+pub fn parse_key_values(value: &str) -> Vec<(String, String)> {
+    value
+        .split(',')
+        .filter_map(|item| item.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .filter(|(key, value)| !key.is_empty() && !value.is_empty())
+        .collect()
+}
+
+/// Resolves the tenant owning a namespace
+#[derive(Debug, Clone, Default)]
+pub struct TenantResolver {
+    /// Namespace (or glob pattern) -> tenant, first match wins
+    mapping: Vec<(String, String)>,
+    /// Namespace label holding the tenant name
+    label: Option<String>,
+}
+
+impl TenantResolver {
+    pub fn new(mapping: Vec<(String, String)>, label: Option<String>) -> Self {
+        Self { mapping, label }
+    }
+
+    /// Reads `TENANT_MAPPING` (`payments-*=payments,shop=retail`) and `TENANT_LABEL`
+    pub fn from_env() -> Self {
+        Self::new(
+            parse_key_values(&std::env::var(super::TENANT_MAPPING_ENV).unwrap_or_default()),
+            std::env::var(super::TENANT_LABEL_ENV)
+                .ok()
+                .map(|label| label.trim().to_string())
+                .filter(|label| !label.is_empty()),
+        )
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.mapping.is_empty() || self.label.is_some()
+    }
+
+    /// Namespace label to look up, if tenants are derived from labels
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    /// The explicit mapping takes precedence over the namespace label
+    pub fn resolve(
+        &self,
+        namespace: &str,
+        namespace_labels: Option<&BTreeMap<String, String>>,
+    ) -> Option<String> {
+        self.mapping
+            .iter()
+            .find(|(pattern, _)| {
+                crate::namespace_utils::namespace_matches(std::slice::from_ref(pattern), namespace)
+            })
+            .map(|(_, tenant)| tenant.clone())
+            .or_else(|| {
+                let label = self.label.as_ref()?;
+                namespace_labels?
+                    .get(label)
+                    .filter(|tenant| !tenant.is_empty())
+                    .cloned()
+            })
+    }
+}
+
+/// Whether a caller belonging to `caller` may see an entry owned by `entry_tenant`.
+/// Entries without a tenant are shared; tenant entries are only visible to that tenant.
+pub fn is_visible(caller: Option<&str>, entry_tenant: Option<&str>) -> bool {
+    match entry_tenant {
+        None => true,
+        Some(tenant) => caller == Some(tenant),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mapping_wins_over_label() {
+        let resolver = TenantResolver::new(
+            parse_key_values("payments-*=payments, shop=retail, broken"),
+            Some("example.com/tenant".to_string()),
+        );
+        let labels = BTreeMap::from([("example.com/tenant".to_string(), "ops".to_string())]);
+
+        assert_eq!(
+            resolver.resolve("payments-eu", Some(&labels)),
+            Some("payments".to_string())
+        );
+        assert_eq!(resolver.resolve("shop", None), Some("retail".to_string()));
+        assert_eq!(
+            resolver.resolve("monitoring", Some(&labels)),
+            Some("ops".to_string())
+        );
+        assert_eq!(resolver.resolve("monitoring", None), None);
+    }
+
+    #[test]
+    fn shared_entries_are_visible_to_everyone() {
+        assert!(is_visible(None, None));
+        assert!(is_visible(Some("retail"), None));
+        assert!(is_visible(Some("retail"), Some("retail")));
+        assert!(!is_visible(Some("retail"), Some("payments")));
+        assert!(!is_visible(None, Some("payments")));
+    }
+}
@@ -0,0 +1,142 @@
+// Quick-switch overlay: Ctrl+K (Cmd+K on macOS) searches APIs, tags and operations
+// through /apis/suggest and jumps to the selected one.
+(function () {
+  const frontend = window.location.pathname.replace(/^\/+|\/+$/g, '');
+  let overlay = null;
+  let input = null;
+  let list = null;
+  let results = [];
+  let selected = 0;
+  let pending = null;
+
+  function build() {
+    overlay = document.createElement('div');
+    overlay.id = 'quick-switch';
+    overlay.setAttribute('role', 'dialog');
+    overlay.setAttribute('aria-label', 'Quick switch');
+    overlay.style.cssText =
+      'position:fixed;inset:0;z-index:2147483647;display:none;background:rgba(0,0,0,.4);' +
+      'font-family:system-ui,-apple-system,sans-serif';
+    overlay.innerHTML =
+      '<div style="max-width:640px;margin:12vh auto 0;background:#fff;color:#222;' +
+      'border-radius:8px;box-shadow:0 12px 40px rgba(0,0,0,.3);overflow:hidden">' +
+      '<input type="search" placeholder="Jump to an API, tag or operation..." ' +
+      'style="width:100%;box-sizing:border-box;padding:14px 16px;font-size:16px;border:0;' +
+      'border-bottom:1px solid #ddd;outline:none">' +
+      '<ul role="listbox" style="list-style:none;margin:0;padding:0;max-height:60vh;' +
+      'overflow-y:auto"></ul></div>';
+    document.body.appendChild(overlay);
+    input = overlay.querySelector('input');
+    list = overlay.querySelector('ul');
+
+    overlay.addEventListener('mousedown', function (event) {
+      if (event.target === overlay) {
+        close();
+      }
+    });
+    input.addEventListener('input', function () {
+      clearTimeout(pending);
+      pending = setTimeout(search, 120);
+    });
+    input.addEventListener('keydown', function (event) {
+      if (event.key === 'ArrowDown' || event.key === 'ArrowUp') {
+        event.preventDefault();
+        const step = event.key === 'ArrowDown' ? 1 : -1;
+        selected = (selected + step + results.length) % Math.max(results.length, 1);
+        render();
+      } else if (event.key === 'Enter' && results[selected]) {
+        event.preventDefault();
+        go(results[selected]);
+      } else if (event.key === 'Escape') {
+        close();
+      }
+    });
+  }
+
+  function open() {
+    if (!overlay) {
+      build();
+    }
+    overlay.style.display = 'block';
+    input.value = '';
+    results = [];
+    render();
+    input.focus();
+  }
+
+  function close() {
+    if (overlay) {
+      overlay.style.display = 'none';
+    }
+  }
+
+  function search() {
+    const query = input.value.trim();
+    if (!query) {
+      results = [];
+      render();
+      return;
+    }
+    const params = new URLSearchParams({ q: query });
+    if (frontend) {
+      params.set('frontend', frontend);
+    }
+    fetch('/apis/suggest?' + params.toString())
+      .then(function (response) {
+        return response.ok ? response.json() : { suggestions: [] };
+      })
+      .then(function (body) {
+        // Ignore answers to queries that were typed over in the meantime
+        if (input.value.trim() === query) {
+          results = body.suggestions || [];
+          selected = 0;
+          render();
+        }
+      })
+      .catch(function () {});
+  }
+
+  function render() {
+    list.innerHTML = '';
+    results.forEach(function (result, index) {
+      const item = document.createElement('li');
+      item.setAttribute('role', 'option');
+      item.style.cssText =
+        'padding:8px 16px;cursor:pointer;display:flex;gap:12px;align-items:baseline;' +
+        (index === selected ? 'background:#eef3ff' : '');
+      const kind = document.createElement('span');
+      kind.textContent = result.kind;
+      kind.style.cssText = 'font-size:11px;text-transform:uppercase;color:#888;width:70px';
+      const label = document.createElement('span');
+      label.textContent = result.label;
+      label.style.cssText = 'flex:1;font-family:ui-monospace,monospace';
+      const api = document.createElement('span');
+      api.textContent = result.kind === 'api' ? result.detail || '' : result.api;
+      api.style.cssText = 'font-size:12px;color:#666';
+      item.append(kind, label, api);
+      item.addEventListener('mousedown', function (event) {
+        event.preventDefault();
+        go(result);
+      });
+      list.appendChild(item);
+    });
+  }
+
+  function go(result) {
+    close();
+    window.location.href = result.url;
+  }
+
+  // Capturing, so the shortcut wins over the frontends' own search
+  window.addEventListener(
+    'keydown',
+    function (event) {
+      if ((event.ctrlKey || event.metaKey) && event.key.toLowerCase() === 'k') {
+        event.preventDefault();
+        event.stopImmediatePropagation();
+        open();
+      }
+    },
+    true
+  );
+})();
@@ -0,0 +1,186 @@
+//! Opt-in usage analytics.
+//!
+//! With `ANALYTICS=true` the server counts, per API, how often its documentation is viewed
+//! in the UI and how often its spec is downloaded through `/specs` or `/api`. Only counters
+//! are kept: nothing about the caller (address, token, tenant, user agent) is recorded.
+//! Counts are kept in daily buckets for 30 days next to all-time totals, and written to
+//! `analytics.json` in `CACHE_DIR` after every cache refresh, so they only survive restarts
+//! when that directory is persistent.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+pub const ANALYTICS_ENV: &str = "ANALYTICS";
+
+const ANALYTICS_FILE: &str = "analytics.json";
+const DAY_SECS: u64 = 24 * 60 * 60;
+const RETENTION_DAYS: u64 = 30;
+
+/// How a spec was requested
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Rendered by one of the frontends
+    View,
+    /// Fetched directly, e.g. by a code generator
+    Download,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct Counts {
+    views: u64,
+    downloads: u64,
+}
+
+impl Counts {
+    fn add(&mut self, access: Access) {
+        match access {
+            Access::View => self.views += 1,
+            Access::Download => self.downloads += 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ApiUsage {
+    total: Counts,
+    /// Day start (unix seconds) -> counts
+    daily: BTreeMap<u64, Counts>,
+    /// Unix seconds of the last view or download
+    last_accessed: Option<u64>,
+}
+
+/// Usage of one API as reported by `/analytics`
+#[derive(Debug, Clone, Default)]
+pub struct UsageReport {
+    pub views: u64,
+    pub downloads: u64,
+    pub views_30d: u64,
+    pub downloads_30d: u64,
+    pub last_accessed: Option<u64>,
+}
+
+#[derive(Clone)]
+pub struct UsageAnalytics {
+    path: PathBuf,
+    /// API slug -> usage
+    usage: Arc<Mutex<BTreeMap<String, ApiUsage>>>,
+}
+
+impl UsageAnalytics {
+    /// `None` unless `ANALYTICS=true`; loads the counters kept in `CACHE_DIR`
+    pub fn from_env(cache_dir: &Path) -> Option<Self> {
+        let enabled = std::env::var(ANALYTICS_ENV)
+            .map(|v| v.trim().eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        if !enabled {
+            return None;
+        }
+
+        let path = cache_dir.join(ANALYTICS_FILE);
+        let usage = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        tracing::info!("Usage analytics enabled");
+        Some(Self {
+            path,
+            usage: Arc::new(Mutex::new(usage)),
+        })
+    }
+
+    /// Counts one view or download and drops days past the retention period
+    pub async fn record(&self, slug: &str, access: Access, now: u64) {
+        let mut usage = self.usage.lock().await;
+        let api = usage.entry(slug.to_string()).or_default();
+        api.total.add(access);
+        api.daily
+            .entry(now - now % DAY_SECS)
+            .or_default()
+            .add(access);
+        api.last_accessed = Some(now);
+
+        let cutoff = now.saturating_sub(RETENTION_DAYS * DAY_SECS);
+        api.daily.retain(|start, _| *start >= cutoff);
+    }
+
+    pub async fn report(&self, slug: &str, now: u64) -> UsageReport {
+        let usage = self.usage.lock().await;
+        let Some(api) = usage.get(slug) else {
+            return UsageReport::default();
+        };
+        let since = now.saturating_sub(RETENTION_DAYS * DAY_SECS);
+        let recent =
+            api.daily
+                .range(since - since % DAY_SECS..)
+                .fold(Counts::default(), |sum, (_, day)| Counts {
+                    views: sum.views + day.views,
+                    downloads: sum.downloads + day.downloads,
+                });
+        UsageReport {
+            views: api.total.views,
+            downloads: api.total.downloads,
+            views_30d: recent.views,
+            downloads_30d: recent.downloads,
+            last_accessed: api.last_accessed,
+        }
+    }
+
+    /// Drops the counters of an API that was removed from the catalog
+    pub async fn forget(&self, slug: &str) {
+        self.usage.lock().await.remove(slug);
+    }
+
+    pub async fn save(&self) -> std::io::Result<()> {
+        let content = serde_json::to_string(&*self.usage.lock().await)?;
+        fs::write(&self.path, content)
+    }
+}
+
+/// Escapes a Prometheus label value
+pub fn label_value(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn counts_views_and_downloads_in_daily_buckets() {
+        let analytics = UsageAnalytics {
+            path: PathBuf::from(ANALYTICS_FILE),
+            usage: Arc::default(),
+        };
+        let start = 100 * DAY_SECS;
+        analytics.record("shop-orders", Access::View, start).await;
+        analytics
+            .record("shop-orders", Access::Download, start + 60)
+            .await;
+        let later = start + 40 * DAY_SECS;
+        analytics.record("shop-orders", Access::View, later).await;
+        analytics.record("shop-carts", Access::View, later).await;
+
+        // The first day is past the retention period, only its totals remain
+        let report = analytics.report("shop-orders", later).await;
+        assert_eq!((report.views, report.downloads), (2, 1));
+        assert_eq!((report.views_30d, report.downloads_30d), (1, 0));
+        assert_eq!(report.last_accessed, Some(later));
+        assert_eq!(analytics.usage.lock().await["shop-orders"].daily.len(), 1);
+
+        analytics.forget("shop-orders").await;
+        assert_eq!(analytics.report("shop-orders", later).await.views, 0);
+        assert_eq!(analytics.report("shop-carts", later).await.views, 1);
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(label_value("a\"b\\c\nd"), r#"a\"b\\c\nd"#);
+    }
+}
@@ -0,0 +1,158 @@
+//! RFC 9727 API catalog.
+//!
+//! `/.well-known/api-catalog` lists the cached APIs as an RFC 9264 Linkset, so discovery
+//! tooling that understands the RFC can find them without knowing this server. The first
+//! context links the catalog to every API with `item`; each API then gets its own context
+//! with `service-desc` (the spec), `service-doc` (the UI) and `status` links. Links must be
+//! absolute: they are built from `PUBLIC_URL`, or from the request's host when it is unset.
+
+use axum::http::{HeaderMap, header};
+use serde_json::{Value, json};
+
+use crate::CachedApiEntry;
+
+pub const PUBLIC_URL_ENV: &str = "PUBLIC_URL";
+
+pub const API_CATALOG_PATH: &str = "/.well-known/api-catalog";
+
+/// Media type of the catalog, see RFC 9727 section 4.2
+pub const API_CATALOG_CONTENT_TYPE: &str =
+    "application/linkset+json; profile=\"https://www.rfc-editor.org/info/rfc9727\"";
+
+/// `PUBLIC_URL` without a trailing slash
+pub fn public_url_from_env() -> Option<String> {
+    std::env::var(PUBLIC_URL_ENV)
+        .ok()
+        .map(|url| url.trim().trim_end_matches('/').to_string())
+        .filter(|url| !url.is_empty())
+}
+
+/// Base URL the links are built from, e.g. `https://apis.example.com`
+pub fn base_url(public_url: Option<&str>, headers: &HeaderMap) -> String {
+    if let Some(url) = public_url {
+        return url.to_string();
+    }
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(',').next())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+    let scheme = header("x-forwarded-proto").unwrap_or("http");
+    let host = header("x-forwarded-host")
+        .or_else(|| header(header::HOST.as_str()))
+        .unwrap_or("localhost");
+    format!("{}://{}", scheme, host)
+}
+
+/// `Link` header advertising the catalog (RFC 9727 section 3)
+pub fn link_header(base_url: &str) -> String {
+    format!("<{}{}>; rel=\"api-catalog\"", base_url, API_CATALOG_PATH)
+}
+
+/// Renders the Linkset document for the given APIs
+pub fn render_linkset(apis: &[CachedApiEntry], base_url: &str) -> Value {
+    let anchors: Vec<String> = apis.iter().map(|api| api_base_url(api, base_url)).collect();
+
+    let mut linkset = vec![json!({
+        "anchor": format!("{}{}", base_url, API_CATALOG_PATH),
+        "item": anchors
+            .iter()
+            .map(|anchor| json!({ "href": anchor }))
+            .collect::<Vec<_>>(),
+    })];
+    for (api, anchor) in apis.iter().zip(anchors) {
+        linkset.push(json!({
+            "anchor": anchor,
+            "service-desc": [{
+                "href": format!("{}/specs/{}", base_url, api.slug),
+                "type": "application/json",
+                "title": api.name,
+            }],
+            "service-doc": [{
+                "href": format!("{}/", base_url),
+                "type": "text/html",
+                "title": api.name,
+            }],
+            "status": [{
+                "href": format!("{}/badges/{}", base_url, api.slug),
+                "type": "image/svg+xml",
+            }],
+        }));
+    }
+    json!({ "linkset": linkset })
+}
+
+/// The API's own base URL (scheme and authority of its spec URL), falling back to its
+/// spec on this server when the URL does not parse
+pub fn api_base_url(api: &CachedApiEntry, base_url: &str) -> String {
+    reqwest::Url::parse(&api.url)
+        .ok()
+        .map(|url| url.origin().ascii_serialization())
+        .filter(|origin| origin != "null")
+        .unwrap_or_else(|| format!("{}/specs/{}", base_url, api.slug))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn base_url_prefers_public_url_then_forwarded_headers() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(base_url(None, &headers), "http://localhost");
+        headers.insert(header::HOST, HeaderValue::from_static("docs.internal:3000"));
+        assert_eq!(base_url(None, &headers), "http://docs.internal:3000");
+        headers.insert("x-forwarded-proto", HeaderValue::from_static("https, http"));
+        headers.insert(
+            "x-forwarded-host",
+            HeaderValue::from_static("apis.example.com"),
+        );
+        assert_eq!(base_url(None, &headers), "https://apis.example.com");
+        assert_eq!(
+            base_url(Some("https://portal.example.com"), &headers),
+            "https://portal.example.com"
+        );
+    }
+
+    #[test]
+    fn linkset_links_the_catalog_to_every_api() {
+        let base = "https://apis.example.com";
+        let mut carts = CachedApiEntry::example("shop", "carts", "");
+        carts.url = "not a url".to_string();
+        let apis = [CachedApiEntry::example("shop", "orders", ""), carts];
+
+        let linkset = render_linkset(&apis, base);
+        let contexts = linkset["linkset"].as_array().unwrap();
+        assert_eq!(contexts.len(), 3);
+        assert_eq!(
+            contexts[0]["anchor"],
+            "https://apis.example.com/.well-known/api-catalog"
+        );
+        // An API's anchor is the origin of its spec URL, or its spec here when unparsable
+        assert_eq!(
+            contexts[0]["item"],
+            json!([
+                { "href": "http://orders.shop.svc.cluster.local:8080" },
+                { "href": "https://apis.example.com/specs/shop-carts" },
+            ])
+        );
+        assert_eq!(contexts[1]["anchor"], contexts[0]["item"][0]["href"]);
+        assert_eq!(
+            contexts[1]["service-desc"][0]["href"],
+            "https://apis.example.com/specs/shop-orders"
+        );
+        assert_eq!(contexts[1]["service-desc"][0]["title"], "orders API");
+        assert_eq!(
+            contexts[2]["status"][0]["href"],
+            "https://apis.example.com/badges/shop-carts"
+        );
+        assert_eq!(
+            link_header(base),
+            "<https://apis.example.com/.well-known/api-catalog>; rel=\"api-catalog\""
+        );
+    }
+}
@@ -0,0 +1,185 @@
+//! Versions of one logical API.
+//!
+//! Services annotated with the same `api-doc.io/api-group` (v1 and v2 deployments, a canary
+//! next to the stable release) are shown as one item in the frontends instead of a list of
+//! near-duplicates. The item opens the newest version, or the one asked for with `?api=`,
+//! and its spec starts with links to the other versions. Versions are labeled with the
+//! spec's `info.version`, or the service name when two versions share one.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::CachedApiEntry;
+
+/// One entry per group, in the order of `apis`: the `selected` slug if it belongs to the
+/// group, the newest version otherwise. APIs without a group are kept as they are.
+pub fn collapse(apis: Vec<CachedApiEntry>, selected: Option<&str>) -> Vec<CachedApiEntry> {
+    let mut shown: HashMap<String, String> = HashMap::new();
+    for group in apis.iter().filter_map(|api| api.api_group.as_deref()) {
+        if shown.contains_key(group) {
+            continue;
+        }
+        let versions = versions(&apis, group);
+        let slug = versions
+            .iter()
+            .find(|api| Some(api.slug.as_str()) == selected)
+            .or_else(|| versions.first())
+            .map(|api| api.slug.clone())
+            .unwrap_or_default();
+        shown.insert(group.to_string(), slug);
+    }
+
+    apis.into_iter()
+        .filter(|api| match &api.api_group {
+            Some(group) => shown.get(group) == Some(&api.slug),
+            None => true,
+        })
+        .collect()
+}
+
+/// The APIs of a group, newest version first; available versions before unavailable ones
+pub fn versions<'a>(apis: &'a [CachedApiEntry], group: &str) -> Vec<&'a CachedApiEntry> {
+    let mut versions: Vec<&CachedApiEntry> = apis
+        .iter()
+        .filter(|api| api.api_group.as_deref() == Some(group))
+        .collect();
+    versions.sort_by(|a, b| {
+        b.available
+            .cmp(&a.available)
+            .then_with(|| compare_versions(b.version.as_deref(), a.version.as_deref()))
+            .then_with(|| a.slug.cmp(&b.slug))
+    });
+    versions
+}
+
+/// Markdown prepended to `info.description` of `api`, linking to the other versions of its
+/// group; `None` when there are none
+pub fn switcher_markdown(api: &CachedApiEntry, versions: &[&CachedApiEntry]) -> Option<String> {
+    if versions.len() < 2 {
+        return None;
+    }
+    let links: Vec<String> = versions
+        .iter()
+        .map(|version| {
+            let label = label(version, versions);
+            if version.slug == api.slug {
+                format!("**{}**", label)
+            } else {
+                format!("[{}](?api={})", label, version.slug)
+            }
+        })
+        .collect();
+    Some(format!("**Versions:** {}", links.join(" · ")))
+}
+
+/// Label of a version within its group
+pub fn label(api: &CachedApiEntry, versions: &[&CachedApiEntry]) -> String {
+    match &api.version {
+        Some(version)
+            if versions
+                .iter()
+                .filter(|other| other.version.as_ref() == Some(version))
+                .count()
+                == 1 =>
+        {
+            version.clone()
+        }
+        Some(version) => format!("{} ({})", version, api.service_name),
+        None => api.service_name.clone(),
+    }
+}
+
+/// Orders `1.10.0` after `1.9.2`: numeric parts compare as numbers, the rest as text
+fn compare_versions(a: Option<&str>, b: Option<&str>) -> Ordering {
+    let parts = |version: &str| -> Vec<(u64, String)> {
+        version
+            .trim_start_matches(['v', 'V'])
+            .split(['.', '-', '+'])
+            .map(|part| (part.parse().unwrap_or(0), part.to_string()))
+            .collect()
+    };
+    match (a, b) {
+        (Some(a), Some(b)) => parts(a).cmp(&parts(b)),
+        (a, b) => a.is_some().cmp(&b.is_some()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(service_name: &str, group: Option<&str>, version: Option<&str>) -> CachedApiEntry {
+        let mut api = CachedApiEntry::example("shop", service_name, "");
+        api.api_group = group.map(str::to_string);
+        api.version = version.map(str::to_string);
+        api
+    }
+
+    fn slugs(apis: &[CachedApiEntry]) -> Vec<&str> {
+        apis.iter().map(|api| api.slug.as_str()).collect()
+    }
+
+    #[test]
+    fn versions_are_ordered_newest_and_available_first() {
+        let mut retired = version("orders-v3", Some("orders"), Some("3.0.0"));
+        retired.available = false;
+        let apis = [
+            version("orders-v1", Some("orders"), Some("v1.9.2")),
+            version("orders-v2", Some("orders"), Some("1.10.0")),
+            retired,
+            version("orders-canary", Some("orders"), None),
+            version("carts", Some("carts"), Some("9.0.0")),
+        ];
+
+        let ordered: Vec<&str> = versions(&apis, "orders")
+            .iter()
+            .map(|api| api.service_name.as_str())
+            .collect();
+        assert_eq!(
+            ordered,
+            ["orders-v2", "orders-v1", "orders-canary", "orders-v3"]
+        );
+    }
+
+    #[test]
+    fn groups_collapse_to_the_newest_or_selected_version() {
+        let apis = vec![
+            version("orders-v1", Some("orders"), Some("1.0.0")),
+            version("carts", None, None),
+            version("orders-v2", Some("orders"), Some("2.0.0")),
+        ];
+        let [v1, carts, v2] = [&apis[0], &apis[1], &apis[2]].map(|api| api.slug.clone());
+
+        assert_eq!(slugs(&collapse(apis.clone(), None)), [&carts, &v2]);
+        assert_eq!(
+            slugs(&collapse(apis.clone(), Some(v1.as_str()))),
+            [&v1, &carts]
+        );
+        // Slugs of other groups or unknown slugs do not change the choice
+        assert_eq!(slugs(&collapse(apis, Some(carts.as_str()))), [&carts, &v2]);
+    }
+
+    #[test]
+    fn switcher_links_to_the_other_versions() {
+        let apis = [
+            version("orders-v2", Some("orders"), Some("2.0.0")),
+            version("orders-canary", Some("orders"), Some("2.0.0")),
+            version("orders-v1", Some("orders"), Some("1.0.0")),
+        ];
+        let versions = versions(&apis, "orders");
+
+        assert_eq!(
+            switcher_markdown(&apis[2], &versions).unwrap(),
+            format!(
+                "**Versions:** [2.0.0 (orders-canary)](?api={}) · [2.0.0 (orders-v2)](?api={}) \
+                 · **1.0.0**",
+                apis[1].slug, apis[0].slug
+            )
+        );
+        assert_eq!(switcher_markdown(&apis[0], &versions[..1]), None);
+        assert_eq!(
+            label(&version("orders-next", Some("orders"), None), &versions),
+            "orders-next"
+        );
+    }
+}
@@ -0,0 +1,120 @@
+//! APIs.json export.
+//!
+//! `/apis.json` describes the cached APIs in the [APIs.json](http://apisjson.org) format
+//! ingested by several API directories: one entry per API with its base URL, a link to
+//! the UI and an `OpenAPI` property pointing at the spec served by this server.
+
+use serde::Serialize;
+
+use crate::CachedApiEntry;
+use crate::api_catalog::api_base_url;
+
+const SPECIFICATION_VERSION: &str = "0.14";
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ApisJson {
+    name: String,
+    description: String,
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modified: Option<String>,
+    specification_version: &'static str,
+    apis: Vec<ApiDescription>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiDescription {
+    name: String,
+    description: String,
+    #[serde(rename = "humanURL")]
+    human_url: String,
+    #[serde(rename = "baseURL")]
+    base_url: String,
+    tags: Vec<String>,
+    properties: Vec<Property>,
+}
+
+#[derive(Serialize)]
+struct Property {
+    #[serde(rename = "type")]
+    property_type: &'static str,
+    url: String,
+}
+
+/// Renders the APIs.json document; `title` names the catalog (e.g. the tenant's title)
+pub fn render_apis_json(apis: &[CachedApiEntry], title: Option<&str>, base_url: &str) -> String {
+    let document = ApisJson {
+        name: title.unwrap_or("API Documentation").to_string(),
+        description: "APIs discovered in the Kubernetes cluster".to_string(),
+        url: format!("{}/apis.json", base_url),
+        modified: apis.iter().map(|api| api.last_updated.clone()).max(),
+        specification_version: SPECIFICATION_VERSION,
+        apis: apis
+            .iter()
+            .map(|api| {
+                let mut tags = vec![api.namespace.clone()];
+                tags.extend(api.lifecycle.map(|stage| stage.to_string()));
+                ApiDescription {
+                    name: api.name.clone(),
+                    description: api.description.clone().unwrap_or_default(),
+                    human_url: format!("{}/", base_url),
+                    base_url: api_base_url(api, base_url),
+                    tags,
+                    properties: vec![Property {
+                        property_type: "OpenAPI",
+                        url: format!("{}/specs/{}", base_url, api.slug),
+                    }],
+                }
+            })
+            .collect(),
+    };
+    serde_json::to_string_pretty(&document).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openapi_common::Lifecycle;
+
+    #[test]
+    fn lists_every_api_with_its_spec_on_this_server() {
+        let mut orders = CachedApiEntry::example("shop", "orders", "");
+        orders.description = Some("Order management".to_string());
+        orders.lifecycle = Some(Lifecycle::Beta);
+        let mut users = CachedApiEntry::example("accounts", "users", "");
+        users.last_updated = "2026-03-01T00:00:00Z".to_string();
+
+        let rendered = render_apis_json(
+            &[orders, users],
+            Some("Retail APIs"),
+            "https://apis.example.com",
+        );
+        let document: serde_json::Value = serde_json::from_str(&rendered).unwrap();
+        assert_eq!(document["name"], "Retail APIs");
+        assert_eq!(document["url"], "https://apis.example.com/apis.json");
+        assert_eq!(document["modified"], "2026-03-01T00:00:00Z");
+        assert_eq!(document["specificationVersion"], SPECIFICATION_VERSION);
+
+        let api = &document["apis"][0];
+        assert_eq!(api["name"], "orders API");
+        assert_eq!(api["description"], "Order management");
+        assert_eq!(api["humanURL"], "https://apis.example.com/");
+        assert_eq!(api["baseURL"], "http://orders.shop.svc.cluster.local:8080");
+        assert_eq!(api["tags"], serde_json::json!(["shop", "beta"]));
+        assert_eq!(
+            api["properties"],
+            serde_json::json!([{
+                "type": "OpenAPI",
+                "url": "https://apis.example.com/specs/shop-orders",
+            }])
+        );
+        assert_eq!(document["apis"][1]["tags"], serde_json::json!(["accounts"]));
+
+        let empty: serde_json::Value =
+            serde_json::from_str(&render_apis_json(&[], None, "http://localhost")).unwrap();
+        assert_eq!(empty["name"], "API Documentation");
+        assert!(empty.get("modified").is_none());
+    }
+}
@@ -0,0 +1,174 @@
+//! Availability history and uptime (SLO) reporting.
+//!
+//! Every cache refresh records whether an API's spec could be fetched. Checks are counted
+//! in hourly buckets kept for 30 days in `<api>.availability.json` next to the cached spec,
+//! and uptime is the share of successful checks over the last 7 or 30 days. The history
+//! lives in `CACHE_DIR`, so it only survives restarts when that directory is persistent.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const SLO_BADGES_ENV: &str = "SLO_BADGES";
+
+const BUCKET_SECS: u64 = 60 * 60;
+const DAY_SECS: u64 = 24 * 60 * 60;
+const RETENTION_DAYS: u64 = 30;
+
+/// Windows reported by `/slo` and offered for badges
+pub const WINDOWS: [u64; 2] = [7, 30];
+
+/// Successful and total checks within one hour
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct Bucket {
+    up: u32,
+    total: u32,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AvailabilityHistory {
+    /// Bucket start (unix seconds) -> counts
+    buckets: BTreeMap<u64, Bucket>,
+}
+
+impl AvailabilityHistory {
+    /// Loads the history, starting empty when the file is missing or unreadable
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        fs::write(path, serde_json::to_string(self)?)
+    }
+
+    /// Counts one check and drops buckets past the retention period
+    pub fn record(&mut self, up: bool, now: u64) {
+        let bucket = self.buckets.entry(now - now % BUCKET_SECS).or_default();
+        bucket.total += 1;
+        if up {
+            bucket.up += 1;
+        }
+
+        let cutoff = now.saturating_sub(RETENTION_DAYS * DAY_SECS);
+        self.buckets.retain(|start, _| *start >= cutoff);
+    }
+
+    /// Uptime in percent over the last `days`, `None` without checks in that window
+    pub fn uptime(&self, days: u64, now: u64) -> Option<f64> {
+        let since = now.saturating_sub(days * DAY_SECS);
+        let (up, total) = self
+            .buckets
+            .range(since - since % BUCKET_SECS..)
+            .fold((0u64, 0u64), |(up, total), (_, bucket)| {
+                (up + u64::from(bucket.up), total + u64::from(bucket.total))
+            });
+        (total > 0).then(|| up as f64 * 100.0 / total as f64)
+    }
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Badges are added to specs rendered in the UI unless `SLO_BADGES=false`
+pub fn badges_enabled() -> bool {
+    std::env::var(SLO_BADGES_ENV)
+        .map(|v| !v.trim().eq_ignore_ascii_case("false"))
+        .unwrap_or(true)
+}
+
+/// Flat, shields.io-style SVG badge such as `uptime 30d | 99.95%`
+pub fn badge_svg(days: u64, uptime: Option<f64>) -> String {
+    let label = format!("uptime {}d", days);
+    let (value, color) = match uptime {
+        Some(pct) if pct >= 99.9 => (format!("{:.2}%", pct), "#4c1"),
+        Some(pct) if pct >= 99.0 => (format!("{:.2}%", pct), "#a4a61d"),
+        Some(pct) if pct >= 95.0 => (format!("{:.2}%", pct), "#dfb317"),
+        Some(pct) => (format!("{:.2}%", pct), "#e05d44"),
+        None => ("no data".to_string(), "#9f9f9f"),
+    };
+    crate::badges::flat_badge(&label, &value, color)
+}
+
+/// Markdown prepended to `info.description` so the frontends show the badges
+pub fn badge_markdown(slug: &str) -> String {
+    WINDOWS
+        .iter()
+        .map(|days| format!("![Uptime {days}d](/badges/{slug}?window={days}d)"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A time on an hour boundary, well past the retention period
+    const NOW: u64 = 1000 * DAY_SECS;
+
+    #[test]
+    fn uptime_is_the_share_of_successful_checks_in_the_window() {
+        let mut history = AvailabilityHistory::default();
+        assert_eq!(history.uptime(7, NOW), None);
+
+        history.record(true, NOW - 10 * DAY_SECS);
+        for up in [true, true, true, false] {
+            history.record(up, NOW + 60);
+        }
+        assert_eq!(history.uptime(7, NOW + 60), Some(75.0));
+        assert_eq!(history.uptime(30, NOW + 60), Some(80.0));
+    }
+
+    #[test]
+    fn checks_past_the_retention_period_are_dropped() {
+        let mut history = AvailabilityHistory::default();
+        history.record(false, NOW - (RETENTION_DAYS + 1) * DAY_SECS);
+        history.record(true, NOW);
+
+        assert_eq!(history.buckets.len(), 1);
+        assert_eq!(history.uptime(365, NOW), Some(100.0));
+    }
+
+    #[test]
+    fn history_is_saved_and_loaded() {
+        let path = std::env::temp_dir().join(format!(
+            "availability-{}-{}.json",
+            std::process::id(),
+            now_secs()
+        ));
+        assert!(AvailabilityHistory::load(&path).buckets.is_empty());
+
+        let mut history = AvailabilityHistory::default();
+        history.record(true, NOW);
+        history.record(false, NOW);
+        history.save(&path).unwrap();
+        let loaded = AvailabilityHistory::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.uptime(7, NOW), Some(50.0));
+    }
+
+    #[test]
+    fn badges_are_colored_by_uptime() {
+        let svg = badge_svg(30, Some(99.95));
+        assert!(svg.contains(">uptime 30d</text>"));
+        assert!(svg.contains(">99.95%</text>"));
+        assert!(svg.contains("#4c1"));
+        assert!(badge_svg(7, Some(99.5)).contains("#a4a61d"));
+        assert!(badge_svg(7, Some(96.0)).contains("#dfb317"));
+        assert!(badge_svg(7, Some(90.0)).contains("#e05d44"));
+        assert!(badge_svg(7, None).contains(">no data</text>"));
+        assert_eq!(
+            badge_markdown("shop-orders"),
+            "![Uptime 7d](/badges/shop-orders?window=7d) \
+             ![Uptime 30d](/badges/shop-orders?window=30d)"
+        );
+    }
+}
@@ -0,0 +1,249 @@
+//! Backstage catalog export.
+//!
+//! Renders every cached API as a Backstage `API` entity (`spec.type: openapi`) so a
+//! Backstage instance can register `/backstage/catalog-info.yaml` as a URL location and
+//! keep its API catalog in sync with the cluster.
+
+use openapi_common::{API_DOC_LIFECYCLE_ANNOTATION, Lifecycle, OpenApiDocument};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::CachedApiEntry;
+
+/// Environment variables used to fill in the fields Backstage requires
+pub const BACKSTAGE_OWNER_ENV: &str = "BACKSTAGE_OWNER";
+pub const BACKSTAGE_LIFECYCLE_ENV: &str = "BACKSTAGE_LIFECYCLE";
+pub const BACKSTAGE_SYSTEM_ENV: &str = "BACKSTAGE_SYSTEM";
+
+/// Backstage limits entity names and tags to 63 characters
+const MAX_NAME_LENGTH: usize = 63;
+
+/// Values applied to every generated entity
+#[derive(Debug, Clone)]
+pub struct BackstageConfig {
+    pub owner: String,
+    pub lifecycle: String,
+    pub system: Option<String>,
+}
+
+impl BackstageConfig {
+    pub fn from_env() -> Self {
+        Self {
+            owner: std::env::var(BACKSTAGE_OWNER_ENV).unwrap_or_else(|_| "unknown".to_string()),
+            lifecycle: std::env::var(BACKSTAGE_LIFECYCLE_ENV)
+                .unwrap_or_else(|_| "production".to_string()),
+            system: std::env::var(BACKSTAGE_SYSTEM_ENV).ok(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Entity {
+    api_version: &'static str,
+    kind: &'static str,
+    metadata: EntityMetadata,
+    spec: ApiSpec,
+}
+
+#[derive(Serialize)]
+struct EntityMetadata {
+    name: String,
+    title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    annotations: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ApiSpec {
+    #[serde(rename = "type")]
+    api_type: &'static str,
+    lifecycle: String,
+    owner: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    definition: String,
+}
+
+/// Lowercases and replaces characters Backstage rejects in names and tags
+fn backstage_name(value: &str) -> String {
+    let name: String = value
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' | '-' => c,
+            _ => '-',
+        })
+        .take(MAX_NAME_LENGTH)
+        .collect();
+    name.trim_matches('-').to_string()
+}
+
+/// Backstage's conventional lifecycle values for a declared stage
+fn backstage_lifecycle(lifecycle: Lifecycle) -> &'static str {
+    match lifecycle {
+        Lifecycle::Alpha | Lifecycle::Beta => "experimental",
+        Lifecycle::Ga => "production",
+        Lifecycle::Deprecated | Lifecycle::Retired => "deprecated",
+    }
+}
+
+fn entity(api: &CachedApiEntry, config: &BackstageConfig) -> Entity {
+    let document = OpenApiDocument::parse(&api.spec).ok();
+    let mut tags: Vec<String> = document
+        .as_ref()
+        .map(|d| d.tag_names().iter().map(|t| backstage_name(t)).collect())
+        .unwrap_or_default();
+    tags.retain(|t| !t.is_empty());
+    // Distinct tags can share a name once normalized
+    tags.sort();
+    tags.dedup();
+
+    let mut annotations = BTreeMap::from([
+        (
+            "backstage.io/kubernetes-namespace".to_string(),
+            api.namespace.clone(),
+        ),
+        (
+            "backstage.io/kubernetes-id".to_string(),
+            api.service_name.clone(),
+        ),
+        ("api-doc.io/source-url".to_string(), api.url.clone()),
+    ]);
+    if let Some(lifecycle) = api.lifecycle {
+        annotations.insert(
+            API_DOC_LIFECYCLE_ANNOTATION.to_string(),
+            lifecycle.to_string(),
+        );
+    }
+
+    Entity {
+        api_version: "backstage.io/v1alpha1",
+        kind: "API",
+        metadata: EntityMetadata {
+            name: backstage_name(&api.id),
+            title: api.name.clone(),
+            description: api
+                .description
+                .clone()
+                .or_else(|| document.as_ref().and_then(|d| d.info.description.clone())),
+            annotations,
+            tags,
+        },
+        spec: ApiSpec {
+            api_type: "openapi",
+            lifecycle: api
+                .lifecycle
+                .map(|stage| backstage_lifecycle(stage).to_string())
+                .unwrap_or_else(|| config.lifecycle.clone()),
+            owner: config.owner.clone(),
+            system: config.system.clone(),
+            definition: api.spec.clone(),
+        },
+    }
+}
+
+/// Renders all entries as a multi-document `catalog-info.yaml`
+pub fn render_catalog_info(
+    apis: &[CachedApiEntry],
+    config: &BackstageConfig,
+) -> Result<String, serde_yaml::Error> {
+    let mut documents = Vec::with_capacity(apis.len());
+    for api in apis {
+        documents.push(serde_yaml::to_string(&entity(api, config))?);
+    }
+    Ok(documents.join("---\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_yaml::Value;
+
+    const SPEC: &str = r#"{
+        "openapi": "3.0.0",
+        "info": { "title": "Orders", "version": "1.0.0", "description": "Orders of the shop" },
+        "tags": [{ "name": "Order Items" }, { "name": "order_items" }, { "name": "Payments" }],
+        "paths": {}
+    }"#;
+
+    fn config() -> BackstageConfig {
+        BackstageConfig {
+            owner: "team-shop".to_string(),
+            lifecycle: "production".to_string(),
+            system: Some("retail".to_string()),
+        }
+    }
+
+    fn documents(rendered: &str) -> Vec<Value> {
+        rendered
+            .split("---\n")
+            .map(|document| serde_yaml::from_str(document).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn every_api_becomes_an_api_entity() {
+        let orders = CachedApiEntry::example("shop", "orders", SPEC);
+        let mut users = CachedApiEntry::example("accounts", "users", "openapi: 3.1.0");
+        users.description = Some("Accounts of customers".to_string());
+        users.lifecycle = Some(Lifecycle::Deprecated);
+
+        let rendered = render_catalog_info(&[orders, users], &config()).unwrap();
+        let documents = documents(&rendered);
+        assert_eq!(documents.len(), 2);
+
+        let orders = &documents[0];
+        assert_eq!(orders["apiVersion"], "backstage.io/v1alpha1");
+        assert_eq!(orders["kind"], "API");
+        assert_eq!(orders["metadata"]["name"], "shop-orders");
+        assert_eq!(orders["metadata"]["title"], "orders API");
+        assert_eq!(orders["metadata"]["description"], "Orders of the shop");
+        assert_eq!(
+            orders["metadata"]["annotations"]["backstage.io/kubernetes-namespace"],
+            "shop"
+        );
+        assert_eq!(
+            orders["metadata"]["annotations"]["api-doc.io/source-url"],
+            "http://orders.shop.svc.cluster.local:8080/openapi.json"
+        );
+        assert_eq!(
+            orders["metadata"]["tags"],
+            serde_yaml::to_value(["order-items", "payments"]).unwrap()
+        );
+        assert_eq!(orders["spec"]["type"], "openapi");
+        assert_eq!(orders["spec"]["lifecycle"], "production");
+        assert_eq!(orders["spec"]["owner"], "team-shop");
+        assert_eq!(orders["spec"]["system"], "retail");
+        assert_eq!(orders["spec"]["definition"], SPEC);
+
+        // The declared lifecycle and description take precedence
+        let users = &documents[1];
+        assert_eq!(users["metadata"]["name"], "accounts-users");
+        assert_eq!(users["metadata"]["description"], "Accounts of customers");
+        assert_eq!(
+            users["metadata"]["annotations"][API_DOC_LIFECYCLE_ANNOTATION],
+            "deprecated"
+        );
+        assert_eq!(users["metadata"].get("tags"), None);
+        assert_eq!(users["spec"]["lifecycle"], "deprecated");
+    }
+
+    #[test]
+    fn names_are_valid_backstage_names() {
+        assert_eq!(backstage_name("Shop.Orders_v2"), "shop-orders-v2");
+        assert_eq!(backstage_name("--orders--"), "orders");
+        assert_eq!(backstage_name(&"a".repeat(80)).len(), MAX_NAME_LENGTH);
+        assert_eq!(backstage_lifecycle(Lifecycle::Alpha), "experimental");
+        assert_eq!(backstage_lifecycle(Lifecycle::Ga), "production");
+        assert_eq!(backstage_lifecycle(Lifecycle::Retired), "deprecated");
+    }
+
+    #[test]
+    fn an_empty_catalog_renders_no_documents() {
+        assert_eq!(render_catalog_info(&[], &config()).unwrap(), "");
+    }
+}
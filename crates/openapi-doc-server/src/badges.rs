@@ -0,0 +1,64 @@
+//! Flat, shields.io-style SVG badges shown above specs in the frontends.
+//!
+//! Uptime badges are rendered by [`crate::availability`]; lifecycle badges show the stage
+//! a service declares with `api-doc.io/lifecycle`.
+
+use openapi_common::Lifecycle;
+
+/// SVG badge such as `lifecycle | beta`
+pub fn flat_badge(label: &str, value: &str, color: &str) -> String {
+    // Approximate Verdana 11px text width
+    let width = |text: &str| text.len() as u32 * 7 + 10;
+    let (label_width, value_width) = (width(label), width(value));
+    let total = label_width + value_width;
+
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{total}" height="20" role="img" aria-label="{label}: {value}"><title>{label}: {value}</title><rect width="{label_width}" height="20" fill="#555"/><rect x="{label_width}" width="{value_width}" height="20" fill="{color}"/><g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11"><text x="{label_x}" y="14">{label}</text><text x="{value_x}" y="14">{value}</text></g></svg>"##,
+        label_x = label_width / 2,
+        value_x = label_width + value_width / 2,
+    )
+}
+
+pub fn lifecycle_badge_svg(lifecycle: Option<Lifecycle>) -> String {
+    let (value, color) = match lifecycle {
+        Some(Lifecycle::Alpha) => ("alpha", "#fe7d37"),
+        Some(Lifecycle::Beta) => ("beta", "#dfb317"),
+        Some(Lifecycle::Ga) => ("ga", "#4c1"),
+        Some(Lifecycle::Deprecated) => ("deprecated", "#e05d44"),
+        Some(Lifecycle::Retired) => ("retired", "#555"),
+        None => ("unknown", "#9f9f9f"),
+    };
+    flat_badge("lifecycle", value, color)
+}
+
+/// Markdown prepended to `info.description` for APIs that declare a stage
+pub fn lifecycle_badge_markdown(slug: &str, lifecycle: Lifecycle) -> String {
+    format!("![Lifecycle: {}](/badges/{}/lifecycle)", lifecycle, slug)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn badges_are_sized_to_their_text() {
+        let svg = flat_badge("lifecycle", "beta", "#dfb317");
+        // 9 and 4 characters of 7px plus 10px padding each
+        assert!(svg.contains(r#"width="111" height="20""#), "{}", svg);
+        assert!(svg.contains(r##"<rect x="73" width="38" height="20" fill="#dfb317"/>"##));
+        assert!(svg.contains(r#"aria-label="lifecycle: beta""#));
+        assert!(svg.contains(r#"<text x="36" y="14">lifecycle</text>"#));
+        assert!(svg.contains(r#"<text x="92" y="14">beta</text>"#));
+    }
+
+    #[test]
+    fn lifecycle_badges_show_the_declared_stage() {
+        assert!(lifecycle_badge_svg(Some(Lifecycle::Ga)).contains(">ga</text>"));
+        assert!(lifecycle_badge_svg(Some(Lifecycle::Deprecated)).contains("#e05d44"));
+        assert!(lifecycle_badge_svg(None).contains(">unknown</text>"));
+        assert_eq!(
+            lifecycle_badge_markdown("shop-orders", Lifecycle::Beta),
+            "![Lifecycle: beta](/badges/shop-orders/lifecycle)"
+        );
+    }
+}
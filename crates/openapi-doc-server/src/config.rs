@@ -7,7 +7,8 @@ use std::collections::HashMap;
 /// - `ENABLED_FRONTENDS`: Comma-separated list of frontends to enable (e.g., "scalar,redoc")
 /// - `DEFAULT_FRONTEND`: Default frontend to show at `/` (e.g., "scalar" or "redoc")
 /// - `CACHE_DIR`: Directory for caching API specs (default: "/tmp/openapi-cache")
-/// - `DISCOVERY_PATH`: Path to discovery.json file (default: "/etc/config/discovery.json")
+/// - `DISCOVERY_PATH`: Path to the discovery payload (default: "/etc/config/" followed by the
+///   file name for `DISCOVERY_ENCODING`, e.g. "/etc/config/discovery.json")
 /// 
 /// Frontend-specific options use prefixes:
 /// - Scalar: `SCALAR_*`
@@ -0,0 +1,271 @@
+//! Catalog source for `DISCOVERY_SOURCE=crd`.
+//!
+//! Watches `DiscoveredApi` resources in all namespaces instead of reading the mounted
+//! `discovery.json`, and takes spec bodies from the ConfigMaps the operator stores them
+//! in, so specs are not fetched from the services again. Signed specs come with the
+//! detached signature stored in the same ConfigMap.
+
+use futures::StreamExt;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{
+    Client,
+    api::Api,
+    runtime::{WatchStreamExt, reflector, reflector::Store, watcher},
+};
+use openapi_common::crd::{DiscoveredApi, SPEC_CONFIGMAP_KEY, SPEC_SIGNATURE_CONFIGMAP_KEY};
+use openapi_common::signing::SpecSignature;
+
+use crate::ServerApiInventoryEntry;
+
+pub const DISCOVERY_SOURCE_ENV: &str = "DISCOVERY_SOURCE";
+
+/// Spec body stored with a `DiscoveredApi`
+pub struct StoredSpec {
+    pub body: String,
+    /// Detached signature, `None` when the operator does not sign specs
+    pub signature: Option<SpecSignature>,
+}
+
+#[derive(Clone)]
+pub struct CrdSource {
+    client: Client,
+    store: Store<DiscoveredApi>,
+}
+
+impl CrdSource {
+    /// Starts watching `DiscoveredApi` resources when `DISCOVERY_SOURCE=crd`
+    pub async fn from_env() -> Result<Option<Self>, Box<dyn std::error::Error + Send + Sync>> {
+        match std::env::var(DISCOVERY_SOURCE_ENV)
+            .unwrap_or_default()
+            .trim()
+        {
+            "" | "file" => return Ok(None),
+            "crd" => {}
+            other => {
+                return Err(format!(
+                    "Invalid {}: '{}', expected 'file' or 'crd'",
+                    DISCOVERY_SOURCE_ENV, other
+                )
+                .into());
+            }
+        }
+
+        let client = Client::try_default().await?;
+        let (store, writer) = reflector::store();
+        let stream = watcher::watcher(
+            Api::<DiscoveredApi>::all(client.clone()),
+            watcher::Config::default(),
+        )
+        .default_backoff()
+        .reflect(writer)
+        .applied_objects();
+        tokio::spawn(async move {
+            let mut stream = std::pin::pin!(stream);
+            while let Some(event) = stream.next().await {
+                if let Err(e) = event {
+                    tracing::warn!("DiscoveredApi watcher error: {}", e);
+                }
+            }
+        });
+
+        tracing::info!("Reading the catalog from DiscoveredApi resources");
+        Ok(Some(Self { client, store }))
+    }
+
+    /// Current entries, with the spec body from their ConfigMap when it could be read
+    pub async fn entries(&self) -> Vec<(ServerApiInventoryEntry, Option<StoredSpec>)> {
+        let mut entries = Vec::new();
+        for resource in self.store.state() {
+            let entry = resource.to_entry();
+            let spec = match &resource.spec.spec_config_map {
+                Some(name) => read_stored_spec(&self.client, &entry.namespace, name).await,
+                None => None,
+            };
+            entries.push((
+                ServerApiInventoryEntry {
+                    id: entry.id,
+                    name: entry.name,
+                    namespace: entry.namespace,
+                    service_name: entry.service_name,
+                    url: entry.url,
+                    description: entry.description,
+                    last_updated: entry.last_updated.to_rfc3339(),
+                    available: entry.available,
+                    last_error: entry.last_error,
+                    consecutive_failures: entry.consecutive_failures,
+                    tenant: entry.tenant,
+                    cluster: entry.cluster,
+                    contract: entry.contract,
+                    lifecycle: entry.lifecycle,
+                    ui: entry.ui,
+                    refresh_interval: entry.refresh_interval,
+                    refresh_window: entry.refresh_window,
+                    stale_by_schedule: entry.stale_by_schedule,
+                    api_group: entry.api_group,
+                    insecure_skip_verify: entry.insecure_skip_verify,
+                    spec_config_map: None,
+                    spec_url: None,
+                    last_change_summary: entry.last_change_summary,
+                },
+                spec,
+            ));
+        }
+        entries
+    }
+}
+
+/// Spec body and signature stored in a ConfigMap by the operator
+pub async fn read_stored_spec(client: &Client, namespace: &str, name: &str) -> Option<StoredSpec> {
+    let configmaps: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
+    match configmaps.get_opt(name).await {
+        Ok(configmap) => {
+            let mut data = configmap.and_then(|cm| cm.data)?;
+            let body = data.remove(SPEC_CONFIGMAP_KEY)?;
+            let signature = data
+                .remove(SPEC_SIGNATURE_CONFIGMAP_KEY)
+                .and_then(|content| {
+                    SpecSignature::from_json(&content)
+                        .inspect_err(|e| {
+                            tracing::warn!(
+                                "Ignoring malformed signature in ConfigMap {}/{}: {}",
+                                namespace,
+                                name,
+                                e
+                            )
+                        })
+                        .ok()
+                });
+            Some(StoredSpec { body, signature })
+        }
+        Err(e) => {
+            tracing::warn!(
+                "Failed to read spec ConfigMap {}/{}: {}",
+                namespace,
+                name,
+                e
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::FakeApiServer;
+    use k8s_openapi::api::core::v1::ConfigMap;
+    use openapi_common::ApiInventoryEntry;
+    use openapi_common::crd::{DiscoveredApiSpec, DiscoveredApiStatus};
+    use std::collections::BTreeMap;
+
+    const SPEC: &str = "{\"openapi\":\"3.0.0\",\"info\":{\"title\":\"Orders\",\"version\":\"1\"}}";
+
+    fn signature() -> SpecSignature {
+        SpecSignature {
+            algorithm: "ed25519".to_string(),
+            key_id: "0123456789abcdef".to_string(),
+            sha256: "ab12".to_string(),
+            signature: "c2lnbmF0dXJl".to_string(),
+        }
+    }
+
+    fn resource(service: &str, spec_config_map: Option<&str>) -> DiscoveredApi {
+        let entry = ApiInventoryEntry::new("shop", service, "http://shop.svc/openapi.json");
+        let mut resource = DiscoveredApi::new(
+            service,
+            DiscoveredApiSpec {
+                spec_config_map: spec_config_map.map(str::to_string),
+                ..DiscoveredApiSpec::from(&entry)
+            },
+        );
+        resource.metadata.namespace = Some("shop".to_string());
+        resource.status = Some(DiscoveredApiStatus {
+            available: true,
+            ..Default::default()
+        });
+        resource
+    }
+
+    fn spec_configmap(data: &[(&str, &str)]) -> ConfigMap {
+        ConfigMap {
+            data: Some(
+                data.iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect::<BTreeMap<_, _>>(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn entries_come_with_the_spec_stored_for_them() {
+        let api = FakeApiServer::start().await;
+        api.insert(
+            "/api/v1/namespaces/shop/configmaps/orders-openapi-spec",
+            spec_configmap(&[
+                (SPEC_CONFIGMAP_KEY, SPEC),
+                (SPEC_SIGNATURE_CONFIGMAP_KEY, &signature().to_json()),
+            ]),
+        );
+        let (store, mut writer) = reflector::store::<DiscoveredApi>();
+        for resource in [
+            resource("orders", Some("orders-openapi-spec")),
+            resource("carts", Some("carts-openapi-spec")),
+            resource("legacy", None),
+        ] {
+            writer.apply_watcher_event(&watcher::Event::Apply(resource));
+        }
+        let source = CrdSource {
+            client: api.client(),
+            store,
+        };
+
+        let mut entries = source.entries().await;
+        entries.sort_by(|a, b| a.0.id.cmp(&b.0.id));
+
+        let ids: Vec<&str> = entries.iter().map(|(entry, _)| entry.id.as_str()).collect();
+        assert_eq!(ids, ["shop.carts", "shop.legacy", "shop.orders"]);
+        let (orders, spec) = &entries[2];
+        assert!(orders.available);
+        assert_eq!(orders.spec_config_map, None);
+        let spec = spec.as_ref().unwrap();
+        assert_eq!(spec.body, SPEC);
+        assert_eq!(
+            spec.signature.as_ref().map(|s| s.key_id.as_str()),
+            Some("0123456789abcdef")
+        );
+        // A missing ConfigMap leaves the spec to be fetched from the service
+        assert!(entries[0].1.is_none());
+        assert!(entries[1].1.is_none());
+        let requests = api.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|request| request.method == "GET"));
+    }
+
+    #[tokio::test]
+    async fn malformed_signatures_are_ignored() {
+        let api = FakeApiServer::start().await;
+        api.insert(
+            "/api/v1/namespaces/shop/configmaps/orders-openapi-spec",
+            spec_configmap(&[
+                (SPEC_CONFIGMAP_KEY, SPEC),
+                (SPEC_SIGNATURE_CONFIGMAP_KEY, "not a signature"),
+            ]),
+        );
+        api.insert(
+            "/api/v1/namespaces/shop/configmaps/empty-openapi-spec",
+            spec_configmap(&[]),
+        );
+
+        let stored = read_stored_spec(&api.client(), "shop", "orders-openapi-spec")
+            .await
+            .unwrap();
+        assert_eq!(stored.body, SPEC);
+        assert!(stored.signature.is_none());
+        assert!(
+            read_stored_spec(&api.client(), "shop", "empty-openapi-spec")
+                .await
+                .is_none()
+        );
+    }
+}
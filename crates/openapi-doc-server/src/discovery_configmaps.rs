@@ -0,0 +1,282 @@
+//! ConfigMaps the mounted `discovery.json` refers to.
+//!
+//! When the catalog does not fit in one ConfigMap, the operator spreads its entries over
+//! `<configmap>-0..n` and `discovery.json` only lists them in `shards`. With
+//! `SPEC_STORAGE=configmap` on the operator, entries name a `spec_config_map` holding the
+//! spec they were stored with, which is served instead of fetching it from the service.
+//! Both are read through the Kubernetes API from `DISCOVERY_NAMESPACE`; the client is only
+//! created once such a reference is seen, so a plain file works without a cluster. With
+//! `DISCOVERY_STORAGE=secret` the shards are Secrets, like the mounted catalog.
+
+use k8s_openapi::api::core::v1::{ConfigMap, Secret};
+use kube::{Client, api::Api};
+use openapi_common::catalog::configmap_payload;
+use openapi_common::encoding::{self, DiscoveryEncoding, DiscoveryStorage, EncodingError};
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+
+use crate::ServerDiscoveryConfig;
+use crate::crd_source::{self, StoredSpec};
+
+#[derive(Clone)]
+pub struct DiscoveryConfigMaps {
+    namespace: String,
+    storage: DiscoveryStorage,
+    client: Arc<OnceCell<Client>>,
+}
+
+impl DiscoveryConfigMaps {
+    pub fn from_env() -> Result<Self, EncodingError> {
+        Ok(Self {
+            namespace: std::env::var(openapi_common::DISCOVERY_NAMESPACE_ENV)
+                .unwrap_or_else(|_| "default".to_string()),
+            storage: DiscoveryStorage::from_env()?,
+            client: Arc::new(OnceCell::new()),
+        })
+    }
+
+    async fn client(&self) -> Result<&Client, kube::Error> {
+        self.client.get_or_try_init(Client::try_default).await
+    }
+
+    /// Adds the entries of all shards listed in `config` to its `apis`
+    pub async fn read_shards(
+        &self,
+        config: &mut ServerDiscoveryConfig,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if config.shards.is_empty() {
+            return Ok(());
+        }
+        let client = self.client().await?.clone();
+        let kind = self.storage.kind();
+        for name in &config.shards {
+            let payload = match self.storage {
+                DiscoveryStorage::ConfigMap => {
+                    let configmaps: Api<ConfigMap> =
+                        Api::namespaced(client.clone(), &self.namespace);
+                    configmaps
+                        .get_opt(name)
+                        .await?
+                        .map(|c| configmap_payload(&c))
+                }
+                DiscoveryStorage::Secret => {
+                    let secrets: Api<Secret> = Api::namespaced(client.clone(), &self.namespace);
+                    secrets.get_opt(name).await?.map(|s| secret_payload(&s))
+                }
+            };
+            let Some(payload) = payload else {
+                tracing::warn!("Discovery {} shard '{}' is missing", kind, name);
+                continue;
+            };
+            let Some(payload) = payload else {
+                tracing::warn!("Discovery {} shard '{}' has no payload", kind, name);
+                continue;
+            };
+            let shard: ServerDiscoveryConfig = encoding::decode(&payload)?;
+            // A shard being rewritten may briefly hold an entry of another one
+            for api in shard.apis {
+                if !config.apis.iter().any(|existing| existing.id == api.id) {
+                    config.apis.push(api);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Spec stored in the named ConfigMap, `None` if it cannot be read
+    pub async fn stored_spec(&self, name: &str) -> Option<StoredSpec> {
+        let client = self
+            .client()
+            .await
+            .inspect_err(|e| tracing::warn!("No Kubernetes client to read '{}': {}", name, e))
+            .ok()?;
+        crd_source::read_stored_spec(client, &self.namespace, name).await
+    }
+}
+
+/// Payload of a Secret, where every encoding is stored under `data`
+fn secret_payload(secret: &Secret) -> Option<Vec<u8>> {
+    let data = secret.data.as_ref()?;
+    DiscoveryEncoding::ALL
+        .iter()
+        .find_map(|encoding| data.get(encoding.key()).map(|b| b.0.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::FakeApiServer;
+    use k8s_openapi::ByteString;
+    use openapi_common::crd::SPEC_CONFIGMAP_KEY;
+    use openapi_common::{ApiInventoryEntry, DiscoveryConfig};
+    use std::collections::BTreeMap;
+
+    const NAMESPACE: &str = "api-docs";
+
+    fn configmaps(api: &FakeApiServer, storage: DiscoveryStorage) -> DiscoveryConfigMaps {
+        DiscoveryConfigMaps {
+            namespace: NAMESPACE.to_string(),
+            storage,
+            client: Arc::new(OnceCell::from(api.client())),
+        }
+    }
+
+    fn catalog(services: &[&str], shards: &[&str]) -> DiscoveryConfig {
+        DiscoveryConfig {
+            apis: services
+                .iter()
+                .map(|service| {
+                    ApiInventoryEntry::new(
+                        "shop",
+                        service,
+                        format!("http://{}.shop.svc/openapi.json", service),
+                    )
+                })
+                .collect(),
+            last_updated: chrono::Utc::now(),
+            tombstones: Vec::new(),
+            shards: shards.iter().map(|shard| shard.to_string()).collect(),
+        }
+    }
+
+    /// The mounted catalog listing `shards`
+    fn mounted(services: &[&str], shards: &[&str]) -> ServerDiscoveryConfig {
+        let payload = DiscoveryEncoding::Json
+            .encode(&catalog(services, shards))
+            .unwrap();
+        encoding::decode(&payload).unwrap()
+    }
+
+    fn ids(config: &ServerDiscoveryConfig) -> Vec<&str> {
+        config.apis.iter().map(|api| api.id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn entries_of_configmap_shards_are_added_once() {
+        let api = FakeApiServer::start().await;
+        let json = DiscoveryEncoding::Json;
+        api.insert(
+            &format!("/api/v1/namespaces/{}/configmaps/catalog-0", NAMESPACE),
+            ConfigMap {
+                data: Some(BTreeMap::from([(
+                    json.key().to_string(),
+                    String::from_utf8(json.encode(&catalog(&["carts", "orders"], &[])).unwrap())
+                        .unwrap(),
+                )])),
+                ..Default::default()
+            },
+        );
+        let gzip = DiscoveryEncoding::Gzip;
+        api.insert(
+            &format!("/api/v1/namespaces/{}/configmaps/catalog-1", NAMESPACE),
+            ConfigMap {
+                binary_data: Some(BTreeMap::from([(
+                    gzip.key().to_string(),
+                    ByteString(gzip.encode(&catalog(&["payments"], &[])).unwrap()),
+                )])),
+                ..Default::default()
+            },
+        );
+        api.insert(
+            &format!("/api/v1/namespaces/{}/configmaps/catalog-2", NAMESPACE),
+            ConfigMap::default(),
+        );
+        let mut config = mounted(
+            &["orders"],
+            &["catalog-0", "catalog-1", "catalog-2", "gone"],
+        );
+
+        configmaps(&api, DiscoveryStorage::ConfigMap)
+            .read_shards(&mut config)
+            .await
+            .unwrap();
+        assert_eq!(ids(&config), ["shop.orders", "shop.carts", "shop.payments"]);
+        let paths: Vec<String> = api
+            .requests()
+            .iter()
+            .map(|r| r.path().to_string())
+            .collect();
+        assert_eq!(
+            paths,
+            ["catalog-0", "catalog-1", "catalog-2", "gone"]
+                .map(|name| format!("/api/v1/namespaces/{}/configmaps/{}", NAMESPACE, name))
+        );
+    }
+
+    #[tokio::test]
+    async fn shards_are_read_from_secrets_with_secret_storage() {
+        let api = FakeApiServer::start().await;
+        let json = DiscoveryEncoding::Json;
+        api.insert(
+            &format!("/api/v1/namespaces/{}/secrets/catalog-0", NAMESPACE),
+            Secret {
+                data: Some(BTreeMap::from([(
+                    json.key().to_string(),
+                    ByteString(json.encode(&catalog(&["carts"], &[])).unwrap()),
+                )])),
+                ..Default::default()
+            },
+        );
+        let mut config = mounted(&["orders"], &["catalog-0"]);
+
+        configmaps(&api, DiscoveryStorage::Secret)
+            .read_shards(&mut config)
+            .await
+            .unwrap();
+        assert_eq!(ids(&config), ["shop.orders", "shop.carts"]);
+    }
+
+    #[tokio::test]
+    async fn undecodable_shards_are_errors() {
+        let api = FakeApiServer::start().await;
+        api.insert(
+            &format!("/api/v1/namespaces/{}/configmaps/catalog-0", NAMESPACE),
+            ConfigMap {
+                data: Some(BTreeMap::from([(
+                    DiscoveryEncoding::Json.key().to_string(),
+                    "not json".to_string(),
+                )])),
+                ..Default::default()
+            },
+        );
+        let mut config = mounted(&["orders"], &["catalog-0"]);
+
+        let configmaps = configmaps(&api, DiscoveryStorage::ConfigMap);
+        assert!(configmaps.read_shards(&mut config).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn catalogs_without_references_need_no_client() {
+        let configmaps = DiscoveryConfigMaps {
+            namespace: NAMESPACE.to_string(),
+            storage: DiscoveryStorage::ConfigMap,
+            client: Arc::new(OnceCell::new()),
+        };
+        let mut config = mounted(&["orders"], &[]);
+
+        configmaps.read_shards(&mut config).await.unwrap();
+        assert_eq!(ids(&config), ["shop.orders"]);
+        assert!(configmaps.client.get().is_none());
+    }
+
+    #[tokio::test]
+    async fn stored_specs_are_read_from_the_discovery_namespace() {
+        let api = FakeApiServer::start().await;
+        api.insert(
+            &format!("/api/v1/namespaces/{}/configmaps/orders-spec", NAMESPACE),
+            ConfigMap {
+                data: Some(BTreeMap::from([(
+                    SPEC_CONFIGMAP_KEY.to_string(),
+                    "openapi: 3.1.0".to_string(),
+                )])),
+                ..Default::default()
+            },
+        );
+        let configmaps = configmaps(&api, DiscoveryStorage::ConfigMap);
+
+        let stored = configmaps.stored_spec("orders-spec").await.unwrap();
+        assert_eq!(stored.body, "openapi: 3.1.0");
+        assert!(stored.signature.is_none());
+        assert!(configmaps.stored_spec("carts-spec").await.is_none());
+    }
+}
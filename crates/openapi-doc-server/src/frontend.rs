@@ -1,7 +1,7 @@
 /// Trait for different API documentation frontends
 pub trait DocFrontend: Send + Sync {
     /// Generate HTML for the documentation page with multiple APIs
-    fn generate_html(&self, apis: &[ApiInfo]) -> String;
+    fn generate_html(&self, apis: &[ApiInfo], branding: &Branding) -> String;
     
     /// Generate HTML for empty state (no APIs found)
     fn generate_empty_html(&self) -> String;
@@ -15,6 +15,18 @@ pub struct ApiInfo {
     pub spec_url: String,
     #[allow(dead_code)] // May be used by frontends in the future
     pub description: Option<String>,
+    /// Options declared with `api-doc.io/ui.*`, taking precedence over the frontend's
+    /// configuration and the tenant's branding
+    pub ui: Option<openapi_common::UiOptions>,
+}
+
+/// Per-tenant overrides of the page appearance
+#[derive(Debug, Clone, Default)]
+pub struct Branding {
+    /// Page title
+    pub title: Option<String>,
+    /// Theme, for frontends that support themes
+    pub theme: Option<String>,
 }
 
 /// Available frontend types
@@ -81,5 +93,63 @@ impl FrontendType {
             _ => None,
         }
     }
+
+    /// URL fragment of an operation in this frontend: `tag/<tag>/<METHOD><path>` in Scalar,
+    /// `operation/<operationId>` in Redoc, which has no stable anchor for operations
+    /// without an id
+    pub fn operation_anchor(
+        &self,
+        path: &str,
+        method: &str,
+        operation: &openapi_common::openapi::Operation,
+    ) -> Option<String> {
+        match self {
+            FrontendType::Scalar => Some(format!(
+                "{}/{}{}",
+                self.tag_anchor(
+                    operation
+                        .tags
+                        .first()
+                        .map(String::as_str)
+                        .unwrap_or(SCALAR_DEFAULT_TAG)
+                ),
+                method.to_ascii_uppercase(),
+                encode_fragment(path)
+            )),
+            FrontendType::Redoc => operation
+                .operation_id
+                .as_deref()
+                .map(|id| format!("operation/{}", encode_fragment(id))),
+        }
+    }
+
+    /// URL fragment of a tag's section in this frontend
+    pub fn tag_anchor(&self, tag: &str) -> String {
+        match self {
+            // Lowercase, words joined by `-`
+            FrontendType::Scalar => format!(
+                "tag/{}",
+                encode_fragment(
+                    &tag.split_whitespace()
+                        .collect::<Vec<_>>()
+                        .join("-")
+                        .to_lowercase()
+                )
+            ),
+            FrontendType::Redoc => format!("tag/{}", encode_fragment(tag)),
+        }
+    }
+}
+
+/// Operations without tags are listed under this tag by Scalar
+const SCALAR_DEFAULT_TAG: &str = "default";
+
+/// Percent-encodes everything but unreserved characters and `/`
+fn encode_fragment(value: &str) -> String {
+    value
+        .split('/')
+        .map(|segment| urlencoding::encode(segment).into_owned())
+        .collect::<Vec<_>>()
+        .join("/")
 }
 
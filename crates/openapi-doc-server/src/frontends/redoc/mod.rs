@@ -1,11 +1,13 @@
 use crate::config::RedocConfig;
-use crate::frontend::{ApiInfo, DocFrontend};
+use crate::frontend::{ApiInfo, Branding, DocFrontend};
 use askama::Template;
+use serde_json::{Map, Value};
 
 /// Template for Redoc main page with API selector
 #[derive(Template)]
 #[template(path = "redoc/main.html")]
 struct RedocMainTemplate {
+    title: String,
     apis: Vec<RedocApiInfo>,
     has_multiple_apis: bool,
     show_api_selector: bool,
@@ -22,7 +24,10 @@ struct RedocEmptyTemplate;
 pub struct RedocApiInfo {
     pub name: String,
     pub slug: String,
+    /// Percent-encoded, so it is written into the page's script unescaped
     pub spec_url: String,
+    /// Options from `api-doc.io/ui.redoc.*` as a JSON object, merged over the configured ones
+    pub options: String,
 }
 
 impl From<&ApiInfo> for RedocApiInfo {
@@ -31,10 +36,27 @@ impl From<&ApiInfo> for RedocApiInfo {
             name: api.name.clone(),
             slug: api.slug.clone(),
             spec_url: api.spec_url.clone(),
+            options: redoc_options(api),
         }
     }
 }
 
+/// Annotation values that are not JSON (`purple`) are passed as strings. `<` is escaped so
+/// a value cannot close the page's script element.
+fn redoc_options(api: &ApiInfo) -> String {
+    let options: Map<String, Value> = api
+        .ui
+        .iter()
+        .flat_map(|ui| &ui.redoc)
+        .map(|(name, value)| {
+            let value =
+                serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.clone()));
+            (name.clone(), value)
+        })
+        .collect();
+    Value::Object(options).to_string().replace('<', "\\u003c")
+}
+
 pub struct RedocFrontend {
     config: RedocConfig,
 }
@@ -46,13 +68,17 @@ impl RedocFrontend {
 }
 
 impl DocFrontend for RedocFrontend {
-    fn generate_html(&self, apis: &[ApiInfo]) -> String {
+    fn generate_html(&self, apis: &[ApiInfo], branding: &Branding) -> String {
         if apis.is_empty() {
             return self.generate_empty_html();
         }
 
         let redoc_apis: Vec<RedocApiInfo> = apis.iter().map(RedocApiInfo::from).collect();
         let template = RedocMainTemplate {
+            title: branding
+                .title
+                .clone()
+                .unwrap_or_else(|| "API Documentation".to_string()),
             apis: redoc_apis,
             has_multiple_apis: apis.len() > 1,
             show_api_selector: self.config.show_api_selector && apis.len() > 1,
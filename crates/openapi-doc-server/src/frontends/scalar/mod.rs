@@ -1,5 +1,5 @@
 use crate::config::ScalarConfig;
-use crate::frontend::{ApiInfo, DocFrontend};
+use crate::frontend::{ApiInfo, Branding, DocFrontend};
 use scalar_api_reference::scalar_html_default;
 use serde_json::json;
 
@@ -14,27 +14,32 @@ impl ScalarFrontend {
 }
 
 impl DocFrontend for ScalarFrontend {
-    fn generate_html(&self, apis: &[ApiInfo]) -> String {
+    fn generate_html(&self, apis: &[ApiInfo], branding: &Branding) -> String {
         if apis.is_empty() {
             return self.generate_empty_html();
         }
 
+        let theme = branding.theme.as_ref().unwrap_or(&self.config.theme);
         let mut configurations = Vec::new();
 
         for (i, api) in apis.iter().enumerate() {
-            let config = json!({
+            let ui = api.ui.clone().unwrap_or_default();
+            let mut config = json!({
                 "title": api.name.clone(),
                 "slug": api.slug.clone(),
                 "url": api.spec_url.clone(),
-                "theme": self.config.theme,
-                "layout": self.config.layout,
-                "darkMode": self.config.dark_mode,
+                "theme": ui.theme.as_ref().unwrap_or(theme),
+                "layout": ui.layout.as_ref().unwrap_or(&self.config.layout),
+                "darkMode": ui.dark_mode.unwrap_or(self.config.dark_mode),
                 "showSidebar": self.config.show_sidebar,
                 "hideDownloadButton": self.config.hide_download_button,
                 "expandAllResponses": self.config.expand_all_responses,
                 "expandAllModelSections": self.config.expand_all_model_sections,
                 "default": i == 0
             });
+            if let Some(title) = &branding.title {
+                config["metaData"] = json!({ "title": title });
+            }
 
             configurations.push(config);
         }
@@ -0,0 +1,297 @@
+//! HTTP server tuning.
+//!
+//! Connections are served by hyper directly instead of `axum::serve`, so the portal can be
+//! hardened before it is exposed beyond a trusted network: HTTP/2 can be turned off, idle
+//! connections are closed, open connections are capped and request headers are limited.
+//!
+//! - `HTTP2_ENABLED`: Accept HTTP/2 (prior knowledge) next to HTTP/1.1 (default: `true`)
+//! - `HTTP_KEEP_ALIVE`: Keep HTTP/1.1 connections open between requests (default: `true`)
+//! - `HTTP_KEEP_ALIVE_TIMEOUT`: Seconds a client has to send the headers of a request,
+//!   which also closes kept-alive connections idle for that long (default: `30`)
+//! - `HTTP2_KEEP_ALIVE_INTERVAL`: Seconds between HTTP/2 keep-alive pings; connections
+//!   that do not answer are closed (default: unset, no pings)
+//! - `HTTP_MAX_CONNECTIONS`: Open connections at most; further clients wait until one is
+//!   closed (default: `0`, unlimited)
+//! - `HTTP_MAX_HEADERS`: Headers per HTTP/1.1 request (default: `100`)
+//! - `HTTP_MAX_HEADER_BYTES`: Size of the request headers, at least 8192 (default: unset,
+//!   hyper's limits)
+
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::Semaphore;
+
+pub const HTTP2_ENABLED_ENV: &str = "HTTP2_ENABLED";
+pub const HTTP_KEEP_ALIVE_ENV: &str = "HTTP_KEEP_ALIVE";
+pub const HTTP_KEEP_ALIVE_TIMEOUT_ENV: &str = "HTTP_KEEP_ALIVE_TIMEOUT";
+pub const HTTP2_KEEP_ALIVE_INTERVAL_ENV: &str = "HTTP2_KEEP_ALIVE_INTERVAL";
+pub const HTTP_MAX_CONNECTIONS_ENV: &str = "HTTP_MAX_CONNECTIONS";
+pub const HTTP_MAX_HEADERS_ENV: &str = "HTTP_MAX_HEADERS";
+pub const HTTP_MAX_HEADER_BYTES_ENV: &str = "HTTP_MAX_HEADER_BYTES";
+
+const DEFAULT_KEEP_ALIVE_TIMEOUT: u64 = 30;
+const DEFAULT_MAX_HEADERS: usize = 100;
+/// hyper cannot buffer less than this
+const MIN_HEADER_BYTES: usize = 8192;
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub http2: bool,
+    pub keep_alive: bool,
+    pub keep_alive_timeout: Duration,
+    pub http2_keep_alive_interval: Option<Duration>,
+    pub max_connections: Option<usize>,
+    pub max_headers: usize,
+    pub max_header_bytes: Option<usize>,
+}
+
+impl ServerConfig {
+    pub fn from_env() -> Result<Self, String> {
+        let max_header_bytes = parse_env::<usize>(HTTP_MAX_HEADER_BYTES_ENV)?;
+        if let Some(bytes) = max_header_bytes
+            && bytes < MIN_HEADER_BYTES
+        {
+            return Err(format!(
+                "Invalid {}: {}, must be at least {}",
+                HTTP_MAX_HEADER_BYTES_ENV, bytes, MIN_HEADER_BYTES
+            ));
+        }
+
+        Ok(Self {
+            http2: parse_env(HTTP2_ENABLED_ENV)?.unwrap_or(true),
+            keep_alive: parse_env(HTTP_KEEP_ALIVE_ENV)?.unwrap_or(true),
+            keep_alive_timeout: Duration::from_secs(
+                parse_env(HTTP_KEEP_ALIVE_TIMEOUT_ENV)?
+                    .filter(|secs| *secs > 0)
+                    .unwrap_or(DEFAULT_KEEP_ALIVE_TIMEOUT),
+            ),
+            http2_keep_alive_interval: parse_env(HTTP2_KEEP_ALIVE_INTERVAL_ENV)?
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+            max_connections: parse_env(HTTP_MAX_CONNECTIONS_ENV)?.filter(|max| *max > 0),
+            max_headers: parse_env(HTTP_MAX_HEADERS_ENV)?.unwrap_or(DEFAULT_MAX_HEADERS),
+            max_header_bytes,
+        })
+    }
+
+    fn builder(&self) -> auto::Builder<TokioExecutor> {
+        let mut builder = auto::Builder::new(TokioExecutor::new());
+        builder
+            .http1()
+            .timer(TokioTimer::new())
+            .keep_alive(self.keep_alive)
+            .header_read_timeout(self.keep_alive_timeout)
+            .max_headers(self.max_headers);
+        builder
+            .http2()
+            .timer(TokioTimer::new())
+            .keep_alive_interval(self.http2_keep_alive_interval);
+        if let Some(bytes) = self.max_header_bytes {
+            builder.http1().max_buf_size(bytes);
+            builder
+                .http2()
+                .max_header_list_size(u32::try_from(bytes).unwrap_or(u32::MAX));
+        }
+
+        if self.http2 {
+            builder
+        } else {
+            builder.http1_only()
+        }
+    }
+}
+
+/// Parsed value of an environment variable, `None` when unset or empty
+fn parse_env<T: std::str::FromStr>(name: &str) -> Result<Option<T>, String> {
+    match std::env::var(name) {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .to_ascii_lowercase()
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("Invalid {}: '{}'", name, value)),
+        _ => Ok(None),
+    }
+}
+
+/// Serves `app` on `listener` with the configured limits
+pub async fn serve(listener: TcpListener, app: Router, config: &ServerConfig) {
+    let builder = Arc::new(config.builder());
+    let connections = config
+        .max_connections
+        .map(|max| Arc::new(Semaphore::new(max)));
+
+    loop {
+        // Waiting for a free slot before accepting leaves further clients in the backlog
+        let permit = match &connections {
+            Some(connections) => match connections.clone().acquire_owned().await {
+                Ok(permit) => Some(permit),
+                Err(_) => return,
+            },
+            None => None,
+        };
+        let (stream, peer) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                // E.g. out of file descriptors, which does not resolve immediately
+                tracing::warn!("Failed to accept connection: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+
+        let builder = builder.clone();
+        let service = TowerToHyperService::new(app.clone());
+        let first_byte_timeout = config.keep_alive_timeout;
+        tokio::spawn(async move {
+            // hyper only starts the header timeout once the protocol is detected, so a
+            // client that never sends anything would keep its slot forever
+            let mut first_byte = [0u8; 1];
+            if !matches!(
+                tokio::time::timeout(first_byte_timeout, stream.peek(&mut first_byte)).await,
+                Ok(Ok(1))
+            ) {
+                tracing::debug!("Closing connection from {} without a request", peer);
+                return;
+            }
+            // Not `serve_connection_with_upgrades`, which ignores `http1_only`; the portal
+            // serves no upgrades
+            if let Err(e) = builder
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::debug!("Connection from {} failed: {}", peer, e);
+            }
+            drop(permit);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    const REQUEST: &[u8] = b"GET / HTTP/1.1\r\nHost: portal\r\n\r\n";
+
+    fn config() -> ServerConfig {
+        ServerConfig {
+            http2: true,
+            keep_alive: true,
+            keep_alive_timeout: Duration::from_secs(5),
+            http2_keep_alive_interval: None,
+            max_connections: None,
+            max_headers: DEFAULT_MAX_HEADERS,
+            max_header_bytes: None,
+        }
+    }
+
+    /// Address of a server answering every request with `ok`
+    async fn start(config: ServerConfig) -> String {
+        let app = Router::new().fallback(|| async { "ok" });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move { serve(listener, app, &config).await });
+        address
+    }
+
+    /// What the server sends within a second, empty when it closes the connection first
+    async fn read_response(stream: &mut TcpStream) -> Option<String> {
+        let mut buffer = vec![0u8; 4096];
+        let read = tokio::time::timeout(Duration::from_secs(1), stream.read(&mut buffer))
+            .await
+            .ok()?
+            .unwrap_or(0);
+        Some(String::from_utf8_lossy(&buffer[..read]).into_owned())
+    }
+
+    #[tokio::test]
+    async fn http2_is_served_unless_disabled() {
+        let http2 = reqwest::Client::builder()
+            .http2_prior_knowledge()
+            .build()
+            .unwrap();
+
+        let address = start(config()).await;
+        let response = http2
+            .get(format!("http://{}/", address))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.version(), reqwest::Version::HTTP_2);
+        assert_eq!(response.text().await.unwrap(), "ok");
+
+        let address = start(ServerConfig {
+            http2: false,
+            ..config()
+        })
+        .await;
+        assert!(
+            http2
+                .get(format!("http://{}/", address))
+                .send()
+                .await
+                .is_err()
+        );
+        let response = reqwest::get(format!("http://{}/", address)).await.unwrap();
+        assert_eq!(response.version(), reqwest::Version::HTTP_11);
+    }
+
+    #[tokio::test]
+    async fn requests_with_too_many_headers_are_rejected() {
+        let address = start(ServerConfig {
+            max_headers: 4,
+            ..config()
+        })
+        .await;
+
+        let mut stream = TcpStream::connect(&address).await.unwrap();
+        let headers: String = (0..8)
+            .map(|i| format!("X-Header-{}: {}\r\n", i, i))
+            .collect();
+        let request = format!("GET / HTTP/1.1\r\nHost: portal\r\n{}\r\n", headers);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let response = read_response(&mut stream).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 431"), "{}", response);
+    }
+
+    #[tokio::test]
+    async fn connections_without_a_request_are_closed() {
+        let address = start(ServerConfig {
+            keep_alive_timeout: Duration::from_millis(100),
+            ..config()
+        })
+        .await;
+
+        let mut stream = TcpStream::connect(&address).await.unwrap();
+        assert_eq!(read_response(&mut stream).await.as_deref(), Some(""));
+    }
+
+    #[tokio::test]
+    async fn clients_beyond_the_connection_limit_wait_for_a_free_slot() {
+        let address = start(ServerConfig {
+            max_connections: Some(1),
+            ..config()
+        })
+        .await;
+
+        let mut first = TcpStream::connect(&address).await.unwrap();
+        first.write_all(REQUEST).await.unwrap();
+        let response = read_response(&mut first).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+
+        // The first connection is kept alive, so the second one is not served yet
+        let mut second = TcpStream::connect(&address).await.unwrap();
+        second.write_all(REQUEST).await.unwrap();
+        assert_eq!(read_response(&mut second).await, None);
+
+        drop(first);
+        let response = read_response(&mut second).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    }
+}
@@ -1,25 +1,61 @@
+mod analytics;
+mod api_catalog;
+mod api_groups;
+mod apis_json;
+mod availability;
+mod backstage;
+mod badges;
 mod config;
+mod crd_source;
+mod discovery_configmaps;
 mod frontend;
 mod frontends;
+mod http_server;
+mod sitemap;
+mod spec_bucket;
+mod subscriptions;
+mod suggest;
+mod tenancy;
+#[cfg(test)]
+mod test_support;
 
 use axum::{
     Router,
-    extract::{Path, State},
-    http::StatusCode,
-    response::{Html, Json},
-    routing::get,
+    extract::{Path, Query, RawQuery, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{Html, IntoResponse, Json, Redirect, Response},
+    routing::{delete, get},
 };
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path as StdPath, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use tower::ServiceBuilder;
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
 
-use openapi_common::spec_utils;
+use openapi_common::complexity::SpecMetrics;
+use openapi_common::contract::ContractReport;
+use openapi_common::diff::{ChangeSummary, diff_documents};
+use openapi_common::encoding::{self, DiscoveryEncoding};
+use openapi_common::fetch::{
+    CacheValidators, FailurePhase, FetchConfig, FetchFailure, FetchOutcome, FetchRequest,
+    FetchedSpec, SpecFetcher, TlsOptions,
+};
+use openapi_common::s3::SpecBucket;
+use openapi_common::signing::{
+    SPEC_VERIFY_KEY_FILE_ENV, SigningError, SpecSignature, SpecVerifier,
+};
+use openapi_common::slugs;
+use openapi_common::{
+    ApiTombstone, Lifecycle, OpenApiDocument, UiOptions, schedule::RefreshWindow, spec_utils,
+};
 use serde::{Deserialize, Serialize};
 
+use analytics::{Access, UsageAnalytics};
 use frontend::{ApiInfo, DocFrontend};
+use subscriptions::{ApiEvent, NewSubscription, Notification, Subscription, SubscriptionStore};
+use tenancy::Caller;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 struct ServerApiInventoryEntry {
@@ -31,17 +67,68 @@ struct ServerApiInventoryEntry {
     description: Option<String>,
     last_updated: String,
     available: bool,
+    /// Why the operator's last fetch failed
+    #[serde(default)]
+    last_error: Option<String>,
+    /// Fetches of the operator that failed in a row
+    #[serde(default)]
+    consecutive_failures: u32,
+    #[serde(default)]
+    tenant: Option<String>,
+    /// Cluster the operator federated the entry from, which keeps its namespace there
+    #[serde(default)]
+    cluster: Option<String>,
+    #[serde(default)]
+    contract: Option<ContractReport>,
+    #[serde(default)]
+    lifecycle: Option<Lifecycle>,
+    #[serde(default)]
+    ui: Option<UiOptions>,
+    /// Seconds between fetches requested by the service, at least the server's interval
+    #[serde(default)]
+    refresh_interval: Option<u64>,
+    /// Cron-like window the spec may be fetched in, see [`RefreshWindow`]
+    #[serde(default)]
+    refresh_window: Option<String>,
+    /// The operator keeps the stored spec while the refresh window is closed
+    #[serde(default)]
+    stale_by_schedule: bool,
+    /// Logical API this entry is a version of, see [`api_groups`]
+    #[serde(default)]
+    api_group: Option<String>,
+    /// Fetch the spec without verifying the server certificate
+    #[serde(default)]
+    insecure_skip_verify: bool,
+    /// ConfigMap in the discovery namespace holding the stored spec, see
+    /// [`discovery_configmaps`]
+    #[serde(default)]
+    spec_config_map: Option<String>,
+    /// `s3://` URL of the stored spec, see [`spec_bucket`]
+    #[serde(default)]
+    spec_url: Option<String>,
+    /// What the operator saw change with the last stored revision
+    #[serde(default)]
+    last_change_summary: Option<ChangeSummary>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 struct ServerDiscoveryConfig {
     apis: Vec<ServerApiInventoryEntry>,
     last_updated: String,
+    #[serde(default)]
+    tombstones: Vec<ApiTombstone>,
+    /// ConfigMaps holding the entries of a sharded catalog, see [`discovery_configmaps`]
+    #[serde(default)]
+    shards: Vec<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 struct CachedApiEntry {
     id: String,
+    /// Canonical name in URLs and cache files, see [`slugs`]; empty in caches written
+    /// before slugs
+    #[serde(default)]
+    slug: String,
     name: String,
     namespace: String,
     service_name: String,
@@ -50,6 +137,88 @@ struct CachedApiEntry {
     last_updated: String,
     available: bool,
     spec: String,
+    #[serde(default)]
+    validators: CacheValidators,
+    #[serde(default)]
+    tenant: Option<String>,
+    #[serde(default)]
+    contract: Option<ContractReport>,
+    #[serde(default)]
+    lifecycle: Option<Lifecycle>,
+    #[serde(default)]
+    ui: Option<UiOptions>,
+    /// Why the last fetch failed, for unavailable APIs
+    #[serde(default)]
+    failure: Option<FetchFailure>,
+    /// Why the operator's last fetch failed, see [`ServerApiInventoryEntry`]
+    #[serde(default)]
+    last_error: Option<String>,
+    #[serde(default)]
+    consecutive_failures: u32,
+    /// Unix seconds of the last fetch from the service
+    #[serde(default)]
+    fetched_at: u64,
+    #[serde(default)]
+    refresh_window: Option<String>,
+    /// The spec is kept from before the refresh window closed
+    #[serde(default)]
+    stale_by_schedule: bool,
+    #[serde(default)]
+    api_group: Option<String>,
+    /// `info.version` of the spec, labels the API among the versions of its group
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(default)]
+    last_change_summary: Option<ChangeSummary>,
+}
+
+#[cfg(test)]
+impl CachedApiEntry {
+    /// Available API of the Service `namespace/service_name` serving `spec`
+    fn example(namespace: &str, service_name: &str, spec: &str) -> Self {
+        CachedApiEntry {
+            id: openapi_common::entry_id(namespace, service_name),
+            slug: slugs::base_slug(namespace, service_name),
+            name: format!("{} API", service_name),
+            namespace: namespace.to_string(),
+            service_name: service_name.to_string(),
+            url: format!(
+                "http://{}.{}.svc.cluster.local:8080/openapi.json",
+                service_name, namespace
+            ),
+            description: None,
+            last_updated: "2026-01-01T00:00:00Z".to_string(),
+            available: true,
+            spec: spec.to_string(),
+            validators: CacheValidators::default(),
+            tenant: None,
+            contract: None,
+            lifecycle: None,
+            ui: None,
+            failure: None,
+            last_error: None,
+            consecutive_failures: 0,
+            fetched_at: 0,
+            refresh_window: None,
+            stale_by_schedule: false,
+            api_group: None,
+            version: None,
+            last_change_summary: None,
+        }
+    }
+}
+
+/// Description of the placeholder spec of an unavailable API, telling readers why the
+/// spec is missing without a look at the server logs
+fn unavailable_description(url: &str, failure: &FetchFailure) -> String {
+    let mut description = format!(
+        "API documentation not available.\n\nFetching `{}` failed in the **{}** phase: {}",
+        url, failure.phase, failure.message
+    );
+    if let Some(snippet) = &failure.snippet {
+        description.push_str(&format!("\n\nResponse:\n\n````\n{}\n````", snippet));
+    }
+    description
 }
 
 /// Frontend manager that holds configured frontend instances
@@ -60,6 +229,7 @@ struct FrontendManager {
 }
 
 impl FrontendManager {
+    #[allow(clippy::collapsible_if)]
     fn from_config(config: &config::FrontendConfig) -> Self {
         use frontend::FrontendType;
         let mut frontends = HashMap::new();
@@ -130,6 +300,14 @@ impl FrontendManager {
         self.frontends.get(name).cloned()
     }
 
+    /// Type of the default frontend, Scalar when there is none
+    fn default_frontend_type(&self) -> frontend::FrontendType {
+        self.default_frontend
+            .as_deref()
+            .and_then(frontend::FrontendType::from_str)
+            .unwrap_or(frontend::FrontendType::Scalar)
+    }
+
     fn get_default_frontend(&self) -> Option<Arc<dyn DocFrontend>> {
         self.default_frontend
             .as_ref()
@@ -142,47 +320,83 @@ struct AppState {
     cache_dir: PathBuf,
     discovery_path: PathBuf,
     frontend_manager: FrontendManager,
+    fetcher: SpecFetcher,
+    /// Same settings, for APIs whose certificate is not verified
+    insecure_fetcher: SpecFetcher,
+    backstage: backstage::BackstageConfig,
+    tenancy: tenancy::TenancyConfig,
+    /// Set when the catalog is read from DiscoveredApi resources instead of discovery.json
+    crd_source: Option<crd_source::CrdSource>,
+    /// Reads the shards and spec ConfigMaps discovery.json refers to
+    discovery_configmaps: discovery_configmaps::DiscoveryConfigMaps,
+    /// Bucket of specs stored with SPEC_STORAGE=s3, when SPEC_STORAGE_S3_BUCKET is set
+    spec_bucket: Option<Arc<SpecBucket>>,
+    /// Whether specs rendered in the UI get uptime badges
+    slo_badges: bool,
+    /// Specs from DiscoveredApi resources are only served when their signature verifies
+    verifier: Option<SpecVerifier>,
+    /// Consumers notified when the APIs they use change
+    subscriptions: SubscriptionStore,
+    /// View and download counters, when `ANALYTICS=true`
+    analytics: Option<UsageAnalytics>,
+    /// Externally visible base URL for absolute links, e.g. in the API catalog
+    public_url: Option<String>,
+    /// How often the catalog is re-read and specs are fetched
+    refresh_interval: Duration,
+    /// When specs may be fetched (REFRESH_WINDOW), unless an API sets its own window
+    refresh_window: Option<RefreshWindow>,
 }
 
-// Default values for cache directory and discovery path
+// Default values for cache directory and the directory the discovery ConfigMap is mounted at
 const DEFAULT_CACHE_DIR: &str = "/tmp/openapi-cache";
-const DEFAULT_DISCOVERY_PATH: &str = "/etc/config/discovery.json";
+/// Seconds between refreshes, see `REFRESH_INTERVAL`
+const DEFAULT_REFRESH_INTERVAL: u64 = 30;
+const DEFAULT_DISCOVERY_DIR: &str = "/etc/config";
 
-fn sanitize_filename(name: &str) -> String {
-    name.chars()
-        .map(|c| match c {
-            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
-            _ => '_',
-        })
-        .collect()
+fn get_spec_file_path(cache_dir: &StdPath, slug: &str) -> PathBuf {
+    cache_dir.join(format!("{slug}.json"))
+}
+
+fn get_metadata_file_path(cache_dir: &StdPath, slug: &str) -> PathBuf {
+    cache_dir.join(format!("{slug}.meta.json"))
 }
 
-fn get_spec_file_path(cache_dir: &StdPath, api_name: &str) -> PathBuf {
-    let sanitized = sanitize_filename(api_name);
-    cache_dir.join(format!("{sanitized}.json"))
+fn get_availability_file_path(cache_dir: &StdPath, slug: &str) -> PathBuf {
+    cache_dir.join(format!("{slug}.availability.json"))
 }
 
-fn get_metadata_file_path(cache_dir: &StdPath, api_name: &str) -> PathBuf {
-    let sanitized = sanitize_filename(api_name);
-    cache_dir.join(format!("{sanitized}.meta.json"))
+fn get_signature_file_path(cache_dir: &StdPath, slug: &str) -> PathBuf {
+    cache_dir.join(format!("{slug}.sig.json"))
+}
+
+/// Removes every cache file of an API
+fn remove_cache_files(cache_dir: &StdPath, file_name: &str) {
+    let _ = fs::remove_file(get_metadata_file_path(cache_dir, file_name));
+    let _ = fs::remove_file(get_spec_file_path(cache_dir, file_name));
+    let _ = fs::remove_file(get_availability_file_path(cache_dir, file_name));
+    let _ = fs::remove_file(get_signature_file_path(cache_dir, file_name));
+}
+
+/// Recently removed APIs; the leading dot cannot clash with a slug
+fn get_tombstones_file_path(cache_dir: &StdPath) -> PathBuf {
+    cache_dir.join(".tombstones.json")
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .init();
+    openapi_common::logging::init(std::io::stdout);
 
     // Get cache directory from environment or use default
     let cache_dir = std::env::var("CACHE_DIR")
         .map(PathBuf::from)
         .unwrap_or_else(|_| PathBuf::from(DEFAULT_CACHE_DIR));
 
-    // Get discovery path from environment or use default
-    let discovery_path = std::env::var("DISCOVERY_PATH")
-        .map(PathBuf::from)
-        .unwrap_or_else(|_| PathBuf::from(DEFAULT_DISCOVERY_PATH));
+    // Get discovery path from environment or derive it from the payload encoding
+    let discovery_path = match std::env::var("DISCOVERY_PATH") {
+        Ok(path) => PathBuf::from(path),
+        Err(_) => StdPath::new(DEFAULT_DISCOVERY_DIR).join(DiscoveryEncoding::from_env()?.key()),
+    };
 
     // Ensure cache directory exists
     fs::create_dir_all(&cache_dir)?;
@@ -193,22 +407,92 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let frontend_config = config::FrontendConfig::from_env();
     let frontend_manager = FrontendManager::from_config(&frontend_config);
 
+    // Only specs stored by the operator carry signatures
+    let crd_source = crd_source::CrdSource::from_env().await?;
+    let verifier = SpecVerifier::from_env()?;
+    if let Some(verifier) = &verifier {
+        if crd_source.is_none() {
+            return Err(format!(
+                "{} requires {}=crd, specs fetched from services are not signed",
+                SPEC_VERIFY_KEY_FILE_ENV,
+                crd_source::DISCOVERY_SOURCE_ENV
+            )
+            .into());
+        }
+        tracing::info!("Verifying spec signatures with key {}", verifier.key_id());
+    }
+
+    let refresh_window = RefreshWindow::from_env()?;
+    if let Some(window) = &refresh_window {
+        tracing::info!(
+            "Fetching specs only within refresh window '{}' (UTC)",
+            window
+        );
+    }
+
+    let server_config = http_server::ServerConfig::from_env()?;
+    tracing::info!("HTTP server settings: {:?}", server_config);
+
+    let fetcher = SpecFetcher::new(FetchConfig::from_env()?)?;
+
+    // Subscriptions registered before slugs are moved to the slug of their API's name
+    let slugs_by_name: HashMap<String, String> = load_apis_from_cache(&cache_dir)
+        .await
+        .into_iter()
+        .map(|api| (api.name, api.slug))
+        .collect();
+    let allowed_hosts = subscriptions::allowed_hosts_from_env();
+    if !allowed_hosts.is_empty() {
+        tracing::info!(
+            "Subscription webhooks may reach internal addresses of {:?}",
+            allowed_hosts
+        );
+    }
+
     // Create application state
     let state = AppState {
         cache_dir: cache_dir.clone(),
         discovery_path: discovery_path.clone(),
         frontend_manager,
+        insecure_fetcher: fetcher.with_tls(TlsOptions {
+            insecure_skip_verify: true,
+            ..fetcher.config().tls.clone()
+        })?,
+        fetcher,
+        backstage: backstage::BackstageConfig::from_env(),
+        tenancy: tenancy::TenancyConfig::from_env(),
+        crd_source,
+        discovery_configmaps: discovery_configmaps::DiscoveryConfigMaps::from_env()?,
+        spec_bucket: SpecBucket::from_env()?.map(Arc::new),
+        slo_badges: availability::badges_enabled(),
+        verifier,
+        subscriptions: SubscriptionStore::load(&cache_dir, &slugs_by_name, allowed_hosts),
+        analytics: UsageAnalytics::from_env(&cache_dir),
+        public_url: api_catalog::public_url_from_env(),
+        refresh_interval: Duration::from_secs(
+            std::env::var("REFRESH_INTERVAL")
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .filter(|secs| *secs > 0)
+                .unwrap_or(DEFAULT_REFRESH_INTERVAL),
+        ),
+        refresh_window,
     };
 
     // Start background task to refresh API cache
     let state_clone = state.clone();
     tokio::spawn(async move {
-        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(30));
+        let mut interval = tokio::time::interval(state_clone.refresh_interval);
         loop {
             interval.tick().await;
             if let Err(e) = refresh_api_cache(&state_clone).await {
                 tracing::error!("Failed to refresh API cache: {}", e);
             }
+            if let Some(analytics) = &state_clone.analytics
+                && let Err(e) = analytics.save().await
+            {
+                tracing::warn!("Failed to store usage analytics: {}", e);
+            }
         }
     });
 
@@ -217,6 +501,22 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         .route("/", get(handle_default))
         .route("/api/{api_name}", get(handle_api_request))
         .route("/specs/{api_name}", get(handle_spec_request))
+        .route("/apis", get(handle_list_apis))
+        .route("/apis/suggest", get(handle_suggest))
+        .route(suggest::SCRIPT_PATH, get(handle_quick_switch_script))
+        .route("/slo", get(handle_slo))
+        .route("/stats", get(handle_stats))
+        .route("/badges/{api_name}", get(handle_badge))
+        .route("/badges/{api_name}/lifecycle", get(handle_lifecycle_badge))
+        .route("/signatures/{api_name}", get(handle_signature))
+        .route(
+            "/subscriptions",
+            get(handle_list_subscriptions).post(handle_create_subscription),
+        )
+        .route("/subscriptions/{id}", delete(handle_delete_subscription))
+        .route("/backstage/catalog-info.yaml", get(handle_backstage_catalog))
+        .route(api_catalog::API_CATALOG_PATH, get(handle_api_catalog))
+        .route("/apis.json", get(handle_apis_json))
         .route("/health", get(handle_health));
 
     // Add frontend-specific routes
@@ -228,6 +528,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         app = app.route("/redoc", get(handle_redoc));
     }
 
+    // Only published portals have a stable URL to list
+    if state.public_url.is_some() {
+        app = app.route(sitemap::SITEMAP_PATH, get(handle_sitemap));
+    }
+
+    if state.analytics.is_some() {
+        app = app
+            .route("/analytics", get(handle_analytics))
+            .route("/metrics", get(handle_metrics));
+    }
+
     let app = app
         .layer(
             ServiceBuilder::new()
@@ -240,14 +551,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await?;
     tracing::info!("Starting OpenAPI documentation server on port 8080");
 
-    axum::serve(listener, app).await?;
+    http_server::serve(listener, app, &server_config).await;
 
     Ok(())
 }
 
-async fn handle_default(State(state): State<AppState>) -> Result<Html<String>, StatusCode> {
+async fn handle_default(
+    Query(page): Query<PageQuery>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> Result<Html<String>, StatusCode> {
     match state.frontend_manager.get_default_frontend() {
-        Some(frontend) => generate_frontend_html(frontend, &state.cache_dir).await,
+        Some(frontend) => generate_frontend_html(frontend, &state, &caller, &page).await,
         None => {
             tracing::error!("No default frontend configured");
             render_error_template().await
@@ -269,9 +584,13 @@ async fn render_error_template() -> Result<Html<String>, StatusCode> {
     })
 }
 
-async fn handle_scalar(State(state): State<AppState>) -> Result<Html<String>, StatusCode> {
+async fn handle_scalar(
+    Query(page): Query<PageQuery>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> Result<Html<String>, StatusCode> {
     match state.frontend_manager.get_frontend("scalar") {
-        Some(frontend) => generate_frontend_html(frontend, &state.cache_dir).await,
+        Some(frontend) => generate_frontend_html(frontend, &state, &caller, &page).await,
         None => {
             tracing::warn!("Scalar frontend not available");
             Err(StatusCode::NOT_FOUND)
@@ -279,9 +598,13 @@ async fn handle_scalar(State(state): State<AppState>) -> Result<Html<String>, St
     }
 }
 
-async fn handle_redoc(State(state): State<AppState>) -> Result<Html<String>, StatusCode> {
+async fn handle_redoc(
+    Query(page): Query<PageQuery>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> Result<Html<String>, StatusCode> {
     match state.frontend_manager.get_frontend("redoc") {
-        Some(frontend) => generate_frontend_html(frontend, &state.cache_dir).await,
+        Some(frontend) => generate_frontend_html(frontend, &state, &caller, &page).await,
         None => {
             tracing::warn!("Redoc frontend not available");
             Err(StatusCode::NOT_FOUND)
@@ -289,76 +612,795 @@ async fn handle_redoc(State(state): State<AppState>) -> Result<Html<String>, Sta
     }
 }
 
+#[derive(Debug, Deserialize)]
+struct PageQuery {
+    /// Slug of the API to open, which also picks the version shown for its group
+    api: Option<String>,
+}
+
 async fn generate_frontend_html(
     frontend: Arc<dyn DocFrontend>,
-    cache_dir: &StdPath,
+    state: &AppState,
+    caller: &Caller,
+    page: &PageQuery,
 ) -> Result<Html<String>, StatusCode> {
-    // Load all API metadata from cache directory
-    let apis = load_apis_from_cache(cache_dir).await;
+    // Load the caller's API metadata from cache directory, one entry per API group
+    let apis = api_groups::collapse(
+        load_visible_apis(&state.cache_dir, caller).await,
+        page.api.as_deref(),
+    );
 
     tracing::info!("Found {} APIs for frontend", apis.len());
 
     // Convert to ApiInfo for frontend
     let api_infos: Vec<ApiInfo> = apis
         .iter()
-        .enumerate()
-        .map(|(i, api)| ApiInfo {
+        .map(|api| ApiInfo {
             name: api.name.clone(),
-            slug: format!("api-{i}"),
-            spec_url: format!("/specs/{}", urlencoding::encode(&api.name)),
+            slug: api.slug.clone(),
+            spec_url: spec_url(state, api),
             description: api.description.clone(),
+            ui: api.ui.clone(),
         })
         .collect();
 
-    let html = frontend.generate_html(&api_infos);
-    Ok(Html(html))
+    let html = frontend.generate_html(&api_infos, &state.tenancy.branding(caller));
+    Ok(Html(with_quick_switch(html)))
+}
+
+/// Adds the quick-switch overlay (Ctrl+K) to a portal page
+fn with_quick_switch(mut html: String) -> String {
+    let script = format!("<script src=\"{}\" defer></script>", suggest::SCRIPT_PATH);
+    match html.rfind("</body>") {
+        Some(end) => html.insert_str(end, &script),
+        None => html.push_str(&script),
+    }
+    html
+}
+
+/// URL the frontends load an API's spec from
+fn spec_url(state: &AppState, api: &CachedApiEntry) -> String {
+    let mut params = Vec::new();
+    if state.analytics.is_some() {
+        params.push("view=true");
+    }
+    if state.slo_badges
+        || api.lifecycle.is_some()
+        || api.stale_by_schedule
+        || api.api_group.is_some()
+    {
+        params.push("badges=true");
+    }
+    let url = format!("/specs/{}", api.slug);
+    if params.is_empty() {
+        url
+    } else {
+        format!("{}?{}", url, params.join("&"))
+    }
+}
+
+/// What the API segment of a request path refers to
+enum ApiLookup {
+    Found(Box<CachedApiEntry>),
+    /// An id or a display name (the URLs used before slugs), moved to this slug
+    Moved(String),
+    Missing,
+}
+
+/// Resolves a slug, id or display name to a cached API; other tenants' APIs are missing
+async fn lookup_api(cache_dir: &StdPath, caller: &Caller, key: &str) -> ApiLookup {
+    if slugs::is_slug(key)
+        && let Some(api) = load_cached_api(cache_dir, key)
+        && api.slug == key
+    {
+        return if caller.can_see(api.tenant.as_deref()) {
+            ApiLookup::Found(Box::new(api))
+        } else {
+            tracing::warn!("API {} is not visible to tenant {:?}", key, caller.tenant());
+            ApiLookup::Missing
+        };
+    }
+
+    // Display names are not unique; an ambiguous one has no canonical URL
+    let apis = load_visible_apis(cache_dir, caller).await;
+    let mut matches = apis.iter().filter(|api| api.id == key || api.name == key);
+    match (matches.next(), matches.next()) {
+        (Some(api), None) => ApiLookup::Moved(api.slug.clone()),
+        (Some(_), Some(_)) => {
+            tracing::warn!("API name '{}' is ambiguous, use the slug", key);
+            ApiLookup::Missing
+        }
+        _ => ApiLookup::Missing,
+    }
+}
+
+/// Permanent redirect from a legacy API URL to the same resource under the slug
+fn moved_to(path: String, query: Option<String>) -> Response {
+    let location = match query {
+        Some(query) => format!("{}?{}", path, query),
+        None => path,
+    };
+    Redirect::permanent(&location).into_response()
+}
+
+fn api_not_found() -> Response {
+    Json(serde_json::json!({
+        "error": "API not found"
+    }))
+    .into_response()
 }
 
 async fn handle_api_request(
-    Path(api_name): Path<String>,
+    Path(key): Path<String>,
+    RawQuery(query): RawQuery,
     State(state): State<AppState>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    // URL decode the API name
-    let decoded_name = urlencoding::decode(&api_name).unwrap_or_else(|_| api_name.clone().into());
-    let decoded_name_str = decoded_name.as_ref();
-
-    tracing::info!(
-        "Looking for API: '{}' (decoded: '{}')",
-        api_name,
-        decoded_name_str
-    );
-
-    // Load spec from file cache
-    let spec_path = get_spec_file_path(&state.cache_dir, decoded_name_str);
+    caller: Caller,
+) -> Response {
+    match lookup_api(&state.cache_dir, &caller, &key).await {
+        ApiLookup::Found(api) => serve_cached_spec(&api, &state, Access::Download)
+            .await
+            .into_response(),
+        ApiLookup::Moved(slug) => moved_to(format!("/api/{}", slug), query),
+        ApiLookup::Missing => api_not_found(),
+    }
+}
 
-    match fs::read_to_string(&spec_path) {
+/// Serves the cached spec of an API, counting the view or download for analytics
+async fn serve_cached_spec(
+    api: &CachedApiEntry,
+    state: &AppState,
+    access: Access,
+) -> Json<serde_json::Value> {
+    match fs::read_to_string(get_spec_file_path(&state.cache_dir, &api.slug)) {
         Ok(spec_content) => {
-            tracing::info!("Serving cached OpenAPI spec for API: {}", decoded_name);
+            tracing::info!("Serving cached OpenAPI spec for API: {}", api.slug);
+            if let Some(analytics) = &state.analytics {
+                analytics
+                    .record(&api.slug, access, availability::now_secs())
+                    .await;
+            }
             match spec_utils::parse_spec_to_json(&spec_content) {
-                Ok(spec) => Ok(Json(spec)),
+                Ok(spec) => Json(spec),
                 Err(e) => {
-                    tracing::warn!("Failed to parse spec for {}: {}", decoded_name, e);
-                    Ok(Json(serde_json::json!({
+                    tracing::warn!("Failed to parse spec for {}: {}", api.slug, e);
+                    Json(serde_json::json!({
                         "error": "Failed to parse API spec"
-                    })))
+                    }))
                 }
             }
         }
         Err(e) => {
-            tracing::warn!("API spec not found: {} (error: {})", decoded_name, e);
-            Ok(Json(serde_json::json!({
+            tracing::warn!("API spec not found: {} (error: {})", api.slug, e);
+            Json(serde_json::json!({
                 "error": "API not found"
-            })))
+            }))
         }
     }
 }
 
+/// Reads the cached metadata of an API
+fn load_cached_api(cache_dir: &StdPath, slug: &str) -> Option<CachedApiEntry> {
+    fs::read_to_string(get_metadata_file_path(cache_dir, slug))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+}
+
+#[derive(Debug, Deserialize)]
+struct SpecQuery {
+    /// Prepend badges and notes (lifecycle, uptime, other versions) to `info.description`,
+    /// used by the frontends
+    #[serde(default)]
+    badges: bool,
+    /// Counted as a documentation view rather than a download, used by the frontends
+    #[serde(default)]
+    view: bool,
+}
+
 async fn handle_spec_request(
-    Path(api_name): Path<String>,
+    Path(key): Path<String>,
+    Query(query): Query<SpecQuery>,
+    RawQuery(raw_query): RawQuery,
     State(state): State<AppState>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+    caller: Caller,
+) -> Response {
     // This is the same as handle_api_request, but provides a cleaner endpoint for specs
-    handle_api_request(Path(api_name), State(state)).await
+    let api = match lookup_api(&state.cache_dir, &caller, &key).await {
+        ApiLookup::Found(api) => api,
+        ApiLookup::Moved(slug) => return moved_to(format!("/specs/{}", slug), raw_query),
+        ApiLookup::Missing => return api_not_found(),
+    };
+    let access = if query.view {
+        Access::View
+    } else {
+        Access::Download
+    };
+    let Json(mut spec) = serve_cached_spec(&api, &state, access).await;
+
+    let mut badges = Vec::new();
+    if query.badges {
+        badges.extend(
+            api.lifecycle
+                .map(|stage| badges::lifecycle_badge_markdown(&api.slug, stage)),
+        );
+        if state.slo_badges {
+            badges.push(availability::badge_markdown(&api.slug));
+        }
+    }
+    let mut notes = vec![badges.join(" ")];
+    if query.badges && api.stale_by_schedule {
+        notes.push(stale_by_schedule_note(&api));
+    }
+    if query.badges
+        && let Some(group) = &api.api_group
+    {
+        let apis = load_visible_apis(&state.cache_dir, &caller).await;
+        notes.extend(api_groups::switcher_markdown(
+            &api,
+            &api_groups::versions(&apis, group),
+        ));
+    }
+    notes.retain(|note| !note.is_empty());
+    if !notes.is_empty()
+        && let Some(info) = spec.get_mut("info").and_then(|info| info.as_object_mut())
+    {
+        let notes = notes.join("\n\n");
+        let description = match info.get("description").and_then(|d| d.as_str()) {
+            Some(description) => format!("{}\n\n{}", notes, description),
+            None => notes,
+        };
+        info.insert(
+            "description".to_string(),
+            serde_json::Value::String(description),
+        );
+    }
+    Json(spec).into_response()
+}
+
+/// Markdown telling readers the spec may be outdated as its refresh window is closed
+fn stale_by_schedule_note(api: &CachedApiEntry) -> String {
+    format!(
+        "> **Stale by schedule:** this spec is only refreshed within `{}` (UTC) and may be \
+         outdated until then",
+        api.refresh_window.as_deref().unwrap_or_default()
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct CatalogQuery {
+    /// Comma-separated lifecycle stages, e.g. `beta,ga`
+    lifecycle: Option<String>,
+}
+
+impl CatalogQuery {
+    /// Drops APIs outside the requested stages; APIs without a stage only match no filter
+    fn apply(&self, apis: &mut Vec<CachedApiEntry>) -> Result<(), StatusCode> {
+        let Some(filter) = &self.lifecycle else {
+            return Ok(());
+        };
+        let stages = Lifecycle::parse_list(filter).map_err(|e| {
+            tracing::warn!("Rejected lifecycle filter: {}", e);
+            StatusCode::BAD_REQUEST
+        })?;
+        apis.retain(|api| api.lifecycle.is_some_and(|stage| stages.contains(&stage)));
+        Ok(())
+    }
+}
+
+/// Uptime of the caller's APIs over the last 7 and 30 days
+async fn handle_slo(
+    Query(query): Query<CatalogQuery>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let mut apis = load_visible_apis(&state.cache_dir, &caller).await;
+    query.apply(&mut apis)?;
+    apis.sort_by(|a, b| a.name.cmp(&b.name));
+    let now = availability::now_secs();
+
+    let apis: Vec<serde_json::Value> = apis
+        .into_iter()
+        .map(|api| {
+            let history = availability::AvailabilityHistory::load(&get_availability_file_path(
+                &state.cache_dir,
+                &api.slug,
+            ));
+            let mut report = serde_json::json!({
+                "id": api.id,
+                "slug": api.slug,
+                "name": api.name,
+                "namespace": api.namespace,
+                "available": api.available,
+                "lifecycle": api.lifecycle,
+            });
+            for days in availability::WINDOWS {
+                report[format!("uptime_{}d", days)] = serde_json::json!(history.uptime(days, now));
+            }
+            report["badge_url"] = serde_json::json!(format!("/badges/{}", api.slug));
+            report
+        })
+        .collect();
+
+    Ok(Json(serde_json::json!({ "apis": apis })))
+}
+
+/// Complexity metrics of the caller's available APIs, largest first, with catalog totals
+async fn handle_stats(
+    Query(query): Query<CatalogQuery>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let mut apis = load_visible_apis(&state.cache_dir, &caller).await;
+    query.apply(&mut apis)?;
+
+    let mut measured: Vec<(CachedApiEntry, SpecMetrics)> = apis
+        .into_iter()
+        .filter(|api| api.available)
+        .filter_map(|api| {
+            let metrics = SpecMetrics::compute(&api.spec)
+                .map_err(|e| tracing::warn!("Failed to measure spec of {}: {}", api.name, e))
+                .ok()?;
+            Some((api, metrics))
+        })
+        .collect();
+    measured.sort_by(|(a, a_metrics), (b, b_metrics)| {
+        b_metrics
+            .operations
+            .cmp(&a_metrics.operations)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+
+    let totals = serde_json::json!({
+        "apis": measured.len(),
+        "operations": measured.iter().map(|(_, m)| u64::from(m.operations)).sum::<u64>(),
+        "schemas": measured.iter().map(|(_, m)| u64::from(m.schemas)).sum::<u64>(),
+        "size_bytes": measured.iter().map(|(_, m)| m.size_bytes).sum::<u64>(),
+    });
+    let apis: Vec<serde_json::Value> = measured
+        .into_iter()
+        .map(|(api, metrics)| {
+            serde_json::json!({
+                "id": api.id,
+                "slug": api.slug,
+                "name": api.name,
+                "namespace": api.namespace,
+                "metrics": metrics,
+            })
+        })
+        .collect();
+
+    Ok(Json(serde_json::json!({ "totals": totals, "apis": apis })))
+}
+
+#[derive(Debug, Deserialize)]
+struct BadgeQuery {
+    /// `7d` or `30d` (default)
+    window: Option<String>,
+}
+
+/// SVG uptime badge for one API
+async fn handle_badge(
+    Path(key): Path<String>,
+    Query(query): Query<BadgeQuery>,
+    RawQuery(raw_query): RawQuery,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> Result<Response, StatusCode> {
+    let days = match query.window.as_deref().map(|w| w.trim_end_matches('d')) {
+        None => 30,
+        Some(window) => window
+            .parse::<u64>()
+            .ok()
+            .filter(|days| availability::WINDOWS.contains(days))
+            .ok_or(StatusCode::BAD_REQUEST)?,
+    };
+
+    let uptime = match lookup_api(&state.cache_dir, &caller, &key).await {
+        ApiLookup::Found(api) => availability::AvailabilityHistory::load(
+            &get_availability_file_path(&state.cache_dir, &api.slug),
+        )
+        .uptime(days, availability::now_secs()),
+        ApiLookup::Moved(slug) => return Ok(moved_to(format!("/badges/{}", slug), raw_query)),
+        ApiLookup::Missing => None,
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/svg+xml"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        availability::badge_svg(days, uptime),
+    )
+        .into_response())
+}
+
+/// SVG badge with the lifecycle stage of one API
+async fn handle_lifecycle_badge(
+    Path(key): Path<String>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> Response {
+    let lifecycle = match lookup_api(&state.cache_dir, &caller, &key).await {
+        ApiLookup::Found(api) => api.lifecycle,
+        ApiLookup::Moved(slug) => return moved_to(format!("/badges/{}/lifecycle", slug), None),
+        ApiLookup::Missing => None,
+    };
+
+    (
+        [
+            (header::CONTENT_TYPE, "image/svg+xml"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        badges::lifecycle_badge_svg(lifecycle),
+    )
+        .into_response()
+}
+
+/// Detached signature of a spec stored by the operator, for clients verifying `/specs`
+async fn handle_signature(
+    Path(key): Path<String>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> Response {
+    let api = match lookup_api(&state.cache_dir, &caller, &key).await {
+        ApiLookup::Found(api) => api,
+        ApiLookup::Moved(slug) => return moved_to(format!("/signatures/{}", slug), None),
+        ApiLookup::Missing => return StatusCode::NOT_FOUND.into_response(),
+    };
+    fs::read_to_string(get_signature_file_path(&state.cache_dir, &api.slug))
+        .ok()
+        .and_then(|content| SpecSignature::from_json(&content).ok())
+        .map(|signature| Json(signature).into_response())
+        .unwrap_or_else(|| StatusCode::NOT_FOUND.into_response())
+}
+
+/// Views and downloads of the caller's APIs, most viewed first
+async fn handle_analytics(
+    Query(query): Query<CatalogQuery>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let analytics = state.analytics.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    let mut apis = load_visible_apis(&state.cache_dir, &caller).await;
+    query.apply(&mut apis)?;
+    let now = availability::now_secs();
+
+    let mut reports = Vec::new();
+    for api in apis {
+        let report = analytics.report(&api.slug, now).await;
+        reports.push((api, report));
+    }
+    reports.sort_by(|(a, a_usage), (b, b_usage)| {
+        (b_usage.views_30d, b_usage.downloads_30d)
+            .cmp(&(a_usage.views_30d, a_usage.downloads_30d))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+
+    let apis: Vec<serde_json::Value> = reports
+        .into_iter()
+        .map(|(api, usage)| {
+            serde_json::json!({
+                "slug": api.slug,
+                "name": api.name,
+                "namespace": api.namespace,
+                "views": usage.views,
+                "downloads": usage.downloads,
+                "views_30d": usage.views_30d,
+                "downloads_30d": usage.downloads_30d,
+                "last_accessed": usage.last_accessed,
+            })
+        })
+        .collect();
+    Ok(Json(serde_json::json!({ "apis": apis })))
+}
+
+/// View and download counters of the caller's APIs in the Prometheus text format
+async fn handle_metrics(
+    State(state): State<AppState>,
+    caller: Caller,
+) -> Result<([(header::HeaderName, &'static str); 1], String), StatusCode> {
+    let analytics = state.analytics.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+    let mut apis = load_visible_apis(&state.cache_dir, &caller).await;
+    apis.sort_by(|a, b| a.name.cmp(&b.name));
+    let now = availability::now_secs();
+
+    let mut views = String::from(
+        "# HELP openapi_doc_views_total Documentation views per API\n\
+         # TYPE openapi_doc_views_total counter\n",
+    );
+    let mut downloads = String::from(
+        "# HELP openapi_doc_spec_downloads_total Spec downloads per API\n\
+         # TYPE openapi_doc_spec_downloads_total counter\n",
+    );
+    for api in apis {
+        let report = analytics.report(&api.slug, now).await;
+        let labels = format!(
+            "api=\"{}\",namespace=\"{}\"",
+            analytics::label_value(&api.name),
+            analytics::label_value(&api.namespace)
+        );
+        views.push_str(&format!(
+            "openapi_doc_views_total{{{}}} {}\n",
+            labels, report.views
+        ));
+        downloads.push_str(&format!(
+            "openapi_doc_spec_downloads_total{{{}}} {}\n",
+            labels, report.downloads
+        ));
+    }
+
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        views + &downloads,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct SubscriptionQuery {
+    /// Only subscriptions to this API
+    api: Option<String>,
+}
+
+/// Subscriptions registered by the caller's tenant
+async fn handle_list_subscriptions(
+    Query(query): Query<SubscriptionQuery>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> Json<serde_json::Value> {
+    let subscriptions: Vec<Subscription> = state
+        .subscriptions
+        .list()
+        .await
+        .into_iter()
+        .filter(|s| caller.owns(s.tenant.as_deref()))
+        .filter(|s| query.api.as_ref().is_none_or(|api| &s.api == api))
+        .collect();
+    Json(serde_json::json!({ "subscriptions": subscriptions }))
+}
+
+/// Registers the caller as a consumer of an API it can see
+async fn handle_create_subscription(
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(mut request): Json<NewSubscription>,
+) -> Result<(StatusCode, Json<Subscription>), (StatusCode, Json<serde_json::Value>)> {
+    let reject = |status: StatusCode, message: String| {
+        (status, Json(serde_json::json!({ "error": message })))
+    };
+    request
+        .validate(state.subscriptions.allowed_hosts())
+        .await
+        .map_err(|message| reject(StatusCode::BAD_REQUEST, message))?;
+    // Subscriptions refer to the slug, whichever way the API was named
+    request.api = match lookup_api(&state.cache_dir, &caller, &request.api).await {
+        ApiLookup::Found(api) => api.slug,
+        ApiLookup::Moved(slug) => slug,
+        ApiLookup::Missing => {
+            return Err(reject(
+                StatusCode::NOT_FOUND,
+                format!("API {} not found", request.api),
+            ));
+        }
+    };
+
+    let tenant = caller.tenant().map(str::to_string);
+    match state
+        .subscriptions
+        .add(request, tenant, availability::now_secs())
+        .await
+    {
+        Ok(subscription) => {
+            tracing::info!(
+                "{} subscribed to {} ({:?})",
+                subscription.consumer,
+                subscription.api,
+                subscription.events
+            );
+            Ok((StatusCode::CREATED, Json(subscription)))
+        }
+        Err(e) => {
+            tracing::error!("Failed to store subscription: {}", e);
+            Err(reject(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "failed to store subscription".to_string(),
+            ))
+        }
+    }
+}
+
+async fn handle_delete_subscription(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> StatusCode {
+    match state.subscriptions.get(&id).await {
+        Some(subscription) if caller.owns(subscription.tenant.as_deref()) => {
+            match state.subscriptions.remove(&id).await {
+                Ok(()) => StatusCode::NO_CONTENT,
+                Err(e) => {
+                    tracing::error!("Failed to remove subscription {}: {}", id, e);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+            }
+        }
+        _ => StatusCode::NOT_FOUND,
+    }
+}
+
+/// Lists cached APIs with their metadata and availability, without spec bodies
+async fn handle_list_apis(
+    Query(query): Query<CatalogQuery>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let mut apis = load_visible_apis(&state.cache_dir, &caller).await;
+    query.apply(&mut apis)?;
+    apis.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let apis: Vec<serde_json::Value> = apis
+        .into_iter()
+        .map(|api| {
+            serde_json::json!({
+                "id": api.id,
+                "slug": api.slug,
+                "name": api.name,
+                "namespace": api.namespace,
+                "service_name": api.service_name,
+                "url": api.url,
+                "description": api.description,
+                "last_updated": api.last_updated,
+                "available": api.available,
+                "tenant": api.tenant,
+                "contract": api.contract,
+                "lifecycle": api.lifecycle,
+                "failure": api.failure,
+                "last_error": api.last_error,
+                "consecutive_failures": api.consecutive_failures,
+                "refresh_window": api.refresh_window,
+                "stale_by_schedule": api.stale_by_schedule,
+                "api_group": api.api_group,
+                "version": api.version,
+                "last_change_summary": api.last_change_summary,
+                "spec_url": format!("/specs/{}", api.slug),
+            })
+        })
+        .collect();
+
+    // Recently removed APIs, so consumers can tell a deletion from a missing entry
+    let removed: Vec<ApiTombstone> = fs::read_to_string(get_tombstones_file_path(&state.cache_dir))
+        .ok()
+        .and_then(|content| serde_json::from_str::<Vec<ApiTombstone>>(&content).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|tombstone| caller.can_see(tombstone.tenant.as_deref()))
+        .collect();
+
+    Ok(Json(
+        serde_json::json!({ "apis": apis, "removed": removed }),
+    ))
+}
+
+/// Serves the caller's cached APIs as Backstage API entities
+async fn handle_backstage_catalog(
+    Query(query): Query<CatalogQuery>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> Result<([(header::HeaderName, &'static str); 1], String), StatusCode> {
+    let mut apis = load_visible_apis(&state.cache_dir, &caller).await;
+    query.apply(&mut apis)?;
+    apis.sort_by(|a, b| a.id.cmp(&b.id));
+
+    match backstage::render_catalog_info(&apis, &state.backstage) {
+        Ok(yaml) => Ok(([(header::CONTENT_TYPE, "application/yaml")], yaml)),
+        Err(e) => {
+            tracing::error!("Failed to render Backstage catalog: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// The caller's APIs as an RFC 9727 Linkset
+async fn handle_api_catalog(
+    Query(query): Query<CatalogQuery>,
+    State(state): State<AppState>,
+    caller: Caller,
+    headers: HeaderMap,
+) -> Result<([(header::HeaderName, String); 2], Json<serde_json::Value>), StatusCode> {
+    let mut apis = load_visible_apis(&state.cache_dir, &caller).await;
+    query.apply(&mut apis)?;
+    apis.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let base_url = api_catalog::base_url(state.public_url.as_deref(), &headers);
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                api_catalog::API_CATALOG_CONTENT_TYPE.to_string(),
+            ),
+            (header::LINK, api_catalog::link_header(&base_url)),
+        ],
+        Json(api_catalog::render_linkset(&apis, &base_url)),
+    ))
+}
+
+/// The caller's APIs in the APIs.json format
+async fn handle_apis_json(
+    Query(query): Query<CatalogQuery>,
+    State(state): State<AppState>,
+    caller: Caller,
+    headers: HeaderMap,
+) -> Result<([(header::HeaderName, &'static str); 1], String), StatusCode> {
+    let mut apis = load_visible_apis(&state.cache_dir, &caller).await;
+    query.apply(&mut apis)?;
+    apis.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let base_url = api_catalog::base_url(state.public_url.as_deref(), &headers);
+    let branding = state.tenancy.branding(&caller);
+    Ok((
+        [(header::CONTENT_TYPE, "application/json")],
+        apis_json::render_apis_json(&apis, branding.title.as_deref(), &base_url),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct SuggestQuery {
+    #[serde(default)]
+    q: String,
+    /// Frontend the links point into (`scalar`, `redoc`), the default one when unset
+    frontend: Option<String>,
+    limit: Option<usize>,
+}
+
+/// Fuzzy matches of the query among the caller's APIs, tags and operations
+async fn handle_suggest(
+    Query(query): Query<SuggestQuery>,
+    State(state): State<AppState>,
+    caller: Caller,
+) -> Json<serde_json::Value> {
+    let apis = load_visible_apis(&state.cache_dir, &caller).await;
+    let frontend = query
+        .frontend
+        .as_deref()
+        .and_then(frontend::FrontendType::from_str)
+        .unwrap_or_else(|| state.frontend_manager.default_frontend_type());
+    let limit = query
+        .limit
+        .unwrap_or(suggest::DEFAULT_LIMIT)
+        .min(suggest::MAX_LIMIT);
+    Json(serde_json::json!({
+        "suggestions": suggest::suggest(&apis, &query.q, frontend, limit),
+    }))
+}
+
+async fn handle_quick_switch_script() -> ([(header::HeaderName, &'static str); 1], &'static str) {
+    (
+        [(header::CONTENT_TYPE, "text/javascript; charset=utf-8")],
+        suggest::SCRIPT,
+    )
+}
+
+/// Sitemap of the APIs anonymous visitors can see, for search engines
+async fn handle_sitemap(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> ([(header::HeaderName, &'static str); 1], String) {
+    // Crawlers are anonymous, whoever asks
+    let anonymous = if state.tenancy.is_enabled() {
+        Caller::Tenant(None)
+    } else {
+        Caller::Unrestricted
+    };
+    let mut apis = load_visible_apis(&state.cache_dir, &anonymous).await;
+    apis.sort_by(|a, b| a.slug.cmp(&b.slug));
+
+    let base_url = api_catalog::base_url(state.public_url.as_deref(), &headers);
+    (
+        [(header::CONTENT_TYPE, "application/xml")],
+        sitemap::render_sitemap(
+            &apis,
+            &base_url,
+            state.frontend_manager.default_frontend_type(),
+        ),
+    )
 }
 
 async fn handle_health() -> Result<Json<serde_json::Value>, StatusCode> {
@@ -378,6 +1420,10 @@ async fn load_apis_from_cache(cache_dir: &StdPath) -> Vec<CachedApiEntry> {
                 if file_name_str.ends_with(".meta.json") {
                     if let Ok(content) = fs::read_to_string(&path) {
                         match serde_json::from_str::<CachedApiEntry>(&content) {
+                            // Replaced by its slug-named files on the next refresh
+                            Ok(api) if api.slug.is_empty() => {
+                                tracing::debug!("Skipping pre-slug cache entry {:?}", path);
+                            }
                             Ok(api) => {
                                 tracing::debug!("Loaded API from cache: {}", api.name);
                                 apis.push(api);
@@ -400,93 +1446,391 @@ async fn load_apis_from_cache(cache_dir: &StdPath) -> Vec<CachedApiEntry> {
     apis
 }
 
+/// Cached APIs the caller's tenant may see
+async fn load_visible_apis(cache_dir: &StdPath, caller: &Caller) -> Vec<CachedApiEntry> {
+    let mut apis = load_apis_from_cache(cache_dir).await;
+    apis.retain(|api| caller.can_see(api.tenant.as_deref()));
+    apis
+}
+
+fn verify_stored_spec(
+    verifier: Option<&SpecVerifier>,
+    stored: &crd_source::StoredSpec,
+) -> Result<(), SigningError> {
+    match (verifier, &stored.signature) {
+        (None, _) => Ok(()),
+        (Some(_), None) => Err(SigningError::Missing),
+        (Some(verifier), Some(signature)) => verifier.verify(&stored.body, signature),
+    }
+}
+
+/// Summary of the changes between two spec revisions, `None` if either does not parse
+fn change_summary(old: &str, new: &str) -> Option<String> {
+    let old = OpenApiDocument::parse(old).ok()?;
+    let new = OpenApiDocument::parse(new).ok()?;
+    Some(diff_documents(&old, &new).summary())
+}
+
+/// Adds the entries the operator stores under their own keys (`DISCOVERY_LAYOUT=entries`),
+/// mounted next to the discovery payload
+fn read_entry_files(discovery_path: &StdPath, config: &mut ServerDiscoveryConfig) {
+    let Some(Ok(files)) = discovery_path.parent().map(fs::read_dir) else {
+        return;
+    };
+    for file in files.flatten() {
+        let name = file.file_name();
+        let Some(name) = name.to_str().filter(|name| encoding::is_entry_key(name)) else {
+            continue;
+        };
+        let entry = fs::read(file.path())
+            .map_err(encoding::EncodingError::from)
+            .and_then(|bytes| encoding::decode::<ServerApiInventoryEntry>(&bytes));
+        match entry {
+            Ok(entry) => {
+                config.apis.retain(|api| api.id != entry.id);
+                config.apis.push(entry);
+            }
+            Err(e) => tracing::warn!("Ignoring undecodable catalog entry {}: {}", name, e),
+        }
+    }
+}
+
 async fn refresh_api_cache(
     state: &AppState,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    // Read the discovery.json from the configured path
-    match fs::read_to_string(&state.discovery_path) {
-        Ok(discovery_json) => {
-            let discovery_config: ServerDiscoveryConfig = serde_json::from_str(&discovery_json)?;
-
-            for api in discovery_config.apis {
-                match fetch_openapi_spec(&api.url).await {
-                    Ok(spec) => {
-                        tracing::info!("Successfully fetched OpenAPI spec for API: {}", api.name);
-
-                        let spec_path = get_spec_file_path(&state.cache_dir, &api.name);
-                        fs::write(&spec_path, &spec)?;
-
-                        let meta = CachedApiEntry {
-                            id: api.id,
-                            name: api.name,
-                            namespace: api.namespace,
-                            service_name: api.service_name,
-                            url: api.url,
-                            description: api.description,
-                            last_updated: api.last_updated,
-                            available: true,
-                            spec,
-                        };
-
-                        let metadata_path = get_metadata_file_path(&state.cache_dir, &meta.name);
-                        let api_json = serde_json::to_string(&meta)?;
-                        fs::write(&metadata_path, api_json)?;
-                    }
-                    Err(e) => {
-                        tracing::warn!("Failed to fetch OpenAPI spec for API {}: {}", api.name, e);
-
-                        let default_spec = serde_json::json!({
-                            "openapi": "3.0.0",
-                            "info": {
-                                "title": api.name,
-                                "version": "1.0.0",
-                                "description": "API documentation not available"
-                            },
-                            "paths": {}
-                        })
-                        .to_string();
-
-                        let spec_path = get_spec_file_path(&state.cache_dir, &api.name);
-                        fs::write(&spec_path, &default_spec)?;
-
-                        let meta = CachedApiEntry {
-                            id: api.id,
-                            name: api.name.clone(),
-                            namespace: api.namespace,
-                            service_name: api.service_name,
-                            url: api.url,
-                            description: api.description,
-                            last_updated: api.last_updated,
-                            available: false,
-                            spec: default_spec,
-                        };
-
-                        let metadata_path = get_metadata_file_path(&state.cache_dir, &api.name);
-                        let api_json = serde_json::to_string(&meta)?;
-                        fs::write(&metadata_path, api_json)?;
-                    }
+    let (apis, tombstones) = match &state.crd_source {
+        Some(source) => (source.entries().await, Vec::new()),
+        // Read the discovery payload from the configured path, in any encoding
+        None => match fs::read(&state.discovery_path) {
+            Ok(payload) => {
+                let mut discovery_config: ServerDiscoveryConfig = encoding::decode(&payload)?;
+                read_entry_files(&state.discovery_path, &mut discovery_config);
+                state
+                    .discovery_configmaps
+                    .read_shards(&mut discovery_config)
+                    .await?;
+                let mut apis = Vec::new();
+                for api in discovery_config.apis {
+                    let stored = match (&api.spec_config_map, &api.spec_url, &state.spec_bucket) {
+                        (Some(name), _, _) => state.discovery_configmaps.stored_spec(name).await,
+                        (None, Some(url), Some(bucket)) => {
+                            spec_bucket::stored_spec(bucket, url).await
+                        }
+                        _ => None,
+                    };
+                    apis.push((api, stored));
                 }
+                (apis, discovery_config.tombstones)
+            }
+            Err(e) => {
+                tracing::error!("Failed to read {:?}: {}", state.discovery_path, e);
+                return Ok(());
             }
+        },
+    };
 
-            // Count cached APIs
-            let apis = load_apis_from_cache(&state.cache_dir).await;
-            tracing::info!("Refreshed API cache with {} APIs", apis.len());
+    // Removed APIs disappear from the frontends; a re-added service wins
+    let cached_apis = load_apis_from_cache(&state.cache_dir).await;
+    for tombstone in &tombstones {
+        let is_same_service = |namespace: &str, service_name: &str| {
+            namespace == tombstone.namespace && service_name == tombstone.service_name
+        };
+        if apis
+            .iter()
+            .any(|(api, _)| is_same_service(&api.namespace, &api.service_name))
+        {
+            continue;
         }
-        Err(e) => {
-            tracing::error!("Failed to read discovery.json: {}", e);
+        for cached in cached_apis
+            .iter()
+            .filter(|cached| is_same_service(&cached.namespace, &cached.service_name))
+        {
+            tracing::info!(
+                "API {} was removed ({:?}), dropping it from the cache",
+                cached.slug,
+                tombstone.reason
+            );
+            remove_cache_files(&state.cache_dir, &cached.slug);
+            if let Some(analytics) = &state.analytics {
+                analytics.forget(&cached.slug).await;
+            }
         }
     }
+    fs::write(
+        get_tombstones_file_path(&state.cache_dir),
+        serde_json::to_string(&tombstones)?,
+    )?;
 
-    Ok(())
-}
+    let services: Vec<slugs::SlugKey> = apis
+        .iter()
+        .map(|(api, _)| slugs::SlugKey {
+            cluster: api.cluster.as_deref(),
+            namespace: &api.namespace,
+            service_name: &api.service_name,
+        })
+        .collect();
+    let api_slugs = slugs::assign_slugs(&services);
+
+    for ((api, stored_spec), slug) in apis.into_iter().zip(api_slugs) {
+        // Caches written before slugs are keyed by the display name
+        let legacy_name = slugs::legacy_cache_name(&api.name);
+        if load_cached_api(&state.cache_dir, &legacy_name).is_some_and(|meta| meta.slug.is_empty())
+        {
+            tracing::info!("Moving cached API {} to {}", api.name, slug);
+            remove_cache_files(&state.cache_dir, &legacy_name);
+        }
 
-async fn fetch_openapi_spec(url: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-    let client = reqwest::Client::new();
-    let response = client.get(url).send().await?;
+        let metadata_path = get_metadata_file_path(&state.cache_dir, &slug);
+        let cached = load_cached_api(&state.cache_dir, &slug);
+        let previous = cached
+            .clone()
+            .filter(|meta| meta.available && meta.url == api.url);
+        let request = FetchRequest {
+            validators: previous.as_ref().map(|meta| meta.validators.clone()),
+            ..Default::default()
+        };
 
-    if response.status().is_success() {
-        Ok(response.text().await?)
-    } else {
-        Err(format!("HTTP error: {}", response.status()).into())
+        // APIs asking for a longer interval keep their spec until it has passed; the spec is
+        // fetched on the tick before that moment rather than a whole tick after it
+        let now = availability::now_secs();
+        let fresh = api.refresh_interval.is_some_and(|interval| {
+            previous.as_ref().is_some_and(|meta| {
+                now.saturating_sub(meta.fetched_at) + state.refresh_interval.as_secs() <= interval
+            })
+        });
+
+        // Outside the refresh window a cached spec is kept; APIs not cached yet are fetched
+        let window = api
+            .refresh_window
+            .as_deref()
+            .and_then(|window| RefreshWindow::parse(window).ok())
+            .or_else(|| state.refresh_window.clone());
+        let closed = stored_spec.is_none()
+            && previous.is_some()
+            && window.is_some_and(|window| !window.is_open(chrono::Utc::now()));
+        let stale_by_schedule = closed || api.stale_by_schedule;
+
+        // Specs stored by the operator, with DiscoveredApi resources or in spec ConfigMaps,
+        // are used as is once their signature checks out; the signature is kept for
+        // clients verifying the served spec
+        let signature_path = get_signature_file_path(&state.cache_dir, &slug);
+        let outcome: Result<FetchOutcome, FetchFailure> = match stored_spec {
+            Some(stored) => match verify_stored_spec(state.verifier.as_ref(), &stored) {
+                Ok(()) => {
+                    match &stored.signature {
+                        Some(signature) => fs::write(&signature_path, signature.to_json())?,
+                        None => {
+                            let _ = fs::remove_file(&signature_path);
+                        }
+                    }
+                    Ok(FetchOutcome::Fetched(FetchedSpec {
+                        body: stored.body,
+                        status: 200,
+                        content_type: None,
+                        validators: CacheValidators::default(),
+                    }))
+                }
+                Err(e) => {
+                    let _ = fs::remove_file(&signature_path);
+                    Err(FetchFailure::new(
+                        FailurePhase::Signature,
+                        format!("rejected stored spec: {}", e),
+                    ))
+                }
+            },
+            None if fresh || closed => Ok(FetchOutcome::NotModified),
+            None if api.insecure_skip_verify => state
+                .insecure_fetcher
+                .fetch(&api.url, &request)
+                .await
+                .map_err(|e| e.failure()),
+            None => state
+                .fetcher
+                .fetch(&api.url, &request)
+                .await
+                .map_err(|e| e.failure()),
+        };
+        // A page served instead of the spec (a login form, a 200 error page) is a failure too;
+        // Swagger 2.0 specs are served as OpenAPI 3.0
+        let outcome = outcome.and_then(|outcome| match outcome {
+            FetchOutcome::Fetched(mut fetched) => match spec_utils::validate_spec(&fetched.body)
+                .and_then(|_| spec_utils::normalize_spec(&fetched.body))
+            {
+                Ok(body) => {
+                    fetched.body = body;
+                    Ok(FetchOutcome::Fetched(fetched))
+                }
+                Err(e) => Err(FetchFailure::parse(&fetched.body, e)),
+            },
+            FetchOutcome::NotModified => Ok(outcome),
+        });
+
+        if !fresh && !closed {
+            let availability_path = get_availability_file_path(&state.cache_dir, &slug);
+            let mut history = availability::AvailabilityHistory::load(&availability_path);
+            history.record(outcome.is_ok() && api.available, now);
+            if let Err(e) = history.save(&availability_path) {
+                tracing::warn!("Failed to store availability of API {}: {}", api.name, e);
+            }
+        }
+
+        if let Some(cached) = &cached {
+            let change = match &outcome {
+                Ok(FetchOutcome::Fetched(_)) if !cached.available => {
+                    Some((ApiEvent::Recovered, None))
+                }
+                Ok(FetchOutcome::Fetched(fetched)) if fetched.body != cached.spec => Some((
+                    ApiEvent::Changed,
+                    change_summary(&cached.spec, &fetched.body),
+                )),
+                Err(e) if cached.available => Some((ApiEvent::Unavailable, Some(e.to_string()))),
+                _ => None,
+            };
+            if let Some((event, detail)) = change {
+                state
+                    .subscriptions
+                    .notify(Notification {
+                        slug: slug.clone(),
+                        api: api.name.clone(),
+                        namespace: api.namespace.clone(),
+                        service_name: api.service_name.clone(),
+                        event,
+                        detail,
+                    })
+                    .await;
+            }
+        }
+
+        match outcome {
+            Ok(FetchOutcome::NotModified) => {
+                tracing::debug!("OpenAPI spec for API {} not modified", api.name);
+
+                let Some(previous) = previous else { continue };
+                let meta = CachedApiEntry {
+                    id: api.id,
+                    slug,
+                    name: api.name,
+                    namespace: api.namespace,
+                    service_name: api.service_name,
+                    url: api.url,
+                    description: api.description,
+                    last_updated: api.last_updated,
+                    available: true,
+                    spec: previous.spec,
+                    validators: previous.validators,
+                    tenant: api.tenant,
+                    contract: api.contract,
+                    lifecycle: api.lifecycle,
+                    ui: api.ui,
+                    failure: None,
+                    last_error: api.last_error,
+                    consecutive_failures: api.consecutive_failures,
+                    fetched_at: if fresh || closed {
+                        previous.fetched_at
+                    } else {
+                        now
+                    },
+                    refresh_window: api.refresh_window,
+                    stale_by_schedule,
+                    api_group: api.api_group,
+                    version: previous.version,
+                    last_change_summary: api.last_change_summary,
+                };
+
+                let api_json = serde_json::to_string(&meta)?;
+                fs::write(&metadata_path, api_json)?;
+            }
+            Ok(FetchOutcome::Fetched(fetched)) => {
+                tracing::info!("Successfully fetched OpenAPI spec for API: {}", api.name);
+
+                let spec_path = get_spec_file_path(&state.cache_dir, &slug);
+                fs::write(&spec_path, &fetched.body)?;
+                let version = spec_utils::parse_spec(&fetched.body)
+                    .ok()
+                    .map(|document| document.info.version)
+                    .filter(|version| !version.is_empty());
+
+                let meta = CachedApiEntry {
+                    id: api.id,
+                    slug,
+                    name: api.name,
+                    namespace: api.namespace,
+                    service_name: api.service_name,
+                    url: api.url,
+                    description: api.description,
+                    last_updated: api.last_updated,
+                    available: true,
+                    spec: fetched.body,
+                    validators: fetched.validators,
+                    tenant: api.tenant,
+                    contract: api.contract,
+                    lifecycle: api.lifecycle,
+                    ui: api.ui,
+                    failure: None,
+                    last_error: api.last_error,
+                    consecutive_failures: api.consecutive_failures,
+                    fetched_at: now,
+                    refresh_window: api.refresh_window,
+                    stale_by_schedule: api.stale_by_schedule,
+                    api_group: api.api_group,
+                    version,
+                    last_change_summary: api.last_change_summary,
+                };
+
+                let api_json = serde_json::to_string(&meta)?;
+                fs::write(&metadata_path, api_json)?;
+            }
+            Err(failure) => {
+                tracing::warn!(
+                    "Failed to fetch OpenAPI spec for API {}: {}",
+                    api.name,
+                    failure
+                );
+
+                let default_spec = spec_utils::create_default_spec(
+                    &api.name,
+                    &unavailable_description(&api.url, &failure),
+                );
+
+                let spec_path = get_spec_file_path(&state.cache_dir, &slug);
+                fs::write(&spec_path, &default_spec)?;
+
+                let meta = CachedApiEntry {
+                    id: api.id,
+                    slug,
+                    name: api.name.clone(),
+                    namespace: api.namespace,
+                    service_name: api.service_name,
+                    url: api.url,
+                    description: api.description,
+                    last_updated: api.last_updated,
+                    available: false,
+                    spec: default_spec,
+                    validators: CacheValidators::default(),
+                    tenant: api.tenant,
+                    contract: api.contract,
+                    lifecycle: api.lifecycle,
+                    ui: api.ui,
+                    failure: Some(failure),
+                    last_error: api.last_error,
+                    consecutive_failures: api.consecutive_failures,
+                    fetched_at: now,
+                    refresh_window: api.refresh_window,
+                    stale_by_schedule: false,
+                    api_group: api.api_group,
+                    version: None,
+                    last_change_summary: api.last_change_summary,
+                };
+
+                let api_json = serde_json::to_string(&meta)?;
+                fs::write(&metadata_path, api_json)?;
+            }
+        }
     }
+
+    // Count cached APIs
+    let apis = load_apis_from_cache(&state.cache_dir).await;
+    tracing::info!("Refreshed API cache with {} APIs", apis.len());
+
+    Ok(())
 }
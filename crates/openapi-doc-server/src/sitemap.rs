@@ -0,0 +1,128 @@
+//! Sitemap for public portals.
+//!
+//! With `PUBLIC_URL` set the portal is considered published, and `/sitemap.xml` lists a
+//! page per API (`/?api=<slug>`, which opens the API in the default frontend) plus a deep
+//! link to every operation that is not marked `x-internal`, so search engines can index
+//! the documentation. Only APIs visible to anonymous callers are listed. Deep links follow
+//! the default frontend's anchors: `#tag/<tag>/<METHOD><path>` for Scalar and
+//! `#operation/<operationId>` for Redoc, which skips operations without an id.
+
+use openapi_common::OpenApiDocument;
+
+use crate::CachedApiEntry;
+use crate::frontend::FrontendType;
+
+pub const SITEMAP_PATH: &str = "/sitemap.xml";
+
+/// Most URLs a single sitemap may contain
+const MAX_URLS: usize = 50_000;
+
+/// Renders the sitemap of `apis`, with deep links into the default `frontend`
+pub fn render_sitemap(apis: &[CachedApiEntry], base_url: &str, frontend: FrontendType) -> String {
+    let mut urls = Vec::new();
+    for api in apis.iter().filter(|api| api.available) {
+        let page = format!("{}/?api={}", base_url, urlencoding::encode(&api.slug));
+        urls.push((page.clone(), api.last_updated.as_str()));
+
+        let Ok(document) = OpenApiDocument::parse(&api.spec) else {
+            continue;
+        };
+        for (path, method, operation) in document.public_operations() {
+            if let Some(anchor) = frontend.operation_anchor(path, method, operation) {
+                urls.push((format!("{}#{}", page, anchor), api.last_updated.as_str()));
+            }
+        }
+    }
+    if urls.len() > MAX_URLS {
+        tracing::warn!(
+            "Sitemap has {} URLs, only the first {} are listed",
+            urls.len(),
+            MAX_URLS
+        );
+        urls.truncate(MAX_URLS);
+    }
+
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    for (url, last_modified) in urls {
+        xml.push_str(&format!(
+            "  <url>\n    <loc>{}</loc>\n    <lastmod>{}</lastmod>\n  </url>\n",
+            escape_xml(&url),
+            escape_xml(last_modified)
+        ));
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: &str = r#"
+openapi: 3.0.3
+info: {title: Orders, version: '1'}
+paths:
+  /orders/{id}:
+    get: {operationId: getOrder, tags: [Order Items]}
+  /orders:
+    post: {summary: Create an order}
+  /admin/reindex:
+    post: {operationId: reindex, x-internal: true}
+"#;
+
+    fn locations(xml: &str) -> Vec<&str> {
+        xml.lines()
+            .filter_map(|line| line.trim().strip_prefix("<loc>"))
+            .filter_map(|line| line.strip_suffix("</loc>"))
+            .collect()
+    }
+
+    #[test]
+    fn lists_available_apis_and_their_public_operations() {
+        let mut down = CachedApiEntry::example("shop", "carts", SPEC);
+        down.available = false;
+        let apis = [CachedApiEntry::example("shop", "orders", SPEC), down];
+        let base = "https://apis.example.com";
+
+        let scalar = render_sitemap(&apis, base, FrontendType::Scalar);
+        assert_eq!(
+            locations(&scalar),
+            vec![
+                "https://apis.example.com/?api=shop-orders",
+                "https://apis.example.com/?api=shop-orders#tag/default/POST/orders",
+                "https://apis.example.com/?api=shop-orders#tag/order-items/GET/orders/%7Bid%7D",
+            ]
+        );
+        assert!(scalar.contains("<lastmod>2026-01-01T00:00:00Z</lastmod>"));
+
+        // Redoc links operations by id, so the one without an id has no deep link
+        let redoc = render_sitemap(&apis, base, FrontendType::Redoc);
+        assert_eq!(
+            locations(&redoc),
+            vec![
+                "https://apis.example.com/?api=shop-orders",
+                "https://apis.example.com/?api=shop-orders#operation/getOrder",
+            ]
+        );
+    }
+
+    #[test]
+    fn urls_are_escaped_for_xml() {
+        assert_eq!(
+            escape_xml("/?api=a&b=<c>\"'"),
+            "/?api=a&amp;b=&lt;c&gt;&quot;&apos;"
+        );
+    }
+}
@@ -0,0 +1,158 @@
+//! Specs the operator stores in S3 with `SPEC_STORAGE=s3`.
+//!
+//! Entries reference their spec as `s3://<bucket>/<key>` in `spec_url`. With the same
+//! `SPEC_STORAGE_S3_*` settings as the operator, the server reads them from the bucket,
+//! with static AWS credentials or the IRSA role of its service account, instead of
+//! fetching the spec from the service.
+
+use openapi_common::s3::SpecBucket;
+use openapi_common::signing::SpecSignature;
+
+use crate::crd_source::StoredSpec;
+
+/// Spec at `url` with its signature, `None` if it cannot be read
+pub async fn stored_spec(bucket: &SpecBucket, url: &str) -> Option<StoredSpec> {
+    if bucket.key(url).is_none() {
+        tracing::warn!(
+            "Stored spec {} is not in bucket {}, check SPEC_STORAGE_S3_BUCKET",
+            url,
+            bucket.bucket()
+        );
+        return None;
+    }
+    match bucket.get(url).await {
+        Ok(Some((body, signature))) => Some(StoredSpec {
+            body,
+            signature: signature.and_then(|content| {
+                SpecSignature::from_json(&content)
+                    .inspect_err(|e| {
+                        tracing::warn!("Ignoring malformed signature of {}: {}", url, e)
+                    })
+                    .ok()
+            }),
+        }),
+        Ok(None) => {
+            tracing::warn!("Stored spec {} does not exist", url);
+            None
+        }
+        Err(e) => {
+            tracing::warn!("Failed to read stored spec {}: {}", url, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{Recorded, TestServer};
+    use openapi_common::s3::RenewingS3Client;
+    use openapi_common::sigv4::AwsCredentials;
+    use std::collections::HashMap;
+
+    const SPEC: &str = "{\"openapi\":\"3.0.0\",\"info\":{\"title\":\"Orders\",\"version\":\"1\"}}";
+
+    /// Bucket serving `objects` by path, failing reads of anything under `/specs/broken/`
+    async fn bucket(objects: HashMap<String, String>) -> TestServer {
+        TestServer::start(move |request: &Recorded| {
+            if request.path().starts_with("/specs/broken/") {
+                return (500, "InternalError".to_string());
+            }
+            match objects.get(request.path()) {
+                Some(body) => (200, body.clone()),
+                None => (404, "NoSuchKey".to_string()),
+            }
+        })
+        .await
+    }
+
+    fn spec_bucket(endpoint: &str) -> SpecBucket {
+        let credentials = AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: None,
+        };
+        let client =
+            RenewingS3Client::with_credentials(credentials, "eu-west-1", "specs", Some(endpoint))
+                .unwrap();
+        SpecBucket::new(client, "specs", "")
+    }
+
+    #[tokio::test]
+    async fn specs_are_read_with_their_signature() {
+        let signature = SpecSignature {
+            algorithm: "ed25519".to_string(),
+            key_id: "0123456789abcdef".to_string(),
+            sha256: "ab12".to_string(),
+            signature: "c2lnbmF0dXJl".to_string(),
+        };
+        let server = bucket(HashMap::from([
+            ("/specs/shop/orders.json".to_string(), SPEC.to_string()),
+            (
+                "/specs/shop/orders.json.sig".to_string(),
+                signature.to_json(),
+            ),
+            ("/specs/shop/carts.json".to_string(), SPEC.to_string()),
+            (
+                "/specs/shop/carts.json.sig".to_string(),
+                "not json".to_string(),
+            ),
+            ("/specs/shop/payments.json".to_string(), SPEC.to_string()),
+        ]))
+        .await;
+        let bucket = spec_bucket(&server.url);
+
+        let stored = stored_spec(&bucket, "s3://specs/shop/orders.json")
+            .await
+            .unwrap();
+        assert_eq!(stored.body, SPEC);
+        assert_eq!(stored.signature, Some(signature));
+        let paths: Vec<String> = server
+            .requests()
+            .into_iter()
+            .map(|r| format!("{} {}", r.method, r.path()))
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "GET /specs/shop/orders.json",
+                "GET /specs/shop/orders.json.sig"
+            ]
+        );
+
+        // Malformed signatures are ignored, the spec is still served
+        let stored = stored_spec(&bucket, "s3://specs/shop/carts.json")
+            .await
+            .unwrap();
+        assert_eq!(stored.body, SPEC);
+        assert_eq!(stored.signature, None);
+        let stored = stored_spec(&bucket, "s3://specs/shop/payments.json")
+            .await
+            .unwrap();
+        assert_eq!(stored.signature, None);
+    }
+
+    #[tokio::test]
+    async fn unreadable_specs_are_none() {
+        let server = bucket(HashMap::new()).await;
+        let bucket = spec_bucket(&server.url);
+
+        assert!(
+            stored_spec(&bucket, "s3://other/shop/orders.json")
+                .await
+                .is_none()
+        );
+        assert!(server.requests().is_empty());
+        assert!(
+            stored_spec(&bucket, "s3://specs/shop/orders.json")
+                .await
+                .is_none()
+        );
+        assert!(
+            stored_spec(&bucket, "s3://specs/broken/orders.json")
+                .await
+                .is_none()
+        );
+        assert_eq!(server.requests().len(), 2);
+    }
+}
@@ -0,0 +1,654 @@
+//! Consumer subscriptions and targeted change notifications.
+//!
+//! Teams register as consumers of the APIs they use (`POST /subscriptions`) with a generic
+//! webhook or a Slack incoming webhook. Cache refreshes notify only the subscribers of an
+//! API when its spec changes, when it becomes unavailable and when it recovers.
+//! Subscriptions are kept in `subscriptions.json` in `CACHE_DIR`, so they only survive
+//! restarts when that directory is persistent.
+//!
+//! Notifications are sent from inside the cluster, so webhook URLs may not point at
+//! loopback, link-local or private addresses (the cloud metadata endpoint, cluster
+//! services) unless their host is listed in `SUBSCRIPTION_ALLOWED_HOSTS`.
+
+use openapi_common::digest::sha256_hex;
+use openapi_common::slugs;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Comma-separated hosts webhooks may be sent to although they resolve to a loopback,
+/// link-local or private address
+pub const SUBSCRIPTION_ALLOWED_HOSTS_ENV: &str = "SUBSCRIPTION_ALLOWED_HOSTS";
+
+const SUBSCRIPTIONS_FILE: &str = "subscriptions.json";
+
+/// How subscribers are notified
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    /// JSON `POST` with the event details
+    #[default]
+    Webhook,
+    /// Slack incoming webhook, `POST` of a `text` message
+    Slack,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiEvent {
+    /// A new spec revision was served
+    Changed,
+    /// The spec could no longer be fetched
+    Unavailable,
+    /// The spec can be fetched again
+    Recovered,
+}
+
+impl ApiEvent {
+    pub const ALL: [ApiEvent; 3] = [
+        ApiEvent::Changed,
+        ApiEvent::Unavailable,
+        ApiEvent::Recovered,
+    ];
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Subscription {
+    pub id: String,
+    /// Slug of the API
+    pub api: String,
+    /// Team or application consuming the API
+    pub consumer: String,
+    pub channel: Channel,
+    /// Webhook or Slack incoming-webhook URL
+    pub url: String,
+    pub events: Vec<ApiEvent>,
+    /// Tenant that registered the subscription
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// Unix seconds
+    pub created_at: u64,
+}
+
+impl Subscription {
+    /// Whether the subscriber wants to hear about the notification
+    fn matches(&self, notification: &Notification) -> bool {
+        self.api == notification.slug && self.events.contains(&notification.event)
+    }
+}
+
+/// Body of `POST /subscriptions`
+#[derive(Deserialize, Debug)]
+pub struct NewSubscription {
+    /// Slug, id or display name of the API
+    pub api: String,
+    pub consumer: String,
+    #[serde(default)]
+    pub channel: Channel,
+    pub url: String,
+    /// All events when empty
+    #[serde(default)]
+    pub events: Vec<ApiEvent>,
+}
+
+impl NewSubscription {
+    /// Checks the consumer and that the URL is an http(s) URL that is not internal, see
+    /// [`check_url`]
+    pub async fn validate(&self, allowed_hosts: &[String]) -> Result<(), String> {
+        if self.consumer.trim().is_empty() {
+            return Err("consumer must not be empty".to_string());
+        }
+        check_url(&self.url, allowed_hosts).await
+    }
+}
+
+/// Checks that `url` is an http(s) URL whose host, unless it is one of `allowed_hosts`,
+/// only resolves to public addresses
+pub async fn check_url(url: &str, allowed_hosts: &[String]) -> Result<(), String> {
+    let parsed = reqwest::Url::parse(url)
+        .ok()
+        .filter(|parsed| matches!(parsed.scheme(), "http" | "https"))
+        .ok_or_else(|| format!("url must be an http(s) URL, got \"{}\"", url))?;
+    let Some(host) = parsed.host_str() else {
+        return Err(format!("url has no host: \"{}\"", url));
+    };
+    if allowed_hosts
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(host))
+    {
+        return Ok(());
+    }
+
+    let port = parsed.port_or_known_default().unwrap_or(443);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addresses = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| format!("url host {} does not resolve: {}", host, e))?;
+    for address in addresses {
+        if is_internal(address.ip()) {
+            return Err(format!(
+                "url host {} resolves to the internal address {}",
+                host,
+                address.ip()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Loopback, link-local, private, shared (carrier-grade NAT) and unspecified addresses
+fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || (ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_internal(IpAddr::V4(ip)),
+            None => {
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    || (ip.segments()[0] & 0xfe00) == 0xfc00
+                    || (ip.segments()[0] & 0xffc0) == 0xfe80
+            }
+        },
+    }
+}
+
+/// Hosts of `SUBSCRIPTION_ALLOWED_HOSTS`
+pub fn allowed_hosts_from_env() -> Vec<String> {
+    std::env::var(SUBSCRIPTION_ALLOWED_HOSTS_ENV)
+        .unwrap_or_default()
+        .split(',')
+        .map(|host| host.trim().to_string())
+        .filter(|host| !host.is_empty())
+        .collect()
+}
+
+/// Something that happened to one API
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub slug: String,
+    /// Display name
+    pub api: String,
+    pub namespace: String,
+    pub service_name: String,
+    pub event: ApiEvent,
+    /// E.g. the change summary of a new revision
+    pub detail: Option<String>,
+}
+
+impl Notification {
+    fn text(&self) -> String {
+        let what = match self.event {
+            ApiEvent::Changed => "has a new spec revision",
+            ApiEvent::Unavailable => "is unavailable",
+            ApiEvent::Recovered => "is available again",
+        };
+        let mut text = format!(
+            "{} ({}/{}) {}",
+            self.api, self.namespace, self.service_name, what
+        );
+        if let Some(detail) = &self.detail {
+            text.push_str(&format!(": {}", detail));
+        }
+        text
+    }
+
+    fn payload(&self, subscription: &Subscription) -> serde_json::Value {
+        match subscription.channel {
+            Channel::Webhook => serde_json::json!({
+                "event": self.event,
+                "api": self.api,
+                "namespace": self.namespace,
+                "service_name": self.service_name,
+                "detail": self.detail,
+                "consumer": subscription.consumer,
+                "subscription_id": subscription.id,
+                "slug": self.slug,
+                "spec_url": format!("/specs/{}", self.slug),
+            }),
+            Channel::Slack => serde_json::json!({ "text": self.text() }),
+        }
+    }
+}
+
+/// Moves subscriptions holding the display name of their API to its slug and drops those
+/// whose API is neither a slug nor a known name; returns whether any changed
+fn migrate_legacy(
+    subscriptions: Vec<Subscription>,
+    slugs_by_name: &HashMap<String, String>,
+) -> (Vec<Subscription>, bool) {
+    let mut migrated = false;
+    let subscriptions = subscriptions
+        .into_iter()
+        .filter_map(|mut subscription| {
+            let known_slug = slugs_by_name.values().any(|slug| *slug == subscription.api);
+            if known_slug {
+                return Some(subscription);
+            }
+            match slugs_by_name.get(&subscription.api) {
+                Some(slug) => {
+                    tracing::info!(
+                        "Moving subscription {} of {} to {}",
+                        subscription.id,
+                        subscription.api,
+                        slug
+                    );
+                    subscription.api = slug.clone();
+                    migrated = true;
+                    Some(subscription)
+                }
+                // The API is not cached yet
+                None if slugs::is_slug(&subscription.api) => Some(subscription),
+                None => {
+                    tracing::warn!(
+                        "Dropping subscription {} of {} to the unknown API {}",
+                        subscription.id,
+                        subscription.consumer,
+                        subscription.api
+                    );
+                    migrated = true;
+                    None
+                }
+            }
+        })
+        .collect();
+    (subscriptions, migrated)
+}
+
+#[derive(Clone)]
+pub struct SubscriptionStore {
+    path: PathBuf,
+    subscriptions: Arc<Mutex<Vec<Subscription>>>,
+    /// Hosts of `SUBSCRIPTION_ALLOWED_HOSTS`
+    allowed_hosts: Arc<Vec<String>>,
+    client: reqwest::Client,
+}
+
+impl SubscriptionStore {
+    /// Loads the subscriptions from `CACHE_DIR`, starting empty when there are none.
+    ///
+    /// Subscriptions registered before slugs hold the display name of their API instead;
+    /// they are moved to the slug `slugs_by_name` has for it, or dropped when the name is
+    /// unknown, as they could match a different API.
+    pub fn load(
+        cache_dir: &Path,
+        slugs_by_name: &HashMap<String, String>,
+        allowed_hosts: Vec<String>,
+    ) -> Self {
+        let path = cache_dir.join(SUBSCRIPTIONS_FILE);
+        let stored: Vec<Subscription> = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        let (subscriptions, migrated) = migrate_legacy(stored, slugs_by_name);
+        if migrated {
+            let saved = serde_json::to_string_pretty(&subscriptions)
+                .map_err(std::io::Error::from)
+                .and_then(|content| fs::write(&path, content));
+            if let Err(e) = saved {
+                tracing::warn!("Failed to store migrated subscriptions: {}", e);
+            }
+        }
+        if !subscriptions.is_empty() {
+            tracing::info!("Loaded {} API subscriptions", subscriptions.len());
+        }
+        Self {
+            path,
+            subscriptions: Arc::new(Mutex::new(subscriptions)),
+            allowed_hosts: Arc::new(allowed_hosts),
+            // A redirect could lead a webhook to an internal address after all
+            client: reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Hosts webhooks may be sent to although they resolve to an internal address
+    pub fn allowed_hosts(&self) -> &[String] {
+        &self.allowed_hosts
+    }
+
+    async fn save(&self, subscriptions: &[Subscription]) -> std::io::Result<()> {
+        tokio::fs::write(&self.path, serde_json::to_string_pretty(subscriptions)?).await
+    }
+
+    pub async fn list(&self) -> Vec<Subscription> {
+        self.subscriptions.lock().await.clone()
+    }
+
+    /// Registers a subscription; registering the same API and URL again updates it
+    pub async fn add(
+        &self,
+        new: NewSubscription,
+        tenant: Option<String>,
+        now: u64,
+    ) -> std::io::Result<Subscription> {
+        let mut subscriptions = self.subscriptions.lock().await;
+        let events = if new.events.is_empty() {
+            ApiEvent::ALL.to_vec()
+        } else {
+            new.events
+        };
+        let existing = subscriptions
+            .iter()
+            .position(|s| s.api == new.api && s.url == new.url && s.tenant == tenant);
+        let subscription = Subscription {
+            id: match existing {
+                Some(index) => subscriptions[index].id.clone(),
+                None => sha256_hex(format!("{}\n{}\n{}", new.api, new.url, now).as_bytes())[..16]
+                    .to_string(),
+            },
+            api: new.api,
+            consumer: new.consumer,
+            channel: new.channel,
+            url: new.url,
+            events,
+            tenant,
+            created_at: existing.map_or(now, |index| subscriptions[index].created_at),
+        };
+        match existing {
+            Some(index) => subscriptions[index] = subscription.clone(),
+            None => subscriptions.push(subscription.clone()),
+        }
+        self.save(&subscriptions).await?;
+        Ok(subscription)
+    }
+
+    pub async fn get(&self, id: &str) -> Option<Subscription> {
+        self.subscriptions
+            .lock()
+            .await
+            .iter()
+            .find(|s| s.id == id)
+            .cloned()
+    }
+
+    pub async fn remove(&self, id: &str) -> std::io::Result<()> {
+        let mut subscriptions = self.subscriptions.lock().await;
+        subscriptions.retain(|s| s.id != id);
+        self.save(&subscriptions).await
+    }
+
+    /// Sends the notification to the API's subscribers in the background
+    pub async fn notify(&self, notification: Notification) {
+        let targets: Vec<Subscription> = self
+            .subscriptions
+            .lock()
+            .await
+            .iter()
+            .filter(|s| s.matches(&notification))
+            .cloned()
+            .collect();
+        for subscription in targets {
+            let client = self.client.clone();
+            let allowed_hosts = self.allowed_hosts.clone();
+            let payload = notification.payload(&subscription);
+            tokio::spawn(async move {
+                // The host may resolve to another address than when it was registered
+                if let Err(e) = check_url(&subscription.url, &allowed_hosts).await {
+                    tracing::warn!(
+                        "Not notifying {} about {}: {}",
+                        subscription.consumer,
+                        subscription.api,
+                        e
+                    );
+                    return;
+                }
+                let result = client
+                    .post(&subscription.url)
+                    .json(&payload)
+                    .timeout(std::time::Duration::from_secs(10))
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status());
+                if let Err(e) = result {
+                    tracing::warn!(
+                        "Failed to notify {} about {}: {}",
+                        subscription.consumer,
+                        subscription.api,
+                        e
+                    );
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notification(event: ApiEvent) -> Notification {
+        Notification {
+            slug: "shop-orders".to_string(),
+            api: "Orders API".to_string(),
+            namespace: "shop".to_string(),
+            service_name: "orders".to_string(),
+            event,
+            detail: Some("2 operations added".to_string()),
+        }
+    }
+
+    fn new_subscription(url: &str, events: Vec<ApiEvent>) -> NewSubscription {
+        NewSubscription {
+            api: "shop-orders".to_string(),
+            consumer: "checkout".to_string(),
+            channel: Channel::Webhook,
+            url: url.to_string(),
+            events,
+        }
+    }
+
+    /// Directory of its own for one test, removed when dropped
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let nanos = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos();
+            let dir =
+                std::env::temp_dir().join(format!("{}-{}-{}", name, std::process::id(), nanos));
+            fs::create_dir(&dir).unwrap();
+            TestDir(dir)
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn subscribers_are_matched_by_slug_and_event() {
+        let dir = TestDir::new("subscriptions");
+        let store = SubscriptionStore::load(&dir.0, &HashMap::new(), Vec::new());
+
+        let all = store
+            .add(
+                new_subscription("https://hooks.example.com/a", Vec::new()),
+                None,
+                1,
+            )
+            .await
+            .unwrap();
+        assert_eq!(all.events, ApiEvent::ALL.to_vec());
+        // Registering the same API and URL again updates the subscription
+        let changes = store
+            .add(
+                new_subscription("https://hooks.example.com/a", vec![ApiEvent::Changed]),
+                None,
+                2,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            (changes.id.as_str(), changes.created_at),
+            (all.id.as_str(), 1)
+        );
+        assert_eq!(store.list().await.len(), 1);
+        let by_name = Subscription {
+            api: "Orders API".to_string(),
+            ..changes.clone()
+        };
+        let other = Subscription {
+            api: "shop-carts".to_string(),
+            ..all.clone()
+        };
+
+        let changed = notification(ApiEvent::Changed);
+        assert!(changes.matches(&changed));
+        assert!(!by_name.matches(&changed));
+        assert!(!other.matches(&changed));
+        assert!(!changes.matches(&notification(ApiEvent::Unavailable)));
+
+        // The subscription was persisted
+        let reloaded = SubscriptionStore::load(&dir.0, &HashMap::new(), Vec::new());
+        assert_eq!(reloaded.list().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn subscriptions_by_display_name_are_moved_to_the_slug_on_load() {
+        let dir = TestDir::new("subscriptions-legacy");
+        let subscription = |api: &str| Subscription {
+            id: api.to_string(),
+            api: api.to_string(),
+            consumer: "checkout".to_string(),
+            channel: Channel::Webhook,
+            url: "https://hooks.example.com/a".to_string(),
+            events: ApiEvent::ALL.to_vec(),
+            tenant: None,
+            created_at: 1,
+        };
+        let stored = [
+            subscription("Orders API"),
+            subscription("Gone API"),
+            subscription("shop-carts"),
+            subscription("shop-payments"),
+        ];
+        fs::write(
+            dir.0.join(SUBSCRIPTIONS_FILE),
+            serde_json::to_string(&stored).unwrap(),
+        )
+        .unwrap();
+        let slugs_by_name = HashMap::from([
+            ("Orders API".to_string(), "shop-orders".to_string()),
+            ("Carts API".to_string(), "shop-carts".to_string()),
+        ]);
+
+        let apis = |store: &SubscriptionStore| {
+            let store = store.clone();
+            async move {
+                let mut apis: Vec<String> = store.list().await.into_iter().map(|s| s.api).collect();
+                apis.sort();
+                apis
+            }
+        };
+        let store = SubscriptionStore::load(&dir.0, &slugs_by_name, Vec::new());
+        // Slugs of APIs not cached yet are kept
+        let expected = ["shop-carts", "shop-orders", "shop-payments"];
+        assert_eq!(apis(&store).await, expected);
+        assert!(
+            store
+                .list()
+                .await
+                .iter()
+                .any(|s| s.matches(&notification(ApiEvent::Changed)))
+        );
+        // The migration was stored
+        let reloaded = SubscriptionStore::load(&dir.0, &HashMap::new(), Vec::new());
+        assert_eq!(apis(&reloaded).await, expected);
+    }
+
+    #[test]
+    fn payloads_match_the_channel() {
+        let subscription = Subscription {
+            id: "abc".to_string(),
+            api: "shop-orders".to_string(),
+            consumer: "checkout".to_string(),
+            channel: Channel::Webhook,
+            url: "https://hooks.example.com/a".to_string(),
+            events: ApiEvent::ALL.to_vec(),
+            tenant: None,
+            created_at: 1,
+        };
+        let webhook = notification(ApiEvent::Changed).payload(&subscription);
+        assert_eq!(webhook["event"], "changed");
+        assert_eq!(webhook["subscription_id"], "abc");
+        assert_eq!(webhook["spec_url"], "/specs/shop-orders");
+
+        let slack = Subscription {
+            channel: Channel::Slack,
+            ..subscription
+        };
+        assert_eq!(
+            notification(ApiEvent::Unavailable).payload(&slack),
+            serde_json::json!({
+                "text": "Orders API (shop/orders) is unavailable: 2 operations added"
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn new_subscriptions_need_a_consumer_and_an_http_url() {
+        let allowed = ["hooks.example.com".to_string()];
+        assert!(
+            new_subscription("https://hooks.example.com/a", Vec::new())
+                .validate(&allowed)
+                .await
+                .is_ok()
+        );
+        assert!(
+            new_subscription("ftp://hooks.example.com", Vec::new())
+                .validate(&allowed)
+                .await
+                .is_err()
+        );
+        let anonymous = NewSubscription {
+            consumer: " ".to_string(),
+            ..new_subscription("https://hooks.example.com/a", Vec::new())
+        };
+        assert_eq!(
+            anonymous.validate(&allowed).await.unwrap_err(),
+            "consumer must not be empty"
+        );
+    }
+
+    #[tokio::test]
+    async fn webhooks_to_internal_addresses_need_an_allowed_host() {
+        for url in [
+            "http://127.0.0.1:8080/hook",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://10.96.0.1/",
+            "https://192.168.1.10/hook",
+            "http://100.64.0.1/",
+            "http://[::1]/hook",
+            "http://[fd00::1]/hook",
+            "http://[::ffff:10.0.0.1]/hook",
+            "http://localhost:9090/",
+        ] {
+            let err = check_url(url, &[]).await.unwrap_err();
+            assert!(err.contains("internal address"), "{}: {}", url, err);
+        }
+        assert!(check_url("https://93.184.216.34/hook", &[]).await.is_ok());
+        assert!(
+            check_url("http://10.96.0.1/hook", &["10.96.0.1".to_string()])
+                .await
+                .is_ok()
+        );
+        assert!(check_url("not a url", &[]).await.is_err());
+    }
+}
@@ -0,0 +1,232 @@
+//! Quick-switch suggestions.
+//!
+//! `/apis/suggest?q=` fuzzy-matches the query against API names, slugs and namespaces, the
+//! tags of their specs and their operations (method and path, summary, operation id), and
+//! returns the best matches with a link into the frontend. The portal pages open an
+//! overlay for it with Ctrl+K (Cmd+K on macOS), see `quick-switch.js`.
+
+use openapi_common::OpenApiDocument;
+use serde::Serialize;
+
+use crate::CachedApiEntry;
+use crate::frontend::FrontendType;
+
+pub const DEFAULT_LIMIT: usize = 20;
+pub const MAX_LIMIT: usize = 50;
+
+/// Script of the overlay, served at [`SCRIPT_PATH`] and included in every portal page
+pub const SCRIPT: &str = include_str!("../assets/quick-switch.js");
+pub const SCRIPT_PATH: &str = "/assets/quick-switch.js";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SuggestionKind {
+    Api,
+    Tag,
+    Operation,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Suggestion {
+    pub kind: SuggestionKind,
+    /// What matched, e.g. `GET /orders/{id}`
+    pub label: String,
+    /// Secondary text, e.g. the operation's summary
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// Name of the API the suggestion belongs to
+    pub api: String,
+    pub slug: String,
+    /// Link relative to the portal page, `?api=<slug>` plus the frontend's anchor
+    pub url: String,
+    #[serde(skip)]
+    score: i64,
+}
+
+/// The best `limit` matches of `query` among `apis`
+pub fn suggest(
+    apis: &[CachedApiEntry],
+    query: &str,
+    frontend: FrontendType,
+    limit: usize,
+) -> Vec<Suggestion> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    let mut suggestions = Vec::new();
+    for api in apis {
+        let page = format!("?api={}", urlencoding::encode(&api.slug));
+        let suggestion = |kind, label: String, detail: Option<String>, anchor: Option<String>| {
+            let url = match anchor {
+                Some(anchor) => format!("{}#{}", page, anchor),
+                None => page.clone(),
+            };
+            Suggestion {
+                kind,
+                label,
+                detail,
+                api: api.name.clone(),
+                slug: api.slug.clone(),
+                url,
+                score: 0,
+            }
+        };
+
+        if let Some(score) = [&api.name, &api.slug, &api.namespace]
+            .into_iter()
+            .filter_map(|text| fuzzy_score(&query, text))
+            .max()
+        {
+            suggestions.push(Suggestion {
+                score,
+                ..suggestion(
+                    SuggestionKind::Api,
+                    api.name.clone(),
+                    Some(api.namespace.clone()),
+                    None,
+                )
+            });
+        }
+
+        let Ok(document) = OpenApiDocument::parse(&api.spec) else {
+            continue;
+        };
+        for tag in document.tag_names() {
+            if let Some(score) = fuzzy_score(&query, &tag) {
+                suggestions.push(Suggestion {
+                    score,
+                    ..suggestion(
+                        SuggestionKind::Tag,
+                        tag.clone(),
+                        None,
+                        Some(frontend.tag_anchor(&tag)),
+                    )
+                });
+            }
+        }
+        for (path, method, operation) in document.public_operations() {
+            let label = format!("{} {}", method.to_ascii_uppercase(), path);
+            let Some(score) = [Some(&label), operation.summary.as_ref()]
+                .into_iter()
+                .chain([operation.operation_id.as_ref()])
+                .flatten()
+                .filter_map(|text| fuzzy_score(&query, text))
+                .max()
+            else {
+                continue;
+            };
+            suggestions.push(Suggestion {
+                score,
+                ..suggestion(
+                    SuggestionKind::Operation,
+                    label,
+                    operation.summary.clone(),
+                    frontend.operation_anchor(path, method, operation),
+                )
+            });
+        }
+    }
+
+    suggestions.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then_with(|| a.label.len().cmp(&b.label.len()))
+            .then_with(|| a.label.cmp(&b.label))
+    });
+    suggestions.truncate(limit);
+    suggestions
+}
+
+/// Score of `text` for a lowercase `query` whose characters must all appear in order;
+/// `None` when they do not. Consecutive characters, characters at the start of a word and
+/// matches near the start of the text score higher, long texts slightly lower.
+fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut score = 0i64;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+    for wanted in query.chars().filter(|c| !c.is_whitespace()) {
+        let found = position + text[position..].iter().position(|c| *c == wanted)?;
+        score += 1;
+        if previous.is_some_and(|previous| previous + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        if previous.is_none() {
+            score -= found.min(10) as i64;
+        }
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(score * 10 - (text.len() as i64 / 10))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: &str = r#"
+openapi: 3.0.3
+info: {title: Orders, version: '1'}
+tags: [{name: Order Items}]
+paths:
+  /orders/{id}:
+    get: {operationId: getOrder, summary: Fetch one order, tags: [Order Items]}
+  /admin/reindex:
+    post: {operationId: reindexOrders, x-internal: true}
+"#;
+
+    #[test]
+    fn scores_prefer_consecutive_characters_at_word_starts() {
+        assert_eq!(fuzzy_score("xyz", "orders"), None);
+        assert_eq!(fuzzy_score("sro", "orders"), None);
+        let prefix = fuzzy_score("ord", "orders").unwrap();
+        let word = fuzzy_score("ord", "legacy-orders").unwrap();
+        let scattered = fuzzy_score("ord", "oxrxdx").unwrap();
+        assert!(prefix > word, "{} > {}", prefix, word);
+        assert!(word > scattered, "{} > {}", word, scattered);
+        // Whitespace in the query is ignored, case in the text too
+        assert_eq!(
+            fuzzy_score("get or", "GET /orders"),
+            fuzzy_score("getor", "get /orders")
+        );
+    }
+
+    #[test]
+    fn suggestions_are_ranked_and_link_into_the_frontend() {
+        let apis = [
+            CachedApiEntry::example("shop", "orders", SPEC),
+            CachedApiEntry::example("archive", "legacy-order-history", ""),
+        ];
+
+        let suggestions = suggest(&apis, "orders", FrontendType::Scalar, DEFAULT_LIMIT);
+        let labels: Vec<_> = suggestions
+            .iter()
+            .map(|s| (s.kind, s.label.as_str()))
+            .collect();
+        assert_eq!(labels[0], (SuggestionKind::Api, "orders API"));
+        assert_eq!(suggestions[0].url, "?api=shop-orders");
+        assert_eq!(suggestions[0].detail.as_deref(), Some("shop"));
+        // The internal operation is not suggested
+        assert!(!labels.contains(&(SuggestionKind::Operation, "POST /admin/reindex")));
+
+        let operation = &suggest(&apis, "getorder", FrontendType::Scalar, 1)[0];
+        assert_eq!(operation.kind, SuggestionKind::Operation);
+        assert_eq!(operation.label, "GET /orders/{id}");
+        assert_eq!(operation.detail.as_deref(), Some("Fetch one order"));
+        assert_eq!(
+            operation.url,
+            "?api=shop-orders#tag/order-items/GET/orders/%7Bid%7D"
+        );
+        let tag = &suggest(&apis, "order items", FrontendType::Redoc, 1)[0];
+        assert_eq!(tag.kind, SuggestionKind::Tag);
+        assert_eq!(tag.url, "?api=shop-orders#tag/Order%20Items");
+
+        assert!(suggest(&apis, "  ", FrontendType::Scalar, DEFAULT_LIMIT).is_empty());
+        assert_eq!(suggest(&apis, "o", FrontendType::Scalar, 2).len(), 2);
+    }
+}
@@ -0,0 +1,224 @@
+//! Tenant-scoped catalogs.
+//!
+//! The caller's tenant is taken from a bearer token (`TENANT_TOKENS`) or from a header
+//! set by a trusted authenticating proxy (`TENANT_HEADER`). Callers only see the APIs of
+//! their own tenant plus the shared APIs without a tenant. When neither variable is set,
+//! tenancy is off and every API is visible.
+
+use axum::{
+    extract::FromRequestParts,
+    http::{HeaderMap, HeaderName, StatusCode, header, request::Parts},
+};
+use openapi_common::tenancy::{is_visible, parse_key_values};
+use std::collections::HashMap;
+
+use crate::AppState;
+use crate::frontend::Branding;
+
+pub const TENANT_HEADER_ENV: &str = "TENANT_HEADER";
+pub const TENANT_TOKENS_ENV: &str = "TENANT_TOKENS";
+pub const TENANT_TITLES_ENV: &str = "TENANT_TITLES";
+pub const TENANT_THEMES_ENV: &str = "TENANT_THEMES";
+
+#[derive(Debug, Clone, Default)]
+pub struct TenancyConfig {
+    /// Header carrying the tenant name, only trustworthy behind a proxy that sets it
+    header: Option<HeaderName>,
+    /// Bearer token -> tenant
+    tokens: HashMap<String, String>,
+    /// Tenant -> page title
+    titles: HashMap<String, String>,
+    /// Tenant -> Scalar theme
+    themes: HashMap<String, String>,
+}
+
+impl TenancyConfig {
+    pub fn from_env() -> Self {
+        let pairs = |name: &str| {
+            parse_key_values(&std::env::var(name).unwrap_or_default())
+                .into_iter()
+                .collect::<HashMap<_, _>>()
+        };
+        let header = std::env::var(TENANT_HEADER_ENV).ok().and_then(|name| {
+            HeaderName::try_from(name.trim())
+                .map_err(|e| tracing::warn!("Ignoring invalid {}: {}", TENANT_HEADER_ENV, e))
+                .ok()
+        });
+
+        let config = Self {
+            header,
+            tokens: pairs(TENANT_TOKENS_ENV),
+            titles: pairs(TENANT_TITLES_ENV),
+            themes: pairs(TENANT_THEMES_ENV),
+        };
+        if config.is_enabled() {
+            tracing::info!("Multi-tenancy enabled, catalogs are scoped to the caller's tenant");
+        }
+        config
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.header.is_some() || !self.tokens.is_empty()
+    }
+
+    /// Identifies the caller, rejecting bearer tokens that belong to no tenant
+    fn caller(&self, headers: &HeaderMap) -> Result<Caller, StatusCode> {
+        if !self.is_enabled() {
+            return Ok(Caller::Unrestricted);
+        }
+
+        if !self.tokens.is_empty()
+            && let Some(token) = headers
+                .get(header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "))
+        {
+            return match self.tokens.get(token.trim()) {
+                Some(tenant) => Ok(Caller::Tenant(Some(tenant.clone()))),
+                None => Err(StatusCode::UNAUTHORIZED),
+            };
+        }
+
+        let tenant = self
+            .header
+            .as_ref()
+            .and_then(|name| headers.get(name))
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|tenant| !tenant.is_empty())
+            .map(str::to_string);
+        Ok(Caller::Tenant(tenant))
+    }
+
+    /// Page branding for the caller's tenant
+    pub fn branding(&self, caller: &Caller) -> Branding {
+        let tenant = caller.tenant();
+        Branding {
+            title: tenant.and_then(|t| self.titles.get(t)).cloned(),
+            theme: tenant.and_then(|t| self.themes.get(t)).cloned(),
+        }
+    }
+}
+
+/// Who is asking, extracted from the request headers
+#[derive(Debug, Clone)]
+pub enum Caller {
+    /// Tenancy is disabled
+    Unrestricted,
+    /// The caller's tenant, `None` for anonymous callers who only see shared APIs
+    Tenant(Option<String>),
+}
+
+impl Caller {
+    pub fn tenant(&self) -> Option<&str> {
+        match self {
+            Caller::Unrestricted => None,
+            Caller::Tenant(tenant) => tenant.as_deref(),
+        }
+    }
+
+    /// Whether the caller manages something registered by `tenant` (stricter than `can_see`:
+    /// tenants do not manage what anonymous callers registered)
+    pub fn owns(&self, tenant: Option<&str>) -> bool {
+        match self {
+            Caller::Unrestricted => true,
+            Caller::Tenant(own) => own.as_deref() == tenant,
+        }
+    }
+
+    pub fn can_see(&self, entry_tenant: Option<&str>) -> bool {
+        match self {
+            Caller::Unrestricted => true,
+            Caller::Tenant(tenant) => is_visible(tenant.as_deref(), entry_tenant),
+        }
+    }
+}
+
+impl FromRequestParts<AppState> for Caller {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        state.tenancy.caller(&parts.headers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| {
+                (
+                    HeaderName::from_static(name),
+                    HeaderValue::from_static(value),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn callers_are_identified_by_token_or_header() {
+        assert!(matches!(
+            TenancyConfig::default().caller(&headers(&[("x-tenant", "retail")])),
+            Ok(Caller::Unrestricted)
+        ));
+
+        let config = TenancyConfig {
+            header: Some(HeaderName::from_static("x-tenant")),
+            tokens: HashMap::from([("s3cret".to_string(), "payments".to_string())]),
+            titles: HashMap::from([("payments".to_string(), "Payments APIs".to_string())]),
+            themes: HashMap::new(),
+        };
+        let tenant = |pairs| {
+            config
+                .caller(&headers(pairs))
+                .map(|c| c.tenant().map(str::to_string))
+        };
+        // A token wins over the header, an unknown one is rejected
+        assert_eq!(
+            tenant(&[("authorization", "Bearer s3cret"), ("x-tenant", "retail")]),
+            Ok(Some("payments".to_string()))
+        );
+        assert_eq!(
+            tenant(&[("authorization", "Bearer wrong")]),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            tenant(&[("x-tenant", " retail ")]),
+            Ok(Some("retail".to_string()))
+        );
+        assert_eq!(tenant(&[("x-tenant", "")]), Ok(None));
+
+        let payments = Caller::Tenant(Some("payments".to_string()));
+        assert_eq!(
+            config.branding(&payments).title.as_deref(),
+            Some("Payments APIs")
+        );
+        assert_eq!(config.branding(&Caller::Tenant(None)).title, None);
+    }
+
+    #[test]
+    fn tenants_see_their_own_and_shared_apis() {
+        let payments = Caller::Tenant(Some("payments".to_string()));
+        let anonymous = Caller::Tenant(None);
+
+        assert!(payments.can_see(Some("payments")));
+        assert!(payments.can_see(None));
+        assert!(!payments.can_see(Some("retail")));
+        assert!(anonymous.can_see(None));
+        assert!(!anonymous.can_see(Some("payments")));
+        assert!(Caller::Unrestricted.can_see(Some("retail")));
+
+        // Shared APIs are visible to tenants, but what anonymous callers registered is not theirs
+        assert!(payments.owns(Some("payments")));
+        assert!(!payments.owns(None));
+        assert!(anonymous.owns(None));
+        assert!(Caller::Unrestricted.owns(Some("retail")));
+    }
+}
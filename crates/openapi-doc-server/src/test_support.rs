@@ -0,0 +1,129 @@
+//! Local HTTP servers the tests point the Kubernetes client and spec bucket at.
+
+use axum::extract::Request;
+use axum::http::StatusCode;
+use kube::Client;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// A request received by [`TestServer`]
+#[derive(Debug, Clone)]
+pub struct Recorded {
+    pub method: String,
+    /// Path and query
+    pub uri: String,
+}
+
+impl Recorded {
+    pub fn path(&self) -> &str {
+        self.uri.split('?').next().unwrap_or_default()
+    }
+}
+
+pub struct TestServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<Recorded>>>,
+}
+
+impl TestServer {
+    /// Answers every request with the status and body `respond` returns for it
+    pub async fn start(
+        respond: impl Fn(&Recorded) -> (u16, String) + Clone + Send + Sync + 'static,
+    ) -> Self {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        let app = axum::Router::new().fallback(move |request: Request| {
+            let recorded = recorded.clone();
+            let respond = respond.clone();
+            async move {
+                let request = Recorded {
+                    method: request.method().to_string(),
+                    uri: request.uri().to_string(),
+                };
+                let (code, body) = respond(&request);
+                recorded.lock().unwrap().push(request);
+                (StatusCode::from_u16(code).unwrap(), body)
+            }
+        });
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        Self { url, requests }
+    }
+
+    /// Requests received so far, oldest first
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Read-only Kubernetes API server holding objects in memory, keyed by their API path.
+/// A list holds the objects stored directly under its path; label selectors are ignored.
+pub struct FakeApiServer {
+    server: TestServer,
+    objects: Arc<Mutex<HashMap<String, Value>>>,
+}
+
+impl FakeApiServer {
+    pub async fn start() -> Self {
+        let objects: Arc<Mutex<HashMap<String, Value>>> = Arc::default();
+        let stored = objects.clone();
+        let server = TestServer::start(move |request: &Recorded| {
+            let objects = stored.lock().unwrap();
+            let path = request.path();
+            if let Some(object) = objects.get(path) {
+                return (200, object.to_string());
+            }
+            let items: Vec<&Value> = objects
+                .iter()
+                .filter(|(key, _)| key.rsplit_once('/').map(|(parent, _)| parent) == Some(path))
+                .map(|(_, object)| object)
+                .collect();
+            if items.is_empty() && !is_collection(path) {
+                let status = json!({
+                    "kind": "Status",
+                    "apiVersion": "v1",
+                    "status": "Failure",
+                    "reason": "NotFound",
+                    "code": 404,
+                });
+                return (404, status.to_string());
+            }
+            let list = json!({ "metadata": { "resourceVersion": "1" }, "items": items });
+            (200, list.to_string())
+        })
+        .await;
+        Self { server, objects }
+    }
+
+    pub fn client(&self) -> Client {
+        let config = kube::Config::new(self.server.url.parse().unwrap());
+        Client::try_from(config).unwrap()
+    }
+
+    pub fn insert(&self, path: &str, object: impl serde::Serialize) {
+        let object = serde_json::to_value(object).unwrap();
+        self.objects
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), object);
+    }
+
+    /// Requests received so far, oldest first
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.server.requests()
+    }
+}
+
+/// Whether the path names a list of objects rather than one object
+fn is_collection(path: &str) -> bool {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    // `api/v1/...` or `apis/<group>/<version>/...`
+    let resource = match segments.first() {
+        Some(&"apis") => &segments[3.min(segments.len())..],
+        _ => &segments[2.min(segments.len())..],
+    };
+    resource.len() % 2 == 1
+}
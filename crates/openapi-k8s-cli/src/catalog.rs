@@ -0,0 +1,102 @@
+//! Access to the discovery catalog, either from the ConfigMap or from a running doc server.
+
+use kube::Client;
+use openapi_common::ApiInventoryEntry;
+use openapi_common::catalog::{self, fetch_url};
+use openapi_common::signing::SpecSignature;
+use serde::Deserialize;
+
+use crate::error::CliError;
+
+/// Where catalog entries and specs are read from
+#[derive(Debug, Clone)]
+pub enum CatalogSource {
+    /// The operator's discovery ConfigMap, read through the current kubeconfig context
+    ConfigMap { namespace: String, name: String },
+    /// A doc server, e.g. `http://localhost:3000` after `kubectl port-forward`
+    Server { base_url: String },
+}
+
+/// Response of the doc server's `/apis` endpoint
+#[derive(Deserialize)]
+struct ServerApiList {
+    apis: Vec<ApiInventoryEntry>,
+}
+
+impl CatalogSource {
+    /// Loads all catalog entries, sorted by namespace and name
+    pub async fn load_entries(&self) -> Result<Vec<ApiInventoryEntry>, CliError> {
+        let mut apis = match self {
+            CatalogSource::ConfigMap { namespace, name } => {
+                let client = Client::try_default().await?;
+                catalog::read_configmap(client, namespace, name).await?
+            }
+            CatalogSource::Server { base_url } => {
+                let url = format!("{}/apis", base_url.trim_end_matches('/'));
+                reqwest::get(&url)
+                    .await?
+                    .error_for_status()?
+                    .json::<ServerApiList>()
+                    .await?
+                    .apis
+            }
+        };
+
+        apis.sort_by(|a, b| (&a.namespace, &a.name).cmp(&(&b.namespace, &b.name)));
+        Ok(apis)
+    }
+
+    /// Finds a single entry by display name, id (`namespace.service`), or service name
+    pub async fn find_entry(&self, query: &str) -> Result<ApiInventoryEntry, CliError> {
+        let apis = self.load_entries().await?;
+        Ok(catalog::find_entry(&apis, query)?.clone())
+    }
+
+    /// Downloads the spec of an entry. The doc server serves its cached copy (looked up by
+    /// id, which unlike the display name is unique); without a server the entry URL is
+    /// fetched directly, which only works from inside the cluster.
+    pub async fn fetch_spec(&self, entry: &ApiInventoryEntry) -> Result<String, CliError> {
+        let url = match self {
+            CatalogSource::Server { base_url } => format!(
+                "{}/specs/{}",
+                base_url.trim_end_matches('/'),
+                urlencoding::encode(&entry.id)
+            ),
+            CatalogSource::ConfigMap { .. } => entry.url.clone(),
+        };
+
+        Ok(fetch_url(&url).await?)
+    }
+
+    /// Downloads the detached signature of the spec served by `fetch_spec`. Only doc servers
+    /// reading `DiscoveredApi` resources have signatures; specs fetched from the service
+    /// URL are never signed.
+    pub async fn fetch_signature(
+        &self,
+        entry: &ApiInventoryEntry,
+    ) -> Result<SpecSignature, CliError> {
+        let CatalogSource::Server { base_url } = self else {
+            return Err(CliError::Message(
+                "Signatures can only be checked against a doc server (--server)".to_string(),
+            ));
+        };
+        let url = format!(
+            "{}/signatures/{}",
+            base_url.trim_end_matches('/'),
+            urlencoding::encode(&entry.id)
+        );
+        let response = reqwest::get(&url).await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(CliError::Message(format!(
+                "The spec of '{}' is not signed",
+                entry.name
+            )));
+        }
+        Ok(response.error_for_status()?.json().await?)
+    }
+
+    /// Downloads the spec straight from the service URL recorded in the entry
+    pub async fn fetch_live_spec(&self, entry: &ApiInventoryEntry) -> Result<String, CliError> {
+        Ok(fetch_url(&entry.url).await?)
+    }
+}
@@ -0,0 +1,149 @@
+use openapi_common::OpenApiDocument;
+use openapi_common::diff::{Severity, SpecDiff, diff_documents};
+
+use super::EXIT_FINDINGS;
+use crate::catalog::CatalogSource;
+use crate::error::CliError;
+use crate::output::{self, Format};
+
+/// A spec revision that can be compared
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Revision {
+    /// The spec currently served by the catalog
+    Catalog,
+    /// The spec fetched directly from the service URL
+    Live,
+    /// A local file
+    File(String),
+}
+
+impl Revision {
+    fn parse(rev: &str) -> Result<Self, CliError> {
+        match rev {
+            "catalog" => Ok(Revision::Catalog),
+            "live" => Ok(Revision::Live),
+            _ => match rev.strip_prefix("file:") {
+                Some(path) if !path.is_empty() => Ok(Revision::File(path.to_string())),
+                _ => Err(CliError::Message(format!(
+                    "Unknown revision '{}', expected 'catalog', 'live' or 'file:<path>'",
+                    rev
+                ))),
+            },
+        }
+    }
+}
+
+async fn load_revision(
+    source: &CatalogSource,
+    api: &str,
+    revision: &Revision,
+) -> Result<OpenApiDocument, CliError> {
+    let content = match revision {
+        Revision::Catalog => source.fetch_spec(&source.find_entry(api).await?).await?,
+        Revision::Live => source.fetch_live_spec(&source.find_entry(api).await?).await?,
+        Revision::File(path) => std::fs::read_to_string(path)?,
+    };
+    OpenApiDocument::parse(&content).map_err(|e| {
+        CliError::Message(format!("Revision {:?} is not a valid spec: {}", revision, e))
+    })
+}
+
+pub async fn run(
+    source: &CatalogSource,
+    api: &str,
+    from: &str,
+    to: &str,
+    fail_on_changes: bool,
+    format: Format,
+) -> Result<i32, CliError> {
+    let old = load_revision(source, api, &Revision::parse(from)?).await?;
+    let new = load_revision(source, api, &Revision::parse(to)?).await?;
+    let diff = diff_documents(&old, &new);
+
+    if format == Format::Json {
+        output::print_json(&diff)?;
+    } else if diff.is_empty() {
+        println!("No changes between {} and {}", from, to);
+    } else {
+        output::print_table(&["SEVERITY", "LOCATION", "CHANGE"], &rows(&diff));
+        println!();
+        println!("{}", diff.summary());
+    }
+
+    Ok(exit_code(&diff, fail_on_changes))
+}
+
+fn rows(diff: &SpecDiff) -> Vec<Vec<String>> {
+    diff.changes
+        .iter()
+        .map(|change| {
+            vec![
+                match change.severity {
+                    Severity::Breaking => "BREAKING".to_string(),
+                    Severity::NonBreaking => "ok".to_string(),
+                },
+                change.location.clone(),
+                change.message.clone(),
+            ]
+        })
+        .collect()
+}
+
+/// [`EXIT_FINDINGS`] for breaking changes, or for any change with `--fail-on-changes`
+fn exit_code(diff: &SpecDiff, fail_on_changes: bool) -> i32 {
+    let failed = diff.has_breaking() || (fail_on_changes && !diff.is_empty());
+    if failed { EXIT_FINDINGS } else { 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(paths: &str) -> OpenApiDocument {
+        OpenApiDocument::parse(&format!(
+            r#"{{"openapi": "3.0.3", "info": {{"title": "Orders", "version": "1"}}, "paths": {}}}"#,
+            paths
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn revisions_are_catalog_live_or_a_file() {
+        assert_eq!(Revision::parse("catalog").unwrap(), Revision::Catalog);
+        assert_eq!(Revision::parse("live").unwrap(), Revision::Live);
+        assert_eq!(
+            Revision::parse("file:specs/orders.yaml").unwrap(),
+            Revision::File("specs/orders.yaml".to_string())
+        );
+        for invalid in ["file:", "staging", ""] {
+            assert_eq!(
+                Revision::parse(invalid).unwrap_err().to_string(),
+                format!(
+                    "Unknown revision '{}', expected 'catalog', 'live' or 'file:<path>'",
+                    invalid
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn only_breaking_changes_fail_unless_asked_to() {
+        let list = r#"{"/orders": {"get": {"responses": {}}}}"#;
+        let list_and_create =
+            r#"{"/orders": {"get": {"responses": {}}, "post": {"responses": {}}}}"#;
+
+        let unchanged = diff_documents(&document(list), &document(list));
+        assert!(rows(&unchanged).is_empty());
+        assert_eq!(exit_code(&unchanged, true), 0);
+
+        let added = diff_documents(&document(list), &document(list_and_create));
+        assert!(!added.is_empty());
+        assert!(rows(&added).iter().all(|row| row[0] == "ok"));
+        assert_eq!(exit_code(&added, false), 0);
+        assert_eq!(exit_code(&added, true), EXIT_FINDINGS);
+
+        let removed = diff_documents(&document(list_and_create), &document(list));
+        assert!(rows(&removed).iter().any(|row| row[0] == "BREAKING"));
+        assert_eq!(exit_code(&removed, false), EXIT_FINDINGS);
+    }
+}
@@ -0,0 +1,244 @@
+use chrono::{DateTime, Utc};
+use openapi_common::{ApiInventoryEntry, Lifecycle, OpenApiDocument};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use super::EXIT_FINDINGS;
+use crate::catalog::CatalogSource;
+use crate::error::CliError;
+use crate::output::{self, Format};
+
+/// Name of the index file written next to the exported specs
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Which catalog entries to export
+#[derive(Debug, Default)]
+pub struct ExportFilter {
+    /// Only APIs in these namespaces (all when empty)
+    pub namespaces: Vec<String>,
+    /// Only APIs whose spec uses at least one of these tags (all when empty)
+    pub tags: Vec<String>,
+    /// Only APIs in these lifecycle stages (all when empty)
+    pub lifecycles: Vec<Lifecycle>,
+}
+
+impl ExportFilter {
+    /// Whether the entry is in one of the namespaces and lifecycle stages
+    fn matches_entry(&self, api: &ApiInventoryEntry) -> bool {
+        (self.namespaces.is_empty() || self.namespaces.contains(&api.namespace))
+            && (self.lifecycles.is_empty()
+                || api
+                    .lifecycle
+                    .is_some_and(|stage| self.lifecycles.contains(&stage)))
+    }
+
+    /// Whether a spec using `tags` uses one of the tags
+    fn matches_tags(&self, tags: &[String]) -> bool {
+        self.tags.is_empty() || tags.iter().any(|t| self.tags.contains(t))
+    }
+}
+
+/// Index of an export directory
+#[derive(Serialize, Debug)]
+struct Manifest {
+    exported_at: DateTime<Utc>,
+    apis: Vec<ManifestEntry>,
+    /// Entries that matched the filter but could not be downloaded
+    failed: Vec<FailedEntry>,
+}
+
+#[derive(Serialize, Debug)]
+struct ManifestEntry {
+    #[serde(flatten)]
+    entry: ApiInventoryEntry,
+    /// Spec file relative to the export directory
+    file: String,
+    title: String,
+    version: String,
+    spec_version: Option<String>,
+    tags: Vec<String>,
+}
+
+impl ManifestEntry {
+    /// Entry of the spec `body` of `api`, saved as `<id>.json` or `<id>.yaml`
+    fn new(api: ApiInventoryEntry, body: &str) -> Self {
+        // Unparseable specs are still exported as-is, but can't match a tag filter
+        let document = OpenApiDocument::parse(body).ok();
+        let extension = if serde_json::from_str::<serde_json::Value>(body).is_ok() {
+            "json"
+        } else {
+            "yaml"
+        };
+        ManifestEntry {
+            file: format!("{}.{}", api.id, extension),
+            title: document
+                .as_ref()
+                .map(|d| d.info.title.clone())
+                .unwrap_or_default(),
+            version: document
+                .as_ref()
+                .map(|d| d.info.version.clone())
+                .unwrap_or_default(),
+            spec_version: document
+                .as_ref()
+                .and_then(|d| d.spec_version().map(str::to_string)),
+            tags: document
+                .as_ref()
+                .map(OpenApiDocument::tag_names)
+                .unwrap_or_default(),
+            entry: api,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct FailedEntry {
+    id: String,
+    name: String,
+    namespace: String,
+    error: String,
+}
+
+pub async fn run(
+    source: &CatalogSource,
+    dir: &Path,
+    filter: &ExportFilter,
+    format: Format,
+) -> Result<i32, CliError> {
+    let apis: Vec<ApiInventoryEntry> = source
+        .load_entries()
+        .await?
+        .into_iter()
+        .filter(|api| filter.matches_entry(api))
+        .collect();
+
+    std::fs::create_dir_all(dir)?;
+    let mut manifest = Manifest {
+        exported_at: Utc::now(),
+        apis: Vec::new(),
+        failed: Vec::new(),
+    };
+
+    for api in apis {
+        let body = match source.fetch_spec(&api).await {
+            Ok(body) => body,
+            Err(e) => {
+                manifest.failed.push(FailedEntry {
+                    id: api.id.clone(),
+                    name: api.name.clone(),
+                    namespace: api.namespace.clone(),
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        };
+
+        let entry = ManifestEntry::new(api, &body);
+        if !filter.matches_tags(&entry.tags) {
+            continue;
+        }
+        std::fs::write(dir.join(&entry.file), &body)?;
+        manifest.apis.push(entry);
+    }
+
+    let manifest_path: PathBuf = dir.join(MANIFEST_FILE);
+    std::fs::write(&manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+
+    if format == Format::Json {
+        output::print_json(&manifest)?;
+    } else {
+        let rows: Vec<Vec<String>> = manifest
+            .apis
+            .iter()
+            .map(|api| {
+                vec![
+                    api.entry.name.clone(),
+                    api.entry.namespace.clone(),
+                    api.file.clone(),
+                    "exported".to_string(),
+                ]
+            })
+            .chain(manifest.failed.iter().map(|failed| {
+                vec![
+                    failed.name.clone(),
+                    failed.namespace.clone(),
+                    String::new(),
+                    format!("failed: {}", failed.error),
+                ]
+            }))
+            .collect();
+        output::print_table(&["NAME", "NAMESPACE", "FILE", "RESULT"], &rows);
+        println!();
+        println!(
+            "Exported {} specs to {} ({} failed)",
+            manifest.apis.len(),
+            dir.display(),
+            manifest.failed.len()
+        );
+    }
+
+    Ok(if manifest.failed.is_empty() {
+        0
+    } else {
+        EXIT_FINDINGS
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(namespace: &str, lifecycle: Option<Lifecycle>) -> ApiInventoryEntry {
+        ApiInventoryEntry {
+            lifecycle,
+            ..ApiInventoryEntry::new(namespace, "orders", "http://orders/openapi.json")
+        }
+    }
+
+    #[test]
+    fn filters_combine_namespaces_lifecycles_and_tags() {
+        let everything = ExportFilter::default();
+        assert!(everything.matches_entry(&entry("shop", None)));
+        assert!(everything.matches_tags(&[]));
+
+        let filter = ExportFilter {
+            namespaces: vec!["shop".to_string()],
+            tags: vec!["orders".to_string(), "carts".to_string()],
+            lifecycles: vec![Lifecycle::Beta, Lifecycle::Ga],
+        };
+        assert!(filter.matches_entry(&entry("shop", Some(Lifecycle::Ga))));
+        assert!(!filter.matches_entry(&entry("billing", Some(Lifecycle::Ga))));
+        assert!(!filter.matches_entry(&entry("shop", Some(Lifecycle::Deprecated))));
+        // APIs without a declared stage match no lifecycle filter
+        assert!(!filter.matches_entry(&entry("shop", None)));
+        assert!(filter.matches_tags(&["admin".to_string(), "carts".to_string()]));
+        assert!(!filter.matches_tags(&["admin".to_string()]));
+        assert!(!filter.matches_tags(&[]));
+    }
+
+    #[test]
+    fn manifest_entries_keep_the_format_and_describe_the_spec() {
+        let yaml = "openapi: 3.0.3\ninfo:\n  title: Orders\n  version: 1.2.0\ntags:\n  - name: orders\npaths: {}\n";
+        let yaml = ManifestEntry::new(entry("shop", None), yaml);
+        assert_eq!(yaml.file, "shop.orders.yaml");
+        assert_eq!(
+            (yaml.title.as_str(), yaml.version.as_str()),
+            ("Orders", "1.2.0")
+        );
+        assert_eq!(yaml.spec_version.as_deref(), Some("3.0.3"));
+        assert_eq!(yaml.tags, ["orders"]);
+
+        let json = ManifestEntry::new(entry("shop", None), r#"{"swagger": "2.0", "paths": {}}"#);
+        assert_eq!(json.file, "shop.orders.json");
+        assert_eq!(json.spec_version.as_deref(), Some("2.0"));
+
+        // Specs that are not documents are exported without metadata
+        let html = ManifestEntry::new(entry("shop", None), "<html>502 Bad Gateway</html>");
+        assert_eq!(html.file, "shop.orders.yaml");
+        assert!(html.title.is_empty() && html.spec_version.is_none() && html.tags.is_empty());
+
+        let manifest = serde_json::to_value(&json).unwrap();
+        assert_eq!(manifest["id"], "shop.orders");
+        assert_eq!(manifest["file"], "shop.orders.json");
+    }
+}
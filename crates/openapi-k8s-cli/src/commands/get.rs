@@ -0,0 +1,67 @@
+use openapi_common::signing::SpecVerifier;
+use openapi_common::spec_utils;
+use std::path::Path;
+
+use crate::catalog::CatalogSource;
+use crate::error::CliError;
+use crate::output;
+
+pub async fn run(
+    source: &CatalogSource,
+    api: &str,
+    raw: bool,
+    verify_key: Option<&Path>,
+) -> Result<(), CliError> {
+    let entry = source.find_entry(api).await?;
+    let spec = source.fetch_spec(&entry).await?;
+
+    // Nothing is printed unless the signature matches
+    if let Some(key) = verify_key {
+        let verifier = SpecVerifier::from_pem(&std::fs::read_to_string(key)?)?;
+        let signature = source.fetch_signature(&entry).await?;
+        verifier.verify(&spec, &signature)?;
+        eprintln!(
+            "Verified signature of '{}' (key {})",
+            entry.name,
+            verifier.key_id()
+        );
+    }
+
+    if raw {
+        println!("{}", spec);
+        return Ok(());
+    }
+
+    output::print_json(&as_json(&entry.name, &spec)?)
+}
+
+/// The spec as JSON, whether it was served as JSON or YAML
+fn as_json(name: &str, spec: &str) -> Result<serde_json::Value, CliError> {
+    spec_utils::parse_spec_to_json(spec).map_err(|e| {
+        CliError::Message(format!("Spec of '{}' is not valid JSON/YAML: {}", name, e))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yaml_specs_are_printed_as_json() {
+        let spec = "openapi: 3.0.3\ninfo:\n  title: Orders\n  version: '1'\npaths: {}\n";
+        assert_eq!(
+            as_json("orders API", spec).unwrap(),
+            serde_json::json!({
+                "openapi": "3.0.3",
+                "info": { "title": "Orders", "version": "1" },
+                "paths": {},
+            })
+        );
+        let err = as_json("orders API", "{ not json").unwrap_err().to_string();
+        assert!(
+            err.starts_with("Spec of 'orders API' is not valid JSON/YAML"),
+            "{}",
+            err
+        );
+    }
+}
@@ -0,0 +1,331 @@
+use openapi_common::annotations::{
+    AnnotationIssue, ApiDocAnnotations, IssueSeverity, NAMESPACE_DEFAULT_ANNOTATIONS, PortRef,
+    is_known_annotation,
+};
+use openapi_common::{API_DOC_PORT_ANNOTATION, API_DOC_PORT_NAME_ANNOTATION};
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Read;
+
+use super::EXIT_FINDINGS;
+use crate::error::CliError;
+use crate::output::{self, Format};
+
+/// Resource kinds whose annotations are checked
+const LINTED_KINDS: &[&str] = &["Service", "Namespace"];
+
+/// Issues found on one resource
+#[derive(Serialize, Debug)]
+struct ResourceReport {
+    file: String,
+    kind: String,
+    name: String,
+    issues: Vec<AnnotationIssue>,
+}
+
+/// A resource found in a manifest
+struct Resource {
+    kind: String,
+    name: String,
+    annotations: BTreeMap<String, String>,
+    /// `(name, port)` of the Service's ports
+    ports: Vec<(Option<String>, i32)>,
+}
+
+impl Resource {
+    /// Annotation issues, plus a port name the manifest does not declare
+    fn issues(&self) -> Vec<AnnotationIssue> {
+        let (parsed, mut issues) = ApiDocAnnotations::parse(&self.annotations);
+        if self.kind == "Namespace" {
+            // Port names are resolved against each Service of the namespace
+            for key in self.annotations.keys().filter(|key| {
+                is_known_annotation(key) && !NAMESPACE_DEFAULT_ANNOTATIONS.contains(&key.as_str())
+            }) {
+                issues.push(AnnotationIssue {
+                    severity: IssueSeverity::Warning,
+                    annotation: key.clone(),
+                    message: "only applies to Services, ignored on a Namespace".to_string(),
+                });
+            }
+            return issues;
+        }
+        let ports = self
+            .ports
+            .iter()
+            .map(|(name, port)| (name.as_deref(), *port));
+        if let Some(port @ PortRef::Name(name)) = &parsed.port
+            && port.resolve(ports).is_none()
+        {
+            let annotation = if self.annotations.contains_key(API_DOC_PORT_NAME_ANNOTATION) {
+                API_DOC_PORT_NAME_ANNOTATION
+            } else {
+                API_DOC_PORT_ANNOTATION
+            };
+            issues.push(AnnotationIssue {
+                severity: IssueSeverity::Error,
+                annotation: annotation.to_string(),
+                message: format!("the Service has no port named \"{}\"", name),
+            });
+        }
+        issues
+    }
+}
+
+/// Extracts the resources of a manifest, descending into `List` items
+fn collect_resources(doc: &serde_json::Value, out: &mut Vec<Resource>) {
+    let kind = doc.get("kind").and_then(|k| k.as_str()).unwrap_or_default();
+    if kind.ends_with("List") {
+        if let Some(items) = doc.get("items").and_then(|i| i.as_array()) {
+            for item in items {
+                collect_resources(item, out);
+            }
+        }
+        return;
+    }
+    if !LINTED_KINDS.contains(&kind) {
+        return;
+    }
+
+    let metadata = doc.get("metadata");
+    let name = metadata
+        .and_then(|m| m.get("name"))
+        .and_then(|n| n.as_str())
+        .unwrap_or("<unnamed>")
+        .to_string();
+    let annotations = metadata
+        .and_then(|m| m.get("annotations"))
+        .and_then(|a| a.as_object())
+        .map(|a| {
+            a.iter()
+                .map(|(k, v)| {
+                    let value = v
+                        .as_str()
+                        .map(str::to_string)
+                        .unwrap_or_else(|| v.to_string());
+                    (k.clone(), value)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let ports = doc
+        .pointer("/spec/ports")
+        .and_then(|p| p.as_array())
+        .map(|ports| {
+            ports
+                .iter()
+                .map(|p| {
+                    let name = p.get("name").and_then(|n| n.as_str()).map(str::to_string);
+                    let port = p.get("port").and_then(|n| n.as_i64()).unwrap_or_default();
+                    (name, port as i32)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    out.push(Resource {
+        kind: kind.to_string(),
+        name,
+        annotations,
+        ports,
+    });
+}
+
+fn read_input(file: &str) -> Result<String, CliError> {
+    if file == "-" {
+        let mut content = String::new();
+        std::io::stdin().read_to_string(&mut content)?;
+        Ok(content)
+    } else {
+        Ok(std::fs::read_to_string(file)?)
+    }
+}
+
+/// Reports of the Services and Namespaces in the (multi-document) YAML `content`
+fn lint(file: &str, content: &str) -> Result<Vec<ResourceReport>, CliError> {
+    let mut reports = Vec::new();
+    for document in serde_yaml::Deserializer::from_str(content) {
+        let doc = serde_json::Value::deserialize(document)
+            .map_err(|e| CliError::Message(format!("{} is not valid YAML: {}", file, e)))?;
+        let mut resources = Vec::new();
+        collect_resources(&doc, &mut resources);
+        for resource in resources {
+            reports.push(ResourceReport {
+                file: file.to_string(),
+                issues: resource.issues(),
+                kind: resource.kind,
+                name: resource.name,
+            });
+        }
+    }
+    Ok(reports)
+}
+
+/// Numbers of errors and warnings
+fn count_issues(reports: &[ResourceReport]) -> (usize, usize) {
+    let issues = || reports.iter().flat_map(|r| r.issues.iter());
+    let errors = issues()
+        .filter(|i| i.severity == IssueSeverity::Error)
+        .count();
+    (errors, issues().count() - errors)
+}
+
+pub fn run(files: &[String], strict: bool, format: Format) -> Result<i32, CliError> {
+    let mut reports = Vec::new();
+    for file in files {
+        reports.extend(lint(file, &read_input(file)?)?);
+    }
+    let (errors, warnings) = count_issues(&reports);
+
+    if format == Format::Json {
+        output::print_json(&reports)?;
+    } else {
+        let rows: Vec<Vec<String>> = reports
+            .iter()
+            .flat_map(|report| {
+                report.issues.iter().map(move |issue| {
+                    vec![
+                        report.file.clone(),
+                        format!("{}/{}", report.kind, report.name),
+                        match issue.severity {
+                            IssueSeverity::Error => "error".to_string(),
+                            IssueSeverity::Warning => "warning".to_string(),
+                        },
+                        issue.annotation.clone(),
+                        issue.message.clone(),
+                    ]
+                })
+            })
+            .collect();
+        if !rows.is_empty() {
+            output::print_table(
+                &["FILE", "RESOURCE", "SEVERITY", "ANNOTATION", "MESSAGE"],
+                &rows,
+            );
+            println!();
+        }
+        println!(
+            "Checked {} resources: {} errors, {} warnings",
+            reports.len(),
+            errors,
+            warnings
+        );
+    }
+
+    let failed = errors > 0 || (strict && warnings > 0);
+    Ok(if failed { EXIT_FINDINGS } else { 0 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `(resource, severity, annotation)` of every issue
+    fn issues(reports: &[ResourceReport]) -> Vec<(String, IssueSeverity, String)> {
+        reports
+            .iter()
+            .flat_map(|report| {
+                report.issues.iter().map(|issue| {
+                    (
+                        format!("{}/{}", report.kind, report.name),
+                        issue.severity,
+                        issue.annotation.clone(),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn services_are_checked_with_the_rules_of_the_webhook() {
+        let manifests = r#"
+apiVersion: v1
+kind: Service
+metadata:
+  name: orders
+  annotations:
+    api-doc.io/enabled: "yes"
+    api-doc.io/path: openapi.json
+    api-doc.io/port-name: metrics
+spec:
+  ports:
+    - name: http
+      port: 8080
+---
+apiVersion: v1
+kind: Service
+metadata:
+  name: carts
+  annotations:
+    api-doc.io/enabled: "true"
+    api-doc.io/port-name: http
+spec:
+  ports:
+    - name: http
+      port: 8080
+---
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: orders
+  annotations:
+    api-doc.io/path: openapi.json
+"#;
+        let reports = lint("services.yaml", manifests).unwrap();
+        assert_eq!(reports.len(), 2, "Deployments are not linted");
+        assert_eq!(
+            issues(&reports),
+            [
+                (
+                    "Service/orders".to_string(),
+                    IssueSeverity::Error,
+                    "api-doc.io/enabled".to_string()
+                ),
+                (
+                    "Service/orders".to_string(),
+                    IssueSeverity::Error,
+                    "api-doc.io/path".to_string()
+                ),
+                (
+                    "Service/orders".to_string(),
+                    IssueSeverity::Error,
+                    API_DOC_PORT_NAME_ANNOTATION.to_string()
+                ),
+            ]
+        );
+        assert_eq!(
+            reports[0].issues[2].message,
+            "the Service has no port named \"metrics\""
+        );
+        assert_eq!(count_issues(&reports), (3, 0));
+    }
+
+    #[test]
+    fn namespaces_in_lists_only_take_the_default_annotations() {
+        let list = r#"
+apiVersion: v1
+kind: List
+items:
+  - apiVersion: v1
+    kind: Namespace
+    metadata:
+      name: shop
+      annotations:
+        api-doc.io/enabled: "true"
+        api-doc.io/path: /openapi.json
+        api-doc.io/name: Shop
+"#;
+        let reports = lint("-", list).unwrap();
+        assert_eq!(
+            issues(&reports),
+            [(
+                "Namespace/shop".to_string(),
+                IssueSeverity::Warning,
+                "api-doc.io/name".to_string()
+            )]
+        );
+        assert_eq!(count_issues(&reports), (0, 1));
+
+        let err = lint("broken.yaml", "kind: [").unwrap_err().to_string();
+        assert!(err.starts_with("broken.yaml is not valid YAML"), "{}", err);
+    }
+}
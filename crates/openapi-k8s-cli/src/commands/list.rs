@@ -0,0 +1,84 @@
+use openapi_common::ApiInventoryEntry;
+
+use crate::catalog::CatalogSource;
+use crate::error::CliError;
+use crate::output::{self, Format};
+
+pub async fn run(source: &CatalogSource, format: Format) -> Result<(), CliError> {
+    let apis = source.load_entries().await?;
+
+    if format == Format::Json {
+        return output::print_json(&apis);
+    }
+
+    let rows = rows(&apis);
+    output::print_table(
+        &[
+            "NAME",
+            "NAMESPACE",
+            "SERVICE",
+            "LIFECYCLE",
+            "AVAILABLE",
+            "LAST UPDATED",
+        ],
+        &rows,
+    );
+    Ok(())
+}
+
+fn rows(apis: &[ApiInventoryEntry]) -> Vec<Vec<String>> {
+    apis.iter()
+        .map(|api| {
+            vec![
+                api.name.clone(),
+                api.namespace.clone(),
+                api.service_name.clone(),
+                api.lifecycle.map(|l| l.to_string()).unwrap_or_default(),
+                api.available.to_string(),
+                api.last_updated.format("%Y-%m-%d %H:%M:%S").to_string(),
+            ]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use openapi_common::Lifecycle;
+
+    #[test]
+    fn rows_show_the_lifecycle_and_availability() {
+        let ga = ApiInventoryEntry {
+            lifecycle: Some(Lifecycle::Ga),
+            last_updated: chrono::Utc.with_ymd_and_hms(2026, 3, 1, 12, 30, 0).unwrap(),
+            ..ApiInventoryEntry::new("shop", "orders", "http://orders.shop.svc/openapi.json")
+        };
+        let unavailable = ApiInventoryEntry {
+            available: false,
+            ..ga.clone()
+        };
+
+        assert_eq!(
+            rows(&[ga, unavailable]),
+            [
+                [
+                    "orders API",
+                    "shop",
+                    "orders",
+                    "ga",
+                    "true",
+                    "2026-03-01 12:30:00"
+                ],
+                [
+                    "orders API",
+                    "shop",
+                    "orders",
+                    "ga",
+                    "false",
+                    "2026-03-01 12:30:00"
+                ],
+            ]
+        );
+    }
+}
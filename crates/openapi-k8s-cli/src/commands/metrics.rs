@@ -0,0 +1,87 @@
+use openapi_common::complexity::SpecMetrics;
+use serde::Serialize;
+use std::path::PathBuf;
+
+use crate::catalog::CatalogSource;
+use crate::error::CliError;
+use crate::output::{self, Format};
+
+#[derive(Serialize)]
+struct Measured {
+    api: String,
+    #[serde(flatten)]
+    metrics: SpecMetrics,
+}
+
+fn measure(api: String, content: &str) -> Result<Measured, CliError> {
+    let metrics = SpecMetrics::compute(content)
+        .map_err(|e| CliError::Message(format!("Spec of '{}' is not a valid spec: {}", api, e)))?;
+    Ok(Measured { api, metrics })
+}
+
+/// Measures local spec files, or the given catalog APIs (all available ones when empty)
+pub async fn run(
+    source: &CatalogSource,
+    apis: &[String],
+    files: &[PathBuf],
+    format: Format,
+) -> Result<(), CliError> {
+    let mut measured = Vec::new();
+    if !files.is_empty() {
+        for file in files {
+            let content = std::fs::read_to_string(file)?;
+            measured.push(measure(file.display().to_string(), &content)?);
+        }
+    } else {
+        let entries = if apis.is_empty() {
+            source
+                .load_entries()
+                .await?
+                .into_iter()
+                .filter(|entry| entry.available)
+                .collect()
+        } else {
+            let mut entries = Vec::new();
+            for api in apis {
+                entries.push(source.find_entry(api).await?);
+            }
+            entries
+        };
+        for entry in entries {
+            let content = source.fetch_spec(&entry).await?;
+            measured.push(measure(entry.name, &content)?);
+        }
+    }
+
+    if format == Format::Json {
+        return output::print_json(&measured);
+    }
+
+    let rows: Vec<Vec<String>> = measured
+        .iter()
+        .map(|m| {
+            vec![
+                m.api.clone(),
+                m.metrics.paths.to_string(),
+                m.metrics.operations.to_string(),
+                m.metrics.schemas.to_string(),
+                m.metrics.max_schema_depth.to_string(),
+                format!("{:.1}", m.metrics.avg_parameters),
+                m.metrics.size_bytes.to_string(),
+            ]
+        })
+        .collect();
+    output::print_table(
+        &[
+            "API",
+            "PATHS",
+            "OPERATIONS",
+            "SCHEMAS",
+            "DEPTH",
+            "AVG PARAMS",
+            "SIZE",
+        ],
+        &rows,
+    );
+    Ok(())
+}
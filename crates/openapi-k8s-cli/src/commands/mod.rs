@@ -0,0 +1,26 @@
+/// `list` subcommand
+pub mod list;
+
+/// `get` subcommand
+pub mod get;
+
+/// `status` subcommand
+pub mod status;
+
+/// `diff` subcommand
+pub mod diff;
+
+/// `lint` subcommand
+pub mod lint;
+
+/// `export` subcommand
+pub mod export;
+
+/// `verify` subcommand
+pub mod verify;
+
+/// `metrics` subcommand
+pub mod metrics;
+
+/// Exit code for commands that ran fine but found problems (breaking changes, lint errors)
+pub const EXIT_FINDINGS: i32 = 2;
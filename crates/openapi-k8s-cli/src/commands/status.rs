@@ -0,0 +1,173 @@
+use chrono::Utc;
+use openapi_common::ApiInventoryEntry;
+
+use crate::catalog::CatalogSource;
+use crate::error::CliError;
+use crate::output::{self, Format};
+
+pub async fn run(source: &CatalogSource, format: Format) -> Result<(), CliError> {
+    let apis = source.load_entries().await?;
+    let unavailable: Vec<_> = apis.iter().filter(|api| !api.available).collect();
+    let drifted: Vec<_> = apis
+        .iter()
+        .filter(|api| api.contract.as_ref().is_some_and(|c| c.has_drift()))
+        .collect();
+
+    if format == Format::Json {
+        return output::print_json(&summary(&apis));
+    }
+
+    println!("{}", headline(&apis));
+
+    if let Some(oldest) = apis.iter().map(|api| api.last_updated).min() {
+        let age = Utc::now() - oldest;
+        println!("Oldest entry last updated {} minutes ago", age.num_minutes());
+    }
+
+    if !unavailable.is_empty() {
+        println!();
+        output::print_table(
+            &["UNAVAILABLE", "NAMESPACE", "URL", "LAST ERROR"],
+            &unavailable_rows(&unavailable),
+        );
+    }
+
+    // Services whose live responses no longer match their documentation
+    if !drifted.is_empty() {
+        println!();
+        output::print_table(&["DRIFT", "OPERATION", "MISMATCH"], &drift_rows(&drifted));
+    }
+    Ok(())
+}
+
+/// Output of `-o json`: counts, and the ids of unavailable and drifted APIs
+fn summary(apis: &[ApiInventoryEntry]) -> serde_json::Value {
+    let ids = |filter: fn(&&ApiInventoryEntry) -> bool| {
+        apis.iter()
+            .filter(filter)
+            .map(|api| api.id.as_str())
+            .collect::<Vec<_>>()
+    };
+    serde_json::json!({
+        "total": apis.len(),
+        "available": apis.iter().filter(|api| api.available).count(),
+        "unavailable": ids(|api| !api.available),
+        "drifted": ids(|api| api.contract.as_ref().is_some_and(|c| c.has_drift())),
+    })
+}
+
+fn headline(apis: &[ApiInventoryEntry]) -> String {
+    let available = apis.iter().filter(|api| api.available).count();
+    format!(
+        "{} APIs in catalog: {} available, {} unavailable",
+        apis.len(),
+        available,
+        apis.len() - available
+    )
+}
+
+fn unavailable_rows(unavailable: &[&ApiInventoryEntry]) -> Vec<Vec<String>> {
+    unavailable
+        .iter()
+        .map(|api| {
+            vec![
+                api.name.clone(),
+                api.namespace.clone(),
+                api.url.clone(),
+                api.last_error.clone().unwrap_or_default(),
+            ]
+        })
+        .collect()
+}
+
+/// One row per mismatch between a service and its documentation
+fn drift_rows(drifted: &[&ApiInventoryEntry]) -> Vec<Vec<String>> {
+    drifted
+        .iter()
+        .flat_map(|api| {
+            api.contract.iter().flat_map(|contract| {
+                contract.mismatches.iter().map(|mismatch| {
+                    vec![
+                        api.name.clone(),
+                        format!("{} {}", mismatch.method, mismatch.path),
+                        mismatch.message.clone(),
+                    ]
+                })
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openapi_common::contract::{ContractMismatch, ContractReport};
+
+    fn catalog() -> Vec<ApiInventoryEntry> {
+        let orders =
+            ApiInventoryEntry::new("shop", "orders", "http://orders.shop.svc/openapi.json");
+        let carts = ApiInventoryEntry {
+            available: false,
+            last_error: Some("connection refused".to_string()),
+            ..ApiInventoryEntry::new("shop", "carts", "http://carts.shop.svc/openapi.json")
+        };
+        let payments = ApiInventoryEntry {
+            contract: Some(ContractReport {
+                checked_at: Utc::now(),
+                operations_checked: 2,
+                mismatches: vec![ContractMismatch {
+                    method: "GET".to_string(),
+                    path: "/payments/{id}".to_string(),
+                    message: "status 404 is not documented".to_string(),
+                }],
+            }),
+            ..ApiInventoryEntry::new(
+                "billing",
+                "payments",
+                "http://payments.billing.svc/openapi.json",
+            )
+        };
+        vec![orders, carts, payments]
+    }
+
+    #[test]
+    fn summary_lists_unavailable_and_drifted_apis_by_id() {
+        let apis = catalog();
+        assert_eq!(
+            summary(&apis),
+            serde_json::json!({
+                "total": 3,
+                "available": 2,
+                "unavailable": ["shop.carts"],
+                "drifted": ["billing.payments"],
+            })
+        );
+        assert_eq!(
+            headline(&apis),
+            "3 APIs in catalog: 2 available, 1 unavailable"
+        );
+    }
+
+    #[test]
+    fn rows_show_the_last_error_and_each_mismatch() {
+        let apis = catalog();
+        assert_eq!(
+            unavailable_rows(&[&apis[1]]),
+            [[
+                "carts API",
+                "shop",
+                "http://carts.shop.svc/openapi.json",
+                "connection refused"
+            ]]
+        );
+        assert_eq!(
+            drift_rows(&[&apis[2]]),
+            [[
+                "payments API",
+                "GET /payments/{id}",
+                "status 404 is not documented"
+            ]]
+        );
+        assert!(drift_rows(&[&apis[0]]).is_empty());
+    }
+}
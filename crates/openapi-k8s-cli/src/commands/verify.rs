@@ -0,0 +1,73 @@
+use openapi_common::signing::{SIGNATURE_SUFFIX, SpecSignature, SpecVerifier};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use super::EXIT_FINDINGS;
+use crate::error::CliError;
+use crate::output::{self, Format};
+
+/// Verification result of one spec file
+#[derive(Serialize, Debug)]
+struct VerifyReport {
+    file: String,
+    valid: bool,
+    /// Why the signature was rejected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+fn verify_file(verifier: &SpecVerifier, file: &Path) -> Result<(), CliError> {
+    let spec = std::fs::read_to_string(file)?;
+    let mut signature_path = file.as_os_str().to_owned();
+    signature_path.push(SIGNATURE_SUFFIX);
+    let signature = match std::fs::read_to_string(PathBuf::from(signature_path)) {
+        Ok(content) => SpecSignature::from_json(&content)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(CliError::Message("no signature file".to_string()));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    Ok(verifier.verify(&spec, &signature)?)
+}
+
+pub fn run(files: &[PathBuf], key: &Path, format: Format) -> Result<i32, CliError> {
+    let verifier = SpecVerifier::from_pem(&std::fs::read_to_string(key)?)?;
+
+    let reports: Vec<VerifyReport> = files
+        .iter()
+        .map(|file| {
+            let result = verify_file(&verifier, file);
+            VerifyReport {
+                file: file.display().to_string(),
+                valid: result.is_ok(),
+                error: result.err().map(|e| e.to_string()),
+            }
+        })
+        .collect();
+    let invalid = reports.iter().filter(|r| !r.valid).count();
+
+    if format == Format::Json {
+        output::print_json(&reports)?;
+    } else {
+        let rows: Vec<Vec<String>> = reports
+            .iter()
+            .map(|report| {
+                vec![
+                    report.file.clone(),
+                    if report.valid { "ok" } else { "invalid" }.to_string(),
+                    report.error.clone().unwrap_or_default(),
+                ]
+            })
+            .collect();
+        output::print_table(&["FILE", "SIGNATURE", "ERROR"], &rows);
+        println!();
+        println!(
+            "Checked {} specs against key {}: {} invalid",
+            reports.len(),
+            verifier.key_id(),
+            invalid
+        );
+    }
+
+    Ok(if invalid > 0 { EXIT_FINDINGS } else { 0 })
+}
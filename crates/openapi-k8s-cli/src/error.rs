@@ -0,0 +1,79 @@
+use openapi_common::catalog::CatalogError;
+use openapi_common::signing::SigningError;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum CliError {
+    Kube(kube::Error),
+    Reqwest(reqwest::Error),
+    Catalog(CatalogError),
+    Serde(serde_json::Error),
+    Signing(SigningError),
+    Io(std::io::Error),
+    /// A user-facing failure that needs no further context (unknown API, bad input, ...)
+    Message(String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Kube(e) => write!(f, "Kubernetes error: {}", e),
+            CliError::Reqwest(e) => write!(f, "HTTP error: {}", e),
+            CliError::Catalog(e) => write!(f, "{}", e),
+            CliError::Serde(e) => write!(f, "Serialization error: {}", e),
+            CliError::Signing(e) => write!(f, "Spec signature error: {}", e),
+            CliError::Io(e) => write!(f, "IO error: {}", e),
+            CliError::Message(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for CliError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CliError::Kube(e) => Some(e),
+            CliError::Reqwest(e) => Some(e),
+            CliError::Catalog(e) => Some(e),
+            CliError::Serde(e) => Some(e),
+            CliError::Signing(e) => Some(e),
+            CliError::Io(e) => Some(e),
+            CliError::Message(_) => None,
+        }
+    }
+}
+
+impl From<kube::Error> for CliError {
+    fn from(err: kube::Error) -> Self {
+        CliError::Kube(err)
+    }
+}
+
+impl From<reqwest::Error> for CliError {
+    fn from(err: reqwest::Error) -> Self {
+        CliError::Reqwest(err)
+    }
+}
+
+impl From<CatalogError> for CliError {
+    fn from(err: CatalogError) -> Self {
+        CliError::Catalog(err)
+    }
+}
+
+impl From<serde_json::Error> for CliError {
+    fn from(err: serde_json::Error) -> Self {
+        CliError::Serde(err)
+    }
+}
+
+impl From<SigningError> for CliError {
+    fn from(err: SigningError) -> Self {
+        CliError::Signing(err)
+    }
+}
+
+impl From<std::io::Error> for CliError {
+    fn from(err: std::io::Error) -> Self {
+        CliError::Io(err)
+    }
+}
@@ -0,0 +1,302 @@
+mod catalog;
+mod commands;
+mod error;
+mod output;
+
+use clap::{Parser, Subcommand};
+
+use catalog::CatalogSource;
+use error::CliError;
+use openapi_common::names::{self, NameError};
+use openapi_common::{DISCOVERY_CONFIGMAP_ENV, DISCOVERY_NAMESPACE_ENV, Lifecycle};
+
+/// Inspect the OpenAPI discovery catalog from the terminal
+#[derive(Parser, Debug)]
+#[command(name = "openapi-k8s-cli", version, about)]
+struct Cli {
+    /// Namespace of the discovery ConfigMap
+    #[arg(short = 'n', long, global = true, env = DISCOVERY_NAMESPACE_ENV, default_value = "default", value_parser = namespace_arg)]
+    namespace: String,
+
+    /// Name of the discovery ConfigMap
+    #[arg(long, global = true, env = DISCOVERY_CONFIGMAP_ENV, default_value = "openapi-discovery", value_parser = configmap_arg)]
+    configmap: String,
+
+    /// Read from a doc server (e.g. http://localhost:3000) instead of the ConfigMap
+    #[arg(long, global = true, env = "OPENAPI_DOC_SERVER")]
+    server: Option<String>,
+
+    /// Output format
+    #[arg(short = 'o', long, global = true, value_enum, default_value_t = output::Format::Table)]
+    output: output::Format,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List APIs in the catalog
+    List,
+    /// Print the OpenAPI spec of an API (by name, id, or service name)
+    Get {
+        api: String,
+        /// Print the spec exactly as served instead of pretty-printed JSON
+        #[arg(long)]
+        raw: bool,
+        /// Refuse the spec unless its signature verifies with this public key (PEM); needs --server
+        #[arg(long)]
+        verify_key: Option<std::path::PathBuf>,
+    },
+    /// Show an availability overview of the catalog
+    Status,
+    /// Compare two revisions of an API spec. Exits with code 2 if breaking changes are found.
+    Diff {
+        api: String,
+        /// Base revision: `catalog` (spec served by the catalog), `live` (fetched from the service) or `file:<path>`
+        #[arg(long, default_value = "catalog")]
+        from: String,
+        /// Revision compared against the base (same syntax as --from)
+        #[arg(long, default_value = "live")]
+        to: String,
+        /// Compare the catalog spec against a local file (shorthand for `--to file:<path>`)
+        #[arg(long, conflicts_with = "to")]
+        against_file: Option<std::path::PathBuf>,
+        /// Exit with code 2 on any change, not only breaking ones
+        #[arg(long)]
+        fail_on_changes: bool,
+    },
+    /// Validate api-doc.io annotations in local manifests. Exits with code 2 on errors.
+    Lint {
+        /// Manifest files to check (`-` for stdin); multi-document YAML is supported
+        #[arg(short = 'f', long = "filename", required = true)]
+        files: Vec<String>,
+        /// Also fail on warnings
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Download all specs into a directory with a manifest.json index. Exits with code 2 if some downloads failed.
+    Export {
+        /// Target directory, created if missing
+        #[arg(long)]
+        dir: std::path::PathBuf,
+        /// Only export APIs from this namespace (repeatable)
+        #[arg(long = "api-namespace", value_parser = namespace_arg)]
+        api_namespaces: Vec<String>,
+        /// Only export APIs whose spec uses this tag (repeatable)
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        /// Only export APIs in this lifecycle stage: alpha, beta, ga, deprecated or retired (repeatable)
+        #[arg(long = "lifecycle")]
+        lifecycles: Vec<Lifecycle>,
+    },
+    /// Check detached signatures of spec files (e.g. from git sync or S3 export). Exits with code 2 on mismatches.
+    Verify {
+        /// Spec files; each signature is read from `<file>.sig`
+        #[arg(required = true)]
+        files: Vec<std::path::PathBuf>,
+        /// Public key (PEM) of the operator's signing key
+        #[arg(long)]
+        key: std::path::PathBuf,
+    },
+    /// Show complexity metrics (operations, schemas, nesting depth, size) of specs
+    Metrics {
+        /// APIs to measure (by name, id, or service name); all available APIs when omitted
+        apis: Vec<String>,
+        /// Measure local spec files instead of catalog APIs
+        #[arg(short = 'f', long = "file", conflicts_with = "apis")]
+        files: Vec<std::path::PathBuf>,
+    },
+}
+
+/// Namespace flags must be valid namespace names (RFC 1123 labels)
+fn namespace_arg(value: &str) -> Result<String, NameError> {
+    names::validate_dns_label(value).map(|()| value.to_string())
+}
+
+/// ConfigMap names are RFC 1123 subdomains
+fn configmap_arg(value: &str) -> Result<String, NameError> {
+    names::validate_dns_subdomain(value).map(|()| value.to_string())
+}
+
+impl Cli {
+    fn source(&self) -> CatalogSource {
+        match &self.server {
+            Some(base_url) => CatalogSource::Server {
+                base_url: base_url.clone(),
+            },
+            None => CatalogSource::ConfigMap {
+                namespace: self.namespace.clone(),
+                name: self.configmap.clone(),
+            },
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+
+    match run(cli).await {
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Runs the selected command and returns the process exit code
+async fn run(cli: Cli) -> Result<i32, CliError> {
+    let source = cli.source();
+    match &cli.command {
+        Command::List => commands::list::run(&source, cli.output).await.map(|_| 0),
+        Command::Get {
+            api,
+            raw,
+            verify_key,
+        } => commands::get::run(&source, api, *raw, verify_key.as_deref())
+            .await
+            .map(|_| 0),
+        Command::Status => commands::status::run(&source, cli.output).await.map(|_| 0),
+        Command::Diff {
+            api,
+            from,
+            to,
+            against_file,
+            fail_on_changes,
+        } => {
+            let to = match against_file {
+                Some(path) => format!("file:{}", path.display()),
+                None => to.clone(),
+            };
+            commands::diff::run(&source, api, from, &to, *fail_on_changes, cli.output).await
+        }
+        Command::Lint { files, strict } => commands::lint::run(files, *strict, cli.output),
+        Command::Export {
+            dir,
+            api_namespaces,
+            tags,
+            lifecycles,
+        } => {
+            let filter = commands::export::ExportFilter {
+                namespaces: api_namespaces.clone(),
+                tags: tags.clone(),
+                lifecycles: lifecycles.clone(),
+            };
+            commands::export::run(&source, dir, &filter, cli.output).await
+        }
+        Command::Verify { files, key } => commands::verify::run(files, key, cli.output),
+        Command::Metrics { apis, files } => {
+            commands::metrics::run(&source, apis, files, cli.output)
+                .await
+                .map(|_| 0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(std::iter::once("openapi-k8s-cli").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn global_flags_are_accepted_after_the_subcommand() {
+        let cli = parse(&["list", "-n", "platform", "-o", "json"]).unwrap();
+        assert_eq!(cli.namespace, "platform");
+        assert_eq!(cli.configmap, "openapi-discovery");
+        assert_eq!(cli.output, output::Format::Json);
+        assert!(matches!(cli.command, Command::List));
+        assert!(matches!(
+            cli.source(),
+            CatalogSource::ConfigMap { ref namespace, ref name }
+                if namespace == "platform" && name == "openapi-discovery"
+        ));
+
+        let cli = parse(&["--server", "http://localhost:3000", "status"]).unwrap();
+        assert!(matches!(
+            cli.source(),
+            CatalogSource::Server { ref base_url } if base_url == "http://localhost:3000"
+        ));
+    }
+
+    #[test]
+    fn get_needs_an_api_and_takes_its_flags() {
+        let cli = parse(&["get", "shop.orders", "--raw"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Get { ref api, raw: true, verify_key: None } if api == "shop.orders"
+        ));
+        assert_eq!(
+            parse(&["get"]).unwrap_err().kind(),
+            clap::error::ErrorKind::MissingRequiredArgument
+        );
+    }
+
+    #[test]
+    fn diff_compares_the_catalog_with_a_file_or_the_live_spec() {
+        let cli = parse(&["diff", "orders", "--against-file", "orders.yaml"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Diff { ref from, ref to, against_file: Some(_), fail_on_changes: false, .. }
+                if from == "catalog" && to == "live"
+        ));
+        let both = ["diff", "orders", "--to", "live", "--against-file", "orders.yaml"];
+        assert_eq!(
+            parse(&both).unwrap_err().kind(),
+            clap::error::ErrorKind::ArgumentConflict
+        );
+    }
+
+    #[test]
+    fn lint_takes_one_or_more_files() {
+        let cli = parse(&["lint", "-f", "service.yaml", "-f", "-", "--strict"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Lint { ref files, strict: true } if files == &["service.yaml", "-"]
+        ));
+        assert_eq!(
+            parse(&["lint"]).unwrap_err().kind(),
+            clap::error::ErrorKind::MissingRequiredArgument
+        );
+    }
+
+    #[test]
+    fn export_filters_are_repeatable() {
+        let cli = parse(&[
+            "export",
+            "--dir",
+            "specs",
+            "--api-namespace",
+            "shop",
+            "--api-namespace",
+            "billing",
+            "--lifecycle",
+            "ga",
+            "--tag",
+            "orders",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Export { ref api_namespaces, ref tags, ref lifecycles, .. }
+                if api_namespaces == &["shop", "billing"]
+                    && tags == &["orders"]
+                    && lifecycles == &[Lifecycle::Ga]
+        ));
+        assert!(parse(&["export", "--dir", "specs", "--lifecycle", "stable"]).is_err());
+        assert!(parse(&["export", "--dir", "specs", "--api-namespace", "Shop"]).is_err());
+        assert!(parse(&["export"]).is_err());
+    }
+
+    #[test]
+    fn namespace_and_configmap_names_are_validated() {
+        assert!(parse(&["list", "-n", "Shop_Team"]).is_err());
+        assert!(parse(&["list", "--configmap", "discovery..json"]).is_err());
+        assert!(parse(&["list", "--configmap", "openapi.discovery"]).is_ok());
+        assert!(parse(&["list", "-o", "yaml"]).is_err());
+    }
+}
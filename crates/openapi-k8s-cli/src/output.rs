@@ -0,0 +1,72 @@
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::error::CliError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Table,
+    Json,
+}
+
+/// Prints rows as a left-aligned table with a header line
+pub fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    print!("{}", format_table(headers, rows));
+}
+
+/// Lines of [`print_table`], each ending with a newline
+fn format_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(cell.chars().count());
+        }
+    }
+
+    let format_row = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| format!("{:<width$}", cell, width = widths[i]))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    let mut table = format_row(headers.to_vec()) + "\n";
+    for row in rows {
+        table.push_str(&format_row(row.iter().map(String::as_str).collect()));
+        table.push('\n');
+    }
+    table
+}
+
+pub fn print_json<T: Serialize>(value: &T) -> Result<(), CliError> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns_are_as_wide_as_their_widest_cell() {
+        let rows = vec![
+            vec![
+                "Orders API".to_string(),
+                "shop".to_string(),
+                "true".to_string(),
+            ],
+            vec!["Ünïcode".to_string(), String::new(), String::new()],
+        ];
+        assert_eq!(
+            format_table(&["NAME", "NAMESPACE", "AVAILABLE"], &rows),
+            "NAME        NAMESPACE  AVAILABLE\n\
+             Orders API  shop       true\n\
+             Ünïcode\n"
+        );
+        assert_eq!(format_table(&["NAME"], &[]), "NAME\n");
+    }
+}
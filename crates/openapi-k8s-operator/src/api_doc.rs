@@ -0,0 +1,437 @@
+//! `WATCH_API_DOCS=true`: `ApiDoc` resources are reconciled into the catalog.
+//!
+//! An `ApiDoc` goes through the same fetch, gate, store and publish steps as an annotated
+//! Service, under the key of the Service it names, and reports the outcome in its status.
+//! While it exists the Service's own annotations are ignored. A finalizer removes the entry
+//! when the `ApiDoc` is deleted; annotations on the Service then apply again on its next
+//! reconcile.
+
+use k8s_openapi::api::core::v1::{Secret, Service};
+use kube::{
+    Resource, ResourceExt,
+    api::{Api, Patch, PatchParams},
+    runtime::{
+        controller::Action,
+        finalizer::{self, Event, finalizer},
+    },
+};
+use openapi_common::RemovalReason;
+use openapi_common::annotations::FetchVia;
+use openapi_common::api_doc::{ApiDoc, ApiDocStatus, FINALIZER};
+use std::sync::Arc;
+use tracing::{error, info, instrument, warn};
+
+use crate::error::AppError;
+use crate::{
+    ContextData, Discovery, EntrySource, discover, remove_entry_from_discovery_configmap,
+    service_origin, service_proxy_path, service_url,
+};
+
+const FIELD_MANAGER: &str = "openapi-k8s-operator";
+
+#[instrument(skip_all, fields(namespace = %api_doc.namespace().unwrap_or_default(), api_doc = %api_doc.name_any()))]
+pub async fn reconcile(api_doc: Arc<ApiDoc>, ctx: Arc<ContextData>) -> Result<Action, AppError> {
+    let namespace = api_doc.namespace().unwrap_or_default();
+    let api_docs: Api<ApiDoc> = Api::namespaced(ctx.discovery.clone().into_client(), &namespace);
+    // A dry run adds no finalizer; deleted ApiDocs are simply no longer seen
+    if ctx.dry_run {
+        return match api_doc.metadata.deletion_timestamp {
+            Some(_) => Ok(Action::await_change()),
+            None => apply(&api_docs, &api_doc, &ctx).await,
+        };
+    }
+    finalizer(&api_docs, FINALIZER, api_doc, |event| async {
+        match event {
+            Event::Apply(api_doc) => apply(&api_docs, &api_doc, &ctx).await,
+            Event::Cleanup(api_doc) => cleanup(&api_doc, &ctx).await,
+        }
+    })
+    .await
+    .map_err(|e| match e {
+        finalizer::Error::ApplyFailed(e) | finalizer::Error::CleanupFailed(e) => e,
+        finalizer::Error::AddFinalizer(e) | finalizer::Error::RemoveFinalizer(e) => {
+            AppError::Kube(e)
+        }
+        other => AppError::Io(std::io::Error::other(other.to_string())),
+    })
+}
+
+pub fn error_policy(api_doc: Arc<ApiDoc>, err: &AppError, ctx: Arc<ContextData>) -> Action {
+    error!(
+        "Reconcile error for ApiDoc {}/{}: {}",
+        api_doc.namespace().unwrap_or_default(),
+        api_doc.name_any(),
+        err
+    );
+    Action::requeue(ctx.error_retry_interval)
+}
+
+async fn apply(
+    api_docs: &Api<ApiDoc>,
+    api_doc: &ApiDoc,
+    ctx: &Arc<ContextData>,
+) -> Result<Action, AppError> {
+    let namespace = api_doc.namespace().unwrap_or_default();
+    let service_name = &api_doc.spec.service;
+    info!(
+        "Reconciling ApiDoc {}/{} for service {}",
+        namespace,
+        api_doc.name_any(),
+        service_name
+    );
+
+    if !ctx.watches_namespace(&namespace) {
+        let message = format!("Namespace {} is not watched", namespace);
+        return unavailable(
+            api_docs,
+            api_doc,
+            ctx,
+            RemovalReason::NamespaceNotWatched,
+            message,
+        )
+        .await;
+    }
+
+    let annotations = match api_doc.spec.annotations() {
+        Ok(annotations) => annotations,
+        Err(e) => {
+            let message = format!("Invalid spec: {}", e);
+            return unavailable(
+                api_docs,
+                api_doc,
+                ctx,
+                RemovalReason::ApiDocRemoved,
+                message,
+            )
+            .await;
+        }
+    };
+
+    let client = ctx.discovery.clone().into_client();
+    let services: Api<Service> = Api::namespaced(client.clone(), &namespace);
+    let Some(service) = services.get_opt(service_name).await? else {
+        let message = format!("Service {} not found", service_name);
+        return unavailable(
+            api_docs,
+            api_doc,
+            ctx,
+            RemovalReason::ServiceDeleted,
+            message,
+        )
+        .await;
+    };
+    let Some(url) = service_url(&service, &annotations) else {
+        let message = format!(
+            "Service {} has no port named {}",
+            service_name,
+            annotations
+                .port
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_default()
+        );
+        return unavailable(api_docs, api_doc, ctx, RemovalReason::PortNotFound, message).await;
+    };
+
+    let auth = match &api_doc.spec.auth {
+        Some(auth) => {
+            let secrets: Api<Secret> = Api::namespaced(client, &namespace);
+            let credentials = match secrets.get_opt(&auth.secret_name).await? {
+                Some(secret) => auth.credentials(&secret),
+                None => Err(format!("Secret {} not found", auth.secret_name)),
+            };
+            match credentials {
+                Ok(credentials) => Some(credentials),
+                Err(message) => {
+                    return unavailable(
+                        api_docs,
+                        api_doc,
+                        ctx,
+                        RemovalReason::Unreachable,
+                        message,
+                    )
+                    .await;
+                }
+            }
+        }
+        None => None,
+    };
+
+    let proxy_path = (annotations.fetch_via.unwrap_or(ctx.fetch_via) == FetchVia::ApiServer)
+        .then(|| service_proxy_path(&service, &annotations))
+        .flatten();
+    let source = EntrySource {
+        object: api_doc.object_ref(&()),
+        owner: api_doc.owner_ref(&()),
+        proxy_path,
+        cluster_origin: service_origin(&service, &annotations),
+    };
+    let discovery = discover(
+        ctx,
+        &source,
+        &namespace,
+        service_name,
+        url.clone(),
+        &annotations,
+        auth,
+    )
+    .await?;
+    let status = match &discovery {
+        Discovery::Stored => ApiDocStatus {
+            available: true,
+            url: Some(url),
+            ..Default::default()
+        },
+        Discovery::Deferred(_) => ApiDocStatus {
+            available: true,
+            url: Some(url),
+            message: Some("Refresh window is closed, the stored spec is kept".to_string()),
+            ..Default::default()
+        },
+        Discovery::Unreachable(failure) => ApiDocStatus {
+            available: false,
+            url: Some(url),
+            message: Some(format!("Spec could not be fetched: {}", failure)),
+            ..Default::default()
+        },
+    };
+    patch_status(api_docs, api_doc, status, ctx.dry_run).await?;
+    Ok(discovery.action(ctx))
+}
+
+/// Takes the API out of the catalog and reports why
+async fn unavailable(
+    api_docs: &Api<ApiDoc>,
+    api_doc: &ApiDoc,
+    ctx: &Arc<ContextData>,
+    reason: RemovalReason,
+    message: String,
+) -> Result<Action, AppError> {
+    let namespace = api_doc.namespace().unwrap_or_default();
+    warn!(
+        "ApiDoc {}/{} is not discovered: {}",
+        namespace,
+        api_doc.name_any(),
+        message
+    );
+    remove_entry_from_discovery_configmap(ctx.clone(), &namespace, &api_doc.spec.service, reason)
+        .await?;
+    let status = ApiDocStatus {
+        available: false,
+        message: Some(message),
+        ..Default::default()
+    };
+    patch_status(api_docs, api_doc, status, ctx.dry_run).await?;
+    Ok(Action::requeue(ctx.reconcile_interval))
+}
+
+async fn cleanup(api_doc: &ApiDoc, ctx: &Arc<ContextData>) -> Result<Action, AppError> {
+    let namespace = api_doc.namespace().unwrap_or_default();
+    info!(
+        "ApiDoc {}/{} deleted, removing service {} from discovery",
+        namespace,
+        api_doc.name_any(),
+        api_doc.spec.service
+    );
+    remove_entry_from_discovery_configmap(
+        ctx.clone(),
+        &namespace,
+        &api_doc.spec.service,
+        RemovalReason::ApiDocRemoved,
+    )
+    .await?;
+    Ok(Action::await_change())
+}
+
+/// With `dry_run`, the API server validates the patch without persisting it
+async fn patch_status(
+    api_docs: &Api<ApiDoc>,
+    api_doc: &ApiDoc,
+    status: ApiDocStatus,
+    dry_run: bool,
+) -> Result<(), AppError> {
+    let status = serde_json::json!({
+        "apiVersion": ApiDoc::api_version(&()),
+        "kind": ApiDoc::kind(&()),
+        "status": ApiDocStatus {
+            observed_generation: api_doc.metadata.generation,
+            ..status
+        },
+    });
+    let mut params = PatchParams::apply(FIELD_MANAGER).force();
+    params.dry_run = dry_run;
+    api_docs
+        .patch_status(&api_doc.name_any(), &params, &Patch::Apply(&status))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, FakeApiServer};
+    use kube::runtime::{reflector, watcher};
+    use openapi_common::{ApiInventoryEntry, DiscoveryConfig};
+    use serde_json::json;
+
+    const API_DOC_PATH: &str = "/apis/api-doc.io/v1alpha1/namespaces/shop/apidocs/orders-doc";
+
+    fn api_doc(spec: serde_json::Value, deleted: bool) -> ApiDoc {
+        let mut api_doc: ApiDoc = serde_json::from_value(json!({
+            "apiVersion": "api-doc.io/v1alpha1",
+            "kind": "ApiDoc",
+            "metadata": {
+                "name": "orders-doc",
+                "namespace": "shop",
+                "generation": 2,
+                "finalizers": [FINALIZER],
+            },
+            "spec": spec,
+        }))
+        .unwrap();
+        if deleted {
+            api_doc.metadata.deletion_timestamp = Some(
+                k8s_openapi::apimachinery::pkg::apis::meta::v1::Time(chrono::Utc::now()),
+            );
+        }
+        api_doc
+    }
+
+    /// Cluster with the ApiDoc and a catalog listing shop/orders
+    async fn cluster(api_doc: &ApiDoc) -> FakeApiServer {
+        let api = FakeApiServer::start().await;
+        api.insert(API_DOC_PATH, api_doc);
+        test_support::insert_catalog(
+            &api,
+            &DiscoveryConfig {
+                apis: vec![ApiInventoryEntry::new(
+                    "shop",
+                    "orders",
+                    "http://orders.shop.svc/openapi.json",
+                )],
+                last_updated: chrono::Utc::now(),
+                tombstones: Vec::new(),
+                shards: Vec::new(),
+            },
+        );
+        api
+    }
+
+    #[tokio::test]
+    async fn api_docs_take_precedence_over_service_annotations() {
+        let api = FakeApiServer::start().await;
+        let (store, mut writer) = reflector::store::<ApiDoc>();
+        let registered = api_doc(json!({ "service": "orders" }), false);
+        writer.apply_watcher_event(&watcher::Event::Apply(registered));
+        let ctx = Arc::new(ContextData {
+            api_docs: Some(store),
+            ..test_support::context(api.client())
+        });
+        let service: Service = serde_json::from_value(json!({
+            "metadata": {
+                "name": "orders",
+                "namespace": "shop",
+                "annotations": { "api-doc.io/enabled": "true" },
+            },
+            "spec": { "ports": [{ "port": 8080 }], "selector": { "app": "orders" } },
+        }))
+        .unwrap();
+
+        let action = crate::reconcile(Arc::new(service.clone()), ctx.clone())
+            .await
+            .unwrap();
+        assert_eq!(action, Action::requeue(ctx.reconcile_interval));
+        assert!(api.requests().is_empty());
+
+        // Once the ApiDoc is being deleted the annotations apply again, and the service
+        // without ready endpoints is looked at again shortly
+        writer.apply_watcher_event(&watcher::Event::Apply(api_doc(
+            json!({ "service": "orders" }),
+            true,
+        )));
+        let action = crate::reconcile(Arc::new(service), ctx).await.unwrap();
+        assert_eq!(action, Action::requeue(crate::endpoints::NOT_READY_REQUEUE));
+        assert_eq!(
+            api.requests()[0].path(),
+            "/apis/discovery.k8s.io/v1/namespaces/shop/endpointslices"
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_fields_remove_the_entry_and_are_reported_in_the_status() {
+        let invalid = api_doc(
+            json!({ "service": "orders", "path": "openapi.json" }),
+            false,
+        );
+        let api = cluster(&invalid).await;
+        let ctx = Arc::new(test_support::context(api.client()));
+
+        let action = reconcile(Arc::new(invalid), ctx.clone()).await.unwrap();
+
+        assert_eq!(action, Action::requeue(ctx.reconcile_interval));
+        assert!(test_support::catalog(&api).apis.is_empty());
+        let stored: ApiDoc = api.get(API_DOC_PATH).unwrap();
+        let status = stored.status.unwrap();
+        assert!(!status.available);
+        assert_eq!(
+            status.message.as_deref(),
+            Some(
+                "Invalid spec: path must start with '/' and contain no whitespace, \
+                 got \"openapi.json\""
+            )
+        );
+        assert_eq!(status.observed_generation, Some(2));
+    }
+
+    #[tokio::test]
+    async fn missing_services_are_reported_in_the_status() {
+        let registered = api_doc(json!({ "service": "orders" }), false);
+        let api = cluster(&registered).await;
+        let ctx = Arc::new(test_support::context(api.client()));
+
+        reconcile(Arc::new(registered), ctx).await.unwrap();
+
+        let stored: ApiDoc = api.get(API_DOC_PATH).unwrap();
+        assert_eq!(
+            stored.status.unwrap().message.as_deref(),
+            Some("Service orders not found")
+        );
+        assert!(test_support::catalog(&api).apis.is_empty());
+    }
+
+    #[tokio::test]
+    async fn deleting_an_api_doc_removes_its_entry_then_the_finalizer() {
+        let deleted = api_doc(json!({ "service": "orders" }), true);
+        let api = cluster(&deleted).await;
+        let ctx = Arc::new(test_support::context(api.client()));
+
+        let action = reconcile(Arc::new(deleted), ctx).await.unwrap();
+
+        assert_eq!(action, Action::await_change());
+        assert!(test_support::catalog(&api).apis.is_empty());
+        let stored: ApiDoc = api.get(API_DOC_PATH).unwrap();
+        assert_eq!(stored.metadata.finalizers, Some(Vec::new()));
+        let writes = api.writes();
+        assert_eq!(writes.last().unwrap().path(), API_DOC_PATH);
+        assert_eq!(
+            writes.last().unwrap().header("content-type"),
+            Some("application/json-patch+json")
+        );
+    }
+
+    #[tokio::test]
+    async fn dry_runs_add_no_finalizer_and_ignore_deleted_api_docs() {
+        let deleted = api_doc(json!({ "service": "orders" }), true);
+        let api = cluster(&deleted).await;
+        let ctx = Arc::new(ContextData {
+            dry_run: true,
+            ..test_support::context(api.client())
+        });
+
+        let action = reconcile(Arc::new(deleted), ctx).await.unwrap();
+
+        assert_eq!(action, Action::await_change());
+        assert!(api.requests().is_empty());
+        assert_eq!(test_support::catalog(&api).apis.len(), 1);
+    }
+}
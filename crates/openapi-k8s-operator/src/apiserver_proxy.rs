@@ -0,0 +1,88 @@
+//! Fetches specs through the API server's service proxy.
+//!
+//! NetworkPolicies often keep the operator from reaching the pods of other namespaces
+//! directly. With `SPEC_FETCH_VIA=apiserver`, or `api-doc.io/fetch-via: apiserver` on a
+//! Service, the spec is requested from
+//! `/api/v1/namespaces/{namespace}/services/{service}:{port}/proxy{path}` with the
+//! operator's own Kubernetes client instead, so only the API server has to reach the
+//! Service. This needs `get` on `services/proxy`. Headers are forwarded to the Service,
+//! except `Authorization`, which the API server consumes itself.
+
+use kube::Client;
+use openapi_common::annotations::UrlScheme;
+use openapi_common::fetch::{FailurePhase, FetchError, FetchFailure};
+
+/// Path of `spec_path` on port `port` of the Service, relative to the API server
+pub fn path(
+    namespace: &str,
+    service: &str,
+    scheme: UrlScheme,
+    port: i32,
+    spec_path: &str,
+) -> String {
+    // The proxy speaks plain HTTP to the Service unless the name is prefixed with the scheme
+    let scheme = match scheme {
+        UrlScheme::Https => "https:",
+        UrlScheme::Http => "",
+    };
+    format!(
+        "/api/v1/namespaces/{}/services/{}{}:{}/proxy{}",
+        namespace, scheme, service, port, spec_path
+    )
+}
+
+/// GETs `path` from the API server; the body is not validated
+pub async fn fetch(
+    client: Client,
+    path: &str,
+    headers: &[(String, String)],
+    max_body_bytes: Option<usize>,
+) -> Result<String, FetchFailure> {
+    let failure = |phase: FailurePhase, message: String| FetchFailure::new(phase, message);
+    let mut request = http::Request::get(path);
+    for (name, value) in headers {
+        request = request.header(name.as_str(), value.as_str());
+    }
+    let request = request.body(Vec::new()).map_err(|e| {
+        failure(
+            FailurePhase::Config,
+            format!("Invalid service proxy request: {}", e),
+        )
+    })?;
+    let body = client.request_text(request).await.map_err(|e| match e {
+        kube::Error::Api(response) => FetchFailure {
+            status: Some(response.code),
+            ..failure(
+                FailurePhase::Status,
+                format!(
+                    "Service proxy error: status {}: {}",
+                    response.code, response.message
+                ),
+            )
+        },
+        e => failure(FailurePhase::Connect, format!("Service proxy error: {}", e)),
+    })?;
+    if let Some(limit) = max_body_bytes
+        && body.len() > limit
+    {
+        return Err(FetchError::TooLarge { limit }.failure());
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proxy_path_names_the_scheme_only_for_https() {
+        assert_eq!(
+            path("shop", "orders", UrlScheme::Http, 8080, "/v3/api-docs"),
+            "/api/v1/namespaces/shop/services/orders:8080/proxy/v3/api-docs"
+        );
+        assert_eq!(
+            path("shop", "orders", UrlScheme::Https, 8443, "/openapi.json"),
+            "/api/v1/namespaces/shop/services/https:orders:8443/proxy/openapi.json"
+        );
+    }
+}
@@ -0,0 +1,248 @@
+//! Audit trail of the changes the operator makes to the catalog (`AUDIT_LOG`).
+//!
+//! Every entry added to, updated in or removed from the discovery ConfigMap, including
+//! federated ones, is written as one JSON line with the time, the operator instance that
+//! made the change (its pod name), the entry and what changed: the fields of an update,
+//! with the summary of a new spec revision, or why an entry was removed. `AUDIT_LOG=stdout`
+//! writes the lines to standard output, independent of `RUST_LOG` and `LOG_FORMAT`, for
+//! the log collector to keep; any other value is a file the lines are appended to, e.g. on
+//! a persistent volume. Restoring a drifted catalog is not recorded, as it only restores
+//! what was recorded before. With `DISCOVERY_OUTPUT=crd` every API is a resource of its
+//! own, whose changes the API server's audit log records.
+
+use chrono::{DateTime, Utc};
+use openapi_common::{ApiInventoryEntry, DiscoveryConfig};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::Mutex;
+use tracing::warn;
+
+use crate::error::AppError;
+
+/// `stdout` or the file audit records are appended to; unset disables the audit trail
+pub const AUDIT_LOG_ENV: &str = "AUDIT_LOG";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+    Add,
+    Update,
+    Remove,
+}
+
+/// One change of one catalog entry
+#[derive(Debug, Serialize)]
+pub struct AuditRecord {
+    pub timestamp: DateTime<Utc>,
+    /// Operator instance that made the change
+    pub actor: String,
+    pub action: AuditAction,
+    pub id: String,
+    pub namespace: String,
+    pub service: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cluster: Option<String>,
+    /// Entry fields an update changed
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changed_fields: Vec<String>,
+    /// What a new spec revision changed, or why the entry was removed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+}
+
+pub struct AuditLog {
+    actor: String,
+    sink: Mutex<Box<dyn Write + Send>>,
+}
+
+impl AuditLog {
+    /// `None` when `AUDIT_LOG` is unset
+    pub fn from_env() -> Result<Option<Self>, AppError> {
+        let Some(target) = std::env::var(AUDIT_LOG_ENV)
+            .ok()
+            .map(|target| target.trim().to_string())
+            .filter(|target| !target.is_empty())
+        else {
+            return Ok(None);
+        };
+        let sink: Box<dyn Write + Send> = if target == "stdout" {
+            Box::new(std::io::stdout())
+        } else {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&target)
+                .map_err(|e| {
+                    AppError::Io(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!(
+                            "Invalid {}: '{}', expected stdout or a writable file ({})",
+                            AUDIT_LOG_ENV, target, e
+                        ),
+                    ))
+                })?;
+            Box::new(file)
+        };
+        let actor = std::env::var("POD_NAME")
+            .or_else(|_| std::env::var("HOSTNAME"))
+            .unwrap_or_else(|_| "openapi-k8s-operator".to_string());
+        Ok(Some(Self {
+            actor,
+            sink: Mutex::new(sink),
+        }))
+    }
+
+    /// Records the changes between the catalog as it was and as it was just written
+    pub fn record(&self, previous: Option<&DiscoveryConfig>, next: &DiscoveryConfig) {
+        let records = changes(&self.actor, previous, next);
+        if records.is_empty() {
+            return;
+        }
+        let mut sink = self.sink.lock().unwrap();
+        for record in records {
+            let written = serde_json::to_string(&record)
+                .map_err(std::io::Error::from)
+                .and_then(|line| writeln!(sink, "{}", line));
+            if let Err(e) = written {
+                warn!("Failed to write the audit record of {}: {}", record.id, e);
+            }
+        }
+        if let Err(e) = sink.flush() {
+            warn!("Failed to flush the audit log: {}", e);
+        }
+    }
+}
+
+/// Records of the entries added, updated and removed between two catalogs, by id
+fn changes(
+    actor: &str,
+    previous: Option<&DiscoveryConfig>,
+    next: &DiscoveryConfig,
+) -> Vec<AuditRecord> {
+    let by_id = |config: Option<&DiscoveryConfig>| -> BTreeMap<String, serde_json::Value> {
+        config
+            .iter()
+            .flat_map(|config| &config.apis)
+            .filter_map(|api| Some((api.id.clone(), serde_json::to_value(api).ok()?)))
+            .collect()
+    };
+    let before = by_id(previous);
+    let after = by_id(Some(next));
+    let timestamp = Utc::now();
+    let record = |action, api: &ApiInventoryEntry| AuditRecord {
+        timestamp,
+        actor: actor.to_string(),
+        action,
+        id: api.id.clone(),
+        namespace: api.namespace.clone(),
+        service: api.service_name.clone(),
+        cluster: api.cluster.clone(),
+        changed_fields: Vec::new(),
+        summary: None,
+    };
+
+    let mut records = Vec::new();
+    for api in &next.apis {
+        let Some(old) = before.get(&api.id) else {
+            records.push(record(AuditAction::Add, api));
+            continue;
+        };
+        let (Some(old), Some(new)) = (
+            old.as_object(),
+            after.get(&api.id).and_then(|new| new.as_object()),
+        ) else {
+            continue;
+        };
+        if old == new {
+            continue;
+        }
+        let mut update = record(AuditAction::Update, api);
+        update.changed_fields = old
+            .keys()
+            .chain(new.keys())
+            // last_updated moves with every change
+            .filter(|field| *field != "last_updated" && old.get(*field) != new.get(*field))
+            .cloned()
+            .collect();
+        update.changed_fields.sort();
+        update.changed_fields.dedup();
+        if update.changed_fields.is_empty() {
+            continue;
+        }
+        if update
+            .changed_fields
+            .iter()
+            .any(|field| field == "spec_sha256")
+        {
+            update.summary = api
+                .last_change_summary
+                .as_ref()
+                .map(|change| change.summary.clone());
+        }
+        records.push(update);
+    }
+    for api in previous.iter().flat_map(|config| &config.apis) {
+        if !after.contains_key(&api.id) {
+            let mut removal = record(AuditAction::Remove, api);
+            removal.summary = next
+                .tombstones
+                .iter()
+                .filter(|tombstone| tombstone.id == api.id)
+                .max_by_key(|tombstone| tombstone.deleted_at)
+                .map(|tombstone| tombstone.reason.description().to_string());
+            records.push(removal);
+        }
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openapi_common::{ApiTombstone, RemovalReason};
+
+    #[test]
+    fn records_added_updated_and_removed_entries() {
+        let entry = |service: &str| {
+            ApiInventoryEntry::new("shop", service, "http://api.shop.svc:8080/openapi.json")
+        };
+        let previous = DiscoveryConfig {
+            apis: vec![entry("orders"), entry("carts"), entry("users")],
+            last_updated: Utc::now(),
+            tombstones: Vec::new(),
+            shards: Vec::new(),
+        };
+        let mut next = previous.clone();
+        next.apis[0].available = false;
+        next.apis[0].last_error = Some("status 503".to_string());
+        next.apis[0].last_updated = Utc::now();
+        let carts = next.apis.remove(1);
+        next.tombstones.push(ApiTombstone::for_entry(
+            &carts,
+            RemovalReason::ServiceDeleted,
+        ));
+        next.apis.push(entry("payments"));
+
+        let records = changes("operator-0", Some(&previous), &next);
+        let summary: Vec<_> = records
+            .iter()
+            .map(|record| (record.action, record.id.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (AuditAction::Update, "shop.orders"),
+                (AuditAction::Add, "shop.payments"),
+                (AuditAction::Remove, "shop.carts"),
+            ]
+        );
+        assert_eq!(records[0].changed_fields, vec!["available", "last_error"]);
+        assert_eq!(records[0].actor, "operator-0");
+        assert_eq!(
+            records[2].summary.as_deref(),
+            Some(RemovalReason::ServiceDeleted.description())
+        );
+        assert!(changes("operator-0", Some(&next), &next).is_empty());
+    }
+}
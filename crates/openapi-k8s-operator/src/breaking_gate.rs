@@ -0,0 +1,158 @@
+//! Holds back spec revisions with breaking changes.
+//!
+//! The gate remembers the last revision it accepted per entry and diffs every new one
+//! against it. A revision with breaking changes is blocked: the previous revision keeps
+//! being stored and published, and the reconcile emits a `BreakingChangeBlocked` Event on
+//! the Service listing what would break. `api-doc.io/allow-breaking: "true"` lets such a
+//! revision through. Accepted revisions are kept in memory; with `DISCOVERY_OUTPUT=crd`
+//! the gate is seeded from the stored spec after a restart, otherwise the first revision
+//! seen is accepted as is.
+
+use openapi_common::OpenApiDocument;
+use openapi_common::diff::{SpecDiff, diff_documents};
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+
+/// `false` publishes every revision, breaking or not
+pub const BREAKING_CHANGE_GATE_ENV: &str = "BREAKING_CHANGE_GATE";
+
+/// Event notes are limited to 1kB by the API server
+const MAX_NOTE_BYTES: usize = 1024;
+
+/// What the gate decided for a new revision
+#[derive(Debug, PartialEq, Eq)]
+pub enum Admission {
+    /// No breaking changes (or nothing to compare with)
+    Accepted,
+    /// Breaking, but the Service allows breaking changes
+    Overridden(SpecDiff),
+    /// Breaking; `previous` is the revision to keep serving
+    Blocked { diff: SpecDiff, previous: String },
+}
+
+#[derive(Default)]
+pub struct BreakingChangeGate {
+    /// Last accepted spec per entry id
+    accepted: Mutex<HashMap<String, String>>,
+}
+
+impl BreakingChangeGate {
+    /// `None` when `BREAKING_CHANGE_GATE=false`
+    pub fn from_env() -> Option<Self> {
+        let enabled = std::env::var(BREAKING_CHANGE_GATE_ENV)
+            .map(|v| !v.trim().eq_ignore_ascii_case("false"))
+            .unwrap_or(true);
+        enabled.then(Self::default)
+    }
+
+    /// Whether an accepted revision is known for the entry
+    pub async fn knows(&self, id: &str) -> bool {
+        self.accepted.lock().await.contains_key(id)
+    }
+
+    /// Records a revision accepted by an earlier run of the operator
+    pub async fn seed(&self, id: &str, spec: String) {
+        self.accepted
+            .lock()
+            .await
+            .entry(id.to_string())
+            .or_insert(spec);
+    }
+
+    /// Compares `spec` with the last accepted revision, accepting it unless it is blocked
+    pub async fn admit(&self, id: &str, spec: &str, allow_breaking: bool) -> Admission {
+        let mut accepted = self.accepted.lock().await;
+        let diff = accepted
+            .get(id)
+            .filter(|previous| previous.as_str() != spec)
+            .and_then(|previous| breaking_diff(previous, spec));
+
+        let admission = match diff {
+            None => Admission::Accepted,
+            Some(diff) if allow_breaking => Admission::Overridden(diff),
+            Some(diff) => {
+                return Admission::Blocked {
+                    diff,
+                    previous: accepted[id].clone(),
+                };
+            }
+        };
+        accepted.insert(id.to_string(), spec.to_string());
+        admission
+    }
+
+    /// Drops the accepted revision of an entry that left the catalog
+    pub async fn forget(&self, id: &str) {
+        self.accepted.lock().await.remove(id);
+    }
+}
+
+/// The diff from `previous` to `spec` if it has breaking changes. Specs that do not
+/// parse are never blocked.
+fn breaking_diff(previous: &str, spec: &str) -> Option<SpecDiff> {
+    let old = OpenApiDocument::parse(previous).ok()?;
+    let new = OpenApiDocument::parse(spec).ok()?;
+    Some(diff_documents(&old, &new)).filter(SpecDiff::has_breaking)
+}
+
+/// Event note listing the breaking changes, cut to the size the API server accepts
+pub fn event_note(diff: &SpecDiff) -> String {
+    let mut note = format!("{}:", diff.summary());
+    for change in diff.breaking() {
+        note.push_str(&format!("\n- {}", change));
+    }
+    truncate_note(note)
+}
+
+/// Cuts an Event note to the size the API server accepts
+pub fn truncate_note(mut note: String) -> String {
+    if note.len() > MAX_NOTE_BYTES {
+        let mut end = MAX_NOTE_BYTES - 3;
+        while !note.is_char_boundary(end) {
+            end -= 1;
+        }
+        note.truncate(end);
+        note.push_str("...");
+    }
+    note
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V1: &str = r#"{"openapi":"3.0.0","info":{"title":"Orders","version":"1"},"paths":{"/orders":{"get":{"responses":{"200":{"description":"ok"}}}},"/orders/{id}":{"get":{"responses":{"200":{"description":"ok"}}}}}}"#;
+    const V2: &str = r#"{"openapi":"3.0.0","info":{"title":"Orders","version":"2"},"paths":{"/orders":{"get":{"responses":{"200":{"description":"ok"}}}}}}"#;
+
+    #[tokio::test]
+    async fn blocks_breaking_revisions_unless_allowed() {
+        let gate = BreakingChangeGate::default();
+        assert_eq!(
+            gate.admit("shop.orders", V1, false).await,
+            Admission::Accepted
+        );
+
+        match gate.admit("shop.orders", V2, false).await {
+            Admission::Blocked { diff, previous } => {
+                assert_eq!(previous, V1);
+                assert!(event_note(&diff).contains("/orders/{id}"));
+            }
+            other => panic!("expected a blocked revision, got {:?}", other),
+        }
+        // The blocked revision did not replace the accepted one
+        assert_eq!(
+            gate.admit("shop.orders", V1, false).await,
+            Admission::Accepted
+        );
+
+        assert!(matches!(
+            gate.admit("shop.orders", V2, true).await,
+            Admission::Overridden(_)
+        ));
+        // V2 is now the accepted revision
+        assert_eq!(
+            gate.admit("shop.orders", V2, false).await,
+            Admission::Accepted
+        );
+    }
+}
@@ -0,0 +1,396 @@
+//! Command line and configuration file of the operator.
+//!
+//! Every setting can be given as a flag or through the environment variable shown by
+//! `--help`; flags take precedence. Values are validated while parsing, so a misspelt
+//! namespace or a non-numeric timeout stops the operator before it touches the cluster.
+//! Settings of the individual subsystems (publishers, spec stores, the webhook, ...) are
+//! read by their modules from the environment.
+//!
+//! `OPERATOR_CONFIG` names a YAML or TOML file (by its `.toml` extension) holding any of
+//! these environment variables as flat keys, written as `watchNamespaces`,
+//! `watch_namespaces` or `WATCH_NAMESPACES`. Its values fill in the variables that are not
+//! set, so the environment overrides the file.
+
+use clap::{Parser, Subcommand, ValueEnum};
+use openapi_common::fetch::{
+    FetchConfig, FetchError, SPEC_FETCH_CONNECT_TIMEOUT_ENV, SPEC_FETCH_MAX_BYTES_ENV, SPEC_FETCH_TIMEOUT_ENV,
+};
+use openapi_common::annotations::parse_interval;
+use openapi_common::names::{self, NameError};
+use openapi_common::{
+    DISCOVERY_CONFIGMAP_ENV, DISCOVERY_NAMESPACE_ENV, DRY_RUN_ENV,
+    ERROR_RETRY_INTERVAL_ENV, EXCLUDE_NAMESPACES_ENV, MAX_CONCURRENT_RECONCILES_ENV,
+    NAMESPACE_DEFAULTS_ENV, RECONCILE_INTERVAL_ENV, REFRESH_ON_ROLLOUT_ENV,
+    TOMBSTONE_GRACE_PERIOD_ENV, WATCH_API_DOCS_ENV, WATCH_DEPLOYMENTS_ENV,
+    WATCH_NAMESPACE_SELECTOR_ENV, WATCH_NAMESPACES_ENV,
+};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::error::AppError;
+use crate::fetch_metrics::METRICS_PORT_ENV;
+use crate::health::{HEALTH_PORT_ENV, HEALTH_STALL_TIMEOUT_ENV};
+
+pub const OPERATOR_CONFIG_ENV: &str = "OPERATOR_CONFIG";
+
+/// Kubernetes operator for discovering and centralizing OpenAPI documentation
+#[derive(Parser, Debug)]
+#[command(name = "openapi-k8s-operator", version, about)]
+pub struct Cli {
+    #[command(flatten)]
+    pub config: OperatorConfig,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print a CRD manifest generated from the Rust types
+    Crd {
+        #[arg(value_enum, default_value_t = CrdKind::Discoveredapis)]
+        kind: CrdKind,
+    },
+    /// Discover the catalog once, write it as JSON and exit, without writing to the cluster
+    Scan {
+        /// File to write the catalog to (`-` for stdout)
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Check the `api-doc.io/*` annotations of the watched Services and exit non-zero on errors
+    Lint {
+        /// Skip fetching the specs, only check the annotations
+        #[arg(long)]
+        offline: bool,
+    },
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrdKind {
+    Discoveredapis,
+    Apidocs,
+}
+
+/// Settings of the controllers
+#[derive(clap::Args, Debug, Clone)]
+pub struct OperatorConfig {
+    /// Namespaces to watch: empty for the operator's own, `all`, or a comma-separated list
+    /// of names and glob patterns (`shop,team-*`)
+    #[arg(long, env = WATCH_NAMESPACES_ENV, default_value = "")]
+    pub watch_namespaces: String,
+
+    /// Also watch namespaces matching this label selector (`team=payments,env!=dev`)
+    #[arg(long, env = WATCH_NAMESPACE_SELECTOR_ENV)]
+    pub watch_namespace_selector: Option<String>,
+
+    /// Namespaces and glob patterns never discovered (`kube-*,vault`)
+    #[arg(long, env = EXCLUDE_NAMESPACES_ENV, default_value = "")]
+    pub exclude_namespaces: String,
+
+    /// Namespace of the discovery ConfigMap
+    #[arg(long, env = DISCOVERY_NAMESPACE_ENV, default_value = "default", value_parser = namespace_arg)]
+    pub discovery_namespace: String,
+
+    /// Name of the discovery ConfigMap
+    #[arg(long, env = DISCOVERY_CONFIGMAP_ENV, default_value = "openapi-discovery", value_parser = configmap_arg)]
+    pub discovery_configmap: String,
+
+    /// Discover and fetch specs, but only log what would be written
+    #[arg(long, env = DRY_RUN_ENV, value_parser = flag_arg, default_value = "false", num_args = 0..=1, default_missing_value = "true")]
+    pub dry_run: bool,
+
+    /// Also discover APIs registered with ApiDoc resources
+    #[arg(long, env = WATCH_API_DOCS_ENV, value_parser = flag_arg, default_value = "false", num_args = 0..=1, default_missing_value = "true")]
+    pub watch_api_docs: bool,
+
+    /// Treat api-doc.io annotations of Deployments like those of the Services selecting them
+    #[arg(long, env = WATCH_DEPLOYMENTS_ENV, value_parser = flag_arg, default_value = "false", num_args = 0..=1, default_missing_value = "true")]
+    pub watch_deployments: bool,
+
+    /// Refresh the specs of Services as soon as a rollout of a Deployment they select completes
+    #[arg(long, env = REFRESH_ON_ROLLOUT_ENV, value_parser = flag_arg, default_value = "false", num_args = 0..=1, default_missing_value = "true")]
+    pub refresh_on_rollout: bool,
+
+    /// Apply api-doc.io annotations of Namespaces to their Services
+    #[arg(long, env = NAMESPACE_DEFAULTS_ENV, value_parser = flag_arg, default_value = "false", num_args = 0..=1, default_missing_value = "true")]
+    pub namespace_defaults: bool,
+
+    /// Seconds removed entries stay in the catalog as tombstones
+    #[arg(long, env = TOMBSTONE_GRACE_PERIOD_ENV, default_value_t = 24 * 60 * 60)]
+    pub tombstone_grace_period: u32,
+
+    /// Reconciles run at the same time per controller (0 = unlimited)
+    #[arg(long, env = MAX_CONCURRENT_RECONCILES_ENV, default_value_t = 0)]
+    pub max_concurrent_reconciles: u16,
+
+    /// Time between reconciles of a discovered object, which refetch its spec
+    #[arg(long, env = RECONCILE_INTERVAL_ENV, default_value = "5m", value_parser = interval_arg)]
+    pub reconcile_interval: Duration,
+
+    /// Time before a failed reconcile is retried
+    #[arg(long, env = ERROR_RETRY_INTERVAL_ENV, default_value = "30s", value_parser = interval_arg)]
+    pub error_retry_interval: Duration,
+
+    /// Timeout in seconds of a single spec fetch attempt [default: 10]
+    #[arg(long, env = SPEC_FETCH_TIMEOUT_ENV)]
+    pub fetch_timeout: Option<u64>,
+
+    /// Connect timeout in seconds of spec fetches [default: 5]
+    #[arg(long, env = SPEC_FETCH_CONNECT_TIMEOUT_ENV)]
+    pub fetch_connect_timeout: Option<u64>,
+
+    /// Largest spec read, in bytes (0 = unlimited) [default: 33554432]
+    #[arg(long, env = SPEC_FETCH_MAX_BYTES_ENV)]
+    pub fetch_max_bytes: Option<usize>,
+
+    /// Port of /healthz and /readyz
+    #[arg(long, env = HEALTH_PORT_ENV, default_value_t = 8081)]
+    pub health_port: u16,
+
+    /// Seconds the watchers may keep failing before /healthz fails
+    #[arg(long, env = HEALTH_STALL_TIMEOUT_ENV, default_value_t = 300, value_parser = clap::value_parser!(u32).range(1..))]
+    pub health_stall_timeout: u32,
+
+    /// Port of the Prometheus /metrics endpoint
+    #[arg(long, env = METRICS_PORT_ENV, default_value_t = 8080)]
+    pub metrics_port: u16,
+}
+
+impl OperatorConfig {
+    /// Watched namespaces and patterns; `None` for all namespaces, `["current"]` for the
+    /// operator's own
+    pub fn watch_namespaces(&self) -> Option<Vec<String>> {
+        match self.watch_namespaces.trim() {
+            "" => Some(vec!["current".to_string()]),
+            value if value.eq_ignore_ascii_case("all") => None,
+            value => Some(split_list(value)),
+        }
+    }
+
+    pub fn namespace_selector(&self) -> Option<String> {
+        self.watch_namespace_selector
+            .as_deref()
+            .map(str::trim)
+            .filter(|selector| !selector.is_empty())
+            .map(str::to_string)
+    }
+
+    pub fn exclude_namespaces(&self) -> Vec<String> {
+        split_list(&self.exclude_namespaces)
+    }
+
+    pub fn tombstone_grace(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.tombstone_grace_period.into())
+    }
+
+    pub fn health_stall_timeout(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.health_stall_timeout.into())
+    }
+
+    /// The fetch settings from the environment, with the timeouts and size limit given here
+    pub fn fetch_config(&self) -> Result<FetchConfig, FetchError> {
+        let mut config = FetchConfig::from_env()?;
+        if let Some(secs) = self.fetch_timeout {
+            config.timeout = Duration::from_secs(secs);
+        }
+        if let Some(secs) = self.fetch_connect_timeout {
+            config.connect_timeout = Duration::from_secs(secs);
+        }
+        if let Some(bytes) = self.fetch_max_bytes {
+            config.max_body_bytes = Some(bytes).filter(|bytes| *bytes > 0);
+        }
+        Ok(config)
+    }
+}
+
+/// Reads the `OPERATOR_CONFIG` file, if any, into the environment variables that are unset.
+/// Returns the path and the number of settings taken from it.
+///
+/// Must run before any other thread is started, as it modifies the environment.
+pub fn load_file() -> Result<Option<(String, usize)>, AppError> {
+    let Some(path) = std::env::var(OPERATOR_CONFIG_ENV)
+        .ok()
+        .map(|path| path.trim().to_string())
+        .filter(|path| !path.is_empty())
+    else {
+        return Ok(None);
+    };
+    let invalid = |message: String| {
+        AppError::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Invalid {} '{}': {}", OPERATOR_CONFIG_ENV, path, message),
+        ))
+    };
+
+    let content = std::fs::read_to_string(&path).map_err(|e| invalid(e.to_string()))?;
+    let settings = parse_file(&path, &content).map_err(invalid)?;
+    let mut applied = 0;
+    for (name, value) in settings {
+        if std::env::var_os(&name).is_none() {
+            // SAFETY: called at the start of `main`, before the runtime or any other thread
+            // exists
+            unsafe { std::env::set_var(&name, value) };
+            applied += 1;
+        }
+    }
+    Ok(Some((path, applied)))
+}
+
+/// Environment variable name -> value of every setting in the file
+fn parse_file(path: &str, content: &str) -> Result<BTreeMap<String, String>, String> {
+    let settings: BTreeMap<String, Value> = if path.ends_with(".toml") {
+        toml::from_str(content).map_err(|e| e.to_string())?
+    } else {
+        serde_yaml::from_str::<Option<_>>(content)
+            .map_err(|e| e.to_string())?
+            .unwrap_or_default()
+    };
+
+    let mut env = BTreeMap::new();
+    for (key, value) in settings {
+        let value = match value {
+            Value::Null => continue,
+            Value::String(value) => value,
+            Value::Array(items) => items
+                .iter()
+                .map(|item| scalar(&key, item))
+                .collect::<Result<Vec<_>, _>>()?
+                .join(","),
+            value => scalar(&key, &value)?,
+        };
+        env.insert(env_name(&key), value);
+    }
+    Ok(env)
+}
+
+fn scalar(key: &str, value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(value) => Ok(value.clone()),
+        Value::Bool(_) | Value::Number(_) => Ok(value.to_string()),
+        _ => Err(format!(
+            "'{}' must be a string, number, boolean or list",
+            key
+        )),
+    }
+}
+
+/// `watchNamespaces`, `watch-namespaces` and `watch_namespaces` -> `WATCH_NAMESPACES`
+fn env_name(key: &str) -> String {
+    let mut name = String::with_capacity(key.len() + 4);
+    let mut previous_lower = false;
+    for c in key.chars() {
+        if c.is_ascii_uppercase() && previous_lower {
+            name.push('_');
+        }
+        previous_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+        name.push(if c == '-' {
+            '_'
+        } else {
+            c.to_ascii_uppercase()
+        });
+    }
+    name
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Namespace names are RFC 1123 labels
+fn namespace_arg(value: &str) -> Result<String, NameError> {
+    names::validate_dns_label(value).map(|()| value.to_string())
+}
+
+/// ConfigMap names are RFC 1123 subdomains
+fn configmap_arg(value: &str) -> Result<String, NameError> {
+    names::validate_dns_subdomain(value).map(|()| value.to_string())
+}
+
+/// Seconds (`300`) or a duration with a unit (`30s`, `5m`, `1h`, `1d`)
+fn interval_arg(value: &str) -> Result<Duration, String> {
+    parse_interval(value).map(Duration::from_secs)
+}
+
+/// Switches are on only for `true`, in any case, as they always have been
+fn flag_arg(value: &str) -> Result<bool, std::convert::Infallible> {
+    Ok(value.trim().eq_ignore_ascii_case("true"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_flags_and_subcommands() {
+        let cli = Cli::try_parse_from([
+            "openapi-k8s-operator",
+            "--watch-namespaces",
+            "shop, team-*",
+            "--dry-run",
+            "--fetch-timeout",
+            "30",
+            "--fetch-max-bytes",
+            "0",
+            "--reconcile-interval",
+            "1h",
+            "scan",
+            "-o",
+            "discovery.json",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.config.watch_namespaces(),
+            Some(vec!["shop".to_string(), "team-*".to_string()])
+        );
+        assert!(cli.config.dry_run);
+        assert!(!cli.config.watch_api_docs);
+        assert_eq!(cli.config.fetch_config().unwrap().timeout, Duration::from_secs(30));
+        assert_eq!(cli.config.fetch_config().unwrap().max_body_bytes, None);
+        assert_eq!(cli.config.reconcile_interval, Duration::from_secs(3600));
+        assert_eq!(cli.config.error_retry_interval, Duration::from_secs(30));
+        assert!(matches!(
+            cli.command,
+            Some(Command::Scan { output: Some(ref path) }) if path == "discovery.json"
+        ));
+
+        let cli =
+            Cli::try_parse_from(["openapi-k8s-operator", "--watch-namespaces", "all"]).unwrap();
+        assert_eq!(cli.config.watch_namespaces(), None);
+
+        for invalid in [
+            vec!["openapi-k8s-operator", "--discovery-namespace", "Shop"],
+            vec!["openapi-k8s-operator", "--fetch-timeout", "10s"],
+            vec!["openapi-k8s-operator", "--health-stall-timeout", "0"],
+            vec!["openapi-k8s-operator", "--reconcile-interval", "5 min"],
+        ] {
+            assert!(Cli::try_parse_from(&invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn reads_yaml_and_toml_files() {
+        let yaml = parse_file(
+            "/etc/operator/config.yaml",
+            "watchNamespaces: [shop, team-*]\nSPEC_FETCH_TIMEOUT: 30\ndry_run: true\nspecStorage: s3\n",
+        )
+        .unwrap();
+        let toml = parse_file(
+            "/etc/operator/config.toml",
+            "watchNamespaces = [\"shop\", \"team-*\"]\nSPEC_FETCH_TIMEOUT = 30\ndry_run = true\nspecStorage = \"s3\"\n",
+        )
+        .unwrap();
+        assert_eq!(yaml, toml);
+        assert_eq!(yaml["WATCH_NAMESPACES"], "shop,team-*");
+        assert_eq!(yaml["SPEC_FETCH_TIMEOUT"], "30");
+        assert_eq!(yaml["DRY_RUN"], "true");
+        assert_eq!(yaml["SPEC_STORAGE"], "s3");
+
+        assert!(parse_file("config.yaml", "").unwrap().is_empty());
+        assert!(parse_file("config.yaml", "webhook: {enabled: true}").is_err());
+    }
+}
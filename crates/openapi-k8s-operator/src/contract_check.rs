@@ -0,0 +1,197 @@
+//! Periodic live contract checks for services annotated with `api-doc.io/contract-check: "true"`.
+//!
+//! Reconciles run every few minutes, but each service is only exercised once per
+//! `CONTRACT_CHECK_INTERVAL`; in between, the last report is attached to the entry again.
+//! Reports are kept in memory, so a restarted operator checks every opted-in service once.
+
+use chrono::Utc;
+use openapi_common::ApiInventoryEntry;
+use openapi_common::contract::{self, ContractReport};
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::error::AppError;
+
+/// Seconds between checks of one service; `0` disables contract checks
+pub const CONTRACT_CHECK_INTERVAL_ENV: &str = "CONTRACT_CHECK_INTERVAL";
+
+const DEFAULT_INTERVAL_SECS: u32 = 60 * 60;
+
+pub struct ContractChecker {
+    interval: chrono::Duration,
+    client: reqwest::Client,
+    /// For services with `api-doc.io/insecure-skip-verify: "true"`
+    insecure_client: reqwest::Client,
+    /// Latest report per entry id
+    reports: Mutex<HashMap<String, ContractReport>>,
+}
+
+impl ContractChecker {
+    /// `None` when `CONTRACT_CHECK_INTERVAL=0`
+    pub fn from_env() -> Result<Option<Self>, AppError> {
+        let secs = match std::env::var(CONTRACT_CHECK_INTERVAL_ENV) {
+            Ok(value) => value.trim().parse::<u32>().map_err(|_| {
+                AppError::Io(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!(
+                        "Invalid {}: '{}', expected seconds",
+                        CONTRACT_CHECK_INTERVAL_ENV, value
+                    ),
+                ))
+            })?,
+            Err(_) => DEFAULT_INTERVAL_SECS,
+        };
+        if secs == 0 {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            interval: chrono::Duration::seconds(secs.into()),
+            client: reqwest::Client::new(),
+            insecure_client: reqwest::Client::builder()
+                .danger_accept_invalid_certs(true)
+                .build()?,
+            reports: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    /// Latest report for the entry, running a new check when the last one is due
+    pub async fn report(&self, entry: &ApiInventoryEntry, spec: &str) -> Option<ContractReport> {
+        if let Some(report) = self.reports.lock().await.get(&entry.id)
+            && Utc::now() - report.checked_at < self.interval
+        {
+            return Some(report.clone());
+        }
+
+        let spec = match openapi_common::spec_utils::parse_spec_to_json(spec) {
+            Ok(spec) => spec,
+            Err(e) => {
+                warn!("Skipping contract check of {}: {}", entry.id, e);
+                return None;
+            }
+        };
+        let base_url = contract::base_url(&entry.url, &spec)?;
+        let client = if entry.insecure_skip_verify {
+            &self.insecure_client
+        } else {
+            &self.client
+        };
+        let report = contract::check(client, &base_url, &spec).await;
+        if report.has_drift() {
+            warn!(
+                "Contract check of {} found {} mismatches in {} operations",
+                entry.id,
+                report.mismatches.len(),
+                report.operations_checked
+            );
+        } else {
+            info!(
+                "Contract check of {} passed ({} operations)",
+                entry.id, report.operations_checked
+            );
+        }
+
+        self.reports
+            .lock()
+            .await
+            .insert(entry.id.clone(), report.clone());
+        Some(report)
+    }
+
+    /// Drops the report of an entry that left the catalog or opted out
+    pub async fn forget(&self, id: &str) {
+        self.reports.lock().await.remove(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{Recorded, TestServer};
+    use serde_json::json;
+
+    fn checker(interval_secs: i64) -> ContractChecker {
+        ContractChecker {
+            interval: chrono::Duration::seconds(interval_secs),
+            client: reqwest::Client::new(),
+            insecure_client: reqwest::Client::new(),
+            reports: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn spec() -> String {
+        json!({
+            "openapi": "3.0.0",
+            "info": { "title": "Orders", "version": "1.0.0" },
+            "servers": [{ "url": "https://api.example.com/v1" }],
+            "paths": {
+                "/orders": {
+                    "get": {
+                        "responses": {
+                            "200": {
+                                "content": {
+                                    "application/json": {
+                                        "schema": {
+                                            "type": "object",
+                                            "required": ["items"],
+                                            "properties": { "items": { "type": "array" } }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn reports_are_reused_until_the_interval_passes() {
+        let server = TestServer::start(|_: &Recorded| (200, r#"{"items":[]}"#.to_string())).await;
+        let entry =
+            ApiInventoryEntry::new("shop", "orders", format!("{}/openapi.json", server.url));
+        let checker = checker(3600);
+
+        let report = checker.report(&entry, &spec()).await.unwrap();
+        assert_eq!(report.operations_checked, 1);
+        assert!(!report.has_drift());
+        // Operations are called on the spec's host under the server's base path
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].uri, "/v1/orders");
+        assert_eq!(requests[0].header("accept"), Some("application/json"));
+
+        assert_eq!(checker.report(&entry, &spec()).await, Some(report));
+        assert_eq!(server.requests().len(), 1);
+
+        checker.forget(&entry.id).await;
+        checker.report(&entry, &spec()).await.unwrap();
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn responses_that_differ_from_the_spec_are_drift() {
+        let server = TestServer::start(|_: &Recorded| (200, r#"{"orders":[]}"#.to_string())).await;
+        let entry =
+            ApiInventoryEntry::new("shop", "orders", format!("{}/openapi.json", server.url));
+
+        let report = checker(0).report(&entry, &spec()).await.unwrap();
+        assert!(report.has_drift());
+        assert_eq!(report.mismatches[0].method, "GET");
+        assert_eq!(report.mismatches[0].path, "/orders");
+    }
+
+    #[tokio::test]
+    async fn unparsable_specs_are_not_checked() {
+        let server = TestServer::start(|_: &Recorded| (200, "{}".to_string())).await;
+        let entry =
+            ApiInventoryEntry::new("shop", "orders", format!("{}/openapi.json", server.url));
+        let checker = checker(3600);
+
+        assert_eq!(checker.report(&entry, "{ not: [a spec").await, None);
+        assert!(server.requests().is_empty());
+        assert!(checker.reports.lock().await.is_empty());
+    }
+}
@@ -0,0 +1,623 @@
+//! `WATCH_DEPLOYMENTS=true`: `api-doc.io/*` annotations on Deployments.
+//!
+//! Some teams annotate the Deployment rather than the Service in front of it. Deployments
+//! are watched in the same scope as Services, and the annotations of every Deployment whose
+//! pods a Service selects apply to that Service as if they were its own; the Service's own
+//! annotations win, namespace defaults come last. Changes to those annotations, or to the
+//! pod labels, reconcile the Services again.
+//!
+//! A Deployment no Service selects (pods reached directly, e.g. behind a mesh) is discovered
+//! on its own, under its name, from the IP of one of its ready pods. Its entry leaves the
+//! catalog when the Deployment is deleted or a Service starts selecting it.
+//!
+//! `REFRESH_ON_ROLLOUT=true`: when a rollout of a Deployment completes, all of its replicas
+//! updated and available, the Services selecting it are reconciled right away, so the
+//! catalog shows the new spec without waiting for the next periodic refresh.
+
+use futures::{Stream, StreamExt};
+use k8s_openapi::api::apps::v1::Deployment;
+use k8s_openapi::api::core::v1::{Namespace, Pod, Service};
+use kube::{
+    Resource, ResourceExt,
+    api::{Api, ListParams},
+    runtime::{
+        controller::Action,
+        reflector::{ObjectRef, Store},
+        watcher,
+    },
+};
+use openapi_common::{
+    RemovalReason,
+    annotations::{ApiDocAnnotations, with_namespace_defaults},
+    namespace_utils,
+};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use tracing::{error, info, instrument, warn};
+
+use crate::error::AppError;
+use crate::{
+    ContextData, EntrySource, discover, endpoints, remove_entry_from_discovery_configmap,
+    remove_entry_with_event,
+};
+
+const ANNOTATION_PREFIX: &str = "api-doc.io/";
+/// Set by the Deployment controller, incremented by every rollout
+const REVISION_ANNOTATION: &str = "deployment.kubernetes.io/revision";
+
+/// Trigger streams fed by the Deployment watcher
+pub struct Triggers {
+    /// Deployments whose annotations or pod labels changed, for the Service controller
+    pub services: futures::channel::mpsc::UnboundedReceiver<Result<Deployment, watcher::Error>>,
+    /// The same Deployments, for the controller of those no Service selects
+    pub deployments: futures::channel::mpsc::UnboundedReceiver<Result<Deployment, watcher::Error>>,
+}
+
+/// Forwards Deployment changes that matter for discovery to both controllers, and removes
+/// the entries of deleted Deployments; with `annotations` off (only `REFRESH_ON_ROLLOUT`),
+/// the Deployment controller gets nothing. Completed rollouts are forwarded to the Service
+/// controller with `rollouts`, other status updates are dropped.
+pub fn spawn(
+    ctx: Arc<ContextData>,
+    events: impl Stream<Item = Result<watcher::Event<Deployment>, watcher::Error>> + Send + 'static,
+    annotations: bool,
+    rollouts: bool,
+) -> Triggers {
+    let (services_tx, services) = futures::channel::mpsc::unbounded();
+    let (deployments_tx, deployments) = futures::channel::mpsc::unbounded();
+    tokio::spawn(async move {
+        let mut events = std::pin::pin!(events);
+        let mut seen: HashMap<ObjectRef<Deployment>, u64> = HashMap::new();
+        // Revision of the last completed rollout of each Deployment
+        let mut completed: HashMap<ObjectRef<Deployment>, String> = HashMap::new();
+        while let Some(event) = events.next().await {
+            let deployment = match event {
+                Ok(watcher::Event::Apply(deployment) | watcher::Event::InitApply(deployment)) => {
+                    let key = ObjectRef::from_obj(&deployment);
+                    let rolled_out = rollouts
+                        && completed_rollout(&deployment).is_some_and(|revision| {
+                            completed
+                                .insert(key.clone(), revision.to_string())
+                                .is_some_and(|previous| previous != revision)
+                        });
+                    let hash = discovery_hash(&deployment);
+                    let changed = annotations && seen.insert(key, hash) != Some(hash);
+                    if rolled_out {
+                        info!(
+                            "Rollout of Deployment {}/{} completed, refreshing the specs of its services",
+                            deployment.namespace().unwrap_or_default(),
+                            deployment.name_any()
+                        );
+                    }
+                    if !changed && !rolled_out {
+                        continue;
+                    }
+                    deployment
+                }
+                Ok(watcher::Event::Delete(deployment)) => {
+                    let key = ObjectRef::from_obj(&deployment);
+                    completed.remove(&key);
+                    if !annotations {
+                        continue;
+                    }
+                    seen.remove(&key);
+                    tokio::spawn(deleted(ctx.clone(), deployment.clone()));
+                    deployment
+                }
+                Ok(watcher::Event::Init | watcher::Event::InitDone) => continue,
+                Err(e) => {
+                    warn!("Deployment watcher error: {}", e);
+                    ctx.health.watcher_error(&e);
+                    continue;
+                }
+            };
+            if services_tx.unbounded_send(Ok(deployment.clone())).is_err()
+                || (annotations && deployments_tx.unbounded_send(Ok(deployment)).is_err())
+            {
+                break;
+            }
+        }
+    });
+    Triggers {
+        services,
+        deployments,
+    }
+}
+
+/// Revision of the Deployment's latest rollout once the controller observed it and all
+/// replicas run it and are available; `None` while a rollout is in progress
+fn completed_rollout(deployment: &Deployment) -> Option<&str> {
+    let revision = deployment.annotations().get(REVISION_ANNOTATION)?.as_str();
+    let status = deployment.status.as_ref()?;
+    let replicas = deployment
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.replicas)
+        .unwrap_or(1);
+    let complete = status.observed_generation >= deployment.metadata.generation
+        && status.updated_replicas.unwrap_or(0) >= replicas
+        && status.available_replicas.unwrap_or(0) >= replicas
+        // Pods of the old ReplicaSet are still terminating
+        && status.replicas.unwrap_or(0) <= replicas;
+    complete.then_some(revision)
+}
+
+/// What discovery depends on: the api-doc.io annotations and the pod labels
+fn discovery_hash(deployment: &Deployment) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    api_doc_annotations(deployment).hash(&mut hasher);
+    pod_labels(deployment).hash(&mut hasher);
+    hasher.finish()
+}
+
+fn api_doc_annotations(deployment: &Deployment) -> BTreeMap<String, String> {
+    deployment
+        .annotations()
+        .iter()
+        .filter(|(key, _)| key.starts_with(ANNOTATION_PREFIX))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+fn pod_labels(deployment: &Deployment) -> Option<&BTreeMap<String, String>> {
+    deployment
+        .spec
+        .as_ref()?
+        .template
+        .metadata
+        .as_ref()?
+        .labels
+        .as_ref()
+}
+
+/// Whether the Service's selector matches the pods of the Deployment
+pub fn selects(service: &Service, deployment: &Deployment) -> bool {
+    if service.namespace() != deployment.namespace() {
+        return false;
+    }
+    let Some(selector) = service
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.selector.as_ref())
+        .filter(|selector| !selector.is_empty())
+    else {
+        return false;
+    };
+    let labels = pod_labels(deployment);
+    selector
+        .iter()
+        .all(|(key, value)| labels.and_then(|labels| labels.get(key)) == Some(value))
+}
+
+/// Services to reconcile when the Deployment changes
+pub fn services_for(services: &Store<Service>, deployment: &Deployment) -> Vec<ObjectRef<Service>> {
+    services
+        .state()
+        .iter()
+        .filter(|service| selects(service, deployment))
+        .map(|service| ObjectRef::from_obj(service.as_ref()))
+        .collect()
+}
+
+/// The Service's annotations on top of the api-doc.io annotations of the Deployments it
+/// selects; of two Deployments setting the same annotation, the first by name wins
+pub fn with_deployment_annotations(
+    deployments: &Store<Deployment>,
+    service: &Service,
+) -> BTreeMap<String, String> {
+    let mut selected: Vec<_> = deployments
+        .state()
+        .into_iter()
+        .filter(|deployment| selects(service, deployment))
+        .collect();
+    selected.sort_by_key(|deployment| deployment.name_any());
+
+    let mut merged = service.annotations().clone();
+    for deployment in selected.iter().rev() {
+        for (key, value) in api_doc_annotations(deployment) {
+            if !service.annotations().contains_key(&key) {
+                merged.insert(key, value);
+            }
+        }
+    }
+    merged
+}
+
+/// Services of the namespace selecting the Deployment's pods
+async fn selecting_services(
+    ctx: &ContextData,
+    deployment: &Deployment,
+) -> Result<Vec<Service>, AppError> {
+    let services: Api<Service> = Api::namespaced(
+        ctx.discovery.clone().into_client(),
+        &deployment.namespace().unwrap_or_default(),
+    );
+    Ok(services
+        .list(&ListParams::default())
+        .await?
+        .items
+        .into_iter()
+        .filter(|service| selects(service, deployment))
+        .collect())
+}
+
+/// Takes a deleted Deployment's own entry out of the catalog, unless a Service of the same
+/// name owns it
+async fn deleted(ctx: Arc<ContextData>, deployment: Deployment) {
+    let namespace = deployment.namespace().unwrap_or_default();
+    let name = deployment.name_any();
+    let services: Api<Service> = Api::namespaced(ctx.discovery.clone().into_client(), &namespace);
+    match services.get_opt(&name).await {
+        Ok(None) => {}
+        Ok(Some(_)) => return,
+        Err(e) => {
+            warn!(
+                "Failed to look up service {}/{} of a deleted Deployment: {}",
+                namespace, name, e
+            );
+            return;
+        }
+    }
+    if let Err(e) = remove_entry_from_discovery_configmap(
+        ctx,
+        &namespace,
+        &name,
+        RemovalReason::DeploymentRemoved,
+    )
+    .await
+    {
+        error!("Failed to remove deleted Deployment from discovery: {}", e);
+    }
+}
+
+/// Reconciles a Deployment no Service selects into an entry of its own
+#[instrument(skip_all, fields(namespace = %deployment.namespace().unwrap_or_default(), deployment = %deployment.name_any()))]
+pub async fn reconcile(
+    deployment: Arc<Deployment>,
+    ctx: Arc<ContextData>,
+) -> Result<Action, AppError> {
+    let namespace = deployment.namespace().unwrap_or_default();
+    let name = deployment.name_any();
+    let object = deployment.object_ref(&());
+    let requeue = Action::requeue(ctx.reconcile_interval);
+
+    // The entry under the Deployment's name belongs to a Service of that name selecting it
+    let services = selecting_services(&ctx, &deployment).await?;
+    let owned_by_service = services.iter().any(|service| service.name_any() == name);
+
+    // Namespace defaults only fill in, so Deployments without annotations of their own are
+    // not discovered and only looked at again when they change
+    let own_annotations = api_doc_annotations(&deployment);
+    if own_annotations.is_empty() {
+        if !owned_by_service {
+            remove_entry_with_event(
+                &ctx,
+                &object,
+                &namespace,
+                &name,
+                RemovalReason::DocsDisabled,
+            )
+            .await?;
+        }
+        return Ok(Action::await_change());
+    }
+
+    if !ctx.watches_namespace(&namespace) {
+        if !owned_by_service {
+            let reason = if namespace_utils::namespace_matches(&ctx.exclude_namespaces, &namespace)
+            {
+                RemovalReason::Excluded
+            } else {
+                RemovalReason::NamespaceNotWatched
+            };
+            remove_entry_with_event(&ctx, &object, &namespace, &name, reason).await?;
+        }
+        return Ok(requeue);
+    }
+
+    // Annotations of Deployments behind a Service are the Service's
+    if !services.is_empty() {
+        if !owned_by_service {
+            remove_entry_with_event(
+                &ctx,
+                &object,
+                &namespace,
+                &name,
+                RemovalReason::DeploymentRemoved,
+            )
+            .await?;
+        }
+        return Ok(requeue);
+    }
+
+    let deployment_annotations = match &ctx.namespace_defaults {
+        Some(store) => {
+            let ns = store.get(&ObjectRef::<Namespace>::new(&namespace));
+            with_namespace_defaults(ns.as_ref().map(|ns| ns.annotations()), &own_annotations)
+        }
+        None => own_annotations,
+    };
+    let (annotations, issues) = ApiDocAnnotations::parse(&deployment_annotations);
+    for issue in &issues {
+        warn!(
+            "Deployment {}/{} has an invalid annotation: {}",
+            namespace, name, issue
+        );
+    }
+    if annotations.exclude {
+        remove_entry_with_event(&ctx, &object, &namespace, &name, RemovalReason::Excluded).await?;
+        return Ok(requeue);
+    }
+    if !annotations.enabled {
+        remove_entry_with_event(
+            &ctx,
+            &object,
+            &namespace,
+            &name,
+            RemovalReason::DocsDisabled,
+        )
+        .await?;
+        return Ok(requeue);
+    }
+
+    let url = match &annotations.url {
+        Some(url) => url.clone(),
+        None => {
+            let Some(port) = container_port(&deployment, &annotations) else {
+                warn!(
+                    "Deployment {}/{} has no container port named {}, removing from discovery",
+                    namespace,
+                    name,
+                    annotations
+                        .port
+                        .as_ref()
+                        .map(ToString::to_string)
+                        .unwrap_or_default()
+                );
+                remove_entry_with_event(
+                    &ctx,
+                    &object,
+                    &namespace,
+                    &name,
+                    RemovalReason::PortNotFound,
+                )
+                .await?;
+                return Ok(requeue);
+            };
+            let Some(ip) = ready_pod_ip(&ctx, &deployment).await? else {
+                info!(
+                    "Deployment {}/{} has no ready pods, fetching its spec in {:?}",
+                    namespace,
+                    name,
+                    endpoints::NOT_READY_REQUEUE
+                );
+                return Ok(Action::requeue(endpoints::NOT_READY_REQUEUE));
+            };
+            pod_url(&ip, port, &annotations)
+        }
+    };
+
+    // The API server's proxy needs a Service, so pods are always fetched directly
+    let source = EntrySource {
+        object,
+        owner: deployment.owner_ref(&()),
+        proxy_path: None,
+        cluster_origin: None,
+    };
+    let discovery = discover(&ctx, &source, &namespace, &name, url, &annotations, None).await?;
+    Ok(discovery.action(&ctx))
+}
+
+pub fn error_policy(deployment: Arc<Deployment>, err: &AppError, ctx: Arc<ContextData>) -> Action {
+    error!(
+        "Reconcile error for Deployment {}/{}: {}",
+        deployment.namespace().unwrap_or_default(),
+        deployment.name_any(),
+        err
+    );
+    Action::requeue(ctx.error_retry_interval)
+}
+
+/// Port of the pods serving the spec; the first container port (or 8080) unless the
+/// annotations name one
+fn container_port(deployment: &Deployment, annotations: &ApiDocAnnotations) -> Option<i32> {
+    let ports: Vec<_> = deployment
+        .spec
+        .as_ref()
+        .map(|spec| spec.template.spec.iter().flat_map(|pod| &pod.containers))
+        .into_iter()
+        .flatten()
+        .flat_map(|container| container.ports.iter().flatten())
+        .collect();
+    let named_ports = ports
+        .iter()
+        .map(|port| (port.name.as_deref(), port.container_port));
+    match &annotations.port {
+        Some(port_ref) => port_ref.resolve(named_ports),
+        None => Some(
+            ports
+                .first()
+                .map(|port| port.container_port)
+                .unwrap_or(8080),
+        ),
+    }
+}
+
+fn pod_url(ip: &str, port: i32, annotations: &ApiDocAnnotations) -> String {
+    let host = if ip.contains(':') {
+        format!("[{}]", ip)
+    } else {
+        ip.to_string()
+    };
+    format!(
+        "{}://{}:{}{}",
+        annotations.scheme.as_str(),
+        host,
+        port,
+        annotations.path
+    )
+}
+
+/// IP of a ready pod of the Deployment, the first by name
+async fn ready_pod_ip(
+    ctx: &ContextData,
+    deployment: &Deployment,
+) -> Result<Option<String>, AppError> {
+    let Some(match_labels) = deployment
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.selector.match_labels.as_ref())
+        .filter(|labels| !labels.is_empty())
+    else {
+        return Ok(None);
+    };
+    let selector = match_labels
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join(",");
+    let pods: Api<Pod> = Api::namespaced(
+        ctx.discovery.clone().into_client(),
+        &deployment.namespace().unwrap_or_default(),
+    );
+    let mut pods = pods
+        .list(&ListParams::default().labels(&selector))
+        .await?
+        .items;
+    pods.sort_by_key(|pod| pod.name_any());
+    Ok(pods.iter().find_map(|pod| {
+        let status = pod.status.as_ref()?;
+        let ready = status
+            .conditions
+            .iter()
+            .flatten()
+            .any(|condition| condition.type_ == "Ready" && condition.status == "True");
+        (ready && pod.metadata.deletion_timestamp.is_none())
+            .then(|| status.pod_ip.clone())
+            .flatten()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::apps::v1::DeploymentSpec;
+    use k8s_openapi::api::core::v1::{PodTemplateSpec, ServiceSpec};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use kube::runtime::reflector::store::Writer;
+    use kube::runtime::watcher::Event;
+
+    fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn deployment(
+        name: &str,
+        pod_labels: &[(&str, &str)],
+        annotations: &[(&str, &str)],
+    ) -> Deployment {
+        Deployment {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                namespace: Some("shop".to_string()),
+                annotations: Some(labels(annotations)),
+                ..Default::default()
+            },
+            spec: Some(DeploymentSpec {
+                template: PodTemplateSpec {
+                    metadata: Some(ObjectMeta {
+                        labels: Some(labels(pod_labels)),
+                        ..Default::default()
+                    }),
+                    spec: None,
+                },
+                ..Default::default()
+            }),
+            status: None,
+        }
+    }
+
+    #[test]
+    fn rollouts_complete_once_all_replicas_are_updated() {
+        use k8s_openapi::api::apps::v1::DeploymentStatus;
+
+        let mut orders = deployment(
+            "orders",
+            &[("app", "orders")],
+            &[("deployment.kubernetes.io/revision", "4")],
+        );
+        orders.metadata.generation = Some(7);
+        orders.spec.as_mut().unwrap().replicas = Some(2);
+        let status = |observed, updated, available, replicas| DeploymentStatus {
+            observed_generation: Some(observed),
+            updated_replicas: Some(updated),
+            available_replicas: Some(available),
+            replicas: Some(replicas),
+            ..Default::default()
+        };
+
+        for in_progress in [
+            status(6, 2, 2, 2),
+            status(7, 1, 2, 3),
+            status(7, 2, 1, 2),
+            status(7, 2, 2, 3),
+        ] {
+            orders.status = Some(in_progress);
+            assert_eq!(completed_rollout(&orders), None);
+        }
+        orders.status = Some(status(7, 2, 2, 2));
+        assert_eq!(completed_rollout(&orders), Some("4"));
+    }
+
+    #[test]
+    fn services_take_annotations_of_selected_deployments() {
+        let service = Service {
+            metadata: ObjectMeta {
+                name: Some("orders".to_string()),
+                namespace: Some("shop".to_string()),
+                annotations: Some(labels(&[("api-doc.io/path", "/v3/api-docs")])),
+                ..Default::default()
+            },
+            spec: Some(ServiceSpec {
+                selector: Some(labels(&[("app", "orders")])),
+                ..Default::default()
+            }),
+            status: None,
+        };
+
+        let mut writer = Writer::<Deployment>::default();
+        let store = writer.as_reader();
+        writer.apply_watcher_event(&Event::Apply(deployment(
+            "orders",
+            &[("app", "orders"), ("version", "v2")],
+            &[
+                ("api-doc.io/enabled", "true"),
+                ("api-doc.io/path", "/openapi.json"),
+                ("deployment.kubernetes.io/revision", "3"),
+            ],
+        )));
+        writer.apply_watcher_event(&Event::Apply(deployment(
+            "billing",
+            &[("app", "billing")],
+            &[("api-doc.io/name", "Billing")],
+        )));
+
+        assert_eq!(
+            with_deployment_annotations(&store, &service),
+            labels(&[
+                ("api-doc.io/enabled", "true"),
+                ("api-doc.io/path", "/v3/api-docs"),
+            ])
+        );
+        assert!(!selects(
+            &service,
+            &deployment("billing", &[("app", "billing")], &[])
+        ));
+        assert!(!selects(&service, &deployment("orders", &[], &[])));
+    }
+}
@@ -0,0 +1,513 @@
+//! `DISCOVERY_OUTPUT=crd`: one `DiscoveredApi` resource per API instead of the shared
+//! discovery ConfigMap.
+//!
+//! Resources live in the Service's namespace and are owned by the Service (or the `ApiDoc`
+//! registering it), so they are garbage collected with it. The spec body is stored in a `<service>-openapi-spec`
+//! ConfigMap owned by the `DiscoveredApi`, together with its signature when specs are signed.
+
+use chrono::Utc;
+use k8s_openapi::api::core::v1::ConfigMap;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+use kube::{
+    Client, Resource, ResourceExt,
+    api::{Api, DeleteParams, Patch, PatchParams},
+};
+use openapi_common::crd::{
+    CONDITION_AVAILABLE, CONDITION_SPEC_CURRENT, DiscoveredApi, DiscoveredApiSpec,
+    DiscoveredApiStatus, SPEC_CONFIGMAP_KEY, SPEC_SIGNATURE_CONFIGMAP_KEY,
+};
+use openapi_common::signing::SpecSignature;
+use openapi_common::{ApiInventoryEntry, DISCOVERY_OUTPUT_ENV, digest::sha256_hex};
+use std::collections::BTreeMap;
+use tracing::info;
+
+use crate::error::AppError;
+
+const FIELD_MANAGER: &str = "openapi-k8s-operator";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscoveryOutput {
+    /// All entries in the `discovery.json` key of one ConfigMap
+    ConfigMap,
+    /// One `DiscoveredApi` per entry
+    Crd,
+}
+
+impl DiscoveryOutput {
+    pub fn from_env() -> Result<Self, AppError> {
+        match std::env::var(DISCOVERY_OUTPUT_ENV)
+            .unwrap_or_default()
+            .trim()
+        {
+            "" | "configmap" => Ok(DiscoveryOutput::ConfigMap),
+            "crd" => Ok(DiscoveryOutput::Crd),
+            other => Err(AppError::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "Invalid {}: '{}', expected 'configmap' or 'crd'",
+                    DISCOVERY_OUTPUT_ENV, other
+                ),
+            ))),
+        }
+    }
+}
+
+fn spec_configmap_name(service_name: &str) -> String {
+    format!("{}-openapi-spec", service_name)
+}
+
+fn labels() -> BTreeMap<String, String> {
+    BTreeMap::from([
+        (
+            "app.kubernetes.io/name".to_string(),
+            "openapi-discovery".to_string(),
+        ),
+        (
+            "app.kubernetes.io/managed-by".to_string(),
+            FIELD_MANAGER.to_string(),
+        ),
+    ])
+}
+
+/// Creates or updates the entry's `DiscoveredApi` and its spec ConfigMap
+pub async fn apply(
+    client: Client,
+    owner: Option<OwnerReference>,
+    entry: &ApiInventoryEntry,
+    spec: &str,
+    signature: Option<&SpecSignature>,
+) -> Result<(), AppError> {
+    let namespace = &entry.namespace;
+    let name = &entry.service_name;
+    let params = PatchParams::apply(FIELD_MANAGER).force();
+    let apis: Api<DiscoveredApi> = Api::namespaced(client.clone(), namespace);
+
+    let mut resource = DiscoveredApi::new(
+        name,
+        DiscoveredApiSpec {
+            spec_config_map: Some(spec_configmap_name(name)),
+            ..DiscoveredApiSpec::from(entry)
+        },
+    );
+    resource.metadata.namespace = Some(namespace.clone());
+    resource.metadata.labels = Some(labels());
+    resource.metadata.owner_references = owner.map(|owner| vec![owner]);
+    let resource = apis.patch(name, &params, &Patch::Apply(&resource)).await?;
+
+    let mut data = BTreeMap::from([(SPEC_CONFIGMAP_KEY.to_string(), spec.to_string())]);
+    if let Some(signature) = signature {
+        data.insert(
+            SPEC_SIGNATURE_CONFIGMAP_KEY.to_string(),
+            signature.to_json(),
+        );
+    }
+    let configmap = ConfigMap {
+        metadata: kube::core::ObjectMeta {
+            name: Some(spec_configmap_name(name)),
+            namespace: Some(namespace.clone()),
+            labels: Some(labels()),
+            owner_references: resource.owner_ref(&()).map(|owner| vec![owner]),
+            ..Default::default()
+        },
+        data: Some(data),
+        ..Default::default()
+    };
+    let configmaps: Api<ConfigMap> = Api::namespaced(client, namespace);
+    configmaps
+        .patch(
+            &spec_configmap_name(name),
+            &params,
+            &Patch::Apply(&configmap),
+        )
+        .await?;
+
+    let mut status = DiscoveredApiStatus {
+        available: entry.available,
+        last_error: entry.last_error.clone(),
+        consecutive_failures: entry.consecutive_failures,
+        last_updated: Some(Utc::now()),
+        spec_sha256: Some(sha256_hex(spec.as_bytes())),
+        contract: entry.contract.clone(),
+        metrics: entry.metrics.clone(),
+        stale_by_schedule: false,
+        // Kept until the next change, also when the operator restarted since
+        last_change_summary: entry.last_change_summary.clone().or_else(|| {
+            resource
+                .status
+                .as_ref()
+                .and_then(|status| status.last_change_summary.clone())
+        }),
+        conditions: resource
+            .status
+            .as_ref()
+            .map(|status| status.conditions.clone())
+            .unwrap_or_default(),
+    };
+    let (reason, message) = if entry.available {
+        ("SpecFetched", "")
+    } else {
+        ("Unavailable", "The service did not serve its spec")
+    };
+    status.set_condition(CONDITION_AVAILABLE, entry.available, reason, message);
+    status.set_condition(
+        CONDITION_SPEC_CURRENT,
+        true,
+        "SpecStored",
+        &format!(
+            "{} bytes in ConfigMap {}",
+            spec.len(),
+            spec_configmap_name(name)
+        ),
+    );
+    let status = serde_json::json!({
+        "apiVersion": DiscoveredApi::api_version(&()),
+        "kind": DiscoveredApi::kind(&()),
+        "status": status,
+    });
+    apis.patch_status(name, &params, &Patch::Apply(&status))
+        .await?;
+
+    info!(
+        "Applied DiscoveredApi {}/{} ({} bytes of spec)",
+        namespace,
+        resource.name_any(),
+        spec.len()
+    );
+    Ok(())
+}
+
+/// Spec currently stored for the entry, `None` if it was never applied
+pub async fn stored_spec(
+    client: Client,
+    namespace: &str,
+    service_name: &str,
+) -> Result<Option<String>, AppError> {
+    let configmaps: Api<ConfigMap> = Api::namespaced(client, namespace);
+    let configmap = configmaps
+        .get_opt(&spec_configmap_name(service_name))
+        .await?;
+    Ok(configmap
+        .and_then(|cm| cm.data)
+        .and_then(|mut data| data.remove(SPEC_CONFIGMAP_KEY)))
+}
+
+/// Flags the entry's `DiscoveredApi` as kept while its refresh window is closed; `false`
+/// if there is none. In a dry run, only logs that an existing one would be flagged.
+pub async fn mark_stale_by_schedule(
+    client: Client,
+    namespace: &str,
+    service_name: &str,
+    dry_run: bool,
+) -> Result<bool, AppError> {
+    let apis: Api<DiscoveredApi> = Api::namespaced(client, namespace);
+    let Some(resource) = apis.get_opt(service_name).await? else {
+        return Ok(false);
+    };
+    if dry_run {
+        info!(
+            "Dry run: would mark DiscoveredApi {}/{} stale by schedule",
+            namespace, service_name
+        );
+        return Ok(true);
+    }
+    let mut status = resource.status.unwrap_or_default();
+    status.set_condition(
+        CONDITION_SPEC_CURRENT,
+        false,
+        "RefreshWindowClosed",
+        "The stored spec is kept until the refresh window opens",
+    );
+    // A merge patch, as applying only these fields would drop the rest of the status
+    let status = serde_json::json!({
+        "status": { "staleBySchedule": true, "conditions": status.conditions }
+    });
+    apis.patch_status(
+        service_name,
+        &PatchParams::default(),
+        &Patch::Merge(&status),
+    )
+    .await?;
+    Ok(true)
+}
+
+/// Marks the entry's `DiscoveredApi` unavailable because its spec could not be fetched,
+/// keeping the stored spec. A service whose spec was never fetched gets a `DiscoveredApi`
+/// of `placeholder` without one.
+pub async fn mark_unavailable(
+    client: Client,
+    owner: Option<OwnerReference>,
+    placeholder: &ApiInventoryEntry,
+    error: &str,
+) -> Result<(), AppError> {
+    let service_name = &placeholder.service_name;
+    let apis: Api<DiscoveredApi> = Api::namespaced(client, &placeholder.namespace);
+    let resource = match apis.get_opt(service_name).await? {
+        Some(resource) => resource,
+        None => {
+            let mut resource =
+                DiscoveredApi::new(service_name, DiscoveredApiSpec::from(placeholder));
+            resource.metadata.namespace = Some(placeholder.namespace.clone());
+            resource.metadata.labels = Some(labels());
+            resource.metadata.owner_references = owner.map(|owner| vec![owner]);
+            apis.patch(
+                service_name,
+                &PatchParams::apply(FIELD_MANAGER).force(),
+                &Patch::Apply(&resource),
+            )
+            .await?
+        }
+    };
+    let mut status = resource.status.unwrap_or_default();
+    status.available = false;
+    status.last_error = Some(error.to_string());
+    status.consecutive_failures += 1;
+    status.set_condition(CONDITION_AVAILABLE, false, "FetchFailed", error);
+    // The whole status is applied, so the next successful apply clears the error again
+    let status = serde_json::json!({
+        "apiVersion": DiscoveredApi::api_version(&()),
+        "kind": DiscoveredApi::kind(&()),
+        "status": status,
+    });
+    apis.patch_status(
+        service_name,
+        &PatchParams::apply(FIELD_MANAGER).force(),
+        &Patch::Apply(&status),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Deletes the entry's `DiscoveredApi`; its spec ConfigMap is garbage collected with it.
+/// `false` if there was none.
+pub async fn delete(client: Client, namespace: &str, service_name: &str) -> Result<bool, AppError> {
+    let apis: Api<DiscoveredApi> = Api::namespaced(client, namespace);
+    match apis.delete(service_name, &DeleteParams::default()).await {
+        Ok(_) => {
+            info!("Deleted DiscoveredApi {}/{}", namespace, service_name);
+            Ok(true)
+        }
+        Err(kube::Error::Api(resp)) if resp.code == 404 => Ok(false),
+        Err(e) => Err(AppError::Kube(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::FakeApiServer;
+    use openapi_common::crd::DiscoveredApiSpec;
+    use openapi_common::diff::ChangeSummary;
+
+    const RESOURCE_PATH: &str = "/apis/api-doc.io/v1alpha1/namespaces/shop/discoveredapis/orders";
+    const SPEC_PATH: &str = "/api/v1/namespaces/shop/configmaps/orders-openapi-spec";
+    const SPEC: &str = "{\"openapi\":\"3.0.0\",\"info\":{\"title\":\"Orders\",\"version\":\"1\"}}";
+
+    fn entry() -> ApiInventoryEntry {
+        ApiInventoryEntry::new("shop", "orders", "http://orders.shop.svc/openapi.json")
+    }
+
+    fn service_owner() -> OwnerReference {
+        OwnerReference {
+            api_version: "v1".to_string(),
+            kind: "Service".to_string(),
+            name: "orders".to_string(),
+            uid: "service-uid".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn condition<'a>(status: &'a DiscoveredApiStatus, type_: &str) -> (&'a str, &'a str) {
+        let condition = status.conditions.iter().find(|c| c.type_ == type_).unwrap();
+        (condition.status.as_str(), condition.reason.as_str())
+    }
+
+    /// A `DiscoveredApi` as stored by an earlier apply
+    fn stored(api: &FakeApiServer, status: DiscoveredApiStatus) {
+        let mut resource = DiscoveredApi::new("orders", DiscoveredApiSpec::from(&entry()));
+        resource.metadata.namespace = Some("shop".to_string());
+        resource.status = Some(status);
+        api.insert(RESOURCE_PATH, resource);
+    }
+
+    #[tokio::test]
+    async fn apply_stores_the_resource_spec_configmap_and_status() {
+        let api = FakeApiServer::start().await;
+        let signature = SpecSignature {
+            algorithm: "ed25519".to_string(),
+            key_id: "0123456789abcdef".to_string(),
+            sha256: sha256_hex(SPEC.as_bytes()),
+            signature: "c2lnbmF0dXJl".to_string(),
+        };
+
+        apply(
+            api.client(),
+            Some(service_owner()),
+            &entry(),
+            SPEC,
+            Some(&signature),
+        )
+        .await
+        .unwrap();
+
+        let resource: DiscoveredApi = api.get(RESOURCE_PATH).unwrap();
+        assert_eq!(
+            resource.spec.spec_config_map.as_deref(),
+            Some("orders-openapi-spec")
+        );
+        assert_eq!(resource.owner_references(), [service_owner()]);
+        assert_eq!(resource.labels(), &labels());
+        let status = resource.status.unwrap();
+        assert!(status.available);
+        assert_eq!(status.spec_sha256, Some(sha256_hex(SPEC.as_bytes())));
+        assert_eq!(
+            condition(&status, CONDITION_AVAILABLE),
+            ("True", "SpecFetched")
+        );
+        assert_eq!(
+            condition(&status, CONDITION_SPEC_CURRENT),
+            ("True", "SpecStored")
+        );
+
+        // The spec ConfigMap is garbage collected with the DiscoveredApi
+        let configmap: ConfigMap = api.get(SPEC_PATH).unwrap();
+        let owner = &configmap.owner_references()[0];
+        assert_eq!(
+            (owner.kind.as_str(), owner.uid.as_str()),
+            ("DiscoveredApi", "uid-orders")
+        );
+        let data = configmap.data.unwrap();
+        assert_eq!(data[SPEC_CONFIGMAP_KEY], SPEC);
+        assert_eq!(data[SPEC_SIGNATURE_CONFIGMAP_KEY], signature.to_json());
+        assert_eq!(
+            stored_spec(api.client(), "shop", "orders").await.unwrap(),
+            Some(SPEC.to_string())
+        );
+        assert_eq!(
+            stored_spec(api.client(), "shop", "carts").await.unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn apply_keeps_the_last_change_summary_until_the_next_change() {
+        let api = FakeApiServer::start().await;
+        let summary = ChangeSummary {
+            changed_at: Utc::now(),
+            summary: "1 path added".to_string(),
+            breaking: 0,
+            added_paths: vec!["/orders".to_string()],
+            removed_paths: Vec::new(),
+            changed_operations: Vec::new(),
+        };
+        stored(
+            &api,
+            DiscoveredApiStatus {
+                last_change_summary: Some(summary.clone()),
+                ..Default::default()
+            },
+        );
+
+        apply(api.client(), None, &entry(), SPEC, None)
+            .await
+            .unwrap();
+
+        let resource: DiscoveredApi = api.get(RESOURCE_PATH).unwrap();
+        assert_eq!(resource.status.unwrap().last_change_summary, Some(summary));
+    }
+
+    #[tokio::test]
+    async fn only_existing_resources_are_marked_stale_by_schedule() {
+        let api = FakeApiServer::start().await;
+        assert!(
+            !mark_stale_by_schedule(api.client(), "shop", "orders", false)
+                .await
+                .unwrap()
+        );
+
+        stored(
+            &api,
+            DiscoveredApiStatus {
+                available: true,
+                ..Default::default()
+            },
+        );
+        assert!(
+            mark_stale_by_schedule(api.client(), "shop", "orders", true)
+                .await
+                .unwrap()
+        );
+        assert!(api.writes().is_empty());
+
+        assert!(
+            mark_stale_by_schedule(api.client(), "shop", "orders", false)
+                .await
+                .unwrap()
+        );
+        let status = api
+            .get::<DiscoveredApi>(RESOURCE_PATH)
+            .unwrap()
+            .status
+            .unwrap();
+        assert!(status.stale_by_schedule);
+        assert!(status.available);
+        assert_eq!(
+            condition(&status, CONDITION_SPEC_CURRENT),
+            ("False", "RefreshWindowClosed")
+        );
+    }
+
+    #[tokio::test]
+    async fn unavailable_services_get_a_placeholder_or_keep_their_spec() {
+        let api = FakeApiServer::start().await;
+
+        mark_unavailable(
+            api.client(),
+            Some(service_owner()),
+            &entry(),
+            "connection refused",
+        )
+        .await
+        .unwrap();
+        let resource: DiscoveredApi = api.get(RESOURCE_PATH).unwrap();
+        assert_eq!(resource.owner_references(), [service_owner()]);
+        let status = resource.status.unwrap();
+        assert!(!status.available);
+        assert_eq!(status.last_error.as_deref(), Some("connection refused"));
+        assert_eq!(status.consecutive_failures, 1);
+        assert_eq!(
+            condition(&status, CONDITION_AVAILABLE),
+            ("False", "FetchFailed")
+        );
+
+        stored(
+            &api,
+            DiscoveredApiStatus {
+                available: true,
+                consecutive_failures: 2,
+                spec_sha256: Some("ab12".to_string()),
+                ..Default::default()
+            },
+        );
+        mark_unavailable(api.client(), None, &entry(), "timed out")
+            .await
+            .unwrap();
+        let status = api
+            .get::<DiscoveredApi>(RESOURCE_PATH)
+            .unwrap()
+            .status
+            .unwrap();
+        assert!(!status.available);
+        assert_eq!(status.consecutive_failures, 3);
+        assert_eq!(status.spec_sha256.as_deref(), Some("ab12"));
+    }
+
+    #[tokio::test]
+    async fn delete_reports_whether_there_was_a_resource() {
+        let api = FakeApiServer::start().await;
+        stored(&api, DiscoveredApiStatus::default());
+
+        assert!(delete(api.client(), "shop", "orders").await.unwrap());
+        assert!(api.get::<DiscoveredApi>(RESOURCE_PATH).is_none());
+        assert!(!delete(api.client(), "shop", "orders").await.unwrap());
+    }
+}
@@ -0,0 +1,89 @@
+//! `DISCOVERY_STORAGE=secret`: the discovery catalog in a Secret instead of a ConfigMap.
+//!
+//! Entry URLs name internal hosts, which everyone allowed to read ConfigMaps in the
+//! discovery namespace can otherwise see. The Secret holds the payload under the key of
+//! the configured encoding, like the ConfigMap would, and shards become Secrets of the same
+//! names. The documentation server mounts it the same way.
+
+use k8s_openapi::ByteString;
+use k8s_openapi::api::core::v1::Secret;
+use kube::api::Api;
+use openapi_common::DiscoveryConfig;
+use openapi_common::encoding::DiscoveryEncoding;
+use std::collections::BTreeMap;
+
+use crate::error::AppError;
+use crate::{ContextData, add_entry_payloads, decode_discovery_config, discovery_labels};
+
+/// Secrets in the discovery namespace
+pub fn secrets(ctx: &ContextData) -> Api<Secret> {
+    Api::namespaced(
+        ctx.discovery.clone().into_client(),
+        &ctx.discovery_namespace,
+    )
+}
+
+/// Document stored in a discovery Secret, whichever encoding wrote it, with the entries
+/// stored under their own keys
+pub fn read_discovery_secret(secret: &Secret, preferred: DiscoveryEncoding) -> DiscoveryConfig {
+    let mut config = decode_discovery_config(preferred, |candidate| {
+        secret
+            .data
+            .as_ref()
+            .and_then(|d| d.get(candidate.key()))
+            .map(|b| b.0.as_slice())
+    });
+    let payloads = secret
+        .data
+        .iter()
+        .flatten()
+        .map(|(key, value)| (key.as_str(), value.0.as_slice()));
+    add_entry_payloads(&mut config, payloads);
+    config
+}
+
+/// Discovery Secret holding `config` under the key of `encoding`
+pub fn discovery_secret(
+    name: &str,
+    namespace: &str,
+    encoding: DiscoveryEncoding,
+    config: &DiscoveryConfig,
+) -> Result<Secret, AppError> {
+    let payload = encoding.encode(config)?;
+    Ok(Secret {
+        metadata: kube::core::ObjectMeta {
+            name: Some(name.to_string()),
+            namespace: Some(namespace.to_string()),
+            labels: Some(discovery_labels()),
+            ..Default::default()
+        },
+        type_: Some("Opaque".to_string()),
+        data: Some(BTreeMap::from([(
+            encoding.key().to_string(),
+            ByteString(payload),
+        )])),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn secrets_round_trip_every_encoding() {
+        let config = DiscoveryConfig {
+            apis: Vec::new(),
+            last_updated: Utc::now(),
+            tombstones: Vec::new(),
+            shards: vec!["openapi-discovery-0".to_string()],
+        };
+        for encoding in DiscoveryEncoding::ALL {
+            let secret = discovery_secret("openapi-discovery", "docs", encoding, &config).unwrap();
+            // Read back with another preferred encoding, as after a switch
+            let read = read_discovery_secret(&secret, DiscoveryEncoding::Json);
+            assert_eq!(read.shards, config.shards, "{:?}", encoding);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! Repairs the discovery ConfigMap when it drifts from the catalog the operator wrote.
+//!
+//! Every update reads the catalog back from the cluster, so a deleted or hand-edited
+//! discovery ConfigMap would lose or keep the wrong entries until each service happens to
+//! be reconciled again. The ConfigMaps (or Secrets) of the discovery namespace are watched
+//! instead, and when the index or one of its shards no longer holds the catalog last
+//! written, that catalog is written again right away. The catalog lock is held while
+//! comparing, so the operator's own writes are never mistaken for drift.
+
+use futures::{Stream, StreamExt};
+use kube::{
+    Resource, ResourceExt,
+    api::Api,
+    runtime::{
+        WatchStreamExt,
+        watcher::{self, Config, Event},
+    },
+};
+use openapi_common::DiscoveryConfig;
+use openapi_common::encoding::DiscoveryStorage;
+use serde::de::DeserializeOwned;
+use std::fmt::Debug;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::ContextData;
+use crate::discovery_secret;
+use crate::error::AppError;
+use crate::sharding::{read_catalog, write_catalog_locked};
+
+/// Watches the discovery namespace until the operator stops
+pub async fn run(ctx: Arc<ContextData>) {
+    info!(
+        "Restoring the discovery {} '{}' when it is deleted or modified",
+        ctx.storage.kind(),
+        ctx.discovery_configmap
+    );
+    let mut changes = match ctx.storage {
+        DiscoveryStorage::ConfigMap => {
+            changes(ctx.discovery.clone(), &ctx.discovery_configmap).boxed()
+        }
+        DiscoveryStorage::Secret => {
+            changes(discovery_secret::secrets(&ctx), &ctx.discovery_configmap).boxed()
+        }
+    };
+    while changes.next().await.is_some() {
+        if let Err(e) = repair(&ctx).await {
+            warn!(
+                "Failed to restore the discovery {}: {}",
+                ctx.storage.kind(),
+                e
+            );
+        }
+    }
+}
+
+/// Changes and deletions of the discovery document named `name` and of its shards
+fn changes<K>(api: Api<K>, name: &str) -> impl Stream<Item = ()> + Send + 'static
+where
+    K: Resource + Clone + DeserializeOwned + Debug + Send + Sync + 'static,
+{
+    let name = name.to_string();
+    watcher::watcher(api, Config::default())
+        .default_backoff()
+        .filter_map(move |event| {
+            let changed = match event {
+                Ok(Event::Apply(object) | Event::Delete(object)) => {
+                    is_catalog_document(&name, &object.name_any())
+                }
+                Ok(Event::InitDone) => true,
+                Ok(Event::Init | Event::InitApply(_)) => false,
+                Err(e) => {
+                    warn!("Discovery ConfigMap watcher error: {}", e);
+                    false
+                }
+            };
+            futures::future::ready(changed.then_some(()))
+        })
+}
+
+/// Whether `object` is the discovery document `name` or one of its shards (`<name>-<n>`)
+fn is_catalog_document(name: &str, object: &str) -> bool {
+    object == name
+        || object.strip_prefix(name).is_some_and(|suffix| {
+            suffix
+                .strip_prefix('-')
+                .is_some_and(|index| !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit()))
+        })
+}
+
+/// Same entries and tombstones, whichever shard holds the entries
+pub fn same_catalog(current: &DiscoveryConfig, expected: &DiscoveryConfig) -> bool {
+    let content = |config: &DiscoveryConfig| {
+        let mut apis: Vec<_> = config.apis.iter().collect();
+        apis.sort_by(|a, b| a.id.cmp(&b.id));
+        serde_json::to_value((apis, &config.tombstones)).ok()
+    };
+    content(current) == content(expected)
+}
+
+async fn repair(ctx: &ContextData) -> Result<(), AppError> {
+    let mut written = ctx.written_catalog.lock().await;
+    let Some(expected) = written.clone() else {
+        return Ok(());
+    };
+    let current = read_catalog(ctx).await?;
+    match &current {
+        Some(current) if same_catalog(current, &expected) => return Ok(()),
+        Some(_) => warn!(
+            "Discovery {} '{}' was modified outside the operator, restoring {} entries",
+            ctx.storage.kind(),
+            ctx.discovery_configmap,
+            expected.apis.len()
+        ),
+        None => warn!(
+            "Discovery {} '{}' was deleted, recreating it with {} entries",
+            ctx.storage.kind(),
+            ctx.discovery_configmap,
+            expected.apis.len()
+        ),
+    }
+    write_catalog_locked(ctx, expected, &mut written).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use openapi_common::ApiInventoryEntry;
+
+    #[test]
+    fn drift_ignores_the_order_of_sharded_entries() {
+        let entry = |service: &str| {
+            ApiInventoryEntry::new("shop", service, "http://api.shop.svc:8080/openapi.json")
+        };
+        let (a, b) = (entry("a"), entry("b"));
+        let config = |apis: &[&ApiInventoryEntry]| DiscoveryConfig {
+            apis: apis.iter().map(|api| (*api).clone()).collect(),
+            last_updated: Utc::now(),
+            tombstones: Vec::new(),
+            shards: Vec::new(),
+        };
+
+        assert!(same_catalog(&config(&[&a, &b]), &config(&[&b, &a])));
+        assert!(!same_catalog(&config(&[&a]), &config(&[&a, &b])));
+        assert!(is_catalog_document(
+            "openapi-discovery",
+            "openapi-discovery-3"
+        ));
+        assert!(!is_catalog_document(
+            "openapi-discovery",
+            "openapi-discovery-spec-shop.orders"
+        ));
+    }
+}
@@ -0,0 +1,105 @@
+//! Holds back spec fetches while a Service has no ready endpoints.
+//!
+//! During a rollout, or while a new Deployment starts, the Service has no ready pods and a
+//! fetch would fail and flip the entry to unavailable. The EndpointSlices of the Service
+//! are checked first instead: without a ready endpoint the stored entry is kept and the
+//! Service is reconciled again shortly. ExternalName Services and Services without a
+//! selector and without EndpointSlices are fetched as usual, and so is every Service when
+//! the EndpointSlices cannot be listed.
+
+use k8s_openapi::api::core::v1::Service;
+use k8s_openapi::api::discovery::v1::EndpointSlice;
+use kube::{
+    Client, ResourceExt,
+    api::{Api, ListParams},
+};
+use std::time::Duration;
+use tracing::warn;
+
+/// How soon a Service without ready endpoints is reconciled again
+pub const NOT_READY_REQUEUE: Duration = Duration::from_secs(15);
+
+/// Label of an EndpointSlice naming the Service it belongs to
+const SERVICE_NAME_LABEL: &str = "kubernetes.io/service-name";
+
+/// Whether the spec of `service` can be fetched now
+pub async fn has_ready_endpoints(client: Client, service: &Service) -> bool {
+    let spec = service.spec.as_ref();
+    if spec.and_then(|s| s.type_.as_deref()) == Some("ExternalName") {
+        return true;
+    }
+    let namespace = service.namespace().unwrap_or_default();
+    let slices: Api<EndpointSlice> = Api::namespaced(client, &namespace);
+    let params =
+        ListParams::default().labels(&format!("{}={}", SERVICE_NAME_LABEL, service.name_any()));
+    let slices = match slices.list(&params).await {
+        Ok(slices) => slices.items,
+        Err(e) => {
+            warn!(
+                "Failed to list EndpointSlices of service {}/{}, fetching its spec anyway: {}",
+                namespace,
+                service.name_any(),
+                e
+            );
+            return true;
+        }
+    };
+    let has_selector = spec
+        .and_then(|s| s.selector.as_ref())
+        .is_some_and(|selector| !selector.is_empty());
+    any_ready(&slices).unwrap_or(!has_selector)
+}
+
+/// Whether any endpoint is ready; `None` without EndpointSlices. Endpoints without a
+/// ready condition count as ready, as they do for kube-proxy.
+fn any_ready(slices: &[EndpointSlice]) -> Option<bool> {
+    if slices.is_empty() {
+        return None;
+    }
+    Some(
+        slices
+            .iter()
+            .flat_map(|slice| &slice.endpoints)
+            .any(|endpoint| {
+                endpoint
+                    .conditions
+                    .as_ref()
+                    .and_then(|conditions| conditions.ready)
+                    .unwrap_or(true)
+            }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::discovery::v1::{Endpoint, EndpointConditions};
+
+    #[test]
+    fn only_ready_endpoints_count() {
+        let slice = |ready: &[Option<bool>]| EndpointSlice {
+            address_type: "IPv4".to_string(),
+            endpoints: ready
+                .iter()
+                .map(|ready| Endpoint {
+                    addresses: vec!["10.0.0.1".to_string()],
+                    conditions: Some(EndpointConditions {
+                        ready: *ready,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                })
+                .collect(),
+            metadata: Default::default(),
+            ports: None,
+        };
+
+        assert_eq!(any_ready(&[]), None);
+        assert_eq!(any_ready(&[slice(&[])]), Some(false));
+        assert_eq!(any_ready(&[slice(&[Some(false)])]), Some(false));
+        assert_eq!(
+            any_ready(&[slice(&[Some(false)]), slice(&[None])]),
+            Some(true)
+        );
+    }
+}
@@ -0,0 +1,243 @@
+//! `DISCOVERY_LAYOUT=entries`: every catalog entry under its own key of the discovery
+//! ConfigMap (or Secret).
+//!
+//! In the default layout an update reads the whole catalog, replaces one entry and writes
+//! the catalog back, so two updates racing each other can drop one another's entries. Here
+//! an update patches only the key of its entry, e.g. `shop.orders.json`; the payload key of
+//! the encoding keeps the tombstones. Removals, drift repair and federation patch only the
+//! keys whose content changed. A catalog in this layout is not sharded.
+
+use openapi_common::encoding::{self, DiscoveryEncoding, DiscoveryLayout};
+use openapi_common::{ApiInventoryEntry, ApiTombstone, DiscoveryConfig, RemovalReason};
+use std::collections::BTreeMap;
+use tracing::info;
+
+use crate::ContextData;
+use crate::error::AppError;
+use crate::sharding::{self, KeyChanges, patch_keys, payloads, write_catalog_locked};
+
+/// The payload key's document: the tombstones, without entries or shards
+fn index(config: &DiscoveryConfig) -> DiscoveryConfig {
+    DiscoveryConfig {
+        apis: Vec::new(),
+        last_updated: config.last_updated,
+        tombstones: config.tombstones.clone(),
+        shards: Vec::new(),
+    }
+}
+
+/// Changes turning the stored `current` keys into `config`: entries and tombstones whose
+/// content differs, entries no longer in the catalog removed, and the payloads of other
+/// encodings dropped
+fn changes(
+    encoding: DiscoveryEncoding,
+    current: &BTreeMap<String, Vec<u8>>,
+    config: &DiscoveryConfig,
+) -> Result<KeyChanges, AppError> {
+    let mut changes = KeyChanges::new();
+    let mut wanted = Vec::new();
+    for api in &config.apis {
+        let key = encoding.entry_key(api);
+        let unchanged = current
+            .get(&key)
+            .and_then(|bytes| encoding::decode::<ApiInventoryEntry>(bytes).ok())
+            .is_some_and(|stored| {
+                serde_json::to_value(&stored).ok() == serde_json::to_value(api).ok()
+            });
+        if !unchanged {
+            changes.insert(key.clone(), Some(encoding.encode(api)?));
+        }
+        wanted.push(key);
+    }
+    for key in current.keys() {
+        if encoding::is_entry_key(key) && !wanted.contains(key) {
+            changes.insert(key.clone(), None);
+        }
+    }
+
+    let stored_index = current
+        .get(encoding.key())
+        .and_then(|bytes| encoding::decode::<DiscoveryConfig>(bytes).ok());
+    let index_unchanged = stored_index.is_some_and(|stored| {
+        stored.apis.is_empty()
+            && stored.shards.is_empty()
+            && serde_json::to_value(&stored.tombstones).ok()
+                == serde_json::to_value(&config.tombstones).ok()
+    });
+    if !index_unchanged {
+        changes.insert(
+            encoding.key().to_string(),
+            Some(encoding.encode(&index(config))?),
+        );
+    }
+    for other in DiscoveryEncoding::ALL
+        .iter()
+        .filter(|other| **other != encoding)
+    {
+        if current.contains_key(other.key()) {
+            changes.insert(other.key().to_string(), None);
+        }
+    }
+    Ok(changes)
+}
+
+/// Writes `config` in the `entries` layout, creating the discovery object if it is missing.
+/// Only called with the `written_catalog` lock held.
+pub async fn write_catalog(ctx: &ContextData, config: &DiscoveryConfig) -> Result<(), AppError> {
+    let name = &ctx.discovery_configmap;
+    let current = match payloads(ctx, name).await? {
+        Some(current) => current,
+        None => {
+            let index = index(config);
+            if sharding::create_document(ctx, name, &index).await? {
+                BTreeMap::from([(ctx.encoding.key().to_string(), ctx.encoding.encode(&index)?)])
+            } else {
+                // Created since it was read: patch it like any existing object
+                payloads(ctx, name).await?.unwrap_or_default()
+            }
+        }
+    };
+    patch_keys(ctx, name, &changes(ctx.encoding, &current, config)?).await?;
+    Ok(())
+}
+
+/// Stores `entry` under its key, leaving every other entry alone
+pub async fn put_entry(ctx: &ContextData, entry: ApiInventoryEntry) -> Result<(), AppError> {
+    let mut written = ctx.written_catalog.lock().await;
+    let key = ctx.encoding.entry_key(&entry);
+    let changes = KeyChanges::from([(key.clone(), Some(ctx.encoding.encode(&entry)?))]);
+    let mut catalog = written.clone().unwrap_or_else(|| DiscoveryConfig {
+        apis: Vec::new(),
+        last_updated: entry.last_updated,
+        tombstones: Vec::new(),
+        shards: Vec::new(),
+    });
+    catalog.apis.retain(|api| api.id != entry.id);
+    catalog.apis.push(entry);
+    catalog.last_updated = chrono::Utc::now();
+
+    match patch_keys(ctx, &ctx.discovery_configmap, &changes).await {
+        Ok(()) => {}
+        // Deleted since startup: recreate it with the whole catalog
+        Err(kube::Error::Api(resp)) if resp.code == 404 => {
+            write_catalog(ctx, &catalog).await?;
+        }
+        Err(e) => return Err(e.into()),
+    }
+    info!(
+        "Stored entry under key '{}' of the discovery {}",
+        key,
+        ctx.storage.kind()
+    );
+    if let Some(audit) = &ctx.audit {
+        audit.record(written.as_ref(), &catalog);
+    }
+    *written = Some(catalog);
+    Ok(())
+}
+
+/// Removes the keys of `removed`, adding their tombstones to the payload key
+pub async fn remove_entries(
+    ctx: &ContextData,
+    removed: &[ApiInventoryEntry],
+    reason: RemovalReason,
+) -> Result<(), AppError> {
+    let mut written = ctx.written_catalog.lock().await;
+    let Some(mut catalog) = sharding::read_catalog(ctx).await? else {
+        return Ok(());
+    };
+    let ids: Vec<_> = removed.iter().map(|entry| entry.id.as_str()).collect();
+    catalog.apis.retain(|api| !ids.contains(&api.id.as_str()));
+    if ctx.tombstone_grace > chrono::Duration::zero() {
+        catalog.tombstones.extend(
+            removed
+                .iter()
+                .map(|entry| ApiTombstone::for_entry(entry, reason)),
+        );
+    }
+    catalog.prune_tombstones(ctx.tombstone_grace);
+    catalog.last_updated = chrono::Utc::now();
+
+    let mut changes: KeyChanges = removed
+        .iter()
+        .map(|entry| (ctx.encoding.entry_key(entry), None))
+        .collect();
+    changes.insert(
+        ctx.encoding.key().to_string(),
+        Some(ctx.encoding.encode(&index(&catalog))?),
+    );
+    patch_keys(ctx, &ctx.discovery_configmap, &changes).await?;
+    if let Some(audit) = &ctx.audit {
+        audit.record(written.as_ref(), &catalog);
+    }
+    *written = Some(catalog);
+    Ok(())
+}
+
+/// Rewrites a catalog left in the other layout, e.g. after `DISCOVERY_LAYOUT` changed
+pub async fn migrate(ctx: &ContextData, config: &DiscoveryConfig) -> Result<(), AppError> {
+    let Some(current) = payloads(ctx, &ctx.discovery_configmap).await? else {
+        return Ok(());
+    };
+    let other_layout = match ctx.layout {
+        DiscoveryLayout::Entries => current
+            .get(ctx.encoding.key())
+            .and_then(|bytes| encoding::decode::<DiscoveryConfig>(bytes).ok())
+            .is_some_and(|stored| !stored.apis.is_empty() || !stored.shards.is_empty()),
+        DiscoveryLayout::Document => current.keys().any(|key| encoding::is_entry_key(key)),
+    };
+    if !other_layout {
+        return Ok(());
+    }
+    info!(
+        "Rewriting the {} catalog entries in the {:?} layout",
+        config.apis.len(),
+        ctx.layout
+    );
+    let mut written = ctx.written_catalog.lock().await;
+    write_catalog_locked(ctx, config.clone(), &mut written).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn only_changed_keys_are_patched() {
+        let entry = |service: &str| {
+            ApiInventoryEntry::new("shop", service, "http://api.shop.svc:8080/openapi.json")
+        };
+        let config = DiscoveryConfig {
+            apis: vec![entry("orders"), entry("carts")],
+            last_updated: Utc::now(),
+            tombstones: Vec::new(),
+            shards: Vec::new(),
+        };
+        let encoding = DiscoveryEncoding::Gzip;
+
+        let created = changes(encoding, &BTreeMap::new(), &config).unwrap();
+        assert_eq!(
+            created.keys().collect::<Vec<_>>(),
+            vec![
+                "discovery.json.gz",
+                "shop.carts.json.gz",
+                "shop.orders.json.gz"
+            ]
+        );
+
+        let mut current: BTreeMap<_, _> = created
+            .into_iter()
+            .map(|(key, payload)| (key, payload.unwrap()))
+            .collect();
+        current.insert("discovery.json".to_string(), b"{}".to_vec());
+        let mut updated = config.clone();
+        updated.apis[0].available = false;
+        updated.apis.pop();
+        let changed = changes(encoding, &current, &updated).unwrap();
+        assert!(changed["shop.orders.json.gz"].is_some());
+        assert!(changed["shop.carts.json.gz"].is_none());
+        assert!(changed["discovery.json"].is_none());
+        assert_eq!(changed.len(), 3);
+    }
+}
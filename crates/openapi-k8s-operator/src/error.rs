@@ -1,11 +1,20 @@
+use openapi_common::encoding::EncodingError;
+use openapi_common::fetch::FetchError;
 use std::fmt;
 
+use crate::publish::PublishError;
+use crate::spec_store::SpecStorageError;
+
 #[derive(Debug)]
 pub enum AppError {
     Kube(kube::Error),
     Reqwest(reqwest::Error),
     Serde(serde_json::Error),
+    Encoding(EncodingError),
     Io(std::io::Error),
+    Fetch(FetchError),
+    Publish(PublishError),
+    SpecStorage(SpecStorageError),
 }
 
 impl fmt::Display for AppError {
@@ -14,7 +23,11 @@ impl fmt::Display for AppError {
             AppError::Kube(e) => write!(f, "Kubernetes error: {}", e),
             AppError::Reqwest(e) => write!(f, "HTTP error: {}", e),
             AppError::Serde(e) => write!(f, "Serialization error: {}", e),
+            AppError::Encoding(e) => write!(f, "Discovery encoding error: {}", e),
             AppError::Io(e) => write!(f, "IO error: {}", e),
+            AppError::Fetch(e) => write!(f, "Spec fetch error: {}", e),
+            AppError::Publish(e) => write!(f, "Publish error: {}", e),
+            AppError::SpecStorage(e) => write!(f, "Spec storage error: {}", e),
         }
     }
 }
@@ -25,7 +38,11 @@ impl std::error::Error for AppError {
             AppError::Kube(e) => Some(e),
             AppError::Reqwest(e) => Some(e),
             AppError::Serde(e) => Some(e),
+            AppError::Encoding(e) => Some(e),
             AppError::Io(e) => Some(e),
+            AppError::Fetch(e) => Some(e),
+            AppError::Publish(e) => Some(e),
+            AppError::SpecStorage(e) => Some(e),
         }
     }
 }
@@ -48,8 +65,32 @@ impl From<serde_json::Error> for AppError {
     }
 }
 
+impl From<EncodingError> for AppError {
+    fn from(err: EncodingError) -> Self {
+        AppError::Encoding(err)
+    }
+}
+
 impl From<std::io::Error> for AppError {
     fn from(err: std::io::Error) -> Self {
         AppError::Io(err)
     }
 }
+
+impl From<FetchError> for AppError {
+    fn from(err: FetchError) -> Self {
+        AppError::Fetch(err)
+    }
+}
+
+impl From<PublishError> for AppError {
+    fn from(err: PublishError) -> Self {
+        AppError::Publish(err)
+    }
+}
+
+impl From<SpecStorageError> for AppError {
+    fn from(err: SpecStorageError) -> Self {
+        AppError::SpecStorage(err)
+    }
+}
@@ -0,0 +1,294 @@
+//! Federation of the catalogs of other clusters into the local one.
+//!
+//! Each file in `FEDERATION_KUBECONFIG_DIR`, e.g. a kubeconfig mounted from a Secret, names
+//! a remote cluster after the file. Every `FEDERATION_INTERVAL` the operator reads the
+//! discovery ConfigMap (or Secret) of each cluster, under the local `DISCOVERY_NAMESPACE`,
+//! `DISCOVERY_CONFIGMAP` and encoding, and merges its entries into the local catalog with
+//! `cluster` set and the id prefixed with the cluster name. Entries a remote operator
+//! federated itself are left out, so operators may federate each other. A cluster that
+//! cannot be read keeps its entries until it can; entries of clusters no longer configured
+//! are dropped.
+
+use chrono::Utc;
+use kube::Client;
+use kube::config::{KubeConfigOptions, Kubeconfig};
+use openapi_common::ApiInventoryEntry;
+use openapi_common::names::validate_dns_label;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::{info, warn};
+
+use crate::ContextData;
+use crate::error::AppError;
+use crate::sharding::{read_catalog, read_catalog_from, write_catalog_locked};
+
+/// Directory holding one kubeconfig per remote cluster; federation is off when unset
+pub const FEDERATION_KUBECONFIG_DIR_ENV: &str = "FEDERATION_KUBECONFIG_DIR";
+/// Seconds between reads of the remote catalogs
+pub const FEDERATION_INTERVAL_ENV: &str = "FEDERATION_INTERVAL";
+
+const DEFAULT_INTERVAL_SECS: u64 = 60;
+
+struct RemoteCluster {
+    name: String,
+    client: Client,
+}
+
+pub struct Federation {
+    clusters: Vec<RemoteCluster>,
+    interval: Duration,
+}
+
+impl Federation {
+    /// `None` when `FEDERATION_KUBECONFIG_DIR` is unset
+    pub async fn from_env() -> Result<Option<Self>, AppError> {
+        let Some(dir) = std::env::var(FEDERATION_KUBECONFIG_DIR_ENV)
+            .ok()
+            .map(|dir| dir.trim().to_string())
+            .filter(|dir| !dir.is_empty())
+        else {
+            return Ok(None);
+        };
+        let interval = match std::env::var(FEDERATION_INTERVAL_ENV) {
+            Ok(value) => value
+                .trim()
+                .parse::<u64>()
+                .ok()
+                .filter(|secs| *secs > 0)
+                .ok_or_else(|| invalid(FEDERATION_INTERVAL_ENV, &value, "seconds above 0"))?,
+            Err(_) => DEFAULT_INTERVAL_SECS,
+        };
+
+        let mut clusters = Vec::new();
+        for path in kubeconfig_files(Path::new(&dir))? {
+            let name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or_default()
+                .to_string();
+            validate_dns_label(&name).map_err(|e| {
+                invalid(
+                    FEDERATION_KUBECONFIG_DIR_ENV,
+                    &path.display().to_string(),
+                    &format!("a file named after its cluster ({})", e),
+                )
+            })?;
+            let kubeconfig = Kubeconfig::read_from(&path).map_err(|e| {
+                invalid(
+                    FEDERATION_KUBECONFIG_DIR_ENV,
+                    &path.display().to_string(),
+                    &format!("a kubeconfig ({})", e),
+                )
+            })?;
+            let config =
+                kube::Config::from_custom_kubeconfig(kubeconfig, &KubeConfigOptions::default())
+                    .await
+                    .map_err(|e| {
+                        invalid(
+                            FEDERATION_KUBECONFIG_DIR_ENV,
+                            &path.display().to_string(),
+                            &format!("a usable kubeconfig ({})", e),
+                        )
+                    })?;
+            clusters.push(RemoteCluster {
+                name,
+                client: Client::try_from(config)?,
+            });
+        }
+
+        Ok(Some(Self {
+            clusters,
+            interval: Duration::from_secs(interval),
+        }))
+    }
+
+    pub fn cluster_names(&self) -> Vec<&str> {
+        self.clusters.iter().map(|c| c.name.as_str()).collect()
+    }
+}
+
+fn invalid(variable: &str, value: &str, expected: &str) -> AppError {
+    AppError::Io(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("Invalid {}: '{}', expected {}", variable, value, expected),
+    ))
+}
+
+/// Files of the directory, sorted, without the hidden `..data` links of Secret volumes
+fn kubeconfig_files(dir: &Path) -> Result<Vec<std::path::PathBuf>, AppError> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let hidden = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_none_or(|name| name.starts_with('.'));
+        if !hidden && path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Merges the remote catalogs into the local one until the operator stops
+pub async fn run(ctx: Arc<ContextData>, federation: Federation) {
+    loop {
+        match sync(&ctx, &federation).await {
+            Ok(None) => {}
+            Ok(Some(count)) => info!(
+                "Federated catalog updated, {} entries from other clusters",
+                count
+            ),
+            Err(e) => warn!("Catalog federation failed: {}", e),
+        }
+        sleep(federation.interval).await;
+    }
+}
+
+/// Reads every remote catalog and writes the merged one; `Some` with the number of federated
+/// entries when the local catalog changed
+async fn sync(ctx: &ContextData, federation: &Federation) -> Result<Option<usize>, AppError> {
+    let mut remote = BTreeMap::new();
+    for cluster in &federation.clusters {
+        let entries = match read_catalog_from(ctx, &cluster.client).await {
+            Ok(catalog) => Some(federated_entries(
+                &cluster.name,
+                catalog.map(|catalog| catalog.apis).unwrap_or_default(),
+            )),
+            Err(e) => {
+                warn!(
+                    "Failed to read the catalog of cluster {}, keeping its entries: {}",
+                    cluster.name, e
+                );
+                None
+            }
+        };
+        remote.insert(cluster.name.clone(), entries);
+    }
+
+    let mut written = ctx.written_catalog.lock().await;
+    let Some(mut config) = read_catalog(ctx).await? else {
+        return Ok(None);
+    };
+    let merged = merge(config.apis.clone(), &remote);
+    if by_id(&merged) == by_id(&config.apis) {
+        return Ok(None);
+    }
+    let count = merged.iter().filter(|api| api.cluster.is_some()).count();
+    if ctx.dry_run {
+        info!(
+            "Dry run: would write the catalog with {} entries from other clusters",
+            count
+        );
+        return Ok(None);
+    }
+
+    config.apis = merged;
+    config.last_updated = Utc::now();
+    config.prune_tombstones(ctx.tombstone_grace);
+    write_catalog_locked(ctx, config, &mut written).await?;
+    Ok(Some(count))
+}
+
+/// The cluster's own entries, tagged with its name and an id unique across clusters. Specs
+/// kept in a ConfigMap of the remote cluster cannot be served here.
+fn federated_entries(cluster: &str, apis: Vec<ApiInventoryEntry>) -> Vec<ApiInventoryEntry> {
+    apis.into_iter()
+        .filter(|api| api.cluster.is_none())
+        .map(|api| ApiInventoryEntry {
+            id: format!("{}.{}", cluster, api.id),
+            cluster: Some(cluster.to_string()),
+            spec_config_map: None,
+            ..api
+        })
+        .collect()
+}
+
+/// Local entries followed by the federated ones: the entries read from a cluster replace
+/// its previous ones, a cluster that could not be read (`None`) keeps them, and entries of
+/// clusters not in `remote` are dropped
+fn merge(
+    apis: Vec<ApiInventoryEntry>,
+    remote: &BTreeMap<String, Option<Vec<ApiInventoryEntry>>>,
+) -> Vec<ApiInventoryEntry> {
+    let mut merged: Vec<_> = apis
+        .into_iter()
+        .filter(|api| match &api.cluster {
+            None => true,
+            Some(cluster) => matches!(remote.get(cluster), Some(None)),
+        })
+        .collect();
+    merged.extend(remote.values().flatten().flatten().cloned());
+    merged
+}
+
+/// Entries by id, so catalogs compare regardless of their order
+fn by_id(apis: &[ApiInventoryEntry]) -> BTreeMap<&str, Option<serde_json::Value>> {
+    apis.iter()
+        .map(|api| (api.id.as_str(), serde_json::to_value(api).ok()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(namespace: &str, service: &str) -> ApiInventoryEntry {
+        ApiInventoryEntry {
+            spec_config_map: Some("openapi-spec-shop.orders".to_string()),
+            ..ApiInventoryEntry::new(
+                namespace,
+                service,
+                format!(
+                    "http://{}.{}.svc.cluster.local:8080/openapi.json",
+                    service, namespace
+                ),
+            )
+        }
+    }
+
+    #[test]
+    fn merges_remote_entries_per_cluster() {
+        // eu federated us itself, which must not come back through eu
+        let mut relayed = entry("shop", "carts");
+        relayed.cluster = Some("us".to_string());
+        let eu = federated_entries("eu", vec![entry("shop", "orders"), relayed]);
+        assert_eq!(eu.len(), 1);
+        assert_eq!(eu[0].id, "eu.shop.orders");
+        assert_eq!(eu[0].cluster.as_deref(), Some("eu"));
+        assert_eq!(eu[0].service_name, "orders");
+        assert_eq!(eu[0].spec_config_map, None);
+
+        let local = entry("shop", "orders");
+        let previous = vec![
+            local.clone(),
+            federated_entries("eu", vec![entry("shop", "payments")]).remove(0),
+            federated_entries("us", vec![entry("shop", "carts")]).remove(0),
+            federated_entries("ap", vec![entry("shop", "carts")]).remove(0),
+        ];
+        let remote = BTreeMap::from([("eu".to_string(), Some(eu)), ("us".to_string(), None)]);
+        let ids: Vec<_> = merge(previous, &remote)
+            .into_iter()
+            .map(|api| api.id)
+            .collect();
+        // eu is replaced, unreadable us kept, unconfigured ap dropped
+        assert_eq!(ids, vec!["shop.orders", "us.shop.carts", "eu.shop.orders"]);
+    }
+
+    #[test]
+    fn federated_entries_get_a_slug_of_their_own() {
+        let local = entry("shop", "orders");
+        let remote = federated_entries("eu", vec![entry("shop", "orders")]).remove(0);
+        assert_eq!(
+            (remote.namespace.as_str(), remote.service_name.as_str()),
+            ("shop", "orders")
+        );
+
+        let slugs = openapi_common::slugs::entry_slugs([&local, &remote]);
+        assert_eq!(slugs, vec!["shop-orders", "eu-shop-orders"]);
+    }
+}
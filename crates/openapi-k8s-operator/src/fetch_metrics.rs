@@ -0,0 +1,255 @@
+//! Per-service spec fetch metrics in the Prometheus text format, served on `/metrics` on
+//! `METRICS_PORT`.
+//!
+//! Every fetch the controllers make is recorded under the namespace and name of the Service
+//! (or `ApiDoc`) it was made for: a histogram of fetch durations, fetches by result, the size
+//! and HTTP status of the last fetch, and the current streak of failed fetches. Series of
+//! APIs that leave the catalog for good are dropped, those of unreachable ones are kept.
+
+use axum::{Router, extract::State, http::header, routing::get};
+use openapi_common::fetch::FetchFailure;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::info;
+
+use crate::error::AppError;
+
+pub const METRICS_PORT_ENV: &str = "METRICS_PORT";
+
+/// Upper bounds of the duration histogram buckets, in seconds
+const DURATION_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+#[derive(Debug, Default)]
+struct ServiceStats {
+    /// Fetches per upper bound of [`DURATION_BUCKETS`], not cumulative
+    duration_buckets: [u64; DURATION_BUCKETS.len()],
+    duration_count: u64,
+    duration_sum: f64,
+    /// `success` or the failure phase -> fetches
+    results: BTreeMap<String, u64>,
+    last_size_bytes: Option<usize>,
+    /// 200 after a successful fetch, 0 when a failed one got no response
+    last_status: u16,
+    consecutive_failures: u64,
+}
+
+/// Fetch statistics of every service, keyed by `(namespace, name)`
+#[derive(Debug, Default)]
+pub struct FetchMetrics {
+    services: Mutex<BTreeMap<(String, String), ServiceStats>>,
+}
+
+impl FetchMetrics {
+    /// Records one fetch with the size of the spec it returned, or its failure. Every
+    /// successful fetch counts as status 200, whatever 2xx status the service answered.
+    pub fn record(
+        &self,
+        namespace: &str,
+        name: &str,
+        elapsed: Duration,
+        result: Result<usize, &FetchFailure>,
+    ) {
+        let mut services = self.services.lock().unwrap();
+        let stats = services
+            .entry((namespace.to_string(), name.to_string()))
+            .or_default();
+
+        let seconds = elapsed.as_secs_f64();
+        if let Some(bucket) = DURATION_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            stats.duration_buckets[bucket] += 1;
+        }
+        stats.duration_count += 1;
+        stats.duration_sum += seconds;
+
+        let result_label = match result {
+            Ok(size) => {
+                stats.last_size_bytes = Some(size);
+                stats.last_status = 200;
+                stats.consecutive_failures = 0;
+                "success".to_string()
+            }
+            Err(failure) => {
+                stats.last_status = failure.status.unwrap_or(0);
+                stats.consecutive_failures += 1;
+                failure.phase.to_string()
+            }
+        };
+        *stats.results.entry(result_label).or_default() += 1;
+    }
+
+    /// Drops the series of an API that left the catalog
+    pub fn forget(&self, namespace: &str, name: &str) {
+        self.services
+            .lock()
+            .unwrap()
+            .remove(&(namespace.to_string(), name.to_string()));
+    }
+
+    /// All series in the Prometheus text format
+    pub fn render(&self) -> String {
+        let services = self.services.lock().unwrap();
+        let labels = |(namespace, name): &(String, String)| {
+            format!(
+                "namespace=\"{}\",service=\"{}\"",
+                label_value(namespace),
+                label_value(name)
+            )
+        };
+
+        let mut out = String::from(
+            "# HELP openapi_operator_spec_fetch_duration_seconds Duration of spec fetches\n\
+             # TYPE openapi_operator_spec_fetch_duration_seconds histogram\n",
+        );
+        for (key, stats) in services.iter() {
+            let labels = labels(key);
+            let mut cumulative = 0;
+            for (bound, count) in DURATION_BUCKETS.iter().zip(stats.duration_buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "openapi_operator_spec_fetch_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "openapi_operator_spec_fetch_duration_seconds_bucket{{{},le=\"+Inf\"}} {}\n\
+                 openapi_operator_spec_fetch_duration_seconds_sum{{{}}} {}\n\
+                 openapi_operator_spec_fetch_duration_seconds_count{{{}}} {}",
+                labels,
+                stats.duration_count,
+                labels,
+                stats.duration_sum,
+                labels,
+                stats.duration_count
+            );
+        }
+
+        out.push_str(
+            "# HELP openapi_operator_spec_fetches_total Spec fetches by result (success or failure phase)\n\
+             # TYPE openapi_operator_spec_fetches_total counter\n",
+        );
+        for (key, stats) in services.iter() {
+            for (result, count) in &stats.results {
+                let _ = writeln!(
+                    out,
+                    "openapi_operator_spec_fetches_total{{{},result=\"{}\"}} {}",
+                    labels(key),
+                    result,
+                    count
+                );
+            }
+        }
+
+        out.push_str(
+            "# HELP openapi_operator_spec_size_bytes Size of the last fetched spec\n\
+             # TYPE openapi_operator_spec_size_bytes gauge\n",
+        );
+        for (key, stats) in services.iter() {
+            if let Some(size) = stats.last_size_bytes {
+                let _ = writeln!(
+                    out,
+                    "openapi_operator_spec_size_bytes{{{}}} {}",
+                    labels(key),
+                    size
+                );
+            }
+        }
+
+        out.push_str(
+            "# HELP openapi_operator_spec_fetch_last_status HTTP status of the last fetch (0 without a response)\n\
+             # TYPE openapi_operator_spec_fetch_last_status gauge\n",
+        );
+        for (key, stats) in services.iter() {
+            let _ = writeln!(
+                out,
+                "openapi_operator_spec_fetch_last_status{{{}}} {}",
+                labels(key),
+                stats.last_status
+            );
+        }
+
+        out.push_str(
+            "# HELP openapi_operator_spec_fetch_consecutive_failures Failed fetches since the last successful one\n\
+             # TYPE openapi_operator_spec_fetch_consecutive_failures gauge\n",
+        );
+        for (key, stats) in services.iter() {
+            let _ = writeln!(
+                out,
+                "openapi_operator_spec_fetch_consecutive_failures{{{}}} {}",
+                labels(key),
+                stats.consecutive_failures
+            );
+        }
+        out
+    }
+}
+
+pub async fn serve(port: u16, metrics: Arc<FetchMetrics>) -> Result<(), AppError> {
+    let app = Router::new()
+        .route("/metrics", get(handle_metrics))
+        .with_state(metrics);
+    let listener = tokio::net::TcpListener::bind(("0.0.0.0", port)).await?;
+    info!("Serving /metrics on port {}", port);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn handle_metrics(
+    State(metrics): State<Arc<FetchMetrics>>,
+) -> ([(header::HeaderName, &'static str); 1], String) {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics.render(),
+    )
+}
+
+/// Escapes a Prometheus label value
+fn label_value(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openapi_common::fetch::FailurePhase;
+
+    #[test]
+    fn renders_per_service_series() {
+        let metrics = FetchMetrics::default();
+        metrics.record("shop", "orders", Duration::from_millis(300), Ok(2048));
+        let mut failure = FetchFailure::new(FailurePhase::Status, "status 503");
+        failure.status = Some(503);
+        for _ in 0..2 {
+            metrics.record("shop", "orders", Duration::from_secs(3), Err(&failure));
+        }
+
+        let out = metrics.render();
+        assert!(out.contains(
+            "openapi_operator_spec_fetch_duration_seconds_bucket{namespace=\"shop\",service=\"orders\",le=\"0.5\"} 1\n"
+        ));
+        assert!(out.contains(
+            "openapi_operator_spec_fetch_duration_seconds_bucket{namespace=\"shop\",service=\"orders\",le=\"5\"} 3\n"
+        ));
+        assert!(out.contains(
+            "openapi_operator_spec_fetches_total{namespace=\"shop\",service=\"orders\",result=\"status\"} 2\n"
+        ));
+        assert!(out.contains(
+            "openapi_operator_spec_size_bytes{namespace=\"shop\",service=\"orders\"} 2048\n"
+        ));
+        assert!(out.contains(
+            "openapi_operator_spec_fetch_last_status{namespace=\"shop\",service=\"orders\"} 503\n"
+        ));
+        assert!(out.contains(
+            "openapi_operator_spec_fetch_consecutive_failures{namespace=\"shop\",service=\"orders\"} 2\n"
+        ));
+
+        metrics.forget("shop", "orders");
+        assert!(!metrics.render().contains("orders"));
+    }
+}
@@ -0,0 +1,173 @@
+//! Background sweep pruning catalog entries whose Service is gone.
+//!
+//! Entries leave the catalog when the reconcile of their Service sees it deleted. That
+//! reconcile never happens for Services deleted while the operator was down or busy, e.g.
+//! together with their namespace, so the discovery ConfigMap would keep them forever. Every
+//! few minutes, entries that have not been refreshed within `CATALOG_TTL` are looked up and
+//! removed when their Service no longer exists. `DiscoveredApi` resources are owned by their
+//! Service and garbage collected by Kubernetes, so the sweep only runs for the ConfigMap.
+
+use chrono::Utc;
+use k8s_openapi::api::core::v1::Service;
+use kube::api::Api;
+use openapi_common::RemovalReason;
+use std::{sync::Arc, time::Duration};
+use tokio::time::sleep;
+use tracing::{info, warn};
+
+use crate::error::AppError;
+use crate::sharding::read_catalog;
+use crate::{ContextData, remove_entry_from_discovery_configmap};
+
+/// Seconds since its last refresh after which an entry is removed if its Service is gone;
+/// `0` disables the sweep
+pub const CATALOG_TTL_ENV: &str = "CATALOG_TTL";
+
+const DEFAULT_TTL_SECS: u32 = 60 * 60;
+const SWEEP_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// `None` when `CATALOG_TTL=0`
+pub fn ttl_from_env() -> Result<Option<chrono::Duration>, AppError> {
+    let secs = match std::env::var(CATALOG_TTL_ENV) {
+        Ok(value) => value.trim().parse::<u32>().map_err(|_| {
+            AppError::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Invalid {}: '{}', expected seconds", CATALOG_TTL_ENV, value),
+            ))
+        })?,
+        Err(_) => DEFAULT_TTL_SECS,
+    };
+    Ok((secs > 0).then(|| chrono::Duration::seconds(secs.into())))
+}
+
+/// Sweeps the discovery ConfigMap until the operator stops
+pub async fn run(ctx: Arc<ContextData>, ttl: chrono::Duration) {
+    loop {
+        sleep(SWEEP_INTERVAL).await;
+        match sweep(&ctx, ttl).await {
+            Ok(0) => {}
+            Ok(removed) => info!("Removed {} orphaned entries from the catalog", removed),
+            Err(e) => warn!("Catalog sweep failed: {}", e),
+        }
+    }
+}
+
+async fn sweep(ctx: &Arc<ContextData>, ttl: chrono::Duration) -> Result<usize, AppError> {
+    let Some(config) = read_catalog(ctx).await? else {
+        return Ok(0);
+    };
+
+    let client = ctx.discovery.clone().into_client();
+    let now = Utc::now();
+    let mut removed = 0;
+    for entry in config
+        .apis
+        .iter()
+        // Entries of other clusters are refreshed by the federation
+        .filter(|entry| entry.cluster.is_none() && now - entry.last_updated > ttl)
+    {
+        let services: Api<Service> = Api::namespaced(client.clone(), &entry.namespace);
+        match services.get_opt(&entry.service_name).await {
+            Ok(Some(_)) => continue,
+            Ok(None) => {}
+            Err(e) => {
+                warn!(
+                    "Failed to look up service {}/{} of a stale entry: {}",
+                    entry.namespace, entry.service_name, e
+                );
+                continue;
+            }
+        }
+        info!(
+            "Service {}/{} is gone and its entry was last updated {}, removing it",
+            entry.namespace, entry.service_name, entry.last_updated
+        );
+        remove_entry_from_discovery_configmap(
+            ctx.clone(),
+            &entry.namespace,
+            &entry.service_name,
+            RemovalReason::Expired,
+        )
+        .await?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, FakeApiServer};
+    use openapi_common::{ApiInventoryEntry, DiscoveryConfig};
+    use serde_json::json;
+
+    fn entry(service: &str, age: chrono::Duration) -> ApiInventoryEntry {
+        let mut entry = ApiInventoryEntry::new(
+            "shop",
+            service,
+            format!("http://{}.shop.svc/openapi.json", service),
+        );
+        entry.last_updated = Utc::now() - age;
+        entry
+    }
+
+    #[tokio::test]
+    async fn stale_entries_of_deleted_services_are_removed() {
+        let api = FakeApiServer::start().await;
+        let mut federated = entry("payments", chrono::Duration::days(1));
+        federated.cluster = Some("eu-west".to_string());
+        test_support::insert_catalog(
+            &api,
+            &DiscoveryConfig {
+                apis: vec![
+                    entry("orders", chrono::Duration::days(1)),
+                    entry("carts", chrono::Duration::days(1)),
+                    entry("invoices", chrono::Duration::minutes(5)),
+                    federated,
+                ],
+                last_updated: Utc::now(),
+                tombstones: Vec::new(),
+                shards: Vec::new(),
+            },
+        );
+        api.insert(
+            "/api/v1/namespaces/shop/services/carts",
+            json!({ "metadata": { "name": "carts", "namespace": "shop" } }),
+        );
+        let ctx = Arc::new(test_support::context(api.client()));
+
+        let removed = sweep(&ctx, chrono::Duration::hours(1)).await.unwrap();
+        assert_eq!(removed, 1);
+        let ids: Vec<String> = test_support::catalog(&api)
+            .apis
+            .into_iter()
+            .map(|api| api.id)
+            .collect();
+        assert_eq!(ids, ["shop.carts", "shop.invoices", "shop.payments"]);
+        // Only entries past the TTL are looked up
+        let lookups: Vec<String> = api
+            .requests()
+            .into_iter()
+            .filter(|r| r.path().contains("/services/"))
+            .map(|r| r.path().to_string())
+            .collect();
+        assert_eq!(
+            lookups,
+            [
+                "/api/v1/namespaces/shop/services/orders",
+                "/api/v1/namespaces/shop/services/carts"
+            ]
+        );
+
+        assert_eq!(sweep(&ctx, chrono::Duration::hours(1)).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn sweeps_without_a_catalog_remove_nothing() {
+        let api = FakeApiServer::start().await;
+        let ctx = Arc::new(test_support::context(api.client()));
+
+        assert_eq!(sweep(&ctx, chrono::Duration::hours(1)).await.unwrap(), 0);
+        assert!(api.writes().is_empty());
+    }
+}
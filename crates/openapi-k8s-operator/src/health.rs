@@ -0,0 +1,213 @@
+//! `/healthz` and `/readyz` for the kubelet's liveness and readiness probes.
+//!
+//! The controllers report into [`Health`]: watcher errors, surfaced by the controller as
+//! queue errors, and the outcome of every reconcile. A replica is alive unless its watchers
+//! have been failing for longer than `HEALTH_STALL_TIMEOUT`, after which a restart is the
+//! remaining way out. It is ready once the controllers run and the watchers are not failing;
+//! a standby replica is ready too, so rollouts and the conversion webhook are not held up
+//! by it.
+
+use axum::{Json, Router, extract::State, http::StatusCode, routing::get};
+use chrono::{DateTime, Utc};
+use kube::runtime::{controller, watcher};
+use serde::Serialize;
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+use tracing::info;
+
+use crate::error::AppError;
+
+/// Port of the probe endpoints
+pub const HEALTH_PORT_ENV: &str = "HEALTH_PORT";
+/// Seconds the watchers may keep failing before `/healthz` reports the replica as stalled
+pub const HEALTH_STALL_TIMEOUT_ENV: &str = "HEALTH_STALL_TIMEOUT";
+
+/// A failing watcher retries with a backoff of at most 30s, so errors further apart than
+/// this belong to separate outages
+const WATCHER_ERROR_WINDOW_SECS: i64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Initializing, the controllers are not running yet
+    Starting,
+    /// Waiting for the leader Lease
+    Standby,
+    /// Running the controllers
+    Active,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthReport {
+    pub role: Role,
+    pub live: bool,
+    pub ready: bool,
+    /// Start of the current streak of watcher errors
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub watcher_failing_since: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_watcher_error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_reconcile: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_successful_reconcile: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default)]
+struct HealthState {
+    role: Option<Role>,
+    watcher_failing_since: Option<DateTime<Utc>>,
+    last_watcher_error: Option<(DateTime<Utc>, String)>,
+    last_reconcile: Option<DateTime<Utc>>,
+    last_successful_reconcile: Option<DateTime<Utc>>,
+}
+
+/// Health of this replica, shared by the controllers and the probe endpoints
+#[derive(Debug)]
+pub struct Health {
+    state: Mutex<HealthState>,
+    stall_timeout: chrono::Duration,
+}
+
+impl Health {
+    pub fn new(stall_timeout: chrono::Duration) -> Self {
+        Self {
+            state: Mutex::new(HealthState::default()),
+            stall_timeout,
+        }
+    }
+
+    pub fn set_role(&self, role: Role) {
+        self.state.lock().unwrap().role = Some(role);
+    }
+
+    pub fn watcher_error(&self, error: &impl Display) {
+        self.watcher_error_at(error, Utc::now());
+    }
+
+    fn watcher_error_at(&self, error: &impl Display, now: DateTime<Utc>) {
+        let mut state = self.state.lock().unwrap();
+        if !is_recent(state.last_watcher_error.as_ref().map(|(at, _)| *at), now) {
+            state.watcher_failing_since = Some(now);
+        }
+        state.last_watcher_error = Some((now, error.to_string()));
+    }
+
+    pub fn reconciled(&self, success: bool) {
+        let now = Utc::now();
+        let mut state = self.state.lock().unwrap();
+        state.last_reconcile = Some(now);
+        if success {
+            state.last_successful_reconcile = Some(now);
+        }
+    }
+
+    /// Records the result of a controller's reconcile stream
+    pub fn observe<T, E>(&self, result: &Result<T, controller::Error<E, watcher::Error>>) {
+        match result {
+            Ok(_) => self.reconciled(true),
+            Err(controller::Error::ReconcilerFailed(..)) => self.reconciled(false),
+            Err(controller::Error::QueueError(e)) => self.watcher_error(e),
+            Err(_) => {}
+        }
+    }
+
+    pub fn report(&self) -> HealthReport {
+        self.report_at(Utc::now())
+    }
+
+    fn report_at(&self, now: DateTime<Utc>) -> HealthReport {
+        let state = self.state.lock().unwrap();
+        let role = state.role.unwrap_or(Role::Starting);
+        let failing_since = state
+            .watcher_failing_since
+            .filter(|_| is_recent(state.last_watcher_error.as_ref().map(|(at, _)| *at), now));
+        let stalled = failing_since.is_some_and(|since| now - since > self.stall_timeout);
+        HealthReport {
+            role,
+            live: !stalled,
+            ready: match role {
+                Role::Starting => false,
+                Role::Standby => true,
+                Role::Active => failing_since.is_none(),
+            },
+            watcher_failing_since: failing_since,
+            last_watcher_error: state
+                .last_watcher_error
+                .as_ref()
+                .map(|(_, error)| error.clone()),
+            last_reconcile: state.last_reconcile,
+            last_successful_reconcile: state.last_successful_reconcile,
+        }
+    }
+}
+
+fn is_recent(at: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    at.is_some_and(|at| now - at <= chrono::Duration::seconds(WATCHER_ERROR_WINDOW_SECS))
+}
+
+pub async fn serve(port: u16, health: Arc<Health>) -> Result<(), AppError> {
+    let app = Router::new()
+        .route("/healthz", get(handle_healthz))
+        .route("/readyz", get(handle_readyz))
+        .with_state(health);
+    let listener = tokio::net::TcpListener::bind(("0.0.0.0", port)).await?;
+    info!("Serving /healthz and /readyz on port {}", port);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn handle_healthz(State(health): State<Arc<Health>>) -> (StatusCode, Json<HealthReport>) {
+    let report = health.report();
+    (status(report.live), Json(report))
+}
+
+async fn handle_readyz(State(health): State<Arc<Health>>) -> (StatusCode, Json<HealthReport>) {
+    let report = health.report();
+    (status(report.ready), Json(report))
+}
+
+fn status(ok: bool) -> StatusCode {
+    if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watcher_failing_past_the_stall_timeout_is_not_live() {
+        let health = Health::new(chrono::Duration::seconds(120));
+        health.set_role(Role::Active);
+        let start = Utc::now();
+        assert!(health.report_at(start).ready);
+
+        // Errors every 30s form one streak
+        let at = |secs| start + chrono::Duration::seconds(secs);
+        for secs in (0..=90).step_by(30) {
+            health.watcher_error_at(&"connection refused", at(secs));
+        }
+        let report = health.report_at(at(100));
+        assert!(report.live);
+        assert!(!report.ready);
+        for secs in (120..=150).step_by(30) {
+            health.watcher_error_at(&"connection refused", at(secs));
+        }
+        let report = health.report_at(at(160));
+        assert!(!report.live);
+        assert_eq!(report.watcher_failing_since, Some(start));
+
+        // No errors for a while: the watcher recovered
+        let report = health.report_at(at(300));
+        assert!(report.live && report.ready);
+        assert_eq!(report.watcher_failing_since, None);
+
+        health.set_role(Role::Standby);
+        assert!(health.report_at(at(160)).ready);
+    }
+}
@@ -0,0 +1,136 @@
+//! `DISCOVERY_LABEL_SELECTOR`: discover every Service matching a label selector.
+//!
+//! Platform teams can onboard services by labeling them (or with a label they already
+//! carry) instead of annotating each one. A matching Service without an
+//! `api-doc.io/enabled` annotation is treated as enabled and discovered with the
+//! conventions: the default path, its first port, and its namespace's defaults
+//! (`NAMESPACE_DEFAULTS`). Annotations still override the conventions, and
+//! `api-doc.io/enabled: "false"` opts a matching Service out.
+//!
+//! The selector uses the `kubectl` syntax, e.g. `team=payments,tier in (backend,edge),!legacy`.
+
+use kube::core::{Expression, Selector};
+use openapi_common::DISCOVERY_LABEL_SELECTOR_ENV;
+use std::collections::BTreeSet;
+
+/// Parses a label selector in the `kubectl` syntax
+pub fn parse(value: &str) -> Result<Selector, String> {
+    split_terms(value)?
+        .into_iter()
+        .map(parse_term)
+        .collect::<Result<Vec<_>, _>>()
+        .map(|expressions| expressions.into_iter().collect())
+}
+
+/// Reads `DISCOVERY_LABEL_SELECTOR`, `None` when unset or empty
+pub fn from_env() -> Result<Option<Selector>, String> {
+    match std::env::var(DISCOVERY_LABEL_SELECTOR_ENV) {
+        Ok(value) if !value.trim().is_empty() => parse(&value).map(Some).map_err(|e| {
+            format!(
+                "Invalid {} '{}': {}",
+                DISCOVERY_LABEL_SELECTOR_ENV, value, e
+            )
+        }),
+        _ => Ok(None),
+    }
+}
+
+/// Comma-separated terms, keeping the commas of `in (a,b)` sets
+fn split_terms(value: &str) -> Result<Vec<&str>, String> {
+    let mut terms = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in value.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Err("unbalanced ')'".to_string()),
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                terms.push(value[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err("unbalanced '('".to_string());
+    }
+    terms.push(value[start..].trim());
+    if terms.iter().any(|term| term.is_empty()) {
+        return Err("empty requirement".to_string());
+    }
+    Ok(terms)
+}
+
+fn parse_term(term: &str) -> Result<Expression, String> {
+    if let Some(key) = term.strip_prefix('!') {
+        return Ok(Expression::DoesNotExist(label_key(key)?));
+    }
+    if let Some((key, value)) = term.split_once("!=") {
+        return Ok(Expression::NotEqual(
+            label_key(key)?,
+            value.trim().to_string(),
+        ));
+    }
+    if let Some((key, value)) = term.split_once("==").or_else(|| term.split_once('=')) {
+        return Ok(Expression::Equal(label_key(key)?, value.trim().to_string()));
+    }
+    if let Some((key, set)) = term.split_once(" notin ") {
+        return Ok(Expression::NotIn(label_key(key)?, value_set(set)?));
+    }
+    if let Some((key, set)) = term.split_once(" in ") {
+        return Ok(Expression::In(label_key(key)?, value_set(set)?));
+    }
+    Ok(Expression::Exists(label_key(term)?))
+}
+
+fn label_key(key: &str) -> Result<String, String> {
+    let key = key.trim();
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return Err(format!("invalid label key '{}'", key));
+    }
+    Ok(key.to_string())
+}
+
+/// Values of `(a,b)`
+fn value_set(set: &str) -> Result<BTreeSet<String>, String> {
+    let inner = set
+        .trim()
+        .strip_prefix('(')
+        .and_then(|s| s.strip_suffix(')'))
+        .ok_or_else(|| format!("expected '(values)', got '{}'", set.trim()))?;
+    Ok(inner.split(',').map(|v| v.trim().to_string()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kube::core::SelectorExt;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn parses_equality_and_set_requirements() {
+        let selector = parse("team=payments, tier in (backend,edge),!legacy").unwrap();
+        let labels = |pairs: &[(&str, &str)]| -> BTreeMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+
+        assert!(selector.matches(&labels(&[("team", "payments"), ("tier", "edge")])));
+        assert!(!selector.matches(&labels(&[("team", "payments"), ("tier", "db")])));
+        assert!(!selector.matches(&labels(&[
+            ("team", "payments"),
+            ("tier", "edge"),
+            ("legacy", "true"),
+        ])));
+        assert!(
+            parse("env!=dev,api")
+                .unwrap()
+                .matches(&labels(&[("api", "")]))
+        );
+        assert!(parse("tier in (a,b").is_err());
+        assert!(parse("team=a,,env=b").is_err());
+    }
+}
@@ -0,0 +1,240 @@
+//! Lease-based leader election for operators running more than one replica.
+//!
+//! With `LEADER_ELECTION=true` replicas compete for a `coordination.k8s.io` Lease in the
+//! operator's namespace. Only the holder runs the controllers, the others stand by and
+//! take over once the holder has not renewed the Lease for its duration. A leader that
+//! cannot renew within two thirds of the duration stops, so it never reconciles next to
+//! its successor; its pod is restarted and rejoins as a standby.
+
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::coordination::v1::{Lease, LeaseSpec};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::MicroTime;
+use kube::{
+    Client,
+    api::{Api, PostParams},
+};
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+use crate::error::AppError;
+
+/// Run the controllers only in the replica holding the Lease (`true` to enable)
+pub const LEADER_ELECTION_ENV: &str = "LEADER_ELECTION";
+/// Name of the Lease, in `POD_NAMESPACE`
+pub const LEADER_ELECTION_LEASE_NAME_ENV: &str = "LEADER_ELECTION_LEASE_NAME";
+/// Seconds a Lease that is not renewed stays with its holder
+pub const LEADER_ELECTION_LEASE_DURATION_ENV: &str = "LEADER_ELECTION_LEASE_DURATION";
+
+const DEFAULT_LEASE_NAME: &str = "openapi-k8s-operator";
+const DEFAULT_LEASE_DURATION_SECS: u32 = 15;
+
+pub struct LeaderElector {
+    leases: Api<Lease>,
+    lease_name: String,
+    /// Holder identity of this replica, its pod name
+    identity: String,
+    lease_duration: Duration,
+}
+
+impl LeaderElector {
+    /// `None` unless `LEADER_ELECTION=true`
+    pub fn from_env(client: Client) -> Result<Option<Self>, AppError> {
+        let enabled = std::env::var(LEADER_ELECTION_ENV)
+            .map(|v| v.trim().eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        if !enabled {
+            return Ok(None);
+        }
+
+        let secs = match std::env::var(LEADER_ELECTION_LEASE_DURATION_ENV) {
+            Ok(value) => value
+                .trim()
+                .parse::<u32>()
+                .ok()
+                .filter(|secs| *secs >= 3)
+                .ok_or_else(|| {
+                    AppError::Io(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!(
+                            "Invalid {}: '{}', expected at least 3 seconds",
+                            LEADER_ELECTION_LEASE_DURATION_ENV, value
+                        ),
+                    ))
+                })?,
+            Err(_) => DEFAULT_LEASE_DURATION_SECS,
+        };
+        let namespace = std::env::var("POD_NAMESPACE").unwrap_or_else(|_| "default".to_string());
+        let identity = std::env::var("POD_NAME")
+            .or_else(|_| std::env::var("HOSTNAME"))
+            .unwrap_or_else(|_| format!("{}-{}", DEFAULT_LEASE_NAME, std::process::id()));
+
+        Ok(Some(Self {
+            leases: Api::namespaced(client, &namespace),
+            lease_name: std::env::var(LEADER_ELECTION_LEASE_NAME_ENV)
+                .unwrap_or_else(|_| DEFAULT_LEASE_NAME.to_string()),
+            identity,
+            lease_duration: Duration::from_secs(secs.into()),
+        }))
+    }
+
+    pub fn identity(&self) -> &str {
+        &self.identity
+    }
+
+    /// Waits until this replica holds the Lease
+    pub async fn acquire(&self) {
+        let mut standing_by = false;
+        loop {
+            match self.try_acquire_or_renew().await {
+                Ok(true) => {
+                    info!(
+                        "Acquired leader Lease {} as {}",
+                        self.lease_name, self.identity
+                    );
+                    return;
+                }
+                Ok(false) if !standing_by => {
+                    info!(
+                        "Leader Lease {} is held by another replica, standing by",
+                        self.lease_name
+                    );
+                    standing_by = true;
+                }
+                Ok(false) => {}
+                Err(e) => warn!("Failed to acquire leader Lease {}: {}", self.lease_name, e),
+            }
+            sleep(self.retry_period()).await;
+        }
+    }
+
+    /// Renews the Lease; returns once this replica is no longer the leader
+    pub async fn keep_renewing(&self) {
+        let renew_deadline = self.lease_duration * 2 / 3;
+        let mut renewed = Instant::now();
+        loop {
+            sleep(self.retry_period()).await;
+            match self.try_acquire_or_renew().await {
+                Ok(true) => renewed = Instant::now(),
+                Ok(false) => {
+                    error!(
+                        "Leader Lease {} was taken over by another replica",
+                        self.lease_name
+                    );
+                    return;
+                }
+                Err(e) => {
+                    warn!("Failed to renew leader Lease {}: {}", self.lease_name, e);
+                    if renewed.elapsed() >= renew_deadline {
+                        error!(
+                            "Leader Lease {} not renewed for {:?}, giving it up",
+                            self.lease_name,
+                            renewed.elapsed()
+                        );
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    fn retry_period(&self) -> Duration {
+        self.lease_duration / 5
+    }
+
+    /// Takes the Lease when it is free or expired, renews it when it is ours; `false` when
+    /// another replica holds it
+    async fn try_acquire_or_renew(&self) -> Result<bool, kube::Error> {
+        let now = Utc::now();
+        let Some(mut lease) = self.leases.get_opt(&self.lease_name).await? else {
+            let lease = Lease {
+                metadata: kube::core::ObjectMeta {
+                    name: Some(self.lease_name.clone()),
+                    ..Default::default()
+                },
+                spec: Some(self.spec(now, now, 0)),
+            };
+            return conflict_is_lost(self.leases.create(&PostParams::default(), &lease).await);
+        };
+
+        let spec = lease.spec.clone().unwrap_or_default();
+        let transitions = spec.lease_transitions.unwrap_or(0);
+        let spec = if spec.holder_identity.as_deref() == Some(self.identity.as_str()) {
+            let acquired = spec.acquire_time.map(|time| time.0).unwrap_or(now);
+            self.spec(acquired, now, transitions)
+        } else if is_expired(&spec, now) {
+            if let Some(holder) = spec.holder_identity.as_deref().filter(|h| !h.is_empty()) {
+                info!(
+                    "Leader Lease {} of {} expired, taking over",
+                    self.lease_name, holder
+                );
+            }
+            self.spec(now, now, transitions + 1)
+        } else {
+            return Ok(false);
+        };
+        // The replacement carries the resourceVersion that was read, so when two replicas
+        // take over at once, only one of them succeeds
+        lease.spec = Some(spec);
+        conflict_is_lost(
+            self.leases
+                .replace(&self.lease_name, &PostParams::default(), &lease)
+                .await,
+        )
+    }
+
+    fn spec(&self, acquired: DateTime<Utc>, renewed: DateTime<Utc>, transitions: i32) -> LeaseSpec {
+        LeaseSpec {
+            holder_identity: Some(self.identity.clone()),
+            lease_duration_seconds: Some(self.lease_duration.as_secs() as i32),
+            acquire_time: Some(MicroTime(acquired)),
+            renew_time: Some(MicroTime(renewed)),
+            lease_transitions: Some(transitions),
+            ..Default::default()
+        }
+    }
+}
+
+/// Whether the holder of the Lease has not renewed it within its duration
+fn is_expired(spec: &LeaseSpec, now: DateTime<Utc>) -> bool {
+    match (
+        spec.holder_identity.as_deref(),
+        &spec.renew_time,
+        spec.lease_duration_seconds,
+    ) {
+        (None | Some(""), _, _) => true,
+        (Some(_), Some(renewed), Some(secs)) => {
+            renewed.0 + chrono::Duration::seconds(secs.into()) < now
+        }
+        _ => true,
+    }
+}
+
+/// A conflicting write means another replica got the Lease first
+fn conflict_is_lost(result: Result<Lease, kube::Error>) -> Result<bool, kube::Error> {
+    match result {
+        Ok(_) => Ok(true),
+        Err(kube::Error::Api(resp)) if resp.code == 409 => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lease_expires_after_its_duration() {
+        let now = Utc::now();
+        let spec = |holder: Option<&str>, renewed_secs_ago: i64| LeaseSpec {
+            holder_identity: holder.map(str::to_string),
+            lease_duration_seconds: Some(15),
+            renew_time: Some(MicroTime(now - chrono::Duration::seconds(renewed_secs_ago))),
+            ..Default::default()
+        };
+        assert!(!is_expired(&spec(Some("operator-0"), 5), now));
+        assert!(is_expired(&spec(Some("operator-0"), 20), now));
+        assert!(is_expired(&spec(None, 5), now));
+        assert!(is_expired(&spec(Some(""), 5), now));
+    }
+}
@@ -0,0 +1,222 @@
+//! `openapi-k8s-operator [OPTIONS] lint [--offline]`: the `api-doc.io/*` annotations of the
+//! cluster's Services, checked once.
+//!
+//! Lists the Services of the watched namespaces like `scan` and reports, per Service with
+//! `api-doc.io/*` annotations, what the CLI's `lint` reports for manifests: unknown keys,
+//! unparsable values and port names the Service does not declare, plus port numbers it does
+//! not expose and, unless `--offline`, spec paths that cannot be fetched. Issues go to stdout;
+//! the exit code is [`EXIT_FINDINGS`] when any is an error. Nothing is written to the cluster,
+//! so it runs with read-only RBAC, e.g. as a `CronJob` in clusters without the webhook.
+
+use k8s_openapi::api::core::v1::Service;
+use kube::{Client, ResourceExt};
+use openapi_common::annotations::{
+    API_DOC_ANNOTATION_PREFIX, AnnotationIssue, ApiDocAnnotations, FetchVia, IssueSeverity, PortRef,
+};
+use openapi_common::fetch::{SpecFetcher, TlsOptions};
+use openapi_common::{API_DOC_PATH_ANNOTATION, API_DOC_PORT_ANNOTATION, API_DOC_URL_ANNOTATION};
+use std::collections::BTreeMap;
+use tracing::info;
+
+use crate::config::OperatorConfig;
+use crate::error::AppError;
+use crate::scan::{effective_annotations, invalid_input, list_services, watched_namespaces};
+use crate::{fetch_service_spec, label_selector, service_proxy_path, service_url};
+
+/// Exit code when an error was found, as for the CLI's `lint`
+pub const EXIT_FINDINGS: i32 = 2;
+
+/// A port number among the annotations that is not one of the Service's ports
+fn port_issue(
+    service: &Service,
+    annotations: &BTreeMap<String, String>,
+    parsed: &ApiDocAnnotations,
+) -> Option<AnnotationIssue> {
+    let Some(PortRef::Number(port)) = parsed.port else {
+        return None;
+    };
+    let ports: Vec<i32> = service
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.ports.as_ref())
+        .into_iter()
+        .flatten()
+        .map(|port| port.port)
+        .collect();
+    // An explicit URL or ConfigMap replaces the port, a headless Service has none to check
+    if parsed.url.is_some()
+        || parsed.spec_config_map.is_some()
+        || ports.is_empty()
+        || ports.contains(&port)
+        || !annotations.contains_key(API_DOC_PORT_ANNOTATION)
+    {
+        return None;
+    }
+    Some(AnnotationIssue {
+        severity: IssueSeverity::Error,
+        annotation: API_DOC_PORT_ANNOTATION.to_string(),
+        message: format!(
+            "the Service has no port {}, it exposes {}",
+            port,
+            ports
+                .iter()
+                .map(|port| port.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    })
+}
+
+/// Runs the lint; `false` when an error was found
+pub async fn run(config: &OperatorConfig, offline: bool) -> Result<bool, AppError> {
+    let client = Client::try_default().await?;
+
+    let label_selector = label_selector::from_env().map_err(invalid_input)?;
+    let fetch_via = FetchVia::from_env().map_err(invalid_input)?;
+    let fetcher = SpecFetcher::new(config.fetch_config()?)?;
+    let insecure_fetcher = fetcher.with_tls(TlsOptions {
+        insecure_skip_verify: true,
+        ..fetcher.config().tls.clone()
+    })?;
+
+    let namespaces = watched_namespaces(&client, config).await?;
+    let services =
+        list_services(&client, namespaces.as_deref(), &config.exclude_namespaces()).await?;
+
+    let mut namespace_annotations = BTreeMap::new();
+    let (mut linted, mut errors, mut warnings) = (0, 0, 0);
+    for service in &services {
+        let namespace = service.namespace().unwrap_or_default();
+        let service_name = service.name_any();
+        let service_annotations = effective_annotations(
+            &client,
+            service,
+            config
+                .namespace_defaults
+                .then_some(&mut namespace_annotations),
+            label_selector.as_ref(),
+        )
+        .await?;
+        if !service_annotations
+            .keys()
+            .any(|key| key.starts_with(API_DOC_ANNOTATION_PREFIX))
+        {
+            continue;
+        }
+        linted += 1;
+
+        let (annotations, _) = ApiDocAnnotations::parse(&service_annotations);
+        let mut issues = crate::webhook::annotation_issues(service, &service_annotations);
+        issues.extend(port_issue(service, &service_annotations, &annotations));
+        let reachable = !issues
+            .iter()
+            .any(|issue| issue.severity == IssueSeverity::Error);
+        if !offline
+            && reachable
+            && annotations.enabled
+            && !annotations.exclude
+            && let Some(url) = service_url(service, &annotations)
+        {
+            let fetcher = if annotations.insecure_skip_verify {
+                &insecure_fetcher
+            } else {
+                &fetcher
+            };
+            let proxy_path = (annotations.fetch_via.unwrap_or(fetch_via) == FetchVia::ApiServer)
+                .then(|| service_proxy_path(service, &annotations))
+                .flatten();
+            let fetched = fetch_service_spec(
+                fetcher,
+                &client,
+                &namespace,
+                &url,
+                proxy_path.as_deref(),
+                &annotations,
+                None,
+            )
+            .await;
+            // An API error, e.g. on a Secret read-only RBAC does not cover, fails this Service only
+            let failure = match fetched {
+                Ok(Ok(_)) => None,
+                Ok(Err(failure)) => Some(failure.to_string()),
+                Err(e) => Some(e.to_string()),
+            };
+            if let Some(failure) = failure {
+                let annotation = if annotations.url.is_some() {
+                    API_DOC_URL_ANNOTATION
+                } else {
+                    API_DOC_PATH_ANNOTATION
+                };
+                issues.push(AnnotationIssue {
+                    severity: IssueSeverity::Error,
+                    annotation: annotation.to_string(),
+                    message: format!("the spec at {} could not be fetched: {}", url, failure),
+                });
+            }
+        }
+
+        for issue in &issues {
+            let severity = match issue.severity {
+                IssueSeverity::Error => {
+                    errors += 1;
+                    "error"
+                }
+                IssueSeverity::Warning => {
+                    warnings += 1;
+                    "warning"
+                }
+            };
+            println!("{}/{}: {}: {}", namespace, service_name, severity, issue);
+        }
+    }
+    info!(
+        "Linted {} of {} services: {} errors, {} warnings",
+        linted,
+        services.len(),
+        errors,
+        warnings
+    );
+    Ok(errors == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::{ServicePort, ServiceSpec};
+
+    #[test]
+    fn reports_port_numbers_the_service_does_not_expose() {
+        let service = Service {
+            spec: Some(ServiceSpec {
+                ports: Some(vec![ServicePort {
+                    port: 8080,
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let issue = |port: &str, extra: Option<(&str, &str)>| {
+            let mut annotations =
+                BTreeMap::from([(API_DOC_PORT_ANNOTATION.to_string(), port.to_string())]);
+            annotations.extend(extra.map(|(key, value)| (key.to_string(), value.to_string())));
+            let (parsed, _) = ApiDocAnnotations::parse(&annotations);
+            port_issue(&service, &annotations, &parsed)
+        };
+
+        let missing = issue("9090", None).unwrap();
+        assert_eq!(missing.severity, IssueSeverity::Error);
+        assert_eq!(
+            missing.message,
+            "the Service has no port 9090, it exposes 8080"
+        );
+        assert!(issue("8080", None).is_none());
+        assert!(
+            issue(
+                "9090",
+                Some((API_DOC_URL_ANNOTATION, "http://docs/openapi.json"))
+            )
+            .is_none()
+        );
+    }
+}
@@ -1,57 +1,278 @@
+mod api_doc;
+mod apiserver_proxy;
+mod audit;
+mod breaking_gate;
+mod config;
+mod contract_check;
+mod deployment;
+mod discovered_api;
+mod discovery_secret;
+mod drift;
+mod endpoints;
+mod entry_layout;
 mod error;
+mod federation;
+mod fetch_metrics;
+mod gc;
+mod health;
+mod label_selector;
+mod leader_election;
+mod lint;
+mod namespace_watch;
+mod publish;
+mod scan;
+mod sharding;
+mod spec_changes;
+mod spec_store;
+#[cfg(test)]
+mod test_support;
+mod webhook;
 
 use chrono::Utc;
-use futures::StreamExt;
-use k8s_openapi::api::core::v1::{ConfigMap, Service};
+use futures::{FutureExt, StreamExt};
+use k8s_openapi::ByteString;
+use k8s_openapi::api::apps::v1::Deployment;
+use k8s_openapi::api::core::v1::{ConfigMap, Namespace, ObjectReference, Secret, Service};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
 use kube::{
-    Client, ResourceExt,
-    api::{Api, Patch, PatchParams},
-    runtime::{controller::{Action, Controller}, watcher::Config},
+    Client, Resource, ResourceExt,
+    api::Api,
+    core::{Selector, SelectorExt},
+    runtime::{
+        WatchStreamExt,
+        controller::{self, Action, Controller},
+        events::{Event, EventType, Recorder, Reporter},
+        predicates,
+        reflector::{self, ObjectRef, Store},
+        watcher::{self, Config},
+    },
 };
-use std::{collections::BTreeMap, env, sync::Arc, time::Duration};
-use tracing::{error, info, warn};
+use std::{collections::BTreeMap, env, sync::Arc, time::{Duration, Instant}};
+use tracing::{debug, error, info, instrument, warn};
 use tokio::time::sleep;
 
+use audit::AuditLog;
+use breaking_gate::{Admission, BreakingChangeGate};
+use clap::Parser;
+use config::{Cli, Command, CrdKind};
+use contract_check::ContractChecker;
+use discovered_api::DiscoveryOutput;
 use error::AppError;
+use fetch_metrics::FetchMetrics;
+use health::{Health, Role};
+use leader_election::LeaderElector;
+use spec_changes::SpecChanges;
+use publish::PublishManager;
+use spec_store::SpecStore;
 use openapi_common::{
-    ApiInventoryEntry, DiscoveryConfig,
-    API_DOC_ENABLED_ANNOTATION, API_DOC_PATH_ANNOTATION, API_DOC_NAME_ANNOTATION, API_DOC_DESCRIPTION_ANNOTATION,
-    DEFAULT_API_DOC_PATH, DISCOVERY_NAMESPACE_ENV, DISCOVERY_CONFIGMAP_ENV,
+    API_DOC_ENABLED_ANNOTATION, ApiInventoryEntry, ApiTombstone, DiscoveryConfig, RemovalReason,
+    annotations::{ApiDocAnnotations, FetchVia, SpecConfigMapRef, with_namespace_defaults},
+    api_doc::ApiDoc,
+    complexity::SpecMetrics,
+    tenancy::TenantResolver,
+    encoding::{self, DiscoveryEncoding, DiscoveryLayout, DiscoveryStorage},
+    logging,
+    fetch::{
+        ClientIdentity, FailurePhase, FetchAuth, FetchFailure, FetchOutcome,
+        FetchRequest, SpecFetcher, TlsOptions,
+    },
+    schedule::RefreshWindow,
+    signing::SpecSigner,
+    digest::sha256_hex,
+    spec_utils,
     namespace_utils
 };
 
+/// Field manager of the operator's catalog writes
+const FIELD_MANAGER: &str = "openapi-k8s-operator";
+
 /// Deterministic key for a discovery entry (namespace + service name). Used for dedup and removal.
 macro_rules! entry_key {
     ($ns:expr, $name:expr) => {
-        format!("{}-{}", $ns, $name)
+        openapi_common::entry_id($ns, $name)
     };
 }
 
 #[derive(Clone)]
 struct ContextData {
     discovery: Api<ConfigMap>,
-    http_client: reqwest::Client,
+    fetcher: SpecFetcher,
+    /// Same settings, without certificate verification (`api-doc.io/insecure-skip-verify`)
+    insecure_fetcher: SpecFetcher,
     watch_namespaces: Vec<String>,
+    /// Namespaces and patterns from EXCLUDE_NAMESPACES, which are never discovered
+    exclude_namespaces: Vec<String>,
+    /// Services matching DISCOVERY_LABEL_SELECTOR are discovered without annotations
+    label_selector: Option<Selector>,
+    /// Namespaces matching WATCH_NAMESPACE_SELECTOR, kept up to date by a Namespace watcher
+    namespace_store: Option<Store<Namespace>>,
+    /// All namespaces, whose api-doc.io annotations are defaults for their services
+    /// (NAMESPACE_DEFAULTS=true)
+    namespace_defaults: Option<Store<Namespace>>,
     discovery_namespace: String,
     discovery_configmap: String,
+    /// Whether entries go to the discovery ConfigMap or to DiscoveredApi resources
+    output: DiscoveryOutput,
+    /// How the payload in the discovery ConfigMap is encoded
+    encoding: DiscoveryEncoding,
+    /// Whether the catalog is kept in ConfigMaps or Secrets
+    storage: DiscoveryStorage,
+    /// Whether entries share one payload or each have a key of their own (DISCOVERY_LAYOUT)
+    layout: DiscoveryLayout,
+    /// Keeps a copy of each stored spec next to the catalog (SPEC_STORAGE)
+    spec_store: Option<Arc<dyn SpecStore>>,
+    /// How long removed entries stay in the discovery document as tombstones
+    tombstone_grace: chrono::Duration,
+    /// Requeue delay after a reconcile (RECONCILE_INTERVAL)
+    reconcile_interval: Duration,
+    /// Requeue delay after a failed reconcile (ERROR_RETRY_INTERVAL)
+    error_retry_interval: Duration,
+    /// External systems specs are pushed to when they change
+    publishers: Arc<PublishManager>,
+    /// Maps namespaces to tenants (TENANT_MAPPING / TENANT_LABEL)
+    tenants: TenantResolver,
+    /// All namespaces, whose TENANT_LABEL names their tenant (when it is set)
+    tenant_namespaces: Option<Store<Namespace>>,
+    /// Signs stored and published specs when SPEC_SIGNING_KEY_FILE is set
+    signer: Option<Arc<SpecSigner>>,
+    /// Live contract checks of services that opted in, unless CONTRACT_CHECK_INTERVAL=0
+    contract_checker: Option<Arc<ContractChecker>>,
+    /// Holds back breaking spec revisions, unless BREAKING_CHANGE_GATE=false
+    breaking_gate: Option<Arc<BreakingChangeGate>>,
+    /// Last stored revision of every spec, to summarize what the next one changes
+    spec_changes: Arc<SpecChanges>,
+    /// Emits Events on reconciled Services
+    recorder: Recorder,
+    /// When specs may be fetched (REFRESH_WINDOW), unless a service sets its own window
+    refresh_window: Option<RefreshWindow>,
+    /// ApiDoc resources, when they are reconciled too (WATCH_API_DOCS=true)
+    api_docs: Option<Store<ApiDoc>>,
+    /// Deployments whose annotations apply to the Services selecting them
+    /// (WATCH_DEPLOYMENTS=true)
+    deployments: Option<Store<Deployment>>,
+    /// Watcher and reconcile health, served on /healthz and /readyz
+    health: Arc<Health>,
+    /// Per-service fetch durations, sizes and failures, served on /metrics
+    fetch_metrics: Arc<FetchMetrics>,
+    /// Records every change to the catalog when AUDIT_LOG is set
+    audit: Option<Arc<AuditLog>>,
+    /// Log catalog writes, Events and publishing instead of performing them (DRY_RUN=true
+    /// or --dry-run)
+    dry_run: bool,
+    /// The catalog as last written or read at startup, restored when the discovery
+    /// ConfigMap drifts from it. Held while the catalog is written.
+    written_catalog: Arc<tokio::sync::Mutex<Option<DiscoveryConfig>>>,
+    /// How specs are fetched unless a service sets `api-doc.io/fetch-via` (SPEC_FETCH_VIA)
+    fetch_via: FetchVia,
+}
+
+impl ContextData {
+    /// Whether services in this namespace should be part of the catalog
+    fn watches_namespace(&self, namespace: &str) -> bool {
+        if namespace_utils::namespace_matches(&self.exclude_namespaces, namespace) {
+            return false;
+        }
+        if self.watch_namespaces.iter().any(|ns| ns == "all")
+            || namespace_utils::namespace_matches(&self.watch_namespaces, namespace)
+        {
+            return true;
+        }
+
+        match &self.namespace_store {
+            Some(store) => store.get(&ObjectRef::new(namespace)).is_some(),
+            None => self.watch_namespaces.is_empty(),
+        }
+    }
+
+    /// Tenant owning the namespace, `None` when tenancy is disabled or no tenant matches
+    fn tenant_for(&self, namespace: &str) -> Option<String> {
+        if !self.tenants.is_enabled() {
+            return None;
+        }
+
+        let ns = self
+            .tenant_namespaces
+            .as_ref()
+            .and_then(|store| store.get(&ObjectRef::new(namespace)));
+        self.tenants
+            .resolve(namespace, ns.as_ref().and_then(|ns| ns.metadata.labels.as_ref()))
+    }
+
+    /// Name of the ApiDoc registering the service, whose spec wins over the annotations
+    fn api_doc_for(&self, namespace: &str, service_name: &str) -> Option<String> {
+        self.api_docs
+            .as_ref()?
+            .state()
+            .into_iter()
+            .find_map(|api_doc| {
+                (api_doc.namespace().as_deref() == Some(namespace)
+                    && api_doc.spec.service == service_name
+                    && api_doc.metadata.deletion_timestamp.is_none())
+                .then(|| api_doc.name_any())
+            })
+    }
+}
+
+fn main() -> Result<(), AppError> {
+    // The file only fills in the environment, so it is read before the runtime starts
+    let config_file = config::load_file()?;
+    run(config_file)
 }
 
 #[tokio::main]
-async fn main() -> Result<(), AppError> {
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .init();
+async fn run(config_file: Option<(String, usize)>) -> Result<(), AppError> {
+    let cli = Cli::parse();
+    let config = cli.config;
+    match cli.command {
+        Some(Command::Crd { kind }) => {
+            let crd = match kind {
+                CrdKind::Discoveredapis => openapi_common::crd::crd(),
+                CrdKind::Apidocs => openapi_common::api_doc::crd(),
+            };
+            let yaml = serde_yaml::to_string(&crd)
+                .map_err(|e| AppError::Io(std::io::Error::other(e)))?;
+            print!("{}", yaml);
+            return Ok(());
+        }
+        // Logs go to stderr so the catalog can be written to stdout
+        Some(Command::Scan { output }) => {
+            logging::init(std::io::stderr);
+            return scan::run(&config, output).await;
+        }
+        Some(Command::Lint { offline }) => {
+            logging::init(std::io::stderr);
+            if !lint::run(&config, offline).await? {
+                std::process::exit(lint::EXIT_FINDINGS);
+            }
+            return Ok(());
+        }
+        None => {}
+    }
+
+    logging::init(std::io::stdout);
 
     info!("Starting OpenAPI K8s Operator");
+    if let Some((path, settings)) = &config_file {
+        info!("Read {} settings from {} not set in the environment", settings, path);
+    }
+
+    let dry_run = config.dry_run;
+    if dry_run {
+        info!("Dry run: discovering and fetching specs without writing the catalog");
+    }
 
     let client = Client::try_default().await.map_err(|e| {
         error!("Failed to create Kubernetes client: {}", e);
         e
     })?;
 
-    let watch_namespaces = match namespace_utils::parse_watch_namespaces() {
+    let namespace_selector = config.namespace_selector();
+    let watch_namespaces = match config.watch_namespaces() {
         Some(namespaces) => {
-            if namespaces.contains(&"current".to_string()) {
+            if namespaces.contains(&"current".to_string()) && namespace_selector.is_some() {
+                // Only namespaces matching the selector are watched
+                Vec::new()
+            } else if namespaces.contains(&"current".to_string()) {
                 // Watch current namespace only
                 let current_namespace = env::var("POD_NAMESPACE").unwrap_or_else(|_| "default".to_string());
                 info!("Watching current namespace: {}", current_namespace);
@@ -67,120 +288,512 @@ async fn main() -> Result<(), AppError> {
         }
     };
     
-    let discovery_namespace =
-        env::var(DISCOVERY_NAMESPACE_ENV).unwrap_or_else(|_| "default".to_string());
-    let discovery_configmap =
-        env::var(DISCOVERY_CONFIGMAP_ENV).unwrap_or_else(|_| "openapi-discovery".to_string());
-
-    // Validate discovery namespace and configmap names
-    if discovery_namespace.is_empty() {
-        error!("DISCOVERY_NAMESPACE cannot be empty");
-        return Err(AppError::Io(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "DISCOVERY_NAMESPACE cannot be empty",
-        )));
-    }
-    
-    if discovery_configmap.is_empty() {
-        error!("DISCOVERY_CONFIGMAP cannot be empty");
-        return Err(AppError::Io(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "DISCOVERY_CONFIGMAP cannot be empty",
-        )));
-    }
-
-    // Validate discovery namespace name follows Kubernetes naming rules
-    if !discovery_namespace.chars().all(|c| c.is_alphanumeric() || c == '-') {
-        error!("Invalid DISCOVERY_NAMESPACE: '{}' contains invalid characters", discovery_namespace);
-        return Err(AppError::Io(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            format!("Invalid discovery namespace name: {}", discovery_namespace),
-        )));
+    let discovery_namespace = config.discovery_namespace.clone();
+    let discovery_configmap = config.discovery_configmap.clone();
+
+    info!("Watching namespaces: {:?}", watch_namespaces);
+    let exclude_namespaces = config.exclude_namespaces();
+    if !exclude_namespaces.is_empty() {
+        info!("Never discovering namespaces: {:?}", exclude_namespaces);
     }
-    
-    if discovery_namespace.len() > 63 {
-        error!("Invalid DISCOVERY_NAMESPACE: '{}' exceeds 63 characters", discovery_namespace);
-        return Err(AppError::Io(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            format!("Discovery namespace name too long: {}", discovery_namespace),
-        )));
-    }
-
-    // Validate discovery configmap name follows Kubernetes naming rules
-    if !discovery_configmap.chars().all(|c| c.is_alphanumeric() || c == '-') {
-        error!("Invalid DISCOVERY_CONFIGMAP: '{}' contains invalid characters", discovery_configmap);
-        return Err(AppError::Io(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            format!("Invalid discovery configmap name: {}", discovery_configmap),
-        )));
+    if let Some(selector) = &namespace_selector {
+        info!("Watching namespaces matching selector: {}", selector);
     }
-    
-    if discovery_configmap.len() > 63 {
-        error!("Invalid DISCOVERY_CONFIGMAP: '{}' exceeds 63 characters", discovery_configmap);
-        return Err(AppError::Io(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            format!("Discovery configmap name too long: {}", discovery_configmap),
-        )));
+    let label_selector = label_selector::from_env().map_err(|e| {
+        AppError::Io(std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+    })?;
+    if let Some(selector) = &label_selector {
+        info!("Discovering services matching label selector: {}", selector);
     }
-
-    info!("Watching namespaces: {:?}", watch_namespaces);
     info!("Discovery namespace: {}", discovery_namespace);
     info!("Discovery ConfigMap: {}", discovery_configmap);
 
-    let services = if watch_namespaces.is_empty() {
+    let output = DiscoveryOutput::from_env()?;
+    if output == DiscoveryOutput::Crd {
+        info!("Writing discovered APIs as DiscoveredApi resources");
+    }
+
+    let encoding = DiscoveryEncoding::from_env()?;
+    if output == DiscoveryOutput::ConfigMap {
+        info!("Discovery payload encoding: {:?} (key '{}')", encoding, encoding.key());
+    }
+    let storage = DiscoveryStorage::from_env()?;
+    let layout = DiscoveryLayout::from_env()?;
+    if output == DiscoveryOutput::ConfigMap && layout == DiscoveryLayout::Entries {
+        info!("Storing every catalog entry under its own key");
+    }
+    let fetch_via = FetchVia::from_env()
+        .map_err(|e| AppError::Io(std::io::Error::new(std::io::ErrorKind::InvalidInput, e)))?;
+    if fetch_via == FetchVia::ApiServer {
+        info!("Fetching specs through the API server's service proxy");
+    }
+    if output == DiscoveryOutput::ConfigMap && storage == DiscoveryStorage::Secret {
+        info!("Storing the discovery catalog in Secret '{}'", discovery_configmap);
+    }
+
+    // Conversions are requested by the API server as soon as a v1beta1 client shows up,
+    // and Services are validated whether or not this replica leads, so the webhook runs
+    // independently of the controller
+    if let Some(webhook_config) = webhook::WebhookConfig::from_env()? {
+        if let Err(e) = webhook::register_conversion(client.clone(), &webhook_config).await {
+            warn!("Failed to register the conversion webhook on the CRD: {}", e);
+        }
+        if let Err(e) = webhook::register_validation(client.clone(), &webhook_config).await {
+            warn!("Failed to register the Service annotation webhook: {}", e);
+        }
+        tokio::spawn(async move {
+            if let Err(e) = webhook::serve(webhook_config).await {
+                error!("Webhook stopped: {}", e);
+            }
+        });
+    }
+
+    let tombstone_grace = config.tombstone_grace();
+    if output == DiscoveryOutput::ConfigMap && tombstone_grace > chrono::Duration::zero() {
+        info!("Keeping tombstones of removed APIs for {}s", tombstone_grace.num_seconds());
+    }
+
+    let signer = SpecSigner::from_env().map_err(|e| {
+        AppError::Io(std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string()))
+    })?;
+    if let Some(signer) = &signer {
+        info!("Signing specs with key {}", signer.key_id());
+    }
+
+    let (health_port, health_stall_timeout) = (config.health_port, config.health_stall_timeout());
+    let metrics_port = config.metrics_port;
+    let catalog_ttl = gc::ttl_from_env()?;
+    let federation = federation::Federation::from_env().await?;
+    let audit = AuditLog::from_env()?.map(Arc::new);
+    if audit.is_some() && output == DiscoveryOutput::ConfigMap {
+        info!("Recording catalog changes in the audit log");
+    }
+    let contract_checker = ContractChecker::from_env()?;
+    if contract_checker.is_none() {
+        info!("Contract checks disabled");
+    }
+
+    if config.max_concurrent_reconciles > 0 {
+        info!(
+            "Running at most {} reconciles at a time per controller",
+            config.max_concurrent_reconciles
+        );
+    }
+    let controller_config =
+        controller::Config::default().concurrency(config.max_concurrent_reconciles);
+
+    let breaking_gate = BreakingChangeGate::from_env();
+    if breaking_gate.is_none() {
+        info!("Breaking-change gate disabled, every spec revision is published");
+    }
+
+    let tenants = TenantResolver::from_env();
+    if tenants.is_enabled() {
+        info!("Multi-tenancy enabled, entries are tagged with their namespace's tenant");
+    }
+
+    let refresh_window = RefreshWindow::from_env().map_err(|e| {
+        AppError::Io(std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+    })?;
+    if let Some(window) = &refresh_window {
+        info!("Fetching specs only within refresh window '{}' (UTC)", window);
+    }
+
+    let dynamic_namespaces = namespace_selector.is_some()
+        || watch_namespaces.iter().any(|ns| namespace_utils::is_pattern(ns));
+
+    // Patterns and selectors get a Service watcher per matching namespace (see
+    // namespace_watch), started and stopped as namespaces come and go
+    let services = if dynamic_namespaces {
+        info!("Watching services per namespace, following namespace patterns/selector");
+        None
+    } else if watch_namespaces.is_empty() {
         let current_namespace =
             env::var("POD_NAMESPACE").unwrap_or_else(|_| "default".to_string());
         info!("Watching current namespace: {}", current_namespace);
-        Api::namespaced(client.clone(), &current_namespace)
+        Some(Api::namespaced(client.clone(), &current_namespace))
     } else if watch_namespaces.len() == 1 && watch_namespaces[0] == "all" {
         info!("Watching all namespaces");
-        Api::all(client.clone())
+        Some(Api::all(client.clone()))
     } else if watch_namespaces.len() == 1 {
         let namespace = &watch_namespaces[0];
         info!("Watching single namespace: {}", namespace);
-        Api::namespaced(client.clone(), namespace)
+        Some(Api::namespaced(client.clone(), namespace))
     } else {
+        // One namespaced Service watcher per namespace (see namespace_watch), so Roles in
+        // the listed namespaces suffice
         info!("Watching multiple namespaces: {:?}", watch_namespaces);
-        Api::all(client.clone())
+        None
     };
 
+    // ApiDocs and Deployments are watched in the same scope as Services, so a namespaced
+    // operator gets by with namespaced RBAC
+    let watch_api_docs = config.watch_api_docs;
+    let api_doc_controller = watch_api_docs.then(|| {
+        info!("Reconciling ApiDoc resources into the catalog");
+        let api_docs: Api<ApiDoc> =
+            watch_scope(client.clone(), dynamic_namespaces, &watch_namespaces);
+        Controller::new(api_docs, Config::default().any_semantic())
+            .with_config(controller_config.clone())
+    });
+    let watch_deployments = config.watch_deployments;
+    let (deployment_store, deployment_writer) = reflector::store::<Deployment>();
+    if watch_deployments {
+        info!("Applying api-doc.io annotations of Deployments to the services selecting them");
+    }
+    let refresh_on_rollout = config.refresh_on_rollout;
+    if refresh_on_rollout {
+        info!("Refreshing the specs of services when a rollout of their Deployment completes");
+    }
+
     let discovery: Api<ConfigMap> =
         Api::namespaced(client.clone(), &discovery_namespace);
+    let spec_store = match output {
+        DiscoveryOutput::ConfigMap => {
+            spec_store::from_env(&discovery, &discovery_configmap, &discovery_namespace)?
+        }
+        // DiscoveredApi resources keep their spec in a ConfigMap of their own
+        DiscoveryOutput::Crd => None,
+    };
+    if let Some(store) = &spec_store {
+        info!("Storing a copy of each spec with the '{}' spec store", store.name());
+    }
+
+    let fetcher = SpecFetcher::new(config.fetch_config()?)?;
+    if let Some(limit) = fetcher.config().rate_limit {
+        info!(
+            "Limiting spec fetches to {} per second (burst {})",
+            limit.rate, limit.burst
+        );
+    }
+    let insecure_fetcher = fetcher.with_tls(TlsOptions {
+        insecure_skip_verify: true,
+        ..fetcher.config().tls.clone()
+    })?;
+
+    // Track all namespaces, and those matching the selector; changes start or stop the
+    // Service watchers of namespaces that appear, disappear, start or stop matching.
+    let (namespace_store, namespace_writer) = reflector::store::<Namespace>();
+    let (all_namespaces, all_namespaces_writer) = reflector::store::<Namespace>();
+    let namespace_events = dynamic_namespaces.then(|| {
+        let namespaces: Api<Namespace> = Api::all(client.clone());
+        let mut events = vec![
+            watcher::watcher(namespaces.clone(), Config::default())
+                .default_backoff()
+                .reflect(all_namespaces_writer)
+                .touched_objects()
+                .boxed(),
+        ];
+        if let Some(selector) = &namespace_selector {
+            events.push(
+                watcher::watcher(namespaces, Config::default().labels(selector))
+                    .default_backoff()
+                    .reflect(namespace_writer)
+                    .touched_objects()
+                    .boxed(),
+            );
+        }
+        futures::stream::select_all(events)
+    });
+    let (service_store, service_writer) = reflector::store::<Service>();
+
+    // Namespace-level api-doc.io annotations apply to every Service in the namespace, so
+    // annotation changes re-evaluate the services
+    let namespace_defaults = config.namespace_defaults;
+    let (defaults_store, defaults_writer) = reflector::store::<Namespace>();
+    let defaults_events = namespace_defaults.then(|| {
+        info!("Applying api-doc.io annotations of namespaces to their services");
+        let namespaces: Api<Namespace> = Api::all(client.clone());
+        namespace_trigger(
+            watcher::watcher(namespaces, Config::default())
+                .default_backoff()
+                .reflect(defaults_writer)
+                .applied_objects()
+                .predicate_filter(predicates::annotations),
+        )
+    });
 
-    let http_client = reqwest::Client::builder()
-        .timeout(Duration::from_secs(10))
-        .build()?;
+    // Namespace labels name the tenants of TENANT_LABEL, so relabelling a namespace moves
+    // its services to another tenant
+    let (tenant_store, tenant_writer) = reflector::store::<Namespace>();
+    let tenant_events = tenants.label().is_some().then(|| {
+        let namespaces: Api<Namespace> = Api::all(client.clone());
+        namespace_trigger(
+            watcher::watcher(namespaces, Config::default())
+                .default_backoff()
+                .reflect(tenant_writer)
+                .applied_objects()
+                .predicate_filter(predicates::labels),
+        )
+    });
 
     let context = Arc::new(ContextData {
         discovery,
-        http_client,
+        fetcher,
+        insecure_fetcher,
         watch_namespaces,
+        exclude_namespaces,
+        label_selector,
+        namespace_store: namespace_selector.as_ref().map(|_| namespace_store),
+        namespace_defaults: namespace_defaults.then_some(defaults_store),
         discovery_namespace,
         discovery_configmap,
+        output,
+        encoding,
+        storage,
+        layout,
+        spec_store,
+        tombstone_grace,
+        reconcile_interval: config.reconcile_interval,
+        error_retry_interval: config.error_retry_interval,
+        publishers: Arc::new(PublishManager::from_env()?),
+        tenant_namespaces: tenant_events.is_some().then_some(tenant_store),
+        tenants,
+        signer: signer.map(Arc::new),
+        contract_checker: contract_checker.map(Arc::new),
+        breaking_gate: breaking_gate.map(Arc::new),
+        spec_changes: Arc::new(SpecChanges::default()),
+        recorder: Recorder::new(client.clone(), Reporter::from("openapi-k8s-operator")),
+        refresh_window,
+        api_docs: api_doc_controller.as_ref().map(Controller::store),
+        deployments: watch_deployments.then(|| deployment_store.clone()),
+        health: Arc::new(Health::new(health_stall_timeout)),
+        fetch_metrics: Arc::new(FetchMetrics::default()),
+        audit,
+        dry_run,
+        written_catalog: Arc::new(tokio::sync::Mutex::new(None)),
+        fetch_via,
+    });
+
+    let health = context.health.clone();
+    tokio::spawn(async move {
+        if let Err(e) = health::serve(health_port, health).await {
+            error!("Health endpoints stopped: {}", e);
+        }
+    });
+    let fetch_metrics = context.fetch_metrics.clone();
+    tokio::spawn(async move {
+        if let Err(e) = fetch_metrics::serve(metrics_port, fetch_metrics).await {
+            error!("Metrics endpoint stopped: {}", e);
+        }
     });
 
+    // Standby replicas wait here; everything from now on writes to the cluster
+    let elector = LeaderElector::from_env(client.clone())?;
+    if let Some(elector) = &elector {
+        info!("Leader election enabled, identity {}", elector.identity());
+        context.health.set_role(Role::Standby);
+        elector.acquire().await;
+    }
+
     // Initialize the ConfigMap if it doesn't exist
-    if let Err(e) = initialize_discovery_configmap(&context).await {
+    if context.output == DiscoveryOutput::ConfigMap
+        && let Err(e) = initialize_discovery_configmap(&context).await
+    {
         error!("Failed to initialize discovery ConfigMap: {}", e);
         return Err(e);
     }
 
-    let controller = Controller::new(services, Config::default().any_semantic())
-        .run(reconcile, error_policy, context)
-        .for_each(|res| async move {
-            match res {
-                Ok(o) => info!("Reconciled service: {:?}", o),
-                Err(e) => error!("Reconcile failed: {:?}", e),
+    let mut controller = match (services, namespace_events) {
+        (Some(services), _) => {
+            let selector = context.label_selector.clone();
+            let trigger = watcher::watcher(services, Config::default().any_semantic())
+                .default_backoff()
+                .reflect(service_writer)
+                .applied_objects()
+                .predicate_filter(move |service: &Service| {
+                    Some(namespace_watch::discovery_hash(service, selector.as_ref()))
+                });
+            Controller::for_stream(trigger, service_store)
+        }
+        (None, Some(events)) => {
+            let trigger = namespace_watch::spawn(
+                context.clone(),
+                Some(all_namespaces),
+                events,
+                service_writer,
+            );
+            Controller::for_stream(trigger, service_store)
+        }
+        (None, None) => {
+            let trigger = namespace_watch::spawn(
+                context.clone(),
+                None,
+                namespace_watch::fixed(&context.watch_namespaces),
+                service_writer,
+            );
+            Controller::for_stream(trigger, service_store)
+        }
+    };
+    controller = controller.with_config(controller_config.clone());
+    if let Some(events) = defaults_events {
+        controller = controller.reconcile_all_on(events);
+    }
+    if let Some(events) = tenant_events {
+        controller = controller.reconcile_all_on(events);
+    }
+    // Deployment changes and completed rollouts reconcile the Services selecting them, and
+    // Deployments no Service selects are reconciled on their own
+    let deployment_controller = if watch_deployments || refresh_on_rollout {
+        let deployments: Api<Deployment> =
+            watch_scope(client.clone(), dynamic_namespaces, &context.watch_namespaces);
+        let triggers = deployment::spawn(
+            context.clone(),
+            watcher::watcher(deployments, Config::default())
+                .default_backoff()
+                .reflect(deployment_writer),
+            watch_deployments,
+            refresh_on_rollout,
+        );
+        let services = controller.store();
+        controller = controller.watches_stream(triggers.services, move |deployment| {
+            deployment::services_for(&services, &deployment)
+        });
+        watch_deployments.then(|| {
+            Controller::for_stream(triggers.deployments, deployment_store)
+                .with_config(controller_config)
+        })
+    } else {
+        None
+    };
+
+    let health = context.health.clone();
+    let controller = controller
+        .run(reconcile, error_policy, context.clone())
+        .for_each(move |res| {
+            health.observe(&res);
+            async move {
+                match res {
+                    Ok(o) => info!("Reconciled service: {:?}", o),
+                    Err(e) => error!("Reconcile failed: {:?}", e),
+                }
             }
         });
 
+    // DiscoveredApi resources are garbage collected with their Service
+    if let Some(ttl) = catalog_ttl
+        && context.output == DiscoveryOutput::ConfigMap
+    {
+        info!(
+            "Removing entries of deleted services not refreshed for {}s",
+            ttl.num_seconds()
+        );
+        tokio::spawn(gc::run(context.clone(), ttl));
+    }
+    if context.output == DiscoveryOutput::ConfigMap && !context.dry_run {
+        tokio::spawn(drift::run(context.clone()));
+    }
+    // Without a shared document there is no catalog to merge into
+    match federation {
+        Some(federation) if context.output == DiscoveryOutput::ConfigMap => {
+            info!(
+                "Federating the catalogs of clusters {:?}",
+                federation.cluster_names()
+            );
+            tokio::spawn(federation::run(context.clone(), federation));
+        }
+        Some(_) => warn!(
+            "{} is ignored with DISCOVERY_OUTPUT=crd",
+            federation::FEDERATION_KUBECONFIG_DIR_ENV
+        ),
+        None => {}
+    }
+
+    context.health.set_role(Role::Active);
     info!("Controller started, watching for services with API documentation annotations");
-    controller.await;
+    let mut controllers = vec![controller.boxed()];
+    if let Some(api_doc_controller) = api_doc_controller {
+        let health = context.health.clone();
+        controllers.push(
+            api_doc_controller
+                .run(api_doc::reconcile, api_doc::error_policy, context.clone())
+                .for_each(move |res| {
+                    health.observe(&res);
+                    async move {
+                        match res {
+                            Ok(o) => info!("Reconciled ApiDoc: {:?}", o),
+                            Err(e) => error!("ApiDoc reconcile failed: {:?}", e),
+                        }
+                    }
+                })
+                .boxed(),
+        );
+    }
+    if let Some(deployment_controller) = deployment_controller {
+        let health = context.health.clone();
+        controllers.push(
+            deployment_controller
+                .run(deployment::reconcile, deployment::error_policy, context.clone())
+                .for_each(move |res| {
+                    health.observe(&res);
+                    async move {
+                        match res {
+                            Ok(o) => info!("Reconciled Deployment: {:?}", o),
+                            Err(e) => error!("Deployment reconcile failed: {:?}", e),
+                        }
+                    }
+                })
+                .boxed(),
+        );
+    }
+    let controllers = futures::future::join_all(controllers).map(drop);
+
+    match &elector {
+        Some(elector) => {
+            tokio::select! {
+                _ = controllers => {}
+                _ = elector.keep_renewing() => {
+                    // Exiting stops all watchers at once; the restarted pod stands by
+                    return Err(AppError::Io(std::io::Error::other(
+                        "lost leadership, stopping the controllers",
+                    )));
+                }
+            }
+        }
+        None => controllers.await,
+    }
 
     Ok(())
 }
 
+/// Api of `K` in the namespaces Services are watched in
+fn watch_scope<K>(client: Client, dynamic_namespaces: bool, watch_namespaces: &[String]) -> Api<K>
+where
+    K: kube::Resource<Scope = k8s_openapi::NamespaceResourceScope, DynamicType = ()>,
+{
+    match (dynamic_namespaces, watch_namespaces) {
+        (false, []) => Api::namespaced(
+            client,
+            &env::var("POD_NAMESPACE").unwrap_or_else(|_| "default".to_string()),
+        ),
+        (false, [namespace]) if namespace != "all" => Api::namespaced(client, namespace),
+        _ => Api::all(client),
+    }
+}
+
+/// Forwards namespace changes as a trigger to reconcile all services
+fn namespace_trigger(
+    stream: impl futures::Stream<Item = Result<Namespace, watcher::Error>> + Send + 'static,
+) -> futures::channel::mpsc::UnboundedReceiver<()> {
+    let (tx, rx) = futures::channel::mpsc::unbounded();
+    tokio::spawn(async move {
+        let mut stream = std::pin::pin!(stream);
+        while let Some(res) = stream.next().await {
+            match res {
+                Ok(ns) => {
+                    info!("Namespace {} changed, re-evaluating services", ns.name_any());
+                    if tx.unbounded_send(()).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => warn!("Namespace watcher error: {}", e),
+            }
+        }
+    });
+    rx
+}
 
+#[instrument(skip_all, fields(namespace = %service.namespace().unwrap_or_default(), service = %service.name_any()))]
 async fn reconcile(
     service: Arc<Service>,
     ctx: Arc<ContextData>,
@@ -188,15 +801,33 @@ async fn reconcile(
     let service_name = service.name_any();
     let namespace = service.namespace().unwrap_or_default();
 
-    if !ctx.watch_namespaces.is_empty()
-        && !ctx.watch_namespaces.contains(&"all".to_string())
-        && !ctx.watch_namespaces.contains(&namespace)
-    {
+    if !ctx.watches_namespace(&namespace) {
+        let reason = if namespace_utils::namespace_matches(&ctx.exclude_namespaces, &namespace) {
+            RemovalReason::Excluded
+        } else {
+            RemovalReason::NamespaceNotWatched
+        };
+        info!(
+            "Skipping service {} in namespace {} ({:?})",
+            service_name, namespace, reason
+        );
+        remove_entry_with_event(
+            &ctx,
+            &service.object_ref(&()),
+            &namespace,
+            &service_name,
+            reason,
+        )
+        .await?;
+        return Ok(Action::requeue(ctx.reconcile_interval));
+    }
+
+    if let Some(api_doc) = ctx.api_doc_for(&namespace, &service_name) {
         info!(
-            "Skipping service {} in namespace {} (not in watch list)",
-            service_name, namespace
+            "Service {}/{} is registered by ApiDoc {}, ignoring its annotations",
+            namespace, service_name, api_doc
         );
-        return Ok(Action::requeue(Duration::from_secs(300)));
+        return Ok(Action::requeue(ctx.reconcile_interval));
     }
 
     info!(
@@ -204,154 +835,951 @@ async fn reconcile(
         service_name, namespace
     );
 
-    let annotations = service.annotations();
-    let enabled = annotations
-        .get(API_DOC_ENABLED_ANNOTATION)
-        .map(|v| v == "true")
-        .unwrap_or(false);
+    let own_annotations = match &ctx.deployments {
+        Some(store) => deployment::with_deployment_annotations(store, &service),
+        None => service.annotations().clone(),
+    };
+    let mut service_annotations = match &ctx.namespace_defaults {
+        Some(store) => {
+            let ns = store.get(&ObjectRef::new(&namespace));
+            with_namespace_defaults(ns.as_ref().map(|ns| ns.annotations()), &own_annotations)
+        }
+        None => own_annotations,
+    };
+    if let Some(selector) = &ctx.label_selector
+        && selector.matches(service.labels())
+    {
+        service_annotations
+            .entry(API_DOC_ENABLED_ANNOTATION.to_string())
+            .or_insert_with(|| "true".to_string());
+    }
+    let (annotations, issues) = ApiDocAnnotations::parse(&service_annotations);
+    for issue in &issues {
+        warn!(
+            "Service {}/{} has an invalid annotation: {}",
+            namespace, service_name, issue
+        );
+    }
 
-    if !enabled {
+    if annotations.exclude {
+        info!(
+            "Service {}/{} is excluded from discovery",
+            namespace, service_name
+        );
+        remove_entry_with_event(
+            &ctx,
+            &service.object_ref(&()),
+            &namespace,
+            &service_name,
+            RemovalReason::Excluded,
+        )
+        .await?;
+        return Ok(Action::requeue(ctx.reconcile_interval));
+    }
+
+    if !annotations.enabled {
         info!(
             "Service {} does not have API documentation enabled, removing from discovery",
             service_name
         );
-        remove_entry_from_discovery_configmap(ctx.clone(), &namespace, &service_name).await?;
-        return Ok(Action::requeue(Duration::from_secs(300)));
+        remove_entry_with_event(
+            &ctx,
+            &service.object_ref(&()),
+            &namespace,
+            &service_name,
+            RemovalReason::DocsDisabled,
+        )
+        .await?;
+        return Ok(Action::requeue(ctx.reconcile_interval));
     }
 
-    let api_path = annotations
-        .get(API_DOC_PATH_ANNOTATION)
-        .cloned()
-        .unwrap_or_else(|| DEFAULT_API_DOC_PATH.to_string());
+    let Some(url) = service_url(&service, &annotations) else {
+        warn!(
+            "Service {}/{} has no port named {}, removing from discovery",
+            namespace,
+            service_name,
+            annotations
+                .port
+                .as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_default()
+        );
+        remove_entry_with_event(
+            &ctx,
+            &service.object_ref(&()),
+            &namespace,
+            &service_name,
+            RemovalReason::PortNotFound,
+        )
+        .await?;
+        return Ok(Action::requeue(ctx.reconcile_interval));
+    };
+
+    // Fetching from a Service without ready pods would only flip the entry to unavailable;
+    // specs hosted elsewhere do not depend on them
+    if annotations.url.is_none()
+        && annotations.spec_config_map.is_none()
+        && !endpoints::has_ready_endpoints(ctx.discovery.clone().into_client(), &service).await
+    {
+        info!(
+            "Service {}/{} has no ready endpoints, fetching its spec in {:?}",
+            namespace,
+            service_name,
+            endpoints::NOT_READY_REQUEUE
+        );
+        return Ok(Action::requeue(endpoints::NOT_READY_REQUEUE));
+    }
 
-    let api_name = annotations
-        .get(API_DOC_NAME_ANNOTATION)
-        .cloned()
-        .unwrap_or_else(|| format!("{} API", service_name));
+    let proxy_path = (annotations.fetch_via.unwrap_or(ctx.fetch_via) == FetchVia::ApiServer)
+        .then(|| service_proxy_path(&service, &annotations))
+        .flatten();
+    let source = EntrySource {
+        object: service.object_ref(&()),
+        owner: service.owner_ref(&()),
+        proxy_path,
+        cluster_origin: service_origin(&service, &annotations),
+    };
+    let discovery = discover(
+        &ctx,
+        &source,
+        &namespace,
+        &service_name,
+        url,
+        &annotations,
+        None,
+    )
+    .await?;
+    if let Discovery::Stored = discovery {
+        info!("Successfully reconciled service: {}", service_name);
+    }
+    Ok(discovery.action(&ctx))
+}
 
-    let description = annotations.get(API_DOC_DESCRIPTION_ANNOTATION).cloned();
+/// What an entry is discovered from, an annotated Service or an ApiDoc
+struct EntrySource {
+    /// Object Events about the entry are emitted on
+    object: ObjectReference,
+    /// Owner of the entry's DiscoveredApi, which is garbage collected with it
+    owner: Option<OwnerReference>,
+    /// Path of the spec through the API server's service proxy, when it is fetched that way
+    proxy_path: Option<String>,
+    /// In-cluster origin of the service, which `cluster` in `api-doc.io/server-url` stands for
+    cluster_origin: Option<String>,
+}
+
+/// Outcome of [`discover`]
+enum Discovery {
+    /// The entry is in the catalog with the fetched spec
+    Stored,
+    /// The refresh window is closed and the stored entry is kept until it opens again
+    Deferred(Duration),
+    /// The spec could not be fetched, so the entry was marked unavailable
+    Unreachable(FetchFailure),
+}
 
-    let port = service
+impl Discovery {
+    fn action(&self, ctx: &ContextData) -> Action {
+        match self {
+            Discovery::Deferred(wait) => Action::requeue(*wait),
+            Discovery::Stored | Discovery::Unreachable(_) => {
+                Action::requeue(ctx.reconcile_interval)
+            }
+        }
+    }
+}
+
+/// Port of the service serving the spec; `None` if the port named by the annotations is not on it
+fn service_port(service: &Service, annotations: &ApiDocAnnotations) -> Option<i32> {
+    let ports = service
         .spec
         .as_ref()
-        .and_then(|s| s.ports.as_ref())
-        .and_then(|ports| ports.first())
-        .map(|p| p.port)
-        .unwrap_or(8080);
-
-    let url = format!(
-        "http://{}.{}.svc.cluster.local:{}{}",
-        service_name, namespace, port, api_path
+        .and_then(|s| s.ports.as_deref())
+        .unwrap_or_default();
+    let named_ports = ports.iter().map(|p| (p.name.as_deref(), p.port));
+    match &annotations.port {
+        Some(port_ref) => port_ref.resolve(named_ports),
+        None => Some(ports.first().map(|p| p.port).unwrap_or(8080)),
+    }
+}
+
+/// URL of the spec on the service, or `api-doc.io/url`; `None` if the port named by the
+/// annotations is not on the service
+fn service_url(service: &Service, annotations: &ApiDocAnnotations) -> Option<String> {
+    if let Some(url) = &annotations.url {
+        return Some(url.clone());
+    }
+    Some(format!(
+        "{}{}",
+        service_origin(service, annotations)?,
+        annotations.path
+    ))
+}
+
+/// Scheme, cluster DNS name and port of the service; `None` if the port named by the
+/// annotations is not on the service
+fn service_origin(service: &Service, annotations: &ApiDocAnnotations) -> Option<String> {
+    Some(format!(
+        "{}://{}.{}.svc.cluster.local:{}",
+        annotations.scheme.as_str(),
+        service.name_any(),
+        service.namespace().unwrap_or_default(),
+        service_port(service, annotations)?
+    ))
+}
+
+/// Path of the spec through the API server's service proxy; `None` for specs hosted outside
+/// the cluster
+fn service_proxy_path(service: &Service, annotations: &ApiDocAnnotations) -> Option<String> {
+    if annotations.url.is_some() {
+        return None;
+    }
+    Some(apiserver_proxy::path(
+        &service.namespace().unwrap_or_default(),
+        &service.name_any(),
+        annotations.scheme,
+        service_port(service, annotations)?,
+        &annotations.path,
+    ))
+}
+
+/// Entry of a service whose spec was fetched from `url`, before its spec is measured
+fn catalog_entry(
+    namespace: &str,
+    service_name: &str,
+    url: String,
+    annotations: &ApiDocAnnotations,
+    refresh_window: Option<&RefreshWindow>,
+) -> ApiInventoryEntry {
+    ApiInventoryEntry {
+        name: annotations
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("{} API", service_name)),
+        description: annotations.description.clone(),
+        lifecycle: annotations.lifecycle,
+        ui: Some(annotations.ui.clone()).filter(|ui| !ui.is_empty()),
+        refresh_interval: annotations.refresh_interval,
+        refresh_window: refresh_window.map(|window| window.to_string()),
+        api_group: annotations.api_group.clone(),
+        insecure_skip_verify: annotations.insecure_skip_verify,
+        // Deterministic ID based on service name and namespace
+        ..ApiInventoryEntry::new(namespace, service_name, url)
+    }
+}
+
+/// Fetches the spec from `url` and stores and publishes the entry described by
+/// `annotations`, under the key of the service
+async fn discover(
+    ctx: &Arc<ContextData>,
+    source: &EntrySource,
+    namespace: &str,
+    service_name: &str,
+    url: String,
+    annotations: &ApiDocAnnotations,
+    auth: Option<FetchAuth>,
+) -> Result<Discovery, AppError> {
+    // Outside the refresh window the stored entry is kept as is; new services are
+    // fetched anyway, so they show up in the catalog right away
+    let refresh_window = annotations
+        .refresh_window
+        .as_ref()
+        .or(ctx.refresh_window.as_ref());
+    if let Some(window) = refresh_window {
+        let now = Utc::now();
+        if !window.is_open(now) && mark_stale_by_schedule(ctx, namespace, service_name).await? {
+            let wait = window
+                .next_open(now)
+                .and_then(|next| (next - now).to_std().ok())
+                .unwrap_or(ctx.reconcile_interval)
+                .max(Duration::from_secs(60));
+            info!(
+                "Refresh window '{}' of service {}/{} is closed, keeping the stored spec for {:?}",
+                window, namespace, service_name, wait
+            );
+            return Ok(Discovery::Deferred(wait));
+        }
+    }
+
+    let fetcher = if annotations.insecure_skip_verify {
+        &ctx.insecure_fetcher
+    } else {
+        &ctx.fetcher
+    };
+    let client = ctx.discovery.clone().into_client();
+    let started = Instant::now();
+    let fetched = fetch_service_spec(
+        fetcher,
+        &client,
+        namespace,
+        &url,
+        source.proxy_path.as_deref(),
+        annotations,
+        auth,
+    )
+    .await?;
+    ctx.fetch_metrics.record(
+        namespace,
+        service_name,
+        started.elapsed(),
+        fetched.as_ref().map(String::len),
     );
+    let spec = match fetched {
+        Ok(spec) => spec,
+        Err(failure) => {
+            let problem = match failure.phase {
+                FailurePhase::Parse => "serves no valid OpenAPI document",
+                FailurePhase::Size => "serves a spec too large to read",
+                _ => "unreachable (wrong name, network, or down)",
+            };
+            warn!(
+                "Service {} {}, marking it unavailable: {}{}",
+                service_name,
+                problem,
+                failure,
+                failure
+                    .snippet
+                    .as_deref()
+                    .map(|snippet| format!(", response: {}", snippet))
+                    .unwrap_or_default()
+            );
+            publish_event(
+                ctx,
+                &source.object,
+                EventType::Warning,
+                "SpecFetchFailed",
+                "FetchSpec",
+                format!("Spec at {} {}: {}", url, problem, failure),
+            )
+            .await;
+            let mut placeholder =
+                catalog_entry(namespace, service_name, url, annotations, refresh_window);
+            placeholder.tenant = ctx.tenant_for(namespace);
+            mark_unavailable(ctx, source, placeholder, &failure.to_string()).await?;
+            return Ok(Discovery::Unreachable(failure));
+        }
+    };
+    let spec = if annotations.server_urls.is_empty() {
+        spec
+    } else {
+        match spec_utils::rewrite_servers(
+            &spec,
+            &annotations.server_urls,
+            source.cluster_origin.as_deref(),
+        ) {
+            Ok(rewritten) => rewritten,
+            Err(e) => {
+                warn!(
+                    "Could not rewrite the servers of the spec of {}/{}, storing it as is: {}",
+                    namespace, service_name, e
+                );
+                spec
+            }
+        }
+    };
 
-    let available = check_api_availability(&ctx.http_client, &url).await;
+    let mut entry = catalog_entry(namespace, service_name, url, annotations, refresh_window);
+    entry.tenant = ctx.tenant_for(namespace);
 
-    if !available {
-        warn!(
-            "Service {} unreachable (wrong name, network, or down), removing from discovery",
-            service_name
+    if let Some(checker) = &ctx.contract_checker {
+        if annotations.contract_check {
+            entry.contract = checker.report(&entry, &spec).await;
+        } else {
+            checker.forget(&entry.id).await;
+        }
+    }
+
+    let spec = match &ctx.breaking_gate {
+        Some(gate) => gate_revision(ctx, gate, &source.object, &entry, annotations, spec).await?,
+        None => spec,
+    };
+    entry.metrics = SpecMetrics::compute(&spec).ok();
+    entry.spec_sha256 = Some(sha256_hex(spec.as_bytes()));
+
+    if ctx.output == DiscoveryOutput::Crd && !ctx.spec_changes.knows(&entry.id).await {
+        let client = ctx.discovery.clone().into_client();
+        if let Some(stored) =
+            discovered_api::stored_spec(client, &entry.namespace, &entry.service_name).await?
+        {
+            ctx.spec_changes.seed(&entry.id, stored).await;
+        }
+    }
+    let change = ctx.spec_changes.diff(&entry.id, &spec).await;
+    entry.last_change_summary = change
+        .as_ref()
+        .map(|diff| diff.change_summary(entry.last_updated));
+
+    if ctx.dry_run {
+        info!(
+            "Dry run: would store spec {} ({} bytes) with entry {}",
+            &sha256_hex(spec.as_bytes())[..12],
+            spec.len(),
+            serde_json::to_string(&entry).unwrap_or_default()
         );
-        remove_entry_from_discovery_configmap(ctx.clone(), &namespace, &service_name).await?;
-        return Ok(Action::requeue(Duration::from_secs(300)));
+        return Ok(Discovery::Stored);
     }
 
-    // Create a deterministic ID based on service name and namespace
-    let entry_id = entry_key!(&namespace, &service_name);
+    let signature = ctx.signer.as_ref().map(|signer| signer.sign(&spec));
+    match ctx.output {
+        DiscoveryOutput::ConfigMap => {
+            if let Some(store) = &ctx.spec_store {
+                store.locate(&mut entry, &spec);
+                store.put(&entry, &spec, signature.as_ref()).await?;
+            }
+            update_discovery_configmap(ctx.clone(), entry.clone()).await?
+        }
+        DiscoveryOutput::Crd => {
+            let client = ctx.discovery.clone().into_client();
+            discovered_api::apply(
+                client,
+                source.owner.clone(),
+                &entry,
+                &spec,
+                signature.as_ref(),
+            )
+            .await?
+        }
+    }
+    publish_event(
+        ctx,
+        &source.object,
+        EventType::Normal,
+        "SpecFetched",
+        "FetchSpec",
+        format!(
+            "Stored spec {} ({} bytes) from {}",
+            &sha256_hex(spec.as_bytes())[..12],
+            spec.len(),
+            entry.url
+        ),
+    )
+    .await;
+    ctx.spec_changes.stored(&entry.id, &spec).await;
+    if let Some(diff) = &change {
+        info!("Spec of {} changed: {}", entry.id, diff.summary());
+        publish_event(
+            ctx,
+            &source.object,
+            EventType::Normal,
+            "SpecChanged",
+            "FetchSpec",
+            spec_changes::event_note(diff),
+        )
+        .await;
+    }
+    ctx.publishers
+        .sync(&entry, annotations, &spec, signature.as_ref())
+        .await;
 
-    let entry = ApiInventoryEntry {
-        id: entry_id,
-        name: api_name,
-        namespace: namespace.clone(),
-        service_name: service_name.clone(),
-        url,
-        description,
-        last_updated: Utc::now(),
-        available: true,
+    Ok(Discovery::Stored)
+}
+
+/// The revision to store and publish: `spec`, or the last accepted one if `spec` is blocked
+async fn gate_revision(
+    ctx: &ContextData,
+    gate: &BreakingChangeGate,
+    object: &ObjectReference,
+    entry: &ApiInventoryEntry,
+    annotations: &ApiDocAnnotations,
+    spec: String,
+) -> Result<String, AppError> {
+    if ctx.output == DiscoveryOutput::Crd && !gate.knows(&entry.id).await {
+        let client = ctx.discovery.clone().into_client();
+        if let Some(stored) =
+            discovered_api::stored_spec(client, &entry.namespace, &entry.service_name).await?
+        {
+            gate.seed(&entry.id, stored).await;
+        }
+    }
+
+    match gate
+        .admit(&entry.id, &spec, annotations.allow_breaking)
+        .await
+    {
+        Admission::Accepted => Ok(spec),
+        Admission::Overridden(diff) => {
+            info!(
+                "Publishing {} with breaking changes allowed by annotation: {}",
+                entry.id,
+                diff.summary()
+            );
+            publish_event(
+                ctx,
+                object,
+                EventType::Normal,
+                "BreakingChangeAllowed",
+                "PublishSpec",
+                breaking_gate::event_note(&diff),
+            )
+            .await;
+            Ok(spec)
+        }
+        Admission::Blocked { diff, previous } => {
+            warn!(
+                "Holding back new spec revision of {}: {}",
+                entry.id,
+                diff.summary()
+            );
+            publish_event(
+                ctx,
+                object,
+                EventType::Warning,
+                "BreakingChangeBlocked",
+                "PublishSpec",
+                breaking_gate::event_note(&diff),
+            )
+            .await;
+            Ok(previous)
+        }
+    }
+}
+
+/// Emits an Event on the Service or ApiDoc; failures are only logged
+async fn publish_event(
+    ctx: &ContextData,
+    object: &ObjectReference,
+    type_: EventType,
+    reason: &str,
+    action: &str,
+    note: String,
+) {
+    if ctx.dry_run {
+        info!(
+            "Dry run: would emit {} event on {} {}: {}",
+            reason,
+            object.kind.as_deref().unwrap_or_default(),
+            object.name.as_deref().unwrap_or_default(),
+            note
+        );
+        return;
+    }
+    let event = Event {
+        type_,
+        reason: reason.to_string(),
+        note: Some(note),
+        action: action.to_string(),
+        secondary: None,
     };
+    if let Err(e) = ctx.recorder.publish(&event, object).await {
+        warn!(
+            "Failed to emit {} event for {} {}: {}",
+            reason,
+            object.kind.as_deref().unwrap_or_default(),
+            object.name.as_deref().unwrap_or_default(),
+            e
+        );
+    }
+}
 
-    update_discovery_configmap(ctx, entry).await?;
+/// Fetches the spec with the client certificate and headers from the Secrets named by
+/// `annotations` in the Service's `namespace`; a missing or unusable Secret fails the fetch.
+/// With `proxy_path` the spec is fetched through the API server's service proxy instead of
+/// from `url`, and with `api-doc.io/spec-configmap` it is read from the ConfigMap.
+async fn fetch_service_spec(
+    fetcher: &SpecFetcher,
+    client: &Client,
+    namespace: &str,
+    url: &str,
+    proxy_path: Option<&str>,
+    annotations: &ApiDocAnnotations,
+    auth: Option<FetchAuth>,
+) -> Result<Result<String, FetchFailure>, AppError> {
+    if let Some(source) = &annotations.spec_config_map {
+        let config_maps: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
+        return Ok(config_map_spec(&config_maps, source).await?);
+    }
+    let secrets: Api<Secret> = Api::namespaced(client.clone(), namespace);
+    if let Some(path) = proxy_path {
+        return fetch_proxied_spec(fetcher, client, &secrets, path, annotations, auth.is_some())
+            .await;
+    }
+    let client_fetcher = match &annotations.tls_secret {
+        Some(name) => {
+            let secret = secrets.get_opt(name).await?;
+            match client_certificate_fetcher(fetcher, name, secret.as_ref()) {
+                Ok(fetcher) => Some(fetcher),
+                Err(failure) => return Ok(Err(failure)),
+            }
+        }
+        None => None,
+    };
+    let headers = match &annotations.auth_secret {
+        Some(name) => {
+            let secret = secrets.get_opt(name).await?;
+            match secret_headers(name, secret.as_ref()) {
+                Ok(headers) => headers,
+                Err(failure) => return Ok(Err(failure)),
+            }
+        }
+        None => Vec::new(),
+    };
 
-    info!(
-        "Successfully reconciled service: {}",
-        service_name
-    );
+    let request = FetchRequest {
+        auth,
+        headers,
+        ..Default::default()
+    };
+    let fetcher = client_fetcher.as_ref().unwrap_or(fetcher);
+    Ok(fetch_api_spec(fetcher, url, &request).await)
+}
 
-    Ok(Action::requeue(Duration::from_secs(300)))
+/// Reads the spec from a ConfigMap key of the Service's namespace, as text or UTF-8
+/// `binaryData`; a missing ConfigMap or key fails like an unreachable service
+async fn config_map_spec(
+    config_maps: &Api<ConfigMap>,
+    source: &SpecConfigMapRef,
+) -> Result<Result<String, FetchFailure>, kube::Error> {
+    let failure = |message: String| FetchFailure::new(FailurePhase::Config, message);
+    let Some(config_map) = config_maps.get_opt(&source.name).await? else {
+        return Ok(Err(failure(format!("ConfigMap {} not found", source.name))));
+    };
+    let text = config_map
+        .data
+        .and_then(|mut data| data.remove(&source.key));
+    let binary = config_map
+        .binary_data
+        .and_then(|mut data| data.remove(&source.key))
+        .map(|value| String::from_utf8(value.0));
+    Ok(match (text, binary) {
+        (Some(spec), _) | (None, Some(Ok(spec))) => validated_spec(&spec),
+        (None, Some(Err(_))) => Err(failure(format!(
+            "Key '{}' of ConfigMap {} is not UTF-8",
+            source.key, source.name
+        ))),
+        (None, None) => Err(failure(format!(
+            "ConfigMap {} has no key '{}'",
+            source.name, source.key
+        ))),
+    })
 }
 
-async fn check_api_availability(client: &reqwest::Client, url: &str) -> bool {
-    match client.get(url).send().await {
-        Ok(response) => response.status().is_success(),
-        Err(e) => {
-            warn!("Failed to check API availability for {}: {}", url, e);
-            false
+/// Fetches the spec through the API server's service proxy. The API server presents its own
+/// credentials, so client certificates and `Authorization` headers cannot be sent.
+async fn fetch_proxied_spec(
+    fetcher: &SpecFetcher,
+    client: &Client,
+    secrets: &Api<Secret>,
+    path: &str,
+    annotations: &ApiDocAnnotations,
+    has_auth: bool,
+) -> Result<Result<String, FetchFailure>, AppError> {
+    let unsupported = |what: &str| {
+        Ok(Err(FetchFailure::new(
+            FailurePhase::Config,
+            format!(
+                "{} cannot be sent through the API server's service proxy",
+                what
+            ),
+        )))
+    };
+    if annotations.tls_secret.is_some() {
+        return unsupported("A client certificate");
+    }
+    if has_auth {
+        return unsupported("Authorization credentials");
+    }
+    let headers = match &annotations.auth_secret {
+        Some(name) => {
+            let secret = secrets.get_opt(name).await?;
+            match secret_headers(name, secret.as_ref()) {
+                Ok(headers) => headers,
+                Err(failure) => return Ok(Err(failure)),
+            }
         }
+        None => Vec::new(),
+    };
+    if headers
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case("authorization"))
+    {
+        return unsupported("An Authorization header");
     }
+
+    Ok(
+        apiserver_proxy::fetch(client.clone(), path, &headers, fetcher.config().max_body_bytes)
+            .await
+            .and_then(|body| validated_spec(&body)),
+    )
 }
 
-async fn remove_entry_from_discovery_configmap(
-    ctx: Arc<ContextData>,
+/// A fetcher like `base` that presents the client certificate of a `kubernetes.io/tls`
+/// Secret; its `ca.crt`, as written by cert-manager, is trusted as well
+fn client_certificate_fetcher(
+    base: &SpecFetcher,
+    secret_name: &str,
+    secret: Option<&Secret>,
+) -> Result<SpecFetcher, FetchFailure> {
+    let failure = |message: String| FetchFailure::new(FailurePhase::Config, message);
+    let secret = secret.ok_or_else(|| failure(format!("TLS Secret {} not found", secret_name)))?;
+    let key = |name: &str| {
+        secret
+            .data
+            .as_ref()
+            .and_then(|data| data.get(name))
+            .map(|value| value.0.clone())
+    };
+    let missing = |name: &str| failure(format!("TLS Secret {} has no '{}' key", secret_name, name));
+    let mut tls = base.config().tls.clone();
+    tls.client_identity = Some(ClientIdentity {
+        certificate: key("tls.crt").ok_or_else(|| missing("tls.crt"))?,
+        private_key: key("tls.key").ok_or_else(|| missing("tls.key"))?,
+    });
+    tls.ca_certificates.extend(key("ca.crt"));
+    base.with_tls(tls)
+        .map_err(|e| failure(format!("TLS Secret {}: {}", secret_name, e)))
+}
+
+/// Request headers from the keys of a Secret, e.g. `Authorization`; trailing newlines of the
+/// values, as left by `kubectl create secret --from-file`, are dropped
+fn secret_headers(
+    secret_name: &str,
+    secret: Option<&Secret>,
+) -> Result<Vec<(String, String)>, FetchFailure> {
+    let failure = |message: String| FetchFailure::new(FailurePhase::Config, message);
+    let secret = secret.ok_or_else(|| failure(format!("Secret {} not found", secret_name)))?;
+    let data = secret.data.clone().unwrap_or_default();
+    if data.is_empty() {
+        return Err(failure(format!("Secret {} has no keys", secret_name)));
+    }
+    data.into_iter()
+        .map(|(key, value)| match String::from_utf8(value.0) {
+            Ok(value) => Ok((key, value.trim_end_matches(['\r', '\n']).to_string())),
+            Err(_) => Err(failure(format!(
+                "Secret {} has a non-UTF-8 value for header '{}'",
+                secret_name, key
+            ))),
+        })
+        .collect()
+}
+
+/// Fetches, validates and normalizes the spec, doubling as the availability check
+async fn fetch_api_spec(
+    fetcher: &SpecFetcher,
+    url: &str,
+    request: &FetchRequest,
+) -> Result<String, FetchFailure> {
+    match fetcher.fetch(url, request).await {
+        Ok(FetchOutcome::Fetched(spec)) => validated_spec(&spec.body),
+        // No validators are sent, so a 304 is not expected; treat the API as reachable
+        Ok(FetchOutcome::NotModified) => Ok(String::new()),
+        Err(e) => Err(e.failure()),
+    }
+}
+
+/// The validated and normalized spec; Swagger 2.0 specs are stored and published as
+/// OpenAPI 3.0
+fn validated_spec(body: &str) -> Result<String, FetchFailure> {
+    spec_utils::validate_spec(body)
+        .and_then(|_| spec_utils::normalize_spec(body))
+        .map_err(|e| FetchFailure::parse(body, e))
+}
+
+/// Marks the service's entry as stale by schedule; `false` if it is not in the catalog yet
+async fn mark_stale_by_schedule(
+    ctx: &Arc<ContextData>,
     namespace: &str,
     service_name: &str,
-) -> Result<(), AppError> {
-    let configmap_name = &ctx.discovery_configmap;
-    let configmap_namespace = &ctx.discovery_namespace;
+) -> Result<bool, AppError> {
+    if ctx.output == DiscoveryOutput::Crd {
+        let client = ctx.discovery.clone().into_client();
+        return discovered_api::mark_stale_by_schedule(
+            client,
+            namespace,
+            service_name,
+            ctx.dry_run,
+        )
+        .await;
+    }
+
+    let Some(existing) = sharding::read_catalog(ctx).await? else {
+        return Ok(false);
+    };
     let key = entry_key!(namespace, service_name);
+    let Some(mut entry) = existing
+        .apis
+        .into_iter()
+        .find(|api| {
+            api.cluster.is_none() && entry_key!(&api.namespace, &api.service_name) == key
+        })
+    else {
+        return Ok(false);
+    };
+    if ctx.dry_run {
+        info!(
+            "Dry run: would mark the entry of {}/{} stale by schedule",
+            namespace, service_name
+        );
+        return Ok(true);
+    }
+    if !entry.stale_by_schedule {
+        entry.stale_by_schedule = true;
+        update_discovery_configmap(ctx.clone(), entry).await?;
+    }
+    Ok(true)
+}
 
-    let discovery_api: Api<ConfigMap> =
-        Api::namespaced(ctx.discovery.clone().into_client(), configmap_namespace);
+/// Marks the service's entry unavailable, with the error and one more failure in a row. A
+/// stored entry keeps its spec; a service whose spec was never fetched is added as
+/// `placeholder`, so the catalog shows why it has no spec.
+async fn mark_unavailable(
+    ctx: &Arc<ContextData>,
+    source: &EntrySource,
+    placeholder: ApiInventoryEntry,
+    error: &str,
+) -> Result<(), AppError> {
+    if ctx.dry_run {
+        info!(
+            "Dry run: would mark the entry of {}/{} unavailable",
+            placeholder.namespace, placeholder.service_name
+        );
+        return Ok(());
+    }
+    if ctx.output == DiscoveryOutput::Crd {
+        let client = ctx.discovery.clone().into_client();
+        return discovered_api::mark_unavailable(client, source.owner.clone(), &placeholder, error)
+            .await;
+    }
 
-    let existing_configmap = match discovery_api.get_opt(configmap_name).await {
-        Ok(Some(cm)) => cm,
-        Ok(None) | Err(_) => return Ok(()),
-    };
+    let key = placeholder.id.clone();
+    let stored = sharding::read_catalog(ctx).await?.and_then(|existing| {
+        existing.apis.into_iter().find(|api| {
+            api.cluster.is_none() && entry_key!(&api.namespace, &api.service_name) == key
+        })
+    });
+    update_discovery_configmap(ctx.clone(), unavailable_entry(stored, placeholder, error)).await
+}
 
-    let apis = existing_configmap
-        .data
-        .as_ref()
-        .and_then(|d| d.get("discovery.json"))
-        .and_then(|j| serde_json::from_str::<DiscoveryConfig>(j).ok())
-        .map(|c| c.apis)
-        .unwrap_or_default();
+/// The stored entry, or `placeholder` if there is none, marked unavailable because of `error`
+fn unavailable_entry(
+    stored: Option<ApiInventoryEntry>,
+    placeholder: ApiInventoryEntry,
+    error: &str,
+) -> ApiInventoryEntry {
+    let mut entry = stored.unwrap_or(ApiInventoryEntry {
+        consecutive_failures: 0,
+        ..placeholder
+    });
+    entry.available = false;
+    entry.last_error = Some(error.to_string());
+    entry.consecutive_failures += 1;
+    entry
+}
 
-    let apis: Vec<ApiInventoryEntry> = apis
-        .into_iter()
-        .filter(|api| entry_key!(&api.namespace, &api.service_name) != key)
-        .collect();
+/// Removes the service's entry from the catalog; `false` if it had none
+async fn remove_entry_from_discovery_configmap(
+    ctx: Arc<ContextData>,
+    namespace: &str,
+    service_name: &str,
+    reason: RemovalReason,
+) -> Result<bool, AppError> {
+    if ctx.dry_run {
+        info!(
+            "Dry run: would remove service {}/{} from discovery ({:?})",
+            namespace, service_name, reason
+        );
+        return Ok(false);
+    }
+    let key = entry_key!(namespace, service_name);
+    ctx.publishers.remove(&key).await;
+    if let Some(checker) = &ctx.contract_checker {
+        checker.forget(&key).await;
+    }
+    // An outage must not reset the baseline a breaking revision is compared with
+    if let Some(gate) = &ctx.breaking_gate
+        && reason != RemovalReason::Unreachable
+    {
+        gate.forget(&key).await;
+    }
+    if reason != RemovalReason::Unreachable {
+        ctx.spec_changes.forget(&key).await;
+    }
+    // Failing services keep their series, so the failures stay visible
+    if !matches!(
+        reason,
+        RemovalReason::Unreachable | RemovalReason::InvalidSpec | RemovalReason::SpecTooLarge
+    ) {
+        ctx.fetch_metrics.forget(namespace, service_name);
+    }
 
-    let discovery_config = DiscoveryConfig {
-        apis,
-        last_updated: Utc::now(),
-    };
-    let discovery_json = serde_json::to_string_pretty(&discovery_config)
-        .map_err(|e| AppError::Serde(e))?;
+    if ctx.output == DiscoveryOutput::Crd {
+        let client = ctx.discovery.clone().into_client();
+        return discovered_api::delete(client, namespace, service_name).await;
+    }
 
-    let configmap = ConfigMap {
-        metadata: kube::core::ObjectMeta {
-            name: Some(configmap_name.to_string()),
-            namespace: Some(configmap_namespace.to_string()),
-            labels: Some(BTreeMap::from([
-                ("app.kubernetes.io/name".to_string(), "openapi-discovery".to_string()),
-                ("app.kubernetes.io/component".to_string(), "discovery".to_string()),
-            ])),
-            ..Default::default()
-        },
-        data: Some(BTreeMap::from([(
-            "discovery.json".to_string(),
-            discovery_json,
-        )])),
-        ..Default::default()
+    let existing = match sharding::read_catalog(&ctx).await {
+        Ok(Some(config)) => config,
+        Ok(None) | Err(_) => return Ok(false),
     };
 
-    let patch_params = PatchParams::apply("openapi-k8s-operator");
-    discovery_api
-        .patch(configmap_name, &patch_params, &Patch::Apply(configmap))
-        .await
-        .map_err(AppError::Kube)?;
+    let (removed, apis): (Vec<ApiInventoryEntry>, Vec<ApiInventoryEntry>) = existing
+        .apis
+        .into_iter()
+        .partition(|api| {
+            api.cluster.is_none() && entry_key!(&api.namespace, &api.service_name) == key
+        });
+    if removed.is_empty() {
+        // Nothing to remove, avoid rewriting the ConfigMap
+        return Ok(false);
+    }
+
+    if ctx.layout == DiscoveryLayout::Entries {
+        entry_layout::remove_entries(&ctx, &removed, reason).await?;
+    } else {
+        let mut discovery_config = DiscoveryConfig {
+            apis,
+            last_updated: Utc::now(),
+            tombstones: existing.tombstones,
+            shards: existing.shards,
+        };
+        if ctx.tombstone_grace > chrono::Duration::zero() {
+            discovery_config.tombstones.extend(
+                removed
+                    .iter()
+                    .map(|entry| ApiTombstone::for_entry(entry, reason)),
+            );
+        }
+        discovery_config.prune_tombstones(ctx.tombstone_grace);
+        sharding::write_catalog(&ctx, discovery_config).await?;
+    }
+    if let Some(store) = &ctx.spec_store {
+        for entry in &removed {
+            if let Err(e) = store.delete(entry).await {
+                warn!("Failed to delete the stored spec of {}: {}", entry.id, e);
+            }
+        }
+    }
     info!(
-        "Removed service {}/{} from discovery ConfigMap",
-        namespace, service_name
+        "Removed service {}/{} from discovery ConfigMap ({:?})",
+        namespace, service_name, reason
     );
+    Ok(true)
+}
+
+/// Removes the service's entry and, if it had one, tells why in an Event on `object`
+async fn remove_entry_with_event(
+    ctx: &Arc<ContextData>,
+    object: &ObjectReference,
+    namespace: &str,
+    service_name: &str,
+    reason: RemovalReason,
+) -> Result<(), AppError> {
+    if remove_entry_from_discovery_configmap(ctx.clone(), namespace, service_name, reason).await? {
+        let type_ = match reason {
+            RemovalReason::Unreachable
+            | RemovalReason::PortNotFound
+            | RemovalReason::InvalidSpec
+            | RemovalReason::SpecTooLarge => EventType::Warning,
+            _ => EventType::Normal,
+        };
+        publish_event(
+            ctx,
+            object,
+            type_,
+            "EntryRemoved",
+            "RemoveEntry",
+            format!("Removed from the API catalog: {}", reason.description()),
+        )
+        .await;
+    }
     Ok(())
 }
 
@@ -363,12 +1791,8 @@ async fn update_discovery_configmap(ctx: Arc<ContextData>, entry: ApiInventoryEn
     let configmap_namespace = &ctx.discovery_namespace;
 
     for attempt in 1..=MAX_RETRIES {
-        let discovery_api: Api<ConfigMap> =
-            Api::namespaced(ctx.discovery.clone().into_client(), configmap_namespace);
-
-        let existing_configmap = match discovery_api.get_opt(configmap_name).await {
-            Ok(Some(configmap)) => Some(configmap),
-            Ok(None) => None,
+        let existing = match sharding::read_catalog(&ctx).await {
+            Ok(existing) => existing,
             Err(e) => {
                 error!("Failed to get ConfigMap '{}' in namespace '{}' (attempt {}/{}): {}", 
                        configmap_name, configmap_namespace, attempt, MAX_RETRIES, e);
@@ -381,26 +1805,43 @@ async fn update_discovery_configmap(ctx: Arc<ContextData>, entry: ApiInventoryEn
                 continue;
             }
         };
-        let apis = if let Some(configmap) = &existing_configmap {
-            if let Some(data) = configmap.data.as_ref() {
-                if let Some(discovery_json) = data.get("discovery.json") {
-                    serde_json::from_str::<DiscoveryConfig>(discovery_json)
-                        .map(|config| config.apis)
-                        .unwrap_or_default()
-                } else {
-                    Vec::new()
-                }
-            } else {
-                Vec::new()
-            }
-        } else {
-            Vec::new()
-        };
+        let (apis, tombstones, shards) = existing
+            .map(|config| (config.apis, config.tombstones, config.shards))
+            .unwrap_or_default();
+
+        // A restart forgets the stored revisions, not what the last change was
+        let key = entry_key!(&entry.namespace, &entry.service_name);
+        let mut entry = entry.clone();
+        if entry.last_change_summary.is_none() {
+            entry.last_change_summary = apis
+                .iter()
+                .find(|api| api.id == key)
+                .and_then(|api| api.last_change_summary.clone());
+        }
+
+        // Rewriting an unchanged entry would only bump last_updated, and with it the
+        // ConfigMap's resourceVersion and every watch on it
+        if apis
+            .iter()
+            .any(|api| api.id == key && api.same_content(&entry))
+        {
+            debug!("Entry {} is unchanged, not updating the ConfigMap", key);
+            return Ok(());
+        }
+
+        // Only the entry's own key is written, so there is nothing to merge or retry
+        if ctx.layout == DiscoveryLayout::Entries {
+            return entry_layout::put_entry(&ctx, entry).await;
+        }
 
         // Deduplicate APIs and keep most recent entries
         let mut unique_apis: std::collections::HashMap<String, ApiInventoryEntry> = std::collections::HashMap::new();
         for api in apis {
-            let key = entry_key!(&api.namespace, &api.service_name);
+            // Entries of other clusters may share the namespace and name of a local one
+            let key = match &api.cluster {
+                Some(_) => api.id.clone(),
+                None => entry_key!(&api.namespace, &api.service_name),
+            };
             if let Some(existing) = unique_apis.get(&key) {
                 if api.last_updated > existing.last_updated {
                     unique_apis.insert(key, api);
@@ -410,62 +1851,37 @@ async fn update_discovery_configmap(ctx: Arc<ContextData>, entry: ApiInventoryEn
             }
         }
 
-        let key = entry_key!(&entry.namespace, &entry.service_name);
         unique_apis.insert(key, entry.clone());
         let apis: Vec<ApiInventoryEntry> = unique_apis.into_values().collect();
+        let api_count = apis.len();
 
-        let discovery_config = DiscoveryConfig {
+        let mut discovery_config = DiscoveryConfig {
             apis,
             last_updated: Utc::now(),
+            tombstones,
+            shards,
         };
+        discovery_config.prune_tombstones(ctx.tombstone_grace);
 
-        let discovery_json = serde_json::to_string_pretty(&discovery_config).map_err(|e| {
-            error!("Failed to serialize discovery config to JSON: {}", e);
-            AppError::Serde(e)
-        })?;
-        
-        info!("Serialized discovery config with {} APIs (attempt {}/{})", 
-              discovery_config.apis.len(), attempt, MAX_RETRIES);
-
-        let configmap = ConfigMap {
-            metadata: kube::core::ObjectMeta {
-                name: Some(configmap_name.to_string()),
-                namespace: Some(configmap_namespace.to_string()),
-                labels: Some(BTreeMap::from([
-                    (
-                        "app.kubernetes.io/name".to_string(),
-                        "openapi-discovery".to_string(),
-                    ),
-                    (
-                        "app.kubernetes.io/component".to_string(),
-                        "discovery".to_string(),
-                    ),
-                ])),
-                ..Default::default()
-            },
-            data: Some(BTreeMap::from([
-                ("discovery.json".to_string(), discovery_json),
-            ])),
-            ..Default::default()
-        };
+        info!("Writing discovery config with {} APIs (attempt {}/{})", 
+              api_count, attempt, MAX_RETRIES);
 
-        let patch_params = PatchParams::apply("openapi-k8s-operator");
-        match discovery_api.patch(configmap_name, &patch_params, &Patch::Apply(configmap)).await {
-            Ok(_) => {
+        match sharding::write_catalog(&ctx, discovery_config).await {
+            Ok(()) => {
                 info!("Successfully updated ConfigMap '{}' in namespace '{}' with {} unique APIs", 
-                      configmap_name, configmap_namespace, discovery_config.apis.len());
+                      configmap_name, configmap_namespace, api_count);
                 return Ok(());
             }
             Err(e) => {
                 // Handle 409 conflicts with exponential backoff
-                if let kube::Error::Api(kube::core::ErrorResponse { code: 409, .. }) = e {
+                if let AppError::Kube(kube::Error::Api(kube::core::ErrorResponse { code: 409, .. })) = e {
                     warn!("ConfigMap conflict detected for '{}' in namespace '{}' (attempt {}/{}): {}", 
                           configmap_name, configmap_namespace, attempt, MAX_RETRIES, e);
                     
                     if attempt == MAX_RETRIES {
                         error!("Max retries reached for ConfigMap '{}' in namespace '{}': {}", 
                                configmap_name, configmap_namespace, e);
-                        return Err(AppError::Kube(e));
+                        return Err(e);
                     }
                     
                     let delay = Duration::from_millis(BASE_DELAY_MS * 2_u64.pow(attempt - 1));
@@ -475,7 +1891,7 @@ async fn update_discovery_configmap(ctx: Arc<ContextData>, entry: ApiInventoryEn
                 } else {
                     error!("Failed to update ConfigMap '{}' in namespace '{}': {}", 
                            configmap_name, configmap_namespace, e);
-                    return Err(AppError::Kube(e));
+                    return Err(e);
                 }
             }
         }
@@ -491,75 +1907,192 @@ async fn update_discovery_configmap(ctx: Arc<ContextData>, entry: ApiInventoryEn
     })))
 }
 
-async fn initialize_discovery_configmap(ctx: &ContextData) -> Result<(), AppError> {
-    let configmap_name = &ctx.discovery_configmap;
-    let configmap_namespace = &ctx.discovery_namespace;
+/// Document stored in the discovery ConfigMap, whichever encoding wrote it, with the
+/// entries stored under their own keys. The configured encoding's key is preferred over
+/// leftovers from a previous encoding.
+fn read_discovery_config(configmap: &ConfigMap, preferred: DiscoveryEncoding) -> DiscoveryConfig {
+    let mut config = decode_discovery_config(preferred, |candidate| {
+        if candidate.is_binary() {
+            configmap
+                .binary_data
+                .as_ref()
+                .and_then(|d| d.get(candidate.key()))
+                .map(|b| b.0.as_slice())
+        } else {
+            configmap
+                .data
+                .as_ref()
+                .and_then(|d| d.get(candidate.key()))
+                .map(|s| s.as_bytes())
+        }
+    });
+    let data = configmap
+        .data
+        .iter()
+        .flatten()
+        .map(|(key, value)| (key.as_str(), value.as_bytes()));
+    let binary_data = configmap
+        .binary_data
+        .iter()
+        .flatten()
+        .map(|(key, value)| (key.as_str(), value.0.as_slice()));
+    add_entry_payloads(&mut config, data.chain(binary_data));
+    config
+}
 
-    let discovery_api: Api<ConfigMap> =
-        Api::namespaced(ctx.discovery.clone().into_client(), configmap_namespace);
+/// Adds the entries stored under their own keys (`DISCOVERY_LAYOUT=entries`) to `config`,
+/// replacing a copy left in the document while the layout changes
+fn add_entry_payloads<'a>(
+    config: &mut DiscoveryConfig,
+    payloads: impl Iterator<Item = (&'a str, &'a [u8])>,
+) {
+    for (key, bytes) in payloads.filter(|(key, _)| encoding::is_entry_key(key)) {
+        match encoding::decode::<ApiInventoryEntry>(bytes) {
+            Ok(entry) => {
+                config.apis.retain(|api| api.id != entry.id);
+                config.last_updated = config.last_updated.max(entry.last_updated);
+                config.apis.push(entry);
+            }
+            Err(e) => warn!("Ignoring undecodable entry '{}' in discovery payload: {}", key, e),
+        }
+    }
+}
+
+/// First decodable payload returned by `payload` for the preferred encoding, then the others
+fn decode_discovery_config<'a>(
+    preferred: DiscoveryEncoding,
+    payload: impl Fn(DiscoveryEncoding) -> Option<&'a [u8]>,
+) -> DiscoveryConfig {
+    let candidates = std::iter::once(preferred)
+        .chain(DiscoveryEncoding::ALL.into_iter().filter(|e| *e != preferred));
+    for candidate in candidates {
+        if let Some(bytes) = payload(candidate) {
+            match encoding::decode::<DiscoveryConfig>(bytes) {
+                Ok(config) => return config,
+                Err(e) => warn!(
+                    "Ignoring undecodable '{}' in discovery payload: {}",
+                    candidate.key(),
+                    e
+                ),
+            }
+        }
+    }
+    DiscoveryConfig {
+        apis: Vec::new(),
+        last_updated: Utc::now(),
+        tombstones: Vec::new(),
+        shards: Vec::new(),
+    }
+}
+
+/// Labels of the discovery ConfigMap or Secret and its shards
+fn discovery_labels() -> BTreeMap<String, String> {
+    BTreeMap::from([
+        (
+            "app.kubernetes.io/name".to_string(),
+            "openapi-discovery".to_string(),
+        ),
+        (
+            "app.kubernetes.io/component".to_string(),
+            "discovery".to_string(),
+        ),
+        (
+            "app.kubernetes.io/managed-by".to_string(),
+            FIELD_MANAGER.to_string(),
+        ),
+    ])
+}
 
-    // Check if ConfigMap already exists
-    match discovery_api.get_opt(configmap_name).await {
-        Ok(Some(_)) => {
-            info!("Discovery ConfigMap '{}' already exists in namespace '{}'", configmap_name, configmap_namespace);
+/// Discovery ConfigMap holding `config` under the key of `encoding`
+fn discovery_configmap(
+    name: &str,
+    namespace: &str,
+    encoding: DiscoveryEncoding,
+    config: &DiscoveryConfig,
+) -> Result<ConfigMap, AppError> {
+    let payload = encoding.encode(config)?;
+    let (data, binary_data) = if encoding.is_binary() {
+        let binary = BTreeMap::from([(encoding.key().to_string(), ByteString(payload))]);
+        (None, Some(binary))
+    } else {
+        // JSON encoding always produces UTF-8
+        let json = String::from_utf8(payload).unwrap_or_default();
+        (Some(BTreeMap::from([(encoding.key().to_string(), json)])), None)
+    };
+
+    Ok(ConfigMap {
+        metadata: kube::core::ObjectMeta {
+            name: Some(name.to_string()),
+            namespace: Some(namespace.to_string()),
+            labels: Some(discovery_labels()),
+            ..Default::default()
+        },
+        data,
+        binary_data,
+        ..Default::default()
+    })
+}
+
+async fn initialize_discovery_configmap(ctx: &ContextData) -> Result<(), AppError> {
+    let name = &ctx.discovery_configmap;
+    let namespace = &ctx.discovery_namespace;
+    let kind = ctx.storage.kind();
+
+    // Check if the ConfigMap (or Secret) already exists
+    match sharding::read_catalog(ctx).await {
+        Ok(Some(config)) => {
+            info!("Discovery {} '{}' already exists in namespace '{}'", kind, name, namespace);
+            if !ctx.dry_run {
+                entry_layout::migrate(ctx, &config).await?;
+            }
+            *ctx.written_catalog.lock().await = Some(config);
+            return Ok(());
+        }
+        Ok(None) if ctx.dry_run => {
+            info!("Dry run: discovery {} '{}' does not exist and is not created", kind, name);
             return Ok(());
         }
         Ok(None) => {
-            info!("Discovery ConfigMap '{}' does not exist, creating it", configmap_name);
+            info!("Discovery {} '{}' does not exist, creating it", kind, name);
         }
         Err(e) => {
-            error!("Failed to check if ConfigMap '{}' exists in namespace '{}': {}", configmap_name, configmap_namespace, e);
+            error!("Failed to check if {} '{}' exists in namespace '{}': {}", kind, name, namespace, e);
             return Err(AppError::Kube(e));
         }
     }
 
+    // A catalog left behind by ConfigMap storage stays readable until it is deleted
+    if ctx.storage == DiscoveryStorage::Secret
+        && let Ok(Some(_)) = ctx.discovery.get_opt(name).await
+    {
+        warn!(
+            "Discovery ConfigMap '{}' from before DISCOVERY_STORAGE=secret still exists; delete it to hide its entries",
+            name
+        );
+    }
+
     // Create empty discovery config
     let discovery_config = DiscoveryConfig {
         apis: Vec::new(),
         last_updated: Utc::now(),
+        tombstones: Vec::new(),
+        shards: Vec::new(),
     };
 
-    let discovery_json = serde_json::to_string_pretty(&discovery_config).map_err(|e| {
-        error!("Failed to serialize initial discovery config to JSON: {}", e);
-        AppError::Serde(e)
-    })?;
-
-    let configmap = ConfigMap {
-        metadata: kube::core::ObjectMeta {
-            name: Some(configmap_name.to_string()),
-            namespace: Some(configmap_namespace.to_string()),
-            labels: Some(BTreeMap::from([
-                (
-                    "app.kubernetes.io/name".to_string(),
-                    "openapi-discovery".to_string(),
-                ),
-                (
-                    "app.kubernetes.io/component".to_string(),
-                    "discovery".to_string(),
-                ),
-            ])),
-            ..Default::default()
-        },
-        data: Some(BTreeMap::from([
-            ("discovery.json".to_string(), discovery_json),
-        ])),
-        ..Default::default()
-    };
-
-    // Create the ConfigMap
-    match discovery_api.create(&Default::default(), &configmap).await {
-        Ok(_) => {
-            info!("Successfully created initial discovery ConfigMap '{}' in namespace '{}'", configmap_name, configmap_namespace);
+    match sharding::write_catalog(ctx, discovery_config).await {
+        Ok(()) => {
+            info!("Successfully created initial discovery {} '{}' in namespace '{}'", kind, name, namespace);
         }
         Err(e) => {
-            error!("Failed to create discovery ConfigMap '{}' in namespace '{}': {}", configmap_name, configmap_namespace, e);
-            return Err(AppError::Kube(e));
+            error!("Failed to create discovery {} '{}' in namespace '{}': {}", kind, name, namespace, e);
+            return Err(e);
         }
     }
 
     Ok(())
 }
 
+#[allow(clippy::collapsible_if, clippy::collapsible_match)]
 fn error_policy(
     service: Arc<Service>,
     err: &AppError,
@@ -580,12 +2113,18 @@ fn error_policy(
                 let name_clone = name.clone();
                 tokio::spawn(async move {
                     if let Err(e) =
-                        remove_entry_from_discovery_configmap(ctx_clone, &ns, &name_clone).await
+                        remove_entry_from_discovery_configmap(
+                            ctx_clone,
+                            &ns,
+                            &name_clone,
+                            RemovalReason::ServiceDeleted,
+                        )
+                        .await
                     {
                         error!("Failed to remove deleted service from discovery: {}", e);
                     }
                 });
-                return Action::requeue(Duration::from_secs(300));
+                return Action::requeue(ctx.reconcile_interval);
             }
         }
     }
@@ -596,7 +2135,7 @@ fn error_policy(
         err
     );
 
-    let requeue_delay = Duration::from_secs(30);
+    let requeue_delay = ctx.error_retry_interval;
     warn!("Requeuing service {} in {:?}", name, requeue_delay);
     Action::requeue(requeue_delay)
 }
@@ -604,29 +2143,23 @@ fn error_policy(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chrono::Utc;
 
     fn make_entry(namespace: &str, service_name: &str) -> ApiInventoryEntry {
-        let id = entry_key!(namespace, service_name);
-        ApiInventoryEntry {
-            id: id.clone(),
-            name: format!("{} API", service_name),
-            namespace: namespace.to_string(),
-            service_name: service_name.to_string(),
-            url: format!(
+        ApiInventoryEntry::new(
+            namespace,
+            service_name,
+            format!(
                 "http://{}.{}.svc.cluster.local:8080/swagger/openapi.yml",
                 service_name, namespace
             ),
-            description: None,
-            last_updated: Utc::now(),
-            available: true,
-        }
+        )
     }
 
     #[test]
     fn entry_key_format() {
-        assert_eq!(entry_key!("eng-main", "my-svc"), "eng-main-my-svc");
-        assert_eq!(entry_key!("default", "kubernetes"), "default-kubernetes");
+        assert_eq!(entry_key!("eng-main", "my-svc"), "eng-main.my-svc");
+        assert_eq!(entry_key!("default", "kubernetes"), "default.kubernetes");
+        assert_ne!(entry_key!("a-b", "c"), entry_key!("a", "b-c"));
     }
 
     #[test]
@@ -655,4 +2188,78 @@ mod tests {
             .collect();
         assert_eq!(filtered.len(), 0);
     }
+
+    #[test]
+    fn chart_crd_matches_generated_manifest() {
+        let chart: serde_json::Value = serde_yaml::from_str(include_str!(
+            "../../../helm/openapi-k8s-operator/crds/discoveredapis.yaml"
+        ))
+        .unwrap();
+        let generated = serde_json::to_value(openapi_common::crd::crd()).unwrap();
+        assert_eq!(
+            chart, generated,
+            "regenerate with `openapi-k8s-operator crd > helm/openapi-k8s-operator/crds/discoveredapis.yaml`"
+        );
+
+        let chart: serde_json::Value = serde_yaml::from_str(include_str!(
+            "../../../helm/openapi-k8s-operator/crds/apidocs.yaml"
+        ))
+        .unwrap();
+        let generated = serde_json::to_value(openapi_common::api_doc::crd()).unwrap();
+        assert_eq!(
+            chart, generated,
+            "regenerate with `openapi-k8s-operator crd apidocs > helm/openapi-k8s-operator/crds/apidocs.yaml`"
+        );
+    }
+
+    #[test]
+    fn secret_keys_become_headers() {
+        let secret = Secret {
+            data: Some(BTreeMap::from([
+                (
+                    "Authorization".to_string(),
+                    ByteString(b"Bearer s3cr3t\n".to_vec()),
+                ),
+                ("X-Api-Key".to_string(), ByteString(b"abc".to_vec())),
+            ])),
+            ..Default::default()
+        };
+        assert_eq!(
+            secret_headers("orders-auth", Some(&secret)).unwrap(),
+            vec![
+                ("Authorization".to_string(), "Bearer s3cr3t".to_string()),
+                ("X-Api-Key".to_string(), "abc".to_string()),
+            ]
+        );
+        let missing = secret_headers("orders-auth", None).unwrap_err();
+        assert_eq!(missing.phase, FailurePhase::Config);
+        assert!(secret_headers("orders-auth", Some(&Secret::default())).is_err());
+    }
+
+    #[test]
+    fn unavailable_services_keep_their_entry_or_get_a_placeholder() {
+        let placeholder = ApiInventoryEntry {
+            consecutive_failures: 3,
+            ..make_entry("shop", "orders")
+        };
+
+        let added = unavailable_entry(None, placeholder.clone(), "connection refused");
+        assert_eq!(added.id, "shop.orders");
+        assert!(!added.available);
+        assert_eq!(added.last_error.as_deref(), Some("connection refused"));
+        assert_eq!(added.consecutive_failures, 1);
+
+        let stored = ApiInventoryEntry {
+            name: "Orders".to_string(),
+            spec_sha256: Some("ab12".to_string()),
+            consecutive_failures: 1,
+            ..make_entry("shop", "orders")
+        };
+        let kept = unavailable_entry(Some(stored), placeholder, "timed out");
+        assert_eq!(kept.name, "Orders");
+        assert_eq!(kept.spec_sha256.as_deref(), Some("ab12"));
+        assert!(!kept.available);
+        assert_eq!(kept.last_error.as_deref(), Some("timed out"));
+        assert_eq!(kept.consecutive_failures, 2);
+    }
 }
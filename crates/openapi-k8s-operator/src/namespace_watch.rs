@@ -0,0 +1,371 @@
+//! Service watchers that follow the set of watched namespaces at runtime.
+//!
+//! With glob patterns in `WATCH_NAMESPACES` or a `WATCH_NAMESPACE_SELECTOR`, the namespaces
+//! to discover are not known at startup. Namespaces are watched instead: every namespace
+//! that is created (or starts matching) gets its own Service watcher, and when it is
+//! deleted (or stops matching) its watcher is stopped and its services leave the catalog.
+//! All watchers feed one store, which the controller reconciles from, so Services are
+//! never listed outside the watched namespaces and no restart is needed.
+//!
+//! A fixed list of namespaces in `WATCH_NAMESPACES` uses the same watchers without the
+//! Namespace watch (see [`fixed`]), so a Role in each namespace is all the RBAC it needs.
+//!
+//! Like the controller's own watcher, these only trigger a reconcile when the
+//! [`discovery_hash`] of a Service changes.
+
+use futures::{Stream, StreamExt};
+use k8s_openapi::api::core::v1::{Namespace, Service};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::{
+    Api, Client, ResourceExt,
+    core::{Selector, SelectorExt},
+    runtime::{
+        WatchStreamExt,
+        reflector::{ObjectRef, Store, store::Writer},
+        watcher::{self, Config},
+    },
+};
+use openapi_common::{RemovalReason, annotations::API_DOC_ANNOTATION_PREFIX, namespace_utils};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Arc;
+use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::{ContextData, remove_entry_from_discovery_configmap};
+
+/// Trigger stream for `Controller::for_stream`
+pub type ServiceTrigger =
+    futures::channel::mpsc::UnboundedReceiver<Result<Service, watcher::Error>>;
+
+/// Event of a namespace's Service watcher, tagged with the watcher generation
+type ServiceEvent = (String, u64, watcher::Event<Service>);
+
+struct NamespaceWatch {
+    /// Tells events of this watcher from those of an earlier one in the same namespace
+    generation: u64,
+    task: JoinHandle<()>,
+    /// Names of the namespace's services in the shared store, with their discovery hash
+    services: BTreeMap<String, u64>,
+    /// Services seen while the watcher (re)lists the namespace
+    listing: Option<BTreeSet<String>>,
+}
+
+struct ServiceWatchers {
+    client: Client,
+    ctx: Arc<ContextData>,
+    /// Every namespace of the cluster, to tell deleted namespaces from unmatched ones;
+    /// `None` for a fixed list of namespaces, which are taken to exist
+    namespaces: Option<Store<Namespace>>,
+    writer: Writer<Service>,
+    trigger: futures::channel::mpsc::UnboundedSender<Result<Service, watcher::Error>>,
+    events: UnboundedSender<ServiceEvent>,
+    running: HashMap<String, NamespaceWatch>,
+    next_generation: u64,
+}
+
+/// Follows `namespace_events` and runs a Service watcher per watched namespace. Services
+/// are written to `writer`, whose store the controller must be created with.
+pub fn spawn(
+    ctx: Arc<ContextData>,
+    namespaces: Option<Store<Namespace>>,
+    namespace_events: impl Stream<Item = Result<Namespace, watcher::Error>> + Send + 'static,
+    mut writer: Writer<Service>,
+) -> ServiceTrigger {
+    // The store is filled one namespace at a time and never relisted as a whole, so it
+    // is ready (for the controller) from the start
+    writer.apply_watcher_event(&watcher::Event::InitDone);
+
+    let (trigger, trigger_rx) = futures::channel::mpsc::unbounded();
+    let (events, mut events_rx) = unbounded_channel();
+    let mut watchers = ServiceWatchers {
+        client: ctx.discovery.clone().into_client(),
+        ctx,
+        namespaces,
+        writer,
+        trigger,
+        events,
+        running: HashMap::new(),
+        next_generation: 0,
+    };
+
+    tokio::spawn(async move {
+        let mut namespace_events = std::pin::pin!(namespace_events);
+        loop {
+            tokio::select! {
+                event = namespace_events.next() => match event {
+                    Some(Ok(namespace)) => watchers.sync(&namespace.name_any()).await,
+                    Some(Err(e)) => warn!("Namespace watcher error: {}", e),
+                    None => break,
+                },
+                Some((namespace, generation, event)) = events_rx.recv() => {
+                    watchers.apply(&namespace, generation, event);
+                }
+            }
+        }
+    });
+    trigger_rx
+}
+
+/// Namespace events naming each of `namespaces` once, for [`spawn`] without watching
+/// Namespaces. The stream stays open, as its end would stop the watchers.
+pub fn fixed(
+    namespaces: &[String],
+) -> impl Stream<Item = Result<Namespace, watcher::Error>> + Send + 'static {
+    let events: Vec<Result<Namespace, watcher::Error>> = namespaces
+        .iter()
+        .map(|name| {
+            Ok(Namespace {
+                metadata: ObjectMeta {
+                    name: Some(name.clone()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+        })
+        .collect();
+    futures::stream::iter(events).chain(futures::stream::pending())
+}
+
+impl ServiceWatchers {
+    /// Starts or stops watchers after a change of `changed`. Running watchers are checked
+    /// too, as a namespace deleted while the namespace watcher was reconnecting leaves no
+    /// event of its own.
+    async fn sync(&mut self, changed: &str) {
+        let mut namespaces: BTreeSet<String> = self.running.keys().cloned().collect();
+        namespaces.insert(changed.to_string());
+
+        for namespace in namespaces {
+            let exists = self
+                .namespaces
+                .as_ref()
+                .is_none_or(|store| store.get(&ObjectRef::new(&namespace)).is_some());
+            let watched = exists && self.ctx.watches_namespace(&namespace);
+            match (watched, self.running.contains_key(&namespace)) {
+                (true, false) => self.start(namespace),
+                (false, true) => self.stop(&namespace, exists).await,
+                _ => {}
+            }
+        }
+    }
+
+    fn start(&mut self, namespace: String) {
+        self.next_generation += 1;
+        let generation = self.next_generation;
+        let services: Api<Service> = Api::namespaced(self.client.clone(), &namespace);
+        let events = self.events.clone();
+        let health = self.ctx.health.clone();
+        let name = namespace.clone();
+        let task = tokio::spawn(async move {
+            let stream =
+                watcher::watcher(services, Config::default().any_semantic()).default_backoff();
+            let mut stream = std::pin::pin!(stream);
+            while let Some(event) = stream.next().await {
+                match event {
+                    Ok(event) => {
+                        if events.send((name.clone(), generation, event)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        warn!("Service watcher for namespace {} failed: {}", name, e);
+                        health.watcher_error(&e);
+                    }
+                }
+            }
+        });
+
+        info!("Started watching services in namespace {}", namespace);
+        self.running.insert(
+            namespace,
+            NamespaceWatch {
+                generation,
+                task,
+                services: BTreeMap::new(),
+                listing: None,
+            },
+        );
+    }
+
+    /// Stops the namespace's watcher and removes its services from the catalog
+    async fn stop(&mut self, namespace: &str, exists: bool) {
+        let Some(watch) = self.running.remove(namespace) else {
+            return;
+        };
+        watch.task.abort();
+
+        let reason = if !exists {
+            RemovalReason::ServiceDeleted
+        } else if namespace_utils::namespace_matches(&self.ctx.exclude_namespaces, namespace) {
+            RemovalReason::Excluded
+        } else {
+            RemovalReason::NamespaceNotWatched
+        };
+        info!(
+            "Stopped watching services in namespace {} ({:?})",
+            namespace, reason
+        );
+
+        for service_name in watch.services.into_keys() {
+            forget(&mut self.writer, namespace, &service_name);
+            if let Err(e) = remove_entry_from_discovery_configmap(
+                self.ctx.clone(),
+                namespace,
+                &service_name,
+                reason,
+            )
+            .await
+            {
+                warn!(
+                    "Failed to remove service {}/{} from discovery: {}",
+                    namespace, service_name, e
+                );
+            }
+        }
+    }
+
+    /// Applies an event of a namespace's watcher to the shared store. A relist of one
+    /// namespace must not reset the others, so it is applied service by service. Only
+    /// Services whose discovery hash changed are reconciled.
+    fn apply(&mut self, namespace: &str, generation: u64, event: watcher::Event<Service>) {
+        // Events still queued from a watcher that was stopped
+        let Some(watch) = self
+            .running
+            .get_mut(namespace)
+            .filter(|watch| watch.generation == generation)
+        else {
+            return;
+        };
+
+        if let watcher::Event::InitApply(service) = &event {
+            watch
+                .listing
+                .get_or_insert_default()
+                .insert(service.name_any());
+        }
+        match event {
+            watcher::Event::Init => watch.listing = Some(BTreeSet::new()),
+            watcher::Event::InitApply(service) | watcher::Event::Apply(service) => {
+                let hash = discovery_hash(&service, self.ctx.label_selector.as_ref());
+                let changed = watch.services.insert(service.name_any(), hash) != Some(hash);
+                self.writer
+                    .apply_watcher_event(&watcher::Event::Apply(service.clone()));
+                if changed {
+                    let _ = self.trigger.unbounded_send(Ok(service));
+                }
+            }
+            watcher::Event::Delete(service) => {
+                watch.services.remove(&service.name_any());
+                self.writer
+                    .apply_watcher_event(&watcher::Event::Delete(service));
+            }
+            watcher::Event::InitDone => {
+                // Services deleted while the watcher was reconnecting
+                let listed = watch.listing.take().unwrap_or_default();
+                for service_name in watch.services.keys() {
+                    if !listed.contains(service_name) {
+                        forget(&mut self.writer, namespace, service_name);
+                    }
+                }
+                watch
+                    .services
+                    .retain(|service_name, _| listed.contains(service_name));
+            }
+        }
+    }
+}
+
+/// Hash of what discovery of the Service depends on: its api-doc.io annotations, ports,
+/// type, selector and generation, and whether `DISCOVERY_LABEL_SELECTOR` matches it. Status
+/// updates and edits of other labels or annotations leave it unchanged and trigger no fetch;
+/// the periodic requeue of every reconcile still refreshes the spec.
+pub fn discovery_hash(service: &Service, label_selector: Option<&Selector>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    service.uid().hash(&mut hasher);
+    service.metadata.generation.hash(&mut hasher);
+    service
+        .annotations()
+        .iter()
+        .filter(|(key, _)| key.starts_with(API_DOC_ANNOTATION_PREFIX))
+        .for_each(|annotation| annotation.hash(&mut hasher));
+    label_selector
+        .map(|selector| selector.matches(service.labels()))
+        .hash(&mut hasher);
+    if let Some(spec) = &service.spec {
+        // k8s-openapi types do not implement Hash
+        serde_json::to_string(&spec.ports)
+            .unwrap_or_default()
+            .hash(&mut hasher);
+        spec.type_.hash(&mut hasher);
+        spec.external_name.hash(&mut hasher);
+        spec.selector.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Drops a service from the shared store
+fn forget(writer: &mut Writer<Service>, namespace: &str, service_name: &str) {
+    let service = Service {
+        metadata: ObjectMeta {
+            name: Some(service_name.to_string()),
+            namespace: Some(namespace.to_string()),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    writer.apply_watcher_event(&watcher::Event::Delete(service));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::{ServicePort, ServiceSpec};
+
+    #[test]
+    fn only_discovery_relevant_changes_alter_the_hash() {
+        let mut service = Service {
+            metadata: ObjectMeta {
+                name: Some("orders".to_string()),
+                namespace: Some("shop".to_string()),
+                annotations: Some(BTreeMap::from([(
+                    "api-doc.io/enabled".to_string(),
+                    "true".to_string(),
+                )])),
+                ..Default::default()
+            },
+            spec: Some(ServiceSpec {
+                ports: Some(vec![ServicePort {
+                    port: 8080,
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let selector = crate::label_selector::parse("team=shop").unwrap();
+        let hash = |service: &Service| discovery_hash(service, Some(&selector));
+        let original = hash(&service);
+
+        service.metadata.resource_version = Some("42".to_string());
+        service.labels_mut().insert("release".to_string(), "7".to_string());
+        service.annotations_mut().insert(
+            "kubectl.kubernetes.io/last-applied-configuration".to_string(),
+            "{}".to_string(),
+        );
+        assert_eq!(hash(&service), original);
+
+        service.labels_mut().insert("team".to_string(), "shop".to_string());
+        let labelled = hash(&service);
+        assert_ne!(labelled, original);
+
+        service
+            .annotations_mut()
+            .insert("api-doc.io/path".to_string(), "/openapi.json".to_string());
+        let annotated = hash(&service);
+        assert_ne!(annotated, labelled);
+
+        service.spec.as_mut().unwrap().ports.as_mut().unwrap()[0].port = 9090;
+        assert_ne!(hash(&service), annotated);
+    }
+}
@@ -0,0 +1,224 @@
+//! Apicurio Registry publishing.
+//!
+//! Each entry is stored as the `OPENAPI` artifact `<entry id>` in the configured group
+//! through the registry's v2 REST API. Changed specs are added as a new artifact version;
+//! removing the entry deletes the artifact with all of its versions.
+
+use async_trait::async_trait;
+use openapi_common::signing::SpecSignature;
+use openapi_common::{ApiInventoryEntry, annotations::ApiDocAnnotations};
+use std::time::Duration;
+
+use super::{NamespaceFilter, PublishError, Publisher, check_response};
+
+pub const APICURIO_URL_ENV: &str = "APICURIO_URL";
+pub const APICURIO_GROUP_ENV: &str = "APICURIO_GROUP";
+pub const APICURIO_TOKEN_ENV: &str = "APICURIO_TOKEN";
+pub const APICURIO_NAMESPACES_ENV: &str = "APICURIO_NAMESPACES";
+
+const DEFAULT_GROUP: &str = "default";
+
+pub struct ApicurioPublisher {
+    /// Registry base URL, e.g. `https://registry.example.com`
+    base_url: String,
+    group: String,
+    token: Option<String>,
+    namespaces: NamespaceFilter,
+    client: reqwest::Client,
+}
+
+impl ApicurioPublisher {
+    /// Returns `None` when `APICURIO_URL` is unset
+    pub fn from_env() -> Result<Option<Self>, PublishError> {
+        let Some(url) = std::env::var(APICURIO_URL_ENV)
+            .ok()
+            .map(|url| url.trim().trim_end_matches('/').to_string())
+            .filter(|url| !url.is_empty())
+        else {
+            return Ok(None);
+        };
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(PublishError::Config(format!(
+                "{} must be an http(s) URL, got \"{}\"",
+                APICURIO_URL_ENV, url
+            )));
+        }
+
+        Ok(Some(Self {
+            base_url: url,
+            group: std::env::var(APICURIO_GROUP_ENV)
+                .ok()
+                .map(|group| group.trim().to_string())
+                .filter(|group| !group.is_empty())
+                .unwrap_or_else(|| DEFAULT_GROUP.to_string()),
+            token: std::env::var(APICURIO_TOKEN_ENV)
+                .ok()
+                .filter(|token| !token.is_empty()),
+            namespaces: NamespaceFilter::from_env(APICURIO_NAMESPACES_ENV),
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()?,
+        }))
+    }
+
+    fn artifacts_url(&self) -> String {
+        format!(
+            "{}/apis/registry/v2/groups/{}/artifacts",
+            self.base_url,
+            urlencoding::encode(&self.group)
+        )
+    }
+
+    fn authorized(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+}
+
+#[async_trait]
+impl Publisher for ApicurioPublisher {
+    fn name(&self) -> &str {
+        "apicurio"
+    }
+
+    fn accepts(&self, entry: &ApiInventoryEntry, _annotations: &ApiDocAnnotations) -> bool {
+        self.namespaces.allows(&entry.namespace)
+    }
+
+    async fn publish(
+        &self,
+        entry: &ApiInventoryEntry,
+        spec: &str,
+        _signature: Option<&SpecSignature>,
+    ) -> Result<(), PublishError> {
+        let content_type = if spec.trim_start().starts_with('{') {
+            "application/json"
+        } else {
+            "application/x-yaml"
+        };
+
+        // Creates the artifact, or adds a version when it already exists
+        let request = self
+            .client
+            .post(self.artifacts_url())
+            .query(&[("ifExists", "UPDATE")])
+            .header("X-Registry-ArtifactId", &entry.id)
+            .header("X-Registry-ArtifactType", "OPENAPI")
+            .header("Content-Type", content_type)
+            .body(spec.to_string());
+        let response = self.authorized(request).send().await?;
+        check_response(response).await?;
+        Ok(())
+    }
+
+    async fn unpublish(&self, id: &str) -> Result<(), PublishError> {
+        let url = format!("{}/{}", self.artifacts_url(), urlencoding::encode(id));
+        let response = self.authorized(self.client.delete(url)).send().await?;
+        match check_response(response).await {
+            Err(PublishError::Status { code: 404, .. }) => Ok(()),
+            other => other.map(|_| ()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestServer;
+
+    fn publisher(base_url: &str, token: Option<&str>) -> ApicurioPublisher {
+        ApicurioPublisher {
+            base_url: base_url.to_string(),
+            group: "team apis".to_string(),
+            token: token.map(str::to_string),
+            namespaces: NamespaceFilter::default(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn entry() -> ApiInventoryEntry {
+        ApiInventoryEntry::new("shop", "orders", "http://orders.shop.svc/openapi.json")
+    }
+
+    #[tokio::test]
+    async fn specs_are_created_or_versioned_as_openapi_artifacts() {
+        let server = TestServer::start(|_| (200, "{}".to_string())).await;
+        let spec = "openapi: 3.0.0\ninfo:\n  title: Orders\n  version: '1'\n";
+
+        publisher(&server.url, Some("registry-token"))
+            .publish(&entry(), spec, None)
+            .await
+            .unwrap();
+
+        let request = &server.requests()[0];
+        assert_eq!(request.method, "POST");
+        assert_eq!(
+            request.uri,
+            "/apis/registry/v2/groups/team%20apis/artifacts?ifExists=UPDATE"
+        );
+        assert_eq!(request.header("x-registry-artifactid"), Some("shop.orders"));
+        assert_eq!(request.header("x-registry-artifacttype"), Some("OPENAPI"));
+        assert_eq!(request.header("content-type"), Some("application/x-yaml"));
+        assert_eq!(
+            request.header("authorization"),
+            Some("Bearer registry-token")
+        );
+        assert_eq!(request.body, spec);
+    }
+
+    #[tokio::test]
+    async fn anonymous_registries_get_no_authorization_header() {
+        let server = TestServer::start(|_| (200, "{}".to_string())).await;
+        let spec = "{\"openapi\":\"3.0.0\"}";
+
+        publisher(&server.url, None)
+            .publish(&entry(), spec, None)
+            .await
+            .unwrap();
+
+        let request = &server.requests()[0];
+        assert_eq!(request.header("authorization"), None);
+        assert_eq!(request.header("content-type"), Some("application/json"));
+    }
+
+    #[tokio::test]
+    async fn unpublishing_deletes_the_artifact_and_ignores_missing_ones() {
+        let server = TestServer::start(|_| (404, String::new())).await;
+
+        publisher(&server.url, Some("registry-token"))
+            .unpublish("shop.orders")
+            .await
+            .unwrap();
+
+        let request = &server.requests()[0];
+        assert_eq!(request.method, "DELETE");
+        assert_eq!(
+            request.uri,
+            "/apis/registry/v2/groups/team%20apis/artifacts/shop.orders"
+        );
+        assert_eq!(
+            request.header("authorization"),
+            Some("Bearer registry-token")
+        );
+    }
+
+    #[tokio::test]
+    async fn rejected_requests_become_status_errors() {
+        let server = TestServer::start(|_| (409, "invalid content".to_string())).await;
+        let publisher = publisher(&server.url, None);
+
+        match publisher.publish(&entry(), "{}", None).await {
+            Err(PublishError::Status { code, body }) => {
+                assert_eq!(code, 409);
+                assert_eq!(body, "invalid content");
+            }
+            other => panic!("expected a status error, got {:?}", other),
+        }
+        assert!(matches!(
+            publisher.unpublish("shop.orders").await,
+            Err(PublishError::Status { code: 409, .. })
+        ));
+    }
+}
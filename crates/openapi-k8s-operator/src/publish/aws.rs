@@ -0,0 +1,336 @@
+//! AWS API Gateway (REST APIs) publishing through the import API.
+//!
+//! Imported APIs are tagged with the catalog entry id, so the matching REST API is found
+//! again after an operator restart and re-imported in `overwrite` mode. Deployments to
+//! stages are left to the gateway owners.
+
+use async_trait::async_trait;
+use openapi_common::signing::SpecSignature;
+use openapi_common::sigv4::{AWS_REGION_ENV, AwsCredentials, AwsSigner};
+use openapi_common::{ApiInventoryEntry, annotations::ApiDocAnnotations, spec_utils};
+use reqwest::{Method, Url};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+use super::{PublishError, Publisher, check_response};
+
+/// Tag holding the catalog entry id on imported REST APIs
+const ENTRY_ID_TAG: &str = "api-doc.io/id";
+
+#[derive(Deserialize)]
+struct RestApi {
+    id: String,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct RestApis {
+    #[serde(default)]
+    item: Vec<RestApi>,
+}
+
+pub struct AwsApiGatewayPublisher {
+    signer: AwsSigner,
+    endpoint: String,
+    client: reqwest::Client,
+    /// Entry id -> REST API id, filled lazily from the gateway's tags
+    rest_api_ids: Mutex<HashMap<String, String>>,
+}
+
+impl AwsApiGatewayPublisher {
+    pub fn from_env() -> Result<Self, PublishError> {
+        let region = std::env::var(AWS_REGION_ENV).map_err(|_| {
+            PublishError::Config(format!("{} is required for AWS publishing", AWS_REGION_ENV))
+        })?;
+        let credentials = AwsCredentials::from_env().ok_or_else(|| {
+            PublishError::Config(
+                "AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY are required".to_string(),
+            )
+        })?;
+
+        Ok(Self {
+            endpoint: format!("https://apigateway.{}.amazonaws.com", region),
+            signer: AwsSigner::new(credentials, &region, "apigateway"),
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(60))
+                .build()?,
+            rest_api_ids: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn url(&self, path: &str) -> Result<Url, PublishError> {
+        Url::parse(&format!("{}{}", self.endpoint, path))
+            .map_err(|e| PublishError::Config(format!("invalid API Gateway URL: {}", e)))
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Vec<u8>,
+    ) -> Result<reqwest::Response, PublishError> {
+        let request = self.signer.request(
+            &self.client,
+            method,
+            self.url(path)?,
+            Some("application/json"),
+            body,
+        );
+        check_response(request.send().await?).await
+    }
+
+    /// Finds the REST API previously imported for this entry
+    async fn find_rest_api(&self, id: &str) -> Result<Option<String>, PublishError> {
+        if let Some(rest_api_id) = self.rest_api_ids.lock().await.get(id) {
+            return Ok(Some(rest_api_id.clone()));
+        }
+
+        let apis: RestApis = self
+            .send(Method::GET, "/restapis?limit=500", Vec::new())
+            .await?
+            .json()
+            .await?;
+        let found = apis
+            .item
+            .into_iter()
+            .find(|api| api.tags.get(ENTRY_ID_TAG).map(String::as_str) == Some(id))
+            .map(|api| api.id);
+        if let Some(rest_api_id) = &found {
+            self.rest_api_ids
+                .lock()
+                .await
+                .insert(id.to_string(), rest_api_id.clone());
+        }
+        Ok(found)
+    }
+}
+
+#[async_trait]
+impl Publisher for AwsApiGatewayPublisher {
+    fn name(&self) -> &str {
+        "aws-apigateway"
+    }
+
+    fn accepts(&self, _entry: &ApiInventoryEntry, annotations: &ApiDocAnnotations) -> bool {
+        annotations.gateway_publish
+    }
+
+    async fn publish(
+        &self,
+        entry: &ApiInventoryEntry,
+        spec: &str,
+        _signature: Option<&SpecSignature>,
+    ) -> Result<(), PublishError> {
+        let spec = spec_utils::parse_spec_to_json(spec)
+            .map_err(|e| PublishError::InvalidSpec(e.to_string()))?;
+        let body =
+            serde_json::to_vec(&spec).map_err(|e| PublishError::InvalidSpec(e.to_string()))?;
+
+        if let Some(rest_api_id) = self.find_rest_api(&entry.id).await? {
+            let path = format!(
+                "/restapis/{}?mode=overwrite&failonwarnings=false",
+                rest_api_id
+            );
+            self.send(Method::PUT, &path, body).await?;
+            return Ok(());
+        }
+
+        let created: RestApi = self
+            .send(
+                Method::POST,
+                "/restapis?mode=import&failonwarnings=false",
+                body,
+            )
+            .await?
+            .json()
+            .await?;
+        let arn = format!(
+            "arn:aws:apigateway:{}::/restapis/{}",
+            self.signer.region, created.id
+        );
+        let tags = serde_json::json!({ "tags": { ENTRY_ID_TAG: entry.id } });
+        self.send(
+            Method::PUT,
+            &format!("/tags/{}", urlencoding::encode(&arn)),
+            tags.to_string().into_bytes(),
+        )
+        .await?;
+        self.rest_api_ids
+            .lock()
+            .await
+            .insert(entry.id.clone(), created.id);
+        Ok(())
+    }
+
+    async fn unpublish(&self, id: &str) -> Result<(), PublishError> {
+        let Some(rest_api_id) = self.find_rest_api(id).await? else {
+            return Ok(());
+        };
+        self.send(
+            Method::DELETE,
+            &format!("/restapis/{}", rest_api_id),
+            Vec::new(),
+        )
+        .await?;
+        self.rest_api_ids.lock().await.remove(id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{Recorded, TestServer};
+
+    const SPEC: &str = "{\"openapi\":\"3.0.0\",\"info\":{\"title\":\"Orders\",\"version\":\"1\"}}";
+
+    fn publisher(endpoint: &str) -> AwsApiGatewayPublisher {
+        let credentials = AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: None,
+        };
+        AwsApiGatewayPublisher {
+            signer: AwsSigner::new(credentials, "eu-west-1", "apigateway"),
+            endpoint: endpoint.to_string(),
+            client: reqwest::Client::new(),
+            rest_api_ids: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn entry() -> ApiInventoryEntry {
+        ApiInventoryEntry::new("shop", "orders", "http://orders.shop.svc/openapi.json")
+    }
+
+    fn requests(server: &TestServer) -> Vec<(String, String)> {
+        server
+            .requests()
+            .into_iter()
+            .map(|r| (r.method, r.uri))
+            .collect()
+    }
+
+    fn gateway(tagged: &'static str) -> impl Fn(&Recorded) -> (u16, String) + Clone {
+        move |request: &Recorded| match request.method.as_str() {
+            "GET" => (200, tagged.to_string()),
+            "POST" => (201, "{\"id\":\"new123\"}".to_string()),
+            _ => (200, "{}".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn new_apis_are_imported_and_tagged_with_the_entry_id() {
+        let server = TestServer::start(gateway("{\"item\":[]}")).await;
+        let publisher = publisher(&server.url);
+
+        publisher.publish(&entry(), SPEC, None).await.unwrap();
+        publisher.publish(&entry(), SPEC, None).await.unwrap();
+
+        let arn = "arn%3Aaws%3Aapigateway%3Aeu-west-1%3A%3A%2Frestapis%2Fnew123";
+        assert_eq!(
+            requests(&server),
+            [
+                ("GET", "/restapis?limit=500".to_string()),
+                (
+                    "POST",
+                    "/restapis?mode=import&failonwarnings=false".to_string()
+                ),
+                ("PUT", format!("/tags/{}", arn)),
+                (
+                    "PUT",
+                    "/restapis/new123?mode=overwrite&failonwarnings=false".to_string()
+                ),
+            ]
+            .map(|(method, uri)| (method.to_string(), uri))
+        );
+        let recorded = server.requests();
+        assert_eq!(recorded[1].json()["info"]["title"], "Orders");
+        assert_eq!(recorded[2].json()["tags"][ENTRY_ID_TAG], "shop.orders");
+    }
+
+    #[tokio::test]
+    async fn requests_are_signed_for_api_gateway() {
+        let server = TestServer::start(gateway("{\"item\":[]}")).await;
+        publisher(&server.url)
+            .publish(&entry(), SPEC, None)
+            .await
+            .unwrap();
+
+        for request in server.requests() {
+            let authorization = request.header("authorization").unwrap();
+            assert!(
+                authorization.starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/"),
+                "{}",
+                authorization
+            );
+            assert!(
+                authorization.contains(
+                    "/eu-west-1/apigateway/aws4_request, \
+                     SignedHeaders=content-type;host;x-amz-date, Signature="
+                ),
+                "{}",
+                authorization
+            );
+            assert!(request.header("x-amz-date").is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn tagged_apis_are_overwritten_and_deleted() {
+        let tagged = r#"{"item":[
+            {"id":"other1","tags":{"api-doc.io/id":"shop.carts"}},
+            {"id":"abc123","tags":{"api-doc.io/id":"shop.orders"}}
+        ]}"#;
+        let server = TestServer::start(gateway(tagged)).await;
+        let publisher = publisher(&server.url);
+
+        publisher.publish(&entry(), SPEC, None).await.unwrap();
+        publisher.unpublish("shop.orders").await.unwrap();
+
+        assert_eq!(
+            requests(&server),
+            [
+                ("GET", "/restapis?limit=500"),
+                (
+                    "PUT",
+                    "/restapis/abc123?mode=overwrite&failonwarnings=false"
+                ),
+                ("DELETE", "/restapis/abc123"),
+            ]
+            .map(|(method, uri)| (method.to_string(), uri.to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_apis_are_not_deleted() {
+        let server = TestServer::start(gateway("{\"item\":[]}")).await;
+        publisher(&server.url)
+            .unpublish("shop.orders")
+            .await
+            .unwrap();
+
+        assert_eq!(requests(&server).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn gateway_errors_become_status_errors() {
+        let server = TestServer::start(|_| (403, "AccessDenied".to_string())).await;
+        let publisher = publisher(&server.url);
+
+        match publisher.publish(&entry(), SPEC, None).await {
+            Err(PublishError::Status { code, body }) => {
+                assert_eq!(code, 403);
+                assert_eq!(body, "AccessDenied");
+            }
+            other => panic!("expected a status error, got {:?}", other),
+        }
+        assert!(matches!(
+            publisher.publish(&entry(), "openapi: [3.0", None).await,
+            Err(PublishError::InvalidSpec(_))
+        ));
+        assert_eq!(requests(&server).len(), 1);
+    }
+}
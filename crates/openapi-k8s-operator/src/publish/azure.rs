@@ -0,0 +1,313 @@
+//! Azure API Management publishing through the ARM REST API.
+//!
+//! Each entry becomes an APIM API named after the entry id, imported with
+//! `PUT .../apis/{id}` (create or replace) and served under `<namespace>/<service>`.
+
+use async_trait::async_trait;
+use openapi_common::signing::SpecSignature;
+use openapi_common::{ApiInventoryEntry, OpenApiDocument, annotations::ApiDocAnnotations};
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use super::{PublishError, Publisher, check_response};
+
+pub const AZURE_TENANT_ID_ENV: &str = "AZURE_TENANT_ID";
+pub const AZURE_CLIENT_ID_ENV: &str = "AZURE_CLIENT_ID";
+pub const AZURE_CLIENT_SECRET_ENV: &str = "AZURE_CLIENT_SECRET";
+pub const AZURE_SUBSCRIPTION_ID_ENV: &str = "AZURE_SUBSCRIPTION_ID";
+pub const AZURE_APIM_RESOURCE_GROUP_ENV: &str = "AZURE_APIM_RESOURCE_GROUP";
+pub const AZURE_APIM_SERVICE_ENV: &str = "AZURE_APIM_SERVICE";
+
+const LOGIN_ENDPOINT: &str = "https://login.microsoftonline.com";
+const ARM_ENDPOINT: &str = "https://management.azure.com";
+const APIM_API_VERSION: &str = "2022-08-01";
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+pub struct AzureApimPublisher {
+    tenant_id: String,
+    client_id: String,
+    client_secret: String,
+    /// Microsoft Entra ID endpoint tokens are requested from
+    login_endpoint: String,
+    arm_endpoint: String,
+    /// ARM resource id of the APIM service
+    service_id: String,
+    client: reqwest::Client,
+    /// Cached access token and when it stops being usable
+    token: Mutex<Option<(String, Instant)>>,
+}
+
+fn required_env(name: &str) -> Result<String, PublishError> {
+    std::env::var(name)
+        .map_err(|_| PublishError::Config(format!("{} is required for Azure publishing", name)))
+}
+
+impl AzureApimPublisher {
+    pub fn from_env() -> Result<Self, PublishError> {
+        Ok(Self {
+            tenant_id: required_env(AZURE_TENANT_ID_ENV)?,
+            client_id: required_env(AZURE_CLIENT_ID_ENV)?,
+            client_secret: required_env(AZURE_CLIENT_SECRET_ENV)?,
+            login_endpoint: LOGIN_ENDPOINT.to_string(),
+            arm_endpoint: ARM_ENDPOINT.to_string(),
+            service_id: format!(
+                "/subscriptions/{}/resourceGroups/{}/providers/Microsoft.ApiManagement/service/{}",
+                required_env(AZURE_SUBSCRIPTION_ID_ENV)?,
+                required_env(AZURE_APIM_RESOURCE_GROUP_ENV)?,
+                required_env(AZURE_APIM_SERVICE_ENV)?
+            ),
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(60))
+                .build()?,
+            token: Mutex::new(None),
+        })
+    }
+
+    /// Client-credentials token for ARM, refreshed a minute before it expires
+    async fn access_token(&self) -> Result<String, PublishError> {
+        let mut token = self.token.lock().await;
+        if let Some((value, valid_until)) = token.as_ref()
+            && Instant::now() < *valid_until
+        {
+            return Ok(value.clone());
+        }
+
+        let response = self
+            .client
+            .post(format!(
+                "{}/{}/oauth2/v2.0/token",
+                self.login_endpoint, self.tenant_id
+            ))
+            .form(&[
+                ("grant_type", "client_credentials"),
+                ("client_id", self.client_id.as_str()),
+                ("client_secret", self.client_secret.as_str()),
+                ("scope", "https://management.azure.com/.default"),
+            ])
+            .send()
+            .await?;
+        let response: TokenResponse = check_response(response).await?.json().await?;
+
+        let valid_for = Duration::from_secs(response.expires_in.saturating_sub(60));
+        *token = Some((response.access_token.clone(), Instant::now() + valid_for));
+        Ok(response.access_token)
+    }
+
+    fn api_url(&self, id: &str) -> String {
+        format!(
+            "{}{}/apis/{}?api-version={}",
+            self.arm_endpoint, self.service_id, id, APIM_API_VERSION
+        )
+    }
+}
+
+#[async_trait]
+impl Publisher for AzureApimPublisher {
+    fn name(&self) -> &str {
+        "azure-apim"
+    }
+
+    fn accepts(&self, _entry: &ApiInventoryEntry, annotations: &ApiDocAnnotations) -> bool {
+        annotations.gateway_publish
+    }
+
+    async fn publish(
+        &self,
+        entry: &ApiInventoryEntry,
+        spec: &str,
+        _signature: Option<&SpecSignature>,
+    ) -> Result<(), PublishError> {
+        let document =
+            OpenApiDocument::parse(spec).map_err(|e| PublishError::InvalidSpec(e.to_string()))?;
+        let is_json = spec.trim_start().starts_with('{');
+        let format = match (document.swagger.is_some(), is_json) {
+            (true, _) => "swagger-json",
+            (false, true) => "openapi+json",
+            (false, false) => "openapi",
+        };
+        let value = if document.swagger.is_some() && !is_json {
+            // APIM only imports Swagger 2.0 as JSON
+            openapi_common::spec_utils::parse_spec_to_json(spec)
+                .map_err(|e| PublishError::InvalidSpec(e.to_string()))?
+                .to_string()
+        } else {
+            spec.to_string()
+        };
+
+        let body = serde_json::json!({
+            "properties": {
+                "format": format,
+                "value": value,
+                "path": format!("{}/{}", entry.namespace, entry.service_name),
+                "displayName": entry.name,
+                "description": entry.description,
+            }
+        });
+        let response = self
+            .client
+            .put(self.api_url(&entry.id))
+            .bearer_auth(self.access_token().await?)
+            .json(&body)
+            .send()
+            .await?;
+        check_response(response).await?;
+        Ok(())
+    }
+
+    async fn unpublish(&self, id: &str) -> Result<(), PublishError> {
+        let response = self
+            .client
+            .delete(format!("{}&deleteRevisions=true", self.api_url(id)))
+            .bearer_auth(self.access_token().await?)
+            .header("If-Match", "*")
+            .send()
+            .await?;
+        match check_response(response).await {
+            Err(PublishError::Status { code: 404, .. }) => Ok(()),
+            other => other.map(|_| ()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{Recorded, TestServer};
+
+    const SERVICE_ID: &str =
+        "/subscriptions/sub/resourceGroups/apis/providers/Microsoft.ApiManagement/service/apim";
+
+    fn publisher(endpoint: &str) -> AzureApimPublisher {
+        AzureApimPublisher {
+            tenant_id: "tenant".to_string(),
+            client_id: "operator".to_string(),
+            client_secret: "secret".to_string(),
+            login_endpoint: endpoint.to_string(),
+            arm_endpoint: endpoint.to_string(),
+            service_id: SERVICE_ID.to_string(),
+            client: reqwest::Client::new(),
+            token: Mutex::new(None),
+        }
+    }
+
+    fn entry() -> ApiInventoryEntry {
+        ApiInventoryEntry::new("shop", "orders", "http://orders.shop.svc/openapi.json")
+    }
+
+    fn azure(api_status: u16) -> impl Fn(&Recorded) -> (u16, String) + Clone {
+        move |request: &Recorded| {
+            if request.uri.ends_with("/oauth2/v2.0/token") {
+                let token = r#"{"access_token":"arm-token","expires_in":3600}"#;
+                (200, token.to_string())
+            } else {
+                (api_status, "{}".to_string())
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn apis_are_imported_with_a_cached_client_credentials_token() {
+        let server = TestServer::start(azure(200)).await;
+        let publisher = publisher(&server.url);
+        let spec = "{\"openapi\":\"3.0.0\",\"info\":{\"title\":\"Orders\",\"version\":\"1\"}}";
+
+        publisher.publish(&entry(), spec, None).await.unwrap();
+        publisher.publish(&entry(), spec, None).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].uri, "/tenant/oauth2/v2.0/token");
+        assert!(requests[0].body.contains("grant_type=client_credentials"));
+        assert!(requests[0].body.contains("client_id=operator"));
+        assert!(requests[0].body.contains("client_secret=secret"));
+
+        assert_eq!(requests[1].method, "PUT");
+        assert_eq!(
+            requests[1].uri,
+            format!("{}/apis/shop.orders?api-version=2022-08-01", SERVICE_ID)
+        );
+        assert_eq!(
+            requests[1].header("authorization"),
+            Some("Bearer arm-token")
+        );
+        let properties = &requests[1].json()["properties"];
+        assert_eq!(properties["format"], "openapi+json");
+        assert_eq!(properties["value"], spec);
+        assert_eq!(properties["path"], "shop/orders");
+        assert_eq!(properties["displayName"], "orders API");
+        assert_eq!(
+            requests[2].header("authorization"),
+            Some("Bearer arm-token")
+        );
+    }
+
+    #[tokio::test]
+    async fn swagger_yaml_is_imported_as_json() {
+        let server = TestServer::start(azure(200)).await;
+        let spec = "swagger: '2.0'\ninfo:\n  title: Orders\n  version: '1'\npaths: {}\n";
+
+        publisher(&server.url)
+            .publish(&entry(), spec, None)
+            .await
+            .unwrap();
+
+        let properties = &server.requests()[1].json()["properties"];
+        assert_eq!(properties["format"], "swagger-json");
+        let value: serde_json::Value =
+            serde_json::from_str(properties["value"].as_str().unwrap()).unwrap();
+        assert_eq!(value["swagger"], "2.0");
+    }
+
+    #[tokio::test]
+    async fn unpublishing_deletes_all_revisions_and_ignores_missing_apis() {
+        let server = TestServer::start(azure(404)).await;
+
+        publisher(&server.url)
+            .unpublish("shop.orders")
+            .await
+            .unwrap();
+
+        let request = &server.requests()[1];
+        assert_eq!(request.method, "DELETE");
+        assert_eq!(
+            request.uri,
+            format!(
+                "{}/apis/shop.orders?api-version=2022-08-01&deleteRevisions=true",
+                SERVICE_ID
+            )
+        );
+        assert_eq!(request.header("if-match"), Some("*"));
+        assert_eq!(request.header("authorization"), Some("Bearer arm-token"));
+    }
+
+    #[tokio::test]
+    async fn token_and_import_failures_become_status_errors() {
+        let server = TestServer::start(|_| (401, "invalid_client".to_string())).await;
+        let spec = "{\"openapi\":\"3.0.0\",\"info\":{\"title\":\"Orders\",\"version\":\"1\"}}";
+        match publisher(&server.url).publish(&entry(), spec, None).await {
+            Err(PublishError::Status { code, body }) => {
+                assert_eq!(code, 401);
+                assert_eq!(body, "invalid_client");
+            }
+            other => panic!("expected a status error, got {:?}", other),
+        }
+        assert_eq!(server.requests().len(), 1);
+
+        let server = TestServer::start(azure(400)).await;
+        assert!(matches!(
+            publisher(&server.url).publish(&entry(), spec, None).await,
+            Err(PublishError::Status { code: 400, .. })
+        ));
+        assert!(matches!(
+            publisher(&server.url).publish(&entry(), "{", None).await,
+            Err(PublishError::InvalidSpec(_))
+        ));
+    }
+}
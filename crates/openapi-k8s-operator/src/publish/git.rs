@@ -0,0 +1,326 @@
+//! Git repository sync: commits changed specs to a branch of a configured repository.
+//!
+//! Uses the `git` binary (installed in the operator image) on a local working copy.
+//! Specs are stored as `<dir>/<namespace>/<service>.<json|yaml>` (signed specs with a
+//! `.sig` file next to them) and every commit message summarizes what changed for
+//! clients, using the spec diff engine.
+
+use async_trait::async_trait;
+use openapi_common::diff::diff_documents;
+use openapi_common::signing::{SIGNATURE_SUFFIX, SpecSignature};
+use openapi_common::{ApiInventoryEntry, OpenApiDocument};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+use tokio::sync::Mutex;
+
+use super::{PublishError, Publisher};
+
+pub const GIT_SYNC_REPO_ENV: &str = "GIT_SYNC_REPO";
+pub const GIT_SYNC_BRANCH_ENV: &str = "GIT_SYNC_BRANCH";
+pub const GIT_SYNC_DIR_ENV: &str = "GIT_SYNC_DIR";
+pub const GIT_SYNC_SSH_KEY_FILE_ENV: &str = "GIT_SYNC_SSH_KEY_FILE";
+pub const GIT_SYNC_WORKDIR_ENV: &str = "GIT_SYNC_WORKDIR";
+pub const GIT_SYNC_AUTHOR_NAME_ENV: &str = "GIT_SYNC_AUTHOR_NAME";
+pub const GIT_SYNC_AUTHOR_EMAIL_ENV: &str = "GIT_SYNC_AUTHOR_EMAIL";
+
+pub struct GitSyncPublisher {
+    repo: String,
+    branch: String,
+    /// Directory inside the repository specs are written to
+    dir: String,
+    workdir: PathBuf,
+    ssh_key_file: Option<PathBuf>,
+    author_name: String,
+    author_email: String,
+    /// Serializes access to the working copy; maps entry ids to their file in the repo
+    files: Mutex<HashMap<String, String>>,
+}
+
+impl GitSyncPublisher {
+    /// Returns `None` when `GIT_SYNC_REPO` is unset
+    pub fn from_env() -> Result<Option<Self>, PublishError> {
+        let Ok(repo) = std::env::var(GIT_SYNC_REPO_ENV) else {
+            return Ok(None);
+        };
+        let env_or =
+            |name: &str, default: &str| std::env::var(name).unwrap_or_else(|_| default.to_string());
+        let workdir = PathBuf::from(env_or(GIT_SYNC_WORKDIR_ENV, "/tmp/openapi-git-sync"));
+        std::fs::create_dir_all(&workdir)?;
+
+        // ssh refuses keys readable by others, and mounted Secrets usually are
+        let ssh_key_file = match std::env::var(GIT_SYNC_SSH_KEY_FILE_ENV) {
+            Ok(path) => {
+                let key_copy = workdir.join("deploy-key");
+                std::fs::copy(&path, &key_copy)?;
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    std::fs::set_permissions(&key_copy, std::fs::Permissions::from_mode(0o600))?;
+                }
+                Some(key_copy)
+            }
+            Err(_) => None,
+        };
+
+        Ok(Some(Self {
+            repo,
+            branch: env_or(GIT_SYNC_BRANCH_ENV, "main"),
+            dir: env_or(GIT_SYNC_DIR_ENV, "specs")
+                .trim_matches('/')
+                .to_string(),
+            workdir,
+            ssh_key_file,
+            author_name: env_or(GIT_SYNC_AUTHOR_NAME_ENV, "openapi-k8s-operator"),
+            author_email: env_or(GIT_SYNC_AUTHOR_EMAIL_ENV, "openapi-k8s-operator@localhost"),
+            files: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    fn checkout(&self) -> PathBuf {
+        self.workdir.join("repo")
+    }
+
+    async fn git(&self, dir: &Path, args: &[&str]) -> Result<String, PublishError> {
+        let mut command = Command::new("git");
+        command
+            .current_dir(dir)
+            .args(["-c", &format!("user.name={}", self.author_name)])
+            .args(["-c", &format!("user.email={}", self.author_email)])
+            .args(args)
+            .env("HOME", &self.workdir)
+            .env("GIT_TERMINAL_PROMPT", "0");
+        if let Some(key) = &self.ssh_key_file {
+            command.env(
+                "GIT_SSH_COMMAND",
+                format!(
+                    "ssh -i {} -o IdentitiesOnly=yes -o StrictHostKeyChecking=accept-new -o UserKnownHostsFile={}",
+                    key.display(),
+                    self.workdir.join("known_hosts").display()
+                ),
+            );
+        }
+
+        let output = command.output().await?;
+        if !output.status.success() {
+            return Err(PublishError::Git(format!(
+                "git {} failed: {}",
+                args.first().copied().unwrap_or_default(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Clones the repository on first use, then resets the working copy to the remote branch
+    async fn sync_checkout(&self) -> Result<PathBuf, PublishError> {
+        let checkout = self.checkout();
+        if !checkout.join(".git").exists() {
+            let target = checkout.to_string_lossy().into_owned();
+            self.git(
+                &self.workdir,
+                &[
+                    "clone",
+                    "--branch",
+                    &self.branch,
+                    "--single-branch",
+                    &self.repo,
+                    &target,
+                ],
+            )
+            .await?;
+        }
+        self.git(&checkout, &["fetch", "origin", &self.branch])
+            .await?;
+        self.git(
+            &checkout,
+            &["reset", "--hard", &format!("origin/{}", self.branch)],
+        )
+        .await?;
+        Ok(checkout)
+    }
+
+    /// Commits staged changes and pushes them, returns false if there was nothing to commit
+    async fn commit_and_push(
+        &self,
+        checkout: &Path,
+        subject: &str,
+        body: &str,
+    ) -> Result<bool, PublishError> {
+        if self
+            .git(checkout, &["diff", "--cached", "--quiet"])
+            .await
+            .is_ok()
+        {
+            return Ok(false);
+        }
+        self.git(checkout, &["commit", "-m", subject, "-m", body])
+            .await?;
+        self.git(
+            checkout,
+            &["push", "origin", &format!("HEAD:{}", self.branch)],
+        )
+        .await?;
+        Ok(true)
+    }
+}
+
+/// Commit subject and body describing the change from `previous` to `spec`
+fn commit_message(
+    entry: &ApiInventoryEntry,
+    previous: Option<&str>,
+    spec: &str,
+) -> (String, String) {
+    let location = format!("{}/{}", entry.namespace, entry.service_name);
+    let new_doc = OpenApiDocument::parse(spec).ok();
+    let version = new_doc
+        .as_ref()
+        .map(|d| d.info.version.clone())
+        .filter(|v| !v.is_empty());
+
+    let Some(previous) = previous else {
+        let subject = format!("Add {} spec", entry.name);
+        let body = match version {
+            Some(version) => format!("Discovered {} (version {}).", location, version),
+            None => format!("Discovered {}.", location),
+        };
+        return (subject, body);
+    };
+
+    let diff = match (OpenApiDocument::parse(previous).ok(), &new_doc) {
+        (Some(old), Some(new)) => Some(diff_documents(&old, new)),
+        _ => None,
+    };
+    let subject = match &diff {
+        Some(diff) if diff.has_breaking() => format!("Update {} spec (breaking)", entry.name),
+        _ => format!("Update {} spec", entry.name),
+    };
+    let mut body = format!("Source: {}", location);
+    if let Some(version) = version {
+        body.push_str(&format!("\nVersion: {}", version));
+    }
+    if let Some(diff) = diff {
+        body.push_str(&format!("\nChanges: {}\n", diff.summary()));
+        for change in diff.changes.iter().take(50) {
+            body.push_str(&format!("\n- {}", change));
+        }
+    }
+    (subject, body)
+}
+
+#[async_trait]
+impl Publisher for GitSyncPublisher {
+    fn name(&self) -> &str {
+        "git"
+    }
+
+    async fn publish(
+        &self,
+        entry: &ApiInventoryEntry,
+        spec: &str,
+        signature: Option<&SpecSignature>,
+    ) -> Result<(), PublishError> {
+        let mut files = self.files.lock().await;
+        let checkout = self.sync_checkout().await?;
+
+        let extension = if spec.trim_start().starts_with('{') {
+            "json"
+        } else {
+            "yaml"
+        };
+        let base = format!("{}/{}/{}", self.dir, entry.namespace, entry.service_name);
+        let file = format!("{}.{}", base, extension);
+
+        let signature_file = format!("{}{}", file, SIGNATURE_SUFFIX);
+
+        // The service may have switched between JSON and YAML since the last commit
+        let mut previous = None;
+        for candidate in [format!("{}.json", base), format!("{}.yaml", base)] {
+            let path = checkout.join(&candidate);
+            if let Ok(content) = tokio::fs::read_to_string(&path).await {
+                previous = Some(content);
+                if candidate != file {
+                    self.git(&checkout, &["rm", "--quiet", &candidate]).await?;
+                }
+            }
+            // A signature left over from a previous format or signing key would not verify
+            let candidate_signature = format!("{}{}", candidate, SIGNATURE_SUFFIX);
+            if checkout.join(&candidate_signature).exists()
+                && (candidate != file || signature.is_none())
+            {
+                self.git(&checkout, &["rm", "--quiet", &candidate_signature])
+                    .await?;
+            }
+        }
+
+        let path = checkout.join(&file);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&path, spec).await?;
+        self.git(&checkout, &["add", &file]).await?;
+        if let Some(signature) = signature {
+            tokio::fs::write(checkout.join(&signature_file), signature.to_json()).await?;
+            self.git(&checkout, &["add", &signature_file]).await?;
+        }
+
+        let (subject, body) = commit_message(entry, previous.as_deref(), spec);
+        self.commit_and_push(&checkout, &subject, &body).await?;
+        files.insert(entry.id.clone(), file);
+        Ok(())
+    }
+
+    async fn unpublish(&self, id: &str) -> Result<(), PublishError> {
+        let mut files = self.files.lock().await;
+        let Some(file) = files.get(id).cloned() else {
+            return Ok(());
+        };
+        let checkout = self.sync_checkout().await?;
+
+        if checkout.join(&file).exists() {
+            self.git(&checkout, &["rm", "--quiet", &file]).await?;
+            let signature_file = format!("{}{}", file, SIGNATURE_SUFFIX);
+            if checkout.join(&signature_file).exists() {
+                self.git(&checkout, &["rm", "--quiet", &signature_file])
+                    .await?;
+            }
+            self.commit_and_push(
+                &checkout,
+                &format!("Remove {} spec", id),
+                "The API is no longer part of the discovery catalog.",
+            )
+            .await?;
+        }
+        files.remove(id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commit_message_summarizes_changes() {
+        let entry = ApiInventoryEntry {
+            name: "Orders API".to_string(),
+            ..ApiInventoryEntry::new(
+                "shop",
+                "orders",
+                "http://orders.shop.svc.cluster.local:8080/openapi.json",
+            )
+        };
+        let old =
+            "openapi: 3.0.0\ninfo: {title: Orders, version: '1'}\npaths:\n  /orders: {get: {}}\n";
+        let new = "openapi: 3.0.0\ninfo: {title: Orders, version: '2'}\npaths: {}\n";
+
+        let (subject, body) = commit_message(&entry, None, old);
+        assert_eq!(subject, "Add Orders API spec");
+        assert_eq!(body, "Discovered shop/orders (version 1).");
+
+        let (subject, body) = commit_message(&entry, Some(old), new);
+        assert_eq!(subject, "Update Orders API spec (breaking)");
+        assert!(body.contains("Version: 2"));
+        assert!(body.contains("- /orders: path removed"));
+    }
+}
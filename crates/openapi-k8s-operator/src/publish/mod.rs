@@ -0,0 +1,393 @@
+//! Pushing discovered specs to external systems (developer portals, gateways, ...).
+//!
+//! Each target implements [`Publisher`]. The [`PublishManager`] remembers a hash of the
+//! last spec pushed per target and entry, so reconciles only publish when a spec
+//! actually changed. Publish failures are logged and retried on the next reconcile;
+//! they never fail the reconcile itself. When the operator signs specs, targets that can
+//! store extra files keep the detached signature next to the spec.
+
+mod apicurio;
+mod aws;
+mod azure;
+mod git;
+mod portal;
+mod s3;
+mod swaggerhub;
+
+use async_trait::async_trait;
+use openapi_common::s3::S3Error;
+use openapi_common::signing::SpecSignature;
+use openapi_common::{ApiInventoryEntry, annotations::ApiDocAnnotations, namespace_utils, tenancy};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+pub use portal::PortalSyncConfig;
+
+/// Comma-separated cloud gateways to publish annotated APIs to (`aws`, `azure`)
+pub const GATEWAY_PUBLISH_ENV: &str = "GATEWAY_PUBLISH";
+/// Publishers per tenant (`payments=kong-portal|git,retail=swaggerhub`); unlisted tenants use all
+pub const TENANT_PUBLISHERS_ENV: &str = "TENANT_PUBLISHERS";
+
+#[derive(Debug)]
+pub enum PublishError {
+    Http(reqwest::Error),
+    /// The target answered with a non-success status
+    Status {
+        code: u16,
+        body: String,
+    },
+    /// Invalid publisher configuration
+    Config(String),
+    /// The spec could not be converted to the format the target expects
+    InvalidSpec(String),
+    /// A git command failed
+    Git(String),
+    Io(std::io::Error),
+    S3(S3Error),
+}
+
+impl fmt::Display for PublishError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PublishError::Http(e) => write!(f, "HTTP error: {}", e),
+            PublishError::Status { code, body } => write!(f, "status {}: {}", code, body),
+            PublishError::Config(msg) => write!(f, "invalid configuration: {}", msg),
+            PublishError::InvalidSpec(msg) => write!(f, "invalid spec: {}", msg),
+            PublishError::Git(msg) => write!(f, "{}", msg),
+            PublishError::Io(e) => write!(f, "IO error: {}", e),
+            PublishError::S3(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for PublishError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PublishError::Http(e) => Some(e),
+            PublishError::Io(e) => Some(e),
+            PublishError::S3(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for PublishError {
+    fn from(err: reqwest::Error) -> Self {
+        PublishError::Http(err)
+    }
+}
+
+impl From<std::io::Error> for PublishError {
+    fn from(err: std::io::Error) -> Self {
+        PublishError::Io(err)
+    }
+}
+
+impl From<S3Error> for PublishError {
+    fn from(err: S3Error) -> Self {
+        PublishError::S3(err)
+    }
+}
+
+/// Turns a non-success response into [`PublishError::Status`]
+async fn check_response(response: reqwest::Response) -> Result<reqwest::Response, PublishError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    Err(PublishError::Status {
+        code: status.as_u16(),
+        body: body.chars().take(512).collect(),
+    })
+}
+
+/// An external system specs are pushed to
+#[async_trait]
+pub trait Publisher: Send + Sync {
+    /// Short name used in logs
+    fn name(&self) -> &str;
+
+    /// Whether the entry should be published to this target
+    fn accepts(&self, _entry: &ApiInventoryEntry, _annotations: &ApiDocAnnotations) -> bool {
+        true
+    }
+
+    /// Creates or updates the entry's spec, and its signature if the target can store it
+    async fn publish(
+        &self,
+        entry: &ApiInventoryEntry,
+        spec: &str,
+        signature: Option<&SpecSignature>,
+    ) -> Result<(), PublishError>;
+
+    /// Removes a previously published entry, identified by its id
+    async fn unpublish(&self, id: &str) -> Result<(), PublishError>;
+}
+
+/// Namespaces a publisher is enabled for. Empty means all namespaces.
+#[derive(Debug, Clone, Default)]
+pub struct NamespaceFilter(Vec<String>);
+
+impl NamespaceFilter {
+    /// Reads a comma-separated list of namespaces or glob patterns
+    pub fn from_env(name: &str) -> Self {
+        Self(
+            std::env::var(name)
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+        )
+    }
+
+    pub fn allows(&self, namespace: &str) -> bool {
+        self.0.is_empty() || namespace_utils::namespace_matches(&self.0, namespace)
+    }
+}
+
+fn content_hash(spec: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    spec.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Fans out spec changes to all configured publishers
+#[derive(Default)]
+pub struct PublishManager {
+    publishers: Vec<Box<dyn Publisher>>,
+    /// Hash of the last spec successfully published, keyed by (publisher, entry id)
+    published: Mutex<HashMap<(String, String), u64>>,
+    /// Tenant -> names of the publishers its entries are exported to
+    tenant_targets: HashMap<String, Vec<String>>,
+}
+
+impl PublishManager {
+    pub fn new(publishers: Vec<Box<dyn Publisher>>) -> Self {
+        Self {
+            publishers,
+            published: Mutex::new(HashMap::new()),
+            tenant_targets: HashMap::new(),
+        }
+    }
+
+    /// Restricts the entries of the listed tenants to the given publishers
+    pub fn with_tenant_targets(mut self, tenant_targets: HashMap<String, Vec<String>>) -> Self {
+        self.tenant_targets = tenant_targets;
+        self
+    }
+
+    fn exports_to(&self, entry: &ApiInventoryEntry, publisher: &str) -> bool {
+        match entry
+            .tenant
+            .as_ref()
+            .and_then(|tenant| self.tenant_targets.get(tenant))
+        {
+            Some(targets) => targets.iter().any(|target| target == publisher),
+            None => true,
+        }
+    }
+
+    /// Builds all publishers enabled through environment variables
+    pub fn from_env() -> Result<Self, PublishError> {
+        let mut publishers: Vec<Box<dyn Publisher>> = Vec::new();
+        if let Some(config) = PortalSyncConfig::from_env()? {
+            publishers.push(config.into_publisher()?);
+        }
+        if let Some(swaggerhub) = swaggerhub::SwaggerHubPublisher::from_env()? {
+            publishers.push(Box::new(swaggerhub));
+        }
+        if let Some(apicurio) = apicurio::ApicurioPublisher::from_env()? {
+            publishers.push(Box::new(apicurio));
+        }
+        if let Some(git) = git::GitSyncPublisher::from_env()? {
+            publishers.push(Box::new(git));
+        }
+        if let Some(s3) = s3::S3ExportPublisher::from_env()? {
+            publishers.push(Box::new(s3));
+        }
+        for gateway in std::env::var(GATEWAY_PUBLISH_ENV)
+            .unwrap_or_default()
+            .split(',')
+        {
+            match gateway.trim() {
+                "" => {}
+                "aws" => publishers.push(Box::new(aws::AwsApiGatewayPublisher::from_env()?)),
+                "azure" => publishers.push(Box::new(azure::AzureApimPublisher::from_env()?)),
+                other => {
+                    return Err(PublishError::Config(format!(
+                        "unknown gateway \"{}\" in {}, expected aws or azure",
+                        other, GATEWAY_PUBLISH_ENV
+                    )));
+                }
+            }
+        }
+
+        for publisher in &publishers {
+            info!("Spec publishing enabled: {}", publisher.name());
+        }
+
+        let tenant_targets =
+            tenancy::parse_key_values(&std::env::var(TENANT_PUBLISHERS_ENV).unwrap_or_default())
+                .into_iter()
+                .map(|(tenant, targets)| {
+                    let targets = targets.split('|').map(|t| t.trim().to_string()).collect();
+                    (tenant, targets)
+                })
+                .collect();
+        Ok(Self::new(publishers).with_tenant_targets(tenant_targets))
+    }
+
+    /// Publishes the spec to every accepting target it changed for since the last push
+    pub async fn sync(
+        &self,
+        entry: &ApiInventoryEntry,
+        annotations: &ApiDocAnnotations,
+        spec: &str,
+        signature: Option<&SpecSignature>,
+    ) {
+        let hash = content_hash(spec);
+        for publisher in &self.publishers {
+            if !self.exports_to(entry, publisher.name()) || !publisher.accepts(entry, annotations) {
+                continue;
+            }
+            let key = (publisher.name().to_string(), entry.id.clone());
+            if self.published.lock().await.get(&key) == Some(&hash) {
+                continue;
+            }
+
+            match publisher.publish(entry, spec, signature).await {
+                Ok(()) => {
+                    info!("Published {} to {}", entry.id, publisher.name());
+                    self.published.lock().await.insert(key, hash);
+                }
+                Err(e) => error!(
+                    "Failed to publish {} to {}: {}",
+                    entry.id,
+                    publisher.name(),
+                    e
+                ),
+            }
+        }
+    }
+
+    /// Removes the entry from every target it was published to by this process
+    pub async fn remove(&self, id: &str) {
+        for publisher in &self.publishers {
+            let key = (publisher.name().to_string(), id.to_string());
+            if !self.published.lock().await.contains_key(&key) {
+                continue;
+            }
+
+            match publisher.unpublish(id).await {
+                Ok(()) => {
+                    info!("Unpublished {} from {}", id, publisher.name());
+                    self.published.lock().await.remove(&key);
+                }
+                Err(e) => error!(
+                    "Failed to unpublish {} from {}: {}",
+                    id,
+                    publisher.name(),
+                    e
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct CountingPublisher(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl Publisher for CountingPublisher {
+        fn name(&self) -> &str {
+            "counting"
+        }
+
+        async fn publish(
+            &self,
+            _: &ApiInventoryEntry,
+            _: &str,
+            _: Option<&SpecSignature>,
+        ) -> Result<(), PublishError> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+
+        async fn unpublish(&self, _: &str) -> Result<(), PublishError> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn publishes_only_changed_specs() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let manager = PublishManager::new(vec![Box::new(CountingPublisher(count.clone()))]);
+        let entry = ApiInventoryEntry {
+            name: "Users".to_string(),
+            ..ApiInventoryEntry::new(
+                "default",
+                "users",
+                "http://users.default.svc.cluster.local:8080/openapi.json",
+            )
+        };
+
+        let annotations = ApiDocAnnotations::default();
+        manager
+            .sync(&entry, &annotations, "{\"openapi\":\"3.0.0\"}", None)
+            .await;
+        manager
+            .sync(&entry, &annotations, "{\"openapi\":\"3.0.0\"}", None)
+            .await;
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        manager
+            .sync(&entry, &annotations, "{\"openapi\":\"3.1.0\"}", None)
+            .await;
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn tenant_entries_only_go_to_their_targets() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let manager = PublishManager::new(vec![Box::new(CountingPublisher(count.clone()))])
+            .with_tenant_targets(HashMap::from([(
+                "payments".to_string(),
+                vec!["portal".to_string()],
+            )]));
+        let mut entry = ApiInventoryEntry {
+            name: "Orders".to_string(),
+            tenant: Some("payments".to_string()),
+            ..ApiInventoryEntry::new(
+                "shop",
+                "orders",
+                "http://orders.shop.svc.cluster.local:8080/openapi.json",
+            )
+        };
+
+        let annotations = ApiDocAnnotations::default();
+        manager.sync(&entry, &annotations, "{}", None).await;
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+
+        entry.tenant = Some("retail".to_string());
+        manager.sync(&entry, &annotations, "{}", None).await;
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn namespace_filter_defaults_to_all() {
+        assert!(NamespaceFilter::default().allows("anything"));
+        let filter = NamespaceFilter(vec!["team-*".to_string()]);
+        assert!(filter.allows("team-a"));
+        assert!(!filter.allows("kube-system"));
+    }
+}
@@ -0,0 +1,265 @@
+//! Developer portal sync: Kong Dev Portal files API, or a generic HTTP portal API.
+
+use async_trait::async_trait;
+use openapi_common::signing::SpecSignature;
+use openapi_common::{ApiInventoryEntry, annotations::ApiDocAnnotations, spec_utils};
+use std::time::Duration;
+
+use super::{NamespaceFilter, PublishError, Publisher, check_response};
+
+pub const PORTAL_SYNC_ENV: &str = "PORTAL_SYNC";
+pub const PORTAL_SYNC_URL_ENV: &str = "PORTAL_SYNC_URL";
+pub const PORTAL_SYNC_TOKEN_ENV: &str = "PORTAL_SYNC_TOKEN";
+pub const PORTAL_SYNC_WORKSPACE_ENV: &str = "PORTAL_SYNC_WORKSPACE";
+pub const PORTAL_SYNC_NAMESPACES_ENV: &str = "PORTAL_SYNC_NAMESPACES";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortalKind {
+    /// Kong Dev Portal, specs are stored as `specs/<id>.json` portal files
+    Kong,
+    /// `PUT <url>/<id>` with a JSON document, `DELETE <url>/<id>` on removal
+    Http,
+}
+
+#[derive(Debug, Clone)]
+pub struct PortalSyncConfig {
+    pub kind: PortalKind,
+    pub url: String,
+    pub token: Option<String>,
+    /// Kong workspace, ignored for generic portals
+    pub workspace: String,
+    pub namespaces: NamespaceFilter,
+}
+
+impl PortalSyncConfig {
+    /// Returns `None` when `PORTAL_SYNC` is unset
+    pub fn from_env() -> Result<Option<Self>, PublishError> {
+        let kind = match std::env::var(PORTAL_SYNC_ENV)
+            .ok()
+            .as_deref()
+            .map(str::trim)
+        {
+            None | Some("") => return Ok(None),
+            Some("kong") => PortalKind::Kong,
+            Some("http") => PortalKind::Http,
+            Some(other) => {
+                return Err(PublishError::Config(format!(
+                    "{} must be \"kong\" or \"http\", got \"{}\"",
+                    PORTAL_SYNC_ENV, other
+                )));
+            }
+        };
+        let url = std::env::var(PORTAL_SYNC_URL_ENV)
+            .map_err(|_| PublishError::Config(format!("{} is required", PORTAL_SYNC_URL_ENV)))?;
+
+        Ok(Some(Self {
+            kind,
+            url: url.trim_end_matches('/').to_string(),
+            token: std::env::var(PORTAL_SYNC_TOKEN_ENV).ok(),
+            workspace: std::env::var(PORTAL_SYNC_WORKSPACE_ENV)
+                .unwrap_or_else(|_| "default".to_string()),
+            namespaces: NamespaceFilter::from_env(PORTAL_SYNC_NAMESPACES_ENV),
+        }))
+    }
+
+    pub fn into_publisher(self) -> Result<Box<dyn Publisher>, PublishError> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()?;
+        Ok(Box::new(PortalPublisher {
+            config: self,
+            client,
+        }))
+    }
+}
+
+struct PortalPublisher {
+    config: PortalSyncConfig,
+    client: reqwest::Client,
+}
+
+impl PortalPublisher {
+    fn resource_url(&self, id: &str) -> String {
+        match self.config.kind {
+            PortalKind::Kong => format!(
+                "{}/{}/files/specs/{}.json",
+                self.config.url, self.config.workspace, id
+            ),
+            PortalKind::Http => format!("{}/{}", self.config.url, urlencoding::encode(id)),
+        }
+    }
+
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match (&self.config.token, self.config.kind) {
+            (Some(token), PortalKind::Kong) => request.header("Kong-Admin-Token", token),
+            (Some(token), PortalKind::Http) => request.bearer_auth(token),
+            (None, _) => request,
+        }
+    }
+}
+
+#[async_trait]
+impl Publisher for PortalPublisher {
+    fn name(&self) -> &str {
+        match self.config.kind {
+            PortalKind::Kong => "kong-portal",
+            PortalKind::Http => "portal",
+        }
+    }
+
+    fn accepts(&self, entry: &ApiInventoryEntry, _annotations: &ApiDocAnnotations) -> bool {
+        self.config.namespaces.allows(&entry.namespace)
+    }
+
+    async fn publish(
+        &self,
+        entry: &ApiInventoryEntry,
+        spec: &str,
+        _signature: Option<&SpecSignature>,
+    ) -> Result<(), PublishError> {
+        let spec = spec_utils::parse_spec_to_json(spec)
+            .map_err(|e| PublishError::InvalidSpec(e.to_string()))?;
+
+        let body = match self.config.kind {
+            // Kong's files endpoint upserts on PUT
+            PortalKind::Kong => serde_json::json!({
+                "path": format!("specs/{}.json", entry.id),
+                "contents": spec.to_string(),
+            }),
+            PortalKind::Http => serde_json::json!({
+                "id": entry.id,
+                "name": entry.name,
+                "namespace": entry.namespace,
+                "service_name": entry.service_name,
+                "description": entry.description,
+                "spec": spec,
+            }),
+        };
+
+        let request = self.client.put(self.resource_url(&entry.id)).json(&body);
+        check_response(self.authorize(request).send().await?).await?;
+        Ok(())
+    }
+
+    async fn unpublish(&self, id: &str) -> Result<(), PublishError> {
+        let request = self.client.delete(self.resource_url(id));
+        match check_response(self.authorize(request).send().await?).await {
+            Err(PublishError::Status { code: 404, .. }) => Ok(()),
+            other => other.map(|_| ()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestServer;
+
+    const SPEC: &str = "{\"openapi\":\"3.0.0\",\"info\":{\"title\":\"Orders\",\"version\":\"1\"}}";
+
+    fn config(kind: PortalKind, url: &str, token: Option<&str>) -> PortalSyncConfig {
+        PortalSyncConfig {
+            kind,
+            url: url.to_string(),
+            token: token.map(str::to_string),
+            workspace: "dev".to_string(),
+            namespaces: NamespaceFilter::default(),
+        }
+    }
+
+    fn entry() -> ApiInventoryEntry {
+        ApiInventoryEntry::new("shop", "orders", "http://orders.shop.svc/openapi.json")
+    }
+
+    #[tokio::test]
+    async fn kong_files_are_upserted_with_the_admin_token() {
+        let server = TestServer::start(|_| (200, "{}".to_string())).await;
+        let publisher = config(PortalKind::Kong, &server.url, Some("secret"))
+            .into_publisher()
+            .unwrap();
+
+        publisher.publish(&entry(), SPEC, None).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "PUT");
+        assert_eq!(requests[0].uri, "/dev/files/specs/shop.orders.json");
+        assert_eq!(requests[0].header("kong-admin-token"), Some("secret"));
+        assert_eq!(requests[0].header("authorization"), None);
+        let body = requests[0].json();
+        assert_eq!(body["path"], "specs/shop.orders.json");
+        let contents: serde_json::Value =
+            serde_json::from_str(body["contents"].as_str().unwrap()).unwrap();
+        assert_eq!(contents["info"]["title"], "Orders");
+    }
+
+    #[tokio::test]
+    async fn http_portals_get_the_entry_as_json_with_a_bearer_token() {
+        let server = TestServer::start(|_| (204, String::new())).await;
+        let publisher = config(PortalKind::Http, &server.url, Some("secret"))
+            .into_publisher()
+            .unwrap();
+
+        let yaml = "openapi: 3.0.0\ninfo:\n  title: Orders\n  version: '1'\npaths: {}\n";
+        publisher.publish(&entry(), yaml, None).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests[0].method, "PUT");
+        assert_eq!(requests[0].uri, "/shop.orders");
+        assert_eq!(requests[0].header("authorization"), Some("Bearer secret"));
+        let body = requests[0].json();
+        assert_eq!(body["id"], "shop.orders");
+        assert_eq!(body["namespace"], "shop");
+        assert_eq!(body["service_name"], "orders");
+        assert_eq!(body["spec"]["info"]["title"], "Orders");
+    }
+
+    #[tokio::test]
+    async fn error_responses_become_status_errors() {
+        let server = TestServer::start(|_| (500, "portal down".to_string())).await;
+        let publisher = config(PortalKind::Http, &server.url, None)
+            .into_publisher()
+            .unwrap();
+
+        match publisher.publish(&entry(), SPEC, None).await {
+            Err(PublishError::Status { code, body }) => {
+                assert_eq!(code, 500);
+                assert_eq!(body, "portal down");
+            }
+            other => panic!("expected a status error, got {:?}", other),
+        }
+        assert!(matches!(
+            publisher.unpublish("shop.orders").await,
+            Err(PublishError::Status { code: 500, .. })
+        ));
+        assert_eq!(server.requests()[0].header("authorization"), None);
+    }
+
+    #[tokio::test]
+    async fn unpublishing_a_missing_resource_succeeds() {
+        let server = TestServer::start(|_| (404, "not found".to_string())).await;
+        let publisher = config(PortalKind::Kong, &server.url, None)
+            .into_publisher()
+            .unwrap();
+
+        publisher.unpublish("shop.orders").await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests[0].method, "DELETE");
+        assert_eq!(requests[0].uri, "/dev/files/specs/shop.orders.json");
+    }
+
+    #[tokio::test]
+    async fn invalid_specs_are_not_sent() {
+        let server = TestServer::start(|_| (200, String::new())).await;
+        let publisher = config(PortalKind::Http, &server.url, None)
+            .into_publisher()
+            .unwrap();
+
+        assert!(matches!(
+            publisher.publish(&entry(), "openapi: [3.0", None).await,
+            Err(PublishError::InvalidSpec(_))
+        ));
+        assert!(server.requests().is_empty());
+    }
+}
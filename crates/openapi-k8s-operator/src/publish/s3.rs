@@ -0,0 +1,375 @@
+//! Archival export of spec revisions and the catalog index to S3.
+//!
+//! Every distinct spec revision is stored under a content-addressed key
+//! (`<prefix>specs/<namespace>/<service>/<sha256>.<json|yaml>`), so uploads are
+//! idempotent and old revisions are never overwritten. Signed revisions get their
+//! detached signature as `<key>.sig`. `<prefix>index.json` lists the current catalog
+//! with the latest revision and revision history of every API.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use openapi_common::ApiInventoryEntry;
+use openapi_common::s3::S3Client;
+use openapi_common::signing::{SIGNATURE_SUFFIX, SpecSignature};
+use openapi_common::digest::sha256_hex;
+use openapi_common::sigv4::{AWS_REGION_ENV, AwsCredentials};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tokio::sync::Mutex;
+
+use super::{PublishError, Publisher};
+
+pub const S3_EXPORT_BUCKET_ENV: &str = "S3_EXPORT_BUCKET";
+pub const S3_EXPORT_PREFIX_ENV: &str = "S3_EXPORT_PREFIX";
+pub const S3_EXPORT_REGION_ENV: &str = "S3_EXPORT_REGION";
+pub const S3_EXPORT_ENDPOINT_ENV: &str = "S3_EXPORT_ENDPOINT";
+
+/// Revisions listed per API in the index; older objects stay in the bucket
+const MAX_INDEXED_REVISIONS: usize = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Revision {
+    sha256: String,
+    key: String,
+    uploaded_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct IndexedApi {
+    #[serde(flatten)]
+    entry: ApiInventoryEntry,
+    latest: Revision,
+    /// Newest first
+    revisions: Vec<Revision>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct ExportIndex {
+    updated_at: Option<DateTime<Utc>>,
+    apis: BTreeMap<String, IndexedApi>,
+}
+
+pub struct S3ExportPublisher {
+    client: S3Client,
+    prefix: String,
+    /// Loaded from the bucket on first use so restarts keep existing history
+    index: Mutex<Option<ExportIndex>>,
+}
+
+impl S3ExportPublisher {
+    /// Returns `None` when `S3_EXPORT_BUCKET` is unset
+    pub fn from_env() -> Result<Option<Self>, PublishError> {
+        let Ok(bucket) = std::env::var(S3_EXPORT_BUCKET_ENV) else {
+            return Ok(None);
+        };
+        let credentials = AwsCredentials::from_env().ok_or_else(|| {
+            PublishError::Config(
+                "AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY are required for S3 export"
+                    .to_string(),
+            )
+        })?;
+        let region = std::env::var(S3_EXPORT_REGION_ENV)
+            .or_else(|_| std::env::var(AWS_REGION_ENV))
+            .unwrap_or_else(|_| "us-east-1".to_string());
+        let endpoint = std::env::var(S3_EXPORT_ENDPOINT_ENV).ok();
+
+        let prefix = std::env::var(S3_EXPORT_PREFIX_ENV).unwrap_or_default();
+        let prefix = match prefix.trim_matches('/') {
+            "" => String::new(),
+            trimmed => format!("{}/", trimmed),
+        };
+
+        Ok(Some(Self {
+            client: S3Client::new(credentials, &region, &bucket, endpoint.as_deref())?,
+            prefix,
+            index: Mutex::new(None),
+        }))
+    }
+
+    fn index_key(&self) -> String {
+        format!("{}index.json", self.prefix)
+    }
+
+    async fn load_index(&self) -> Result<ExportIndex, PublishError> {
+        match self.client.get_object(&self.index_key()).await? {
+            Some(body) => serde_json::from_slice(&body).map_err(|e| {
+                PublishError::InvalidSpec(format!(
+                    "existing {} is invalid: {}",
+                    self.index_key(),
+                    e
+                ))
+            }),
+            None => Ok(ExportIndex::default()),
+        }
+    }
+
+    async fn upload_index(&self, index: &mut ExportIndex) -> Result<(), PublishError> {
+        index.updated_at = Some(Utc::now());
+        let body = serde_json::to_vec_pretty(index)
+            .map_err(|e| PublishError::InvalidSpec(e.to_string()))?;
+        self.client
+            .put_object(&self.index_key(), "application/json", body)
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Publisher for S3ExportPublisher {
+    fn name(&self) -> &str {
+        "s3-export"
+    }
+
+    async fn publish(
+        &self,
+        entry: &ApiInventoryEntry,
+        spec: &str,
+        signature: Option<&SpecSignature>,
+    ) -> Result<(), PublishError> {
+        let mut guard = self.index.lock().await;
+        if guard.is_none() {
+            *guard = Some(self.load_index().await?);
+        }
+        let index = guard.as_mut().expect("index loaded above");
+
+        let sha256 = sha256_hex(spec.as_bytes());
+        let (extension, content_type) = if spec.trim_start().starts_with('{') {
+            ("json", "application/json")
+        } else {
+            ("yaml", "application/yaml")
+        };
+        let key = format!(
+            "{}specs/{}/{}/{}.{}",
+            self.prefix, entry.namespace, entry.service_name, sha256, extension
+        );
+        self.client
+            .put_object(&key, content_type, spec.as_bytes().to_vec())
+            .await?;
+        if let Some(signature) = signature {
+            self.client
+                .put_object(
+                    &format!("{}{}", key, SIGNATURE_SUFFIX),
+                    "application/json",
+                    signature.to_json().into_bytes(),
+                )
+                .await?;
+        }
+
+        let revision = Revision {
+            sha256,
+            key,
+            uploaded_at: Utc::now(),
+        };
+        let mut revisions = index
+            .apis
+            .remove(&entry.id)
+            .map(|api| api.revisions)
+            .unwrap_or_default();
+        if revisions.first().map(|r| &r.sha256) != Some(&revision.sha256) {
+            revisions.insert(0, revision.clone());
+            revisions.truncate(MAX_INDEXED_REVISIONS);
+        }
+        index.apis.insert(
+            entry.id.clone(),
+            IndexedApi {
+                entry: entry.clone(),
+                latest: revisions[0].clone(),
+                revisions,
+            },
+        );
+
+        self.upload_index(index).await
+    }
+
+    /// Drops the API from the index; archived revisions are kept
+    async fn unpublish(&self, id: &str) -> Result<(), PublishError> {
+        let mut guard = self.index.lock().await;
+        if guard.is_none() {
+            *guard = Some(self.load_index().await?);
+        }
+        let index = guard.as_mut().expect("index loaded above");
+
+        if index.apis.remove(id).is_some() {
+            self.upload_index(index).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{Recorded, TestServer};
+    use openapi_common::s3::S3Error;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    const SPEC: &str = "{\"openapi\":\"3.0.0\",\"info\":{\"title\":\"Orders\",\"version\":\"1\"}}";
+
+    type Objects = Arc<std::sync::Mutex<HashMap<String, String>>>;
+
+    /// In-memory bucket answering S3 object requests
+    async fn bucket() -> (TestServer, Objects) {
+        let objects = Objects::default();
+        let stored = objects.clone();
+        let server = TestServer::start(move |request: &Recorded| {
+            let mut objects = stored.lock().unwrap();
+            match request.method.as_str() {
+                "PUT" => {
+                    objects.insert(request.uri.clone(), request.body.clone());
+                    (200, String::new())
+                }
+                _ => match objects.get(&request.uri) {
+                    Some(body) => (200, body.clone()),
+                    None => (404, "NoSuchKey".to_string()),
+                },
+            }
+        })
+        .await;
+        (server, objects)
+    }
+
+    fn publisher(endpoint: &str) -> S3ExportPublisher {
+        let credentials = AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: None,
+        };
+        S3ExportPublisher {
+            client: S3Client::new(credentials, "eu-west-1", "archive", Some(endpoint)).unwrap(),
+            prefix: "catalog/".to_string(),
+            index: Mutex::new(None),
+        }
+    }
+
+    fn entry() -> ApiInventoryEntry {
+        ApiInventoryEntry::new("shop", "orders", "http://orders.shop.svc/openapi.json")
+    }
+
+    fn index(objects: &Objects) -> ExportIndex {
+        serde_json::from_str(&objects.lock().unwrap()["/archive/catalog/index.json"]).unwrap()
+    }
+
+    #[tokio::test]
+    async fn revisions_are_stored_content_addressed_with_their_signature() {
+        let (server, objects) = bucket().await;
+        let signature = SpecSignature {
+            algorithm: "ed25519".to_string(),
+            key_id: "0123456789abcdef".to_string(),
+            sha256: sha256_hex(SPEC.as_bytes()),
+            signature: "c2lnbmF0dXJl".to_string(),
+        };
+
+        publisher(&server.url)
+            .publish(&entry(), SPEC, Some(&signature))
+            .await
+            .unwrap();
+
+        let key = format!(
+            "catalog/specs/shop/orders/{}.json",
+            sha256_hex(SPEC.as_bytes())
+        );
+        let requests: Vec<(String, String)> = server
+            .requests()
+            .into_iter()
+            .map(|r| (r.method, r.uri))
+            .collect();
+        assert_eq!(
+            requests,
+            [
+                ("GET", "/archive/catalog/index.json".to_string()),
+                ("PUT", format!("/archive/{}", key)),
+                ("PUT", format!("/archive/{}.sig", key)),
+                ("PUT", "/archive/catalog/index.json".to_string()),
+            ]
+            .map(|(method, uri)| (method.to_string(), uri))
+        );
+        assert_eq!(objects.lock().unwrap()[&format!("/archive/{}", key)], SPEC);
+        assert_eq!(
+            objects.lock().unwrap()[&format!("/archive/{}.sig", key)],
+            signature.to_json()
+        );
+
+        let index = index(&objects);
+        let api = &index.apis["shop.orders"];
+        assert_eq!(api.latest.key, key);
+        assert_eq!(api.revisions.len(), 1);
+        assert!(index.updated_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn requests_are_signed_for_s3() {
+        let (server, _) = bucket().await;
+        publisher(&server.url)
+            .publish(&entry(), SPEC, None)
+            .await
+            .unwrap();
+
+        for request in server.requests() {
+            let authorization = request.header("authorization").unwrap();
+            assert!(
+                authorization.contains("/eu-west-1/s3/aws4_request"),
+                "{}",
+                authorization
+            );
+            assert!(request.header("x-amz-content-sha256").is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn history_survives_restarts_and_keeps_the_newest_first() {
+        let (server, objects) = bucket().await;
+        publisher(&server.url)
+            .publish(&entry(), SPEC, None)
+            .await
+            .unwrap();
+
+        let restarted = publisher(&server.url);
+        let yaml = "openapi: 3.0.0\ninfo:\n  title: Orders\n  version: '2'\n";
+        restarted.publish(&entry(), yaml, None).await.unwrap();
+        restarted.publish(&entry(), yaml, None).await.unwrap();
+
+        let index = index(&objects);
+        let revisions = &index.apis["shop.orders"].revisions;
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[0].sha256, sha256_hex(yaml.as_bytes()));
+        assert!(revisions[0].key.ends_with(".yaml"));
+        assert_eq!(revisions[1].sha256, sha256_hex(SPEC.as_bytes()));
+    }
+
+    #[tokio::test]
+    async fn unpublishing_drops_the_api_from_the_index_only() {
+        let (server, objects) = bucket().await;
+        let publisher = publisher(&server.url);
+        publisher.publish(&entry(), SPEC, None).await.unwrap();
+
+        publisher.unpublish("shop.carts").await.unwrap();
+        assert_eq!(server.requests().len(), 3);
+
+        publisher.unpublish("shop.orders").await.unwrap();
+        assert!(index(&objects).apis.is_empty());
+        assert_eq!(objects.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn bucket_errors_and_invalid_indexes_are_reported() {
+        let server = TestServer::start(|_| (403, "AccessDenied".to_string())).await;
+        match publisher(&server.url).publish(&entry(), SPEC, None).await {
+            Err(PublishError::S3(S3Error::Status { code, body })) => {
+                assert_eq!(code, 403);
+                assert_eq!(body, "AccessDenied");
+            }
+            other => panic!("expected an S3 status error, got {:?}", other),
+        }
+
+        let (server, objects) = bucket().await;
+        objects.lock().unwrap().insert(
+            "/archive/catalog/index.json".to_string(),
+            "not json".to_string(),
+        );
+        assert!(matches!(
+            publisher(&server.url).unpublish("shop.orders").await,
+            Err(PublishError::InvalidSpec(_))
+        ));
+    }
+}
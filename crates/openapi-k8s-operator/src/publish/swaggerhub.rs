@@ -0,0 +1,227 @@
+//! SwaggerHub registry publishing.
+//!
+//! Each entry is saved as the API `<owner>/<entry id>`; the version comes from the
+//! spec's `info.version`, so a changed version shows up as a new SwaggerHub version and
+//! an unchanged one is overwritten in place.
+
+use async_trait::async_trait;
+use openapi_common::signing::SpecSignature;
+use openapi_common::{ApiInventoryEntry, OpenApiDocument, annotations::ApiDocAnnotations};
+use std::time::Duration;
+
+use super::{NamespaceFilter, PublishError, Publisher, check_response};
+
+pub const SWAGGERHUB_OWNER_ENV: &str = "SWAGGERHUB_OWNER";
+pub const SWAGGERHUB_API_KEY_ENV: &str = "SWAGGERHUB_API_KEY";
+pub const SWAGGERHUB_URL_ENV: &str = "SWAGGERHUB_URL";
+pub const SWAGGERHUB_PRIVATE_ENV: &str = "SWAGGERHUB_PRIVATE";
+pub const SWAGGERHUB_NAMESPACES_ENV: &str = "SWAGGERHUB_NAMESPACES";
+
+const DEFAULT_SWAGGERHUB_URL: &str = "https://api.swaggerhub.com";
+
+pub struct SwaggerHubPublisher {
+    base_url: String,
+    owner: String,
+    api_key: String,
+    private: bool,
+    namespaces: NamespaceFilter,
+    client: reqwest::Client,
+}
+
+impl SwaggerHubPublisher {
+    /// Returns `None` when `SWAGGERHUB_OWNER` is unset
+    pub fn from_env() -> Result<Option<Self>, PublishError> {
+        let Ok(owner) = std::env::var(SWAGGERHUB_OWNER_ENV) else {
+            return Ok(None);
+        };
+        let api_key = std::env::var(SWAGGERHUB_API_KEY_ENV).map_err(|_| {
+            PublishError::Config(format!(
+                "{} is required when {} is set",
+                SWAGGERHUB_API_KEY_ENV, SWAGGERHUB_OWNER_ENV
+            ))
+        })?;
+
+        Ok(Some(Self {
+            base_url: std::env::var(SWAGGERHUB_URL_ENV)
+                .unwrap_or_else(|_| DEFAULT_SWAGGERHUB_URL.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            owner,
+            api_key,
+            private: std::env::var(SWAGGERHUB_PRIVATE_ENV)
+                .map(|v| v != "false")
+                .unwrap_or(true),
+            namespaces: NamespaceFilter::from_env(SWAGGERHUB_NAMESPACES_ENV),
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(30))
+                .build()?,
+        }))
+    }
+
+    fn api_url(&self, id: &str) -> String {
+        format!(
+            "{}/apis/{}/{}",
+            self.base_url,
+            urlencoding::encode(&self.owner),
+            urlencoding::encode(id)
+        )
+    }
+}
+
+#[async_trait]
+impl Publisher for SwaggerHubPublisher {
+    fn name(&self) -> &str {
+        "swaggerhub"
+    }
+
+    fn accepts(&self, entry: &ApiInventoryEntry, _annotations: &ApiDocAnnotations) -> bool {
+        self.namespaces.allows(&entry.namespace)
+    }
+
+    async fn publish(
+        &self,
+        entry: &ApiInventoryEntry,
+        spec: &str,
+        _signature: Option<&SpecSignature>,
+    ) -> Result<(), PublishError> {
+        let document =
+            OpenApiDocument::parse(spec).map_err(|e| PublishError::InvalidSpec(e.to_string()))?;
+        let content_type = if spec.trim_start().starts_with('{') {
+            "application/json"
+        } else {
+            "application/yaml"
+        };
+
+        let mut query = vec![
+            ("isPrivate", self.private.to_string()),
+            ("force", "true".to_string()),
+        ];
+        if !document.info.version.is_empty() {
+            query.push(("version", document.info.version.clone()));
+        }
+
+        let response = self
+            .client
+            .post(self.api_url(&entry.id))
+            .query(&query)
+            .header("Authorization", &self.api_key)
+            .header("Content-Type", content_type)
+            .body(spec.to_string())
+            .send()
+            .await?;
+        check_response(response).await?;
+        Ok(())
+    }
+
+    async fn unpublish(&self, id: &str) -> Result<(), PublishError> {
+        let response = self
+            .client
+            .delete(self.api_url(id))
+            .header("Authorization", &self.api_key)
+            .send()
+            .await?;
+        match check_response(response).await {
+            Err(PublishError::Status { code: 404, .. }) => Ok(()),
+            other => other.map(|_| ()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestServer;
+
+    fn publisher(base_url: &str, private: bool) -> SwaggerHubPublisher {
+        SwaggerHubPublisher {
+            base_url: base_url.to_string(),
+            owner: "acme corp".to_string(),
+            api_key: "hub-key".to_string(),
+            private,
+            namespaces: NamespaceFilter::default(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn entry() -> ApiInventoryEntry {
+        ApiInventoryEntry::new("shop", "orders", "http://orders.shop.svc/openapi.json")
+    }
+
+    #[tokio::test]
+    async fn specs_are_saved_under_their_info_version() {
+        let server = TestServer::start(|_| (201, String::new())).await;
+        let spec = "openapi: 3.0.0\ninfo:\n  title: Orders\n  version: 2.1.0\npaths: {}\n";
+
+        publisher(&server.url, true)
+            .publish(&entry(), spec, None)
+            .await
+            .unwrap();
+
+        let request = &server.requests()[0];
+        assert_eq!(request.method, "POST");
+        assert_eq!(
+            request.uri,
+            "/apis/acme%20corp/shop.orders?isPrivate=true&force=true&version=2.1.0"
+        );
+        assert_eq!(request.header("authorization"), Some("hub-key"));
+        assert_eq!(request.header("content-type"), Some("application/yaml"));
+        assert_eq!(request.body, spec);
+    }
+
+    #[tokio::test]
+    async fn json_specs_without_a_version_are_published_publicly() {
+        let server = TestServer::start(|_| (200, String::new())).await;
+        let spec = "{\"openapi\":\"3.0.0\",\"info\":{\"title\":\"Orders\",\"version\":\"\"}}";
+
+        publisher(&server.url, false)
+            .publish(&entry(), spec, None)
+            .await
+            .unwrap();
+
+        let request = &server.requests()[0];
+        assert_eq!(
+            request.uri,
+            "/apis/acme%20corp/shop.orders?isPrivate=false&force=true"
+        );
+        assert_eq!(request.header("content-type"), Some("application/json"));
+    }
+
+    #[tokio::test]
+    async fn unpublishing_deletes_the_api_and_ignores_missing_ones() {
+        let server = TestServer::start(|_| (404, String::new())).await;
+
+        publisher(&server.url, true)
+            .unpublish("shop.orders")
+            .await
+            .unwrap();
+
+        let request = &server.requests()[0];
+        assert_eq!(request.method, "DELETE");
+        assert_eq!(request.uri, "/apis/acme%20corp/shop.orders");
+        assert_eq!(request.header("authorization"), Some("hub-key"));
+    }
+
+    #[tokio::test]
+    async fn rejected_requests_become_status_errors() {
+        let server = TestServer::start(|_| (403, "forbidden".to_string())).await;
+        let publisher = publisher(&server.url, true);
+        let spec = "{\"openapi\":\"3.0.0\",\"info\":{\"title\":\"Orders\",\"version\":\"1\"}}";
+
+        match publisher.publish(&entry(), spec, None).await {
+            Err(PublishError::Status { code, body }) => {
+                assert_eq!(code, 403);
+                assert_eq!(body, "forbidden");
+            }
+            other => panic!("expected a status error, got {:?}", other),
+        }
+        assert!(matches!(
+            publisher.unpublish("shop.orders").await,
+            Err(PublishError::Status { code: 403, .. })
+        ));
+        assert!(matches!(
+            publisher.publish(&entry(), "openapi: [3.0", None).await,
+            Err(PublishError::InvalidSpec(_))
+        ));
+        assert_eq!(server.requests().len(), 2);
+    }
+}
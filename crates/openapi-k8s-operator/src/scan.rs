@@ -0,0 +1,431 @@
+//! `openapi-k8s-operator [OPTIONS] scan [--output <file>]`: the catalog, discovered once.
+//!
+//! Lists the Services of the watched namespaces (`--watch-namespaces`,
+//! `--watch-namespace-selector`, `--exclude-namespaces`) that are enabled by their annotations
+//! or by `DISCOVERY_LABEL_SELECTOR`, fetches their specs with the operator's fetch settings
+//! and writes the resulting `DiscoveryConfig` as JSON to stdout or the file, then exits.
+//! Nothing is written to the cluster, so it runs with read-only RBAC, e.g. in CI pipelines
+//! or to take a snapshot for air-gapped environments. Services whose spec cannot be fetched
+//! are left out and logged.
+
+use chrono::Utc;
+use k8s_openapi::api::core::v1::{Namespace, Service};
+use kube::{
+    Client, ResourceExt,
+    api::{Api, ListParams},
+    core::{Selector, SelectorExt},
+};
+use openapi_common::{
+    API_DOC_ENABLED_ANNOTATION, DiscoveryConfig,
+    annotations::{ApiDocAnnotations, FetchVia, with_namespace_defaults},
+    complexity::SpecMetrics,
+    digest::sha256_hex,
+    fetch::{SpecFetcher, TlsOptions},
+    namespace_utils,
+    schedule::RefreshWindow,
+};
+use std::collections::BTreeMap;
+use std::env;
+use tracing::{info, warn};
+
+use crate::config::OperatorConfig;
+use crate::error::AppError;
+use crate::{catalog_entry, fetch_service_spec, label_selector, service_proxy_path, service_url};
+
+pub fn invalid_input(message: String) -> AppError {
+    AppError::Io(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        message,
+    ))
+}
+
+/// Namespaces the operator would watch; `None` for all of them
+pub async fn watched_namespaces(
+    client: &Client,
+    config: &OperatorConfig,
+) -> Result<Option<Vec<String>>, AppError> {
+    let mut namespaces = match config.watch_namespaces() {
+        None => return Ok(None),
+        Some(namespaces) if namespaces.contains(&"current".to_string()) => {
+            match config.namespace_selector() {
+                Some(_) => Vec::new(),
+                None => vec![env::var("POD_NAMESPACE").unwrap_or_else(|_| "default".to_string())],
+            }
+        }
+        Some(namespaces) => namespaces,
+    };
+    if let Some(selector) = config.namespace_selector() {
+        let selected: Api<Namespace> = Api::all(client.clone());
+        let selected = selected
+            .list(&ListParams::default().labels(&selector))
+            .await?;
+        namespaces.extend(selected.items.iter().map(|ns| ns.name_any()));
+    }
+    Ok(Some(namespaces))
+}
+
+/// Services of the watched namespaces, listed per namespace unless patterns are involved
+pub async fn list_services(
+    client: &Client,
+    namespaces: Option<&[String]>,
+    exclude: &[String],
+) -> Result<Vec<Service>, AppError> {
+    let services = match namespaces {
+        Some(namespaces) if !namespaces.iter().any(|ns| namespace_utils::is_pattern(ns)) => {
+            let mut services = Vec::new();
+            for namespace in namespaces {
+                let api: Api<Service> = Api::namespaced(client.clone(), namespace);
+                services.extend(api.list(&ListParams::default()).await?.items);
+            }
+            services
+        }
+        _ => {
+            let api: Api<Service> = Api::all(client.clone());
+            api.list(&ListParams::default()).await?.items
+        }
+    };
+    Ok(services
+        .into_iter()
+        .filter(|service| {
+            let namespace = service.namespace().unwrap_or_default();
+            namespaces
+                .is_none_or(|namespaces| namespace_utils::namespace_matches(namespaces, &namespace))
+                && !namespace_utils::namespace_matches(exclude, &namespace)
+        })
+        .collect())
+}
+
+/// Annotations the operator acts on: the Service's over those of its namespace when
+/// `namespace_annotations` caches them (`NAMESPACE_DEFAULTS`), enabled by `label_selector`
+pub async fn effective_annotations(
+    client: &Client,
+    service: &Service,
+    namespace_annotations: Option<&mut BTreeMap<String, BTreeMap<String, String>>>,
+    label_selector: Option<&Selector>,
+) -> Result<BTreeMap<String, String>, AppError> {
+    let mut annotations = match namespace_annotations {
+        Some(cache) => {
+            let namespace = service.namespace().unwrap_or_default();
+            if !cache.contains_key(&namespace) {
+                let namespaces: Api<Namespace> = Api::all(client.clone());
+                let annotations = namespaces
+                    .get_opt(&namespace)
+                    .await?
+                    .map(|ns| ns.annotations().clone())
+                    .unwrap_or_default();
+                cache.insert(namespace.clone(), annotations);
+            }
+            with_namespace_defaults(cache.get(&namespace), service.annotations())
+        }
+        None => service.annotations().clone(),
+    };
+    if let Some(selector) = label_selector
+        && selector.matches(service.labels())
+    {
+        annotations
+            .entry(API_DOC_ENABLED_ANNOTATION.to_string())
+            .or_insert_with(|| "true".to_string());
+    }
+    Ok(annotations)
+}
+
+/// Runs the scan; `output` of `None` or `-` writes to stdout
+pub async fn run(config: &OperatorConfig, output: Option<String>) -> Result<(), AppError> {
+    let output = output.filter(|path| path != "-");
+    let client = Client::try_default().await?;
+
+    let label_selector = label_selector::from_env().map_err(invalid_input)?;
+    let refresh_window = RefreshWindow::from_env().map_err(invalid_input)?;
+    let namespace_defaults = config.namespace_defaults;
+    let fetch_via = FetchVia::from_env().map_err(invalid_input)?;
+    let fetcher = SpecFetcher::new(config.fetch_config()?)?;
+    let insecure_fetcher = fetcher.with_tls(TlsOptions {
+        insecure_skip_verify: true,
+        ..fetcher.config().tls.clone()
+    })?;
+
+    let namespaces = watched_namespaces(&client, config).await?;
+    let services =
+        list_services(&client, namespaces.as_deref(), &config.exclude_namespaces()).await?;
+    info!("Scanning {} services", services.len());
+
+    let mut namespace_annotations: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+    let mut apis = Vec::new();
+    let mut failed = 0;
+    for service in &services {
+        let namespace = service.namespace().unwrap_or_default();
+        let service_name = service.name_any();
+
+        let service_annotations = effective_annotations(
+            &client,
+            service,
+            namespace_defaults.then_some(&mut namespace_annotations),
+            label_selector.as_ref(),
+        )
+        .await?;
+        let (annotations, issues) = ApiDocAnnotations::parse(&service_annotations);
+        if annotations.exclude || !annotations.enabled {
+            continue;
+        }
+        for issue in &issues {
+            warn!(
+                "Service {}/{} has an invalid annotation: {}",
+                namespace, service_name, issue
+            );
+        }
+        let Some(url) = service_url(service, &annotations) else {
+            warn!(
+                "Service {}/{} has no port named by its annotations, skipping it",
+                namespace, service_name
+            );
+            failed += 1;
+            continue;
+        };
+
+        let fetcher = if annotations.insecure_skip_verify {
+            &insecure_fetcher
+        } else {
+            &fetcher
+        };
+        let proxy_path = (annotations.fetch_via.unwrap_or(fetch_via) == FetchVia::ApiServer)
+            .then(|| service_proxy_path(service, &annotations))
+            .flatten();
+        let fetched = fetch_service_spec(
+            fetcher,
+            &client,
+            &namespace,
+            &url,
+            proxy_path.as_deref(),
+            &annotations,
+            None,
+        )
+        .await?;
+        let spec = match fetched {
+            Ok(spec) => spec,
+            Err(failure) => {
+                warn!(
+                    "Spec of service {}/{} at {} could not be fetched: {}",
+                    namespace, service_name, url, failure
+                );
+                failed += 1;
+                continue;
+            }
+        };
+
+        let window = annotations
+            .refresh_window
+            .as_ref()
+            .or(refresh_window.as_ref());
+        let mut entry = catalog_entry(&namespace, &service_name, url, &annotations, window);
+        entry.metrics = SpecMetrics::compute(&spec).ok();
+        entry.spec_sha256 = Some(sha256_hex(spec.as_bytes()));
+        info!("Discovered {} ({} bytes)", entry.id, spec.len());
+        apis.push(entry);
+    }
+    apis.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let discovery_config = DiscoveryConfig {
+        apis,
+        last_updated: Utc::now(),
+        tombstones: Vec::new(),
+        shards: Vec::new(),
+    };
+    let json = serde_json::to_string_pretty(&discovery_config)?;
+    match &output {
+        Some(path) => std::fs::write(path, json + "\n")?,
+        None => println!("{}", json),
+    }
+    info!(
+        "Scanned {} APIs ({} skipped) into {}",
+        discovery_config.apis.len(),
+        failed,
+        output.as_deref().unwrap_or("stdout")
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Cli;
+    use crate::test_support::FakeApiServer;
+    use clap::Parser;
+    use serde_json::{Value, json};
+
+    fn service(namespace: &str, name: &str) -> Value {
+        json!({ "metadata": { "name": name, "namespace": namespace } })
+    }
+
+    fn list(items: Vec<Value>) -> Value {
+        json!({ "metadata": {}, "items": items })
+    }
+
+    fn names(services: &[Service]) -> Vec<String> {
+        services
+            .iter()
+            .map(|s| format!("{}/{}", s.namespace().unwrap(), s.name_any()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn watched_namespaces_include_those_of_the_selector() {
+        let api = FakeApiServer::start().await;
+        api.insert(
+            "/api/v1/namespaces",
+            list(vec![json!({ "metadata": { "name": "payments" } })]),
+        );
+        let cli = Cli::try_parse_from([
+            "openapi-k8s-operator",
+            "--watch-namespaces",
+            "shop",
+            "--watch-namespace-selector",
+            "team=payments",
+        ])
+        .unwrap();
+
+        let namespaces = watched_namespaces(&api.client(), &cli.config)
+            .await
+            .unwrap();
+        assert_eq!(
+            namespaces,
+            Some(vec!["shop".to_string(), "payments".to_string()])
+        );
+        let requests = api.requests();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].uri.contains("labelSelector=team%3Dpayments"));
+
+        let cli =
+            Cli::try_parse_from(["openapi-k8s-operator", "--watch-namespaces", "all"]).unwrap();
+        assert_eq!(
+            watched_namespaces(&api.client(), &cli.config)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn services_are_listed_per_namespace_unless_patterns_are_watched() {
+        let api = FakeApiServer::start().await;
+        api.insert(
+            "/api/v1/namespaces/shop/services",
+            list(vec![service("shop", "orders")]),
+        );
+        api.insert(
+            "/api/v1/services",
+            list(vec![
+                service("shop", "orders"),
+                service("team-a", "carts"),
+                service("team-b", "payments"),
+                service("kube-system", "dns"),
+            ]),
+        );
+        let client = api.client();
+
+        let services = list_services(&client, Some(&["shop".to_string()]), &[])
+            .await
+            .unwrap();
+        assert_eq!(names(&services), ["shop/orders"]);
+        assert_eq!(api.requests()[0].path(), "/api/v1/namespaces/shop/services");
+
+        let namespaces = ["shop".to_string(), "team-*".to_string()];
+        let services = list_services(&client, Some(&namespaces), &["team-b".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(names(&services), ["shop/orders", "team-a/carts"]);
+
+        let services = list_services(&client, None, &["kube-*".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(
+            names(&services),
+            ["shop/orders", "team-a/carts", "team-b/payments"]
+        );
+        assert!(
+            api.requests()[1..]
+                .iter()
+                .all(|r| r.path() == "/api/v1/services")
+        );
+    }
+
+    #[tokio::test]
+    async fn namespace_annotations_are_read_once_per_namespace() {
+        let api = FakeApiServer::start().await;
+        api.insert(
+            "/api/v1/namespaces/shop",
+            json!({
+                "metadata": {
+                    "name": "shop",
+                    "annotations": {
+                        API_DOC_ENABLED_ANNOTATION: "true",
+                        "api-doc.io/path": "/v3/api-docs",
+                    }
+                }
+            }),
+        );
+        let client = api.client();
+        let orders: Service = serde_json::from_value(json!({
+            "metadata": {
+                "name": "orders",
+                "namespace": "shop",
+                "annotations": { "api-doc.io/path": "/openapi.json" }
+            }
+        }))
+        .unwrap();
+        let carts: Service = serde_json::from_value(service("shop", "carts")).unwrap();
+
+        let mut cache = BTreeMap::new();
+        let annotations = effective_annotations(&client, &orders, Some(&mut cache), None)
+            .await
+            .unwrap();
+        assert_eq!(annotations[API_DOC_ENABLED_ANNOTATION], "true");
+        assert_eq!(annotations["api-doc.io/path"], "/openapi.json");
+        let annotations = effective_annotations(&client, &carts, Some(&mut cache), None)
+            .await
+            .unwrap();
+        assert_eq!(annotations["api-doc.io/path"], "/v3/api-docs");
+        assert_eq!(api.requests().len(), 1);
+
+        // Without namespace defaults only the Service's own annotations apply
+        let annotations = effective_annotations(&client, &carts, None, None)
+            .await
+            .unwrap();
+        assert!(annotations.is_empty());
+        assert_eq!(api.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn the_label_selector_enables_matching_services() {
+        let api = FakeApiServer::start().await;
+        let selector = label_selector::parse("api=openapi").unwrap();
+        let labeled: Service = serde_json::from_value(json!({
+            "metadata": { "name": "orders", "namespace": "shop", "labels": { "api": "openapi" } }
+        }))
+        .unwrap();
+        let opted_out: Service = serde_json::from_value(json!({
+            "metadata": {
+                "name": "carts",
+                "namespace": "shop",
+                "labels": { "api": "openapi" },
+                "annotations": { API_DOC_ENABLED_ANNOTATION: "false" }
+            }
+        }))
+        .unwrap();
+        let unlabeled: Service = serde_json::from_value(service("shop", "payments")).unwrap();
+
+        let enabled = |annotations: BTreeMap<String, String>| {
+            annotations.get(API_DOC_ENABLED_ANNOTATION).cloned()
+        };
+        let client = api.client();
+        for (service, expected) in [
+            (&labeled, Some("true")),
+            (&opted_out, Some("false")),
+            (&unlabeled, None),
+        ] {
+            let annotations = effective_annotations(&client, service, None, Some(&selector))
+                .await
+                .unwrap();
+            assert_eq!(enabled(annotations).as_deref(), expected);
+        }
+    }
+}
@@ -0,0 +1,467 @@
+//! Spreads the discovery catalog over several ConfigMaps once it outgrows one.
+//!
+//! A ConfigMap holds at most 1 MiB, which the entries of a few dozen services with their
+//! contracts and metrics can exceed. When the encoded catalog is larger than
+//! [`MAX_PAYLOAD_BYTES`], its entries go to `<configmap>-0..n` and the discovery ConfigMap
+//! becomes an index listing them in `shards`, next to the tombstones. Entries are assigned
+//! to shards by a hash of their id, so an update rewrites the index and a single shard
+//! unless the number of shards changes. With `DISCOVERY_STORAGE=secret` the index and its
+//! shards are Secrets of the same names. Catalogs in the `entries` layout are written by
+//! [`entry_layout`](crate::entry_layout) instead and never sharded.
+
+use k8s_openapi::ByteString;
+use k8s_openapi::api::core::v1::{ConfigMap, Secret};
+use kube::Client;
+use kube::api::{Api, DeleteParams, Patch, PatchParams, PostParams};
+use openapi_common::digest::sha256_hex;
+use openapi_common::encoding::{
+    self, DiscoveryEncoding, DiscoveryLayout, DiscoveryStorage, EncodingError,
+};
+use openapi_common::{ApiInventoryEntry, DiscoveryConfig};
+use serde_json::{Map, Value, json};
+use std::collections::BTreeMap;
+use tracing::{info, warn};
+
+use crate::discovery_secret::{self, discovery_secret, read_discovery_secret};
+use crate::drift::same_catalog;
+use crate::entry_layout;
+use crate::error::AppError;
+use crate::{ContextData, FIELD_MANAGER, discovery_configmap, read_discovery_config};
+
+/// Largest payload written to one ConfigMap, leaving room for its metadata
+pub const MAX_PAYLOAD_BYTES: usize = 900 * 1024;
+
+/// New content of keys of a discovery object, `None` removing the key
+pub type KeyChanges = BTreeMap<String, Option<Vec<u8>>>;
+
+pub fn shard_name(configmap: &str, index: usize) -> String {
+    format!("{}-{}", configmap, index)
+}
+
+fn shard_of(id: &str, shards: usize) -> usize {
+    let hash = u64::from_str_radix(&sha256_hex(id.as_bytes())[..16], 16).unwrap_or_default();
+    (hash % shards as u64) as usize
+}
+
+fn shard_config(config: &DiscoveryConfig, apis: Vec<ApiInventoryEntry>) -> DiscoveryConfig {
+    DiscoveryConfig {
+        apis,
+        last_updated: config.last_updated,
+        tombstones: Vec::new(),
+        shards: Vec::new(),
+    }
+}
+
+/// Entries of each shard, `None` when the whole catalog fits in one ConfigMap
+pub fn split(
+    config: &DiscoveryConfig,
+    encoding: DiscoveryEncoding,
+    limit: usize,
+) -> Result<Option<Vec<Vec<ApiInventoryEntry>>>, EncodingError> {
+    let size = encoding.encode(config)?.len();
+    if size <= limit {
+        return Ok(None);
+    }
+
+    let mut count = size.div_ceil(limit).max(2);
+    loop {
+        let mut shards = vec![Vec::new(); count];
+        for api in &config.apis {
+            shards[shard_of(&api.id, count)].push(api.clone());
+        }
+        let mut fits = true;
+        for shard in &shards {
+            if encoding.encode(&shard_config(config, shard.clone()))?.len() > limit {
+                fits = false;
+                break;
+            }
+        }
+        // An entry too large on its own is left for the API server to reject
+        if fits || count >= config.apis.len() {
+            return Ok(Some(shards));
+        }
+        count += 1;
+    }
+}
+
+/// Document stored under `name` in the discovery namespace of the cluster `client` talks
+/// to, `None` if there is no such object
+async fn get_document(
+    ctx: &ContextData,
+    client: &Client,
+    name: &str,
+) -> Result<Option<DiscoveryConfig>, kube::Error> {
+    Ok(match ctx.storage {
+        DiscoveryStorage::ConfigMap => {
+            Api::<ConfigMap>::namespaced(client.clone(), &ctx.discovery_namespace)
+                .get_opt(name)
+                .await?
+                .map(|configmap| read_discovery_config(&configmap, ctx.encoding))
+        }
+        DiscoveryStorage::Secret => {
+            Api::<Secret>::namespaced(client.clone(), &ctx.discovery_namespace)
+                .get_opt(name)
+                .await?
+                .map(|secret| read_discovery_secret(&secret, ctx.encoding))
+        }
+    })
+}
+
+/// Payloads of all keys of the discovery object `name`, `None` if there is no such object
+pub async fn payloads(
+    ctx: &ContextData,
+    name: &str,
+) -> Result<Option<BTreeMap<String, Vec<u8>>>, kube::Error> {
+    Ok(match ctx.storage {
+        DiscoveryStorage::ConfigMap => ctx.discovery.get_opt(name).await?.map(|configmap| {
+            let data = configmap
+                .data
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(key, value)| (key, value.into_bytes()));
+            let binary_data = configmap
+                .binary_data
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(key, value)| (key, value.0));
+            data.chain(binary_data).collect()
+        }),
+        DiscoveryStorage::Secret => {
+            discovery_secret::secrets(ctx)
+                .get_opt(name)
+                .await?
+                .map(|secret| {
+                    secret
+                        .data
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|(key, value)| (key, value.0))
+                        .collect()
+                })
+        }
+    })
+}
+
+/// `data` (and `binaryData`) fields setting or, for `None`, removing the keys
+fn key_fields(storage: DiscoveryStorage, changes: &KeyChanges) -> Map<String, Value> {
+    let mut data = Map::new();
+    let mut binary_data = Map::new();
+    for (key, payload) in changes {
+        let binary = storage == DiscoveryStorage::Secret
+            || DiscoveryEncoding::for_key(key).is_some_and(|encoding| encoding.is_binary());
+        let value = match payload {
+            None => Value::Null,
+            Some(bytes) if binary => json!(ByteString(bytes.clone())),
+            Some(bytes) => Value::String(String::from_utf8_lossy(bytes).into_owned()),
+        };
+        if binary && storage == DiscoveryStorage::ConfigMap {
+            binary_data.insert(key.clone(), value);
+        } else {
+            data.insert(key.clone(), value);
+        }
+    }
+
+    let mut fields = Map::new();
+    if !data.is_empty() {
+        fields.insert("data".to_string(), Value::Object(data));
+    }
+    if !binary_data.is_empty() {
+        fields.insert("binaryData".to_string(), Value::Object(binary_data));
+    }
+    fields
+}
+
+/// Changes only the given keys of the discovery object `name` with a JSON merge patch,
+/// which unlike an apply neither sends nor conflicts over the keys left alone
+pub async fn patch_keys(
+    ctx: &ContextData,
+    name: &str,
+    changes: &KeyChanges,
+) -> Result<(), kube::Error> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let params = PatchParams {
+        field_manager: Some(FIELD_MANAGER.to_string()),
+        ..Default::default()
+    };
+    let patch = Patch::Merge(Value::Object(key_fields(ctx.storage, changes)));
+    match ctx.storage {
+        DiscoveryStorage::ConfigMap => {
+            ctx.discovery.patch(name, &params, &patch).await?;
+        }
+        DiscoveryStorage::Secret => {
+            discovery_secret::secrets(ctx)
+                .patch(name, &params, &patch)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Creates the discovery object `name` holding `config`; `false` when it already exists,
+/// e.g. because another replica created it after it was found missing
+pub async fn create_document(
+    ctx: &ContextData,
+    name: &str,
+    config: &DiscoveryConfig,
+) -> Result<bool, AppError> {
+    let params = PostParams {
+        field_manager: Some(FIELD_MANAGER.to_string()),
+        ..Default::default()
+    };
+    let created = match ctx.storage {
+        DiscoveryStorage::ConfigMap => {
+            let configmap =
+                discovery_configmap(name, &ctx.discovery_namespace, ctx.encoding, config)?;
+            ctx.discovery.create(&params, &configmap).await.map(drop)
+        }
+        DiscoveryStorage::Secret => {
+            let secret = discovery_secret(name, &ctx.discovery_namespace, ctx.encoding, config)?;
+            discovery_secret::secrets(ctx)
+                .create(&params, &secret)
+                .await
+                .map(drop)
+        }
+    };
+    match created {
+        Ok(()) => Ok(true),
+        Err(kube::Error::Api(resp)) if resp.code == 409 => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Writes the document, creating its object if it is missing. Only the payload key is
+/// patched, and only when its content changed; the payloads of a previous encoding and
+/// entries left by the `entries` layout are removed with it.
+async fn put_document(
+    ctx: &ContextData,
+    name: &str,
+    config: &DiscoveryConfig,
+) -> Result<(), AppError> {
+    let current = match payloads(ctx, name).await? {
+        Some(current) => current,
+        None => {
+            if create_document(ctx, name, config).await? {
+                return Ok(());
+            }
+            // Created since it was read: patch it like any existing object
+            payloads(ctx, name).await?.unwrap_or_default()
+        }
+    };
+    let changes = document_changes(ctx.encoding, &current, config)?;
+    patch_keys(ctx, name, &changes).await?;
+    Ok(())
+}
+
+/// Changes turning the `current` keys of a document's object into `config`
+fn document_changes(
+    encoding: DiscoveryEncoding,
+    current: &BTreeMap<String, Vec<u8>>,
+    config: &DiscoveryConfig,
+) -> Result<KeyChanges, EncodingError> {
+    let mut changes: KeyChanges = current
+        .keys()
+        .filter(|key| {
+            *key != encoding.key()
+                && (encoding::is_entry_key(key)
+                    || DiscoveryEncoding::ALL
+                        .iter()
+                        .any(|other| other.key() == *key))
+        })
+        .map(|key| (key.clone(), None))
+        .collect();
+    let unchanged = current
+        .get(encoding.key())
+        .and_then(|bytes| encoding::decode::<DiscoveryConfig>(bytes).ok())
+        .is_some_and(|stored| same_catalog(&stored, config) && stored.shards == config.shards);
+    if !unchanged {
+        changes.insert(encoding.key().to_string(), Some(encoding.encode(config)?));
+    }
+    Ok(changes)
+}
+
+async fn delete_document(ctx: &ContextData, name: &str) -> Result<(), kube::Error> {
+    let params = DeleteParams::default();
+    match ctx.storage {
+        DiscoveryStorage::ConfigMap => {
+            ctx.discovery.delete(name, &params).await?;
+        }
+        DiscoveryStorage::Secret => {
+            discovery_secret::secrets(ctx).delete(name, &params).await?;
+        }
+    }
+    Ok(())
+}
+
+/// The catalog with the entries of all its shards, `None` without a discovery ConfigMap.
+/// `shards` keeps the names of the shards read, for [`write_catalog`] to clean up.
+pub async fn read_catalog(ctx: &ContextData) -> Result<Option<DiscoveryConfig>, kube::Error> {
+    read_catalog_from(ctx, &ctx.discovery.clone().into_client()).await
+}
+
+/// [`read_catalog`] of the cluster `client` talks to, under the same names and encoding
+pub async fn read_catalog_from(
+    ctx: &ContextData,
+    client: &Client,
+) -> Result<Option<DiscoveryConfig>, kube::Error> {
+    let Some(mut config) = get_document(ctx, client, &ctx.discovery_configmap).await? else {
+        return Ok(None);
+    };
+    for name in &config.shards {
+        match get_document(ctx, client, name).await? {
+            Some(shard) => config.apis.extend(shard.apis),
+            None => warn!(
+                "Discovery {} shard '{}' is missing",
+                ctx.storage.kind(),
+                name
+            ),
+        }
+    }
+    Ok(Some(config))
+}
+
+/// Writes the catalog, sharded when it does not fit in one ConfigMap, then deletes the
+/// shards it no longer needs. Shards are written before the index referencing them.
+pub async fn write_catalog(ctx: &ContextData, config: DiscoveryConfig) -> Result<(), AppError> {
+    let mut written = ctx.written_catalog.lock().await;
+    write_catalog_locked(ctx, config, &mut written).await
+}
+
+/// [`write_catalog`] for a holder of the `written_catalog` lock, recording the written
+/// catalog there on success
+pub async fn write_catalog_locked(
+    ctx: &ContextData,
+    mut config: DiscoveryConfig,
+    written: &mut Option<DiscoveryConfig>,
+) -> Result<(), AppError> {
+    let previous = std::mem::take(&mut config.shards);
+    let mut catalog = config.clone();
+
+    if ctx.layout == DiscoveryLayout::Entries {
+        entry_layout::write_catalog(ctx, &config).await?;
+    } else {
+        if let Some(shards) = split(&config, ctx.encoding, MAX_PAYLOAD_BYTES)? {
+            for (index, apis) in shards.into_iter().enumerate() {
+                let name = shard_name(&ctx.discovery_configmap, index);
+                put_document(ctx, &name, &shard_config(&config, apis)).await?;
+                config.shards.push(name);
+            }
+            config.apis.clear();
+        }
+        put_document(ctx, &ctx.discovery_configmap, &config).await?;
+    }
+    if ctx.layout == DiscoveryLayout::Document && config.shards.len() != previous.len() {
+        info!(
+            "Discovery catalog is now split over {} {} shards",
+            config.shards.len(),
+            ctx.storage.kind()
+        );
+    }
+
+    for name in previous.iter().filter(|name| !config.shards.contains(name)) {
+        match delete_document(ctx, name).await {
+            Ok(()) => {}
+            Err(kube::Error::Api(resp)) if resp.code == 404 => {}
+            Err(e) => warn!(
+                "Failed to delete unused {} shard '{}': {}",
+                ctx.storage.kind(),
+                name,
+                e
+            ),
+        }
+    }
+    catalog.shards = config.shards;
+    if let Some(audit) = &ctx.audit {
+        audit.record(written.as_ref(), &catalog);
+    }
+    *written = Some(catalog);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn large_catalogs_are_split_by_entry_id() {
+        let entry = |id: usize| ApiInventoryEntry {
+            name: "x".repeat(200),
+            ..ApiInventoryEntry::new(
+                "shop",
+                &format!("api-{}", id),
+                "http://api.shop.svc:8080/openapi.json",
+            )
+        };
+        let config = DiscoveryConfig {
+            apis: (0..40).map(entry).collect(),
+            last_updated: Utc::now(),
+            tombstones: Vec::new(),
+            shards: Vec::new(),
+        };
+
+        let encoding = DiscoveryEncoding::Json;
+        assert!(split(&config, encoding, 1024 * 1024).unwrap().is_none());
+        let shards = split(&config, encoding, 4096).unwrap().unwrap();
+        assert!(shards.len() >= 3);
+        assert_eq!(shards.iter().map(Vec::len).sum::<usize>(), 40);
+        for shard in &shards {
+            let size = encoding
+                .encode(&shard_config(&config, shard.clone()))
+                .unwrap();
+            assert!(size.len() <= 4096);
+        }
+        // The same entry always lands in the same shard
+        let again = split(&config, encoding, 4096).unwrap().unwrap();
+        assert_eq!(
+            shards[0].iter().map(|api| &api.id).collect::<Vec<_>>(),
+            again[0].iter().map(|api| &api.id).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn only_changed_payloads_are_patched() {
+        let entry = |service: &str| {
+            ApiInventoryEntry::new("shop", service, "http://api.shop.svc:8080/openapi.json")
+        };
+        let config = DiscoveryConfig {
+            apis: vec![entry("orders"), entry("carts")],
+            last_updated: Utc::now(),
+            tombstones: Vec::new(),
+            shards: Vec::new(),
+        };
+        let encoding = DiscoveryEncoding::Json;
+        let current = BTreeMap::from([
+            (
+                "discovery.json".to_string(),
+                encoding.encode(&config).unwrap(),
+            ),
+            ("discovery.msgpack".to_string(), vec![0x80]),
+            ("shop.orders.json".to_string(), b"{}".to_vec()),
+        ]);
+
+        // Neither the order of the entries nor last_updated make a change
+        let mut same = config.clone();
+        same.apis.reverse();
+        same.last_updated = Utc::now();
+        let changes = document_changes(encoding, &current, &same).unwrap();
+        assert_eq!(
+            changes,
+            KeyChanges::from([
+                ("discovery.msgpack".to_string(), None),
+                ("shop.orders.json".to_string(), None),
+            ])
+        );
+
+        let mut changed = config.clone();
+        changed.apis.pop();
+        let changes = document_changes(encoding, &current, &changed).unwrap();
+        assert!(changes["discovery.json"].is_some());
+
+        let fields = key_fields(DiscoveryStorage::ConfigMap, &changes);
+        assert!(fields["data"]["discovery.json"].is_string());
+        assert_eq!(fields["data"]["shop.orders.json"], Value::Null);
+        assert_eq!(fields["binaryData"]["discovery.msgpack"], Value::Null);
+    }
+}
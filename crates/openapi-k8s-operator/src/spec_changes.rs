@@ -0,0 +1,93 @@
+//! Detects what changed between the stored revisions of a spec.
+//!
+//! The last stored revision of every entry is kept in memory, and each new revision is
+//! diffed against it. A revision that changes the document emits a `SpecChanged` Event on
+//! the Service listing the changes, and its [`ChangeSummary`] is recorded as the entry's
+//! `last_change_summary` until the next change. With `DISCOVERY_OUTPUT=crd` the stored
+//! spec is read back after a restart; otherwise the first revision after a restart is
+//! taken as is, keeping the summary already in the catalog.
+
+use openapi_common::OpenApiDocument;
+use openapi_common::diff::{SpecDiff, diff_documents};
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+
+use crate::breaking_gate::truncate_note;
+
+#[derive(Default)]
+pub struct SpecChanges {
+    /// Last stored spec per entry id
+    stored: Mutex<HashMap<String, String>>,
+}
+
+impl SpecChanges {
+    /// Whether a stored revision is known for the entry
+    pub async fn knows(&self, id: &str) -> bool {
+        self.stored.lock().await.contains_key(id)
+    }
+
+    /// Records a revision stored by an earlier run of the operator
+    pub async fn seed(&self, id: &str, spec: String) {
+        self.stored
+            .lock()
+            .await
+            .entry(id.to_string())
+            .or_insert(spec);
+    }
+
+    /// What `spec` changes against the stored revision. `None` for the first revision,
+    /// an identical one, or specs that do not parse.
+    pub async fn diff(&self, id: &str, spec: &str) -> Option<SpecDiff> {
+        let stored = self.stored.lock().await;
+        let previous = stored
+            .get(id)
+            .filter(|previous| previous.as_str() != spec)?;
+        let old = OpenApiDocument::parse(previous).ok()?;
+        let new = OpenApiDocument::parse(spec).ok()?;
+        Some(diff_documents(&old, &new)).filter(|diff| !diff.is_empty())
+    }
+
+    /// Remembers `spec` as the stored revision
+    pub async fn stored(&self, id: &str, spec: &str) {
+        self.stored
+            .lock()
+            .await
+            .insert(id.to_string(), spec.to_string());
+    }
+
+    /// Drops the stored revision of an entry that left the catalog
+    pub async fn forget(&self, id: &str) {
+        self.stored.lock().await.remove(id);
+    }
+}
+
+/// Event note listing every change, cut to the size the API server accepts
+pub fn event_note(diff: &SpecDiff) -> String {
+    let mut note = format!("{}:", diff.summary());
+    for change in &diff.changes {
+        note.push_str(&format!("\n- {}", change));
+    }
+    truncate_note(note)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V1: &str = r#"{"openapi":"3.0.0","info":{"title":"Orders","version":"1"},"paths":{"/orders":{"get":{"responses":{"200":{"description":"ok"}}}}}}"#;
+    const V2: &str = r#"{"openapi":"3.0.0","info":{"title":"Orders","version":"1"},"paths":{"/orders":{"get":{"responses":{"200":{"description":"ok"}}}},"/orders/{id}":{"get":{"responses":{"200":{"description":"ok"}}}}}}"#;
+
+    #[tokio::test]
+    async fn changes_are_reported_against_the_last_stored_revision() {
+        let changes = SpecChanges::default();
+        assert_eq!(changes.diff("shop.orders", V1).await, None);
+        changes.stored("shop.orders", V1).await;
+        assert_eq!(changes.diff("shop.orders", V1).await, None);
+
+        let diff = changes.diff("shop.orders", V2).await.unwrap();
+        let summary = diff.change_summary(chrono::Utc::now());
+        assert_eq!(summary.added_paths, vec!["/orders/{id}".to_string()]);
+        assert_eq!(summary.breaking, 0);
+        assert!(event_note(&diff).contains("/orders/{id}: path added"));
+    }
+}
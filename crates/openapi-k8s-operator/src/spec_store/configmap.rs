@@ -0,0 +1,265 @@
+//! `SPEC_STORAGE=configmap`: one ConfigMap per API holding its spec next to the catalog.
+//!
+//! The stored spec, its signature and the entry travel together in `<configmap>-spec-<id>`
+//! in the discovery namespace, referenced by the entry's `spec_config_map`. They are
+//! labeled with the service they describe, e.g. for
+//! `kubectl get cm -l api-doc.io/namespace=shop`.
+//!
+//! With a history limit, earlier revisions are kept in `<configmap>-history-<id>`, one key
+//! per revision next to the `versions.json` index. All of them count against the 1 MiB
+//! limit of that ConfigMap.
+
+use async_trait::async_trait;
+use chrono::Utc;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::api::{Api, DeleteParams, Patch, PatchParams};
+use openapi_common::ApiInventoryEntry;
+use openapi_common::crd::{SPEC_CONFIGMAP_KEY, SPEC_SIGNATURE_CONFIGMAP_KEY};
+use openapi_common::signing::SpecSignature;
+use std::collections::BTreeMap;
+use tracing::debug;
+
+use super::{SpecStorageError, SpecStore, SpecVersion, push_version};
+
+/// Key of the entry, as JSON, next to the spec
+pub const ENTRY_CONFIGMAP_KEY: &str = "entry.json";
+
+/// Key of the [`SpecVersion`] index in a history ConfigMap
+pub const VERSIONS_CONFIGMAP_KEY: &str = "versions.json";
+
+pub struct ConfigMapSpecStore {
+    /// ConfigMaps in the discovery namespace
+    configmaps: Api<ConfigMap>,
+    discovery_configmap: String,
+    namespace: String,
+    /// Earlier revisions kept per API, `0` for none
+    history_limit: usize,
+}
+
+impl ConfigMapSpecStore {
+    pub fn new(
+        configmaps: Api<ConfigMap>,
+        discovery_configmap: &str,
+        namespace: &str,
+        history_limit: usize,
+    ) -> Self {
+        Self {
+            configmaps,
+            discovery_configmap: discovery_configmap.to_string(),
+            namespace: namespace.to_string(),
+            history_limit,
+        }
+    }
+
+    fn history_name(&self, entry: &ApiInventoryEntry) -> String {
+        format!("{}-history-{}", self.discovery_configmap, entry.id)
+    }
+
+    /// Adds `spec` to the history of the entry, dropping the oldest revisions
+    async fn record_history(
+        &self,
+        entry: &ApiInventoryEntry,
+        spec: &str,
+    ) -> Result<(), SpecStorageError> {
+        let name = self.history_name(entry);
+        let mut data = self
+            .configmaps
+            .get_opt(&name)
+            .await?
+            .and_then(|configmap| configmap.data)
+            .unwrap_or_default();
+        let mut versions: Vec<SpecVersion> = data
+            .get(VERSIONS_CONFIGMAP_KEY)
+            .and_then(|json| serde_json::from_str(json).ok())
+            .unwrap_or_default();
+        let version = SpecVersion::new(spec, Utc::now());
+        let key = version.key.clone();
+        let Some(dropped) = push_version(&mut versions, version, self.history_limit) else {
+            return Ok(());
+        };
+        for version in &dropped {
+            data.remove(&version.key);
+        }
+        data.insert(key, spec.to_string());
+        data.insert(
+            VERSIONS_CONFIGMAP_KEY.to_string(),
+            serde_json::to_string_pretty(&versions)?,
+        );
+
+        let configmap = ConfigMap {
+            metadata: kube::core::ObjectMeta {
+                name: Some(name.clone()),
+                namespace: Some(self.namespace.clone()),
+                labels: Some(labels(entry, "spec-history")),
+                ..Default::default()
+            },
+            data: Some(data),
+            ..Default::default()
+        };
+        self.configmaps
+            .patch(
+                &name,
+                &PatchParams::apply("openapi-k8s-operator"),
+                &Patch::Apply(&configmap),
+            )
+            .await?;
+        debug!(
+            "Recorded revision {} of {} in '{}' ({} kept)",
+            versions.last().map(|v| v.key.as_str()).unwrap_or_default(),
+            entry.id,
+            name,
+            versions.len()
+        );
+        Ok(())
+    }
+}
+
+fn labels(entry: &ApiInventoryEntry, component: &str) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        (
+            "app.kubernetes.io/name".to_string(),
+            "openapi-discovery".to_string(),
+        ),
+        (
+            "app.kubernetes.io/component".to_string(),
+            component.to_string(),
+        ),
+        ("api-doc.io/namespace".to_string(), entry.namespace.clone()),
+        ("api-doc.io/service".to_string(), entry.service_name.clone()),
+    ])
+}
+
+/// Whether the ConfigMap already holds `spec` with `signature` and the same entry
+fn is_current(
+    configmap: &ConfigMap,
+    entry: &ApiInventoryEntry,
+    spec: &str,
+    signature: Option<&SpecSignature>,
+) -> bool {
+    let Some(data) = &configmap.data else {
+        return false;
+    };
+    let stored = data
+        .get(ENTRY_CONFIGMAP_KEY)
+        .and_then(|json| serde_json::from_str::<ApiInventoryEntry>(json).ok());
+    data.get(SPEC_CONFIGMAP_KEY).map(String::as_str) == Some(spec)
+        && data.get(SPEC_SIGNATURE_CONFIGMAP_KEY).cloned() == signature.map(|s| s.to_json())
+        && stored.is_some_and(|stored| stored.same_content(entry))
+}
+
+#[async_trait]
+impl SpecStore for ConfigMapSpecStore {
+    fn name(&self) -> &str {
+        "configmap"
+    }
+
+    fn locate(&self, entry: &mut ApiInventoryEntry, _spec: &str) {
+        entry.spec_config_map = Some(format!("{}-spec-{}", self.discovery_configmap, entry.id));
+    }
+
+    async fn put(
+        &self,
+        entry: &ApiInventoryEntry,
+        spec: &str,
+        signature: Option<&SpecSignature>,
+    ) -> Result<(), SpecStorageError> {
+        let Some(name) = &entry.spec_config_map else {
+            return Ok(());
+        };
+        if let Some(existing) = self.configmaps.get_opt(name).await?
+            && is_current(&existing, entry, spec, signature)
+        {
+            debug!("Spec ConfigMap '{}' is unchanged", name);
+            return Ok(());
+        }
+
+        let mut data = BTreeMap::from([
+            (SPEC_CONFIGMAP_KEY.to_string(), spec.to_string()),
+            (
+                ENTRY_CONFIGMAP_KEY.to_string(),
+                serde_json::to_string_pretty(entry)?,
+            ),
+        ]);
+        if let Some(signature) = signature {
+            data.insert(
+                SPEC_SIGNATURE_CONFIGMAP_KEY.to_string(),
+                signature.to_json(),
+            );
+        }
+        let configmap = ConfigMap {
+            metadata: kube::core::ObjectMeta {
+                name: Some(name.clone()),
+                namespace: Some(self.namespace.clone()),
+                labels: Some(labels(entry, "spec")),
+                ..Default::default()
+            },
+            data: Some(data),
+            ..Default::default()
+        };
+        self.configmaps
+            .patch(
+                name,
+                &PatchParams::apply("openapi-k8s-operator"),
+                &Patch::Apply(&configmap),
+            )
+            .await?;
+        if self.history_limit > 0 {
+            self.record_history(entry, spec).await?;
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, entry: &ApiInventoryEntry) -> Result<(), SpecStorageError> {
+        let Some(name) = &entry.spec_config_map else {
+            return Ok(());
+        };
+        for name in [name.clone(), self.history_name(entry)] {
+            match self
+                .configmaps
+                .delete(&name, &DeleteParams::default())
+                .await
+            {
+                Ok(_) => debug!("Deleted spec ConfigMap '{}'", name),
+                Err(kube::Error::Api(resp)) if resp.code == 404 => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn configmap_with_the_same_spec_and_entry_is_current() {
+        let entry = ApiInventoryEntry {
+            spec_sha256: Some("ab12".to_string()),
+            spec_config_map: Some("openapi-discovery-spec-shop.orders".to_string()),
+            ..ApiInventoryEntry::new("shop", "orders", "http://orders.shop.svc:8080/openapi.json")
+        };
+        let configmap = ConfigMap {
+            data: Some(BTreeMap::from([
+                (SPEC_CONFIGMAP_KEY.to_string(), "openapi: 3.1.0".to_string()),
+                (
+                    ENTRY_CONFIGMAP_KEY.to_string(),
+                    serde_json::to_string(&entry).unwrap(),
+                ),
+            ])),
+            ..Default::default()
+        };
+
+        let refreshed = ApiInventoryEntry {
+            last_updated: chrono::Utc::now(),
+            ..entry.clone()
+        };
+        assert!(is_current(&configmap, &refreshed, "openapi: 3.1.0", None));
+        assert!(!is_current(&configmap, &refreshed, "openapi: 3.0.3", None));
+        let renamed = ApiInventoryEntry {
+            name: "Orders".to_string(),
+            ..entry
+        };
+        assert!(!is_current(&configmap, &renamed, "openapi: 3.1.0", None));
+    }
+}
@@ -0,0 +1,213 @@
+//! Copies of stored specs kept next to the catalog (`SPEC_STORAGE`).
+//!
+//! The discovery ConfigMap only carries entry metadata, so the documentation server fetches
+//! every spec from its service again. A [`SpecStore`] keeps the spec the operator accepted,
+//! with its signature, and the entry references it: `spec_config_map` for one ConfigMap
+//! per API, `spec_url` for objects in an S3-compatible bucket. The documentation server
+//! serves the referenced copy instead of fetching the spec. Large specs thereby stay out
+//! of the discovery ConfigMap whatever the backend.
+//!
+//! With `SPEC_HISTORY_LIMIT`, each backend also keeps the last revisions of every spec,
+//! named by the time they were stored, with a [`SpecVersion`] index, so the spec that was
+//! live at a given time can be looked up after it was replaced.
+
+mod configmap;
+mod s3;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::api::Api;
+use openapi_common::s3::{S3Error, SpecBucket};
+use openapi_common::signing::SpecSignature;
+use openapi_common::digest::sha256_hex;
+use openapi_common::{ApiInventoryEntry, SPEC_HISTORY_LIMIT_ENV, SPEC_STORAGE_ENV};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+
+pub use configmap::ConfigMapSpecStore;
+pub use s3::S3SpecStore;
+
+#[derive(Debug)]
+pub enum SpecStorageError {
+    Kube(kube::Error),
+    S3(S3Error),
+    Serde(serde_json::Error),
+    /// Invalid storage configuration
+    Config(String),
+}
+
+impl fmt::Display for SpecStorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpecStorageError::Kube(e) => write!(f, "Kubernetes error: {}", e),
+            SpecStorageError::S3(e) => write!(f, "{}", e),
+            SpecStorageError::Serde(e) => write!(f, "serialization error: {}", e),
+            SpecStorageError::Config(msg) => write!(f, "invalid configuration: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SpecStorageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SpecStorageError::Kube(e) => Some(e),
+            SpecStorageError::S3(e) => Some(e),
+            SpecStorageError::Serde(e) => Some(e),
+            SpecStorageError::Config(_) => None,
+        }
+    }
+}
+
+impl From<kube::Error> for SpecStorageError {
+    fn from(err: kube::Error) -> Self {
+        SpecStorageError::Kube(err)
+    }
+}
+
+impl From<S3Error> for SpecStorageError {
+    fn from(err: S3Error) -> Self {
+        SpecStorageError::S3(err)
+    }
+}
+
+impl From<serde_json::Error> for SpecStorageError {
+    fn from(err: serde_json::Error) -> Self {
+        SpecStorageError::Serde(err)
+    }
+}
+
+/// One retained revision of a spec
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SpecVersion {
+    /// ConfigMap key or object name of the revision, e.g. `20260115T103000Z.json`
+    pub key: String,
+    pub stored_at: DateTime<Utc>,
+    pub spec_sha256: String,
+}
+
+impl SpecVersion {
+    pub fn new(spec: &str, stored_at: DateTime<Utc>) -> Self {
+        let extension = if spec.trim_start().starts_with('{') {
+            "json"
+        } else {
+            "yaml"
+        };
+        Self {
+            key: format!("{}.{}", stored_at.format("%Y%m%dT%H%M%SZ"), extension),
+            stored_at,
+            spec_sha256: sha256_hex(spec.as_bytes()),
+        }
+    }
+}
+
+/// Appends `version` to the index, oldest first, and returns the versions beyond `limit`
+/// to delete; `None` when the newest version already holds the same spec
+fn push_version(
+    versions: &mut Vec<SpecVersion>,
+    version: SpecVersion,
+    limit: usize,
+) -> Option<Vec<SpecVersion>> {
+    if versions
+        .last()
+        .is_some_and(|newest| newest.spec_sha256 == version.spec_sha256)
+    {
+        return None;
+    }
+    versions.retain(|v| v.key != version.key);
+    versions.push(version);
+    let excess = versions.len().saturating_sub(limit);
+    Some(versions.drain(..excess).collect())
+}
+
+/// Where the specs of catalog entries are stored
+#[async_trait]
+pub trait SpecStore: Send + Sync {
+    /// Short name used in logs
+    fn name(&self) -> &str;
+
+    /// Records in the entry where its spec is stored, before the entry is written anywhere
+    fn locate(&self, entry: &mut ApiInventoryEntry, spec: &str);
+
+    /// Stores the spec of a located entry, unless the stored copy is current
+    async fn put(
+        &self,
+        entry: &ApiInventoryEntry,
+        spec: &str,
+        signature: Option<&SpecSignature>,
+    ) -> Result<(), SpecStorageError>;
+
+    /// Deletes the stored spec of a removed entry
+    async fn delete(&self, entry: &ApiInventoryEntry) -> Result<(), SpecStorageError>;
+}
+
+/// The store selected by `SPEC_STORAGE`, `None` when unset
+pub fn from_env(
+    discovery: &Api<ConfigMap>,
+    discovery_configmap: &str,
+    discovery_namespace: &str,
+) -> Result<Option<Arc<dyn SpecStore>>, SpecStorageError> {
+    let history_limit = match std::env::var(SPEC_HISTORY_LIMIT_ENV) {
+        Ok(value) => value.trim().parse::<usize>().map_err(|_| {
+            SpecStorageError::Config(format!(
+                "Invalid {}: '{}', expected a number of revisions",
+                SPEC_HISTORY_LIMIT_ENV, value
+            ))
+        })?,
+        Err(_) => 0,
+    };
+    match std::env::var(SPEC_STORAGE_ENV).unwrap_or_default().trim() {
+        "" | "none" => Ok(None),
+        "configmap" => Ok(Some(Arc::new(ConfigMapSpecStore::new(
+            discovery.clone(),
+            discovery_configmap,
+            discovery_namespace,
+            history_limit,
+        )))),
+        "s3" => {
+            let bucket = SpecBucket::from_env()?.ok_or_else(|| {
+                SpecStorageError::Config(format!(
+                    "{}=s3 requires {}",
+                    SPEC_STORAGE_ENV,
+                    openapi_common::s3::SPEC_STORAGE_S3_BUCKET_ENV
+                ))
+            })?;
+            Ok(Some(Arc::new(S3SpecStore::new(bucket, history_limit))))
+        }
+        other => Err(SpecStorageError::Config(format!(
+            "Invalid {}: '{}', expected 'configmap' or 's3'",
+            SPEC_STORAGE_ENV, other
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_keeps_the_newest_changed_revisions() {
+        let at = |minute: u32| {
+            DateTime::parse_from_rfc3339(&format!("2026-01-15T10:{:02}:00Z", minute))
+                .unwrap()
+                .with_timezone(&Utc)
+        };
+        let mut versions = Vec::new();
+        assert_eq!(
+            push_version(&mut versions, SpecVersion::new("{\"v\":1}", at(0)), 2),
+            Some(Vec::new())
+        );
+        assert_eq!(versions[0].key, "20260115T100000Z.json");
+        assert_eq!(
+            push_version(&mut versions, SpecVersion::new("{\"v\":1}", at(1)), 2),
+            None
+        );
+        push_version(&mut versions, SpecVersion::new("v: 2", at(2)), 2);
+
+        let dropped = push_version(&mut versions, SpecVersion::new("v: 3", at(3)), 2).unwrap();
+        assert_eq!(dropped[0].key, "20260115T100000Z.json");
+        let keys: Vec<_> = versions.iter().map(|v| v.key.as_str()).collect();
+        assert_eq!(keys, ["20260115T100200Z.yaml", "20260115T100300Z.yaml"]);
+    }
+}
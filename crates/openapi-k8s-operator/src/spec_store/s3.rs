@@ -0,0 +1,324 @@
+//! `SPEC_STORAGE=s3`: specs stored as objects in an S3-compatible bucket.
+//!
+//! The current spec of a service is kept at `<prefix><namespace>/<service>.<json|yaml>`,
+//! with its detached signature as `<key>.sig`, and the entry only carries the
+//! `s3://<bucket>/<key>` URL. Credentials come from `AWS_ACCESS_KEY_ID` /
+//! `AWS_SECRET_ACCESS_KEY` or, on EKS, from the IRSA role of the operator's service account.
+//!
+//! With a history limit, earlier revisions are kept as
+//! `<prefix>history/<namespace>/<service>/<time>.<json|yaml>`, listed in `index.json` of
+//! the same directory.
+
+use async_trait::async_trait;
+use chrono::Utc;
+use openapi_common::ApiInventoryEntry;
+use openapi_common::s3::SpecBucket;
+use openapi_common::signing::SpecSignature;
+use openapi_common::digest::sha256_hex;
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+use tracing::debug;
+
+use super::{SpecStorageError, SpecStore, SpecVersion, push_version};
+
+/// Object listing the [`SpecVersion`]s of a service's history
+const HISTORY_INDEX: &str = "index.json";
+
+pub struct S3SpecStore {
+    bucket: SpecBucket,
+    /// Earlier revisions kept per API, `0` for none
+    history_limit: usize,
+    /// Hash of the spec and signature last uploaded per URL, so unchanged specs are not
+    /// uploaded on every reconcile
+    uploaded: Mutex<HashMap<String, String>>,
+}
+
+impl S3SpecStore {
+    pub fn new(bucket: SpecBucket, history_limit: usize) -> Self {
+        Self {
+            bucket,
+            history_limit,
+            uploaded: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn history(&self, prefix: &str) -> Result<Vec<SpecVersion>, SpecStorageError> {
+        let index = self
+            .bucket
+            .get_object(&format!("{}{}", prefix, HISTORY_INDEX))
+            .await?;
+        Ok(index
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .unwrap_or_default())
+    }
+
+    /// Adds `spec` to the history of the entry, dropping the oldest revisions
+    async fn record_history(
+        &self,
+        entry: &ApiInventoryEntry,
+        spec: &str,
+    ) -> Result<(), SpecStorageError> {
+        let prefix = self
+            .bucket
+            .history_prefix(&entry.namespace, &entry.service_name);
+        let mut versions = self.history(&prefix).await?;
+        let version = SpecVersion::new(spec, Utc::now());
+        let key = format!("{}{}", prefix, version.key);
+        let Some(dropped) = push_version(&mut versions, version, self.history_limit) else {
+            return Ok(());
+        };
+        let content_type = if key.ends_with(".json") {
+            "application/json"
+        } else {
+            "application/yaml"
+        };
+        self.bucket
+            .put_object(&key, content_type, spec.as_bytes().to_vec())
+            .await?;
+        self.bucket
+            .put_object(
+                &format!("{}{}", prefix, HISTORY_INDEX),
+                "application/json",
+                serde_json::to_vec_pretty(&versions)?,
+            )
+            .await?;
+        for version in &dropped {
+            self.bucket
+                .delete_object(&format!("{}{}", prefix, version.key))
+                .await?;
+        }
+        debug!("Recorded revision {} ({} kept)", key, versions.len());
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SpecStore for S3SpecStore {
+    fn name(&self) -> &str {
+        "s3"
+    }
+
+    fn locate(&self, entry: &mut ApiInventoryEntry, spec: &str) {
+        entry.spec_url = Some(self.bucket.url(&entry.namespace, &entry.service_name, spec));
+    }
+
+    async fn put(
+        &self,
+        entry: &ApiInventoryEntry,
+        spec: &str,
+        signature: Option<&SpecSignature>,
+    ) -> Result<(), SpecStorageError> {
+        let Some(url) = &entry.spec_url else {
+            return Ok(());
+        };
+        let hash = sha256_hex(
+            format!(
+                "{}\n{}",
+                spec,
+                signature.map(|s| s.to_json()).unwrap_or_default()
+            )
+            .as_bytes(),
+        );
+        let mut uploaded = self.uploaded.lock().await;
+        if uploaded.get(url) == Some(&hash) {
+            debug!("Spec object {} is unchanged", url);
+            return Ok(());
+        }
+        self.bucket.put(url, spec, signature).await?;
+        uploaded.insert(url.clone(), hash);
+        if self.history_limit > 0 {
+            self.record_history(entry, spec).await?;
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, entry: &ApiInventoryEntry) -> Result<(), SpecStorageError> {
+        let Some(url) = &entry.spec_url else {
+            return Ok(());
+        };
+        self.uploaded.lock().await.remove(url);
+        self.bucket.delete(url).await?;
+        debug!("Deleted spec object {}", url);
+        if self.history_limit > 0 {
+            let prefix = self
+                .bucket
+                .history_prefix(&entry.namespace, &entry.service_name);
+            for version in self.history(&prefix).await? {
+                self.bucket
+                    .delete_object(&format!("{}{}", prefix, version.key))
+                    .await?;
+            }
+            self.bucket
+                .delete_object(&format!("{}{}", prefix, HISTORY_INDEX))
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{Recorded, TestServer};
+    use openapi_common::s3::{RenewingS3Client, S3Error};
+    use openapi_common::sigv4::AwsCredentials;
+    use std::sync::Arc;
+
+    const SPEC: &str = "{\"openapi\":\"3.0.0\",\"info\":{\"title\":\"Orders\",\"version\":\"1\"}}";
+
+    type Objects = Arc<std::sync::Mutex<HashMap<String, String>>>;
+
+    /// In-memory bucket answering S3 object requests
+    async fn bucket() -> (TestServer, Objects) {
+        let objects = Objects::default();
+        let stored = objects.clone();
+        let server = TestServer::start(move |request: &Recorded| {
+            let mut objects = stored.lock().unwrap();
+            match request.method.as_str() {
+                "PUT" => {
+                    objects.insert(request.uri.clone(), request.body.clone());
+                    (200, String::new())
+                }
+                "DELETE" => {
+                    objects.remove(&request.uri);
+                    (204, String::new())
+                }
+                _ => match objects.get(&request.uri) {
+                    Some(body) => (200, body.clone()),
+                    None => (404, "NoSuchKey".to_string()),
+                },
+            }
+        })
+        .await;
+        (server, objects)
+    }
+
+    fn s3_store(endpoint: &str, history_limit: usize) -> S3SpecStore {
+        let credentials = AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: None,
+        };
+        let client =
+            RenewingS3Client::with_credentials(credentials, "eu-west-1", "specs", Some(endpoint))
+                .unwrap();
+        S3SpecStore::new(SpecBucket::new(client, "specs", "catalog"), history_limit)
+    }
+
+    fn located(store: &S3SpecStore, spec: &str) -> ApiInventoryEntry {
+        let mut entry =
+            ApiInventoryEntry::new("shop", "orders", "http://orders.shop.svc/openapi.json");
+        store.locate(&mut entry, spec);
+        entry
+    }
+
+    fn requests(server: &TestServer) -> Vec<(String, String)> {
+        server
+            .requests()
+            .into_iter()
+            .map(|r| (r.method, r.uri))
+            .collect()
+    }
+
+    fn pairs<const N: usize>(requests: [(&str, &str); N]) -> Vec<(String, String)> {
+        requests
+            .iter()
+            .map(|(method, uri)| (method.to_string(), uri.to_string()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn specs_are_uploaded_with_their_signature_once_per_change() {
+        let (server, objects) = bucket().await;
+        let store = s3_store(&server.url, 0);
+        let entry = located(&store, SPEC);
+        assert_eq!(
+            entry.spec_url.as_deref(),
+            Some("s3://specs/catalog/shop/orders.json")
+        );
+        let signature = SpecSignature {
+            algorithm: "ed25519".to_string(),
+            key_id: "0123456789abcdef".to_string(),
+            sha256: sha256_hex(SPEC.as_bytes()),
+            signature: "c2lnbmF0dXJl".to_string(),
+        };
+
+        store.put(&entry, SPEC, Some(&signature)).await.unwrap();
+        store.put(&entry, SPEC, Some(&signature)).await.unwrap();
+        assert_eq!(
+            requests(&server),
+            pairs([
+                ("PUT", "/specs/catalog/shop/orders.json"),
+                ("PUT", "/specs/catalog/shop/orders.json.sig"),
+            ])
+        );
+        assert_eq!(
+            objects.lock().unwrap()["/specs/catalog/shop/orders.json"],
+            SPEC
+        );
+        for request in server.requests() {
+            let authorization = request.header("authorization").unwrap();
+            assert!(authorization.contains("/eu-west-1/s3/aws4_request"));
+        }
+
+        // A signature left from before is removed
+        store.put(&entry, SPEC, None).await.unwrap();
+        assert_eq!(
+            requests(&server)[2..],
+            pairs([
+                ("PUT", "/specs/catalog/shop/orders.json"),
+                ("DELETE", "/specs/catalog/shop/orders.json.sig"),
+            ])
+        );
+
+        let unlocated =
+            ApiInventoryEntry::new("shop", "carts", "http://carts.shop.svc/openapi.json");
+        store.put(&unlocated, SPEC, None).await.unwrap();
+        store.delete(&unlocated).await.unwrap();
+        assert_eq!(server.requests().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn history_keeps_changed_revisions_until_the_spec_is_deleted() {
+        let (server, objects) = bucket().await;
+        let store = s3_store(&server.url, 2);
+        let entry = located(&store, SPEC);
+        store.put(&entry, SPEC, None).await.unwrap();
+
+        let index = "/specs/catalog/history/shop/orders/index.json";
+        let versions: Vec<SpecVersion> =
+            serde_json::from_str(&objects.lock().unwrap()[index]).unwrap();
+        assert_eq!(versions.len(), 1);
+        assert_eq!(versions[0].spec_sha256, sha256_hex(SPEC.as_bytes()));
+        let revision = format!("/specs/catalog/history/shop/orders/{}", versions[0].key);
+        assert_eq!(objects.lock().unwrap()[&revision], SPEC);
+
+        // After a restart the spec is uploaded again, but the history already holds it
+        let restarted = s3_store(&server.url, 2);
+        restarted.put(&entry, SPEC, None).await.unwrap();
+        let versions: Vec<SpecVersion> =
+            serde_json::from_str(&objects.lock().unwrap()[index]).unwrap();
+        assert_eq!(versions.len(), 1);
+
+        restarted.delete(&entry).await.unwrap();
+        assert!(objects.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn bucket_errors_are_reported() {
+        let server = TestServer::start(|_| (403, "AccessDenied".to_string())).await;
+        let store = s3_store(&server.url, 0);
+        let entry = located(&store, SPEC);
+
+        match store.put(&entry, SPEC, None).await {
+            Err(SpecStorageError::S3(S3Error::Status { code, body })) => {
+                assert_eq!(code, 403);
+                assert_eq!(body, "AccessDenied");
+            }
+            other => panic!("expected an S3 status error, got {:?}", other),
+        }
+        // Failed uploads are retried by the next reconcile
+        assert!(store.put(&entry, SPEC, None).await.is_err());
+        assert_eq!(server.requests().len(), 2);
+    }
+}
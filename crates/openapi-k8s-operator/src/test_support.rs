@@ -0,0 +1,348 @@
+//! Local HTTP servers the tests point publishers, stores and the Kubernetes client at.
+
+use axum::body::to_bytes;
+use axum::extract::Request;
+use axum::http::{HeaderMap, StatusCode};
+use kube::Client;
+use kube::runtime::events::{Recorder, Reporter};
+use openapi_common::fetch::{FetchConfig, SpecFetcher};
+use openapi_common::tenancy::TenantResolver;
+use openapi_common::{DiscoveryConfig, annotations::FetchVia};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::discovered_api::DiscoveryOutput;
+use crate::fetch_metrics::FetchMetrics;
+use crate::health::Health;
+use crate::publish::PublishManager;
+use crate::spec_changes::SpecChanges;
+use crate::{ContextData, discovery_configmap, read_discovery_config};
+
+/// A request received by [`TestServer`]
+#[derive(Debug, Clone)]
+pub struct Recorded {
+    pub method: String,
+    /// Path and query
+    pub uri: String,
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+impl Recorded {
+    pub fn path(&self) -> &str {
+        self.uri.split('?').next().unwrap_or_default()
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|v| v.to_str().ok())
+    }
+
+    pub fn json(&self) -> Value {
+        serde_json::from_str(&self.body).unwrap()
+    }
+}
+
+pub struct TestServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<Recorded>>>,
+}
+
+impl TestServer {
+    /// Answers every request with the status and body `respond` returns for it
+    pub async fn start(
+        respond: impl Fn(&Recorded) -> (u16, String) + Clone + Send + Sync + 'static,
+    ) -> Self {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        let app = axum::Router::new().fallback(move |request: Request| {
+            let recorded = recorded.clone();
+            let respond = respond.clone();
+            async move {
+                let (parts, body) = request.into_parts();
+                let body = to_bytes(body, usize::MAX).await.unwrap();
+                let request = Recorded {
+                    method: parts.method.to_string(),
+                    uri: parts.uri.to_string(),
+                    headers: parts.headers,
+                    body: String::from_utf8_lossy(&body).into_owned(),
+                };
+                let (code, body) = respond(&request);
+                recorded.lock().unwrap().push(request);
+                let content_type = [("content-type", "application/json")];
+                (StatusCode::from_u16(code).unwrap(), content_type, body)
+            }
+        });
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        Self { url, requests }
+    }
+
+    /// Requests received so far, oldest first
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Objects of a [`FakeApiServer`], keyed by their API path
+type Objects = Arc<Mutex<HashMap<String, Value>>>;
+
+/// Kubernetes API server holding objects in memory. Gets, creates, updates, merge, apply
+/// and JSON patches (of `/status` too) and deletes act on the stored objects, and applies
+/// create missing ones. Lists only hold the objects stored under the list's own path.
+pub struct FakeApiServer {
+    server: TestServer,
+    objects: Objects,
+}
+
+impl FakeApiServer {
+    pub async fn start() -> Self {
+        let objects = Objects::default();
+        let stored = objects.clone();
+        let server = TestServer::start(move |request: &Recorded| {
+            let (code, body) = respond(&mut stored.lock().unwrap(), request);
+            (code, body.to_string())
+        })
+        .await;
+        Self { server, objects }
+    }
+
+    pub fn client(&self) -> Client {
+        let config = kube::Config::new(self.server.url.parse().unwrap());
+        Client::try_from(config).unwrap()
+    }
+
+    pub fn insert(&self, path: &str, object: impl serde::Serialize) {
+        let object = serde_json::to_value(object).unwrap();
+        self.objects
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), object);
+    }
+
+    pub fn get<K: serde::de::DeserializeOwned>(&self, path: &str) -> Option<K> {
+        let object = self.objects.lock().unwrap().get(path).cloned()?;
+        Some(serde_json::from_value(object).unwrap())
+    }
+
+    /// Requests received so far, oldest first
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.server.requests()
+    }
+
+    /// Requests other than reads
+    pub fn writes(&self) -> Vec<Recorded> {
+        self.requests()
+            .into_iter()
+            .filter(|r| r.method != "GET")
+            .collect()
+    }
+}
+
+fn not_found(path: &str) -> (u16, Value) {
+    let status = json!({
+        "kind": "Status",
+        "apiVersion": "v1",
+        "status": "Failure",
+        "message": format!("{} not found", path),
+        "reason": "NotFound",
+        "code": 404,
+    });
+    (404, status)
+}
+
+/// Whether the path names a list of objects rather than one object
+fn is_collection(path: &str) -> bool {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    // `api/v1/...` or `apis/<group>/<version>/...`
+    let resource = match segments.first() {
+        Some(&"apis") => &segments[3.min(segments.len())..],
+        _ => &segments[2.min(segments.len())..],
+    };
+    resource.len() % 2 == 1
+}
+
+fn respond(objects: &mut HashMap<String, Value>, request: &Recorded) -> (u16, Value) {
+    let path = request.path().to_string();
+    // Subresources live on their object
+    let object_path = path.strip_suffix("/status").unwrap_or(&path).to_string();
+    match request.method.as_str() {
+        "GET" => match objects.get(&path) {
+            Some(object) => (200, object.clone()),
+            None if is_collection(&path) => (200, json!({ "metadata": {}, "items": [] })),
+            None => not_found(&path),
+        },
+        "POST" => {
+            let object = request.json();
+            let Some(name) = object["metadata"]["name"].as_str() else {
+                return (201, object);
+            };
+            let path = format!("{}/{}", path, name);
+            if objects.contains_key(&path) {
+                return (
+                    409,
+                    json!({ "kind": "Status", "code": 409, "reason": "AlreadyExists" }),
+                );
+            }
+            let object = with_uid(object, &path);
+            objects.insert(path, object.clone());
+            (201, object)
+        }
+        "PUT" => {
+            let object = request.json();
+            objects.insert(object_path, object.clone());
+            (200, object)
+        }
+        "PATCH" => {
+            let content_type = request.header("content-type").unwrap_or_default();
+            // Server-side apply creates missing objects
+            if content_type.starts_with("application/apply-patch") && path == object_path {
+                objects
+                    .entry(object_path.clone())
+                    .or_insert_with(|| with_uid(json!({}), &object_path));
+            }
+            let Some(object) = objects.get_mut(&object_path) else {
+                return not_found(&object_path);
+            };
+            if content_type.starts_with("application/json-patch") {
+                for operation in request.json().as_array().into_iter().flatten() {
+                    json_patch(object, operation);
+                }
+            } else {
+                merge_patch(object, &request.json());
+            }
+            (200, object.clone())
+        }
+        "DELETE" => match objects.remove(&path) {
+            Some(object) => (200, object),
+            None => not_found(&path),
+        },
+        _ => (405, json!({})),
+    }
+}
+
+/// The object with the uid the API server gives it on creation
+fn with_uid(mut object: Value, path: &str) -> Value {
+    object["metadata"]["uid"] = json!(format!("uid-{}", path.rsplit('/').next().unwrap()));
+    object
+}
+
+/// RFC 7386 merge patch
+fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = json!({});
+    }
+    let target = target.as_object_mut().unwrap();
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// The `add` and `remove` operations of RFC 6902; `test` always passes
+fn json_patch(target: &mut Value, operation: &Value) {
+    let path = operation["path"].as_str().unwrap_or_default();
+    let (parent, last) = path.rsplit_once('/').unwrap_or_default();
+    let Some(parent) = target.pointer_mut(parent) else {
+        return;
+    };
+    match (operation["op"].as_str(), parent) {
+        (Some("add"), Value::Array(items)) if last == "-" => items.push(operation["value"].clone()),
+        (Some("add"), Value::Array(items)) => {
+            items.insert(last.parse().unwrap(), operation["value"].clone())
+        }
+        (Some("add"), Value::Object(fields)) => {
+            fields.insert(last.to_string(), operation["value"].clone());
+        }
+        (Some("remove"), Value::Array(items)) => {
+            items.remove(last.parse().unwrap());
+        }
+        (Some("remove"), Value::Object(fields)) => {
+            fields.remove(last);
+        }
+        _ => {}
+    }
+}
+
+/// Namespace and name of the discovery ConfigMap of [`context`]
+pub const DISCOVERY_NAMESPACE: &str = "api-docs";
+pub const DISCOVERY_CONFIGMAP: &str = "openapi-discovery";
+
+/// API path of the discovery ConfigMap of [`context`]
+pub fn discovery_path() -> String {
+    format!(
+        "/api/v1/namespaces/{}/configmaps/{}",
+        DISCOVERY_NAMESPACE, DISCOVERY_CONFIGMAP
+    )
+}
+
+/// Stores `catalog` as the discovery ConfigMap of [`context`]
+pub fn insert_catalog(api: &FakeApiServer, catalog: &DiscoveryConfig) {
+    let configmap = discovery_configmap(
+        DISCOVERY_CONFIGMAP,
+        DISCOVERY_NAMESPACE,
+        Default::default(),
+        catalog,
+    )
+    .unwrap();
+    api.insert(&discovery_path(), configmap);
+}
+
+/// The catalog in the discovery ConfigMap of [`context`]
+pub fn catalog(api: &FakeApiServer) -> DiscoveryConfig {
+    let configmap: k8s_openapi::api::core::v1::ConfigMap = api.get(&discovery_path()).unwrap();
+    read_discovery_config(&configmap, Default::default())
+}
+
+/// Operator context with the defaults of an unconfigured operator watching all namespaces,
+/// talking to `client`
+pub fn context(client: Client) -> ContextData {
+    let fetcher = SpecFetcher::new(FetchConfig::default()).unwrap();
+    ContextData {
+        discovery: kube::Api::namespaced(client.clone(), DISCOVERY_NAMESPACE),
+        insecure_fetcher: fetcher.clone(),
+        fetcher,
+        watch_namespaces: vec!["all".to_string()],
+        exclude_namespaces: Vec::new(),
+        label_selector: None,
+        namespace_store: None,
+        namespace_defaults: None,
+        discovery_namespace: DISCOVERY_NAMESPACE.to_string(),
+        discovery_configmap: DISCOVERY_CONFIGMAP.to_string(),
+        output: DiscoveryOutput::ConfigMap,
+        encoding: Default::default(),
+        storage: Default::default(),
+        layout: Default::default(),
+        spec_store: None,
+        tombstone_grace: chrono::Duration::zero(),
+        reconcile_interval: Duration::from_secs(300),
+        error_retry_interval: Duration::from_secs(60),
+        publishers: Arc::new(PublishManager::default()),
+        tenants: TenantResolver::default(),
+        tenant_namespaces: None,
+        signer: None,
+        contract_checker: None,
+        breaking_gate: None,
+        spec_changes: Arc::new(SpecChanges::default()),
+        recorder: Recorder::new(client, Reporter::from("openapi-k8s-operator")),
+        refresh_window: None,
+        api_docs: None,
+        deployments: None,
+        health: Arc::new(Health::new(chrono::Duration::minutes(5))),
+        fetch_metrics: Arc::new(FetchMetrics::default()),
+        audit: None,
+        dry_run: false,
+        written_catalog: Arc::new(tokio::sync::Mutex::new(None)),
+        fetch_via: FetchVia::default(),
+    }
+}
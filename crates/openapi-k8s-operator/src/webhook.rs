@@ -0,0 +1,520 @@
+//! HTTPS endpoint the API server calls to convert `DiscoveredApi` objects between versions.
+//!
+//! Enabled with `WEBHOOK_ENABLED=true`. The serving certificate and key are read from
+//! `WEBHOOK_TLS_DIR` (`tls.crt` and `tls.key`, the layout of a cert-manager Secret). When
+//! `WEBHOOK_SERVICE` names the Service in front of the operator, the operator points the
+//! CRD's conversion strategy at it on startup, with `ca.crt` (or `tls.crt`) as CA bundle.
+//!
+//! The same server validates `api-doc.io/*` annotations of Services on create and update.
+//! With `WEBHOOK_VALIDATE_SERVICES=true` the operator registers a `ValidatingWebhookConfiguration`
+//! for it; issues are returned as warnings, or reject the change with
+//! `WEBHOOK_REJECT_INVALID=true`.
+
+use axum::{Json, Router, extract::State, routing::post};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use k8s_openapi::ByteString;
+use k8s_openapi::api::admissionregistration::v1 as admissionregistration;
+use k8s_openapi::api::admissionregistration::v1::{
+    RuleWithOperations, ValidatingWebhook, ValidatingWebhookConfiguration,
+};
+use k8s_openapi::api::core::v1::Service;
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::{
+    CustomResourceConversion, CustomResourceDefinition, ServiceReference, WebhookClientConfig,
+    WebhookConversion,
+};
+use kube::{
+    Client,
+    api::{Api, Patch, PatchParams},
+    core::{
+        DynamicObject, ObjectMeta, Status,
+        admission::{AdmissionRequest, AdmissionResponse, AdmissionReview, Operation},
+        conversion::{ConversionRequest, ConversionResponse, ConversionReview},
+    },
+};
+use openapi_common::annotations::{
+    AnnotationIssue, ApiDocAnnotations, IssueSeverity, PortRef, is_known_annotation,
+};
+use openapi_common::crd::{self, CRD_NAME};
+use openapi_common::{API_DOC_PORT_ANNOTATION, API_DOC_PORT_NAME_ANNOTATION, names};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject};
+use tracing::{debug, info, warn};
+
+use crate::error::AppError;
+
+pub const WEBHOOK_ENABLED_ENV: &str = "WEBHOOK_ENABLED";
+pub const WEBHOOK_PORT_ENV: &str = "WEBHOOK_PORT";
+pub const WEBHOOK_TLS_DIR_ENV: &str = "WEBHOOK_TLS_DIR";
+pub const WEBHOOK_SERVICE_ENV: &str = "WEBHOOK_SERVICE";
+pub const WEBHOOK_VALIDATE_SERVICES_ENV: &str = "WEBHOOK_VALIDATE_SERVICES";
+pub const WEBHOOK_REJECT_INVALID_ENV: &str = "WEBHOOK_REJECT_INVALID";
+
+const DEFAULT_PORT: u16 = 8443;
+const DEFAULT_TLS_DIR: &str = "/etc/webhook/tls";
+const CONVERT_PATH: &str = "/convert";
+const VALIDATE_SERVICES_PATH: &str = "/validate-services";
+/// Name of the Service webhook in the `ValidatingWebhookConfiguration`
+const SERVICE_WEBHOOK_NAME: &str = "services.api-doc.io";
+const FIELD_MANAGER: &str = "openapi-k8s-operator";
+
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    port: u16,
+    tls_dir: PathBuf,
+    /// Service (port 443) the API server reaches the webhook through
+    service: Option<String>,
+    namespace: String,
+    /// Register the Service annotation webhook
+    validate_services: bool,
+    /// Deny Service changes whose annotations have errors instead of only warning
+    reject_invalid: bool,
+}
+
+fn env_flag(name: &str) -> bool {
+    std::env::var(name)
+        .map(|v| v.trim().eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+impl WebhookConfig {
+    /// `None` unless `WEBHOOK_ENABLED=true`
+    pub fn from_env() -> Result<Option<Self>, AppError> {
+        if !env_flag(WEBHOOK_ENABLED_ENV) {
+            return Ok(None);
+        }
+
+        let port = match std::env::var(WEBHOOK_PORT_ENV) {
+            Ok(value) => value.trim().parse::<u16>().map_err(|_| {
+                AppError::Io(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Invalid {}: '{}'", WEBHOOK_PORT_ENV, value),
+                ))
+            })?,
+            Err(_) => DEFAULT_PORT,
+        };
+        let service = std::env::var(WEBHOOK_SERVICE_ENV)
+            .ok()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty());
+        if let Some(service) = &service {
+            names::validate_service_name(service).map_err(|e| {
+                AppError::Io(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Invalid {} '{}': {}", WEBHOOK_SERVICE_ENV, service, e),
+                ))
+            })?;
+        }
+        Ok(Some(Self {
+            port,
+            tls_dir: std::env::var(WEBHOOK_TLS_DIR_ENV)
+                .unwrap_or_else(|_| DEFAULT_TLS_DIR.to_string())
+                .into(),
+            service,
+            namespace: std::env::var("POD_NAMESPACE").unwrap_or_else(|_| "default".to_string()),
+            validate_services: env_flag(WEBHOOK_VALIDATE_SERVICES_ENV),
+            reject_invalid: env_flag(WEBHOOK_REJECT_INVALID_ENV),
+        }))
+    }
+
+    /// CA bundle the API server verifies the webhook with
+    fn ca_bundle(&self) -> std::io::Result<Vec<u8>> {
+        std::fs::read(self.tls_dir.join("ca.crt"))
+            .or_else(|_| std::fs::read(self.tls_dir.join("tls.crt")))
+    }
+
+    fn tls_acceptor(&self) -> Result<TlsAcceptor, AppError> {
+        let invalid = |e: &dyn std::fmt::Display| {
+            AppError::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid webhook TLS material in {:?}: {}", self.tls_dir, e),
+            ))
+        };
+        let certs = CertificateDer::pem_file_iter(self.tls_dir.join("tls.crt"))
+            .map_err(|e| invalid(&e))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| invalid(&e))?;
+        let key =
+            PrivateKeyDer::from_pem_file(self.tls_dir.join("tls.key")).map_err(|e| invalid(&e))?;
+
+        let mut config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(|e| invalid(&e))?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+/// Serves the conversion and validation endpoints until the process exits
+pub async fn serve(config: WebhookConfig) -> Result<(), AppError> {
+    let acceptor = config.tls_acceptor()?;
+    let app = Router::new()
+        .route(CONVERT_PATH, post(handle_convert))
+        .route(VALIDATE_SERVICES_PATH, post(handle_validate_service))
+        .with_state(config.reject_invalid);
+    let listener = tokio::net::TcpListener::bind(("0.0.0.0", config.port)).await?;
+    info!("Webhook listening on port {}", config.port);
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                warn!("Failed to accept webhook connection: {}", e);
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let service = TowerToHyperService::new(app.clone());
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    debug!("TLS handshake with {} failed: {}", peer, e);
+                    return;
+                }
+            };
+            if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                debug!("Webhook connection from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle_convert(Json(review): Json<ConversionReview>) -> Json<ConversionReview> {
+    let request = match ConversionRequest::from_review(review) {
+        Ok(request) => request,
+        Err(_) => {
+            let status = Status::failure("ConversionReview has no request", "BadRequest");
+            return Json(ConversionResponse::invalid(status).into_review());
+        }
+    };
+
+    let desired = request.desired_api_version.clone();
+    let objects = request.objects.clone();
+    let response = ConversionResponse::for_request(request);
+    let converted: Result<Vec<_>, _> = objects
+        .into_iter()
+        .map(|object| crd::convert(object, &desired))
+        .collect();
+
+    let response = match converted {
+        Ok(objects) => {
+            debug!(
+                "Converted {} DiscoveredApi objects to {}",
+                objects.len(),
+                desired
+            );
+            response.success(objects)
+        }
+        Err(e) => {
+            warn!("DiscoveredApi conversion to {} failed: {}", desired, e);
+            response.failure(Status::failure(&e.to_string(), "ConversionFailed"))
+        }
+    };
+    Json(response.into_review())
+}
+
+async fn handle_validate_service(
+    State(reject_invalid): State<bool>,
+    Json(review): Json<AdmissionReview<Service>>,
+) -> Json<AdmissionReview<DynamicObject>> {
+    let request: AdmissionRequest<Service> = match review.try_into() {
+        Ok(request) => request,
+        Err(_) => {
+            return Json(
+                AdmissionResponse::invalid("AdmissionReview has no request").into_review(),
+            );
+        }
+    };
+    Json(review_service(&request, reject_invalid).into_review())
+}
+
+/// Admits the Service with its annotation issues as warnings. With `reject_invalid`, errors
+/// deny the request, unless an update leaves the annotations as they were.
+fn review_service(request: &AdmissionRequest<Service>, reject_invalid: bool) -> AdmissionResponse {
+    let mut response = AdmissionResponse::from(request);
+    let Some(service) = &request.object else {
+        return response;
+    };
+    let issues = service_issues(service);
+    if issues.is_empty() {
+        return response;
+    }
+
+    let annotations_changed = request.operation != Operation::Update
+        || request
+            .old_object
+            .as_ref()
+            .map(|old| &old.metadata.annotations)
+            != Some(&service.metadata.annotations);
+    let errors: Vec<String> = issues
+        .iter()
+        .filter(|issue| issue.severity == IssueSeverity::Error)
+        .map(ToString::to_string)
+        .collect();
+    if reject_invalid && annotations_changed && !errors.is_empty() {
+        debug!(
+            "Rejecting Service {}/{}: {}",
+            request.namespace.as_deref().unwrap_or_default(),
+            request.name,
+            errors.join("; ")
+        );
+        return response.deny(format!(
+            "Invalid api-doc.io annotations: {}",
+            errors.join("; ")
+        ));
+    }
+    response.warnings = Some(issues.iter().map(ToString::to_string).collect());
+    response
+}
+
+/// Annotation issues of the Service, plus a port name the Service does not declare
+fn service_issues(service: &Service) -> Vec<AnnotationIssue> {
+    match &service.metadata.annotations {
+        Some(annotations) => annotation_issues(service, annotations),
+        None => Vec::new(),
+    }
+}
+
+/// Issues of `annotations` as set on the Service, e.g. merged with its namespace's defaults
+pub fn annotation_issues(
+    service: &Service,
+    annotations: &BTreeMap<String, String>,
+) -> Vec<AnnotationIssue> {
+    let (parsed, mut issues) = ApiDocAnnotations::parse(annotations);
+    if !annotations.keys().any(|key| is_known_annotation(key)) {
+        return issues;
+    }
+    let ports = service
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.ports.as_ref())
+        .into_iter()
+        .flatten()
+        .map(|port| (port.name.as_deref(), port.port));
+    if let Some(port @ PortRef::Name(name)) = &parsed.port
+        && port.resolve(ports).is_none()
+    {
+        let annotation = if annotations.contains_key(API_DOC_PORT_NAME_ANNOTATION) {
+            API_DOC_PORT_NAME_ANNOTATION
+        } else {
+            API_DOC_PORT_ANNOTATION
+        };
+        issues.push(AnnotationIssue {
+            severity: IssueSeverity::Error,
+            annotation: annotation.to_string(),
+            message: format!("the Service has no port named \"{}\"", name),
+        });
+    }
+    issues
+}
+
+/// Registers the Service annotation webhook when `WEBHOOK_VALIDATE_SERVICES` and
+/// `WEBHOOK_SERVICE` are set. It fails open, so Services can still be changed while the
+/// operator is down.
+pub async fn register_validation(client: Client, config: &WebhookConfig) -> Result<(), AppError> {
+    let Some(service) = config.service.as_ref().filter(|_| config.validate_services) else {
+        return Ok(());
+    };
+    let configuration = ValidatingWebhookConfiguration {
+        metadata: ObjectMeta {
+            name: Some(service.clone()),
+            ..Default::default()
+        },
+        webhooks: Some(vec![ValidatingWebhook {
+            name: SERVICE_WEBHOOK_NAME.to_string(),
+            admission_review_versions: vec!["v1".to_string()],
+            client_config: admissionregistration::WebhookClientConfig {
+                ca_bundle: Some(ByteString(config.ca_bundle()?)),
+                service: Some(admissionregistration::ServiceReference {
+                    name: service.clone(),
+                    namespace: config.namespace.clone(),
+                    path: Some(VALIDATE_SERVICES_PATH.to_string()),
+                    port: Some(443),
+                }),
+                url: None,
+            },
+            failure_policy: Some("Ignore".to_string()),
+            side_effects: "None".to_string(),
+            timeout_seconds: Some(5),
+            rules: Some(vec![RuleWithOperations {
+                api_groups: Some(vec![String::new()]),
+                api_versions: Some(vec!["v1".to_string()]),
+                operations: Some(vec!["CREATE".to_string(), "UPDATE".to_string()]),
+                resources: Some(vec!["services".to_string()]),
+                scope: Some("Namespaced".to_string()),
+            }]),
+            ..Default::default()
+        }]),
+    };
+
+    let configurations: Api<ValidatingWebhookConfiguration> = Api::all(client);
+    configurations
+        .patch(
+            service,
+            &PatchParams::apply(FIELD_MANAGER).force(),
+            &Patch::Apply(&configuration),
+        )
+        .await?;
+    info!(
+        "Registered Service annotation webhook {}/{} (rejecting invalid annotations: {})",
+        config.namespace, service, config.reject_invalid
+    );
+    Ok(())
+}
+
+/// Points the CRD's conversion strategy at this webhook when `WEBHOOK_SERVICE` is set
+pub async fn register_conversion(client: Client, config: &WebhookConfig) -> Result<(), AppError> {
+    let Some(service) = &config.service else {
+        return Ok(());
+    };
+    let ca_bundle = config.ca_bundle()?;
+
+    let conversion = CustomResourceConversion {
+        strategy: "Webhook".to_string(),
+        webhook: Some(WebhookConversion {
+            conversion_review_versions: vec!["v1".to_string()],
+            client_config: Some(WebhookClientConfig {
+                ca_bundle: Some(ByteString(ca_bundle)),
+                service: Some(ServiceReference {
+                    name: service.clone(),
+                    namespace: config.namespace.clone(),
+                    path: Some(CONVERT_PATH.to_string()),
+                    port: Some(443),
+                }),
+                url: None,
+            }),
+        }),
+    };
+    let patch = serde_json::json!({ "spec": { "conversion": conversion } });
+
+    let crds: Api<CustomResourceDefinition> = Api::all(client);
+    crds.patch(CRD_NAME, &PatchParams::default(), &Patch::Merge(&patch))
+        .await?;
+    info!(
+        "Registered conversion webhook {}/{} for {}",
+        config.namespace, service, CRD_NAME
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openapi_common::crd::{DiscoveredApi, DiscoveredApiSpec};
+
+    #[tokio::test]
+    async fn converts_review_objects() {
+        let resource = DiscoveredApi::new(
+            "orders",
+            DiscoveredApiSpec {
+                name: "Orders API".to_string(),
+                service_name: "orders".to_string(),
+                url: "http://orders.shop.svc.cluster.local:8080/openapi.json".to_string(),
+                description: None,
+                tenant: None,
+                lifecycle: None,
+                ui: None,
+                refresh_interval: None,
+                refresh_window: None,
+                api_group: None,
+                insecure_skip_verify: false,
+                spec_config_map: None,
+            },
+        );
+        let review: ConversionReview = serde_json::from_value(serde_json::json!({
+            "apiVersion": "apiextensions.k8s.io/v1",
+            "kind": "ConversionReview",
+            "request": {
+                "uid": "705ab4f5-6393-11e8-b7cc-42010a800002",
+                "desiredAPIVersion": "api-doc.io/v1beta1",
+                "objects": [resource],
+            },
+        }))
+        .unwrap();
+
+        let Json(review) = handle_convert(Json(review)).await;
+        let response = review.response.unwrap();
+        assert_eq!(response.uid, "705ab4f5-6393-11e8-b7cc-42010a800002");
+        assert_eq!(
+            response.result.status,
+            Some(kube::core::response::StatusSummary::Success)
+        );
+        assert_eq!(
+            response.converted_objects[0]["spec"]["source"]["url"],
+            "http://orders.shop.svc.cluster.local:8080/openapi.json"
+        );
+    }
+
+    fn service_review(
+        operation: &str,
+        port: &str,
+        old_port: Option<&str>,
+    ) -> AdmissionRequest<Service> {
+        let service = |port: &str| {
+            serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "Service",
+                "metadata": {
+                    "name": "orders",
+                    "namespace": "shop",
+                    "annotations": {
+                        "api-doc.io/enabled": "true",
+                        "api-doc.io/port": port,
+                    },
+                },
+                "spec": { "ports": [{ "name": "http", "port": 8080 }] },
+            })
+        };
+        let review: AdmissionReview<Service> = serde_json::from_value(serde_json::json!({
+            "apiVersion": "admission.k8s.io/v1",
+            "kind": "AdmissionReview",
+            "request": {
+                "uid": "705ab4f5-6393-11e8-b7cc-42010a800002",
+                "kind": { "group": "", "version": "v1", "kind": "Service" },
+                "resource": { "group": "", "version": "v1", "resource": "services" },
+                "name": "orders",
+                "namespace": "shop",
+                "operation": operation,
+                "userInfo": {},
+                "object": service(port),
+                "oldObject": old_port.map(service),
+            },
+        }))
+        .unwrap();
+        review.try_into().unwrap()
+    }
+
+    #[test]
+    fn validates_service_annotations() {
+        let valid = review_service(&service_review("CREATE", "http", None), true);
+        assert!(valid.allowed);
+        assert_eq!(valid.warnings, None);
+
+        let request = service_review("CREATE", "grpc", None);
+        let warned = review_service(&request, false);
+        assert!(warned.allowed);
+        assert_eq!(
+            warned.warnings.unwrap(),
+            vec!["api-doc.io/port: the Service has no port named \"grpc\"".to_string()]
+        );
+        let rejected = review_service(&request, true);
+        assert!(!rejected.allowed);
+        assert!(rejected.result.message.contains("no port named \"grpc\""));
+
+        // Unrelated updates of a Service that was already misconfigured are let through
+        let unchanged = review_service(&service_review("UPDATE", "grpc", Some("grpc")), true);
+        assert!(unchanged.allowed);
+        let changed = review_service(&service_review("UPDATE", "grpc", Some("http")), true);
+        assert!(!changed.allowed);
+    }
+}
@@ -96,8 +96,8 @@ Create the name of the openapi server service
 Validate namespace configuration
 */}}
 {{- define "openapi-k8s-operator.validateNamespaces" -}}
-{{- if and (ne .Values.operator.config.watchNamespaces "") (ne .Values.operator.config.watchNamespaces "all") (not (contains "," .Values.operator.config.watchNamespaces)) }}
-{{- fail (printf "Invalid watchNamespaces configuration: '%s'. Must be empty string (current namespace), 'all' (all namespaces), or comma-separated list of namespaces" .Values.operator.config.watchNamespaces) }}
+{{- if and (ne .Values.operator.config.watchNamespaces "") (ne .Values.operator.config.watchNamespaces "all") (not (contains "," .Values.operator.config.watchNamespaces)) (not (contains "*" .Values.operator.config.watchNamespaces)) }}
+{{- fail (printf "Invalid watchNamespaces configuration: '%s'. Must be empty string (current namespace), 'all' (all namespaces), a glob pattern, or comma-separated list of namespaces" .Values.operator.config.watchNamespaces) }}
 {{- end }}
 {{- end }}
 
@@ -106,7 +106,7 @@ Determine if cluster-wide RBAC is needed
 */}}
 {{- define "openapi-k8s-operator.clusterWideRBAC" -}}
 {{- include "openapi-k8s-operator.validateNamespaces" . }}
-{{- if or (eq .Values.operator.config.watchNamespaces "all") (contains "," .Values.operator.config.watchNamespaces) }}
+{{- if or (eq .Values.operator.config.watchNamespaces "all") (and .Values.operator.config.watchApiDocs (contains "," .Values.operator.config.watchNamespaces)) (contains "*" .Values.operator.config.watchNamespaces) .Values.operator.config.watchNamespaceSelector .Values.operator.tenancy.label .Values.operator.webhook.enabled .Values.operator.config.namespaceDefaults }}
 {{- true }}
 {{- else }}
 {{- .Values.operator.rbac.clusterWide }}
@@ -152,3 +152,64 @@ Create the openapi server image name
 {{- printf "%s:%s" $repository $tag }}
 {{- end }}
 {{- end }}
+
+{{/*
+Volume with the CA bundle trusted for spec fetches, empty when none is configured
+*/}}
+{{- define "openapi-k8s-operator.caBundleVolume" -}}
+{{- with .Values.global.specFetchCaBundle }}
+{{- if .secret }}
+- name: spec-fetch-ca
+  secret:
+    secretName: {{ .secret }}
+{{- else if .configMap }}
+- name: spec-fetch-ca
+  configMap:
+    name: {{ .configMap }}
+{{- end }}
+{{- end }}
+{{- end }}
+
+{{/*
+Environment of the spec storage bucket, shared by the operator and the documentation server
+*/}}
+{{- define "openapi-k8s-operator.specStorageS3Env" -}}
+{{- with .Values.operator.specStorageS3 -}}
+- name: SPEC_STORAGE_S3_BUCKET
+  value: {{ required "operator.specStorageS3.bucket is required when specStorage is s3" .bucket | quote }}
+- name: SPEC_STORAGE_S3_PREFIX
+  value: {{ .prefix | quote }}
+{{- with .region }}
+- name: SPEC_STORAGE_S3_REGION
+  value: {{ . | quote }}
+{{- end }}
+{{- with .endpoint }}
+- name: SPEC_STORAGE_S3_ENDPOINT
+  value: {{ . | quote }}
+{{- end }}
+{{- end }}
+{{- end }}
+
+{{/*
+Proxy environment for spec fetches, empty when no proxy is configured
+*/}}
+{{- define "openapi-k8s-operator.proxyEnv" -}}
+{{- with .Values.global.proxy }}
+{{- if .httpProxy }}
+- name: HTTP_PROXY
+  value: {{ .httpProxy | quote }}
+{{- end }}
+{{- if .httpsProxy }}
+- name: HTTPS_PROXY
+  value: {{ .httpsProxy | quote }}
+{{- end }}
+{{- if .noProxy }}
+- name: NO_PROXY
+  value: {{ .noProxy | quote }}
+{{- end }}
+{{- if .specFetchProxy }}
+- name: SPEC_FETCH_PROXY
+  value: {{ .specFetchProxy | quote }}
+{{- end }}
+{{- end }}
+{{- end }}